@@ -1,28 +1,52 @@
 use actix_cors::Cors;
 use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
     get,
-    middleware::{self, Condition},
-    route,
+    http::header,
+    middleware::Condition,
+    post, route,
     rt::task::spawn_blocking,
-    web::{self, Data},
-    App, HttpResponse, HttpServer, Responder,
+    web::{self, Bytes, Data},
+    App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
+use actix_web_lab::{
+    middleware::{from_fn, Next},
+    respond::Html,
+    sse,
 };
-use actix_web_lab::respond::Html;
 use clap::Parser;
 use database::{
-    database::{
-        commands::ShutdownRequest, database::Database, options::DatabaseOptions,
-        request_manager::RequestManager,
+    database::table::arrow::record_batch_to_ipc,
+    prelude::{
+        read_config_file, ConfigError, Database, DatabaseConfig, DatabaseOptions, DiffSource,
+        LoggingConfig, RequestManager, RowPolicy, ShutdownRequest, SnapshotTimestamp, TableVersion,
+        TransactionContext, TransactionId,
     },
-    persistence::storage::{
-        dynamodb::DynamoOptions, postgres::PostgresOptions, s3::S3Options, StorageEngine,
+};
+use juniper::{
+    futures::{stream, StreamExt},
+    http::{graphiql::graphiql_source, GraphQLRequest},
+    Object, Value,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    io,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
-use juniper::http::{graphiql::graphiql_source, GraphQLRequest};
-use std::{io, sync::Arc};
 
-use crate::schema::{create_schema, GraphQLContext, Schema};
+use crate::{
+    operations::OperationsToken,
+    schema::{create_schema, BulkLimits, FieldRedactions, GraphQLContext, Schema},
+};
 
+mod operations;
 mod schema;
 
 /// GraphiQL playground UI
@@ -31,104 +55,567 @@ async fn graphql_playground() -> impl Responder {
     Html(graphiql_source("/graphql", None))
 }
 
+/// Operator UI for live stats, active requests, the snapshot catalog and maintenance actions, see `--admin-ui`
+#[get("/admin")]
+async fn admin_page() -> impl Responder {
+    Html(include_str!("admin.html").to_string())
+}
+
+/// Header clients can set to identify themselves, used for per client statistics
+const CLIENT_ID_HEADER: &str = "x-client-id";
+
+/// Header the request's role is read from, reads are restricted by the role's row policies. This should be set
+/// by a trusted proxy rather than by clients directly
+const ROLE_HEADER: &str = "x-role";
+
+/// Header that turns the request's mutations into dry runs, they report their results (or why they would roll
+/// back) without committing anything. Set to `true` to enable it
+const DRY_RUN_HEADER: &str = "x-dry-run";
+
+/// Header that tags the request, tags have their own metrics and can be limited (see `--tag-limit`)
+const TAG_HEADER: &str = "x-request-tag";
+
+fn header_value(request: &HttpRequest, header: &str) -> Option<String> {
+    request
+        .headers()
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+fn graphql_context(
+    request: &HttpRequest,
+    request_manager: &RequestManager,
+    bulk_limits: BulkLimits,
+    redactions: &web::Data<FieldRedactions>,
+) -> GraphQLContext {
+    GraphQLContext {
+        request_manager: request_manager.clone(),
+        bulk_limits,
+        client_id: header_value(request, CLIENT_ID_HEADER),
+        role: header_value(request, ROLE_HEADER),
+        redactions: redactions.clone().into_inner(),
+        dry_run: header_value(request, DRY_RUN_HEADER).is_some_and(|value| value == "true"),
+        tag: header_value(request, TAG_HEADER),
+        if_none_match: if_none_match(request),
+        conditional_reads: Mutex::new(vec![]),
+    }
+}
+
 /// GraphQL endpoint -- triggered once per request
 #[route("/graphql", method = "GET", method = "POST")]
 async fn graphql(
+    request: HttpRequest,
     schema: web::Data<Schema>,
     request_manager_ref: web::Data<RequestManager>,
+    bulk_limits: web::Data<BulkLimits>,
+    redactions: web::Data<FieldRedactions>,
     data: web::Json<GraphQLRequest>,
 ) -> impl Responder {
-    let request_manager = request_manager_ref.as_ref();
+    let graphql_context = graphql_context(
+        &request,
+        request_manager_ref.as_ref(),
+        *bulk_limits.get_ref(),
+        &redactions,
+    );
 
-    let graphql_context = GraphQLContext {
-        request_manager: request_manager.clone(),
+    let user = data.execute(&schema, &graphql_context).await;
+
+    let conditional_reads = graphql_context.conditional_reads.lock().unwrap().clone();
+
+    let Some((_, version)) = conditional_reads.last() else {
+        return HttpResponse::Ok().json(user);
     };
 
-    let user = data.execute(&schema, &graphql_context).await;
+    let etag = header::EntityTag::new_strong(version.to_string());
+
+    // Only a response that consists entirely of unchanged conditional reads can be answered from the client's cache
+    let not_modified = graphql_context.if_none_match.is_some()
+        && user.is_ok()
+        && conditional_reads
+            .iter()
+            .all(|(not_modified, _)| *not_modified);
+
+    match not_modified {
+        true => HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish(),
+        false => HttpResponse::Ok()
+            .insert_header(header::ETag(etag))
+            .json(user),
+    }
+}
 
-    HttpResponse::Ok().json(user)
+/// An event of `/graphql/subscriptions`, the result of one of the subscription's fields
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SubscriptionEvent<E: Serialize> {
+    Data(Value),
+    Errors(E),
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
-enum StorageEngineFlag {
-    File,
-    Dynamo,
-    Postgres,
-    S3,
+fn subscription_event(event: SubscriptionEvent<impl Serialize>) -> sse::Event {
+    sse::Data::new_json(event)
+        .expect("GraphQL results should always serialize")
+        .into()
 }
 
-fn to_storage_engine(args: &Cli) -> StorageEngine {
-    match args.storage {
-        StorageEngineFlag::File => StorageEngine::File(args.data.clone()),
-        StorageEngineFlag::Dynamo => {
-            StorageEngine::DynamoDB(DynamoOptions::new(args.table.clone()))
+/// GraphQL subscriptions (e.g. `watchHuman`) as server-sent events, every result is sent as a `{"data": ...}` or
+/// `{"errors": [...]}` event until the client disconnects
+#[post("/graphql/subscriptions")]
+async fn graphql_subscriptions(
+    request: HttpRequest,
+    schema: web::Data<Schema>,
+    request_manager_ref: web::Data<RequestManager>,
+    bulk_limits: web::Data<BulkLimits>,
+    redactions: web::Data<FieldRedactions>,
+    data: web::Json<GraphQLRequest>,
+) -> impl Responder {
+    let graphql_context = graphql_context(
+        &request,
+        request_manager_ref.as_ref(),
+        *bulk_limits.get_ref(),
+        &redactions,
+    );
+
+    let (sender, receiver) = flume::unbounded();
+
+    // The subscription's streams borrow the request, the task owns it until the client disconnects
+    actix_web::rt::spawn(async move {
+        let data = data.into_inner();
+
+        let (value, errors) =
+            match juniper::http::resolve_into_stream(&data, &schema, &graphql_context).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    let _ = sender.send(subscription_event(SubscriptionEvent::Errors(e)));
+                    return;
+                }
+            };
+
+        if !errors.is_empty() {
+            let _ = sender.send(subscription_event(SubscriptionEvent::Errors(errors)));
+        }
+
+        let Value::Object(fields) = value else {
+            return;
+        };
+
+        let mut results =
+            stream::select_all(fields.into_iter().filter_map(|(name, value)| match value {
+                Value::Scalar(results) => Some(results.map(move |result| (name.clone(), result))),
+                _ => None,
+            }));
+
+        while let Some((name, result)) = results.next().await {
+            let event = match result {
+                Ok(value) => subscription_event(SubscriptionEvent::<()>::Data(Value::Object(
+                    Object::from_iter([(name, value)]),
+                ))),
+                Err(e) => subscription_event(SubscriptionEvent::Errors([e])),
+            };
+
+            if sender.send_async(event).await.is_err() {
+                return;
+            }
         }
-        StorageEngineFlag::Postgres => StorageEngine::Postgres(PostgresOptions::new(
-            args.database_user.clone(),
-            args.database_database.clone(),
-            args.database_host.clone(),
-            args.database_password.clone(),
-        )),
-        StorageEngineFlag::S3 => StorageEngine::S3(S3Options::new(args.bucket.clone())),
+    });
+
+    sse::Sse::from_infallible_stream(receiver.into_stream())
+        .with_keep_alive(Duration::from_secs(15))
+}
+
+#[derive(Deserialize)]
+struct ArrowExportQuery {
+    /// Reads the table as it was at the transaction id, defaults to the latest transaction
+    snapshot_id: Option<usize>,
+}
+
+/// Every human as an Arrow IPC stream, for analytical engines that aggregate the table (e.g. DuckDB or pandas
+/// via pyarrow). Reads are restricted by the role's row policies like GraphQL reads
+#[get("/export/arrow")]
+async fn export_arrow(
+    request: HttpRequest,
+    request_manager: web::Data<RequestManager>,
+    query: web::Query<ArrowExportQuery>,
+) -> impl Responder {
+    let snapshot_timestamp = match query.snapshot_id {
+        Some(transaction_id) => SnapshotTimestamp::AtTransactionId(TransactionId(transaction_id)),
+        None => SnapshotTimestamp::Latest,
+    };
+
+    let transaction_context = TransactionContext::new(snapshot_timestamp)
+        .set_client_id(header_value(&request, CLIENT_ID_HEADER))
+        .set_role(header_value(&request, ROLE_HEADER))
+        .set_tag(header_value(&request, TAG_HEADER));
+
+    let batch = match request_manager.send_list_arrow(None, transaction_context) {
+        Ok(batch) => batch,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    match record_batch_to_ipc(&batch) {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type("application/vnd.apache.arrow.stream")
+            .body(bytes),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
-/// 📀 Lineagedb GraphQL Server, provides a simple GraphQL interface for interacting with the database
-#[derive(Parser, Debug)]
-struct Cli {
-    /// Port the graphql server will run on
-    #[clap(short, long, default_value = "9000")]
-    port: u16,
+/// Bearer token that authorizes snapshot downloads, see `--snapshot-download-token`
+struct SnapshotDownloadToken(String);
+
+/// The latest snapshot and the WAL tail as newline delimited JSON, a consistent copy of the database for replicas,
+/// backup tooling and local development that does not need the storage engine's credentials. Restore it with
+/// `SnapshotArchive::from_ndjson(..).restore_into(..)`
+#[get("/snapshot/download")]
+async fn download_snapshot(
+    request: HttpRequest,
+    request_manager: web::Data<RequestManager>,
+    token: web::Data<SnapshotDownloadToken>,
+) -> impl Responder {
+    if !token.authorizes(&request) {
+        return HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .finish();
+    }
+
+    match request_manager.send_download_snapshot_request() {
+        Ok(archive) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .body(archive),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
 
-    /// Address the graphql server will run on
-    #[clap(short, long, default_value = "0.0.0.0")]
-    address: String,
+#[derive(Deserialize)]
+struct SnapshotDiffQuery {
+    /// Snapshot key (see `system.snapshots`) or `live`
+    from: String,
+    /// Snapshot key or `live`, defaults to the live table
+    to: Option<String>,
+}
 
-    /// Whether to log out GraphQL HTTP requests
-    #[clap(long, default_value = "false")]
-    log_http: bool,
+/// The humans added, removed or changed between two snapshots (or a snapshot and the live table) as newline
+/// delimited JSON in id order, with the changed fields of each human. The diff is streamed as it is computed
+#[get("/snapshot/diff")]
+async fn snapshot_diff(
+    request: HttpRequest,
+    request_manager: web::Data<RequestManager>,
+    token: web::Data<SnapshotDownloadToken>,
+    query: web::Query<SnapshotDiffQuery>,
+) -> impl Responder {
+    if !token.authorizes(&request) {
+        return HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .finish();
+    }
 
-    #[clap(long, default_value_t = 2)]
-    http_workers: usize,
+    let SnapshotDiffQuery { from, to } = query.into_inner();
+    let to = to.unwrap_or_else(|| "live".to_string());
 
-    #[clap(long)]
-    #[clap(help = "Which storage mechanism to use")]
-    #[clap(value_enum, default_value_t=StorageEngineFlag::File)]
-    storage: StorageEngineFlag,
+    let request_manager = request_manager.get_ref().clone();
 
-    /// When using file storage, location of the database. Reads / writes to this directory. Note: Does not support shell paths, e.g. ~
-    #[clap(long, default_value = "data")]
-    data: std::path::PathBuf,
+    let diff = spawn_blocking(move || {
+        let from = DiffSource::from_str(&from).expect("Every string is a diff source");
+        let to = DiffSource::from_str(&to).expect("Every string is a diff source");
 
-    /// When using DynamoDB the table name
-    #[clap(long, default_value = "lineagedb-ddb")]
-    table: String,
+        request_manager.send_snapshot_diff_request(from, to)
+    })
+    .await;
+
+    match diff {
+        Ok(Ok(diff)) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .streaming(stream::iter(diff.map(|entity| {
+                Ok::<_, Infallible>(Bytes::from(entity.to_ndjson_line()))
+            }))),
+        Ok(Err(e)) => HttpResponse::BadRequest().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
 
-    /// When using S3 the bucket name
-    #[clap(long, default_value = "dalesalter-test-bucket")]
-    bucket: String,
+impl SnapshotDownloadToken {
+    fn authorizes(&self, request: &HttpRequest) -> bool {
+        bearer_authorizes(request, &self.0)
+    }
+}
+
+/// Whether the request has an `Authorization: Bearer <token>` header with the token
+fn bearer_authorizes(request: &HttpRequest, token: &str) -> bool {
+    header_value(request, header::AUTHORIZATION.as_str())
+        .and_then(|value| value.strip_prefix("Bearer ").map(str::to_string))
+        .is_some_and(|bearer| constant_time_eq(bearer.as_bytes(), token.as_bytes()))
+}
 
-    /// When using Postgres the database information
-    #[clap(long, default_value = "dalesalter")]
-    database_user: String,
+/// Compares the tokens without returning early, so the response time does not reveal how much of a guess matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// The version in the `If-None-Match` header, see `listHumanIfChanged`. Weak validators are accepted, only the
+/// first version of a list is used
+fn if_none_match(request: &HttpRequest) -> Option<TableVersion> {
+    let value = header_value(request, header::IF_NONE_MATCH.as_str())?;
 
-    #[clap(long, default_value = "dalesalter1")]
-    database_database: String,
+    let tag = value.split(',').next()?.trim();
+    let tag = tag.strip_prefix("W/").unwrap_or(tag);
 
-    #[clap(long, default_value = "localhost")]
-    database_host: String,
+    tag.trim_matches('"').parse().ok()
+}
 
-    #[clap(long, default_value = "mysecretpassword")]
-    database_password: String,
+/// Set once Ctrl-C is received, the server stops accepting connections and finishes its in-flight requests
+/// before the database is shut down
+struct Drain {
+    draining: AtomicBool,
+    /// Sent as `Retry-After` to requests that arrive while draining, e.g. on a kept-alive connection
+    retry_after: Duration,
+}
+
+/// Rejects requests with a 503 while the server is draining, they would fail once the database shuts down
+async fn reject_while_draining(
+    drain: web::Data<Drain>,
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if !drain.draining.load(Ordering::SeqCst) {
+        return next.call(request).await.map(|r| r.map_into_left_body());
+    }
+
+    let response = HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, drain.retry_after.as_secs().to_string()))
+        .insert_header((header::CONNECTION, "close"))
+        .body("Server is shutting down");
+
+    Ok(request.into_response(response).map_into_right_body())
+}
+
+/// The trace id and parent span id of a W3C `traceparent` header, `None` if the header is malformed
+fn trace_context(traceparent: &str) -> Option<(String, String)> {
+    let mut parts = traceparent.trim().split('-');
+    let (_version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+
+    let is_id = |id: &str, len| id.len() == len && id.bytes().all(|b| b.is_ascii_hexdigit());
+
+    (is_id(trace_id, 32) && is_id(span_id, 16)).then(|| (trace_id.to_string(), span_id.to_string()))
+}
+
+/// Logs a line per HTTP request, see `--log-http`. Requests sent with a `traceparent` header are logged with its
+/// `trace_id` and `span_id`, so the line can be correlated with the client's trace
+async fn log_http_request(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let path = request.path().to_string();
+
+    let trace = request
+        .headers()
+        .get("traceparent")
+        .and_then(|traceparent| traceparent.to_str().ok())
+        .and_then(trace_context);
+
+    let response = next.call(request).await?;
+
+    let (method, path) = (method.as_str(), path.as_str());
+    let status = response.status().as_u16();
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    match trace {
+        Some((trace_id, span_id)) => log::info!(
+            method,
+            path,
+            status,
+            duration_ms,
+            trace_id = trace_id.as_str(),
+            span_id = span_id.as_str();
+            "HTTP request"
+        ),
+        None => log::info!(method, path, status, duration_ms; "HTTP request"),
+    }
+
+    Ok(response)
+}
+
+#[derive(clap::Args, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ServerConfig {
+    /// Port the graphql server will run on [default: 9000]
+    #[clap(short, long, env = "LINEAGEDB_PORT")]
+    port: Option<u16>,
+
+    /// Address the graphql server will run on [default: 0.0.0.0]
+    #[clap(short, long, env = "LINEAGEDB_ADDRESS")]
+    address: Option<String>,
+
+    /// Whether to log out GraphQL HTTP requests
+    #[clap(long, env = "LINEAGEDB_LOG_HTTP", num_args = 0..=1, default_missing_value = "true")]
+    log_http: Option<bool>,
+
+    /// [default: 2]
+    #[clap(long, env = "LINEAGEDB_HTTP_WORKERS")]
+    http_workers: Option<usize>,
+
+    /// On Ctrl-C, how long in-flight requests have to finish before the database is shut down [default: 30]
+    #[clap(long, env = "LINEAGEDB_DRAIN_TIMEOUT_SECS")]
+    drain_timeout_secs: Option<u64>,
+
+    /// Humans `createHumans` creates per transaction, larger inputs are split into chunks [default: 500]
+    #[clap(long, env = "LINEAGEDB_CREATE_HUMANS_CHUNK_SIZE")]
+    create_humans_chunk_size: Option<usize>,
+
+    /// Humans a single `createHumans` call can create, larger calls are rejected [default: 10000]
+    #[clap(long, env = "LINEAGEDB_MAX_CREATE_HUMANS")]
+    max_create_humans: Option<usize>,
+
+    /// Serves the admin UI at /admin, it can snapshot and pause the database so it should not be exposed publicly
+    #[clap(long, env = "LINEAGEDB_ADMIN_UI", num_args = 0..=1, default_missing_value = "true")]
+    admin_ui: Option<bool>,
+
+    /// Serves the latest snapshot and WAL tail at /snapshot/download and snapshot diffs at /snapshot/diff to requests
+    /// with an `Authorization: Bearer <token>` header. The endpoints are disabled unless a token is set
+    #[clap(long, env = "LINEAGEDB_SNAPSHOT_DOWNLOAD_TOKEN")]
+    snapshot_download_token: Option<String>,
+
+    /// Serves snapshot, compact-wal, vacuum-attachments, squash-history and backup operations at /operations to requests
+    /// with an `Authorization: Bearer <token>` header. The endpoints are disabled unless a token is set
+    #[clap(long, env = "LINEAGEDB_OPERATIONS_TOKEN")]
+    operations_token: Option<String>,
+
+    /// Omits a field of humans from the GraphQL responses of a role (see the x-role header) as <role>=<field>, e.g.
+    /// support=email. Fields: email, address, phone_numbers. Can be provided multiple times
+    #[clap(long, env = "LINEAGEDB_REDACT_FIELD", value_delimiter = ',')]
+    redact_field: Option<Vec<String>>,
+}
+
+impl ServerConfig {
+    /// Parses `<role>=<field>` redactions, requests without a role are redacted as the default role
+    fn field_redactions(
+        &self,
+        default_role: Option<String>,
+    ) -> Result<FieldRedactions, ConfigError> {
+        self.redact_field.iter().flatten().try_fold(
+            FieldRedactions::new(default_role),
+            |redactions, value| {
+                let invalid = |reason: String| ConfigError::InvalidValue("redact_field", reason);
+
+                let (role, field) = value
+                    .split_once('=')
+                    .ok_or_else(|| invalid(format!("expected <role>=<field>, got: {}", value)))?;
+
+                Ok(redactions.add_redaction(role.to_string(), field.parse().map_err(invalid)?))
+            },
+        )
+    }
+}
+
+/// Layout of the config file, see `--config`
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    server: ServerConfig,
+    #[serde(default)]
+    database: DatabaseConfig,
+}
+
+/// The server config, database options, row policies and field redactions, see `Cli::load`
+type LoadedConfig = (
+    ServerConfig,
+    DatabaseOptions,
+    Vec<RowPolicy>,
+    FieldRedactions,
+);
+
+/// 📀 Lineagedb GraphQL Server, provides a simple GraphQL interface for interacting with the database
+#[derive(Parser, Debug)]
+struct Cli {
+    /// TOML config file with `[server]` and `[database]` tables, command line arguments and environment variables take precedence
+    #[clap(long, env = "LINEAGEDB_CONFIG")]
+    config: Option<std::path::PathBuf>,
+
+    #[clap(flatten)]
+    server: ServerConfig,
+
+    #[clap(flatten)]
+    database: DatabaseConfig,
+
+    #[clap(flatten)]
+    logging: LoggingConfig,
+}
+
+impl Cli {
+    fn load(self) -> Result<LoadedConfig, ConfigError> {
+        let file: ConfigFile = match &self.config {
+            Some(path) => read_config_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let server = ServerConfig {
+            port: self.server.port.or(file.server.port),
+            address: self.server.address.or(file.server.address),
+            log_http: self.server.log_http.or(file.server.log_http),
+            http_workers: self.server.http_workers.or(file.server.http_workers),
+            drain_timeout_secs: self
+                .server
+                .drain_timeout_secs
+                .or(file.server.drain_timeout_secs),
+            create_humans_chunk_size: self
+                .server
+                .create_humans_chunk_size
+                .or(file.server.create_humans_chunk_size),
+            max_create_humans: self
+                .server
+                .max_create_humans
+                .or(file.server.max_create_humans),
+            admin_ui: self.server.admin_ui.or(file.server.admin_ui),
+            snapshot_download_token: self
+                .server
+                .snapshot_download_token
+                .or(file.server.snapshot_download_token),
+            operations_token: self
+                .server
+                .operations_token
+                .or(file.server.operations_token),
+            redact_field: self.server.redact_field.or(file.server.redact_field),
+        };
+
+        let database = file.database.merge(self.database);
+        let redactions = server.field_redactions(database.default_role.clone())?;
+
+        Ok((
+            server,
+            database.to_options()?,
+            database.row_policies()?,
+            redactions,
+        ))
+    }
 }
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let cli = Cli::parse();
 
-    let args = Cli::parse();
+    cli.logging.init("lineagedb-graphql");
 
-    let database_options = DatabaseOptions::default().set_storage_engine(to_storage_engine(&args));
+    let (server, database_options, row_policies, redactions) = match cli.load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            log::logger().flush();
+            std::process::exit(1);
+        }
+    };
+
+    let port = server.port.unwrap_or(9000);
+    let address = server.address.unwrap_or("0.0.0.0".to_string());
+    let log_http = server.log_http.unwrap_or(false);
+    let drain_timeout = Duration::from_secs(server.drain_timeout_secs.unwrap_or(30));
+    let bulk_limits = BulkLimits {
+        chunk_size: server.create_humans_chunk_size.unwrap_or(500).max(1),
+        max_humans: server.max_create_humans.unwrap_or(10_000),
+    };
 
     // For S3 (an optional backing storage engine), we must use tokio. This would be fine
     //  but the database uses sync apis (blocking_send). blocking_send CANNOT be called with any call-stack
@@ -142,48 +629,140 @@ async fn main() -> io::Result<()> {
     // tasks.
     //
     // Context reference: Actix (Async) -> Database (Sync) -> Tokio S3 (Async)
-    let request_manager: RequestManager = spawn_blocking(|| Database::new(database_options).run())
-        .await
-        .unwrap();
+    let request_manager: RequestManager = spawn_blocking(move || {
+        let request_manager = Database::new(database_options).run();
 
-    // Set up Ctrl-C handler
-    let set_handler_database_sender_clone = request_manager.clone();
+        for policy in row_policies {
+            let status = request_manager
+                .send_create_policy_request(policy)
+                .expect("Should be able to create row policies on startup");
 
-    ctrlc::set_handler(move || {
-        let shutdown_response = set_handler_database_sender_clone
-            .clone()
-            .send_shutdown_request(ShutdownRequest::Coordinator)
-            .expect("Should not timeout");
+            log::info!("{}", status);
+        }
 
-        log::info!("Shutting down server: {}", shutdown_response);
+        request_manager
     })
-    .expect("Error setting Ctrl-C handler");
+    .await
+    .unwrap();
 
     // Create Juniper schema
     let schema = Arc::new(create_schema());
 
-    log::info!("starting HTTP server on port {}.", args.port);
+    log::info!("starting HTTP server on port {}.", port);
 
-    log::info!(
-        "GraphiQL playground: http://{}:{}/graphiql",
-        args.address,
-        args.port
-    );
+    log::info!("GraphiQL playground: http://{}:{}/graphiql", address, port);
+
+    let admin_ui_enabled = server.admin_ui.unwrap_or(false);
+
+    if admin_ui_enabled {
+        log::info!("Admin UI: http://{}:{}/admin", address, port);
+    }
+
+    let snapshot_download_token = server
+        .snapshot_download_token
+        .filter(|token| !token.is_empty())
+        .map(|token| Data::new(SnapshotDownloadToken(token)));
+
+    if snapshot_download_token.is_some() {
+        log::info!(
+            "Snapshot download: http://{}:{}/snapshot/download",
+            address,
+            port
+        );
+    }
+
+    let operations_token = server
+        .operations_token
+        .filter(|token| !token.is_empty())
+        .map(|token| Data::new(OperationsToken(token)));
+
+    if operations_token.is_some() {
+        log::info!("Operations: http://{}:{}/operations", address, port);
+    }
+
+    let drain = Data::new(Drain {
+        draining: AtomicBool::new(false),
+        retry_after: drain_timeout,
+    });
+
+    let app_request_manager = request_manager.clone();
+    let app_drain = drain.clone();
+    let redactions = Data::new(redactions);
 
-    // Start HTTP server
-    HttpServer::new(move || {
+    // Start HTTP server, signals are handled below so the database outlives the in-flight requests
+    let http_server = HttpServer::new(move || {
         let app = App::new()
             .app_data(Data::from(schema.clone()))
-            .app_data(web::Data::new(request_manager.clone()))
+            .app_data(web::Data::new(app_request_manager.clone()))
+            .app_data(web::Data::new(bulk_limits))
+            .app_data(app_drain.clone())
+            .app_data(redactions.clone())
             .service(graphql)
+            .service(graphql_subscriptions)
             .service(graphql_playground)
+            .service(export_arrow)
+            .configure(|config| {
+                if admin_ui_enabled {
+                    config.service(admin_page);
+                }
+
+                if let Some(token) = &snapshot_download_token {
+                    config
+                        .app_data(token.clone())
+                        .service(download_snapshot)
+                        .service(snapshot_diff);
+                }
+
+                if let Some(token) = &operations_token {
+                    config
+                        .app_data(token.clone())
+                        .service(operations::start_operation)
+                        .service(operations::operation_status)
+                        .service(operations::operation_archive);
+                }
+            })
+            .wrap(from_fn(reject_while_draining))
             .wrap(Cors::permissive())
-            .wrap(Condition::new(args.log_http, middleware::Logger::default()));
+            .wrap(Condition::new(log_http, from_fn(log_http_request)));
 
         app
     })
-    .workers(args.http_workers)
-    .bind((args.address, args.port))?
-    .run()
+    .workers(server.http_workers.unwrap_or(2))
+    .shutdown_timeout(drain_timeout.as_secs())
+    .disable_signals()
+    .bind((address, port))?
+    .run();
+
+    let server_handle = http_server.handle();
+
+    // Set up Ctrl-C handler, stops accepting connections and waits up to the drain timeout for in-flight requests
+    ctrlc::set_handler(move || {
+        if drain.draining.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        log::info!(
+            "Draining in-flight requests, waiting up to {} seconds",
+            drain_timeout.as_secs()
+        );
+
+        // The stop command is sent immediately, the returned future only waits for the server to stop
+        let _ = server_handle.stop(true);
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    http_server.await?;
+
+    let shutdown_response = spawn_blocking(move || {
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .expect("Should not timeout")
+    })
     .await
+    .unwrap();
+
+    log::info!("Shutting down server: {}", shutdown_response);
+    log::logger().flush();
+
+    Ok(())
 }
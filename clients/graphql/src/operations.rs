@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use actix_web::{
+    get, http::header, post, rt::task::spawn_blocking, web, HttpRequest, HttpResponse, Responder,
+};
+use database::{
+    database::{
+        commands::{Control, DatabaseCommandControlResponse},
+        operations::{OperationId, OperationState, OperationStatus},
+    },
+    prelude::{RequestManager, RequestManagerError},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{bearer_authorizes, schema::DatabaseOperation};
+
+/// Long polls are answered after at most this long, so they end before the timeouts of proxies and clients
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Name of the control a backup runs as, see `OperationStatus::kind`
+const BACKUP_KIND: &str = "DownloadSnapshot";
+
+/// Bearer token that authorizes the operation endpoints, see `--operations-token`
+pub struct OperationsToken(pub String);
+
+impl OperationsToken {
+    fn authorizes(&self, request: &HttpRequest) -> bool {
+        bearer_authorizes(request, &self.0)
+    }
+}
+
+#[derive(Deserialize)]
+struct StartOperationQuery {
+    /// Used by `vacuum-attachments`, attachments written less than this many seconds ago are kept
+    min_age_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct OperationStatusQuery {
+    /// Holds the response of a running operation until it finishes, for up to this many seconds
+    wait_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct OperationResponse {
+    #[serde(flatten)]
+    operation: DatabaseOperation,
+    /// Where the archive of a finished backup is downloaded from, the archive is not part of `result`
+    archive: Option<String>,
+}
+
+impl OperationResponse {
+    fn from_status(status: OperationStatus) -> Self {
+        let archive = match &status.state {
+            OperationState::Completed(response) if status.kind == BACKUP_KIND => {
+                matches!(**response, DatabaseCommandControlResponse::Success(_))
+                    .then(|| format!("/operations/{}/archive", status.id))
+            }
+            _ => None,
+        };
+
+        let mut operation = DatabaseOperation::from_status(status);
+
+        if archive.is_some() {
+            operation.result.clear();
+        }
+
+        OperationResponse { operation, archive }
+    }
+}
+
+fn operation_control(kind: &str, min_age: Duration) -> Option<Control> {
+    match kind {
+        "snapshot" => Some(Control::SnapshotDatabase),
+        "compact-wal" => Some(Control::CompactWal),
+        "vacuum-attachments" => Some(Control::VacuumAttachments(min_age)),
+        "squash-history" => Some(Control::SquashHistory),
+        "backup" => Some(Control::DownloadSnapshot),
+        _ => None,
+    }
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .finish()
+}
+
+fn error_response(e: RequestManagerError) -> HttpResponse {
+    match e {
+        // The status of an operation is only an error if the operation is unknown or was dropped
+        RequestManagerError::DatabaseErrorStatus(e) => HttpResponse::NotFound().body(e),
+        RequestManagerError::DatabaseRestarting => {
+            HttpResponse::ServiceUnavailable().body(e.to_string())
+        }
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// The operation's status, waits up to `wait` for it to finish if it is running
+fn wait_for_status(
+    request_manager: &RequestManager,
+    id: OperationId,
+    wait: Duration,
+) -> Result<OperationStatus, RequestManagerError> {
+    let status = request_manager.send_operation_status_request(id)?;
+
+    if status.is_finished() || wait.is_zero() {
+        return Ok(status);
+    }
+
+    match request_manager.wait_for_operation(id, wait) {
+        Err(RequestManagerError::DatabaseTimeout) => {
+            request_manager.send_operation_status_request(id)
+        }
+        result => result,
+    }
+}
+
+/// Starts a control as an operation and responds straight away with a 202, the operation's status is at the
+/// `Location` header. Kinds: snapshot, compact-wal, vacuum-attachments, squash-history and backup
+#[post("/operations/{kind}")]
+pub async fn start_operation(
+    request: HttpRequest,
+    request_manager: web::Data<RequestManager>,
+    token: web::Data<OperationsToken>,
+    kind: web::Path<String>,
+    query: web::Query<StartOperationQuery>,
+) -> impl Responder {
+    if !token.authorizes(&request) {
+        return unauthorized();
+    }
+
+    let min_age = Duration::from_secs(query.min_age_secs.unwrap_or(3600));
+
+    let Some(control) = operation_control(&kind, min_age) else {
+        return HttpResponse::NotFound().body(format!("Unknown operation kind {}", kind));
+    };
+
+    match request_manager.send_start_operation_request(control) {
+        Ok(status) => HttpResponse::Accepted()
+            .insert_header((header::LOCATION, format!("/operations/{}", status.id)))
+            .json(OperationResponse::from_status(status)),
+        Err(RequestManagerError::DatabaseErrorStatus(e)) => {
+            HttpResponse::InternalServerError().body(e)
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// The status of an operation, finished operations are kept for a while. With `wait_secs` (at most 60) the
+/// response to a running operation is held until it finishes or the wait is over, i.e. a long poll
+#[get("/operations/{id}")]
+pub async fn operation_status(
+    request: HttpRequest,
+    request_manager: web::Data<RequestManager>,
+    token: web::Data<OperationsToken>,
+    id: web::Path<usize>,
+    query: web::Query<OperationStatusQuery>,
+) -> impl Responder {
+    if !token.authorizes(&request) {
+        return unauthorized();
+    }
+
+    let id = OperationId(id.into_inner());
+    let wait = Duration::from_secs(query.wait_secs.unwrap_or(0)).min(MAX_WAIT);
+
+    let request_manager = request_manager.get_ref().clone();
+
+    // The wait blocks, it must not hold the HTTP worker
+    let status = spawn_blocking(move || wait_for_status(&request_manager, id, wait)).await;
+
+    match status {
+        Ok(Ok(status)) => HttpResponse::Ok().json(OperationResponse::from_status(status)),
+        Ok(Err(e)) => error_response(e),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// The archive of a finished backup operation as newline delimited JSON, the same archive as `/snapshot/download`
+#[get("/operations/{id}/archive")]
+pub async fn operation_archive(
+    request: HttpRequest,
+    request_manager: web::Data<RequestManager>,
+    token: web::Data<OperationsToken>,
+    id: web::Path<usize>,
+) -> impl Responder {
+    if !token.authorizes(&request) {
+        return unauthorized();
+    }
+
+    match request_manager.send_operation_status_request(OperationId(id.into_inner())) {
+        Ok(OperationStatus {
+            kind,
+            state: OperationState::Completed(response),
+            ..
+        }) if kind == BACKUP_KIND => match *response {
+            DatabaseCommandControlResponse::Success(archive) => HttpResponse::Ok()
+                .content_type("application/x-ndjson")
+                .body(archive),
+            _ => HttpResponse::NotFound().body("The backup failed, see the operation's result"),
+        },
+        Ok(status) if !status.is_finished() => {
+            HttpResponse::Conflict().body("The operation is still running")
+        }
+        Ok(_) => HttpResponse::NotFound().body("The operation is not a finished backup"),
+        Err(e) => error_response(e),
+    }
+}
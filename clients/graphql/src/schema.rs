@@ -1,35 +1,220 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
+use base64::prelude::{Engine, BASE64_STANDARD};
 use database::{
     consts::consts::EntityId,
     database::{
-        commands::{SnapshotTimestamp, TransactionContext},
-        request_manager::RequestManager,
+        activity::{ActivityReport, RequestId},
+        commands::{
+            Control, DatabaseCommandControlResponse, MaintenanceTask, SnapshotTimestamp,
+            TransactionContext,
+        },
+        operations::{OperationId, OperationState, OperationStatus},
+        protocol::{
+            decode_statements, encode_results, statement_proto, Capabilities, ClientHello,
+            Negotiated,
+        },
+        request_manager::{ConditionalRead, RequestManager},
+        scheduler::{JobAction, JobDefinition},
         table::{
-            query::{QueryMatch, QueryPersonData},
-            row::{UpdatePersonData, UpdateStatement},
+            attachment::AttachmentContent,
+            history::{HistoryCursor, HistoryRequest},
+            index::IndexedField,
+            meta::EntityMeta,
+            outbox::{OutboxId, OutboxMessage},
+            pagination::{Cursor, PageRequest},
+            prepared_query::{ParameterField, PreparedQueryDefinition, QueryOrder, QueryParameter},
+            query::{QueryAddressData, QueryMatch, QueryPersonData},
+            row::{
+                Lineage, PersonVersion, UpdateAddressData, UpdateAddressStatement,
+                UpdateListStatement, UpdatePersonData, UpdateStatement,
+            },
+            view::{PersonField, ViewDefinition, ViewResult},
+            watermark::TableVersion,
         },
     },
-    model::{person::Person, statement::Statement},
+    model::{
+        person::{Address, Attachment, Person},
+        statement::Statement,
+    },
+    prelude::EntityWatchError,
 };
-use juniper::{EmptySubscription, FieldResult, Nullable, RootNode};
-use uuid::Uuid;
+use juniper::{
+    futures::{stream, Stream},
+    graphql_value, FieldError, FieldResult, IntoFieldError, Nullable, RootNode, ScalarValue,
+};
+use serde::Serialize;
 
 pub struct GraphQLContext {
     pub request_manager: RequestManager,
+    pub bulk_limits: BulkLimits,
+    /// Identifies the client making the request, see `x-client-id`
+    pub client_id: Option<String>,
+    /// The role the request runs as, see `x-role`
+    pub role: Option<String>,
+    /// Fields the request's role cannot read, see `--redact-field`
+    pub redactions: Arc<FieldRedactions>,
+    /// Mutations are rolled back after they are applied, see `x-dry-run`
+    pub dry_run: bool,
+    /// Tags the request for per tag metrics and limits, see `x-request-tag`
+    pub tag: Option<String>,
+    /// The version of the client's cached result, see `If-None-Match`
+    pub if_none_match: Option<TableVersion>,
+    /// Conditional reads of the request (whether each was not modified and the version it returned), the server
+    /// answers with an `ETag` and, if none of them were modified, `304 Not Modified`
+    pub conditional_reads: Mutex<Vec<(bool, TableVersion)>>,
+}
+
+impl GraphQLContext {
+    fn transaction_context(&self, snapshot_timestamp: SnapshotTimestamp) -> TransactionContext {
+        TransactionContext::new(snapshot_timestamp)
+            .set_client_id(self.client_id.clone())
+            .set_role(self.role.clone())
+            .set_dry_run(self.dry_run)
+            .set_tag(self.tag.clone())
+    }
+
+    fn redacts(&self, field: RedactedField) -> bool {
+        self.redactions.redacts(self.role.as_deref(), field)
+    }
+}
+
+/// Bounds on `createHumans`, see `--create-humans-chunk-size` and `--max-create-humans`
+#[derive(Clone, Copy, Debug)]
+pub struct BulkLimits {
+    /// Humans created per transaction
+    pub chunk_size: usize,
+    /// Humans a single call can create, larger calls are rejected before anything is created
+    pub max_humans: usize,
+}
+
+/// A field of a human that can be redacted, see `--redact-field`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedactedField {
+    Email,
+    Address,
+    PhoneNumbers,
+}
+
+impl FromStr for RedactedField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(RedactedField::Email),
+            "address" => Ok(RedactedField::Address),
+            "phone_numbers" => Ok(RedactedField::PhoneNumbers),
+            field => Err(format!(
+                "unknown field {}, expected email, address or phone_numbers",
+                field
+            )),
+        }
+    }
+}
+
+/// The fields of a human each role cannot read, they are omitted as humans are serialized so every query (and
+/// subscription) that returns a human is covered. Requests without a role use the default role
+#[derive(Clone, Debug, Default)]
+pub struct FieldRedactions {
+    roles: HashMap<String, Vec<RedactedField>>,
+    default_role: Option<String>,
+}
+
+impl FieldRedactions {
+    pub fn new(default_role: Option<String>) -> Self {
+        Self {
+            roles: HashMap::new(),
+            default_role,
+        }
+    }
+
+    pub fn add_redaction(mut self, role: String, field: RedactedField) -> Self {
+        self.roles.entry(role).or_default().push(field);
+        self
+    }
+
+    pub fn redacts(&self, role: Option<&str>, field: RedactedField) -> bool {
+        role.or(self.default_role.as_deref())
+            .and_then(|role| self.roles.get(role))
+            .is_some_and(|fields| fields.contains(&field))
+    }
 }
 
 // https://graphql-rust.github.io/juniper/master/types/objects/using_contexts.html
 impl juniper::Context for GraphQLContext {}
 
-use juniper::{GraphQLInputObject, GraphQLObject};
+use juniper::{GraphQLEnum, GraphQLInputObject, GraphQLObject};
 
-#[derive(GraphQLObject)]
-#[graphql(description = "A humanoid creature in the Star Wars universe")]
 struct Human {
     pub id: String,
     pub full_name: String,
     pub email: Option<String>,
+    pub address: Option<HumanAddress>,
+    pub phone_numbers: Vec<String>,
+    pub attachments: Vec<HumanAttachment>,
+}
+
+#[juniper::graphql_object(
+    context = GraphQLContext,
+    description = "A humanoid creature in the Star Wars universe"
+)]
+impl Human {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    /// Null if the field is redacted for the request's role
+    fn email(&self, context: &GraphQLContext) -> Option<&str> {
+        match context.redacts(RedactedField::Email) {
+            true => None,
+            false => self.email.as_deref(),
+        }
+    }
+
+    /// Null if the field is redacted for the request's role
+    fn address(&self, context: &GraphQLContext) -> Option<&HumanAddress> {
+        match context.redacts(RedactedField::Address) {
+            true => None,
+            false => self.address.as_ref(),
+        }
+    }
+
+    /// Empty if the field is redacted for the request's role
+    fn phone_numbers(&self, context: &GraphQLContext) -> &[String] {
+        match context.redacts(RedactedField::PhoneNumbers) {
+            true => &[],
+            false => self.phone_numbers.as_slice(),
+        }
+    }
+
+    fn attachments(&self) -> &[HumanAttachment] {
+        &self.attachments
+    }
+
+    /// Version count and last modification of the human, read with a request per human so only select it
+    /// when it is shown, see `humanMeta`
+    fn meta(&self, context: &GraphQLContext) -> FieldResult<Option<HumanMeta>> {
+        let tx_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let meta = context
+            .request_manager
+            .send_meta(EntityId(self.id.clone()), tx_context)?
+            .map(HumanMeta::from_meta);
+
+        Ok(meta)
+    }
 }
 
 impl Human {
@@ -38,227 +223,2054 @@ impl Human {
             id: person.id.to_string(),
             full_name: person.full_name,
             email: person.email,
+            address: person.address.map(HumanAddress::from_address),
+            phone_numbers: person.phone_numbers,
+            attachments: person
+                .attachments
+                .into_iter()
+                .map(HumanAttachment::from_attachment)
+                .collect(),
         }
     }
 }
 
-#[derive(GraphQLInputObject)]
-#[graphql(description = "A humanoid creature in the Star Wars universe")]
-struct NewHuman {
-    pub full_name: String,
-    pub email: Option<String>,
+#[derive(GraphQLObject)]
+#[graphql(
+    description = "A binary payload attached to a human, see `humanAttachment` for its content"
+)]
+struct HumanAttachment {
+    pub name: String,
+    /// SHA-256 of the content, hex encoded
+    pub digest: String,
+    /// Size of the content in bytes
+    pub size: f64,
+    pub content_type: Option<String>,
 }
 
-impl NewHuman {
-    pub fn to_person(self) -> Person {
-        Person {
-            id: EntityId(Uuid::new_v4().to_string()),
-            full_name: self.full_name,
-            email: self.email,
+impl HumanAttachment {
+    pub fn from_attachment(attachment: Attachment) -> HumanAttachment {
+        HumanAttachment {
+            name: attachment.name,
+            digest: attachment.digest,
+            size: attachment.size as f64,
+            content_type: attachment.content_type,
         }
     }
 }
 
-#[derive(GraphQLInputObject)]
-#[graphql(description = "A humanoid creature in the Star Wars universe")]
-pub struct UpdateHumanData {
-    pub full_name: Nullable<String>,
-    pub email: Nullable<String>,
+#[derive(GraphQLObject)]
+#[graphql(description = "The content of an attachment")]
+struct HumanAttachmentContent {
+    pub attachment: HumanAttachment,
+    pub content_base64: String,
 }
 
-#[derive(GraphQLInputObject)]
-#[graphql(description = "A humanoid creature in the Star Wars universe")]
-pub struct QueryHumanData {
-    pub full_name: Nullable<String>,
-    pub email: Nullable<String>,
+impl HumanAttachmentContent {
+    pub fn from_content(content: AttachmentContent) -> HumanAttachmentContent {
+        HumanAttachmentContent {
+            attachment: HumanAttachment::from_attachment(content.attachment),
+            content_base64: BASE64_STANDARD.encode(content.bytes),
+        }
+    }
 }
 
-pub struct QueryRoot;
+#[derive(GraphQLObject)]
+#[graphql(description = "How often and when a human changed, without its history")]
+struct HumanMeta {
+    pub version_count: i32,
+    pub first_transaction_id: i32,
+    pub last_transaction_id: i32,
+    /// Milliseconds since the epoch, null for humans that have not changed since the database started
+    pub last_modified_ms: Option<f64>,
+    pub deleted: bool,
+}
 
-#[juniper::graphql_object(context = GraphQLContext)]
-impl QueryRoot {
-    fn human(
-        id: String,
-        version_id: Option<i32>,
-        snapshot_id: Nullable<i32>,
-        context: &'db GraphQLContext,
-    ) -> FieldResult<Option<Human>> {
-        let request_manager = &context.request_manager;
+impl HumanMeta {
+    pub fn from_meta(meta: EntityMeta) -> HumanMeta {
+        HumanMeta {
+            version_count: meta.version_count as i32,
+            first_transaction_id: meta.first_transaction_id.0 as i32,
+            last_transaction_id: meta.last_transaction_id.0 as i32,
+            last_modified_ms: meta.last_modified_ms.map(|modified_ms| modified_ms as f64),
+            deleted: meta.deleted,
+        }
+    }
+}
 
-        let entity_id = EntityId(id);
+#[derive(GraphQLObject)]
+#[graphql(description = "Postal address of a human, every part is optional")]
+struct HumanAddress {
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+}
 
-        let snapshot_timestamp = match snapshot_id {
-            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
-            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
-        };
+impl HumanAddress {
+    pub fn from_address(address: Address) -> HumanAddress {
+        HumanAddress {
+            street: address.street,
+            city: address.city,
+            country: address.country,
+        }
+    }
+}
 
-        let tx_context = TransactionContext::new(snapshot_timestamp);
+#[derive(GraphQLObject)]
+#[graphql(description = "Outcome of one transaction of a createHumans call")]
+struct CreateHumansChunk {
+    /// Index of the chunk's first human in the input
+    pub start: i32,
+    pub size: i32,
+    /// Whether the chunk was committed, if not none of its humans were created
+    pub committed: bool,
+    pub error: Option<String>,
+}
 
-        let optional_person = match version_id {
-            Some(v) => request_manager.send_get_version(entity_id, v.try_into()?, tx_context)?,
-            None => request_manager.send_get(entity_id, tx_context)?,
-        };
+#[derive(GraphQLObject)]
+#[graphql(
+    context = GraphQLContext,
+    description = "Summary of a createHumans call, the input is created in chunks that commit independently"
+)]
+struct CreateHumansResult {
+    pub humans: Vec<Human>,
+    pub created_ids: Vec<String>,
+    /// Humans in chunks that were not committed
+    pub failed: i32,
+    pub chunks: Vec<CreateHumansChunk>,
+}
 
-        Ok(optional_person.and_then(|p| Some(Human::from_person(p))))
+/// Errors of `createHumans` that are raised before anything is created
+enum CreateHumansError {
+    TooManyHumans { count: usize, limit: usize },
+}
+
+impl<S: ScalarValue> IntoFieldError<S> for CreateHumansError {
+    fn into_field_error(self) -> FieldError<S> {
+        match self {
+            CreateHumansError::TooManyHumans { count, limit } => FieldError::new(
+                format!(
+                    "Unable to create {} humans in a single call, the limit is {}",
+                    count, limit
+                ),
+                graphql_value!({
+                    "code": "TOO_MANY_HUMANS",
+                    "count": (count as i32),
+                    "limit": (limit as i32),
+                }),
+            ),
+        }
     }
+}
 
-    fn list_human(
-        query: Nullable<QueryHumanData>,
-        snapshot_id: Nullable<i32>,
-        context: &'db GraphQLContext,
-    ) -> FieldResult<Vec<Human>> {
-        let request_manager = &context.request_manager;
+#[derive(GraphQLObject)]
+#[graphql(
+    context = GraphQLContext,
+    description = "A page of humans, pass the next cursor back in to get the next page"
+)]
+struct HumanPage {
+    pub humans: Vec<Human>,
+    pub next_cursor: Option<String>,
+}
 
-        let snapshot_timestamp = match snapshot_id {
-            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
-            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
-        };
+#[derive(GraphQLObject)]
+#[graphql(
+    context = GraphQLContext,
+    description = "A page of the versions of a human, pass the next cursor back in to get the next page"
+)]
+struct HumanHistoryPage {
+    pub versions: Vec<HumanVersion>,
+    pub next_cursor: Option<String>,
+}
 
-        let tx_context = TransactionContext::new(snapshot_timestamp);
-
-        let list_query = match query {
-            Nullable::ImplicitNull => None,
-            Nullable::ExplicitNull => None,
-            Nullable::Some(t) => {
-                let full_name = match t.full_name {
-                    Nullable::ImplicitNull => QueryMatch::Any,
-                    Nullable::ExplicitNull => QueryMatch::Null,
-                    Nullable::Some(t) => QueryMatch::Value(t),
-                };
-
-                let email = match t.email {
-                    Nullable::ImplicitNull => QueryMatch::Any,
-                    Nullable::ExplicitNull => QueryMatch::Null,
-                    Nullable::Some(t) => QueryMatch::Value(t),
-                };
-
-                Some(QueryPersonData { full_name, email })
-            }
-        };
+#[derive(GraphQLObject)]
+#[graphql(
+    context = GraphQLContext,
+    description = "Humans that are only listed if the table changed since the client's version, humans is empty when not modified"
+)]
+struct ConditionalHumans {
+    pub not_modified: bool,
+    pub humans: Vec<Human>,
+    /// Version of the result, sent back as `ifNotChangedSince` (or the `If-None-Match` header) on the next poll
+    pub etag: String,
+    /// The last transaction that modified the table as of the result
+    pub last_transaction_id: i32,
+}
 
-        let result = request_manager
-            .send_list(list_query, tx_context)?
-            .into_iter()
-            .map(Human::from_person)
-            .collect();
+#[derive(GraphQLEnum)]
+#[graphql(
+    description = "A field of a human, used to project views and to pick the fields that survive a merge"
+)]
+enum HumanField {
+    FullName,
+    Email,
+}
 
-        return Ok(result);
+impl HumanField {
+    pub fn to_person_field(self) -> PersonField {
+        match self {
+            HumanField::FullName => PersonField::FullName,
+            HumanField::Email => PersonField::Email,
+        }
     }
+}
 
-    fn database_info(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
-        let request_manager = &context.request_manager;
-
-        let database_info = request_manager
-            .send_info_request()?
-            .into_iter()
-            .map(|r| format!("[{}] {}", r.0, r.1))
-            .collect();
+#[derive(GraphQLEnum)]
+#[graphql(description = "An action that can be run on a schedule")]
+enum ScheduledAction {
+    Snapshot,
+    DatabaseStats,
+    CompactWal,
+    SquashHistory,
+    IndexAdvisor,
+}
 
-        return Ok(database_info);
+impl ScheduledAction {
+    pub fn to_job_action(self) -> JobAction {
+        match self {
+            ScheduledAction::Snapshot => JobAction::Snapshot,
+            ScheduledAction::DatabaseStats => JobAction::DatabaseStats,
+            ScheduledAction::CompactWal => JobAction::CompactWal,
+            ScheduledAction::SquashHistory => JobAction::SquashHistory,
+            ScheduledAction::IndexAdvisor => JobAction::IndexAdvisor,
+        }
     }
+}
 
-    fn sleep(sleep: i32, context: &'db GraphQLContext) -> FieldResult<String> {
-        let request_manager = &context.request_manager;
+#[derive(GraphQLEnum)]
+#[graphql(
+    description = "A field of a human that is not indexed by default, an index can be created with createIndex"
+)]
+enum HumanIndexField {
+    Street,
+    City,
+    Country,
+    PhoneNumber,
+}
 
-        let sleep_duration: Duration = Duration::from_secs(sleep as u64);
+impl HumanIndexField {
+    pub fn to_indexed_field(self) -> IndexedField {
+        match self {
+            HumanIndexField::Street => IndexedField::Street,
+            HumanIndexField::City => IndexedField::City,
+            HumanIndexField::Country => IndexedField::Country,
+            HumanIndexField::PhoneNumber => IndexedField::PhoneNumber,
+        }
+    }
+}
 
-        let status = request_manager.send_sleep_request(sleep_duration)?;
+#[derive(GraphQLEnum)]
+#[graphql(description = "Work that is run while the database is in maintenance mode")]
+enum MaintenanceAction {
+    Snapshot,
+}
 
-        return Ok(status);
+impl MaintenanceAction {
+    pub fn to_maintenance_task(self) -> MaintenanceTask {
+        match self {
+            MaintenanceAction::Snapshot => MaintenanceTask::Snapshot,
+        }
     }
 }
 
-pub struct MutationRoot;
+#[derive(GraphQLEnum)]
+#[graphql(
+    description = "Controls that can run for longer than a request waits, see `startOperation`"
+)]
+enum OperationKind {
+    Snapshot,
+    CompactWal,
+    VacuumAttachments,
+    SquashHistory,
+}
 
-#[juniper::graphql_object(context = GraphQLContext)]
-impl MutationRoot {
-    fn create_human(new_human: NewHuman, context: &'db GraphQLContext) -> FieldResult<Human> {
-        let request_manager = &context.request_manager;
+impl OperationKind {
+    pub fn to_control(self, min_age: Duration) -> Control {
+        match self {
+            OperationKind::Snapshot => Control::SnapshotDatabase,
+            OperationKind::CompactWal => Control::CompactWal,
+            OperationKind::VacuumAttachments => Control::VacuumAttachments(min_age),
+            OperationKind::SquashHistory => Control::SquashHistory,
+        }
+    }
+}
 
-        let transaction_context = TransactionContext::default();
+#[derive(GraphQLObject, Serialize)]
+#[graphql(description = "A long running control, poll `operation` until it is no longer running")]
+pub struct DatabaseOperation {
+    pub id: i32,
+    pub kind: String,
+    pub elapsed_ms: f64,
+    /// Running, Completed or Abandoned (the control did not respond)
+    pub state: String,
+    /// Null while the operation is running
+    pub succeeded: Option<bool>,
+    /// The control's response, one line per reported value
+    pub result: Vec<String>,
+}
 
-        // Might seem a bit weird, but this is to ensure that the id is unique
-        let new_person = request_manager.send_add(new_human.to_person(), transaction_context)?;
+impl DatabaseOperation {
+    pub fn from_status(status: OperationStatus) -> DatabaseOperation {
+        let (state, succeeded, result) = match status.state {
+            OperationState::Running => ("Running", None, vec![]),
+            OperationState::Abandoned => ("Abandoned", Some(false), vec![]),
+            OperationState::Completed(response) => match *response {
+                DatabaseCommandControlResponse::Success(s) => ("Completed", Some(true), vec![s]),
+                DatabaseCommandControlResponse::Error(e) => ("Completed", Some(false), vec![e]),
+                DatabaseCommandControlResponse::Info(info) => (
+                    "Completed",
+                    Some(true),
+                    info.into_iter()
+                        .map(|r| format!("[{}] {}", r.0, r.1))
+                        .collect(),
+                ),
+                DatabaseCommandControlResponse::Activity(_)
+                | DatabaseCommandControlResponse::Operation(_) => ("Completed", Some(true), vec![]),
+            },
+        };
 
-        Ok(Human::from_person(new_person))
+        DatabaseOperation {
+            id: status.id.0 as i32,
+            kind: status.kind,
+            elapsed_ms: status.elapsed.as_secs_f64() * 1000.0,
+            state: state.to_string(),
+            succeeded,
+            result,
+        }
     }
+}
 
-    fn create_humans(
-        new_humans: Vec<NewHuman>,
-        context: &'db GraphQLContext,
-    ) -> FieldResult<Vec<Human>> {
-        let request_manager = &context.request_manager;
+#[derive(GraphQLObject)]
+#[graphql(description = "A request that is currently running on a database worker")]
+struct ActiveRequestInfo {
+    pub request_id: i32,
+    pub thread_id: i32,
+    pub transaction_id: i32,
+    pub kind: String,
+    pub client_id: Option<String>,
+    pub tag: Option<String>,
+    pub elapsed_ms: f64,
+}
 
-        let transaction_context = TransactionContext::default();
+#[derive(GraphQLObject)]
+#[graphql(description = "Counters for the requests made by a client since the database started")]
+struct ClientStatsInfo {
+    pub client_id: String,
+    pub requests: i32,
+    pub rollbacks: i32,
+    pub total_elapsed_ms: f64,
+    pub last_seen_ms_ago: f64,
+}
 
-        let add_people = new_humans
-            .into_iter()
-            .map(NewHuman::to_person)
-            .map(Statement::Add)
-            .collect();
+#[derive(GraphQLObject)]
+#[graphql(description = "Counters for the requests with a tag since the database started")]
+struct TagStatsInfo {
+    pub tag: String,
+    pub requests: i32,
+    pub rollbacks: i32,
+    pub total_elapsed_ms: f64,
+}
 
-        // TODO: In this context we can use single, but, because it can panic an exception
-        //  we probably shouldn't
-        let humans = request_manager
-            .send_transaction(add_people, transaction_context)?
-            .into_iter()
-            .map(|r| Human::from_person(r.single()))
-            .collect();
+#[derive(GraphQLObject)]
+#[graphql(description = "Rows of a system table, every value is formatted as a string")]
+struct DatabaseSystemTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
 
-        Ok(humans)
+#[derive(GraphQLObject)]
+#[graphql(description = "What the database supports, clients should only use what is listed")]
+struct DatabaseCapabilities {
+    pub protocol_version: i32,
+    pub min_protocol_version: i32,
+    pub statements: Vec<String>,
+    pub formats: Vec<String>,
+    pub features: Vec<String>,
+}
+
+impl DatabaseCapabilities {
+    pub fn from_capabilities(capabilities: Capabilities) -> DatabaseCapabilities {
+        DatabaseCapabilities {
+            protocol_version: capabilities.protocol_version as i32,
+            min_protocol_version: capabilities.min_protocol_version as i32,
+            statements: capabilities.statements,
+            formats: capabilities.formats.iter().map(|f| f.to_string()).collect(),
+            features: capabilities
+                .features
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        }
     }
+}
 
-    fn update_human(
-        id: String,
-        update_human: UpdateHumanData,
-        context: &'db GraphQLContext,
-    ) -> FieldResult<Human> {
-        let request_manager = &context.request_manager;
+#[derive(GraphQLObject)]
+#[graphql(description = "What the client and the database agreed on for the session")]
+struct NegotiatedSession {
+    pub protocol_version: i32,
+    pub format: String,
+    pub features: Vec<String>,
+    pub unsupported_features: Vec<String>,
+}
 
-        let transaction_context = TransactionContext::default();
+impl NegotiatedSession {
+    pub fn from_negotiated(negotiated: Negotiated) -> NegotiatedSession {
+        NegotiatedSession {
+            protocol_version: negotiated.protocol_version as i32,
+            format: negotiated.format.to_string(),
+            features: negotiated.features.iter().map(|f| f.to_string()).collect(),
+            unsupported_features: negotiated.unsupported_features,
+        }
+    }
+}
 
-        let full_name_update = match update_human.full_name {
-            Nullable::ImplicitNull => UpdateStatement::NoChanges,
-            Nullable::ExplicitNull => UpdateStatement::Unset,
-            Nullable::Some(t) => UpdateStatement::Set(t),
-        };
+#[derive(GraphQLObject)]
+#[graphql(description = "What the database is doing right now, similar to pg_stat_activity")]
+struct DatabaseActivity {
+    pub active_requests: Vec<ActiveRequestInfo>,
+    pub clients: Vec<ClientStatsInfo>,
+    pub tags: Vec<TagStatsInfo>,
+}
 
-        let email_update = match update_human.email {
-            Nullable::ImplicitNull => UpdateStatement::NoChanges,
-            Nullable::ExplicitNull => UpdateStatement::Unset,
-            Nullable::Some(t) => UpdateStatement::Set(t),
-        };
+impl DatabaseActivity {
+    pub fn from_report(report: ActivityReport) -> DatabaseActivity {
+        DatabaseActivity {
+            active_requests: report
+                .active_requests
+                .into_iter()
+                .map(|r| ActiveRequestInfo {
+                    request_id: r.request_id.0 as i32,
+                    thread_id: r.thread_id as i32,
+                    transaction_id: r.transaction_id.0 as i32,
+                    kind: r.kind,
+                    client_id: r.client_id,
+                    tag: r.tag,
+                    elapsed_ms: r.elapsed.as_secs_f64() * 1000.0,
+                })
+                .collect(),
+            clients: report
+                .clients
+                .into_iter()
+                .map(|c| ClientStatsInfo {
+                    client_id: c.client_id,
+                    requests: c.requests as i32,
+                    rollbacks: c.rollbacks as i32,
+                    total_elapsed_ms: c.total_elapsed.as_secs_f64() * 1000.0,
+                    last_seen_ms_ago: c.last_seen.as_secs_f64() * 1000.0,
+                })
+                .collect(),
+            tags: report
+                .tags
+                .into_iter()
+                .map(|t| TagStatsInfo {
+                    tag: t.tag,
+                    requests: t.requests as i32,
+                    rollbacks: t.rollbacks as i32,
+                    total_elapsed_ms: t.total_elapsed.as_secs_f64() * 1000.0,
+                })
+                .collect(),
+        }
+    }
+}
 
-        let update_person_date = UpdatePersonData {
-            full_name: full_name_update,
-            email: email_update,
-        };
+struct HumanViewRow {
+    pub id: String,
+    pub full_name: Option<String>,
+    pub email: Option<String>,
+}
 
-        let person =
-            request_manager.send_update(EntityId(id), update_person_date, transaction_context)?;
+#[juniper::graphql_object(
+    context = GraphQLContext,
+    description = "A projected human, fields outside of the view's projection are null"
+)]
+impl HumanViewRow {
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-        Ok(Human::from_person(person))
+    fn full_name(&self) -> Option<&str> {
+        self.full_name.as_deref()
     }
 
-    fn snapshot(context: &'db GraphQLContext) -> FieldResult<String> {
-        let request_manager = &context.request_manager;
+    /// Null if the field is redacted for the request's role
+    fn email(&self, context: &GraphQLContext) -> Option<&str> {
+        match context.redacts(RedactedField::Email) {
+            true => None,
+            false => self.email.as_deref(),
+        }
+    }
+}
 
-        let shutdown_status = request_manager.send_snapshot_request()?;
+#[derive(GraphQLObject)]
+#[graphql(
+    context = GraphQLContext,
+    description = "The rows of a materialized view or prepared query, fresh as of the transaction id"
+)]
+struct HumanView {
+    pub rows: Vec<HumanViewRow>,
+    pub freshness_transaction_id: i32,
+}
 
-        return Ok(shutdown_status);
+impl HumanView {
+    pub fn from_view_result(view: ViewResult) -> HumanView {
+        HumanView {
+            rows: view
+                .rows
+                .into_iter()
+                .map(|row| HumanViewRow {
+                    id: row.id.to_string(),
+                    full_name: row.full_name,
+                    email: row.email,
+                })
+                .collect(),
+            freshness_transaction_id: view.freshness_transaction_id.0 as i32,
+        }
     }
+}
 
-    fn reset(context: &'db GraphQLContext) -> FieldResult<String> {
-        let request_manager = &context.request_manager;
+#[derive(GraphQLEnum)]
+#[graphql(description = "How a version of a human is linked to other humans")]
+enum LineageEvent {
+    RenamedFrom,
+    RenamedTo,
+    MergedFrom,
+    MergedInto,
+    SplitFrom,
+    SplitInto,
+    ConflictResolved,
+}
 
-        let reset_status = request_manager.send_reset_request()?;
+impl LineageEvent {
+    pub fn from_lineage(lineage: &Lineage) -> LineageEvent {
+        match lineage {
+            Lineage::RenamedFrom(_) => LineageEvent::RenamedFrom,
+            Lineage::RenamedTo(_) => LineageEvent::RenamedTo,
+            Lineage::MergedFrom(_) => LineageEvent::MergedFrom,
+            Lineage::MergedInto(_) => LineageEvent::MergedInto,
+            Lineage::SplitFrom(_) => LineageEvent::SplitFrom,
+            Lineage::SplitInto(_) => LineageEvent::SplitInto,
+            Lineage::ConflictResolved(_) => LineageEvent::ConflictResolved,
+        }
+    }
+}
 
-        return Ok(reset_status);
+#[derive(GraphQLObject)]
+#[graphql(
+    context = GraphQLContext,
+    description = "A version of a human, the predecessor / successor ids link versions across renames, merges and splits"
+)]
+struct HumanVersion {
+    pub id: String,
+    pub version: i32,
+    pub transaction_id: i32,
+    /// Null if the version is a delete
+    pub human: Option<Human>,
+    pub lineage_event: Option<LineageEvent>,
+    pub predecessor_id: Option<String>,
+    pub successor_ids: Vec<String>,
+    /// The strategy that resolved the conflict, only set on versions that resolved a conflict
+    pub conflict_strategy: Option<String>,
+}
+
+impl HumanVersion {
+    pub fn from_version(version: PersonVersion) -> HumanVersion {
+        HumanVersion {
+            id: version.id.to_string(),
+            version: version.version.0 as i32,
+            transaction_id: version.transaction_id.0 as i32,
+            lineage_event: version.lineage.as_ref().map(LineageEvent::from_lineage),
+            predecessor_id: version.predecessor_id().map(EntityId::to_string),
+            successor_ids: version
+                .successor_ids()
+                .into_iter()
+                .map(EntityId::to_string)
+                .collect(),
+            conflict_strategy: match &version.lineage {
+                Some(Lineage::ConflictResolved(record)) => Some(record.strategy.clone()),
+                _ => None,
+            },
+            human: version.get_person().map(Human::from_person),
+        }
+    }
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "A message enqueued in the same transaction as the changes it describes")]
+struct OutboxEntry {
+    /// Passed to `ackOutbox` once the message has been handled
+    pub id: String,
+    pub topic: String,
+    pub payload: String,
+}
+
+impl OutboxEntry {
+    pub fn from_message(message: OutboxMessage) -> OutboxEntry {
+        OutboxEntry {
+            id: message.id.to_string(),
+            topic: message.topic,
+            payload: message.payload,
+        }
+    }
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "A humanoid creature in the Star Wars universe")]
+struct NewHuman {
+    pub full_name: String,
+    pub email: Option<String>,
+    pub address: Option<NewHumanAddress>,
+    pub phone_numbers: Option<Vec<String>>,
+}
+
+impl NewHuman {
+    pub fn to_person(self, request_manager: &RequestManager) -> Person {
+        Person {
+            id: request_manager.next_id(),
+            full_name: self.full_name,
+            email: self.email,
+            address: self.address.map(NewHumanAddress::to_address),
+            phone_numbers: self.phone_numbers.unwrap_or_default(),
+            attachments: vec![],
+        }
+    }
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "Postal address of a human, every part is optional")]
+struct NewHumanAddress {
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+}
+
+impl NewHumanAddress {
+    pub fn to_address(self) -> Address {
+        Address {
+            street: self.street,
+            city: self.city,
+            country: self.country,
+        }
+    }
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "A humanoid creature in the Star Wars universe")]
+pub struct UpdateHumanData {
+    pub full_name: Nullable<String>,
+    pub email: Nullable<String>,
+    /// Null removes the address, otherwise only the parts that are set are updated
+    pub address: Nullable<UpdateHumanAddress>,
+    /// Replaces every phone number, null removes them. The other phone number updates are applied after it
+    pub phone_numbers: Nullable<Vec<String>>,
+    pub add_phone_numbers: Option<Vec<String>>,
+    pub remove_phone_numbers: Option<Vec<String>>,
+    /// The update fails if the index is out of bounds
+    pub replace_phone_number: Option<ReplaceListElement>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "Replaces the element at an index of a list")]
+pub struct ReplaceListElement {
+    pub index: i32,
+    pub value: String,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "Parts of an address to update, null unsets the part")]
+pub struct UpdateHumanAddress {
+    pub street: Nullable<String>,
+    pub city: Nullable<String>,
+    pub country: Nullable<String>,
+}
+
+impl UpdateHumanData {
+    pub fn to_update_person_data(self) -> FieldResult<UpdatePersonData> {
+        let address = match self.address {
+            Nullable::ImplicitNull => UpdateAddressStatement::NoChanges,
+            Nullable::ExplicitNull => UpdateAddressStatement::Unset,
+            Nullable::Some(address) => UpdateAddressStatement::Set(UpdateAddressData {
+                street: to_update_statement(address.street),
+                city: to_update_statement(address.city),
+                country: to_update_statement(address.country),
+            }),
+        };
+
+        // Applied in order within the same version
+        let mut phone_numbers = vec![];
+
+        match self.phone_numbers {
+            Nullable::ImplicitNull => {}
+            Nullable::ExplicitNull => phone_numbers.push(UpdateListStatement::Set(vec![])),
+            Nullable::Some(numbers) => phone_numbers.push(UpdateListStatement::Set(numbers)),
+        }
+
+        if let Some(numbers) = self.add_phone_numbers {
+            phone_numbers.push(UpdateListStatement::Add(numbers));
+        }
+
+        if let Some(numbers) = self.remove_phone_numbers {
+            phone_numbers.push(UpdateListStatement::Remove(numbers));
+        }
+
+        if let Some(replace) = self.replace_phone_number {
+            let index = usize::try_from(replace.index)
+                .map_err(|_| FieldError::new("Index cannot be negative", graphql_value!(None)))?;
+
+            phone_numbers.push(UpdateListStatement::ReplaceAt(index, replace.value));
+        }
+
+        let phone_numbers = match phone_numbers.len() {
+            0 => UpdateListStatement::NoChanges,
+            1 => phone_numbers.remove(0),
+            _ => UpdateListStatement::Many(phone_numbers),
+        };
+
+        Ok(UpdatePersonData {
+            full_name: to_update_statement(self.full_name),
+            email: to_update_statement(self.email),
+            address,
+            phone_numbers,
+            ..UpdatePersonData::default()
+        })
+    }
+}
+
+fn to_update_statement(value: Nullable<String>) -> UpdateStatement {
+    match value {
+        Nullable::ImplicitNull => UpdateStatement::NoChanges,
+        Nullable::ExplicitNull => UpdateStatement::Unset,
+        Nullable::Some(t) => UpdateStatement::Set(t),
+    }
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "A humanoid creature in the Star Wars universe")]
+pub struct QueryHumanData {
+    pub full_name: Nullable<String>,
+    pub email: Nullable<String>,
+    pub address: Option<QueryHumanAddress>,
+    /// Matches humans that have the phone number, null matches humans without phone numbers
+    pub phone_number: Nullable<String>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "Parts of an address to match, null matches humans without the part")]
+pub struct QueryHumanAddress {
+    pub street: Nullable<String>,
+    pub city: Nullable<String>,
+    pub country: Nullable<String>,
+}
+
+fn to_query_match(value: Nullable<String>) -> QueryMatch {
+    match value {
+        Nullable::ImplicitNull => QueryMatch::Any,
+        Nullable::ExplicitNull => QueryMatch::Null,
+        Nullable::Some(t) => QueryMatch::Value(t),
+    }
+}
+
+fn to_query_person_data(query: Nullable<QueryHumanData>) -> Option<QueryPersonData> {
+    match query {
+        Nullable::ImplicitNull => None,
+        Nullable::ExplicitNull => None,
+        Nullable::Some(t) => Some(QueryPersonData {
+            full_name: to_query_match(t.full_name),
+            email: to_query_match(t.email),
+            address: t
+                .address
+                .map(|address| QueryAddressData {
+                    street: to_query_match(address.street),
+                    city: to_query_match(address.city),
+                    country: to_query_match(address.country),
+                })
+                .unwrap_or_default(),
+            phone_number: to_query_match(t.phone_number),
+        }),
+    }
+}
+
+#[derive(GraphQLEnum)]
+#[graphql(description = "The part of a prepared query a parameter's value is matched against")]
+enum HumanParameterField {
+    FullName,
+    Email,
+    Street,
+    City,
+    Country,
+    PhoneNumber,
+}
+
+impl HumanParameterField {
+    pub fn to_parameter_field(self) -> ParameterField {
+        match self {
+            HumanParameterField::FullName => ParameterField::FullName,
+            HumanParameterField::Email => ParameterField::Email,
+            HumanParameterField::Street => ParameterField::Street,
+            HumanParameterField::City => ParameterField::City,
+            HumanParameterField::Country => ParameterField::Country,
+            HumanParameterField::PhoneNumber => ParameterField::PhoneNumber,
+        }
+    }
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "A named parameter of a prepared query")]
+struct PreparedQueryParameter {
+    name: String,
+    field: HumanParameterField,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "Orders the rows of a prepared query, ties are ordered by id")]
+struct HumanOrder {
+    field: HumanField,
+    descending: Option<bool>,
+}
+
+#[derive(GraphQLInputObject)]
+#[graphql(description = "The value a prepared query is executed with for one of its parameters")]
+struct PreparedQueryValue {
+    name: String,
+    value: String,
+}
+
+pub struct QueryRoot;
+
+#[juniper::graphql_object(context = GraphQLContext)]
+impl QueryRoot {
+    fn human(
+        id: String,
+        version_id: Option<i32>,
+        snapshot_id: Nullable<i32>,
+        clone: Option<String>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Option<Human>> {
+        let request_manager = &context.request_manager;
+
+        let entity_id = EntityId(id);
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context
+            .transaction_context(snapshot_timestamp)
+            .set_clone(clone);
+
+        let optional_person = match version_id {
+            Some(v) => request_manager.send_get_version(entity_id, v.try_into()?, tx_context)?,
+            None => request_manager.send_get(entity_id, tx_context)?,
+        };
+
+        Ok(optional_person.and_then(|p| Some(Human::from_person(p))))
+    }
+
+    /// Every version of a human, including the versions of the ids it was renamed or merged from (and to). Null
+    /// if the human does not exist
+    fn human_lineage(
+        id: String,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Option<Vec<HumanVersion>>> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        let result = request_manager
+            .send_lineage(EntityId(id), tx_context)?
+            .map(|versions| {
+                versions
+                    .into_iter()
+                    .map(HumanVersion::from_version)
+                    .collect()
+            });
+
+        Ok(result)
+    }
+
+    /// A page of the versions of a human ordered by transaction id, renames and merges are not followed (see
+    /// `humanLineage`). Versions can be limited to a transaction range and to the versions that changed one of
+    /// the fields. Null if the human does not exist
+    fn human_history(
+        id: String,
+        first: i32,
+        after: Nullable<String>,
+        from_transaction_id: Option<i32>,
+        to_transaction_id: Option<i32>,
+        changed_fields: Option<Vec<HumanField>>,
+        newest_first: Option<bool>,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Option<HumanHistoryPage>> {
+        let request_manager = &context.request_manager;
+
+        // The cursor pins the snapshot, so the snapshot id is only used for the first page
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        let cursor = match after {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => None,
+            Nullable::Some(c) => Some(c.parse::<HistoryCursor>()?),
+        };
+
+        let request = HistoryRequest::new(first.try_into()?)
+            .set_cursor(cursor)
+            .set_transaction_range(
+                from_transaction_id.map(Into::into),
+                to_transaction_id.map(Into::into),
+            )
+            .set_changed_fields(
+                changed_fields
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(HumanField::to_person_field)
+                    .collect(),
+            )
+            .set_newest_first(newest_first.unwrap_or(false));
+
+        let page = request_manager.send_history(EntityId(id), request, tx_context)?;
+
+        Ok(page.map(|page| HumanHistoryPage {
+            versions: page
+                .versions
+                .into_iter()
+                .map(HumanVersion::from_version)
+                .collect(),
+            next_cursor: page.next_cursor.map(|c| c.to_string()),
+        }))
+    }
+
+    /// If a clone is given the humans are read from the clone, see `cloneAtTransaction`
+    fn list_human(
+        query: Nullable<QueryHumanData>,
+        snapshot_id: Nullable<i32>,
+        clone: Option<String>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<Human>> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context
+            .transaction_context(snapshot_timestamp)
+            .set_clone(clone);
+
+        let list_query = to_query_person_data(query);
+
+        let result = request_manager
+            .send_list(list_query, tx_context)?
+            .into_iter()
+            .map(Human::from_person)
+            .collect();
+
+        return Ok(result);
+    }
+
+    /// The content of a human's attachment, null if the human does not exist or has no attachment with the name
+    fn human_attachment(
+        id: String,
+        name: String,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Option<HumanAttachmentContent>> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        let result = request_manager
+            .send_get_attachment(EntityId(id), name, tx_context)?
+            .map(HumanAttachmentContent::from_content);
+
+        Ok(result)
+    }
+
+    /// Whether the human exists, cheaper than `human` as the human is not returned
+    fn human_exists(
+        id: String,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<bool> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        Ok(request_manager.send_exists(EntityId(id), tx_context)?)
+    }
+
+    /// Version count and first / last modification of the human, cheaper than `humanHistory`. Removed humans
+    /// are returned with `deleted` set
+    fn human_meta(
+        id: String,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Option<HumanMeta>> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        let meta = request_manager
+            .send_meta(EntityId(id), tx_context)?
+            .map(HumanMeta::from_meta);
+
+        Ok(meta)
+    }
+
+    /// The number of humans that match the query, cheaper than `listHuman` as the humans are not returned
+    fn count_human(
+        query: Nullable<QueryHumanData>,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<i32> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        let count = request_manager.send_count(to_query_person_data(query), tx_context)?;
+
+        Ok(i32::try_from(count)?)
+    }
+
+    /// Same as `listHuman`, but the humans are only listed if the table changed since `ifNotChangedSince` (an
+    /// `etag` of a previous result). Defaults to the `If-None-Match` header
+    fn list_human_if_changed(
+        query: Nullable<QueryHumanData>,
+        if_not_changed_since: Option<String>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<ConditionalHumans> {
+        let request_manager = &context.request_manager;
+
+        let if_unchanged_since = match if_not_changed_since {
+            Some(version) => Some(version.parse::<TableVersion>()?),
+            None => context.if_none_match.clone(),
+        };
+
+        let result = request_manager.send_list_if_changed(
+            to_query_person_data(query),
+            if_unchanged_since,
+            context.transaction_context(SnapshotTimestamp::Latest),
+        )?;
+
+        let (not_modified, humans, version) = match result {
+            ConditionalRead::NotModified(version) => (true, vec![], version),
+            ConditionalRead::Modified(people, version) => (
+                false,
+                people.into_iter().map(Human::from_person).collect(),
+                version,
+            ),
+        };
+
+        context
+            .conditional_reads
+            .lock()
+            .unwrap()
+            .push((not_modified, version.clone()));
+
+        Ok(ConditionalHumans {
+            not_modified,
+            humans,
+            etag: version.to_string(),
+            last_transaction_id: version.transaction_id.0 as i32,
+        })
+    }
+
+    /// How `listHuman` would scan the table for the query (full or index scan), the query is not run
+    fn explain_list_human(
+        query: Nullable<QueryHumanData>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let explain = request_manager
+            .send_explain_query_request(to_query_person_data(query))?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(explain);
+    }
+
+    /// The fields list and count queries filter on, and whether an index is recommended for each
+    fn index_advisor(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let advice = request_manager
+            .send_index_advisor_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(advice);
+    }
+
+    /// The progress of the index backfills started with `backfillIndex`
+    fn index_backfills(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let backfills = request_manager
+            .send_list_index_backfills_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(backfills);
+    }
+
+    fn list_human_page(
+        query: Nullable<QueryHumanData>,
+        first: i32,
+        after: Nullable<String>,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<HumanPage> {
+        let request_manager = &context.request_manager;
+
+        // The cursor pins the snapshot, so the snapshot id is only used for the first page
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let tx_context = context.transaction_context(snapshot_timestamp);
+
+        let cursor = match after {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => None,
+            Nullable::Some(c) => Some(c.parse::<Cursor>()?),
+        };
+
+        let page_request = PageRequest {
+            cursor,
+            limit: first.try_into()?,
+        };
+
+        let page = request_manager.send_list_page(
+            to_query_person_data(query),
+            page_request,
+            tx_context,
+        )?;
+
+        Ok(HumanPage {
+            humans: page.people.into_iter().map(Human::from_person).collect(),
+            next_cursor: page.next_cursor.map(|c| c.to_string()),
+        })
+    }
+
+    /// Humans as of the transaction, every human reflects the same commits even across calls. Ids that do not
+    /// exist at the transaction are left out
+    fn humans_at_transaction(
+        ids: Vec<String>,
+        transaction_id: i32,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<Human>> {
+        let request_manager = &context.request_manager;
+
+        let people = request_manager.send_get_many_at_transaction(
+            ids.into_iter().map(EntityId).collect(),
+            transaction_id.into(),
+            context.transaction_context(SnapshotTimestamp::Latest),
+        )?;
+
+        Ok(people.into_iter().map(Human::from_person).collect())
+    }
+
+    /// Humans with an id in the range ordered by id. The range starts at `from` (inclusive) or `after`
+    /// (exclusive) and ends before `before`, leaving a bound out leaves that side of the range open
+    fn scan_humans(
+        from: Nullable<String>,
+        after: Nullable<String>,
+        before: Nullable<String>,
+        first: Nullable<i32>,
+        snapshot_id: Nullable<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<Human>> {
+        let request_manager = &context.request_manager;
+
+        let snapshot_timestamp = match snapshot_id {
+            Nullable::ImplicitNull | Nullable::ExplicitNull => SnapshotTimestamp::Latest,
+            Nullable::Some(t) => SnapshotTimestamp::AtTransactionId(t.into()),
+        };
+
+        let start = match (from.some(), after.some()) {
+            (Some(_), Some(_)) => return Err("Only one of from and after can be set".into()),
+            (Some(from), None) => Bound::Included(EntityId(from)),
+            (None, Some(after)) => Bound::Excluded(EntityId(after)),
+            (None, None) => Bound::Unbounded,
+        };
+
+        let end = match before.some() {
+            Some(before) => Bound::Excluded(EntityId(before)),
+            None => Bound::Unbounded,
+        };
+
+        let limit = match first.some() {
+            Some(first) => Some(first.try_into()?),
+            None => None,
+        };
+
+        let people = request_manager.send_scan(
+            start,
+            end,
+            limit,
+            context.transaction_context(snapshot_timestamp),
+        )?;
+
+        Ok(people.into_iter().map(Human::from_person).collect())
+    }
+
+    fn view(name: String, context: &'db GraphQLContext) -> FieldResult<HumanView> {
+        let request_manager = &context.request_manager;
+
+        let view = request_manager
+            .send_query_view(name, context.transaction_context(SnapshotTimestamp::Latest))?;
+
+        Ok(HumanView::from_view_result(view))
+    }
+
+    fn prepared_query(
+        name: String,
+        values: Vec<PreparedQueryValue>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<HumanView> {
+        let request_manager = &context.request_manager;
+
+        let values = values
+            .into_iter()
+            .map(|value| (value.name, value.value))
+            .collect::<BTreeMap<String, String>>();
+
+        let view = request_manager.send_execute_prepared_query(
+            name,
+            values,
+            context.transaction_context(SnapshotTimestamp::Latest),
+        )?;
+
+        Ok(HumanView::from_view_result(view))
+    }
+
+    fn database_info(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let database_info = request_manager
+            .send_info_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(database_info);
+    }
+
+    /// Rows of a read-only table of the database's internal state, e.g. `system.snapshots` for the snapshot catalog
+    fn system_table(
+        name: String,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<DatabaseSystemTable> {
+        let request_manager = &context.request_manager;
+
+        let table = request_manager.send_query_system_table(
+            name,
+            context.transaction_context(SnapshotTimestamp::Latest),
+        )?;
+
+        Ok(DatabaseSystemTable {
+            columns: table.columns,
+            rows: table.rows,
+        })
+    }
+
+    /// Pending outbox messages, oldest first. A message is returned until it is acknowledged with `ackOutbox`
+    fn outbox(
+        topic: Option<String>,
+        limit: Option<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<OutboxEntry>> {
+        let request_manager = &context.request_manager;
+
+        let messages = request_manager.send_read_outbox(
+            topic,
+            limit.unwrap_or(100).max(0) as usize,
+            context.transaction_context(SnapshotTimestamp::Latest),
+        )?;
+
+        Ok(messages
+            .into_iter()
+            .map(OutboxEntry::from_message)
+            .collect())
+    }
+
+    fn list_clones(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let clones = request_manager
+            .send_list_clones_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(clones);
+    }
+
+    fn verify_snapshot(
+        shadow_table: Option<bool>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let verification = request_manager
+            .send_verify_snapshot_request(shadow_table.unwrap_or(false))?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(verification);
+    }
+
+    /// Exports every row encrypted for the given age public keys, e.g. age1...
+    fn export(recipients: Vec<String>, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let export = request_manager.send_export_request(recipients)?;
+
+        return Ok(export);
+    }
+
+    fn capabilities(context: &'db GraphQLContext) -> FieldResult<DatabaseCapabilities> {
+        let capabilities = context.request_manager.capabilities();
+
+        Ok(DatabaseCapabilities::from_capabilities(capabilities))
+    }
+
+    /// Agrees on a protocol version and the features the client can use, e.g. a client that was built
+    /// against a newer database learns which features to avoid
+    fn negotiate(
+        protocol_version: i32,
+        formats: Option<Vec<String>>,
+        features: Option<Vec<String>>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<NegotiatedSession> {
+        let hello = ClientHello {
+            protocol_version: protocol_version.max(0) as u32,
+            formats: formats.unwrap_or_default(),
+            features: features.unwrap_or_default(),
+        };
+
+        let negotiated = context.request_manager.capabilities().negotiate(&hello)?;
+
+        Ok(NegotiatedSession::from_negotiated(negotiated))
+    }
+
+    /// The statement frame of `executeStatements` as a proto3 definition, generated from the database's statements
+    fn statement_proto() -> String {
+        statement_proto()
+    }
+
+    /// A control started with `startOperation`, finished operations are kept for a while
+    fn operation(id: i32, context: &'db GraphQLContext) -> FieldResult<DatabaseOperation> {
+        let request_manager = &context.request_manager;
+
+        let status =
+            request_manager.send_operation_status_request(OperationId(id.max(0) as usize))?;
+
+        Ok(DatabaseOperation::from_status(status))
+    }
+
+    fn active_requests(context: &'db GraphQLContext) -> FieldResult<DatabaseActivity> {
+        let request_manager = &context.request_manager;
+
+        let report = request_manager.send_list_active_requests()?;
+
+        Ok(DatabaseActivity::from_report(report))
+    }
+
+    /// The latest rolled back transactions with mutations, newest first, requires --audit-rollbacks
+    fn rollback_audit(
+        limit: Option<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let records = request_manager
+            .send_list_rollback_audit_request(limit.unwrap_or(100).max(0) as usize)?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(records);
+    }
+
+    /// The latest purges, newest first, see `purgeHuman`
+    fn purge_audit(limit: Option<i32>, context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let records = request_manager
+            .send_list_purge_audit_request(limit.unwrap_or(100).max(0) as usize)?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        Ok(records)
+    }
+
+    /// Whether each storage feature is recorded for the data directory, enabled and supported by this build
+    fn enabled_features(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let features = request_manager
+            .send_list_enabled_features_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(features);
+    }
+
+    fn list_jobs(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let jobs = request_manager
+            .send_list_jobs_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        return Ok(jobs);
+    }
+
+    fn sleep(sleep: i32, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let sleep_duration: Duration = Duration::from_secs(sleep as u64);
+
+        let status = request_manager.send_sleep_request(sleep_duration)?;
+
+        return Ok(status);
+    }
+}
+
+pub struct MutationRoot;
+
+#[juniper::graphql_object(context = GraphQLContext)]
+impl MutationRoot {
+    fn create_human(new_human: NewHuman, context: &'db GraphQLContext) -> FieldResult<Human> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        // Might seem a bit weird, but this is to ensure that the id is unique
+        let new_person =
+            request_manager.send_add(new_human.to_person(request_manager), transaction_context)?;
+
+        Ok(Human::from_person(new_person))
+    }
+
+    /// Creates the humans in transactions of at most `--create-humans-chunk-size` humans. A chunk that fails
+    /// does not roll back the others, see the summary for which were created
+    fn create_humans(
+        new_humans: Vec<NewHuman>,
+        context: &'db GraphQLContext,
+    ) -> Result<CreateHumansResult, CreateHumansError> {
+        let request_manager = &context.request_manager;
+        let limits = context.bulk_limits;
+
+        if new_humans.len() > limits.max_humans {
+            return Err(CreateHumansError::TooManyHumans {
+                count: new_humans.len(),
+                limit: limits.max_humans,
+            });
+        }
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let add_people = new_humans
+            .into_iter()
+            .map(|new_human| new_human.to_person(request_manager))
+            .map(Statement::Add)
+            .collect();
+
+        let mut result = CreateHumansResult {
+            humans: vec![],
+            created_ids: vec![],
+            failed: 0,
+            chunks: vec![],
+        };
+
+        for chunk in request_manager.send_chunked_transaction(
+            add_people,
+            limits.chunk_size,
+            transaction_context,
+        ) {
+            let error = match chunk.result {
+                Ok(statement_results) => {
+                    // TODO: In this context we can use single, but, because it can panic an exception
+                    //  we probably shouldn't
+                    for statement_result in statement_results {
+                        let human = Human::from_person(statement_result.single());
+
+                        result.created_ids.push(human.id.clone());
+                        result.humans.push(human);
+                    }
+
+                    None
+                }
+                Err(e) => {
+                    result.failed += chunk.len as i32;
+
+                    Some(e.to_string())
+                }
+            };
+
+            result.chunks.push(CreateHumansChunk {
+                start: chunk.start as i32,
+                size: chunk.len as i32,
+                committed: error.is_none(),
+                error,
+            });
+        }
+
+        Ok(result)
+    }
+
+    fn update_human(
+        id: String,
+        update_human: UpdateHumanData,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Human> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let update_person_date = update_human.to_update_person_data()?;
+
+        let person =
+            request_manager.send_update(EntityId(id), update_person_date, transaction_context)?;
+
+        Ok(Human::from_person(person))
+    }
+
+    /// Attaches a payload (e.g. an avatar or a document) to a human, an attachment with the same name is replaced
+    fn attach_to_human(
+        id: String,
+        name: String,
+        content_type: Option<String>,
+        content_base64: String,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Human> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let bytes = BASE64_STANDARD.decode(content_base64)?;
+
+        let person = request_manager.send_attach(
+            EntityId(id),
+            name,
+            content_type,
+            bytes,
+            transaction_context,
+        )?;
+
+        Ok(Human::from_person(person))
+    }
+
+    /// Removes an attachment from a human, earlier versions of the human keep it
+    fn detach_from_human(
+        id: String,
+        name: String,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Human> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let person = request_manager.send_detach(EntityId(id), name, transaction_context)?;
+
+        Ok(Human::from_person(person))
+    }
+
+    /// Starts a control that can take longer than a request waits and returns straight away, poll `operation`
+    /// for its result. `minAgeSeconds` (default 3600) is used by `VacuumAttachments`
+    fn start_operation(
+        kind: OperationKind,
+        min_age_seconds: Option<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<DatabaseOperation> {
+        let request_manager = &context.request_manager;
+
+        let min_age = Duration::from_secs(min_age_seconds.unwrap_or(3600).max(0) as u64);
+
+        let status = request_manager.send_start_operation_request(kind.to_control(min_age))?;
+
+        Ok(DatabaseOperation::from_status(status))
+    }
+
+    /// Deletes attachments that no version of any human references, attachments written less than
+    /// `minAgeSeconds` (default 3600) ago are kept as their update may not have committed yet
+    fn vacuum_attachments(
+        min_age_seconds: Option<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let min_age = Duration::from_secs(min_age_seconds.unwrap_or(3600).max(0) as u64);
+
+        let report = request_manager
+            .send_vacuum_attachments_request(min_age)?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        Ok(report)
+    }
+
+    /// Squashes the history of every human with the configured window (see `--history-squash-window`), only the
+    /// first and last version of each window are kept
+    fn squash_history(context: &'db GraphQLContext) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let report = request_manager
+            .send_squash_history_request()?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        Ok(report)
+    }
+
+    /// Moves a human to a new id in a single transaction, the history of the old id is kept
+    fn rename_human(
+        id: String,
+        new_id: String,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Human> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let person = request_manager
+            .send_single_statement(
+                Statement::Rename(EntityId(id), EntityId(new_id)),
+                transaction_context,
+            )?
+            .single();
+
+        Ok(Human::from_person(person))
+    }
+
+    /// Merges a human into another human, the merged human is deleted. `takeFields` are copied from the merged
+    /// human, every other field of `intoId` survives
+    fn merge_humans(
+        id: String,
+        into_id: String,
+        take_fields: Option<Vec<HumanField>>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Human> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let fields = take_fields
+            .unwrap_or_default()
+            .into_iter()
+            .map(HumanField::to_person_field)
+            .collect();
+
+        let person = request_manager
+            .send_single_statement(
+                Statement::Merge(EntityId(id), EntityId(into_id), fields),
+                transaction_context,
+            )?
+            .single();
+
+        Ok(Human::from_person(person))
+    }
+
+    /// Splits a human into several new humans, the human being split is deleted
+    fn split_human(
+        id: String,
+        new_humans: Vec<NewHuman>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<Human>> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let people = new_humans
+            .into_iter()
+            .map(|new_human| new_human.to_person(request_manager))
+            .collect();
+
+        let humans = request_manager
+            .send_single_statement(Statement::Split(EntityId(id), people), transaction_context)?
+            .list()
+            .into_iter()
+            .map(Human::from_person)
+            .collect();
+
+        Ok(humans)
+    }
+
+    /// Irreversibly removes a human and every version of it, e.g. for a right to be forgotten request. Returns the
+    /// number of versions that were removed, the purge is recorded in `purgeAudit`
+    fn purge_human(id: String, context: &'db GraphQLContext) -> FieldResult<i32> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let versions = request_manager.send_purge(EntityId(id), transaction_context)?;
+
+        Ok(i32::try_from(versions)?)
+    }
+
+    /// Returns the next value of a named sequence, sequences start at 1
+    fn next_val(name: String, context: &'db GraphQLContext) -> FieldResult<i32> {
+        let request_manager = &context.request_manager;
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        let value = request_manager.send_next_val(name, transaction_context)?;
+
+        Ok(i32::try_from(value)?)
+    }
+
+    /// Acknowledges outbox messages so they are not returned again, either every message is acknowledged or none
+    /// is. Fails if a message has already been acknowledged. Returns the number of acknowledged messages
+    fn ack_outbox(ids: Vec<String>, context: &'db GraphQLContext) -> FieldResult<i32> {
+        let request_manager = &context.request_manager;
+
+        let ids = ids
+            .iter()
+            .map(|id| id.parse::<OutboxId>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let count = ids.len();
+
+        let transaction_context = context.transaction_context(SnapshotTimestamp::Latest);
+
+        request_manager.send_ack_outbox(ids, transaction_context)?;
+
+        Ok(i32::try_from(count)?)
+    }
+
+    fn snapshot(context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let shutdown_status = request_manager.send_snapshot_request()?;
+
+        return Ok(shutdown_status);
+    }
+
+    /// Drops the transactions covered by the latest snapshot from the WAL, without pausing the database
+    fn compact_wal(context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        Ok(request_manager.send_compact_wal_request()?)
+    }
+
+    /// Switches storage to the engine the database is migrating to (see `--migrate-to`), the engines converge
+    /// once a snapshot has been taken
+    fn cutover_migration(context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        Ok(request_manager.send_cutover_migration_request()?)
+    }
+
+    /// Creates (or replaces) a read-only clone of the humans as they were at the transaction
+    fn clone_at_transaction(
+        name: String,
+        transaction_id: i32,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status =
+            request_manager.send_clone_at_transaction_request(name, transaction_id.into())?;
+
+        return Ok(status);
+    }
+
+    fn drop_clone(name: String, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_drop_clone_request(name)?;
+
+        return Ok(status);
+    }
+
+    fn create_view(
+        name: String,
+        query: Nullable<QueryHumanData>,
+        fields: Vec<HumanField>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let definition = ViewDefinition {
+            name,
+            query: to_query_person_data(query),
+            projection: fields
+                .into_iter()
+                .map(HumanField::to_person_field)
+                .collect(),
+        };
+
+        let status = request_manager.send_create_view_request(definition)?;
+
+        return Ok(status);
+    }
+
+    fn drop_view(name: String, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_drop_view_request(name)?;
+
+        return Ok(status);
+    }
+
+    fn create_index(field: HumanIndexField, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_create_index_request(field.to_indexed_field())?;
+
+        return Ok(status);
+    }
+
+    /// Creates the index without pausing the database, the existing rows are indexed in the background
+    fn backfill_index(field: HumanIndexField, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_backfill_index_request(field.to_indexed_field())?;
+
+        return Ok(status);
+    }
+
+    fn create_prepared_query(
+        name: String,
+        query: Nullable<QueryHumanData>,
+        parameters: Vec<PreparedQueryParameter>,
+        fields: Vec<HumanField>,
+        order_by: Option<HumanOrder>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let definition = PreparedQueryDefinition {
+            name,
+            query: to_query_person_data(query).unwrap_or_default(),
+            parameters: parameters
+                .into_iter()
+                .map(|parameter| QueryParameter {
+                    name: parameter.name,
+                    field: parameter.field.to_parameter_field(),
+                })
+                .collect(),
+            projection: fields
+                .into_iter()
+                .map(HumanField::to_person_field)
+                .collect(),
+            order: order_by.map(|order| QueryOrder {
+                field: order.field.to_person_field(),
+                descending: order.descending.unwrap_or(false),
+            }),
+        };
+
+        let status = request_manager.send_create_prepared_query_request(definition)?;
+
+        return Ok(status);
+    }
+
+    fn drop_prepared_query(name: String, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_drop_prepared_query_request(name)?;
+
+        return Ok(status);
+    }
+
+    fn schedule_job(
+        name: String,
+        schedule: String,
+        action: ScheduledAction,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let definition = JobDefinition {
+            name,
+            schedule,
+            action: action.to_job_action(),
+        };
+
+        let status = request_manager.send_schedule_job_request(definition)?;
+
+        return Ok(status);
+    }
+
+    fn cancel_job(name: String, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_cancel_job_request(name)?;
+
+        return Ok(status);
+    }
+
+    fn kill_request(request_id: i32, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_kill_request(RequestId(request_id as usize))?;
+
+        return Ok(status);
+    }
+
+    /// Queues transactions for up to `seconds` while the action runs, then replays them
+    fn enter_maintenance(
+        seconds: i32,
+        action: Option<MaintenanceAction>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let status = request_manager.send_enter_maintenance_request(
+            Duration::from_secs(seconds as u64),
+            action.map(MaintenanceAction::to_maintenance_task),
+        )?;
+
+        return Ok(status);
+    }
+
+    /// Runs any statement of the database as JSON, a single statement or an array run as one transaction, e.g.
+    /// `{"Get":"luke"}`. Returns the results as a JSON array, statements without a typed field are available here
+    /// as soon as the database supports them
+    fn execute_statements(statements: String, context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let results = request_manager.send_transaction(
+            decode_statements(&statements)?,
+            TransactionContext::default(),
+        )?;
+
+        Ok(encode_results(&results))
+    }
+
+    /// Removes every human in a single transaction, returns the number of humans removed. Unlike `reset` the
+    /// history of every human is kept and can still be read at earlier transactions
+    fn truncate_humans(context: &'db GraphQLContext) -> FieldResult<i32> {
+        let request_manager = &context.request_manager;
+
+        let removed = request_manager.send_truncate_request()?;
+
+        Ok(i32::try_from(removed)?)
+    }
+
+    /// Moves every human whose id starts with `from` to the same id starting with `to`, the lineage of each human
+    /// is kept. Without a chunk size the renames are committed in one transaction while the database is paused
+    fn rename_id_prefix(
+        from: String,
+        to: String,
+        chunk_size: Option<i32>,
+        context: &'db GraphQLContext,
+    ) -> FieldResult<Vec<String>> {
+        let request_manager = &context.request_manager;
+
+        let report = request_manager
+            .send_rename_id_prefix_request(
+                from,
+                to,
+                chunk_size.map(|chunk_size| chunk_size.max(1) as usize),
+            )?
+            .into_iter()
+            .map(|r| format!("[{}] {}", r.0, r.1))
+            .collect();
+
+        Ok(report)
+    }
+
+    fn reset(context: &'db GraphQLContext) -> FieldResult<String> {
+        let request_manager = &context.request_manager;
+
+        let reset_status = request_manager.send_reset_request()?;
+
+        return Ok(reset_status);
+    }
+}
+
+/// How often a watch with no new versions checks that the subscriber is still connected
+const WATCH_HEARTBEAT: Duration = Duration::from_secs(5);
+
+type HumanVersionStream = Pin<Box<dyn Stream<Item = FieldResult<HumanVersion>> + Send>>;
+
+pub struct SubscriptionRoot;
+
+#[juniper::graphql_subscription(context = GraphQLContext)]
+impl SubscriptionRoot {
+    /// Every committed version of the human from now on, useful to find out what keeps changing a human. Renames
+    /// and merges are not followed
+    async fn watch_human(id: String, context: &GraphQLContext) -> HumanVersionStream {
+        let watch = context.request_manager.watch_entity(
+            EntityId(id),
+            context.transaction_context(SnapshotTimestamp::Latest),
+        );
+
+        let mut watch = match watch {
+            Ok(watch) => watch,
+            Err(e) => {
+                let error = stream::once(async move { Err(FieldError::from(e)) });
+
+                return Box::pin(error) as HumanVersionStream;
+            }
+        };
+
+        // The watch blocks, its versions are forwarded until the subscriber disconnects
+        let (sender, receiver) = flume::unbounded();
+
+        thread::spawn(move || loop {
+            let result = match watch.next_timeout(WATCH_HEARTBEAT) {
+                Ok(version) => Ok(HumanVersion::from_version(version)),
+                Err(EntityWatchError::Timeout) if !sender.is_disconnected() => continue,
+                Err(EntityWatchError::Timeout | EntityWatchError::Closed) => return,
+                Err(e) => Err(FieldError::from(e)),
+            };
+
+            if sender.send(result).is_err() {
+                return;
+            }
+        });
+
+        Box::pin(receiver.into_stream())
     }
 }
 
-pub type Schema = RootNode<'static, QueryRoot, MutationRoot, EmptySubscription<GraphQLContext>>;
+pub type Schema = RootNode<'static, QueryRoot, MutationRoot, SubscriptionRoot>;
 
 pub fn create_schema() -> Schema {
-    Schema::new(QueryRoot {}, MutationRoot {}, EmptySubscription::new())
+    Schema::new(QueryRoot {}, MutationRoot {}, SubscriptionRoot {})
 }
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a drain checks whether the connections have closed
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The open connections of the server, limits how many are open at once and closes them when the server shuts down
+pub struct Connections {
+    limit: usize,
+    open: Mutex<HashMap<u64, TcpStream>>,
+    next_id: AtomicU64,
+    draining: AtomicBool,
+}
+
+/// Removes the connection from `Connections` when dropped, i.e. once its thread exits
+pub struct Connection {
+    id: u64,
+    connections: Arc<Connections>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.connections.open.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Connections {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            open: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    /// `None` once the limit is reached or the server is draining
+    pub fn register(self: &Arc<Self>, stream: &TcpStream) -> Option<Connection> {
+        let mut open = self.open.lock().unwrap();
+
+        if open.len() >= self.limit || self.is_draining() {
+            return None;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        open.insert(id, stream.try_clone().ok()?);
+
+        Some(Connection {
+            id,
+            connections: self.clone(),
+        })
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Stops reading from every connection, a request that is running still gets its response. Connections that
+    /// are still open after `timeout` (e.g. a slow request) are closed, returns how many were
+    pub fn drain(&self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+
+        {
+            let open = self.open.lock().unwrap();
+
+            self.draining.store(true, Ordering::SeqCst);
+
+            for stream in open.values() {
+                let _ = stream.shutdown(Shutdown::Read);
+            }
+        }
+
+        while Instant::now() < deadline {
+            if self.open.lock().unwrap().is_empty() {
+                return 0;
+            }
+
+            thread::sleep(DRAIN_POLL_INTERVAL);
+        }
+
+        let open = self.open.lock().unwrap();
+
+        for stream in open.values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+
+        open.len()
+    }
+}
@@ -1,104 +1,396 @@
-use std::io::{Read, Write};
-use std::net::TcpListener;
-use std::str::from_utf8;
+mod connections;
+mod session;
+
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 use clap::Parser;
-use database::consts::consts::EntityId;
-use database::database::commands::TransactionContext;
-use database::database::database::Database;
-use database::database::options::DatabaseOptions;
-use database::database::table::row::{UpdatePersonData, UpdateStatement};
-use database::model::person::Person;
-use database::model::statement::Statement; // TCP Stream defines implementation
+use connections::Connections;
+use database::prelude::{
+    decode_statements, encode_results, read_config_file, statement_proto, ClientHello, ConfigError,
+    Database, DatabaseConfig, DatabaseOptions, EntityId, EntityWatchError, LoggingConfig, Person,
+    RequestManager, ShutdownRequest, Statement, TransactionContext, UpdatePersonData,
+    UpdateStatement,
+}; // TCP Stream defines implementation
+use serde::Deserialize;
+use session::{FrameAction, SequencedSession};
+
+#[derive(clap::Args, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ServerConfig {
+    /// Port the tcp server will run on [default: 9000]
+    #[clap(short, long, env = "LINEAGEDB_PORT")]
+    port: Option<u16>,
+
+    /// Address the tcp server will run on [default: 0.0.0.0]
+    #[clap(short, long, env = "LINEAGEDB_ADDRESS")]
+    address: Option<String>,
+
+    /// Connections open at once, further connections are rejected until one closes [default: 256]
+    #[clap(long, env = "LINEAGEDB_MAX_CONNECTIONS")]
+    max_connections: Option<usize>,
+
+    /// On Ctrl-C, how long in-flight requests have to finish before the database is shut down [default: 30]
+    #[clap(long, env = "LINEAGEDB_DRAIN_TIMEOUT_SECS")]
+    drain_timeout_secs: Option<u64>,
+}
+
+/// Layout of the config file, see `--config`
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    server: ServerConfig,
+    #[serde(default)]
+    database: DatabaseConfig,
+}
 
 /// 📀 Lineagedb TCP Server, provides a simple tcp interface for interacting with the database
 ///
 /// Can connect via netcat `echo "l" | netcat 127.0.0.1 9000`
+///
+/// Clients can check what the server supports with `c` (capabilities) and negotiate a session with
+/// `h <hello json>`, e.g. `echo 'h {"protocol_version":1,"features":["Pagination"]}' | netcat 127.0.0.1 9000`
+///
+/// Clients that retry keep the connection open and number their frames `#<sequence> <command>`, e.g. `#1 a`. A
+/// frame resent with the same sequence number returns the cached response instead of running the command again
+///
+/// Any statement runs with `s <json>`, a single statement or an array of statements run as one transaction, e.g.
+/// `echo 's {"Get":"test"}' | netcat 127.0.0.1 9000`. The results are a JSON array, `p` returns the statement
+/// frame as a proto3 definition for generating clients
+///
+/// `watch <id>` keeps the connection open and writes every committed version of the row as a JSON line, e.g.
+/// `echo "watch test" | netcat 127.0.0.1 9000`
 #[derive(Parser, Debug)]
 struct Cli {
-    /// Location of the database. Reads / writes to this directory. Note: Does not support shell paths, e.g. ~
-    #[clap(short, long, default_value = "data")]
-    data: std::path::PathBuf,
+    /// TOML config file with `[server]` and `[database]` tables, command line arguments and environment variables take precedence
+    #[clap(long, env = "LINEAGEDB_CONFIG")]
+    config: Option<std::path::PathBuf>,
 
-    /// Port the graphql server will run on
-    #[clap(short, long, default_value = "9000")]
-    port: u16,
+    #[clap(flatten)]
+    server: ServerConfig,
 
-    /// Address the graphql server will run on
-    #[clap(short, long, default_value = "0.0.0.0")]
-    address: String,
+    #[clap(flatten)]
+    database: DatabaseConfig,
+
+    #[clap(flatten)]
+    logging: LoggingConfig,
+}
+
+impl Cli {
+    fn load(self) -> Result<(ServerConfig, DatabaseOptions), ConfigError> {
+        let file: ConfigFile = match &self.config {
+            Some(path) => read_config_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let server = ServerConfig {
+            port: self.server.port.or(file.server.port),
+            address: self.server.address.or(file.server.address),
+            max_connections: self.server.max_connections.or(file.server.max_connections),
+            drain_timeout_secs: self
+                .server
+                .drain_timeout_secs
+                .or(file.server.drain_timeout_secs),
+        };
+
+        Ok((server, file.database.merge(self.database).to_options()?))
+    }
+}
+
+/// Runs a single command, the response includes the trailing newline
+fn handle_request(request: &str, request_manager: &RequestManager) -> String {
+    // Capabilities and negotiation are JSON, older clients only use the commands below
+    if request == "c" {
+        let capabilities = request_manager.capabilities();
+
+        return format!("{}\n", serde_json::to_string(&capabilities).unwrap());
+    }
+
+    if let Some(hello) = request.strip_prefix("h ") {
+        let negotiated = serde_json::from_str::<ClientHello>(hello)
+            .map_err(|e| format!("Invalid hello: {}", e))
+            .and_then(|hello| {
+                request_manager
+                    .capabilities()
+                    .negotiate(&hello)
+                    .map_err(|e| e.to_string())
+            });
+
+        return match negotiated {
+            Ok(negotiated) => format!("{}\n", serde_json::to_string(&negotiated).unwrap()),
+            Err(e) => format!("Error: {}\n", e),
+        };
+    }
+
+    if request == "p" {
+        return statement_proto();
+    }
+
+    if let Some(frame) = request.strip_prefix("s ") {
+        let results = decode_statements(frame)
+            .map_err(|e| e.to_string())
+            .and_then(|statements| {
+                request_manager
+                    .send_transaction(statements, TransactionContext::default())
+                    .map_err(|e| e.to_string())
+            });
+
+        return match results {
+            Ok(results) => format!("{}\n", encode_results(&results)),
+            Err(e) => format!("Error: {}\n", e),
+        };
+    }
+
+    let statement = match request {
+        "l" => Some(Statement::List(None)),
+        "a" => Some(Statement::Add(Person {
+            id: EntityId("test".to_string()),
+            full_name: format!("[Count 0] Dale Salter"),
+            email: Some(format!("dalejsalter-{}@outlook.com", "test")),
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
+        })),
+        "u" => Some(Statement::Update(
+            EntityId("test".to_string()),
+            UpdatePersonData {
+                full_name: UpdateStatement::Set(format!("[Count TEST] Dale Salter")),
+                email: UpdateStatement::NoChanges,
+                ..UpdatePersonData::default()
+            },
+        )),
+        "d" => Some(Statement::Remove(EntityId("test".to_string()))),
+        _ => None,
+    };
+
+    if let Some(statement) = statement {
+        let response = request_manager
+            .send_single_statement(statement, TransactionContext::default())
+            .expect("Should not timeout");
+
+        format!("{:#?}\n", response)
+    } else {
+        "Unknown Command\n".to_string()
+    }
+}
+
+/// How often a watch with no new versions checks that the client is still connected
+const WATCH_HEARTBEAT: Duration = Duration::from_secs(5);
+
+/// How often the accept loop checks for the shutdown signal when no connection is waiting
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Writes every committed version of the row until the client disconnects or the server drains
+fn watch(
+    id: &str,
+    stream: &mut TcpStream,
+    request_manager: &RequestManager,
+    connections: &Connections,
+) {
+    let mut watch = match request_manager
+        .watch_entity(EntityId(id.to_string()), TransactionContext::default())
+    {
+        Ok(watch) => watch,
+        Err(e) => {
+            let _ = stream.write_all(format!("Error: {}\n", e).as_bytes());
+            return;
+        }
+    };
+
+    while !connections.is_draining() {
+        let line = match watch.next_timeout(WATCH_HEARTBEAT) {
+            Ok(version) => format!("{}\n", serde_json::to_string(&version).unwrap()),
+            // A disconnected client is only noticed once a write fails
+            Err(EntityWatchError::Timeout) => "\n".to_string(),
+            Err(EntityWatchError::Closed) => return,
+            Err(e) => format!("Error: {}\n", e),
+        };
+
+        if let Err(e) = stream.write_all(line.as_bytes()) {
+            log::info!(entity_id = id; "Stopped watching: {}", e);
+            return;
+        }
+    }
+}
+
+/// Answers requests until the client disconnects or the server drains
+fn handle_connection(
+    mut stream: TcpStream,
+    request_manager: &RequestManager,
+    connections: &Connections,
+) {
+    println!("Connected stream");
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut session = SequencedSession::default();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                log::info!("Failed to read connection: {}", e);
+                break;
+            }
+        }
+
+        let request = line.trim_end_matches(['\r', '\n']);
+
+        log::info!("Request: {}", request);
+
+        if let Some(id) = request.strip_prefix("watch ") {
+            watch(id, &mut stream, request_manager, connections);
+
+            break;
+        }
+
+        let response = match SequencedSession::parse_frame(request) {
+            // Unsequenced requests are answered and the connection is closed, e.g. netcat
+            None => {
+                let response = handle_request(request, request_manager);
+
+                let _ = stream.write_all(response.as_bytes());
+
+                break;
+            }
+            Some(Err(e)) => format!("Error: {}\n", e),
+            Some(Ok((sequence, command))) => match session.check(sequence) {
+                FrameAction::Run => {
+                    let response = handle_request(command, request_manager);
+
+                    session.complete(sequence, response.clone());
+
+                    response
+                }
+                FrameAction::Duplicate(response) => {
+                    log::info!(
+                        "Duplicate frame #{}, returning the cached response",
+                        sequence
+                    );
+
+                    response.to_string()
+                }
+                FrameAction::Stale { last_sequence } => format!(
+                    "Error: Stale sequence number {}, the last frame was #{}\n",
+                    sequence, last_sequence
+                ),
+            },
+        };
+
+        if let Err(e) = stream.write_all(response.as_bytes()) {
+            log::info!("Failed to write to connection: {}", e);
+            break;
+        }
+    }
+
+    if session.duplicates() > 0 {
+        log::info!("Dropped {} duplicate frames", session.duplicates());
+    }
 }
 
 fn main() {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let cli = Cli::parse();
+
+    cli.logging.init("lineagedb-tcp");
 
-    let args = Cli::parse();
+    let (server, database_options) = match cli.load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            log::logger().flush();
+            std::process::exit(1);
+        }
+    };
 
-    log::info!("TCP Server running on {}:{}", args.address, args.port);
+    let port = server.port.unwrap_or(9000);
+    let address = server.address.unwrap_or("0.0.0.0".to_string());
+    let max_connections = server.max_connections.unwrap_or(256).max(1);
+    let drain_timeout = Duration::from_secs(server.drain_timeout_secs.unwrap_or(30));
 
-    let database_options = DatabaseOptions::default();
+    log::info!("TCP Server running on {}:{}", address, port);
 
     // Setup database
     let rm = Database::new(database_options).run();
 
-    let listener = TcpListener::bind(format!("{}:{}", args.address, args.port)).unwrap();
+    let listener = TcpListener::bind(format!("{}:{}", address, port)).unwrap();
 
-    loop {
+    // Polled so the accept loop notices the shutdown signal
+    listener
+        .set_nonblocking(true)
+        .expect("Should be able to make the listener non-blocking");
+
+    let connections = Arc::new(Connections::new(max_connections));
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    {
+        let shutdown = shutdown.clone();
+
+        // Set up Ctrl-C handler, stops accepting connections and waits up to the drain timeout for in-flight requests
+        ctrlc::set_handler(move || shutdown.store(true, Ordering::SeqCst))
+            .expect("Error setting Ctrl-C handler");
+    }
+
+    while !shutdown.load(Ordering::SeqCst) {
         match listener.accept() {
             Ok((mut stream, _)) => {
+                let Some(connection) = connections.register(&stream) else {
+                    log::warn!(
+                        "Rejected connection, {} connections are open",
+                        max_connections
+                    );
+
+                    let _ = stream.write_all(b"Error: Too many connections\n");
+
+                    continue;
+                };
+
+                // Accepted connections inherit the listener's non-blocking mode on some platforms
+                if let Err(e) = stream.set_nonblocking(false) {
+                    log::info!("Failed to establish connection: {}", e);
+                    continue;
+                }
+
                 let request_manager = rm.clone();
+                let connections = connections.clone();
 
                 thread::spawn(move || {
-                    println!("Connected stream");
-
-                    // Must initalize memory to 0s usage
-                    let mut buffer = [0; 1024];
-
-                    match stream.read(&mut buffer) {
-                        Ok(_) => {
-                            let (request, _) =
-                                from_utf8(&buffer[..]).unwrap().split_once('\n').unwrap();
-
-                            log::info!("Request: {}", request);
-
-                            let statement = match request {
-                                "l" => Some(Statement::List(None)),
-                                "a" => Some(Statement::Add(Person {
-                                    id: EntityId("test".to_string()),
-                                    full_name: format!("[Count 0] Dale Salter"),
-                                    email: Some(format!("dalejsalter-{}@outlook.com", "test")),
-                                })),
-                                "u" => Some(Statement::Update(
-                                    EntityId("test".to_string()),
-                                    UpdatePersonData {
-                                        full_name: UpdateStatement::Set(format!(
-                                            "[Count TEST] Dale Salter"
-                                        )),
-                                        email: UpdateStatement::NoChanges,
-                                    },
-                                )),
-                                "d" => Some(Statement::Remove(EntityId("test".to_string()))),
-                                _ => None,
-                            };
-
-                            if let Some(statement) = statement {
-                                let response = request_manager
-                                    .send_single_statement(statement, TransactionContext::default())
-                                    .expect("Should not timeout");
-
-                                writeln!(stream, "{:#?}", response).unwrap();
-                            } else {
-                                writeln!(stream, "Unknown Command").unwrap();
-                            }
-                        }
-                        Err(e) => log::info!("Failed to read connection: {}", e),
-                    }
+                    handle_connection(stream, &request_manager, &connections);
+
+                    drop(connection);
                 });
             }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
             Err(e) => {
                 log::info!("Failed to establish connection: {}", e)
             }
         }
     }
+
+    drop(listener);
+
+    log::info!(
+        "Draining connections, waiting up to {} seconds",
+        drain_timeout.as_secs()
+    );
+
+    let closed = connections.drain(drain_timeout);
+
+    if closed > 0 {
+        log::warn!("Closed {} connections that did not finish in time", closed);
+    }
+
+    let shutdown_response = rm
+        .send_shutdown_request(ShutdownRequest::Coordinator)
+        .expect("Should not timeout");
+
+    log::info!("Shutting down server: {}", shutdown_response);
+    log::logger().flush();
 }
@@ -0,0 +1,59 @@
+/// What to do with a sequenced frame, see `SequencedSession`
+#[derive(Debug, PartialEq)]
+pub enum FrameAction<'a> {
+    /// A new frame, run the command and cache the response with `SequencedSession::complete`
+    Run,
+    /// The client retried the last frame, e.g. after a timeout, the cached response is returned without
+    /// running the command again
+    Duplicate(&'a str),
+    /// The frame is older than the last frame, its response is no longer cached
+    Stale { last_sequence: u64 },
+}
+
+/// Tracks the client sequence numbers of a connection, a client sends frames one at a time as `#<sequence> <command>`
+/// with increasing sequence numbers and resends a frame with the same sequence number when it did not receive
+/// the response
+#[derive(Default)]
+pub struct SequencedSession {
+    last: Option<(u64, String)>,
+    duplicates: usize,
+}
+
+impl SequencedSession {
+    /// Splits `#<sequence> <command>` frames, returns `None` for unsequenced requests
+    pub fn parse_frame(request: &str) -> Option<Result<(u64, &str), String>> {
+        let frame = request.strip_prefix('#')?;
+
+        let (sequence, command) = frame.split_once(' ').unwrap_or((frame, ""));
+
+        Some(
+            sequence
+                .parse::<u64>()
+                .map(|sequence| (sequence, command))
+                .map_err(|e| format!("Invalid sequence number `{}`: {}", sequence, e)),
+        )
+    }
+
+    pub fn check(&mut self, sequence: u64) -> FrameAction<'_> {
+        match &self.last {
+            Some((last_sequence, _)) if sequence < *last_sequence => FrameAction::Stale {
+                last_sequence: *last_sequence,
+            },
+            Some((last_sequence, response)) if sequence == *last_sequence => {
+                self.duplicates += 1;
+
+                FrameAction::Duplicate(response)
+            }
+            _ => FrameAction::Run,
+        }
+    }
+
+    pub fn complete(&mut self, sequence: u64, response: String) {
+        self.last = Some((sequence, response));
+    }
+
+    /// Frames that were dropped as duplicates of the last frame
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
@@ -1,7 +1,13 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use database::{
     consts::consts::{EntityId, TransactionId},
-    database::database::{test_utils::apply_transaction_at_next_timestamp, Database},
+    database::{
+        database::{test_utils::apply_transaction_at_next_timestamp, Database},
+        table::{
+            row::{UpdatePersonData, UpdateStatement},
+            table::ReadOptions,
+        },
+    },
     model::{person::Person, statement::Statement},
 };
 use std::sync::{mpsc::channel, Arc};
@@ -41,6 +47,9 @@ pub fn database_add_benchmark(c: &mut Criterion) {
                                     id: EntityId(Uuid::new_v4().to_string()),
                                     full_name: "Test".to_string(),
                                     email: None,
+                                    address: None,
+                                    phone_numbers: vec![],
+                                    attachments: vec![],
                                 };
 
                                 let statements = vec![Statement::Add(person.clone())];
@@ -77,6 +86,9 @@ pub fn database_get_benchmark(c: &mut Criterion) {
                 id: EntityId(i.to_string()),
                 full_name: "Test".to_string(),
                 email: None,
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
             };
 
             let statements = vec![Statement::Add(person.clone())];
@@ -101,8 +113,11 @@ pub fn database_get_benchmark(c: &mut Criterion) {
                             for i in 0..SAMPLE_SIZE / thread_count as u64 {
                                 let statements = vec![Statement::Get(EntityId(i.to_string()))];
 
-                                let _ = database
-                                    .query_transaction(&TransactionId(100_0000), statements);
+                                let _ = database.query_transaction(
+                                    &TransactionId(100_0000),
+                                    statements,
+                                    &ReadOptions::default(),
+                                );
                             }
 
                             test_tx.send(1).expect("Should not timeout");
@@ -121,6 +136,81 @@ pub fn database_get_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, database_add_benchmark, database_get_benchmark);
+/// Each thread appends versions to its own rows, the threads only share the table's structures
+pub fn database_update_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("database_update");
+    let mut pool = ThreadPool::new(1);
+
+    for size in POOL_SIZE.iter() {
+        let database = Arc::new(Database::new_benchmark());
+        pool.set_num_threads(*size);
+
+        for i in 0..SAMPLE_SIZE {
+            let person = Person {
+                id: EntityId(i.to_string()),
+                full_name: "Test".to_string(),
+                email: None,
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
+            };
+
+            let statements = vec![Statement::Add(person.clone())];
+
+            let _ = apply_transaction_at_next_timestamp(&database, statements);
+        }
+
+        group.throughput(Throughput::Elements(SAMPLE_SIZE));
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            size,
+            |b, &thread_count| {
+                b.iter_with_large_drop(|| {
+                    let (test_tx, test_rx) = channel::<i32>();
+
+                    let rows_per_thread = SAMPLE_SIZE / thread_count as u64;
+
+                    for thread_index in 0..thread_count as u64 {
+                        let test_tx = test_tx.clone();
+                        let database = database.clone();
+
+                        pool.execute(move || {
+                            let first_row = thread_index * rows_per_thread;
+
+                            for i in first_row..first_row + rows_per_thread {
+                                let statements = vec![Statement::Update(
+                                    EntityId(i.to_string()),
+                                    UpdatePersonData {
+                                        full_name: UpdateStatement::Set("Updated".to_string()),
+                                        ..UpdatePersonData::default()
+                                    },
+                                )];
+
+                                let _ = apply_transaction_at_next_timestamp(&database, statements);
+                            }
+
+                            test_tx.send(1).expect("Should not timeout");
+                        });
+                    }
+
+                    test_rx
+                        .iter()
+                        .take(thread_count)
+                        .fold(0, |a: i32, b: i32| a + b);
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    database_add_benchmark,
+    database_get_benchmark,
+    database_update_benchmark
+);
 
 criterion_main!(benches);
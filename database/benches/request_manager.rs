@@ -4,9 +4,10 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Through
 use database::{
     consts::consts::EntityId,
     database::{
-        commands::{ShutdownRequest, TransactionContext},
+        commands::ShutdownRequest,
         database::{test_utils::run_action, Database},
         options::DatabaseOptions,
+        seed::Seed,
     },
     model::{
         person::Person,
@@ -90,6 +91,9 @@ pub fn rm_add_benchmark(c: &mut Criterion) {
                                             id: EntityId(Uuid::new_v4().to_string()),
                                             full_name: index.to_string(),
                                             email: None,
+                                            address: None,
+                                            phone_numbers: vec![],
+                                            attachments: vec![],
                                         });
                                     },
                                 );
@@ -121,24 +125,12 @@ pub fn rm_get_benchmark(c: &mut Criterion) {
     let pool = ThreadPool::new(POOL_SIZE);
 
     for database_read_threads in DATABASE_THREADS_READ.iter() {
-        let options = DatabaseOptions::new_benchmark().set_threads(*database_read_threads);
+        let options = DatabaseOptions::new_benchmark()
+            .set_threads(*database_read_threads)
+            .set_seed(Seed::generated(SAMPLE_SIZE as usize, "Test"));
 
         let rm = Database::new(options).run();
 
-        for i in 0..SAMPLE_SIZE {
-            let person = Person {
-                id: EntityId(i.to_string()),
-                full_name: "Test".to_string(),
-                email: None,
-            };
-
-            rm.send_single_statement(
-                Statement::Add(person.clone()),
-                TransactionContext::default(),
-            )
-            .expect("Should not timeout");
-        }
-
         group.throughput(Throughput::Elements(SAMPLE_SIZE));
 
         group.bench_with_input(
@@ -194,24 +186,12 @@ pub fn rm_hybrid_benchmark(c: &mut Criterion) {
     let pool = ThreadPool::new(POOL_SIZE);
 
     for database_read_threads in DATABASE_THREADS_READ.iter() {
-        let options = DatabaseOptions::new_benchmark().set_threads(*database_read_threads);
+        let options = DatabaseOptions::new_benchmark()
+            .set_threads(*database_read_threads)
+            .set_seed(Seed::generated(SAMPLE_SIZE as usize, "Test"));
 
         let rm = Database::new(options).run();
 
-        for i in 0..SAMPLE_SIZE {
-            let person = Person {
-                id: EntityId(i.to_string()),
-                full_name: "Test".to_string(),
-                email: None,
-            };
-
-            rm.send_single_statement(
-                Statement::Add(person.clone()),
-                TransactionContext::default(),
-            )
-            .expect("Should not timeout");
-        }
-
         group.throughput(Throughput::Elements(SAMPLE_SIZE));
 
         group.bench_with_input(
@@ -240,6 +220,9 @@ pub fn rm_hybrid_benchmark(c: &mut Criterion) {
                                             id: EntityId(Uuid::new_v4().to_string()),
                                             full_name: index.to_string(),
                                             email: None,
+                                            address: None,
+                                            phone_numbers: vec![],
+                                            attachments: vec![],
                                         }),
                                         _ => Statement::Get(EntityId(index.to_string())),
                                     },
@@ -0,0 +1,84 @@
+//! Captures changes with the transactional outbox: each write enqueues an event in the same transaction, and a
+//! consumer thread reads the events and acknowledges them once they have been handled
+//!
+//! `cargo run -p database --example cdc_consumer`
+
+use std::{error::Error, thread, time::Duration};
+
+use database::prelude::*;
+
+const TOPIC: &str = "person.added";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let data = std::env::temp_dir().join(format!("lineagedb-example-{}", EntityId::new()));
+
+    let options = DatabaseOptionsBuilder::new()
+        .set_storage_engine(StorageEngine::File(FileOptions::new(data.clone())))
+        .set_restore(false)
+        .build()?;
+
+    let request_manager = Database::new(options).run();
+
+    let names = ["Katherine Johnson", "Dorothy Vaughan", "Mary Jackson"];
+
+    // Request managers are cheap to clone, each thread gets its own
+    let consumer = {
+        let request_manager = request_manager.clone();
+
+        thread::spawn(move || -> Result<(), RequestManagerError> {
+            let mut handled = 0;
+
+            while handled < names.len() {
+                let messages = request_manager.send_read_outbox(
+                    Some(TOPIC.to_string()),
+                    10,
+                    TransactionContext::default(),
+                )?;
+
+                if messages.is_empty() {
+                    thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+
+                for message in &messages {
+                    println!(
+                        "Transaction {}: {}",
+                        message.id.transaction_id, message.payload
+                    );
+                }
+
+                handled += messages.len();
+
+                // Until they are acknowledged the messages are returned again, e.g. after the consumer crashed
+                request_manager.send_ack_outbox(
+                    messages.into_iter().map(|message| message.id).collect(),
+                    TransactionContext::default(),
+                )?;
+            }
+
+            Ok(())
+        })
+    };
+
+    for name in names {
+        let person = Person::new(name.to_string(), None);
+        let event = format!(r#"{{"id":"{}","full_name":"{}"}}"#, person.id, name);
+
+        // The event is only visible to the consumer if the person is committed
+        request_manager.send_transaction(
+            vec![
+                Statement::Add(person),
+                Statement::Enqueue(TOPIC.to_string(), event),
+            ],
+            TransactionContext::default(),
+        )?;
+    }
+
+    consumer.join().expect("the consumer panicked")?;
+
+    request_manager.send_shutdown_request(ShutdownRequest::Coordinator)?;
+
+    std::fs::remove_dir_all(data)?;
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! Plugs an in-memory storage engine into the database, the storage outlives the database so a second database
+//! restores from it
+//!
+//! `cargo run -p database --example custom_storage`
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use database::prelude::*;
+
+/// Blobs and WAL of a namespace
+#[derive(Default)]
+struct MemoryState {
+    blobs: HashMap<String, Vec<u8>>,
+    transactions: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+struct MemoryStorage {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl Storage for MemoryStorage {
+    fn init(&mut self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    fn reset_database(&mut self) -> StorageResult<()> {
+        *self.state.lock().unwrap() = MemoryState::default();
+
+        Ok(())
+    }
+
+    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+        self.state.lock().unwrap().blobs.insert(path, bytes);
+
+        Ok(())
+    }
+
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        Ok(match self.state.lock().unwrap().blobs.get(&path) {
+            Some(bytes) => ReadBlobState::Found(bytes.clone()),
+            None => ReadBlobState::NotFound,
+        })
+    }
+
+    fn delete_blob(&self, path: String) -> StorageResult<()> {
+        self.state.lock().unwrap().blobs.remove(&path);
+
+        Ok(())
+    }
+
+    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+        let transaction = String::from_utf8(transaction.to_vec())
+            .map_err(|e| StorageError::UnableToWriteTransaction(e.into()))?;
+
+        self.state.lock().unwrap().transactions.push(transaction);
+
+        Ok(())
+    }
+
+    // Writes are visible as soon as they return
+    fn transaction_sync(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        self.state.lock().unwrap().transactions.clear();
+
+        Ok(())
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        Ok(self.state.lock().unwrap().transactions.clone())
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let namespaces: Arc<Mutex<HashMap<String, MemoryStorage>>> = Arc::default();
+
+    let engine = StorageEngine::Custom(CustomStorage::new(
+        "in-memory".to_string(),
+        move |namespace| {
+            let storage = namespaces
+                .lock()
+                .unwrap()
+                .entry(namespace.unwrap_or_default().to_string())
+                .or_default()
+                .clone();
+
+            Box::new(storage)
+        },
+    ))
+    .set_namespace("example".to_string());
+
+    let options = |restore: bool| {
+        DatabaseOptionsBuilder::new()
+            .set_storage_engine(engine.clone())
+            .set_restore(restore)
+            .build()
+    };
+
+    let request_manager = Database::new(options(false)?).run();
+
+    let person = request_manager.send_add(
+        Person::new("Alan Turing".to_string(), None),
+        TransactionContext::default(),
+    )?;
+
+    // Half of the table is in the snapshot, the other half in the WAL
+    request_manager.send_snapshot_request()?;
+    request_manager.send_add(
+        Person::new("Joan Clarke".to_string(), None),
+        TransactionContext::default(),
+    )?;
+    request_manager.send_shutdown_request(ShutdownRequest::Coordinator)?;
+
+    let restored = Database::new(options(true)?).run();
+
+    println!(
+        "Restored: {:?}, people: {}",
+        restored.send_get(person.id, TransactionContext::default())?,
+        restored.send_count(None, TransactionContext::default())?
+    );
+
+    restored.send_shutdown_request(ShutdownRequest::Coordinator)?;
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! Embeds the database in a binary and creates, reads, updates and removes a person
+//!
+//! `cargo run -p database --example embedded_crud`
+
+use std::error::Error;
+
+use database::prelude::*;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let data = std::env::temp_dir().join(format!("lineagedb-example-{}", EntityId::new()));
+
+    let options = DatabaseOptionsBuilder::new()
+        .set_storage_engine(StorageEngine::File(FileOptions::new(data.clone())))
+        .set_restore(false)
+        .build()?;
+
+    let request_manager = Database::new(options).run();
+
+    let person = request_manager.send_add(
+        Person::new("Ada Lovelace".to_string(), None),
+        TransactionContext::default(),
+    )?;
+
+    // Requests can also be sent without waiting for them, see `send_add_task`
+    let task = request_manager.send_add_task(
+        Person::new("Charles Babbage".to_string(), None),
+        TransactionContext::default(),
+    );
+
+    let updated = request_manager.send_update(
+        person.id.clone(),
+        UpdatePersonData {
+            email: UpdateStatement::Set("ada@example.com".to_string()),
+            ..UpdatePersonData::default()
+        },
+        TransactionContext::default(),
+    )?;
+
+    println!("Updated: {:?}", updated);
+
+    let babbage = task.get()?;
+
+    request_manager.send_transaction(
+        vec![Statement::Remove(babbage.id.clone())],
+        TransactionContext::default(),
+    )?;
+
+    let people = request_manager.send_list(None, TransactionContext::default())?;
+
+    println!(
+        "People: {:?}, Babbage exists: {}",
+        people,
+        request_manager.send_exists(babbage.id, TransactionContext::default())?
+    );
+
+    request_manager.send_shutdown_request(ShutdownRequest::Coordinator)?;
+
+    std::fs::remove_dir_all(data)?;
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! Reads a person as of earlier transactions, pages through its history and clones the table as it was
+//!
+//! `cargo run -p database --example time_travel`
+
+use std::error::Error;
+
+use database::prelude::*;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let data = std::env::temp_dir().join(format!("lineagedb-example-{}", EntityId::new()));
+
+    let options = DatabaseOptionsBuilder::new()
+        .set_storage_engine(StorageEngine::File(FileOptions::new(data.clone())))
+        .set_restore(false)
+        .build()?;
+
+    let request_manager = Database::new(options).run();
+
+    let person = request_manager.send_add(
+        Person::new("Grace Brewster".to_string(), None),
+        TransactionContext::default(),
+    )?;
+
+    let added_at = last_transaction_id(&request_manager, &person.id)?;
+
+    request_manager.send_update(
+        person.id.clone(),
+        UpdatePersonData {
+            full_name: UpdateStatement::Set("Grace Hopper".to_string()),
+            ..UpdatePersonData::default()
+        },
+        TransactionContext::default(),
+    )?;
+
+    // Every read runs at a transaction id, the latest by default
+    let at_add = TransactionContext::new(SnapshotTimestamp::AtTransactionId(added_at.clone()));
+
+    println!(
+        "Now: {:?}, when added: {:?}",
+        request_manager.send_get(person.id.clone(), TransactionContext::default())?,
+        request_manager.send_get(person.id.clone(), at_add)?
+    );
+
+    let history = request_manager
+        .send_history(
+            person.id.clone(),
+            HistoryRequest::new(10),
+            TransactionContext::default(),
+        )?
+        .expect("the person exists");
+
+    for version in history.versions {
+        println!(
+            "Transaction {}: {:?}",
+            version.transaction_id,
+            version.get_person().map(|person| person.full_name)
+        );
+    }
+
+    // A clone is a copy of the table as of the transaction id that can be read from until it is dropped
+    request_manager.send_clone_at_transaction_request("at-add".to_string(), added_at)?;
+
+    let cloned = request_manager.send_list(
+        None,
+        TransactionContext::default().set_clone(Some("at-add".to_string())),
+    )?;
+
+    println!("Clone: {:?}", cloned);
+
+    request_manager.send_drop_clone_request("at-add".to_string())?;
+    request_manager.send_shutdown_request(ShutdownRequest::Coordinator)?;
+
+    std::fs::remove_dir_all(data)?;
+
+    Ok(())
+}
+
+fn last_transaction_id(
+    request_manager: &RequestManager,
+    id: &EntityId,
+) -> Result<TransactionId, RequestManagerError> {
+    let meta = request_manager
+        .send_meta(id.clone(), TransactionContext::default())?
+        .expect("the person exists");
+
+    Ok(meta.last_transaction_id)
+}
@@ -0,0 +1,11 @@
+#![no_main]
+
+use database::database::table::pagination::Cursor;
+use libfuzzer_sys::fuzz_target;
+
+// Cursors are handed to clients as an opaque string and sent back to continue a listing
+fuzz_target!(|data: &str| {
+    if let Ok(cursor) = data.parse::<Cursor>() {
+        assert_eq!(cursor.to_string().parse::<Cursor>(), Ok(cursor));
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use database::{
+    database::table::{row::PersonVersion, table::PersonTable},
+    persistence::snapshot::Metadata,
+};
+use libfuzzer_sys::fuzz_target;
+
+// Snapshots and their metadata are read back from storage, see `SnapshotManager::restore_snapshot`
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_json::from_slice::<Metadata>(data);
+
+    if let Ok(version_snapshots) = serde_json::from_slice::<Vec<PersonVersion>>(data) {
+        PersonTable::new().restore_table(version_snapshots);
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use database::model::statement::Statement;
+use libfuzzer_sys::fuzz_target;
+
+// Statements are deserialized from the WAL and from the clients
+fuzz_target!(|data: &[u8]| {
+    if let Ok(statements) = serde_json::from_slice::<Vec<Statement>>(data) {
+        // Anything that deserializes must serialize again, the WAL writes the statements back out on compaction
+        serde_json::to_vec(&statements).expect("Deserialized statements should serialize");
+    }
+});
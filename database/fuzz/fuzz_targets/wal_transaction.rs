@@ -0,0 +1,11 @@
+#![no_main]
+
+use database::persistence::transaction::{Transaction, UnknownStatementPolicy};
+use libfuzzer_sys::fuzz_target;
+
+// The WAL is read back from storage, see `TransactionWAL::restore`
+fuzz_target!(|data: &[u8]| {
+    if let Ok(transaction) = std::str::from_utf8(data) {
+        let _ = Transaction::from_wal(transaction, UnknownStatementPolicy::Skip);
+    }
+});
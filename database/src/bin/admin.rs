@@ -0,0 +1,174 @@
+use std::{fs, path::PathBuf, process};
+
+use clap::{Parser, Subcommand};
+use database::prelude::{
+    admin, read_config_file, ConfigError, DatabaseConfig, DatabaseOptions, EntityId,
+    StorageEngineFlag,
+};
+use serde::Deserialize;
+
+/// Layout of the config file, the `[database]` table of the headless server's config file
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default)]
+    database: DatabaseConfig,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Summarises the WAL (transaction ids, statuses, statements and unreadable lines) without applying it
+    InspectWal,
+    /// Checks the latest snapshot against its checksum and record count, exits with 1 if it is invalid
+    VerifySnapshot,
+    /// Takes a snapshot and drops the transactions it covers from the WAL
+    Compact,
+    /// Writes the latest version of every person as JSON lines, or as an age file when recipients are given
+    Export {
+        /// age (x25519) public key the export is encrypted for, can be repeated
+        #[clap(long)]
+        recipient: Vec<String>,
+
+        /// File the export is written to [default: stdout]
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Irreversibly removes a person and every version of it, then snapshots and compacts the WAL
+    PurgeEntity { id: String },
+    /// Copies the database into an empty file database, e.g. to switch to the directory per table layout
+    MigrateFormat {
+        /// Data directory of the copy
+        #[clap(long)]
+        to_data: PathBuf,
+
+        /// Writes the copy's table blobs to a directory per table
+        #[clap(long)]
+        to_directory_per_table: bool,
+    },
+}
+
+/// 🛠️ Lineagedb admin, maintenance tasks that run directly against a database's storage. The database must not be
+/// running against the same storage
+///
+/// The storage is configured with the same flags (and environment variables) as the database, e.g. `--data`
+#[derive(Parser, Debug)]
+struct Cli {
+    /// TOML config file with a `[database]` table, command line arguments and environment variables take precedence
+    #[clap(long, env = "LINEAGEDB_CONFIG")]
+    config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    database: DatabaseConfig,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+impl Cli {
+    fn load(&self) -> Result<DatabaseConfig, ConfigError> {
+        let file: ConfigFile = match &self.config {
+            Some(path) => read_config_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        Ok(file.database.merge(self.database.clone()))
+    }
+}
+
+fn print_info(info: Vec<(String, String)>) {
+    for (key, value) in info {
+        println!("{}: {}", key, value);
+    }
+}
+
+fn run(command: Command, config: DatabaseConfig) -> Result<bool, String> {
+    let options: DatabaseOptions = config.to_options().map_err(|e| e.to_string())?;
+
+    match command {
+        Command::InspectWal => {
+            let inspection = admin::inspect_wal(&options).map_err(|e| e.to_string())?;
+
+            print_info(inspection.to_info());
+
+            Ok(inspection.unreadable.is_empty())
+        }
+        Command::VerifySnapshot => {
+            let verification = admin::verify_snapshot(&options).map_err(|e| e.to_string())?;
+
+            print_info(verification.to_info());
+
+            Ok(verification.is_valid())
+        }
+        Command::Compact => {
+            println!("{}", admin::compact(options).map_err(|e| e.to_string())?);
+
+            Ok(true)
+        }
+        Command::Export { recipient, output } => {
+            let export = admin::export(options, recipient).map_err(|e| e.to_string())?;
+
+            match output {
+                Some(path) => fs::write(&path, export)
+                    .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?,
+                None => print!("{}", export),
+            }
+
+            Ok(true)
+        }
+        Command::PurgeEntity { id } => {
+            let purged =
+                admin::purge_entity(options, EntityId(id.clone())).map_err(|e| e.to_string())?;
+
+            println!("Purged {} versions of {}", purged, id);
+
+            Ok(true)
+        }
+        Command::MigrateFormat {
+            to_data,
+            to_directory_per_table,
+        } => {
+            let target = DatabaseConfig {
+                storage: Some(StorageEngineFlag::File),
+                data: Some(to_data),
+                snapshot_data: None,
+                wal_data: None,
+                directory_per_table: Some(to_directory_per_table),
+                ..config
+            }
+            .to_options()
+            .map_err(|e| e.to_string())?;
+
+            let report = admin::migrate_format(&options, target.storage_engine)
+                .map_err(|e| e.to_string())?;
+
+            match report.already_restored {
+                true => println!("The target already contains the database"),
+                false => println!(
+                    "Copied {} blobs and {} transactions",
+                    report.blobs, report.transactions
+                ),
+            }
+
+            Ok(true)
+        }
+    }
+}
+
+fn main() {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("warn"));
+
+    let cli = Cli::parse();
+
+    let result = cli
+        .load()
+        .map_err(|e| e.to_string())
+        .and_then(|config| run(cli.command, config));
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            log::error!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use chrono::Utc;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Runs the criterion benches, records their throughput per commit and compares it against a stored baseline.
+/// Exits with an error when a benchmark's throughput regressed by more than the threshold
+///
+/// Run with `cargo bench-regression`, see `.cargo/config.toml`
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Only run this bench, e.g. `request_manager` or `database`. All benches are run by default
+    #[clap(long)]
+    bench: Option<String>,
+
+    /// Does not run the benches, compares the results of the previous `cargo bench` run
+    #[clap(long)]
+    skip_run: bool,
+
+    /// Where criterion writes its results
+    #[clap(long, default_value = "target/criterion")]
+    criterion_dir: PathBuf,
+
+    /// Results of each run are written to `<results-dir>/<commit>.json`
+    #[clap(long, default_value = "target/bench-results")]
+    results_dir: PathBuf,
+
+    /// Results that the run is compared against
+    #[clap(long, default_value = "database/benches/baseline.json")]
+    baseline: PathBuf,
+
+    /// Maximum throughput regression in percent before the run fails
+    #[clap(long, default_value_t = 10.0)]
+    threshold: f64,
+
+    /// Overwrites the baseline with the results of this run instead of comparing against it
+    #[clap(long)]
+    save_baseline: bool,
+}
+
+/// Throughput of a single benchmark, e.g. `rm_add_group/2`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct BenchmarkResult {
+    id: String,
+    /// Mean time of an iteration in nanoseconds
+    mean_ns: f64,
+    /// Elements (e.g. transactions) per second, iterations per second if the benchmark has no throughput
+    throughput: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BenchmarkRun {
+    commit: String,
+    recorded_at: String,
+    results: Vec<BenchmarkResult>,
+}
+
+struct Regression {
+    id: String,
+    baseline: f64,
+    current: f64,
+}
+
+impl Regression {
+    fn change_percent(&self) -> f64 {
+        (self.current - self.baseline) / self.baseline * 100.0
+    }
+}
+
+/// `benchmark.json` written by criterion, only the fields we need
+#[derive(Deserialize)]
+struct CriterionBenchmark {
+    full_id: String,
+    throughput: Option<CriterionThroughput>,
+}
+
+#[derive(Deserialize)]
+enum CriterionThroughput {
+    Elements(u64),
+    Bytes(u64),
+    BytesDecimal(u64),
+}
+
+/// `estimates.json` written by criterion, only the fields we need
+#[derive(Deserialize)]
+struct CriterionEstimates {
+    mean: CriterionEstimate,
+}
+
+#[derive(Deserialize)]
+struct CriterionEstimate {
+    point_estimate: f64,
+}
+
+/// Reads the latest result of every benchmark, criterion writes them to `<criterion-dir>/<benchmark>/new`
+fn read_criterion_results(criterion_dir: &Path) -> Result<Vec<BenchmarkResult>, String> {
+    let mut results = vec![];
+    let mut directories = vec![criterion_dir.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = fs::read_dir(&directory)
+            .map_err(|e| format!("Unable to read {}: {}", directory.display(), e))?;
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            if !path.is_dir() {
+                continue;
+            }
+
+            if path.file_name().is_some_and(|name| name == "new") {
+                results.push(read_criterion_result(&path)?);
+            } else {
+                directories.push(path);
+            }
+        }
+    }
+
+    results.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(results)
+}
+
+fn read_criterion_result(directory: &Path) -> Result<BenchmarkResult, String> {
+    let read = |file: &str| {
+        let path = directory.join(file);
+
+        fs::read_to_string(&path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))
+    };
+
+    let benchmark: CriterionBenchmark = serde_json::from_str(&read("benchmark.json")?)
+        .map_err(|e| format!("Unable to parse benchmark.json: {}", e))?;
+
+    let estimates: CriterionEstimates = serde_json::from_str(&read("estimates.json")?)
+        .map_err(|e| format!("Unable to parse estimates.json: {}", e))?;
+
+    let mean_ns = estimates.mean.point_estimate;
+
+    let per_iteration = match benchmark.throughput {
+        Some(CriterionThroughput::Elements(count))
+        | Some(CriterionThroughput::Bytes(count))
+        | Some(CriterionThroughput::BytesDecimal(count)) => count as f64,
+        None => 1.0,
+    };
+
+    Ok(BenchmarkResult {
+        id: benchmark.full_id,
+        mean_ns,
+        throughput: per_iteration / (mean_ns / 1_000_000_000.0),
+    })
+}
+
+/// Benchmarks whose throughput dropped by more than the threshold (in percent). Benchmarks that are not in
+/// the baseline are not compared
+fn compare(
+    baseline: &[BenchmarkResult],
+    current: &[BenchmarkResult],
+    threshold: f64,
+) -> Vec<Regression> {
+    current
+        .iter()
+        .filter_map(|result| {
+            let baseline = baseline.iter().find(|baseline| baseline.id == result.id)?;
+
+            let regression = Regression {
+                id: result.id.clone(),
+                baseline: baseline.throughput,
+                current: result.throughput,
+            };
+
+            (regression.change_percent() < -threshold).then_some(regression)
+        })
+        .collect()
+}
+
+/// Short hash of the checked out commit, suffixed with `-dirty` if there are uncommitted changes
+fn current_commit() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    let Some(commit) = git(&["rev-parse", "--short", "HEAD"]) else {
+        return "unknown".to_string();
+    };
+
+    match git(&["status", "--porcelain"]) {
+        Some(status) if !status.is_empty() => format!("{}-dirty", commit),
+        _ => commit,
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Unable to create {}: {}", parent.display(), e))?;
+    }
+
+    let json = serde_json::to_string_pretty(value).expect("Results should serialize");
+
+    fs::write(path, json).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    if !cli.skip_run {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+
+        let mut command = Command::new(cargo);
+        command.args(["bench", "-p", "database"]);
+
+        if let Some(bench) = &cli.bench {
+            command.args(["--bench", bench]);
+        }
+
+        let status = command
+            .status()
+            .map_err(|e| format!("Unable to run the benches: {}", e))?;
+
+        if !status.success() {
+            return Err(format!("Benches failed: {}", status));
+        }
+    }
+
+    let run = BenchmarkRun {
+        commit: current_commit(),
+        recorded_at: Utc::now().to_rfc3339(),
+        results: read_criterion_results(&cli.criterion_dir)?,
+    };
+
+    let results_path = cli.results_dir.join(format!("{}.json", run.commit));
+    write_json(&results_path, &run)?;
+
+    println!(
+        "📊 Recorded {} benchmarks to {}",
+        run.results.len(),
+        results_path.display()
+    );
+
+    if cli.save_baseline {
+        write_json(&cli.baseline, &run)?;
+
+        println!("✅ Saved the baseline to {}", cli.baseline.display());
+
+        return Ok(());
+    }
+
+    let Ok(baseline) = fs::read_to_string(&cli.baseline) else {
+        println!(
+            "⚠️ No baseline at {}, save one with `--save-baseline`",
+            cli.baseline.display()
+        );
+
+        return Ok(());
+    };
+
+    let baseline: BenchmarkRun = serde_json::from_str(&baseline)
+        .map_err(|e| format!("Unable to parse the baseline: {}", e))?;
+
+    println!(
+        "Comparing against the baseline from commit {} ({})",
+        baseline.commit, baseline.recorded_at
+    );
+
+    for result in &run.results {
+        match baseline
+            .results
+            .iter()
+            .find(|baseline| baseline.id == result.id)
+        {
+            Some(baseline) => println!(
+                "  {:<32} {:>14.0}/s -> {:>14.0}/s ({:+.2}%)",
+                result.id,
+                baseline.throughput,
+                result.throughput,
+                (result.throughput - baseline.throughput) / baseline.throughput * 100.0
+            ),
+            None => println!("  {:<32} {:>14.0}/s (new)", result.id, result.throughput),
+        }
+    }
+
+    let regressions = compare(&baseline.results, &run.results, cli.threshold);
+
+    if regressions.is_empty() {
+        println!("✅ No regressions beyond {}%", cli.threshold);
+
+        return Ok(());
+    }
+
+    let report = regressions
+        .iter()
+        .map(|regression| {
+            format!(
+                "  {}: {:.0}/s -> {:.0}/s ({:+.2}%)",
+                regression.id,
+                regression.baseline,
+                regression.current,
+                regression.change_percent()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    Err(format!(
+        "{} benchmarks regressed by more than {}%:\n{}",
+        regressions.len(),
+        cli.threshold,
+        report
+    ))
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("❌ {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: &str, throughput: f64) -> BenchmarkResult {
+        BenchmarkResult {
+            id: id.to_string(),
+            mean_ns: 1_000_000_000.0 / throughput,
+            throughput,
+        }
+    }
+
+    #[test]
+    fn only_regressions_beyond_the_threshold_fail() {
+        let baseline = vec![
+            result("rm_add_group/1", 100_000.0),
+            result("rm_get_group/1", 100_000.0),
+        ];
+
+        let current = vec![
+            // Within the threshold
+            result("rm_add_group/1", 95_000.0),
+            result("rm_get_group/1", 80_000.0),
+            // Not in the baseline
+            result("rm_hybrid_group/1", 1.0),
+        ];
+
+        let regressions = compare(&baseline, &current, 10.0);
+
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].id, "rm_get_group/1");
+        assert_eq!(regressions[0].change_percent().round(), -20.0);
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process,
+};
+
+use clap::Parser;
+use database::prelude::{
+    read_config_file, ConfigError, Database, DatabaseConfig, DatabaseOptions, LoggingConfig,
+    RequestManager, RowPolicy, ShutdownRequest,
+};
+use serde::Deserialize;
+
+#[derive(clap::Args, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ServerConfig {
+    /// Address the health endpoints are served on [default: 0.0.0.0]
+    #[clap(long, env = "LINEAGEDB_HEALTH_ADDRESS")]
+    health_address: Option<String>,
+
+    /// Port the health endpoints are served on, `GET /health/live` and `GET /health/ready` [default: 9100]
+    #[clap(long, env = "LINEAGEDB_HEALTH_PORT")]
+    health_port: Option<u16>,
+}
+
+/// Layout of the config file, see `--config`
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    server: ServerConfig,
+    #[serde(default)]
+    database: DatabaseConfig,
+}
+
+/// 📀 Lineagedb headless server, runs the database without a client interface. Only health endpoints are served
+///
+/// Every flag can also be set with an environment variable, e.g. `--database-password` with `LINEAGEDB_DATABASE_PASSWORD`
+#[derive(Parser, Debug)]
+struct Cli {
+    /// TOML config file with `[server]` and `[database]` tables, command line arguments and environment variables take precedence
+    #[clap(long, env = "LINEAGEDB_CONFIG")]
+    config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    server: ServerConfig,
+
+    #[clap(flatten)]
+    database: DatabaseConfig,
+
+    #[clap(flatten)]
+    logging: LoggingConfig,
+}
+
+impl Cli {
+    fn load(self) -> Result<(ServerConfig, DatabaseOptions, Vec<RowPolicy>), ConfigError> {
+        let file: ConfigFile = match &self.config {
+            Some(path) => read_config_file(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let server = ServerConfig {
+            health_address: self.server.health_address.or(file.server.health_address),
+            health_port: self.server.health_port.or(file.server.health_port),
+        };
+
+        let database = file.database.merge(self.database);
+
+        Ok((server, database.to_options()?, database.row_policies()?))
+    }
+}
+
+/// Answers a single health check, the database is ready once a worker thread responds to a stats request
+fn serve_health_check(
+    stream: &mut TcpStream,
+    request_manager: &RequestManager,
+) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&*stream).read_line(&mut request_line)?;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, body) = match path {
+        "/health/live" => ("200 OK", "live".to_string()),
+        "/health/ready" => match request_manager.send_info_request() {
+            Ok(_) => ("200 OK", "ready".to_string()),
+            Err(e) => ("503 Service Unavailable", e.to_string()),
+        },
+        _ => ("404 Not Found", "not found".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    cli.logging.init("lineagedb-headless");
+
+    let (server, database_options, row_policies) = match cli.load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            log::logger().flush();
+            process::exit(1);
+        }
+    };
+
+    let health_address = server.health_address.unwrap_or("0.0.0.0".to_string());
+    let health_port = server.health_port.unwrap_or(9100);
+
+    let request_manager = Database::new(database_options).run();
+
+    for policy in row_policies {
+        let status = request_manager
+            .send_create_policy_request(policy)
+            .expect("Should be able to create row policies on startup");
+
+        log::info!("{}", status);
+    }
+
+    let shutdown_request_manager = request_manager.clone();
+
+    ctrlc::set_handler(move || {
+        let shutdown_response = shutdown_request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .expect("Should not timeout");
+
+        log::info!("Shutting down server: {}", shutdown_response);
+        log::logger().flush();
+
+        process::exit(0);
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    let listener = TcpListener::bind((health_address.as_str(), health_port))
+        .expect("Should be able to bind the health endpoint address");
+
+    log::info!(
+        "Health endpoints: http://{}:{}/health/ready",
+        health_address,
+        health_port
+    );
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                if let Err(e) = serve_health_check(&mut stream, &request_manager) {
+                    log::warn!("Failed to serve health check: {}", e);
+                }
+            }
+            Err(e) => log::warn!("Failed to accept health check connection: {}", e),
+        }
+    }
+}
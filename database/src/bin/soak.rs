@@ -0,0 +1,812 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Parser;
+use database::{
+    consts::consts::EntityId,
+    database::{
+        chaos::ChaosOptions,
+        commands::TransactionContext,
+        database::Database,
+        options::DatabaseOptions,
+        request_manager::{RequestManager, RequestManagerError},
+        table::row::{PersonVersion, UpdatePersonData, UpdateStatement},
+    },
+    model::{person::Person, statement::Statement},
+    persistence::storage::{file::FileOptions, StorageEngine},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Exit code of a worker that found a broken invariant, any other exit before the deadline is treated as a crash
+const VIOLATION_EXIT_CODE: i32 = 1;
+
+/// Sequence the clients draw ids from, its values must never repeat or go backwards across restarts
+const SOAK_SEQUENCE: &str = "soak";
+
+/// Runs the database for hours under a mix of CRUD, control commands, restarts and injected storage faults while
+/// checking invariants against an oracle log kept outside of the database: no committed write is lost, ids are
+/// monotonic and the history of every person is the tail of its committed writes
+///
+/// The database runs in a worker process, storage faults crash it (see `DatabaseCrash`) and the worker is started
+/// again, restoring the database and checking every person against the oracle. Exits with an error once an
+/// invariant is broken. Run with `cargo soak`, see `.cargo/config.toml`
+#[derive(Parser, Debug, Clone)]
+struct Cli {
+    /// How long the soak runs for, in seconds
+    #[clap(long, default_value_t = 4 * 60 * 60)]
+    duration_secs: u64,
+
+    /// Where the database stores its snapshots and WAL, the oracle log is written to `<data-dir>/oracle.jsonl`
+    #[clap(long, default_value = "target/soak")]
+    data_dir: PathBuf,
+
+    /// Continues from the data and oracle log of a previous run instead of starting fresh
+    #[clap(long)]
+    resume: bool,
+
+    /// Number of client threads, each owns the people it creates so the oracle knows their state exactly
+    #[clap(long, default_value_t = 4)]
+    clients: usize,
+
+    /// How often a control command (snapshot, WAL compaction, stats) is sent, in milliseconds
+    #[clap(long, default_value_t = 500)]
+    control_interval_ms: u64,
+
+    /// How often the database is restarted in-process with a restore, in seconds
+    #[clap(long, default_value_t = 60)]
+    restart_interval_secs: u64,
+
+    /// Chance that a storage call fails, failed WAL writes and snapshots crash the worker process
+    #[clap(long, default_value_t = 0.0001)]
+    storage_error_probability: f64,
+
+    /// Chance that a worker thread drops the request it received and restarts
+    #[clap(long, default_value_t = 0.001)]
+    worker_restart_probability: f64,
+
+    /// Set by the supervisor, runs the database until the unix timestamp (in seconds) instead of supervising
+    #[clap(long, hide = true)]
+    worker_until: Option<u64>,
+}
+
+#[derive(Error, Debug)]
+enum Violation {
+    #[error("Lost committed write of {id}: expected {expected:?}, found {found:?}")]
+    LostWrite {
+        id: String,
+        expected: Vec<Option<String>>,
+        found: Option<String>,
+    },
+
+    #[error("History of {0} is not ordered by version and transaction id: {1:?}")]
+    NonMonotonicHistory(String, Vec<(usize, usize)>),
+
+    #[error("Transaction id of {id} went backwards from {previous} to {current}")]
+    TransactionIdWentBackwards {
+        id: String,
+        previous: usize,
+        current: usize,
+    },
+
+    #[error("History of {id} is not the tail of its committed states: committed {committed:?}, history {history:?}")]
+    BrokenHistory {
+        id: String,
+        committed: Vec<Option<String>>,
+        history: Vec<Option<String>>,
+    },
+
+    #[error("Sequence value {value} is not above the previous high-water mark {high_water_mark} or repeats")]
+    NonMonotonicSequence { value: u64, high_water_mark: u64 },
+}
+
+/// A line of the oracle log, a write is `Sent` before the database sees it and resolved once its outcome is known
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "entry")]
+enum OracleEntry {
+    /// `state` is the full name of the person, none once the person is removed
+    Sent {
+        id: String,
+        state: Option<String>,
+    },
+    Committed {
+        id: String,
+        state: Option<String>,
+    },
+    /// The write was not run, e.g. it was rolled back or dropped by a restarting worker
+    NotRun {
+        id: String,
+    },
+    Sequence {
+        value: u64,
+    },
+}
+
+/// What the oracle knows about a person
+#[derive(Debug, Default, Clone)]
+struct EntityOracle {
+    /// States of every committed write in commit order, none for a removal
+    committed: Vec<Option<String>>,
+    /// A write whose outcome is unknown, either it timed out or the process crashed while it was in flight
+    pending: Option<Option<String>>,
+    /// Transaction id of the latest version seen in the history
+    last_transaction_id: usize,
+}
+
+impl EntityOracle {
+    fn current(&self) -> Option<String> {
+        self.committed.last().cloned().flatten()
+    }
+
+    /// Whether the person exists and no write to it has an unknown outcome
+    fn is_writable(&self) -> bool {
+        self.pending.is_none() && self.current().is_some()
+    }
+}
+
+#[derive(Default)]
+struct OracleState {
+    entities: BTreeMap<String, EntityOracle>,
+    sequence_values: HashSet<u64>,
+    sequence_high_water_mark: u64,
+}
+
+impl OracleState {
+    fn apply(&mut self, entry: &OracleEntry) {
+        match entry {
+            OracleEntry::Sent { id, state } => {
+                self.entities.entry(id.clone()).or_default().pending = Some(state.clone());
+            }
+            OracleEntry::Committed { id, state } => {
+                let entity = self.entities.entry(id.clone()).or_default();
+                entity.pending = None;
+                entity.committed.push(state.clone());
+            }
+            OracleEntry::NotRun { id } => {
+                self.entities.entry(id.clone()).or_default().pending = None;
+            }
+            OracleEntry::Sequence { value } => {
+                self.sequence_values.insert(*value);
+                self.sequence_high_water_mark = self.sequence_high_water_mark.max(*value);
+            }
+        }
+    }
+
+    /// Compares what the database returned for a person against the oracle, a pending write is resolved by what
+    /// was found. Returns the entry that resolved it
+    fn check(
+        &mut self,
+        id: &str,
+        found: Option<String>,
+        history: &[PersonVersion],
+    ) -> Result<Option<OracleEntry>, Violation> {
+        let entity = self.entities.entry(id.to_string()).or_default();
+
+        let resolved = match &entity.pending {
+            Some(pending) if *pending == found && entity.current() != found => {
+                Some(OracleEntry::Committed {
+                    id: id.to_string(),
+                    state: pending.clone(),
+                })
+            }
+            Some(_) if entity.current() == found => {
+                Some(OracleEntry::NotRun { id: id.to_string() })
+            }
+            _ => None,
+        };
+
+        if entity.current() != found && resolved.is_none() {
+            let mut expected = vec![entity.current()];
+            expected.extend(entity.pending.clone());
+
+            return Err(Violation::LostWrite {
+                id: id.to_string(),
+                expected,
+                found,
+            });
+        }
+
+        if let Some(entry) = &resolved {
+            self.apply(entry);
+        }
+
+        let entity = self.entities.get_mut(id).expect("entity was just checked");
+
+        let ids: Vec<(usize, usize)> = history
+            .iter()
+            .map(|version| (version.version.0, version.transaction_id.to_number()))
+            .collect();
+
+        if ids
+            .windows(2)
+            .any(|pair| pair[0].0 >= pair[1].0 || pair[0].1 >= pair[1].1)
+        {
+            return Err(Violation::NonMonotonicHistory(id.to_string(), ids));
+        }
+
+        if let Some((_, transaction_id)) = ids.last() {
+            if *transaction_id < entity.last_transaction_id {
+                return Err(Violation::TransactionIdWentBackwards {
+                    id: id.to_string(),
+                    previous: entity.last_transaction_id,
+                    current: *transaction_id,
+                });
+            }
+
+            entity.last_transaction_id = *transaction_id;
+        }
+
+        // Snapshots only keep the latest version, so the history is the tail of the committed writes without gaps
+        let states: Vec<Option<String>> = history
+            .iter()
+            .map(|version| version.get_person().map(|person| person.full_name))
+            .collect();
+
+        let is_tail = entity.committed.ends_with(&states)
+            && (!states.is_empty() || entity.current().is_none());
+
+        if !is_tail || states.last().cloned().flatten() != found {
+            return Err(Violation::BrokenHistory {
+                id: id.to_string(),
+                committed: entity.committed.clone(),
+                history: states,
+            });
+        }
+
+        Ok(resolved)
+    }
+
+    fn check_sequence(&self, value: u64) -> Result<(), Violation> {
+        if self.sequence_values.contains(&value) {
+            return Err(Violation::NonMonotonicSequence {
+                value,
+                high_water_mark: self.sequence_high_water_mark,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The expected state of every person, backed by an append-only log that survives crashes of the worker process
+struct Oracle {
+    log: Mutex<(File, OracleState)>,
+    /// High-water mark of the sequence when the worker started, values drawn since must be above it
+    sequence_floor: u64,
+}
+
+impl Oracle {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let mut state = OracleState::default();
+
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                // The last line may be torn if the process crashed while writing it
+                match serde_json::from_str::<OracleEntry>(&line?) {
+                    Ok(entry) => state.apply(&entry),
+                    Err(e) => log::warn!("Skipping unreadable oracle entry: {}", e),
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            sequence_floor: state.sequence_high_water_mark,
+            log: Mutex::new((file, state)),
+        })
+    }
+
+    fn record(&self, entry: OracleEntry) {
+        let mut log = self.log.lock().unwrap();
+        let (file, state) = &mut *log;
+
+        Self::append(file, &entry);
+        state.apply(&entry);
+    }
+
+    fn append(file: &mut File, entry: &OracleEntry) {
+        let mut line = serde_json::to_string(entry).expect("Oracle entries should serialize");
+        line.push('\n');
+
+        file.write_all(line.as_bytes())
+            .expect("Should be able to append to the oracle log");
+    }
+
+    fn entity(&self, id: &str) -> Option<EntityOracle> {
+        self.log.lock().unwrap().1.entities.get(id).cloned()
+    }
+
+    fn ids(&self) -> Vec<String> {
+        self.log
+            .lock()
+            .unwrap()
+            .1
+            .entities
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Reads the person and its history and checks them against the oracle. Reads that fail (e.g. during a
+    /// restart) are not checked
+    fn verify(&self, request_manager: &RequestManager, id: &str) -> Result<(), Violation> {
+        let entity_id = EntityId(id.to_string());
+
+        let Ok(results) = request_manager.send_transaction(
+            vec![
+                Statement::Get(entity_id.clone()),
+                Statement::Lineage(entity_id),
+            ],
+            TransactionContext::default(),
+        ) else {
+            return Ok(());
+        };
+
+        let mut results = results.into_iter();
+        let found = results
+            .next()
+            .expect("get should generate a response")
+            .get_single()
+            .map(|person| person.full_name);
+        let history = results
+            .next()
+            .expect("lineage should generate a response")
+            .list_version();
+
+        let mut log = self.log.lock().unwrap();
+        let (file, state) = &mut *log;
+
+        if let Some(resolved) = state.check(id, found, &history)? {
+            Self::append(file, &resolved);
+        }
+
+        Ok(())
+    }
+
+    fn verify_sequence(&self, value: u64) -> Result<(), Violation> {
+        let mut log = self.log.lock().unwrap();
+        let (file, state) = &mut *log;
+
+        if value <= self.sequence_floor {
+            return Err(Violation::NonMonotonicSequence {
+                value,
+                high_water_mark: self.sequence_floor,
+            });
+        }
+
+        state.check_sequence(value)?;
+
+        let entry = OracleEntry::Sequence { value };
+        Self::append(file, &entry);
+        state.apply(&entry);
+
+        Ok(())
+    }
+}
+
+fn fail(violation: Violation) -> ! {
+    log::error!("Invariant violated: {}", violation);
+    process::exit(VIOLATION_EXIT_CODE);
+}
+
+/// Sends a write the oracle knows about, the write is resolved unless its outcome is unknown
+fn write(
+    oracle: &Oracle,
+    request_manager: &RequestManager,
+    id: &str,
+    state: Option<String>,
+    statement: Statement,
+) {
+    oracle.record(OracleEntry::Sent {
+        id: id.to_string(),
+        state: state.clone(),
+    });
+
+    match request_manager.send_transaction(vec![statement], TransactionContext::default()) {
+        Ok(_) => {
+            oracle.record(OracleEntry::Committed {
+                id: id.to_string(),
+                state,
+            });
+        }
+        // The request may still be queued or the WAL may not have been flushed, the write is resolved by the next
+        //  restart or worker process
+        Err(RequestManagerError::DatabaseTimeout | RequestManagerError::TransactionStatus(_)) => {}
+        Err(e) => {
+            log::debug!("Write to {} was not run: {}", id, e);
+
+            oracle.record(OracleEntry::NotRun { id: id.to_string() });
+        }
+    }
+}
+
+/// Creates, updates, removes and checks the people it owns until stopped
+fn run_client(
+    client: usize,
+    mut owned: Vec<String>,
+    oracle: &Oracle,
+    request_manager: &RequestManager,
+    operations: &RwLock<()>,
+    stop: &AtomicBool,
+) {
+    let mut rng = rand::thread_rng();
+
+    while !stop.load(Ordering::Relaxed) {
+        let _operation = operations.read().unwrap();
+        let operation = rng.gen_range(0..100);
+
+        if owned.is_empty() || operation < 15 {
+            let id = format!("soak-{}-{}", client, Uuid::new_v4());
+            let full_name = Uuid::new_v4().to_string();
+
+            let person = Person {
+                id: EntityId(id.clone()),
+                full_name: full_name.clone(),
+                email: None,
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
+            };
+
+            write(
+                oracle,
+                request_manager,
+                &id,
+                Some(full_name),
+                Statement::Add(person),
+            );
+
+            if oracle
+                .entity(&id)
+                .is_some_and(|entity| entity.is_writable())
+            {
+                owned.push(id);
+            }
+
+            continue;
+        }
+
+        let index = rng.gen_range(0..owned.len());
+        let id = owned[index].clone();
+
+        match operation {
+            15..=69 => {
+                let full_name = Uuid::new_v4().to_string();
+                let update = UpdatePersonData {
+                    full_name: UpdateStatement::Set(full_name.clone()),
+                    ..Default::default()
+                };
+
+                write(
+                    oracle,
+                    request_manager,
+                    &id,
+                    Some(full_name),
+                    Statement::Update(EntityId(id.clone()), update),
+                );
+            }
+            70..=74 => write(
+                oracle,
+                request_manager,
+                &id,
+                None,
+                Statement::Remove(EntityId(id.clone())),
+            ),
+            75..=79 => {
+                if let Ok(value) = request_manager
+                    .send_next_val(SOAK_SEQUENCE.to_string(), TransactionContext::default())
+                {
+                    oracle.verify_sequence(value).unwrap_or_else(|e| fail(e));
+                }
+            }
+            _ => oracle
+                .verify(request_manager, &id)
+                .unwrap_or_else(|e| fail(e)),
+        }
+
+        // People with a write of unknown outcome, or that were removed, are no longer written to
+        if !oracle
+            .entity(&id)
+            .is_some_and(|entity| entity.is_writable())
+        {
+            owned.swap_remove(index);
+        }
+    }
+}
+
+/// Sends snapshots, WAL compactions and stats requests and restarts the database in-process until stopped
+fn run_control(
+    cli: &Cli,
+    options: &DatabaseOptions,
+    oracle: &Oracle,
+    request_manager: &RequestManager,
+    operations: &RwLock<()>,
+    stop: &AtomicBool,
+) {
+    let mut rng = rand::thread_rng();
+    let restart_every = (cli.restart_interval_secs * 1000 / cli.control_interval_ms.max(1)).max(1);
+    let mut ticks = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(cli.control_interval_ms));
+        ticks += 1;
+
+        if ticks % restart_every == 0 {
+            // Clients wait until every person has been checked, none of their writes are in flight
+            let _exclusive = operations.write().unwrap();
+
+            log::info!("Restarting the database");
+
+            if let Err(e) = request_manager.restart(options.clone()) {
+                log::warn!("Unable to restart the database: {}", e);
+            }
+
+            // The previous workers were joined, nothing queued before the restart is applied later on
+            for id in oracle.ids() {
+                oracle
+                    .verify(request_manager, &id)
+                    .unwrap_or_else(|e| fail(e));
+            }
+
+            continue;
+        }
+
+        let result = match rng.gen_range(0..3) {
+            0 => request_manager.send_snapshot_request(),
+            1 => request_manager.send_compact_wal_request(),
+            _ => request_manager
+                .send_info_request()
+                .map(|_| "stats".to_string()),
+        };
+
+        if let Err(e) = result {
+            log::warn!("Control command failed: {}", e);
+        }
+    }
+}
+
+fn run_worker(cli: Cli, until: u64) {
+    let oracle = Oracle::open(&cli.data_dir.join("oracle.jsonl"))
+        .expect("Should be able to open the oracle log");
+
+    let options = DatabaseOptions::default()
+        .set_storage_engine(StorageEngine::File(FileOptions::new(
+            cli.data_dir.join("data"),
+        )))
+        .set_restore(true)
+        .set_paranoid_checks(true)
+        .set_chaos(
+            ChaosOptions::default()
+                .set_storage_error_probability(cli.storage_error_probability)
+                .set_worker_restart_probability(cli.worker_restart_probability),
+        );
+
+    let request_manager = Database::new(options.clone()).run();
+
+    // Writes that were in flight when the previous worker crashed are resolved by what was restored
+    let ids = oracle.ids();
+
+    for id in &ids {
+        oracle
+            .verify(&request_manager, id)
+            .unwrap_or_else(|e| fail(e));
+    }
+
+    log::info!(
+        "Verified {} people against the oracle after the restore",
+        ids.len()
+    );
+
+    let live: Vec<String> = ids
+        .into_iter()
+        .filter(|id| oracle.entity(id).is_some_and(|entity| entity.is_writable()))
+        .collect();
+
+    let stop = AtomicBool::new(false);
+    let operations = RwLock::new(());
+    let clients = cli.clients.max(1);
+
+    thread::scope(|scope| {
+        for client in 0..clients {
+            let owned = live.iter().skip(client).step_by(clients).cloned().collect();
+            let (oracle, request_manager, operations, stop) =
+                (&oracle, &request_manager, &operations, &stop);
+
+            scope.spawn(move || {
+                run_client(client, owned, oracle, request_manager, operations, stop)
+            });
+        }
+
+        scope.spawn(|| {
+            run_control(
+                &cli,
+                &options,
+                &oracle,
+                &request_manager,
+                &operations,
+                &stop,
+            )
+        });
+
+        while unix_now() < until {
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    for id in oracle.ids() {
+        oracle
+            .verify(&request_manager, &id)
+            .unwrap_or_else(|e| fail(e));
+    }
+
+    let _ = request_manager.close();
+
+    log::info!("Soak finished, every invariant held");
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Clock should be after the unix epoch")
+        .as_secs()
+}
+
+/// Starts worker processes until the deadline, a worker that exits early crashed and is started again
+fn supervise(cli: Cli) -> Result<(), String> {
+    if !cli.resume {
+        match fs::remove_dir_all(&cli.data_dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Unable to clear {}: {}", cli.data_dir.display(), e)),
+        }
+    }
+
+    fs::create_dir_all(&cli.data_dir)
+        .map_err(|e| format!("Unable to create {}: {}", cli.data_dir.display(), e))?;
+
+    let until = unix_now() + cli.duration_secs;
+    let executable =
+        std::env::current_exe().map_err(|e| format!("Unable to find the soak binary: {}", e))?;
+    let mut crashes = 0;
+
+    loop {
+        let status = Command::new(&executable)
+            .args(std::env::args().skip(1))
+            .args(["--worker-until", &until.to_string()])
+            .status()
+            .map_err(|e| format!("Unable to start a worker: {}", e))?;
+
+        if status.code() == Some(VIOLATION_EXIT_CODE) {
+            return Err(format!(
+                "Invariant violated after {} crashes, see the worker log",
+                crashes
+            ));
+        }
+
+        if unix_now() >= until && status.success() {
+            println!(
+                "✅ Soaked for {}s with {} crashes",
+                cli.duration_secs, crashes
+            );
+
+            return Ok(());
+        }
+
+        crashes += 1;
+
+        log::warn!("Worker exited with {}, restarting it", status);
+    }
+}
+
+fn main() {
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    let cli = Cli::parse();
+
+    if let Some(until) = cli.worker_until {
+        return run_worker(cli, until);
+    }
+
+    if let Err(e) = supervise(cli) {
+        eprintln!("❌ {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use database::{
+        consts::consts::{TransactionId, VersionId},
+        database::table::row::PersonVersionState,
+    };
+
+    use super::*;
+
+    fn version(version: usize, transaction_id: usize, state: Option<&str>) -> PersonVersion {
+        PersonVersion {
+            id: EntityId("soak".to_string()),
+            state: match state {
+                Some(full_name) => PersonVersionState::State(Person {
+                    id: EntityId("soak".to_string()),
+                    full_name: full_name.to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                }),
+                None => PersonVersionState::Delete,
+            },
+            version: VersionId(version),
+            transaction_id: TransactionId(transaction_id),
+            lineage: None,
+        }
+    }
+
+    fn committed(state: &str) -> OracleEntry {
+        OracleEntry::Committed {
+            id: "soak".to_string(),
+            state: Some(state.to_string()),
+        }
+    }
+
+    #[test]
+    fn resolves_pending_writes_and_detects_lost_writes() {
+        let mut oracle = OracleState::default();
+        oracle.apply(&committed("a"));
+        oracle.apply(&OracleEntry::Sent {
+            id: "soak".to_string(),
+            state: Some("b".to_string()),
+        });
+
+        // The pending write made it before the crash
+        let history = [version(1, 1, Some("a")), version(2, 3, Some("b"))];
+        let resolved = oracle
+            .check("soak", Some("b".to_string()), &history)
+            .unwrap();
+        assert_eq!(resolved, Some(committed("b")));
+
+        // A committed write that is missing is a violation
+        let lost = oracle.check("soak", Some("a".to_string()), &history[..1]);
+        assert!(matches!(lost, Err(Violation::LostWrite { .. })));
+    }
+
+    #[test]
+    fn detects_broken_histories() {
+        let mut oracle = OracleState::default();
+        oracle.apply(&committed("a"));
+        oracle.apply(&committed("b"));
+
+        // Out of order transaction ids
+        let history = [version(1, 4, Some("a")), version(2, 3, Some("b"))];
+        let result = oracle.check("soak", Some("b".to_string()), &history);
+        assert!(matches!(result, Err(Violation::NonMonotonicHistory(..))));
+
+        // A committed state missing from the middle of the history
+        oracle.apply(&committed("c"));
+        let history = [version(1, 1, Some("a")), version(2, 2, Some("c"))];
+        let result = oracle.check("soak", Some("c".to_string()), &history);
+        assert!(matches!(result, Err(Violation::BrokenHistory { .. })));
+
+        // Versions before a snapshot are not kept
+        let history = [version(1, 1, Some("b")), version(2, 2, Some("c"))];
+        assert!(oracle
+            .check("soak", Some("c".to_string()), &history)
+            .is_ok());
+    }
+}
@@ -5,7 +5,7 @@ use thiserror::Error;
 use uuid::Uuid;
 
 // New Type Pattern -- https://doc.rust-lang.org/rust-by-example/generics/new_types.html
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TransactionId(pub usize);
 
 impl TransactionId {
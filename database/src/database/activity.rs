@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::consts::consts::TransactionId;
+
+use super::commands::DatabaseCommand;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(pub usize);
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Cooperative cancellation flag for a request, long running statements periodically check whether
+/// they have been cancelled and stop early if they have
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A request that a database worker is currently executing
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveRequest {
+    pub request_id: RequestId,
+    pub thread_id: usize,
+    pub transaction_id: TransactionId,
+    /// Statement kinds for transactions (e.g. `Add, Update`) or the control name for controls
+    pub kind: String,
+    pub client_id: Option<String>,
+    pub tag: Option<String>,
+    pub elapsed: Duration,
+}
+
+/// Counters for requests made by a single client since the database started
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientStats {
+    pub client_id: String,
+    pub requests: usize,
+    pub rollbacks: usize,
+    pub total_elapsed: Duration,
+    /// Time since the client's last request finished
+    pub last_seen: Duration,
+}
+
+/// Counters for requests with a tag since the database started, see `TransactionContext::set_tag`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagStats {
+    pub tag: String,
+    pub requests: usize,
+    pub rollbacks: usize,
+    pub total_elapsed: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActivityReport {
+    pub active_requests: Vec<ActiveRequest>,
+    pub clients: Vec<ClientStats>,
+    pub tags: Vec<TagStats>,
+}
+
+struct RunningRequest {
+    thread_id: usize,
+    transaction_id: TransactionId,
+    kind: String,
+    client_id: Option<String>,
+    tag: Option<String>,
+    started: Instant,
+    cancellation: CancellationToken,
+}
+
+struct RequestCounters {
+    requests: usize,
+    rollbacks: usize,
+    total_elapsed: Duration,
+    last_seen: Instant,
+}
+
+impl RequestCounters {
+    fn new() -> Self {
+        Self {
+            requests: 0,
+            rollbacks: 0,
+            total_elapsed: Duration::ZERO,
+            last_seen: Instant::now(),
+        }
+    }
+
+    fn finished(&mut self, elapsed: Duration, rolled_back: bool) {
+        self.requests += 1;
+        self.total_elapsed += elapsed;
+        self.last_seen = Instant::now();
+
+        if rolled_back {
+            self.rollbacks += 1;
+        }
+    }
+}
+
+/// Tracks the requests that are being executed across every database worker, similar to `pg_stat_activity`
+#[derive(Default)]
+pub struct ActivityTracker {
+    next_request_id: AtomicUsize,
+    running: Mutex<HashMap<RequestId, RunningRequest>>,
+    clients: Mutex<HashMap<String, RequestCounters>>,
+    tags: Mutex<HashMap<String, RequestCounters>>,
+}
+
+impl ActivityTracker {
+    /// Registers a request as running, the request is finished when the returned guard is dropped
+    pub fn start(
+        &self,
+        thread_id: usize,
+        transaction_id: &TransactionId,
+        command: &DatabaseCommand,
+        client_id: Option<String>,
+        tag: Option<String>,
+    ) -> ActivityGuard {
+        let request_id = RequestId(self.next_request_id.fetch_add(1, Ordering::SeqCst));
+        let cancellation = CancellationToken::default();
+
+        self.running.lock().unwrap().insert(
+            request_id,
+            RunningRequest {
+                thread_id,
+                transaction_id: transaction_id.clone(),
+                kind: command.kind(),
+                client_id,
+                tag,
+                started: Instant::now(),
+                cancellation: cancellation.clone(),
+            },
+        );
+
+        ActivityGuard {
+            tracker: self,
+            request_id,
+            cancellation,
+            rolled_back: false,
+        }
+    }
+
+    /// Flags a running request as cancelled, returns false if the request is not running. The request
+    /// stops the next time it checks its cancellation token
+    pub fn kill(&self, request_id: RequestId) -> bool {
+        match self.running.lock().unwrap().get(&request_id) {
+            Some(request) => {
+                request.cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn report(&self) -> ActivityReport {
+        let mut active_requests: Vec<ActiveRequest> = self
+            .running
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(request_id, request)| ActiveRequest {
+                request_id: *request_id,
+                thread_id: request.thread_id,
+                transaction_id: request.transaction_id.clone(),
+                kind: request.kind.clone(),
+                client_id: request.client_id.clone(),
+                tag: request.tag.clone(),
+                elapsed: request.started.elapsed(),
+            })
+            .collect();
+
+        // Longest running requests first
+        active_requests.sort_by(|a, b| b.elapsed.cmp(&a.elapsed));
+
+        let mut clients: Vec<ClientStats> = self
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(client_id, counters)| ClientStats {
+                client_id: client_id.clone(),
+                requests: counters.requests,
+                rollbacks: counters.rollbacks,
+                total_elapsed: counters.total_elapsed,
+                last_seen: counters.last_seen.elapsed(),
+            })
+            .collect();
+
+        clients.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+
+        let mut tags: Vec<TagStats> = self
+            .tags
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tag, counters)| TagStats {
+                tag: tag.clone(),
+                requests: counters.requests,
+                rollbacks: counters.rollbacks,
+                total_elapsed: counters.total_elapsed,
+            })
+            .collect();
+
+        tags.sort_by(|a, b| a.tag.cmp(&b.tag));
+
+        ActivityReport {
+            active_requests,
+            clients,
+            tags,
+        }
+    }
+
+    fn finish(&self, request_id: RequestId, rolled_back: bool) {
+        let Some(request) = self.running.lock().unwrap().remove(&request_id) else {
+            return;
+        };
+
+        let elapsed = request.started.elapsed();
+
+        if let Some(client_id) = request.client_id {
+            self.clients
+                .lock()
+                .unwrap()
+                .entry(client_id)
+                .or_insert_with(RequestCounters::new)
+                .finished(elapsed, rolled_back);
+        }
+
+        if let Some(tag) = request.tag {
+            self.tags
+                .lock()
+                .unwrap()
+                .entry(tag)
+                .or_insert_with(RequestCounters::new)
+                .finished(elapsed, rolled_back);
+        }
+    }
+}
+
+pub struct ActivityGuard<'a> {
+    tracker: &'a ActivityTracker,
+    request_id: RequestId,
+    cancellation: CancellationToken,
+    rolled_back: bool,
+}
+
+impl<'a> ActivityGuard<'a> {
+    pub fn request_id(&self) -> RequestId {
+        self.request_id
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    pub fn set_rolled_back(&mut self) {
+        self.rolled_back = true;
+    }
+}
+
+impl<'a> Drop for ActivityGuard<'a> {
+    fn drop(&mut self) {
+        self.tracker.finish(self.request_id, self.rolled_back);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::commands::Control, model::statement::Statement};
+
+    #[test]
+    fn tracks_running_requests_and_clients() {
+        let tracker = ActivityTracker::default();
+        let transaction_id = TransactionId::new_first_transaction();
+
+        let list = DatabaseCommand::Transaction(vec![Statement::List(None)]);
+        let stats = DatabaseCommand::Control(Control::DatabaseStats);
+
+        let mut list_guard = tracker.start(
+            0,
+            &transaction_id,
+            &list,
+            Some("a".to_string()),
+            Some("interactive".to_string()),
+        );
+        let stats_guard = tracker.start(1, &transaction_id, &stats, None, None);
+
+        let report = tracker.report();
+
+        let mut kinds: Vec<String> = report
+            .active_requests
+            .iter()
+            .map(|r| r.kind.clone())
+            .collect();
+        kinds.sort();
+
+        assert_eq!(kinds, vec!["DatabaseStats", "List"]);
+        assert!(report.clients.is_empty());
+
+        list_guard.set_rolled_back();
+        drop(list_guard);
+        drop(stats_guard);
+
+        let report = tracker.report();
+
+        assert!(report.active_requests.is_empty());
+        assert_eq!(report.clients.len(), 1);
+        assert_eq!(report.clients[0].client_id, "a");
+        assert_eq!(report.clients[0].requests, 1);
+        assert_eq!(report.clients[0].rollbacks, 1);
+        assert_eq!(report.tags.len(), 1);
+        assert_eq!(report.tags[0].tag, "interactive");
+        assert_eq!(report.tags[0].requests, 1);
+    }
+
+    #[test]
+    fn kill_cancels_running_request() {
+        let tracker = ActivityTracker::default();
+        let transaction_id = TransactionId::new_first_transaction();
+
+        let list = DatabaseCommand::Transaction(vec![Statement::List(None)]);
+
+        let guard = tracker.start(0, &transaction_id, &list, None, None);
+        let request_id = guard.request_id();
+
+        assert!(!guard.cancellation().is_cancelled());
+        assert!(tracker.kill(request_id));
+        assert!(guard.cancellation().is_cancelled());
+
+        // Requests that have finished can no longer be killed
+        drop(guard);
+        assert!(!tracker.kill(request_id));
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use thiserror::Error;
+
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    model::statement::Statement,
+    persistence::{
+        backup::{restore_from_backup, BackupRestoreError, BackupRestoreReport},
+        persistence::Persistence,
+        snapshot::SnapshotVerification,
+        storage::{
+            network::StorageLatency, replication::StorageReplication, Storage, StorageEngine,
+            StorageError,
+        },
+        transaction::TransactionStatus,
+    },
+};
+
+use super::{
+    commands::{ShutdownRequest, TransactionContext},
+    database::Database,
+    options::DatabaseOptions,
+    request_manager::{RequestManager, RequestManagerError},
+};
+
+/// Offline maintenance of a database's storage, see the `lineagedb-admin` binary. Nothing else may be running
+/// against the storage while a task runs
+#[derive(Error, Debug)]
+pub enum AdminError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Request failed: {0}")]
+    Request(#[from] RequestManagerError),
+
+    #[error("Unable to copy the database: {0}")]
+    Copy(#[from] BackupRestoreError),
+}
+
+/// Summary of the WAL as it is stored, the transactions are decoded but not applied
+#[derive(Debug, Default, PartialEq)]
+pub struct WalInspection {
+    pub transaction_count: usize,
+    pub first_transaction_id: Option<TransactionId>,
+    pub last_transaction_id: Option<TransactionId>,
+    /// Transactions per status, e.g. `Committed` or `Prepared`
+    pub statuses: BTreeMap<&'static str, usize>,
+    /// Statements per kind, e.g. `Add` or `Update`
+    pub statements: BTreeMap<&'static str, usize>,
+    /// Line (starting at 1) and error of every transaction that could not be decoded, e.g. a torn write
+    pub unreadable: Vec<(usize, String)>,
+}
+
+impl WalInspection {
+    pub fn to_info(&self) -> Vec<(String, String)> {
+        let id = |id: &Option<TransactionId>| {
+            id.as_ref()
+                .map(|id| id.to_number().to_string())
+                .unwrap_or("None".to_string())
+        };
+
+        let mut info = vec![
+            (
+                "Transactions".to_string(),
+                self.transaction_count.to_string(),
+            ),
+            (
+                "First transaction id".to_string(),
+                id(&self.first_transaction_id),
+            ),
+            (
+                "Last transaction id".to_string(),
+                id(&self.last_transaction_id),
+            ),
+        ];
+
+        info.extend(
+            self.statuses
+                .iter()
+                .map(|(status, count)| (format!("Status {}", status), count.to_string())),
+        );
+        info.extend(
+            self.statements
+                .iter()
+                .map(|(statement, count)| (format!("Statement {}", statement), count.to_string())),
+        );
+        info.extend(
+            self.unreadable
+                .iter()
+                .map(|(line, e)| (format!("Unreadable line {}", line), e.clone())),
+        );
+
+        info
+    }
+}
+
+fn status_name(status: &TransactionStatus) -> &'static str {
+    match status {
+        TransactionStatus::Committed => "Committed",
+        TransactionStatus::Prepared(_) => "Prepared",
+        TransactionStatus::CommitPrepared(_) => "CommitPrepared",
+        TransactionStatus::AbortPrepared(_) => "AbortPrepared",
+    }
+}
+
+fn open_storage(options: &DatabaseOptions) -> Arc<Mutex<dyn Storage + Sync + Send>> {
+    StorageEngine::get_engine(
+        options.clone(),
+        None,
+        Arc::new(StorageLatency::default()),
+        Arc::new(StorageReplication::default()),
+    )
+}
+
+/// Reads the WAL without restoring the database. Transactions archived with earlier snapshots are not included
+pub fn inspect_wal(options: &DatabaseOptions) -> Result<WalInspection, AdminError> {
+    let mut inspection = WalInspection::default();
+
+    for (line, transaction) in Persistence::open_read_only(options.clone()).transactions()? {
+        let transaction = match transaction {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                inspection.unreadable.push((line, e.to_string()));
+                continue;
+            }
+        };
+
+        inspection.transaction_count += 1;
+        *inspection
+            .statuses
+            .entry(status_name(&transaction.status))
+            .or_default() += 1;
+
+        for statement in &transaction.statements {
+            let name: &'static str = statement.into();
+            *inspection.statements.entry(name).or_default() += 1;
+        }
+
+        if inspection.first_transaction_id.is_none() {
+            inspection.first_transaction_id = Some(transaction.id.clone());
+        }
+
+        inspection.last_transaction_id = Some(transaction.id);
+    }
+
+    Ok(inspection)
+}
+
+/// Checks the latest snapshot against its checksum and record count without restoring it
+pub fn verify_snapshot(options: &DatabaseOptions) -> Result<SnapshotVerification, AdminError> {
+    Ok(Persistence::open_read_only(options.clone()).verify_snapshot()?)
+}
+
+/// Restores the database without serving clients, runs `task` and shuts the database down again
+fn with_database<T>(
+    options: DatabaseOptions,
+    task: impl FnOnce(&RequestManager) -> Result<T, AdminError>,
+) -> Result<T, AdminError> {
+    let request_manager = Database::new(options.set_restore(true)).run();
+
+    let result = task(&request_manager);
+
+    request_manager.send_shutdown_request(ShutdownRequest::Coordinator)?;
+
+    result
+}
+
+/// Takes a snapshot and drops the transactions it covers from the WAL, returns the compaction's status
+pub fn compact(options: DatabaseOptions) -> Result<String, AdminError> {
+    with_database(options, |request_manager| {
+        request_manager.send_snapshot_request()?;
+
+        Ok(request_manager.send_compact_wal_request()?)
+    })
+}
+
+/// The latest version of every person as JSON lines, or as an ASCII armored age file encrypted for the
+/// recipients, see `Control::Export`
+pub fn export(options: DatabaseOptions, recipients: Vec<String>) -> Result<String, AdminError> {
+    with_database(options, |request_manager| {
+        if !recipients.is_empty() {
+            return Ok(request_manager.send_export_request(recipients)?);
+        }
+
+        let people = request_manager.send_list(None, TransactionContext::default())?;
+
+        Ok(people
+            .iter()
+            .map(|person| serde_json::to_string(person).expect("People always serialize") + "\n")
+            .collect())
+    })
+}
+
+/// Purges the person, then snapshots and compacts the WAL so the purged versions are only left in WAL archives
+/// (see `DatabaseOptions::set_archive_wal`). Returns the number of versions that were removed
+pub fn purge_entity(options: DatabaseOptions, id: EntityId) -> Result<usize, AdminError> {
+    with_database(options, |request_manager| {
+        let purged = request_manager
+            .send_single_statement(Statement::Purge(id), TransactionContext::default())?
+            .purged();
+
+        request_manager.send_snapshot_request()?;
+        request_manager.send_compact_wal_request()?;
+
+        Ok(purged)
+    })
+}
+
+/// Copies the database into `target`, which must be empty, e.g. to move a file database to the directory per
+/// table layout or to another engine. Blobs are copied as is, see `restore_from_backup`
+pub fn migrate_format(
+    options: &DatabaseOptions,
+    target: StorageEngine,
+) -> Result<BackupRestoreReport, AdminError> {
+    let target_storage = open_storage(&options.clone().set_storage_engine(target));
+    let mut target_storage = target_storage.lock().unwrap();
+
+    target_storage.init()?;
+
+    let report = restore_from_backup(
+        &mut *open_storage(options).lock().unwrap(),
+        &mut *target_storage,
+    )?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::{
+        model::person::Person,
+        persistence::storage::file::{FileLayout, FileOptions},
+    };
+
+    use super::*;
+
+    fn options(dir: &PathBuf, layout: FileLayout) -> DatabaseOptions {
+        DatabaseOptions::default()
+            .set_storage_engine(StorageEngine::File(
+                FileOptions::new(dir.clone()).set_layout(layout),
+            ))
+            .set_restore(false)
+    }
+
+    #[test]
+    fn maintenance_tasks_run_against_stopped_storage() {
+        let dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+        let flat = options(&dir, FileLayout::Flat);
+
+        let request_manager = Database::new(flat.clone()).run();
+
+        let kept = request_manager
+            .send_add(
+                Person::new("Kept".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+        let purged = request_manager
+            .send_add(
+                Person::new("Purged".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .unwrap();
+
+        let inspection = inspect_wal(&flat).unwrap();
+
+        assert_eq!(inspection.transaction_count, 2);
+        assert_eq!(inspection.statements.get("Add"), Some(&2));
+        assert!(inspection.unreadable.is_empty());
+
+        assert_eq!(purge_entity(flat.clone(), purged.id.clone()).unwrap(), 1);
+
+        // The purge was snapshotted and compacted away
+        assert_eq!(inspect_wal(&flat).unwrap().transaction_count, 0);
+        assert!(verify_snapshot(&flat).unwrap().is_valid());
+
+        let export = export(flat.clone(), vec![]).unwrap();
+
+        assert!(export.contains(&kept.id.0));
+        assert!(!export.contains(&purged.id.0));
+
+        let per_table = options(&dir.join("per-table"), FileLayout::DirectoryPerTable);
+
+        migrate_format(&flat, per_table.storage_engine.clone()).unwrap();
+
+        assert!(dir.join("per-table").join("person").exists());
+        assert!(verify_snapshot(&per_table).unwrap().is_valid());
+        assert!(
+            migrate_format(&flat, per_table.storage_engine.clone())
+                .unwrap()
+                .already_restored
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    model::statement::{Statement, StatementResult},
+    persistence::storage::{ReadBlobState, Storage, StorageError, StorageResult},
+};
+
+use super::{
+    commands::{DatabaseCommandTransactionResponse, TransactionContext},
+    database::Database,
+};
+
+/// Defines how rolled back transactions are recorded, see `RollbackAudit`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollbackAuditOptions {
+    /// Records per segment blob, the current segment is rewritten with every record
+    pub segment_size: usize,
+    /// Segments that are kept, the oldest segment is deleted once a new segment is started
+    pub retained_segments: usize,
+}
+
+impl Default for RollbackAuditOptions {
+    fn default() -> Self {
+        Self {
+            segment_size: 100,
+            retained_segments: 100,
+        }
+    }
+}
+
+impl RollbackAuditOptions {
+    pub fn set_segment_size(mut self, segment_size: usize) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    pub fn set_retained_segments(mut self, retained_segments: usize) -> Self {
+        self.retained_segments = retained_segments;
+        self
+    }
+}
+
+/// A transaction with mutations that was rolled back, only the statement kinds are recorded, not the rows
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RollbackRecord {
+    pub transaction_id: TransactionId,
+    /// Milliseconds since the unix epoch
+    pub timestamp_ms: u128,
+    pub role: Option<String>,
+    pub client_id: Option<String>,
+    pub statement_kinds: Vec<String>,
+    pub reason: String,
+}
+
+impl fmt::Display for RollbackRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "at {}ms, role: {}, client: {}, statements: [{}], reason: {}",
+            self.timestamp_ms,
+            self.role.as_deref().unwrap_or("<none>"),
+            self.client_id.as_deref().unwrap_or("<none>"),
+            self.statement_kinds.join(", "),
+            self.reason
+        )
+    }
+}
+
+impl RollbackRecord {
+    pub fn new(
+        transaction_id: &TransactionId,
+        context: &TransactionContext,
+        statement_kinds: Vec<String>,
+        reason: String,
+        timestamp_ms: u128,
+    ) -> Self {
+        Self {
+            transaction_id: transaction_id.clone(),
+            timestamp_ms,
+            role: context.role.clone(),
+            client_id: context.client_id.clone(),
+            statement_kinds,
+            reason,
+        }
+    }
+
+    pub fn statement_kinds(statements: &[Statement]) -> Vec<String> {
+        statements
+            .iter()
+            .map(|statement| <&'static str>::from(statement).to_string())
+            .collect()
+    }
+}
+
+/// Which segments exist, segments are numbered and `first..=current` are stored
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+struct AuditHead {
+    first: usize,
+    current: usize,
+}
+
+struct AuditState {
+    head: AuditHead,
+    /// Records of the current segment
+    records: Vec<RollbackRecord>,
+}
+
+/// Records rolled back transactions with mutations so that failing or suspicious write patterns can be
+/// investigated after the fact. Records are stored in their own blobs, separate from the WAL and snapshots,
+/// and are never replayed on restore
+pub struct RollbackAudit {
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    options: RollbackAuditOptions,
+    /// Loaded from storage on first use, storage is not initialized when the audit is created
+    state: Mutex<Option<AuditState>>,
+}
+
+const HEAD_PATH: &str = "rollback_audit/head";
+
+fn segment_path(segment: usize) -> String {
+    format!("rollback_audit/{}", segment)
+}
+
+impl RollbackAudit {
+    pub fn new(
+        storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+        options: RollbackAuditOptions,
+    ) -> Self {
+        Self {
+            storage,
+            options,
+            state: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, record: RollbackRecord) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let state = self.loaded(&mut state)?;
+
+        if state.records.len() >= self.options.segment_size {
+            state.head.current += 1;
+            state.records.clear();
+
+            let mut dropped = vec![];
+
+            while state.head.current - state.head.first >= self.options.retained_segments {
+                dropped.push(state.head.first);
+                state.head.first += 1;
+            }
+
+            self.write(HEAD_PATH.to_string(), &state.head)?;
+
+            for segment in dropped {
+                self.storage
+                    .lock()
+                    .unwrap()
+                    .delete_blob(segment_path(segment))?;
+            }
+        }
+
+        state.records.push(record);
+
+        self.write(segment_path(state.head.current), &state.records)
+    }
+
+    /// The latest records, newest first
+    pub fn load(&self, limit: usize) -> StorageResult<Vec<RollbackRecord>> {
+        let mut state = self.state.lock().unwrap();
+        let state = self.loaded(&mut state)?;
+
+        let mut records: Vec<RollbackRecord> = state.records.iter().rev().cloned().collect();
+
+        for segment in (state.head.first..state.head.current).rev() {
+            if records.len() >= limit {
+                break;
+            }
+
+            let segment_records: Vec<RollbackRecord> = self.read(segment_path(segment))?;
+
+            records.extend(segment_records.into_iter().rev());
+        }
+
+        records.truncate(limit);
+
+        Ok(records)
+    }
+
+    /// The audit blobs have been removed with the rest of storage, e.g. the database was reset
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = None;
+    }
+
+    fn loaded<'a>(&self, state: &'a mut Option<AuditState>) -> StorageResult<&'a mut AuditState> {
+        if state.is_none() {
+            let head: AuditHead = self.read(HEAD_PATH.to_string())?;
+            let records = self.read(segment_path(head.current))?;
+
+            *state = Some(AuditState { head, records });
+        }
+
+        Ok(state.as_mut().expect("The state has just been loaded"))
+    }
+
+    fn read<T: DeserializeOwned + Default>(&self, path: String) -> StorageResult<T> {
+        match self.storage.lock().unwrap().read_blob(path)? {
+            ReadBlobState::Found(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e))),
+            ReadBlobState::NotFound => Ok(T::default()),
+        }
+    }
+
+    fn write<T: Serialize>(&self, path: String, value: &T) -> StorageResult<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .write_blob(path, serde_json::to_vec(value).unwrap())
+    }
+}
+
+/// A person that was purged, see `Statement::Purge`. Only the id is recorded, none of the purged versions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PurgeRecord {
+    pub entity_id: EntityId,
+    pub transaction_id: TransactionId,
+    /// Milliseconds since the unix epoch
+    pub timestamp_ms: u128,
+    pub role: Option<String>,
+    pub client_id: Option<String>,
+    /// Number of versions that were removed
+    pub versions: usize,
+}
+
+impl fmt::Display for PurgeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}ms, role: {}, client: {}, versions: {}",
+            self.entity_id,
+            self.timestamp_ms,
+            self.role.as_deref().unwrap_or("<none>"),
+            self.client_id.as_deref().unwrap_or("<none>"),
+            self.versions
+        )
+    }
+}
+
+impl PurgeRecord {
+    /// The ids purged by the statements, in statement order
+    pub fn purged_ids(statements: &[Statement]) -> Vec<EntityId> {
+        statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Purge(id) => Some(id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Records every committed purge so that an erasure request can be shown to have been carried out. Unlike the
+/// rollback audit it is always enabled and is never pruned, purges are rare so the records are kept in one blob
+pub struct PurgeAudit {
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    /// Loaded from storage on first use, storage is not initialized when the audit is created
+    records: Mutex<Option<Vec<PurgeRecord>>>,
+}
+
+const PURGE_AUDIT_PATH: &str = "purge_audit";
+
+impl PurgeAudit {
+    pub fn new(storage: Arc<Mutex<dyn Storage + Sync + Send>>) -> Self {
+        Self {
+            storage,
+            records: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, new_records: Vec<PurgeRecord>) -> StorageResult<()> {
+        let mut records = self.records.lock().unwrap();
+        let records = self.loaded(&mut records)?;
+
+        records.extend(new_records);
+
+        self.storage.lock().unwrap().write_blob(
+            PURGE_AUDIT_PATH.to_string(),
+            serde_json::to_vec(records).unwrap(),
+        )
+    }
+
+    /// The latest records, newest first
+    pub fn load(&self, limit: usize) -> StorageResult<Vec<PurgeRecord>> {
+        let mut records = self.records.lock().unwrap();
+        let records = self.loaded(&mut records)?;
+
+        Ok(records.iter().rev().take(limit).cloned().collect())
+    }
+
+    /// The audit blob has been removed with the rest of storage, e.g. the database was reset
+    pub fn reset(&self) {
+        *self.records.lock().unwrap() = None;
+    }
+
+    fn loaded<'a>(
+        &self,
+        records: &'a mut Option<Vec<PurgeRecord>>,
+    ) -> StorageResult<&'a mut Vec<PurgeRecord>> {
+        if records.is_none() {
+            let loaded = match self
+                .storage
+                .lock()
+                .unwrap()
+                .read_blob(PURGE_AUDIT_PATH.to_string())?
+            {
+                ReadBlobState::Found(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?,
+                ReadBlobState::NotFound => vec![],
+            };
+
+            *records = Some(loaded);
+        }
+
+        Ok(records.as_mut().expect("The records have just been loaded"))
+    }
+}
+
+impl Database {
+    /// Records the purges of a committed transaction, `purged_ids` are the ids of its `Statement::Purge`s. Like
+    /// `audit_rollback` a failure to record is logged, the purge has already been applied
+    pub(super) fn audit_purges(
+        &self,
+        transaction_id: &TransactionId,
+        context: &TransactionContext,
+        purged_ids: Vec<EntityId>,
+        response: &DatabaseCommandTransactionResponse,
+    ) {
+        if purged_ids.is_empty() {
+            return;
+        }
+
+        let DatabaseCommandTransactionResponse::Commit(results) = response else {
+            return;
+        };
+
+        let timestamp_ms = self.database_options.clock.now_ms();
+
+        let versions = results.iter().filter_map(|result| match result {
+            StatementResult::Purged(versions) => Some(*versions),
+            _ => None,
+        });
+
+        let records = purged_ids
+            .into_iter()
+            .zip(versions)
+            .map(|(entity_id, versions)| PurgeRecord {
+                entity_id,
+                transaction_id: transaction_id.clone(),
+                timestamp_ms,
+                role: context.role.clone(),
+                client_id: context.client_id.clone(),
+                versions,
+            })
+            .collect();
+
+        if let Err(e) = self.purge_audit.record(records) {
+            log::error!(
+                "Unable to record the purges of transaction {} in the audit: {}",
+                transaction_id,
+                e
+            );
+        }
+    }
+
+    /// Records the transaction if it was rolled back, `statement_kinds` is only set for audited transactions. The
+    /// response has already been sent, so a failure to record is logged rather than failing the transaction
+    pub(super) fn audit_rollback(
+        &self,
+        transaction_id: &TransactionId,
+        context: &TransactionContext,
+        statement_kinds: Option<Vec<String>>,
+        response: &DatabaseCommandTransactionResponse,
+    ) {
+        let (Some(audit), Some(statement_kinds)) = (&self.rollback_audit, statement_kinds) else {
+            return;
+        };
+
+        let reason = match response {
+            DatabaseCommandTransactionResponse::Rollback(message) => message.clone(),
+            DatabaseCommandTransactionResponse::QuotaExceeded(e) => e.to_string(),
+            DatabaseCommandTransactionResponse::LimitExceeded(e) => e.to_string(),
+            DatabaseCommandTransactionResponse::Commit(_)
+            | DatabaseCommandTransactionResponse::Status(_)
+            | DatabaseCommandTransactionResponse::ContextOverrideRejected(_)
+            | DatabaseCommandTransactionResponse::NotModified(_)
+            | DatabaseCommandTransactionResponse::DryRun(_)
+            | DatabaseCommandTransactionResponse::Throttled(_) => return,
+        };
+
+        // A dry run always rolls back, only the transactions that were meant to commit are audited
+        if context.dry_run {
+            return;
+        }
+
+        let record = RollbackRecord::new(
+            transaction_id,
+            context,
+            statement_kinds,
+            reason,
+            self.database_options.clock.now_ms(),
+        );
+
+        if let Err(e) = audit.record(record) {
+            log::warn!(
+                "Unable to record rolled back transaction {} in the audit: {}",
+                transaction_id,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::{
+        database::commands::SnapshotTimestamp,
+        persistence::{
+            storage::file::{FileOptions, FileStorage},
+            transaction::TransactionWriteMode,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_segments() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let mut storage =
+            FileStorage::new(FileOptions::new(database_dir), TransactionWriteMode::Off);
+        storage.init().unwrap();
+
+        let storage: Arc<Mutex<dyn Storage + Sync + Send>> = Arc::new(Mutex::new(storage));
+
+        let options = RollbackAuditOptions::default()
+            .set_segment_size(2)
+            .set_retained_segments(2);
+
+        let audit = RollbackAudit::new(storage.clone(), options.clone());
+        let context =
+            TransactionContext::new(SnapshotTimestamp::Latest).set_role(Some("ops".to_string()));
+
+        let mut transaction_id = TransactionId::new_first_transaction();
+        let mut last_transaction_id = transaction_id.clone();
+
+        for _ in 0..5 {
+            audit
+                .record(RollbackRecord::new(
+                    &transaction_id,
+                    &context,
+                    RollbackRecord::statement_kinds(&[Statement::NextVal("orders".to_string())]),
+                    "failed".to_string(),
+                    0,
+                ))
+                .unwrap();
+
+            last_transaction_id = transaction_id.clone();
+            transaction_id = transaction_id.increment();
+        }
+
+        // Segments of 2 records, only the last 2 segments (3 records) are kept
+        let reloaded = RollbackAudit::new(storage, options);
+        let records = reloaded.load(10).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].transaction_id, last_transaction_id);
+        assert_eq!(records[0].role.as_deref(), Some("ops"));
+        assert_eq!(records[0].statement_kinds, vec!["NextVal".to_string()]);
+
+        assert_eq!(reloaded.load(1).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+/// What a worker thread is doing, requests are not routed to threads that are unavailable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadAvailability {
+    /// Picking up requests, or running a transaction
+    Healthy,
+    /// Running a control command (e.g. a sleep or a snapshot), which can take an unbounded amount of time
+    ExecutingControl,
+    /// Parked until a `DatabasePauseEvent` is dropped
+    Paused,
+}
+
+impl ThreadAvailability {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::ExecutingControl,
+            2 => Self::Paused,
+            _ => Self::Healthy,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::Healthy => 0,
+            Self::ExecutingControl => 1,
+            Self::Paused => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for ThreadAvailability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Healthy => write!(f, "Healthy"),
+            Self::ExecutingControl => write!(f, "ExecutingControl"),
+            Self::Paused => write!(f, "Paused"),
+        }
+    }
+}
+
+/// Availability of each worker thread, shared between the worker threads (which set it) and the request
+/// manager (which reads it to route requests), indexed by thread id
+#[derive(Clone)]
+pub struct WorkerAvailability(Arc<Vec<AtomicU8>>);
+
+impl WorkerAvailability {
+    pub fn new(threads: usize) -> Self {
+        Self(Arc::new(
+            (0..threads)
+                .map(|_| AtomicU8::new(ThreadAvailability::Healthy.as_u8()))
+                .collect(),
+        ))
+    }
+
+    pub fn set(&self, thread_id: usize, availability: ThreadAvailability) {
+        if let Some(state) = self.0.get(thread_id) {
+            state.store(availability.as_u8(), Ordering::Release);
+        }
+    }
+
+    /// Unknown threads are reported as healthy
+    pub fn get(&self, thread_id: usize) -> ThreadAvailability {
+        self.0
+            .get(thread_id)
+            .map(|state| ThreadAvailability::from_u8(state.load(Ordering::Acquire)))
+            .unwrap_or(ThreadAvailability::Healthy)
+    }
+
+    pub fn is_available(&self, thread_id: usize) -> bool {
+        self.get(thread_id) == ThreadAvailability::Healthy
+    }
+
+    pub fn threads(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn availability_is_shared_between_clones() {
+        let availability = WorkerAvailability::new(2);
+        let request_manager_view = availability.clone();
+
+        availability.set(1, ThreadAvailability::Paused);
+
+        assert!(request_manager_view.is_available(0));
+        assert!(!request_manager_view.is_available(1));
+        assert_eq!(request_manager_view.get(1), ThreadAvailability::Paused);
+
+        availability.set(1, ThreadAvailability::Healthy);
+
+        assert!(request_manager_view.is_available(1));
+    }
+}
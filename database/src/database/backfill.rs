@@ -0,0 +1,192 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::consts::consts::EntityId;
+
+use super::{request_manager::RequestManager, table::index::IndexedField};
+
+/// How fast an index is backfilled, see `Control::BackfillIndex`. A batch only locks its rows while their
+/// versions are added, the pause between batches leaves the worker threads to requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexBackfillOptions {
+    /// Rows added to the index per batch
+    pub batch_size: usize,
+    pub batch_pause: Duration,
+}
+
+impl Default for IndexBackfillOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 1_000,
+            batch_pause: Duration::from_millis(10),
+        }
+    }
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl IndexBackfillOptions {
+    pub fn set_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn set_batch_pause(mut self, batch_pause: Duration) -> Self {
+        self.batch_pause = batch_pause;
+        self
+    }
+}
+
+/// A point in time copy of a backfill's progress
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexBackfillProgress {
+    pub field: IndexedField,
+    /// Rows whose versions have been added to the index
+    pub rows: usize,
+    /// Rows in the table when the backfill started, rows added since are indexed as they are committed
+    pub total_rows: usize,
+    /// The last row that was added, the next batch starts after it
+    pub cursor: Option<EntityId>,
+    /// Time the backfill has been running for, or took once it has completed
+    pub elapsed: Duration,
+    pub completed: bool,
+}
+
+impl IndexBackfillProgress {
+    pub fn to_info(&self) -> (String, String) {
+        let state = match self.completed {
+            true => "Completed",
+            false => "Running",
+        };
+
+        (
+            self.field.to_string(),
+            format!(
+                "{} [Rows: {}/{}, Elapsed: {:.2}s]",
+                state,
+                self.rows,
+                self.total_rows,
+                self.elapsed.as_secs_f64()
+            ),
+        )
+    }
+}
+
+struct Backfill {
+    progress: IndexBackfillProgress,
+    started_at: Instant,
+}
+
+/// Backfills run on their own thread, which sends a `Control::BackfillIndexBatch` to the workers for each batch
+/// so the table is only read by the worker threads. The thread stops once the index is complete or the database
+/// has shut down
+///
+/// A backfill is not resumed from its cursor after a restart, the index is rebuilt while the table is restored
+/// instead, see `SnapshotManager::save_indexes`
+#[derive(Default)]
+pub struct IndexBackfills {
+    backfills: Arc<Mutex<Vec<Backfill>>>,
+    /// Set once the database is running, see `start`
+    request_manager: Mutex<Option<RequestManager>>,
+}
+
+impl IndexBackfills {
+    pub fn start(&self, request_manager: RequestManager) {
+        *self.request_manager.lock().unwrap() = Some(request_manager);
+    }
+
+    /// Records the backfill and starts sending its batches, the index must already be maintained as versions
+    /// are committed, see `PersonIndexes::start_building`
+    pub fn begin(&self, field: IndexedField, total_rows: usize, options: IndexBackfillOptions) {
+        let mut backfills = self.backfills.lock().unwrap();
+
+        backfills.retain(|backfill| backfill.progress.field != field);
+        backfills.push(Backfill {
+            progress: IndexBackfillProgress {
+                field,
+                rows: 0,
+                total_rows,
+                cursor: None,
+                elapsed: Duration::ZERO,
+                completed: false,
+            },
+            started_at: Instant::now(),
+        });
+
+        drop(backfills);
+
+        let Some(request_manager) = self.request_manager.lock().unwrap().clone() else {
+            log::error!(
+                "The database is not running, the index on {} is not backfilled",
+                field
+            );
+            return;
+        };
+
+        let backfills = self.backfills.clone();
+
+        thread::Builder::new()
+            .name(format!("Backfill {}", field))
+            .spawn(move || loop {
+                if let Err(e) = request_manager.send_backfill_index_batch_request(field) {
+                    log::warn!("Stopped backfilling the index on {}: {}", field, e);
+                    return;
+                }
+
+                let completed = backfills.lock().unwrap().iter().any(|backfill| {
+                    backfill.progress.field == field && backfill.progress.completed
+                });
+
+                if completed {
+                    return;
+                }
+
+                thread::sleep(options.batch_pause);
+            })
+            .expect("Should be able to spawn the backfill thread");
+    }
+
+    /// Where the next batch of the backfill starts, none for the first batch
+    pub fn cursor(&self, field: &IndexedField) -> Option<EntityId> {
+        self.backfills
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|backfill| backfill.progress.field == *field)
+            .and_then(|backfill| backfill.progress.cursor.clone())
+    }
+
+    /// Records a batch, the cursor is none once every row has been added
+    pub fn advance(&self, field: &IndexedField, rows: usize, cursor: Option<EntityId>) {
+        let mut backfills = self.backfills.lock().unwrap();
+
+        let Some(backfill) = backfills
+            .iter_mut()
+            .find(|backfill| backfill.progress.field == *field)
+        else {
+            return;
+        };
+
+        backfill.progress.rows += rows;
+        backfill.progress.completed = cursor.is_none();
+        backfill.progress.cursor = cursor;
+        backfill.progress.elapsed = backfill.started_at.elapsed();
+    }
+
+    pub fn list(&self) -> Vec<IndexBackfillProgress> {
+        self.backfills
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|backfill| match backfill.progress.completed {
+                true => backfill.progress.clone(),
+                false => IndexBackfillProgress {
+                    elapsed: backfill.started_at.elapsed(),
+                    ..backfill.progress.clone()
+                },
+            })
+            .collect()
+    }
+}
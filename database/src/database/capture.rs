@@ -0,0 +1,400 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{consts::consts::TransactionId, model::statement::Statement};
+
+use super::{
+    commands::{SnapshotTimestamp, TransactionContext},
+    request_manager::{RequestManager, RequestManagerError},
+    table::watermark::TableVersion,
+};
+
+#[derive(Error, Debug)]
+pub enum CaptureError {
+    #[error("Unable to read or write the capture file: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid capture file, line {0}: {1}")]
+    Json(usize, serde_json::Error),
+}
+
+/// Defines where and for how long requests are captured, see `RequestCapture`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestCaptureOptions {
+    /// JSON lines file the requests are written to, an existing file is replaced
+    pub path: PathBuf,
+    /// Requests are captured for this long after the database has started
+    pub window: Duration,
+    /// If set, the capture stops once this many requests have been captured
+    pub max_requests: Option<usize>,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl RequestCaptureOptions {
+    pub fn new(path: PathBuf, window: Duration) -> Self {
+        Self {
+            path,
+            window,
+            max_requests: None,
+        }
+    }
+
+    pub fn set_max_requests(mut self, max_requests: usize) -> Self {
+        self.max_requests = Some(max_requests);
+        self
+    }
+}
+
+/// The parts of a `TransactionContext` that are replayed, the request manager fills in the rest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CapturedContext {
+    /// None if the transaction ran at the latest transaction id
+    pub snapshot_transaction_id: Option<TransactionId>,
+    pub client_id: Option<String>,
+    pub role: Option<String>,
+    pub clone: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub if_unchanged_since: Option<TableVersion>,
+    pub dry_run: bool,
+    pub defer_constraints: bool,
+    pub tag: Option<String>,
+}
+
+impl CapturedContext {
+    pub fn from_context(context: &TransactionContext) -> Self {
+        Self {
+            snapshot_transaction_id: match &context.snapshot_timestamp {
+                SnapshotTimestamp::AtTransactionId(transaction_id) => Some(transaction_id.clone()),
+                SnapshotTimestamp::Latest => None,
+            },
+            client_id: context.client_id.clone(),
+            role: context.role.clone(),
+            clone: context.clone.clone(),
+            timeout_ms: context.timeout.map(|timeout| timeout.as_millis() as u64),
+            if_unchanged_since: context.if_unchanged_since.clone(),
+            dry_run: context.dry_run,
+            defer_constraints: context.defer_constraints,
+            tag: context.tag.clone(),
+        }
+    }
+
+    /// Transaction ids of the captured database are not those of the database the capture is replayed into, a
+    /// read at a transaction id may read a different snapshot
+    pub fn to_context(&self) -> TransactionContext {
+        let mut context = TransactionContext::new(match &self.snapshot_transaction_id {
+            Some(transaction_id) => SnapshotTimestamp::AtTransactionId(transaction_id.clone()),
+            None => SnapshotTimestamp::Latest,
+        })
+        .set_client_id(self.client_id.clone())
+        .set_role(self.role.clone())
+        .set_clone(self.clone.clone())
+        .set_if_unchanged_since(self.if_unchanged_since.clone())
+        .set_dry_run(self.dry_run)
+        .set_defer_constraints(self.defer_constraints)
+        .set_tag(self.tag.clone());
+
+        if let Some(timeout_ms) = self.timeout_ms {
+            context = context.set_timeout(Duration::from_millis(timeout_ms));
+        }
+
+        context
+    }
+}
+
+/// A transaction received by a worker, a line of the capture file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CapturedRequest {
+    /// When the request was queued, relative to the start of the capture
+    pub offset_ms: u64,
+    pub statements: Vec<Statement>,
+    pub context: CapturedContext,
+}
+
+/// Records the transactions the workers receive to a file, so that a hard to reproduce bug or performance issue
+/// can be replayed against a fresh database, see `CaptureReplay`. Control commands (snapshots, shutdowns, etc) are
+/// not captured. Statements are written as they were received, fields that are encrypted at rest are not encrypted
+/// in the capture
+pub struct RequestCapture {
+    options: RequestCaptureOptions,
+    started_at: Instant,
+    state: Mutex<CaptureState>,
+}
+
+struct CaptureState {
+    /// None once the capture has stopped. Requests are written unbuffered, so a capture of a crash is complete
+    writer: Option<File>,
+    captured: usize,
+}
+
+impl RequestCapture {
+    pub fn start(options: RequestCaptureOptions) -> Result<Self, CaptureError> {
+        let file = File::create(&options.path)?;
+
+        log::info!(
+            "🎥 Capturing requests to {} for {}s",
+            options.path.display(),
+            options.window.as_secs()
+        );
+
+        Ok(Self {
+            options,
+            started_at: Instant::now(),
+            state: Mutex::new(CaptureState {
+                writer: Some(file),
+                captured: 0,
+            }),
+        })
+    }
+
+    pub fn record(
+        &self,
+        statements: &[Statement],
+        context: &TransactionContext,
+        enqueued_at: Instant,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.writer.is_none() {
+            return;
+        }
+
+        let offset = enqueued_at.saturating_duration_since(self.started_at);
+
+        let is_full = self
+            .options
+            .max_requests
+            .map_or(false, |max_requests| state.captured >= max_requests);
+
+        if offset > self.options.window || is_full {
+            self.stop(&mut state);
+            return;
+        }
+
+        let request = CapturedRequest {
+            offset_ms: offset.as_millis() as u64,
+            statements: statements.to_vec(),
+            context: CapturedContext::from_context(context),
+        };
+
+        let mut line = serde_json::to_vec(&request).expect("Statements should always serialize");
+        line.push(b'\n');
+
+        let written = match state.writer.as_mut() {
+            Some(file) => file.write_all(&line),
+            None => return,
+        };
+
+        // A capture is a debugging aid, failing to write it stops the capture instead of failing the request
+        if let Err(e) = written {
+            log::warn!("Unable to capture request, stopping the capture: {}", e);
+            self.stop(&mut state);
+            return;
+        }
+
+        state.captured += 1;
+    }
+
+    /// Number of requests captured so far
+    pub fn captured(&self) -> usize {
+        self.state.lock().unwrap().captured
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.state.lock().unwrap().writer.is_some()
+    }
+
+    fn stop(&self, state: &mut CaptureState) {
+        if state.writer.take().is_some() {
+            log::info!(
+                "🎥 Captured {} requests to {}",
+                state.captured,
+                self.options.path.display()
+            );
+        }
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        vec![
+            ("CapturedRequests".to_string(), self.captured().to_string()),
+            ("CaptureActive".to_string(), self.is_capturing().to_string()),
+        ]
+    }
+}
+
+impl Drop for RequestCapture {
+    fn drop(&mut self) {
+        self.stop(&mut self.state.lock().unwrap());
+    }
+}
+
+/// How fast a capture is replayed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayPacing {
+    /// Requests are sent at the offsets they were captured at
+    Original,
+    /// Requests are sent this many times faster than they were captured, e.g. 10 replays a minute in 6 seconds
+    Accelerated(f64),
+    /// Requests are sent one after another without waiting
+    Unpaced,
+}
+
+impl ReplayPacing {
+    fn delay(&self, offset_ms: u64) -> Option<Duration> {
+        let offset = Duration::from_millis(offset_ms);
+
+        match self {
+            ReplayPacing::Original => Some(offset),
+            ReplayPacing::Accelerated(speed) => Some(offset.div_f64(*speed)),
+            ReplayPacing::Unpaced => None,
+        }
+    }
+}
+
+/// A capture replayed into a fresh database once it has started, see `DatabaseOptions::set_capture_replay`
+#[derive(Debug, Clone)]
+pub struct CaptureReplay {
+    pub requests: Vec<CapturedRequest>,
+    pub pacing: ReplayPacing,
+}
+
+/// Outcome of the replayed requests. Requests may roll back where they committed when they were captured (e.g. the
+/// captured database was not empty), so the report is only a summary
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaptureReplayReport {
+    pub requests: usize,
+    pub commits: usize,
+    pub rollbacks: usize,
+    /// Requests that neither committed nor rolled back, e.g. timeouts or throttled requests
+    pub failures: usize,
+    pub elapsed: Duration,
+}
+
+impl CaptureReplay {
+    /// Reads a capture file written by `RequestCapture`, the requests are ordered by their offset
+    pub fn from_file(path: &Path, pacing: ReplayPacing) -> Result<Self, CaptureError> {
+        let contents = fs::read_to_string(path)?;
+
+        let mut requests = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str::<CapturedRequest>(line)
+                    .map_err(|e| CaptureError::Json(index + 1, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Workers record requests as they pick them up, which is not always the order they were queued in
+        requests.sort_by_key(|request| request.offset_ms);
+
+        Ok(Self { requests, pacing })
+    }
+
+    /// Sends the requests without waiting for their responses, so requests that overlapped when they were
+    /// captured overlap again. Returns once every request has been answered
+    pub fn replay(&self, request_manager: &RequestManager) -> CaptureReplayReport {
+        let started_at = Instant::now();
+
+        let tasks: Vec<_> = self
+            .requests
+            .iter()
+            .map(|request| {
+                if let Some(delay) = self.pacing.delay(request.offset_ms) {
+                    thread::sleep(delay.saturating_sub(started_at.elapsed()));
+                }
+
+                request_manager
+                    .send_transaction_task(request.statements.clone(), request.context.to_context())
+            })
+            .collect();
+
+        let mut report = CaptureReplayReport {
+            requests: tasks.len(),
+            ..CaptureReplayReport::default()
+        };
+
+        for task in tasks {
+            match task.get() {
+                Ok(_) => report.commits += 1,
+                Err(RequestManagerError::TransactionRollback(_)) => report.rollbacks += 1,
+                Err(_) => report.failures += 1,
+            }
+        }
+
+        report.elapsed = started_at.elapsed();
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{database::fixtures::TestDatabase, model::person::Person};
+
+    use super::*;
+
+    #[test]
+    fn captured_requests_are_replayed() {
+        let path = std::env::temp_dir().join(format!("{}.jsonl", Uuid::new_v4()));
+
+        let capture = RequestCapture::start(
+            RequestCaptureOptions::new(path.clone(), Duration::from_secs(60)).set_max_requests(2),
+        )
+        .unwrap();
+
+        let person = Person::new("Captured".to_string(), None);
+        let context = TransactionContext::default().set_tag(Some("import".to_string()));
+
+        capture.record(&[Statement::Add(person.clone())], &context, Instant::now());
+        capture.record(
+            &[Statement::Get(person.id.clone())],
+            &context,
+            Instant::now(),
+        );
+
+        // Past the maximum the capture stops
+        capture.record(
+            &[Statement::Remove(person.id.clone())],
+            &context,
+            Instant::now(),
+        );
+
+        assert_eq!(capture.captured(), 2);
+        assert!(!capture.is_capturing());
+
+        drop(capture);
+
+        let replay = CaptureReplay::from_file(&path, ReplayPacing::Accelerated(100.0)).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(replay.requests.len(), 2);
+        assert_eq!(replay.requests[0].context.tag, Some("import".to_string()));
+
+        let database = TestDatabase::new();
+
+        let report = replay.replay(&database);
+
+        assert_eq!(report.requests, 2);
+        assert_eq!(report.commits, 2);
+        assert_eq!(
+            database
+                .send_get(person.id.clone(), TransactionContext::default())
+                .unwrap(),
+            Some(person)
+        );
+
+        // Replaying the add again rolls back, the person already exists
+        assert_eq!(replay.replay(&database).rollbacks, 1);
+    }
+}
@@ -0,0 +1,56 @@
+use std::{thread, time::Duration};
+
+/// Failures injected at random while the database runs, used by soak tests to exercise the crash and recovery
+/// paths under adverse conditions. Probabilities are between 0 (never) and 1 (always)
+///
+/// Note: only available with the `chaos` feature, this must never be enabled in production
+#[derive(Debug, Clone, Default)]
+pub struct ChaosOptions {
+    /// Chance that a worker sleeps for `worker_delay` before running a request
+    pub worker_delay_probability: f64,
+    pub worker_delay: Duration,
+    /// Chance that a storage call fails without reaching the storage engine, see `ChaosStorage`. Failed WAL
+    /// writes and snapshots crash the database, see `DatabaseCrash`
+    pub storage_error_probability: f64,
+    /// Chance that a worker drops the request it received and restarts, the requester sees the request fail
+    pub worker_restart_probability: f64,
+}
+
+impl ChaosOptions {
+    pub fn set_worker_delay(mut self, probability: f64, delay: Duration) -> Self {
+        self.worker_delay_probability = probability;
+        self.worker_delay = delay;
+        self
+    }
+
+    pub fn set_storage_error_probability(mut self, probability: f64) -> Self {
+        self.storage_error_probability = probability;
+        self
+    }
+
+    pub fn set_worker_restart_probability(mut self, probability: f64) -> Self {
+        self.worker_restart_probability = probability;
+        self
+    }
+
+    /// Sleeps the worker thread if a delay is injected
+    pub fn inject_worker_delay(&self, thread_id: usize) {
+        if roll(self.worker_delay_probability) {
+            log::warn!(
+                thread_id;
+                "Chaos: delaying worker for {}ms",
+                self.worker_delay.as_millis()
+            );
+
+            thread::sleep(self.worker_delay);
+        }
+    }
+
+    pub fn inject_worker_restart(&self) -> bool {
+        roll(self.worker_restart_probability)
+    }
+}
+
+pub fn roll(probability: f64) -> bool {
+    probability > 0.0 && rand::random::<f64>() < probability
+}
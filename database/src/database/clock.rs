@@ -0,0 +1,84 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+
+/// Source of wall clock time, see `DatabaseOptions::set_clock`. Elapsed times (latencies, throttling windows) are
+/// measured with `Instant` and are not read from the clock
+///
+/// Blob keys (e.g. snapshot keys) always use the system time, keys written with a frozen clock would collide
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Called with a sender that is notified whenever the time moves other than by the passing of real time,
+    /// e.g. so the scheduler re-checks which jobs are due once a `MockClock` is advanced
+    fn subscribe(&self, _changed: flume::Sender<()>) {}
+
+    fn now_ms(&self) -> u128 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    }
+
+    fn now_utc(&self) -> DateTime<Utc> {
+        DateTime::from(self.now())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when it is told to, for tests of expiry, retention and schedules
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+    subscribers: Mutex<Vec<flume::Sender<()>>>,
+}
+
+impl MockClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+
+        self.notify();
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+
+        self.notify();
+    }
+
+    fn notify(&self) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(()).is_ok());
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    fn subscribe(&self, changed: flume::Sender<()>) {
+        self.subscribers.lock().unwrap().push(changed);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::{consts::consts::TransactionId, model::statement::Statement};
+
+use super::table::table::PersonTable;
+
+/// A read-only copy of the table frozen at a transaction, see `Control::CloneAtTransaction`
+///
+/// Only the version of each row that was current at the transaction is copied, so reads against the clone do
+/// not contend with the row locks of the live table
+pub struct TableClone {
+    pub transaction_id: TransactionId,
+    pub table: PersonTable,
+}
+
+impl TableClone {
+    pub fn new(table: &PersonTable, transaction_id: TransactionId) -> Self {
+        let versions = table
+            .query_statement(Statement::ListLatestVersions, &transaction_id)
+            .expect("Should always be able to list latest versions")
+            .list_version();
+
+        // Attachments of the clone are read from the live table's store, vacuum keeps what the live rows reference
+        let clone = PersonTable::new().set_attachment_store(table.attachment_store());
+        clone.restore_table(versions);
+
+        Self {
+            transaction_id,
+            table: clone,
+        }
+    }
+
+    /// Number of rows in the clone, including rows that were deleted before the transaction
+    pub fn row_count(&self) -> usize {
+        self.table.person_rows.len()
+    }
+}
+
+/// Named clones that can be queried instead of the live table, see `TransactionContext::set_clone`
+///
+/// Clones are only held in memory, they are not restored on startup
+#[derive(Default)]
+pub struct TableClones {
+    clones: RwLock<BTreeMap<String, Arc<TableClone>>>,
+}
+
+impl TableClones {
+    /// Registers (or replaces) a clone
+    pub fn insert(&self, name: String, clone: TableClone) {
+        self.clones.write().unwrap().insert(name, Arc::new(clone));
+    }
+
+    /// Readers hold on to the clone, so a clone that is dropped or replaced is freed once its last reader is done
+    pub fn get(&self, name: &str) -> Option<Arc<TableClone>> {
+        self.clones.read().unwrap().get(name).cloned()
+    }
+
+    pub fn drop_clone(&self, name: &str) -> bool {
+        self.clones.write().unwrap().remove(name).is_some()
+    }
+
+    /// The transaction id and row count of each clone, keyed by name
+    pub fn list(&self) -> Vec<(String, TransactionId, usize)> {
+        self.clones
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, clone)| {
+                (
+                    name.clone(),
+                    clone.transaction_id.clone(),
+                    clone.row_count(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        self.clones.write().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{consts::consts::EntityId, model::person::Person};
+
+    use super::*;
+
+    #[test]
+    fn clone_is_frozen_at_the_transaction() {
+        let table = PersonTable::new();
+
+        let first = TransactionId::new_first_transaction();
+        let second = first.increment();
+
+        let person = Person {
+            id: EntityId("a".to_string()),
+            full_name: "Person".to_string(),
+            email: None,
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
+        };
+
+        table
+            .apply(Statement::Add(person.clone()), first.clone())
+            .unwrap();
+        table
+            .apply(Statement::Remove(person.id.clone()), second.clone())
+            .unwrap();
+
+        let clones = TableClones::default();
+        clones.insert("before".to_string(), TableClone::new(&table, first.clone()));
+        clones.insert("after".to_string(), TableClone::new(&table, second.clone()));
+
+        let before = clones.get("before").unwrap();
+        let people = before
+            .table
+            .query_statement(Statement::List(None), &before.transaction_id)
+            .unwrap()
+            .list();
+
+        assert_eq!(people, vec![person.clone()]);
+
+        let after = clones.get("after").unwrap();
+        let people = after
+            .table
+            .query_statement(Statement::List(None), &after.transaction_id)
+            .unwrap()
+            .list();
+
+        assert!(people.is_empty());
+
+        // Readers keep a dropped clone alive
+        assert!(clones.drop_clone("before"));
+        assert!(clones.get("before").is_none());
+        assert_eq!(before.row_count(), 1);
+    }
+}
@@ -1,8 +1,25 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::{
     consts::consts::TransactionId,
+    database::{
+        activity::{ActivityReport, RequestId},
+        context_policy::ContextOverrideRejected,
+        limits::LimitExceeded,
+        operations::{OperationId, OperationStatus},
+        quota::QuotaExceeded,
+        scheduler::JobDefinition,
+        table::{
+            index::IndexedField, policy::RowPolicy, prepared_query::PreparedQueryDefinition,
+            query::QueryPersonData, view::ViewDefinition, watermark::TableVersion,
+        },
+        tags::{TagPermit, TagThrottled},
+    },
     model::statement::{Statement, StatementResult},
+    persistence::{
+        snapshot_diff::{DiffSource, SnapshotDiff},
+        transaction::Transaction,
+    },
 };
 
 /// Database commands are how we interact with the database, they are how we ask the database to run a transaction, shutdown, etc
@@ -32,6 +49,21 @@ impl DatabaseCommand {
             _ => format!("{:?}", self),
         }
     }
+
+    /// Short description of the command, e.g. the statement kinds of a transaction or the control name
+    pub fn kind(&self) -> String {
+        match self {
+            DatabaseCommand::Transaction(statements) => statements
+                .iter()
+                .map(|statement| statement.into())
+                .collect::<Vec<&'static str>>()
+                .join(", "),
+            DatabaseCommand::Control(control) => {
+                let kind: &'static str = control.into();
+                kind.to_string()
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -42,6 +74,20 @@ pub enum DatabaseCommandTransactionResponse {
     Rollback(String),
     /// Status
     Status(String),
+    /// The request's tenant exceeded its quota, nothing was applied
+    QuotaExceeded(QuotaExceeded),
+    /// The transaction exceeded the transaction limits, nothing was applied
+    LimitExceeded(LimitExceeded),
+    /// The transaction's context set a field the context policy does not allow clients to set, nothing was applied
+    ContextOverrideRejected(ContextOverrideRejected),
+    /// The table has not been modified since the version the read was conditional on, the read was not run. See
+    /// `TransactionContext::set_if_unchanged_since`
+    NotModified(TableVersion),
+    /// The mutations would have committed with these results, they were rolled back. See
+    /// `TransactionContext::set_dry_run`
+    DryRun(Vec<StatementResult>),
+    /// The request timed out while it waited for its tag's limit, it was not queued. See `TagLimit`
+    Throttled(TagThrottled),
 }
 
 impl DatabaseCommandTransactionResponse {
@@ -64,6 +110,10 @@ pub enum DatabaseCommandControlResponse {
     Error(String),
     /// Returns a tuple, used for database information
     Info(Vec<(String, String)>),
+    /// Requests that are currently running and per client counters
+    Activity(ActivityReport),
+    /// The state of a long running control, see `Control::StartOperation`
+    Operation(OperationStatus),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -85,6 +135,18 @@ impl DatabaseCommandResponse {
         )
     }
 
+    pub fn control_activity(report: ActivityReport) -> Self {
+        DatabaseCommandResponse::DatabaseCommandControlResponse(
+            DatabaseCommandControlResponse::Activity(report),
+        )
+    }
+
+    pub fn control_operation(status: OperationStatus) -> Self {
+        DatabaseCommandResponse::DatabaseCommandControlResponse(
+            DatabaseCommandControlResponse::Operation(status),
+        )
+    }
+
     pub fn control_error(message: &str) -> Self {
         DatabaseCommandResponse::DatabaseCommandControlResponse(
             DatabaseCommandControlResponse::Error(message.to_string()),
@@ -118,22 +180,175 @@ pub enum ShutdownRequest {
     Worker,
 }
 
-#[derive(Debug)]
+#[derive(Debug, strum_macros::IntoStaticStr)]
 pub enum Control {
     /// Performs a safe shutdown of the database, requests before the shutdown will be run / committed, requests after the shutdown will be ignored
     Shutdown(ShutdownRequest),
     /// Writes the current state of the database to disk, removes the need for a WAL replay on next startup
     SnapshotDatabase,
+    /// Rewrites the WAL without the transactions that are covered by the latest snapshot, this does not pause
+    /// the database. Only supported by file storage
+    CompactWal,
+    /// Switches storage to the engine the database is migrating to (see `DatabaseOptions::set_migrate_to`), the
+    /// engines must have converged
+    CutoverMigration,
     /// Resets the database to the initial state, removes all data from the database, resets transaction ids, etc
     ResetDatabase,
+    /// Removes every person as a versioned delete in a single transaction. Unlike `ResetDatabase` the transaction
+    /// ids, the WAL and the history of every row are kept, reads at earlier transactions still see the people
+    TruncateTable,
+    /// Renames every person whose id starts with `from` to the same id starting with `to`, e.g. to move a tenant's
+    /// ids to another namespace. The renames keep the lineage of each row (see `Statement::Rename`). Without a
+    /// chunk size the database is paused and every rename is committed in one transaction, with a chunk size each
+    /// chunk is committed as its own transaction while requests keep being served
+    RenameIdPrefix {
+        from: String,
+        to: String,
+        chunk_size: Option<usize>,
+    },
     /// Pauses the database so that we can perform certain operations
     PauseDatabase(flume::Receiver<()>),
     /// Provides the caller some KV information on database stats
     DatabaseStats,
     /// Sleeps the database thread for a certain duration
     Sleep(Duration),
+    /// Re-reads the latest snapshot and validates it against the stored metadata, if `shadow_table` is set
+    /// the snapshot is also restored into an in-memory table and compared with the live table
+    VerifySnapshot { shadow_table: bool },
+    /// Exports the latest version of every row encrypted for the given age (x25519) public keys
+    Export { recipients: Vec<String> },
+    /// Reads the latest snapshot and the WAL tail into a `SnapshotArchive`, encoded as newline delimited JSON
+    DownloadSnapshot,
+    /// Writes the bytes of an attachment to the `AttachmentStore`, the caller receives the digest to attach with
+    /// `UpdateAttachmentStatement::Attach`
+    WriteAttachment(Vec<u8>),
+    /// Deletes the attachments no version references that were written at least the duration ago, see
+    /// `AttachmentStore::vacuum`
+    VacuumAttachments(Duration),
+    /// Squashes the history of the table's rows, see `DatabaseOptions::set_history_squash`
+    SquashHistory,
+    /// Creates (or replaces) a materialized view, the view is populated from the current state of the table
+    CreateView(ViewDefinition),
+    /// Drops a materialized view
+    DropView(String),
+    /// Creates (or replaces) a named read-only clone of the table as of the transaction, statements can read
+    /// from the clone instead of the live table, see `TransactionContext::set_clone`
+    CloneAtTransaction {
+        name: String,
+        transaction_id: TransactionId,
+    },
+    /// Drops a clone, requests that are reading from the clone finish against it
+    DropClone(String),
+    /// Provides the caller the clones and the transaction each clone is frozen at
+    ListClones,
+    /// Creates (or replaces) a row security policy
+    CreatePolicy(RowPolicy),
+    /// Drops a row security policy
+    DropPolicy(String),
+    /// Provides the caller the row security policies
+    ListPolicies,
+    /// Registers (or replaces) a prepared query, see `Statement::ExecutePreparedQuery`
+    CreatePreparedQuery(PreparedQueryDefinition),
+    /// Drops a prepared query
+    DropPreparedQuery(String),
+    /// Provides the caller the prepared queries
+    ListPreparedQueries,
+    /// Provides the caller how a list query would be scanned and the statistics the choice was based on, the
+    /// query is not run
+    ExplainQuery(Option<QueryPersonData>),
+    /// Provides the caller the fields list and count queries filter on and whether an index is recommended for
+    /// each, see `IndexAdvisor`. With `DatabaseOptions::set_auto_create_indexes` the recommended indexes are created
+    IndexAdvisor,
+    /// Creates an index on a field that is not indexed by default, see `IndexedField`
+    CreateIndex(IndexedField),
+    /// Creates an index on a field that is not indexed by default without pausing the database, the existing
+    /// rows are added in throttled batches in the background, see `IndexBackfills`
+    BackfillIndex(IndexedField),
+    /// Adds the next batch of rows to an index that is being backfilled, sent by the backfill's thread
+    BackfillIndexBatch(IndexedField),
+    /// Provides the caller the progress of the index backfills
+    ListIndexBackfills,
+    /// Provides the caller the latest rolled back transactions (newest first), see `RollbackAudit`
+    ListRollbackAudit(usize),
+    /// Provides the caller the latest purges (newest first), see `PurgeAudit`
+    ListPurgeAudit(usize),
+    /// Provides the caller the storage features recorded for the data directory and the ones this database has
+    /// enabled, see `StorageFeature`
+    ListEnabledFeatures,
+    /// Schedules (or replaces) a recurring job
+    ScheduleJob(JobDefinition),
+    /// Provides the caller the scheduled jobs and when they will next run
+    ListJobs,
+    /// Cancels a scheduled job
+    CancelJob(String),
+    /// Provides the caller the requests that are currently running on each worker and per client counters
+    ListActiveRequests,
+    /// Cancels a running request, the request stops the next time it checks for cancellation
+    KillRequest(RequestId),
+    /// Phase one of a two-phase commit, validates the statements and locks the rows they mutate. The prepare
+    /// record is durable once the caller receives the response, see `PreparedTransactions`
+    PrepareTransaction {
+        global_id: String,
+        statements: Vec<Statement>,
+    },
+    /// Applies a prepared transaction's statements, the caller receives the statement results
+    CommitPrepared(String),
+    /// Drops a prepared transaction without applying it, its rows are unlocked
+    AbortPrepared(String),
+    /// Provides the caller the prepared (in-doubt) transactions and the rows they lock
+    ListPrepared,
+    /// Sends committed transactions to the listener once they are durable, see
+    /// `TransactionWAL::add_committed_listener`
+    AddCommittedListener(flume::Sender<Transaction>),
+    /// Reads both sides of a diff, the caller receives the diff of the people that were added, removed or
+    /// changed between them
+    DiffSnapshots {
+        from: DiffSource,
+        to: DiffSource,
+        diff: flume::Sender<SnapshotDiff>,
+    },
+    /// Queues incoming transactions (up to a bound) instead of running them, waits for in-flight requests,
+    /// runs the maintenance task and then replays the queue. Without a task the window is held for `duration`
+    EnterMaintenance {
+        duration: Duration,
+        task: Option<MaintenanceTask>,
+    },
+    /// Runs a long running control (see `Control::is_operation`) as an operation, the caller receives the
+    /// operation id straight away and reads the control's response with `OperationStatus` or `WatchOperation`
+    StartOperation(Box<Control>),
+    /// Provides the caller the state of an operation, and the control's response once it has finished
+    OperationStatus(OperationId),
+    /// Sends the operation's status to the subscriber once it has finished
+    WatchOperation(OperationId, flume::Sender<OperationStatus>),
+}
+
+impl Control {
+    /// Controls that can take longer than a request is willing to wait for, e.g. on a large table. Only these
+    /// can run as an operation, their response is a control response
+    pub fn is_operation(&self) -> bool {
+        matches!(
+            self,
+            Control::SnapshotDatabase
+                | Control::CompactWal
+                | Control::VerifySnapshot { .. }
+                | Control::Export { .. }
+                | Control::DownloadSnapshot
+                | Control::VacuumAttachments(_)
+                | Control::SquashHistory
+                | Control::CreateIndex(_)
+                | Control::RenameIdPrefix { .. }
+        )
+    }
+}
+
+/// Work that is run while the database is in maintenance mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaintenanceTask {
+    /// Same as `Control::SnapshotDatabase`
+    Snapshot,
 }
 
+#[derive(Clone)]
 pub enum SnapshotTimestamp {
     /// The transaction id that the statement is running on
     AtTransactionId(TransactionId),
@@ -142,14 +357,89 @@ pub enum SnapshotTimestamp {
 }
 
 /// Information about the transaction that is being run
+#[derive(Clone)]
 pub struct TransactionContext {
     /// The snapshot id that the transaction is running on. If none, use the latest transaction id
     pub snapshot_timestamp: SnapshotTimestamp,
+    /// Identifies the client that made the request, used for per client statistics
+    pub client_id: Option<String>,
+    /// The role the request runs as, reads are restricted by the role's row policies
+    pub role: Option<String>,
+    /// If set, the statements read from the named clone instead of the live table, see `Control::CloneAtTransaction`
+    pub clone: Option<String>,
+    /// How long the request manager waits for the transaction. If none, the default of the `ContextPolicy`
+    pub timeout: Option<Duration>,
+    /// If set, a read-only transaction at the latest snapshot is answered with `NotModified` instead of being run
+    /// when the table is unchanged since the version
+    pub if_unchanged_since: Option<TableVersion>,
+    /// If set, mutations are applied and always rolled back, nothing is written to the WAL
+    pub dry_run: bool,
+    /// If set, the table's constraints are checked at commit instead of after each statement
+    pub defer_constraints: bool,
+    /// Groups requests for metrics and throttling, e.g. `batch-import` or `interactive`. See `TagLimit`
+    pub tag: Option<String>,
 }
 
 impl TransactionContext {
     pub fn new(snapshot_timestamp: SnapshotTimestamp) -> Self {
-        TransactionContext { snapshot_timestamp }
+        TransactionContext {
+            snapshot_timestamp,
+            client_id: None,
+            role: None,
+            clone: None,
+            timeout: None,
+            if_unchanged_since: None,
+            dry_run: false,
+            defer_constraints: false,
+            tag: None,
+        }
+    }
+
+    pub fn set_client_id(mut self, client_id: Option<String>) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    pub fn set_role(mut self, role: Option<String>) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn set_clone(mut self, clone: Option<String>) -> Self {
+        self.clone = clone;
+        self
+    }
+
+    pub fn set_if_unchanged_since(mut self, version: Option<TableVersion>) -> Self {
+        self.if_unchanged_since = version;
+        self
+    }
+
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Reports what the transaction's mutations would do (their results or the rollback reason) without
+    /// committing them, e.g. to validate an import before running it. Sequences are not transactional, a dry run
+    /// skips the values it draws like a rollback does
+    pub fn set_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Checks the table's constraints once every statement has been applied, e.g. to swap the emails of two
+    /// people in one transaction. See `DatabaseOptions::set_unique_email`
+    pub fn set_defer_constraints(mut self, defer_constraints: bool) -> Self {
+        self.defer_constraints = defer_constraints;
+        self
+    }
+
+    /// Tags the request, per tag metrics are reported in the activity and tags can be limited with
+    /// `DatabaseOptions::set_tag_limit`
+    pub fn set_tag(mut self, tag: Option<String>) -> Self {
+        self.tag = tag;
+        self
     }
 }
 
@@ -157,6 +447,14 @@ impl Default for TransactionContext {
     fn default() -> Self {
         TransactionContext {
             snapshot_timestamp: SnapshotTimestamp::Latest,
+            client_id: None,
+            role: None,
+            clone: None,
+            timeout: None,
+            if_unchanged_since: None,
+            dry_run: false,
+            defer_constraints: false,
+            tag: None,
         }
     }
 }
@@ -165,4 +463,8 @@ pub struct DatabaseCommandRequest {
     pub resolver: oneshot::Sender<DatabaseCommandResponse>,
     pub command: DatabaseCommand,
     pub transaction_context: TransactionContext,
+    /// When the request manager queued the request, used to measure how long the request waited for a worker
+    pub enqueued_at: Instant,
+    /// Held while the request of a limited tag is queued or running, see `TagThrottle`
+    pub permit: Option<TagPermit>,
 }
@@ -0,0 +1,1674 @@
+use std::{collections::HashMap, fs, io, path::Path, path::PathBuf, time::Duration};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use thiserror::Error;
+
+#[cfg(feature = "chaos")]
+use super::chaos::ChaosOptions;
+#[cfg(feature = "publisher")]
+use super::publisher::{PublisherOptions, PublisherSink};
+#[cfg(feature = "thread-tuning")]
+use super::thread_tuning::{ThreadPlacement, ThreadTuningOptions};
+use super::{
+    audit::RollbackAuditOptions,
+    capture::{CaptureReplay, ReplayPacing, RequestCaptureOptions},
+    context_policy::{ContextField, ContextPolicy},
+    ids::IdGeneration,
+    limits::TransactionLimits,
+    options::{DatabaseOptions, OptionsError},
+    quota::Quota,
+    request_log::RequestLogSampling,
+    restore_verification::RestoreVerificationOptions,
+    seed::Seed,
+    shadow::ShadowReadOptions,
+    statement_stats::StatementStatsOptions,
+    table::{
+        conflict::ConflictResolution,
+        constraint::ConstraintTiming,
+        policy::{PolicyPredicate, RowPolicy},
+        squash::HistorySquash,
+        view::PersonField,
+    },
+    tags::{TagLimit, TagPriority},
+    warmup::WarmupOptions,
+};
+#[cfg(feature = "dynamodb")]
+use crate::persistence::storage::dynamodb::DynamoOptions;
+#[cfg(feature = "s3")]
+use crate::persistence::storage::s3::{S3Options, S3Replica};
+#[cfg(feature = "postgres")]
+use crate::persistence::storage::{postgres::PostgresOptions, secret::Secret};
+use crate::persistence::{
+    field_encryption::FieldEncryptionOptions,
+    parquet::ParquetExportTarget,
+    snapshot_shards::SnapshotSharding,
+    storage::{
+        file::{FileLayout, FileOptions},
+        network::StorageTimeouts,
+        StorageEngine,
+    },
+    transaction::{TransactionFileWriteMode, TransactionWriteMode, UnknownStatementPolicy},
+    wal_batching::AdaptiveBatchingOptions,
+};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unable to read config file {0}: {1}")]
+    UnableToReadFile(PathBuf, io::Error),
+
+    /// Includes the offending key and its position in the file
+    #[error("Invalid config file {0}: {1}")]
+    InvalidFile(PathBuf, toml::de::Error),
+
+    #[error("Invalid value for `{0}`: {1}")]
+    InvalidValue(&'static str, String),
+
+    /// Combinations of values that are invalid, see `DatabaseOptions::validate`
+    #[error("Invalid config: {0}")]
+    InvalidOptions(#[from] OptionsError),
+}
+
+/// Reads a TOML config file, unknown keys are rejected
+pub fn read_config_file<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigError::UnableToReadFile(path.to_path_buf(), e))?;
+
+    toml::from_str(&contents).map_err(|e| ConfigError::InvalidFile(path.to_path_buf(), e))
+}
+
+/// The engine of `key` was not compiled in, see the storage features of the crate
+#[cfg(not(all(feature = "s3", feature = "dynamodb", feature = "postgres")))]
+fn missing_feature(key: &'static str, feature: &str) -> ConfigError {
+    ConfigError::InvalidValue(
+        key,
+        format!(
+            "lineagedb was built without the `{}` storage feature",
+            feature
+        ),
+    )
+}
+
+/// Every engine can be named, engines that were not compiled in are rejected when the options are built
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageEngineFlag {
+    File,
+    Dynamo,
+    Postgres,
+    S3,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WalSyncFlag {
+    Fsync,
+    Fdatasync,
+    Dsync,
+    OsBuffered,
+    Off,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictResolutionFlag {
+    LastWriterWins,
+    FieldMerge,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConstraintTimingFlag {
+    Immediate,
+    Deferred,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownStatementsFlag {
+    Fail,
+    Skip,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensitiveFieldFlag {
+    FullName,
+    Email,
+}
+
+/// Database configuration shared by the servers. Each value is read from (highest precedence first) the command
+/// line, a `LINEAGEDB_` environment variable or the `[database]` table of the config file, unset values fall back
+/// to `DatabaseOptions::default()`
+#[derive(clap::Args, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Number of database worker threads [default: 2]
+    #[clap(long, env = "LINEAGEDB_THREADS")]
+    pub threads: Option<usize>,
+
+    /// Number of worker threads that run read-only transactions, setting it (or --write-threads) splits the workers into a read and a write pool [default: threads]
+    #[clap(long, env = "LINEAGEDB_READ_THREADS")]
+    pub read_threads: Option<usize>,
+
+    /// Number of worker threads that run transactions with mutations, see --read-threads [default: threads]
+    #[clap(long, env = "LINEAGEDB_WRITE_THREADS")]
+    pub write_threads: Option<usize>,
+
+    /// Number of threads that replay the WAL on startup, transactions that touch disjoint rows are replayed in parallel [default: worker threads]
+    #[clap(long, env = "LINEAGEDB_REPLAY_THREADS")]
+    pub replay_threads: Option<usize>,
+
+    /// Checkpoints the WAL replay every this many transactions, a restore that is killed part way resumes from the checkpoint instead of the snapshot. Defaults to no checkpoints
+    #[clap(long, env = "LINEAGEDB_REPLAY_CHECKPOINT_INTERVAL")]
+    pub replay_checkpoint_interval: Option<usize>,
+
+    /// Restores the database from the snapshot and WAL on startup, otherwise previous state is removed [default: true]
+    #[clap(long, env = "LINEAGEDB_RESTORE")]
+    pub restore: Option<bool>,
+
+    /// Which storage mechanism to use [default: file]
+    #[clap(long, env = "LINEAGEDB_STORAGE", value_enum)]
+    pub storage: Option<StorageEngineFlag>,
+
+    /// Storage mechanism to migrate to, writes are mirrored to it until a snapshot has been taken. Then the
+    /// database can be cut over to it (see the `cutoverMigration` mutation). Uses the same storage options, e.g. --bucket
+    #[clap(long, env = "LINEAGEDB_MIGRATE_TO", value_enum)]
+    pub migrate_to: Option<StorageEngineFlag>,
+
+    /// Copies a backup into the storage engine before the restore, the engine has to be empty. The backup is a data
+    /// directory or `s3://<bucket>[/<prefix>]` of another database and may use a different engine than --storage
+    #[clap(
+        long,
+        env = "LINEAGEDB_RESTORE_FROM_BACKUP",
+        value_name = "PATH_OR_S3_URL"
+    )]
+    pub restore_from_backup: Option<String>,
+
+    /// How the WAL is made durable before a commit is acknowledged [default: fsync]
+    #[clap(long, env = "LINEAGEDB_WAL_SYNC", value_enum)]
+    pub wal_sync: Option<WalSyncFlag>,
+
+    /// Measures and logs the WAL sync latency on startup
+    #[clap(long, env = "LINEAGEDB_DURABILITY_SELF_TEST", num_args = 0..=1, default_missing_value = "true")]
+    pub durability_self_test: Option<bool>,
+
+    /// Restores the snapshot even if it was written by an incompatible database version
+    #[clap(long, env = "LINEAGEDB_IGNORE_SNAPSHOT_COMPATIBILITY", num_args = 0..=1, default_missing_value = "true")]
+    pub ignore_snapshot_compatibility: Option<bool>,
+
+    /// Asserts MVCC invariants on every read and rollback, this is slow and meant for testing
+    #[clap(long, env = "LINEAGEDB_PARANOID_CHECKS", num_args = 0..=1, default_missing_value = "true")]
+    pub paranoid_checks: Option<bool>,
+
+    /// Rejects transactions that filter people with an ad-hoc query, the table can only be filtered with prepared queries
+    #[clap(long, env = "LINEAGEDB_PREPARED_QUERIES_ONLY", num_args = 0..=1, default_missing_value = "true")]
+    pub prepared_queries_only: Option<bool>,
+
+    /// How a divergent version of a row, found by replication or an import, is resolved against the current version [default: last-writer-wins]
+    #[clap(long, env = "LINEAGEDB_CONFLICT_RESOLUTION", value_enum)]
+    pub conflict_resolution: Option<ConflictResolutionFlag>,
+
+    /// Rejects transactions that would leave two live people with the same email, checked after each statement (immediate) or at commit (deferred)
+    #[clap(long, env = "LINEAGEDB_UNIQUE_EMAIL", value_enum)]
+    pub unique_email: Option<ConstraintTimingFlag>,
+
+    /// Squashes the versions of a row committed within the same window of this many transactions into the first and last version, run with the squashHistory mutation or a job
+    #[clap(long, env = "LINEAGEDB_HISTORY_SQUASH_WINDOW")]
+    pub history_squash_window: Option<usize>,
+
+    /// Versions committed within this many transactions of the latest transaction are not squashed [default: 0]
+    #[clap(long, env = "LINEAGEDB_HISTORY_SQUASH_MIN_AGE")]
+    pub history_squash_min_age: Option<usize>,
+
+    /// Generates entity ids from this seed instead of at random, so that test and simulation runs are reproducible
+    #[clap(long, env = "LINEAGEDB_ID_SEED")]
+    pub id_seed: Option<u64>,
+
+    /// Number of recent versions per row kept in memory, older versions are spilled to storage. Defaults to keeping every version in memory
+    #[clap(long, env = "LINEAGEDB_HOT_VERSIONS")]
+    pub hot_versions: Option<usize>,
+
+    /// Number of snapshots kept for rollback, a restore falls back to an older snapshot if the newest is corrupt [default: 3]
+    #[clap(long, env = "LINEAGEDB_RETAINED_SNAPSHOTS")]
+    pub retained_snapshots: Option<usize>,
+
+    /// Archives the WAL with each snapshot, so that restoring from an older snapshot (if the newest is corrupt) does not lose transactions
+    #[clap(long, env = "LINEAGEDB_ARCHIVE_WAL", num_args = 0..=1, default_missing_value = "true")]
+    pub archive_wal: Option<bool>,
+
+    /// Serializes the next batch of commits while the previous batch is written, hides the write latency of network storage engines (S3, DynamoDB)
+    #[clap(long, env = "LINEAGEDB_PIPELINE_WAL", num_args = 0..=1, default_missing_value = "true")]
+    pub pipeline_wal: Option<bool>,
+
+    /// Sizes the WAL's batches so that commits are durable within this many milliseconds, otherwise up to 51 commits are written at once
+    #[clap(long, env = "LINEAGEDB_WAL_LATENCY_SLO_MS")]
+    pub wal_latency_slo_ms: Option<u64>,
+
+    /// Fewest commits an adaptive WAL batch is limited to [default: 1]
+    #[clap(long, env = "LINEAGEDB_WAL_MIN_BATCH")]
+    pub wal_min_batch: Option<usize>,
+
+    /// Most commits an adaptive WAL batch takes [default: 1000]
+    #[clap(long, env = "LINEAGEDB_WAL_MAX_BATCH")]
+    pub wal_max_batch: Option<usize>,
+
+    /// What a restore does with a WAL statement written by a newer build, e.g. after rolling back an upgrade: fail the restore or log and skip the statement [default: fail]
+    #[clap(long, env = "LINEAGEDB_UNKNOWN_STATEMENTS", value_enum)]
+    pub unknown_statements: Option<UnknownStatementsFlag>,
+
+    /// Exports the WAL as Parquet when a snapshot flushes it, either to the storage engine (`storage`) or to a local directory
+    #[clap(
+        long,
+        env = "LINEAGEDB_PARQUET_EXPORT",
+        value_name = "storage|DIRECTORY"
+    )]
+    pub parquet_export: Option<String>,
+
+    /// Splits snapshots into this many shards that are uploaded concurrently, which shortens snapshots on object stores such as S3
+    #[clap(long, env = "LINEAGEDB_SNAPSHOT_SHARDS")]
+    pub snapshot_shards: Option<usize>,
+
+    /// Number of snapshot shards uploaded at once [default: 4]
+    #[clap(long, env = "LINEAGEDB_SNAPSHOT_UPLOAD_PARALLELISM")]
+    pub snapshot_upload_parallelism: Option<usize>,
+
+    /// Number of times failed snapshot shard uploads are retried before the snapshot fails [default: 3]
+    #[clap(long, env = "LINEAGEDB_SNAPSHOT_UPLOAD_RETRIES")]
+    pub snapshot_upload_retries: Option<usize>,
+
+    /// JSON or TOML file of people (and their history) added on startup if the database is empty, see `Seed`
+    #[clap(long, env = "LINEAGEDB_SEED")]
+    pub seed: Option<PathBuf>,
+
+    /// Records the transactions the database receives to this JSON lines file for `--capture-window-secs` after it starts, replay it with `--replay-capture`
+    #[clap(long, env = "LINEAGEDB_CAPTURE_REQUESTS")]
+    pub capture_requests: Option<PathBuf>,
+
+    /// How long requests are captured for after the database starts [default: 300]
+    #[clap(long, env = "LINEAGEDB_CAPTURE_WINDOW_SECS")]
+    pub capture_window_secs: Option<u64>,
+
+    /// Stops the capture once this many requests have been captured
+    #[clap(long, env = "LINEAGEDB_CAPTURE_MAX_REQUESTS")]
+    pub capture_max_requests: Option<usize>,
+
+    /// Replays a file written by `--capture-requests` once the database has started, use a fresh database (e.g. `--restore false`)
+    #[clap(long, env = "LINEAGEDB_REPLAY_CAPTURE")]
+    pub replay_capture: Option<PathBuf>,
+
+    /// Replays the capture this many times faster than it was captured, 0 sends the requests without waiting [default: 1]
+    #[clap(long, env = "LINEAGEDB_REPLAY_CAPTURE_SPEED")]
+    pub replay_capture_speed: Option<f64>,
+
+    /// Logs a warning when a request waits longer than this many milliseconds for a database worker thread
+    #[clap(long, env = "LINEAGEDB_QUEUE_WAIT_SLO_MS")]
+    pub queue_wait_slo_ms: Option<u64>,
+
+    /// Logs a warning when a control operation (e.g. a snapshot or reset) pauses the database worker threads for longer than this many milliseconds
+    #[clap(long, env = "LINEAGEDB_PAUSE_WARN_MS")]
+    pub pause_warn_ms: Option<u64>,
+
+    /// Fraction of finished transactions logged at info (0 to 1), by default every transaction is logged
+    #[clap(long, env = "LINEAGEDB_REQUEST_LOG_SAMPLE_RATE")]
+    pub request_log_sample_rate: Option<f64>,
+
+    /// Only logs finished transactions that took longer than this many milliseconds
+    #[clap(long, env = "LINEAGEDB_REQUEST_LOG_SLOWER_THAN_MS")]
+    pub request_log_slower_than_ms: Option<u64>,
+
+    /// Fraction of list and prepared query reads (0 to 1) that are duplicated to a full scan and compared with the planned read, discrepancies are logged
+    #[clap(long, env = "LINEAGEDB_SHADOW_READ_SAMPLE_RATE")]
+    pub shadow_read_sample_rate: Option<f64>,
+
+    /// Maximum number of transactions queued while the database is in maintenance mode, further transactions are rolled back
+    #[clap(long, env = "LINEAGEDB_MAINTENANCE_QUEUE_LIMIT")]
+    pub maintenance_queue_limit: Option<usize>,
+
+    /// Maximum number of statements in a transaction, larger transactions are rejected. Unlimited by default
+    #[clap(long, env = "LINEAGEDB_MAX_STATEMENTS_PER_TRANSACTION")]
+    pub max_statements_per_transaction: Option<usize>,
+
+    /// Maximum size of a single statement in bytes once serialized, transactions with a larger statement are rejected. Unlimited by default
+    #[clap(long, env = "LINEAGEDB_MAX_STATEMENT_BYTES")]
+    pub max_statement_bytes: Option<usize>,
+
+    /// Milliseconds the S3, DynamoDB and Postgres engines have to write to the WAL before the write fails [default: 10000]
+    #[clap(long, env = "LINEAGEDB_STORAGE_WAL_WRITE_TIMEOUT_MS")]
+    pub storage_wal_write_timeout_ms: Option<u64>,
+
+    /// Milliseconds the S3, DynamoDB and Postgres engines have to read or write a snapshot [default: 60000]
+    #[clap(long, env = "LINEAGEDB_STORAGE_BLOB_TIMEOUT_MS")]
+    pub storage_blob_timeout_ms: Option<u64>,
+
+    /// Milliseconds the S3, DynamoDB and Postgres engines have to initialize, reset, load or flush the WAL [default: 120000]
+    #[clap(long, env = "LINEAGEDB_STORAGE_CONTROL_TIMEOUT_MS")]
+    pub storage_control_timeout_ms: Option<u64>,
+
+    /// Warms up the worker threads with no-op reads on startup, before serving requests
+    #[clap(long, env = "LINEAGEDB_WARMUP", num_args = 0..=1, default_missing_value = "true")]
+    pub warmup: Option<bool>,
+
+    /// No-op reads sent to each worker thread during the warm-up, implies --warmup [default: 8]
+    #[clap(long, env = "LINEAGEDB_WARMUP_TRANSACTIONS")]
+    pub warmup_transactions: Option<usize>,
+
+    /// Rows read into memory, in id order, during the warm-up, implies --warmup [default: 0]
+    #[clap(long, env = "LINEAGEDB_WARMUP_PRELOAD_ROWS")]
+    pub warmup_preload_rows: Option<usize>,
+
+    /// Compares a sample of the restored rows against a table re-derived from the snapshot and WAL before serving
+    /// requests, the database does not start if a row differs
+    #[clap(long, env = "LINEAGEDB_VERIFY_RESTORE", num_args = 0..=1, default_missing_value = "true")]
+    pub verify_restore: Option<bool>,
+
+    /// Rows compared by the restore verification, implies --verify-restore [default: 1000]
+    #[clap(long, env = "LINEAGEDB_VERIFY_RESTORE_SAMPLE_SIZE")]
+    pub verify_restore_sample_size: Option<usize>,
+
+    /// Records rolled back transactions with mutations in an audit stream, see the rollbackAudit query
+    #[clap(long, env = "LINEAGEDB_AUDIT_ROLLBACKS", num_args = 0..=1, default_missing_value = "true")]
+    pub audit_rollbacks: Option<bool>,
+
+    /// Segments of 100 records the rollback audit keeps, implies --audit-rollbacks [default: 100]
+    #[clap(long, env = "LINEAGEDB_AUDIT_ROLLBACKS_RETAINED_SEGMENTS")]
+    pub audit_rollbacks_retained_segments: Option<usize>,
+
+    /// Aggregates counters and latencies per statement kind and persists them, so they survive restarts, see the
+    /// stats query
+    #[clap(long, env = "LINEAGEDB_STATEMENT_STATS", num_args = 0..=1, default_missing_value = "true")]
+    pub statement_stats: Option<bool>,
+
+    /// Seconds between writes of the statement stats, implies --statement-stats [default: 60]
+    #[clap(long, env = "LINEAGEDB_STATEMENT_STATS_PERSIST_INTERVAL_SECS")]
+    pub statement_stats_persist_interval_secs: Option<u64>,
+
+    /// Role of requests that do not set one (see the x-role header), e.g. a role restricted by row policies
+    #[clap(long, env = "LINEAGEDB_DEFAULT_ROLE")]
+    pub default_role: Option<String>,
+
+    /// How long requests wait for a transaction when the client does not set a timeout [default: 30000]
+    #[clap(long, env = "LINEAGEDB_DEFAULT_REQUEST_TIMEOUT_MS")]
+    pub default_request_timeout_ms: Option<u64>,
+
+    /// Request context fields clients may set, others are rejected: SnapshotTimestamp, Role, Clone, Timeout [default: all]
+    #[clap(long, env = "LINEAGEDB_CLIENT_OVERRIDABLE", value_delimiter = ',')]
+    pub client_overridable: Option<Vec<String>>,
+
+    /// Restricts a role (see the x-role header) to rows with an email in the domain, e.g. tenant-x=x.com. Can be provided multiple times
+    #[clap(long, env = "LINEAGEDB_ROW_POLICY", value_delimiter = ',')]
+    pub row_policy: Option<Vec<String>>,
+
+    /// Limits a role (see the x-role header) as <role>:<max-rows|max-wal-bytes-per-day|max-requests-per-second>=<value>,
+    /// e.g. tenant-x:max-rows=1000. Can be provided multiple times
+    #[clap(long, env = "LINEAGEDB_QUOTA", value_delimiter = ',')]
+    pub quota: Option<Vec<String>>,
+
+    /// Limits requests with a tag (see the x-request-tag header) as <tag>:<max-concurrent=<value>|priority=<normal|background>>,
+    /// e.g. batch-import:max-concurrent=2. Can be provided multiple times
+    #[clap(long, env = "LINEAGEDB_TAG_LIMIT", value_delimiter = ',')]
+    pub tag_limit: Option<Vec<String>>,
+
+    /// Base64 encoded 32 byte key used to encrypt the sensitive fields in the WAL, snapshots and cold version storage
+    #[clap(long, env = "LINEAGEDB_FIELD_ENCRYPTION_KEY", hide_env_values = true)]
+    pub field_encryption_key: Option<String>,
+
+    /// Field that is encrypted with the field encryption key, can be provided multiple times [default: email]
+    #[clap(
+        long,
+        env = "LINEAGEDB_SENSITIVE_FIELD",
+        value_delimiter = ',',
+        value_enum
+    )]
+    pub sensitive_field: Option<Vec<SensitiveFieldFlag>>,
+
+    /// Role (see the x-role header) that can read sensitive fields, other roles receive masked values. Can be provided multiple times
+    #[clap(long, env = "LINEAGEDB_SENSITIVE_FIELD_READER", value_delimiter = ',')]
+    pub sensitive_field_reader: Option<Vec<String>>,
+
+    /// When using file storage, location of the database. Reads / writes to this directory. Note: Does not support shell paths, e.g. ~ [default: data]
+    #[clap(long, env = "LINEAGEDB_DATA")]
+    pub data: Option<PathBuf>,
+
+    /// When using file storage, location of the snapshots. Defaults to the data directory
+    #[clap(long, env = "LINEAGEDB_SNAPSHOT_DATA")]
+    pub snapshot_data: Option<PathBuf>,
+
+    /// When using file storage, location of the WAL. Can be provided multiple times to mirror the WAL, the first is used for restores. Defaults to the data directory
+    #[clap(long, env = "LINEAGEDB_WAL_DATA", value_delimiter = ',')]
+    pub wal_data: Option<Vec<PathBuf>>,
+
+    /// When using file storage, stores each table's snapshot in its own directory
+    #[clap(long, env = "LINEAGEDB_DIRECTORY_PER_TABLE", num_args = 0..=1, default_missing_value = "true")]
+    pub directory_per_table: Option<bool>,
+
+    /// When using DynamoDB the table name [default: lineagedb-ddb]
+    #[clap(long, env = "LINEAGEDB_TABLE")]
+    pub table: Option<String>,
+
+    /// When using S3 the bucket name [default: dalesalter-test-bucket]
+    #[clap(long, env = "LINEAGEDB_BUCKET")]
+    pub bucket: Option<String>,
+
+    /// When using Postgres the database information
+    #[clap(long, env = "LINEAGEDB_DATABASE_USER")]
+    pub database_user: Option<String>,
+
+    #[clap(long, env = "LINEAGEDB_DATABASE_DATABASE")]
+    pub database_database: Option<String>,
+
+    #[clap(long, env = "LINEAGEDB_DATABASE_HOST")]
+    pub database_host: Option<String>,
+
+    /// Prefer the config file or environment variable, arguments are visible to other processes
+    #[clap(long, env = "LINEAGEDB_DATABASE_PASSWORD", hide_env_values = true)]
+    pub database_password: Option<String>,
+
+    /// When using Postgres, file containing the database password, e.g. a mounted secret. Trailing newlines are ignored
+    #[clap(long, env = "LINEAGEDB_DATABASE_PASSWORD_FILE")]
+    pub database_password_file: Option<PathBuf>,
+
+    /// When using DynamoDB or S3 the AWS profile used for credentials. Defaults to the AWS default credential chain
+    #[clap(long, env = "LINEAGEDB_AWS_PROFILE")]
+    pub aws_profile: Option<String>,
+
+    /// When using S3 the region of the bucket. Defaults to the region of the AWS profile
+    #[clap(long, env = "LINEAGEDB_AWS_REGION")]
+    pub aws_region: Option<String>,
+
+    /// When using S3 a bucket, usually in another region, that snapshots and the WAL are copied to in the background. The database is restored from it when the bucket cannot be read
+    #[clap(long, env = "LINEAGEDB_REPLICA_BUCKET")]
+    pub replica_bucket: Option<String>,
+
+    /// Region of --replica-bucket. Defaults to the region of the AWS profile
+    #[clap(long, env = "LINEAGEDB_REPLICA_REGION")]
+    pub replica_region: Option<String>,
+
+    /// Scopes the storage to a namespace so environments (e.g. dev and staging) can share a data directory, bucket, table or Postgres database. Resets only delete the namespace
+    #[clap(long, env = "LINEAGEDB_STORAGE_NAMESPACE")]
+    pub storage_namespace: Option<String>,
+
+    /// Chance (0 to 1) that a worker sleeps for --chaos-worker-delay-ms before running a transaction
+    #[cfg(feature = "chaos")]
+    #[clap(long)]
+    pub chaos_worker_delay_probability: Option<f64>,
+
+    /// [default: 100]
+    #[cfg(feature = "chaos")]
+    #[clap(long)]
+    pub chaos_worker_delay_ms: Option<u64>,
+
+    /// Chance (0 to 1) that a storage call fails, failed WAL writes crash the database
+    #[cfg(feature = "chaos")]
+    #[clap(long)]
+    pub chaos_storage_error_probability: Option<f64>,
+
+    /// Chance (0 to 1) that a worker drops the transaction it received and restarts
+    #[cfg(feature = "chaos")]
+    #[clap(long)]
+    pub chaos_worker_restart_probability: Option<f64>,
+
+    /// `host:port` of a NATS server, committed transactions are published to --publish-subject as CloudEvents
+    #[cfg(feature = "publisher")]
+    #[clap(long, env = "LINEAGEDB_PUBLISH_NATS")]
+    pub publish_nats: Option<String>,
+
+    /// `host:port` of a Kafka REST proxy, committed transactions are published to the --publish-subject topic as
+    /// CloudEvents
+    #[cfg(feature = "publisher")]
+    #[clap(long, env = "LINEAGEDB_PUBLISH_KAFKA_REST")]
+    pub publish_kafka_rest: Option<String>,
+
+    /// NATS subject or Kafka topic events are published to [default: lineagedb.events]
+    #[cfg(feature = "publisher")]
+    #[clap(long, env = "LINEAGEDB_PUBLISH_SUBJECT")]
+    pub publish_subject: Option<String>,
+
+    /// CPUs the worker threads are pinned to, e.g. 2,3,4,5 (Linux only)
+    #[cfg(feature = "thread-tuning")]
+    #[clap(long, env = "LINEAGEDB_WORKER_CPUS", value_delimiter = ',')]
+    pub worker_cpus: Option<Vec<usize>>,
+
+    /// Nice value (-20 to 19) of the worker threads, negative values require CAP_SYS_NICE (Linux only)
+    #[cfg(feature = "thread-tuning")]
+    #[clap(long, env = "LINEAGEDB_WORKER_NICE", allow_negative_numbers = true)]
+    pub worker_nice: Option<i32>,
+
+    /// CPUs the WAL thread is pinned to, e.g. 1. Keeps fsyncs off the CPUs of --worker-cpus (Linux only)
+    #[cfg(feature = "thread-tuning")]
+    #[clap(long, env = "LINEAGEDB_WAL_CPUS", value_delimiter = ',')]
+    pub wal_cpus: Option<Vec<usize>>,
+
+    /// Nice value (-20 to 19) of the WAL thread, negative values require CAP_SYS_NICE (Linux only)
+    #[cfg(feature = "thread-tuning")]
+    #[clap(long, env = "LINEAGEDB_WAL_NICE", allow_negative_numbers = true)]
+    pub wal_nice: Option<i32>,
+
+    /// Creates the indexes the index advisor recommends each time it runs (see the IndexAdvisor job action)
+    #[cfg(feature = "index-advisor")]
+    #[clap(long, env = "LINEAGEDB_AUTO_CREATE_INDEXES", num_args = 0..=1, default_missing_value = "true")]
+    pub auto_create_indexes: Option<bool>,
+}
+
+/// Takes each value from `$overrides` if it is set, otherwise from `$base`
+macro_rules! merge {
+    ($base:ident, $overrides:ident, { $($field:ident),* $(,)? }) => {
+        DatabaseConfig {
+            $($field: $overrides.$field.or($base.$field),)*
+            #[cfg(feature = "chaos")]
+            chaos_worker_delay_probability: $overrides
+                .chaos_worker_delay_probability
+                .or($base.chaos_worker_delay_probability),
+            #[cfg(feature = "chaos")]
+            chaos_worker_delay_ms: $overrides.chaos_worker_delay_ms.or($base.chaos_worker_delay_ms),
+            #[cfg(feature = "chaos")]
+            chaos_storage_error_probability: $overrides
+                .chaos_storage_error_probability
+                .or($base.chaos_storage_error_probability),
+            #[cfg(feature = "chaos")]
+            chaos_worker_restart_probability: $overrides
+                .chaos_worker_restart_probability
+                .or($base.chaos_worker_restart_probability),
+            #[cfg(feature = "publisher")]
+            publish_nats: $overrides.publish_nats.or($base.publish_nats),
+            #[cfg(feature = "publisher")]
+            publish_kafka_rest: $overrides.publish_kafka_rest.or($base.publish_kafka_rest),
+            #[cfg(feature = "publisher")]
+            publish_subject: $overrides.publish_subject.or($base.publish_subject),
+            #[cfg(feature = "thread-tuning")]
+            worker_cpus: $overrides.worker_cpus.or($base.worker_cpus),
+            #[cfg(feature = "thread-tuning")]
+            worker_nice: $overrides.worker_nice.or($base.worker_nice),
+            #[cfg(feature = "thread-tuning")]
+            wal_cpus: $overrides.wal_cpus.or($base.wal_cpus),
+            #[cfg(feature = "thread-tuning")]
+            wal_nice: $overrides.wal_nice.or($base.wal_nice),
+            #[cfg(feature = "index-advisor")]
+            auto_create_indexes: $overrides.auto_create_indexes.or($base.auto_create_indexes),
+        }
+    };
+}
+
+impl DatabaseConfig {
+    /// Values set in `overrides` take precedence, e.g. command line values over config file values
+    pub fn merge(self, overrides: DatabaseConfig) -> DatabaseConfig {
+        merge!(self, overrides, {
+            threads,
+            read_threads,
+            write_threads,
+            replay_threads,
+            replay_checkpoint_interval,
+            restore,
+            storage,
+            migrate_to,
+            restore_from_backup,
+            wal_sync,
+            durability_self_test,
+            ignore_snapshot_compatibility,
+            paranoid_checks,
+            prepared_queries_only,
+            conflict_resolution,
+            unique_email,
+            history_squash_window,
+            history_squash_min_age,
+            id_seed,
+            hot_versions,
+            retained_snapshots,
+            archive_wal,
+            pipeline_wal,
+            wal_latency_slo_ms,
+            wal_min_batch,
+            wal_max_batch,
+            unknown_statements,
+            parquet_export,
+            snapshot_shards,
+            snapshot_upload_parallelism,
+            snapshot_upload_retries,
+            seed,
+            capture_requests,
+            capture_window_secs,
+            capture_max_requests,
+            replay_capture,
+            replay_capture_speed,
+            queue_wait_slo_ms,
+            pause_warn_ms,
+            request_log_sample_rate,
+            request_log_slower_than_ms,
+            shadow_read_sample_rate,
+            maintenance_queue_limit,
+            max_statements_per_transaction,
+            max_statement_bytes,
+            storage_wal_write_timeout_ms,
+            storage_blob_timeout_ms,
+            storage_control_timeout_ms,
+            warmup,
+            warmup_transactions,
+            warmup_preload_rows,
+            verify_restore,
+            verify_restore_sample_size,
+            audit_rollbacks,
+            audit_rollbacks_retained_segments,
+            statement_stats,
+            statement_stats_persist_interval_secs,
+            default_role,
+            default_request_timeout_ms,
+            client_overridable,
+            row_policy,
+            quota,
+            tag_limit,
+            field_encryption_key,
+            sensitive_field,
+            sensitive_field_reader,
+            data,
+            snapshot_data,
+            wal_data,
+            directory_per_table,
+            table,
+            bucket,
+            database_user,
+            database_database,
+            database_host,
+            database_password,
+            database_password_file,
+            aws_profile,
+            aws_region,
+            replica_bucket,
+            replica_region,
+            storage_namespace,
+        })
+    }
+
+    /// Parses `<role>=<email domain>` row policies
+    pub fn row_policies(&self) -> Result<Vec<RowPolicy>, ConfigError> {
+        self.row_policy
+            .iter()
+            .flatten()
+            .map(|value| {
+                let (role, domain) = value.split_once('=').ok_or_else(|| {
+                    ConfigError::InvalidValue(
+                        "row_policy",
+                        format!("expected <role>=<email domain>, got: {}", value),
+                    )
+                })?;
+
+                Ok(RowPolicy {
+                    name: format!("{}={}", role, domain),
+                    role: role.to_string(),
+                    predicate: PolicyPredicate::EmailDomain(domain.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    /// Parses `<role>:<limit>=<value>` quotas, limits of the same role are combined
+    pub fn quotas(&self) -> Result<HashMap<String, Quota>, ConfigError> {
+        let mut quotas: HashMap<String, Quota> = HashMap::new();
+
+        for value in self.quota.iter().flatten() {
+            let invalid = || {
+                ConfigError::InvalidValue(
+                    "quota",
+                    format!("expected <role>:<limit>=<value>, got: {}", value),
+                )
+            };
+
+            let (role, limit) = value.split_once(':').ok_or_else(invalid)?;
+            let (limit, amount) = limit.split_once('=').ok_or_else(invalid)?;
+            let amount: usize = amount.parse().map_err(|_| invalid())?;
+
+            let quota = quotas.remove(role).unwrap_or_default();
+
+            let quota = match limit {
+                "max-rows" => quota.set_max_rows(amount),
+                "max-wal-bytes-per-day" => quota.set_max_wal_bytes_per_day(amount),
+                "max-requests-per-second" => quota.set_max_requests_per_second(amount),
+                _ => return Err(invalid()),
+            };
+
+            quotas.insert(role.to_string(), quota);
+        }
+
+        Ok(quotas)
+    }
+
+    /// Parses `<tag>:<limit>=<value>` tag limits, limits of the same tag are combined
+    pub fn tag_limits(&self) -> Result<HashMap<String, TagLimit>, ConfigError> {
+        let mut tag_limits: HashMap<String, TagLimit> = HashMap::new();
+
+        for value in self.tag_limit.iter().flatten() {
+            let invalid = || {
+                ConfigError::InvalidValue(
+                    "tag_limit",
+                    format!("expected <tag>:<limit>=<value>, got: {}", value),
+                )
+            };
+
+            let (tag, limit) = value.split_once(':').ok_or_else(invalid)?;
+            let (limit, amount) = limit.split_once('=').ok_or_else(invalid)?;
+
+            let tag_limit = tag_limits.remove(tag).unwrap_or_default();
+
+            let tag_limit = match (limit, amount) {
+                ("max-concurrent", amount) => {
+                    tag_limit.set_max_concurrent(amount.parse().map_err(|_| invalid())?)
+                }
+                ("priority", "normal") => tag_limit.set_priority(TagPriority::Normal),
+                ("priority", "background") => tag_limit.set_priority(TagPriority::Background),
+                _ => return Err(invalid()),
+            };
+
+            tag_limits.insert(tag.to_string(), tag_limit);
+        }
+
+        Ok(tag_limits)
+    }
+
+    fn storage_engine(&self, storage: StorageEngineFlag) -> Result<StorageEngine, ConfigError> {
+        let engine = match storage {
+            StorageEngineFlag::File => {
+                let data = self.data.clone().unwrap_or_else(|| PathBuf::from("data"));
+
+                let mut options =
+                    FileOptions::new(data).set_wal_dirs(self.wal_data.clone().unwrap_or_default());
+
+                if let Some(snapshot_data) = &self.snapshot_data {
+                    options = options.set_snapshot_dir(snapshot_data.clone());
+                }
+
+                if self.directory_per_table.unwrap_or(false) {
+                    options = options.set_layout(FileLayout::DirectoryPerTable);
+                }
+
+                StorageEngine::File(options)
+            }
+            #[cfg(feature = "dynamodb")]
+            StorageEngineFlag::Dynamo => {
+                let mut options =
+                    DynamoOptions::new(self.table.clone().unwrap_or("lineagedb-ddb".to_string()));
+
+                if let Some(profile) = &self.aws_profile {
+                    options = options.set_profile(profile.clone());
+                }
+
+                StorageEngine::DynamoDB(options)
+            }
+            #[cfg(feature = "postgres")]
+            StorageEngineFlag::Postgres => StorageEngine::Postgres(PostgresOptions::new(
+                self.database_user
+                    .clone()
+                    .unwrap_or("dalesalter".to_string()),
+                self.database_database
+                    .clone()
+                    .unwrap_or("dalesalter1".to_string()),
+                self.database_host
+                    .clone()
+                    .unwrap_or("localhost".to_string()),
+                self.database_password()?,
+            )),
+            #[cfg(feature = "s3")]
+            StorageEngineFlag::S3 => {
+                let mut options = S3Options::new(
+                    self.bucket
+                        .clone()
+                        .unwrap_or("dalesalter-test-bucket".to_string()),
+                );
+
+                if let Some(profile) = &self.aws_profile {
+                    options = options.set_profile(profile.clone());
+                }
+
+                if let Some(region) = &self.aws_region {
+                    options = options.set_region(region.clone());
+                }
+
+                if let Some(bucket) = &self.replica_bucket {
+                    let mut replica = S3Replica::new(bucket.clone());
+
+                    if let Some(region) = &self.replica_region {
+                        replica = replica.set_region(region.clone());
+                    }
+
+                    options = options.set_replica(replica);
+                }
+
+                StorageEngine::S3(options)
+            }
+            #[cfg(not(feature = "dynamodb"))]
+            StorageEngineFlag::Dynamo => return Err(missing_feature("storage", "dynamodb")),
+            #[cfg(not(feature = "postgres"))]
+            StorageEngineFlag::Postgres => return Err(missing_feature("storage", "postgres")),
+            #[cfg(not(feature = "s3"))]
+            StorageEngineFlag::S3 => return Err(missing_feature("storage", "s3")),
+        };
+
+        match &self.storage_namespace {
+            Some(namespace) => Ok(engine.set_namespace(namespace.clone())),
+            None => Ok(engine),
+        }
+    }
+
+    /// A backup is either the data directory of a file database or an S3 bucket with an optional key prefix
+    fn backup_engine(&self, backup: &str) -> Result<StorageEngine, ConfigError> {
+        match backup.strip_prefix("s3://") {
+            Some(location) => self.s3_backup_engine(backup, location),
+            None => Ok(StorageEngine::File(FileOptions::new(PathBuf::from(backup)))),
+        }
+    }
+
+    #[cfg(not(feature = "s3"))]
+    fn s3_backup_engine(&self, _: &str, _: &str) -> Result<StorageEngine, ConfigError> {
+        Err(missing_feature("restore_from_backup", "s3"))
+    }
+
+    #[cfg(feature = "s3")]
+    fn s3_backup_engine(&self, backup: &str, location: &str) -> Result<StorageEngine, ConfigError> {
+        let (bucket, prefix) = match location.split_once('/') {
+            Some((bucket, prefix)) => (bucket, Some(prefix.trim_end_matches('/'))),
+            None => (location, None),
+        };
+
+        if bucket.is_empty() {
+            return Err(ConfigError::InvalidValue(
+                "restore_from_backup",
+                format!("{} does not name an S3 bucket", backup),
+            ));
+        }
+
+        let mut options = S3Options::new(bucket.to_string());
+
+        if let Some(prefix) = prefix.filter(|prefix| !prefix.is_empty()) {
+            options = options.set_base_path(PathBuf::from(prefix));
+        }
+
+        if let Some(profile) = &self.aws_profile {
+            options = options.set_profile(profile.clone());
+        }
+
+        Ok(StorageEngine::S3(options))
+    }
+
+    #[cfg(feature = "postgres")]
+    fn database_password(&self) -> Result<Secret, ConfigError> {
+        match (&self.database_password, &self.database_password_file) {
+            (Some(_), Some(_)) => Err(ConfigError::InvalidValue(
+                "database_password_file",
+                "cannot be combined with database_password".to_string(),
+            )),
+            (Some(password), None) => Ok(Secret::new(password.clone())),
+            (None, Some(path)) => Secret::from_file(path).map_err(|e| {
+                ConfigError::InvalidValue(
+                    "database_password_file",
+                    format!("unable to read {}: {}", path.display(), e),
+                )
+            }),
+            (None, None) => Ok(Secret::new("mysecretpassword".to_string())),
+        }
+    }
+
+    fn write_mode(&self) -> TransactionWriteMode {
+        match self.wal_sync.clone().unwrap_or(WalSyncFlag::Fsync) {
+            WalSyncFlag::Fsync => TransactionWriteMode::File(TransactionFileWriteMode::Sync),
+            WalSyncFlag::Fdatasync => {
+                TransactionWriteMode::File(TransactionFileWriteMode::DataSync)
+            }
+            WalSyncFlag::Dsync => TransactionWriteMode::File(TransactionFileWriteMode::DSync),
+            WalSyncFlag::OsBuffered => {
+                TransactionWriteMode::File(TransactionFileWriteMode::OSBuffered)
+            }
+            WalSyncFlag::Off => TransactionWriteMode::Off,
+        }
+    }
+
+    /// Builds the database options, invalid values are reported with the key they were set with
+    pub fn to_options(&self) -> Result<DatabaseOptions, ConfigError> {
+        let defaults = DatabaseOptions::default();
+        let storage = self.storage.clone().unwrap_or(StorageEngineFlag::File);
+
+        let mut database_options = defaults
+            .clone()
+            .set_threads(self.threads.unwrap_or(defaults.threads))
+            .set_restore(self.restore.unwrap_or(defaults.restore))
+            .set_storage_engine(self.storage_engine(storage.clone())?)
+            .set_sync_file_write(self.write_mode())
+            .set_durability_self_test(self.durability_self_test.unwrap_or(false))
+            .set_ignore_snapshot_compatibility(self.ignore_snapshot_compatibility.unwrap_or(false))
+            .set_paranoid_checks(self.paranoid_checks.unwrap_or(false))
+            .set_prepared_queries_only(self.prepared_queries_only.unwrap_or(false))
+            .set_conflict_resolution(match self.conflict_resolution {
+                Some(ConflictResolutionFlag::FieldMerge) => ConflictResolution::FieldMerge,
+                Some(ConflictResolutionFlag::LastWriterWins) | None => {
+                    ConflictResolution::LastWriterWins
+                }
+            })
+            .set_id_generation(match self.id_seed {
+                Some(seed) => IdGeneration::Seeded(seed),
+                None => IdGeneration::Random,
+            })
+            .set_archive_wal(self.archive_wal.unwrap_or(false))
+            .set_pipeline_wal(self.pipeline_wal.unwrap_or(false))
+            .set_unknown_statement_policy(match self.unknown_statements {
+                Some(UnknownStatementsFlag::Skip) => UnknownStatementPolicy::Skip,
+                Some(UnknownStatementsFlag::Fail) | None => UnknownStatementPolicy::Fail,
+            });
+
+        if let Some(unique_email) = &self.unique_email {
+            database_options = database_options.set_unique_email(match unique_email {
+                ConstraintTimingFlag::Immediate => ConstraintTiming::Immediate,
+                ConstraintTimingFlag::Deferred => ConstraintTiming::Deferred,
+            });
+        }
+
+        match (self.history_squash_window, self.history_squash_min_age) {
+            (Some(window), min_age) => {
+                database_options = database_options.set_history_squash(
+                    HistorySquash::new(window).set_min_age(min_age.unwrap_or(0)),
+                );
+            }
+            (None, Some(_)) => {
+                return Err(ConfigError::InvalidValue(
+                    "history_squash_min_age",
+                    "requires history_squash_window".to_string(),
+                ));
+            }
+            (None, None) => {}
+        }
+
+        match (
+            self.wal_latency_slo_ms,
+            self.wal_min_batch.is_some() || self.wal_max_batch.is_some(),
+        ) {
+            (Some(slo_ms), _) => {
+                let defaults = AdaptiveBatchingOptions::new(Duration::from_millis(slo_ms));
+
+                database_options = database_options.set_adaptive_wal_batching(
+                    defaults
+                        .clone()
+                        .set_min_batch(self.wal_min_batch.unwrap_or(defaults.min_batch))
+                        .set_max_batch(self.wal_max_batch.unwrap_or(defaults.max_batch)),
+                );
+            }
+            (None, true) => {
+                let key = match self.wal_min_batch {
+                    Some(_) => "wal_min_batch",
+                    None => "wal_max_batch",
+                };
+
+                return Err(ConfigError::InvalidValue(
+                    key,
+                    "requires wal_latency_slo_ms".to_string(),
+                ));
+            }
+            (None, false) => {}
+        }
+
+        for (key, threads) in [
+            ("threads", self.threads),
+            ("read_threads", self.read_threads),
+            ("write_threads", self.write_threads),
+            ("replay_threads", self.replay_threads),
+        ] {
+            if threads == Some(0) {
+                return Err(ConfigError::InvalidValue(
+                    key,
+                    "at least one worker thread is required".to_string(),
+                ));
+            }
+        }
+
+        if let Some(read_threads) = self.read_threads {
+            database_options = database_options.set_read_threads(read_threads);
+        }
+
+        if let Some(write_threads) = self.write_threads {
+            database_options = database_options.set_write_threads(write_threads);
+        }
+
+        if let Some(replay_threads) = self.replay_threads {
+            database_options = database_options.set_replay_threads(replay_threads);
+        }
+
+        if let Some(replay_checkpoint_interval) = self.replay_checkpoint_interval {
+            database_options =
+                database_options.set_replay_checkpoint_interval(replay_checkpoint_interval);
+        }
+
+        if let Some(migrate_to) = &self.migrate_to {
+            if *migrate_to == storage {
+                return Err(ConfigError::InvalidValue(
+                    "migrate_to",
+                    "must be a different storage engine than storage".to_string(),
+                ));
+            }
+
+            database_options =
+                database_options.set_migrate_to(self.storage_engine(migrate_to.clone())?);
+        }
+
+        if let Some(backup) = &self.restore_from_backup {
+            if !database_options.restore {
+                return Err(ConfigError::InvalidValue(
+                    "restore_from_backup",
+                    "cannot be combined with restore = false".to_string(),
+                ));
+            }
+
+            database_options =
+                database_options.set_restore_from_backup(self.backup_engine(backup)?);
+        }
+
+        if let Some(hot_versions) = self.hot_versions {
+            database_options = database_options.set_hot_versions(hot_versions);
+        }
+
+        if let Some(key) = &self.field_encryption_key {
+            let sensitive_fields = self
+                .sensitive_field
+                .clone()
+                .unwrap_or(vec![SensitiveFieldFlag::Email])
+                .into_iter()
+                .map(|field| match field {
+                    SensitiveFieldFlag::FullName => PersonField::FullName,
+                    SensitiveFieldFlag::Email => PersonField::Email,
+                })
+                .collect();
+
+            let field_encryption = FieldEncryptionOptions::new(key, sensitive_fields)
+                .map_err(|e| ConfigError::InvalidValue("field_encryption_key", e.to_string()))?
+                .set_authorized_roles(self.sensitive_field_reader.clone().unwrap_or_default());
+
+            database_options = database_options.set_field_encryption(field_encryption);
+        }
+
+        if let Some(retained_snapshots) = self.retained_snapshots {
+            if retained_snapshots == 0 {
+                return Err(ConfigError::InvalidValue(
+                    "retained_snapshots",
+                    "at least one snapshot has to be retained".to_string(),
+                ));
+            }
+
+            database_options = database_options.set_retained_snapshots(retained_snapshots);
+        }
+
+        if let Some(parquet_export) = &self.parquet_export {
+            database_options = database_options.set_parquet_export(match parquet_export.as_str() {
+                "storage" => ParquetExportTarget::Storage,
+                directory => ParquetExportTarget::Directory(PathBuf::from(directory)),
+            });
+        }
+
+        match self.snapshot_shards {
+            Some(shards) => {
+                let mut sharding = SnapshotSharding::new(shards);
+
+                if let Some(parallelism) = self.snapshot_upload_parallelism {
+                    sharding = sharding.set_parallelism(parallelism);
+                }
+
+                if let Some(retries) = self.snapshot_upload_retries {
+                    sharding = sharding.set_retries(retries);
+                }
+
+                database_options = database_options.set_snapshot_sharding(sharding);
+            }
+            None => {
+                if self.snapshot_upload_parallelism.is_some() {
+                    return Err(ConfigError::InvalidValue(
+                        "snapshot_upload_parallelism",
+                        "requires snapshot_shards".to_string(),
+                    ));
+                }
+
+                if self.snapshot_upload_retries.is_some() {
+                    return Err(ConfigError::InvalidValue(
+                        "snapshot_upload_retries",
+                        "requires snapshot_shards".to_string(),
+                    ));
+                }
+            }
+        }
+
+        if let Some(seed) = &self.seed {
+            database_options = database_options.set_seed(
+                Seed::from_file(seed)
+                    .map_err(|e| ConfigError::InvalidValue("seed", e.to_string()))?,
+            );
+        }
+
+        match &self.capture_requests {
+            Some(path) => {
+                let mut capture = RequestCaptureOptions::new(
+                    path.clone(),
+                    Duration::from_secs(self.capture_window_secs.unwrap_or(300)),
+                );
+
+                if let Some(max_requests) = self.capture_max_requests {
+                    capture = capture.set_max_requests(max_requests);
+                }
+
+                database_options = database_options.set_request_capture(capture);
+            }
+            None => {
+                if self.capture_window_secs.is_some() {
+                    return Err(ConfigError::InvalidValue(
+                        "capture_window_secs",
+                        "requires capture_requests".to_string(),
+                    ));
+                }
+
+                if self.capture_max_requests.is_some() {
+                    return Err(ConfigError::InvalidValue(
+                        "capture_max_requests",
+                        "requires capture_requests".to_string(),
+                    ));
+                }
+            }
+        }
+
+        match (&self.replay_capture, self.replay_capture_speed) {
+            (Some(path), speed) => {
+                let pacing = match speed {
+                    None => ReplayPacing::Original,
+                    Some(speed) if speed == 0.0 => ReplayPacing::Unpaced,
+                    Some(speed) => ReplayPacing::Accelerated(speed),
+                };
+
+                database_options = database_options.set_capture_replay(
+                    CaptureReplay::from_file(path, pacing)
+                        .map_err(|e| ConfigError::InvalidValue("replay_capture", e.to_string()))?,
+                );
+            }
+            (None, Some(_)) => {
+                return Err(ConfigError::InvalidValue(
+                    "replay_capture_speed",
+                    "requires replay_capture".to_string(),
+                ));
+            }
+            (None, None) => {}
+        }
+
+        if let Some(queue_wait_slo_ms) = self.queue_wait_slo_ms {
+            database_options =
+                database_options.set_queue_wait_slo(Duration::from_millis(queue_wait_slo_ms));
+        }
+
+        if let Some(pause_warn_ms) = self.pause_warn_ms {
+            database_options =
+                database_options.set_pause_warn_threshold(Duration::from_millis(pause_warn_ms));
+        }
+
+        match (
+            self.request_log_sample_rate,
+            self.request_log_slower_than_ms,
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(ConfigError::InvalidValue(
+                    "request_log_sample_rate",
+                    "cannot be combined with request_log_slower_than_ms".to_string(),
+                ))
+            }
+            (Some(rate), None) if !(0.0..=1.0).contains(&rate) => {
+                return Err(ConfigError::InvalidValue(
+                    "request_log_sample_rate",
+                    format!("expected a value between 0 and 1, got: {}", rate),
+                ))
+            }
+            (Some(rate), None) => {
+                database_options =
+                    database_options.set_request_log_sampling(RequestLogSampling::Rate(rate));
+            }
+            (None, Some(slower_than_ms)) => {
+                database_options = database_options.set_request_log_sampling(
+                    RequestLogSampling::SlowerThan(Duration::from_millis(slower_than_ms)),
+                );
+            }
+            (None, None) => {}
+        }
+
+        match self.shadow_read_sample_rate {
+            Some(rate) if !(0.0..=1.0).contains(&rate) => {
+                return Err(ConfigError::InvalidValue(
+                    "shadow_read_sample_rate",
+                    format!("expected a value between 0 and 1, got: {}", rate),
+                ))
+            }
+            Some(rate) => {
+                database_options = database_options.set_shadow_reads(ShadowReadOptions::new(rate));
+            }
+            None => {}
+        }
+
+        for (role, quota) in self.quotas()? {
+            database_options = database_options.set_quota(role, quota);
+        }
+
+        for (tag, tag_limit) in self.tag_limits()? {
+            database_options = database_options.set_tag_limit(tag, tag_limit);
+        }
+
+        if let Some(maintenance_queue_limit) = self.maintenance_queue_limit {
+            database_options =
+                database_options.set_maintenance_queue_limit(maintenance_queue_limit);
+        }
+
+        let mut limits = TransactionLimits::default();
+
+        if let Some(max_statements) = self.max_statements_per_transaction {
+            limits = limits.set_max_statements(max_statements);
+        }
+
+        if let Some(max_statement_bytes) = self.max_statement_bytes {
+            limits = limits.set_max_statement_bytes(max_statement_bytes);
+        }
+
+        database_options = database_options.set_transaction_limits(limits);
+
+        let mut storage_timeouts = StorageTimeouts::default();
+
+        if let Some(timeout_ms) = self.storage_wal_write_timeout_ms {
+            storage_timeouts =
+                storage_timeouts.set_transaction_write(Duration::from_millis(timeout_ms));
+        }
+
+        if let Some(timeout_ms) = self.storage_blob_timeout_ms {
+            storage_timeouts = storage_timeouts.set_blob(Duration::from_millis(timeout_ms));
+        }
+
+        if let Some(timeout_ms) = self.storage_control_timeout_ms {
+            storage_timeouts = storage_timeouts.set_control(Duration::from_millis(timeout_ms));
+        }
+
+        database_options = database_options.set_storage_timeouts(storage_timeouts);
+
+        let mut context_policy = ContextPolicy::default();
+
+        if let Some(default_role) = &self.default_role {
+            context_policy = context_policy.set_default_role(default_role.clone());
+        }
+
+        if let Some(timeout_ms) = self.default_request_timeout_ms {
+            context_policy = context_policy.set_default_timeout(Duration::from_millis(timeout_ms));
+        }
+
+        if let Some(fields) = &self.client_overridable {
+            let fields = fields
+                .iter()
+                .map(|field| {
+                    field.parse::<ContextField>().map_err(|_| {
+                        ConfigError::InvalidValue(
+                            "client_overridable",
+                            format!(
+                                "expected one of SnapshotTimestamp, Role, Clone or Timeout, got: {}",
+                                field
+                            ),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<ContextField>, ConfigError>>()?;
+
+            context_policy = context_policy.set_overridable(fields);
+        }
+
+        database_options = database_options.set_context_policy(context_policy);
+
+        let warmup = self.warmup.unwrap_or(false)
+            || self.warmup_transactions.is_some()
+            || self.warmup_preload_rows.is_some();
+
+        if warmup {
+            let mut warmup_options = WarmupOptions::default();
+
+            if let Some(transactions) = self.warmup_transactions {
+                warmup_options = warmup_options.set_transactions_per_thread(transactions);
+            }
+
+            if let Some(preload_rows) = self.warmup_preload_rows {
+                warmup_options = warmup_options.set_preload_rows(preload_rows);
+            }
+
+            database_options = database_options.set_warmup(warmup_options);
+        }
+
+        if self.verify_restore.unwrap_or(false) || self.verify_restore_sample_size.is_some() {
+            let mut verify_options = RestoreVerificationOptions::default();
+
+            if let Some(sample_size) = self.verify_restore_sample_size {
+                verify_options = verify_options.set_sample_size(sample_size);
+            }
+
+            database_options = database_options.set_verify_restore(verify_options);
+        }
+
+        if self.audit_rollbacks.unwrap_or(false) || self.audit_rollbacks_retained_segments.is_some()
+        {
+            let mut audit_options = RollbackAuditOptions::default();
+
+            if let Some(retained_segments) = self.audit_rollbacks_retained_segments {
+                audit_options = audit_options.set_retained_segments(retained_segments);
+            }
+
+            database_options = database_options.set_rollback_audit(audit_options);
+        }
+
+        if self.statement_stats.unwrap_or(false)
+            || self.statement_stats_persist_interval_secs.is_some()
+        {
+            let mut stats_options = StatementStatsOptions::default();
+
+            if let Some(interval) = self.statement_stats_persist_interval_secs {
+                stats_options = stats_options.set_persist_interval(Duration::from_secs(interval));
+            }
+
+            database_options = database_options.set_statement_stats(stats_options);
+        }
+
+        #[cfg(feature = "chaos")]
+        {
+            database_options = database_options.set_chaos(
+                ChaosOptions::default()
+                    .set_worker_delay(
+                        self.chaos_worker_delay_probability.unwrap_or(0.0),
+                        Duration::from_millis(self.chaos_worker_delay_ms.unwrap_or(100)),
+                    )
+                    .set_storage_error_probability(
+                        self.chaos_storage_error_probability.unwrap_or(0.0),
+                    )
+                    .set_worker_restart_probability(
+                        self.chaos_worker_restart_probability.unwrap_or(0.0),
+                    ),
+            );
+        }
+
+        #[cfg(feature = "thread-tuning")]
+        {
+            let placement = |cpus: &Option<Vec<usize>>, nice: Option<i32>| {
+                let placement =
+                    ThreadPlacement::default().set_cpus(cpus.clone().unwrap_or_default());
+
+                match nice {
+                    Some(nice) => placement.set_nice(nice),
+                    None => placement,
+                }
+            };
+
+            if self.worker_cpus.is_some()
+                || self.worker_nice.is_some()
+                || self.wal_cpus.is_some()
+                || self.wal_nice.is_some()
+            {
+                database_options = database_options.set_thread_tuning(
+                    ThreadTuningOptions::default()
+                        .set_workers(placement(&self.worker_cpus, self.worker_nice))
+                        .set_wal(placement(&self.wal_cpus, self.wal_nice)),
+                );
+            }
+        }
+
+        #[cfg(feature = "index-advisor")]
+        {
+            database_options =
+                database_options.set_auto_create_indexes(self.auto_create_indexes.unwrap_or(false));
+        }
+
+        #[cfg(feature = "publisher")]
+        {
+            let subject = self
+                .publish_subject
+                .clone()
+                .unwrap_or("lineagedb.events".to_string());
+
+            let sink = match (self.publish_nats.clone(), self.publish_kafka_rest.clone()) {
+                (Some(_), Some(_)) => {
+                    return Err(ConfigError::InvalidValue(
+                        "publish_kafka_rest",
+                        "events are published to either NATS or Kafka, not both".to_string(),
+                    ))
+                }
+                (Some(address), None) => Some(PublisherSink::Nats { address, subject }),
+                (None, Some(address)) => Some(PublisherSink::KafkaRest {
+                    address,
+                    topic: subject,
+                }),
+                (None, None) => None,
+            };
+
+            if let Some(sink) = sink {
+                database_options = database_options.set_publisher(PublisherOptions::new(sink));
+            }
+        }
+
+        database_options.validate()?;
+
+        Ok(database_options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct ConfigFile {
+        database: DatabaseConfig,
+    }
+
+    fn parse(contents: &str) -> Result<ConfigFile, String> {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn layers_and_validates_config() {
+        let file = parse(
+            r#"
+            [database]
+            threads = 4
+            wal_sync = "off"
+            restore = false
+            conflict_resolution = "field-merge"
+            unique_email = "deferred"
+            replay_checkpoint_interval = 100000
+            history_squash_window = 1000
+            history_squash_min_age = 50
+            id_seed = 7
+            snapshot_shards = 8
+            snapshot_upload_parallelism = 16
+            capture_requests = "/tmp/lineagedb-capture.jsonl"
+            capture_window_secs = 60
+            capture_max_requests = 1000
+            pause_warn_ms = 250
+            prepared_queries_only = true
+            pipeline_wal = true
+            unknown_statements = "skip"
+            wal_latency_slo_ms = 20
+            wal_max_batch = 400
+            database_password = "from-file"
+            quota = ["tenant-x:max-rows=10", "tenant-x:max-requests-per-second=5"]
+            tag_limit = ["batch-import:max-concurrent=2", "batch-import:priority=background"]
+            "#,
+        )
+        .unwrap()
+        .database;
+
+        let overrides = DatabaseConfig {
+            threads: Some(8),
+            ..DatabaseConfig::default()
+        };
+
+        let config = file.merge(overrides);
+
+        assert_eq!(config.threads, Some(8));
+        assert_eq!(config.wal_sync, Some(WalSyncFlag::Off));
+        assert_eq!(config.database_password.as_deref(), Some("from-file"));
+
+        let options = config.to_options().unwrap();
+        assert_eq!(options.threads, 8);
+        assert_eq!(options.write_mode, TransactionWriteMode::Off);
+        assert!(options.pipeline_wal);
+        assert_eq!(options.unknown_statements, UnknownStatementPolicy::Skip);
+        assert_eq!(
+            options.adaptive_wal_batching,
+            Some(AdaptiveBatchingOptions::new(Duration::from_millis(20)).set_max_batch(400))
+        );
+        assert_eq!(options.conflict_resolution.name(), "FieldMerge");
+        assert_eq!(options.unique_email, Some(ConstraintTiming::Deferred));
+        assert_eq!(options.replay_checkpoint_interval, Some(100_000));
+        assert_eq!(
+            options.history_squash,
+            Some(HistorySquash::new(1000).set_min_age(50))
+        );
+        assert_eq!(options.id_generation, IdGeneration::Seeded(7));
+        assert_eq!(
+            options.snapshot_sharding,
+            Some(SnapshotSharding::new(8).set_parallelism(16))
+        );
+        assert_eq!(
+            options.request_capture,
+            Some(
+                RequestCaptureOptions::new(
+                    PathBuf::from("/tmp/lineagedb-capture.jsonl"),
+                    Duration::from_secs(60)
+                )
+                .set_max_requests(1000)
+            )
+        );
+        assert_eq!(
+            options.tag_limits["batch-import"],
+            TagLimit::default()
+                .set_max_concurrent(2)
+                .set_priority(TagPriority::Background)
+        );
+        assert_eq!(
+            options.pause_warn_threshold,
+            Some(Duration::from_millis(250))
+        );
+        assert!(options.prepared_queries_only);
+        assert_eq!(
+            options.quotas["tenant-x"],
+            Quota::default()
+                .set_max_rows(10)
+                .set_max_requests_per_second(5)
+        );
+
+        // Errors point at the offending key
+        let error = parse("[database]\nthreads = \"many\"").err().unwrap();
+        assert!(error.contains("database.threads"), "{}", error);
+
+        let error = parse("[database]\nwal_sync_mode = \"off\"").err().unwrap();
+        assert!(error.contains("wal_sync_mode"), "{}", error);
+
+        let invalid = DatabaseConfig {
+            request_log_sample_rate: Some(2.0),
+            ..DatabaseConfig::default()
+        };
+        let error = invalid.to_options().err().unwrap().to_string();
+        assert!(error.contains("`request_log_sample_rate`"), "{}", error);
+
+        let speed_without_capture = DatabaseConfig {
+            replay_capture_speed: Some(10.0),
+            ..DatabaseConfig::default()
+        };
+        let error = speed_without_capture
+            .to_options()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("`replay_capture_speed`"), "{}", error);
+
+        let invalid_quota = DatabaseConfig {
+            quota: Some(vec!["tenant-x:max-rows".to_string()]),
+            ..DatabaseConfig::default()
+        };
+        let error = invalid_quota.to_options().err().unwrap().to_string();
+        assert!(error.contains("`quota`"), "{}", error);
+
+        let conflicting_passwords = DatabaseConfig {
+            storage: Some(StorageEngineFlag::Postgres),
+            database_password: Some("from-env".to_string()),
+            database_password_file: Some(PathBuf::from("/run/secrets/pg")),
+            ..DatabaseConfig::default()
+        };
+        let error = conflicting_passwords
+            .to_options()
+            .err()
+            .unwrap()
+            .to_string();
+        #[cfg(feature = "postgres")]
+        assert!(error.contains("`database_password_file`"), "{}", error);
+        // The engine is rejected before the password is read
+        #[cfg(not(feature = "postgres"))]
+        assert!(error.contains("`storage`"), "{}", error);
+
+        let restore_without_wal = DatabaseConfig {
+            wal_sync: Some(WalSyncFlag::Off),
+            ..DatabaseConfig::default()
+        };
+        assert!(matches!(
+            restore_without_wal.to_options(),
+            Err(ConfigError::InvalidOptions(OptionsError::RestoreWithoutWal))
+        ));
+
+        let backup = DatabaseConfig {
+            restore_from_backup: Some("s3://backups/lineagedb/".to_string()),
+            ..DatabaseConfig::default()
+        };
+        #[cfg(feature = "s3")]
+        assert!(matches!(
+            backup.to_options().unwrap().restore_from_backup,
+            Some(StorageEngine::S3(options)) if options.bucket == "backups"
+        ));
+        #[cfg(not(feature = "s3"))]
+        assert!(backup.to_options().is_err());
+
+        let backup_without_bucket = DatabaseConfig {
+            restore_from_backup: Some("s3://".to_string()),
+            ..DatabaseConfig::default()
+        };
+        let error = backup_without_bucket
+            .to_options()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("`restore_from_backup`"), "{}", error);
+
+        let audit = DatabaseConfig {
+            audit_rollbacks_retained_segments: Some(5),
+            ..DatabaseConfig::default()
+        };
+        assert_eq!(
+            audit.to_options().unwrap().rollback_audit,
+            Some(RollbackAuditOptions::default().set_retained_segments(5))
+        );
+
+        let statement_stats = DatabaseConfig {
+            statement_stats_persist_interval_secs: Some(10),
+            ..DatabaseConfig::default()
+        };
+        assert_eq!(
+            statement_stats.to_options().unwrap().statement_stats,
+            Some(StatementStatsOptions::default().set_persist_interval(Duration::from_secs(10)))
+        );
+
+        let verify_without_restore = DatabaseConfig {
+            restore: Some(false),
+            verify_restore_sample_size: Some(10),
+            ..DatabaseConfig::default()
+        };
+        assert!(matches!(
+            verify_without_restore.to_options(),
+            Err(ConfigError::InvalidOptions(
+                OptionsError::VerifyRestoreWithoutRestore
+            ))
+        ));
+
+        let audit_without_segments = DatabaseConfig {
+            audit_rollbacks_retained_segments: Some(0),
+            ..DatabaseConfig::default()
+        };
+        assert!(matches!(
+            audit_without_segments.to_options(),
+            Err(ConfigError::InvalidOptions(OptionsError::ZeroLimit(
+                "rollback_audit.retained_segments"
+            )))
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::HashSet, time::Duration};
+
+use strum::IntoEnumIterator;
+use thiserror::Error;
+
+use super::commands::{SnapshotTimestamp, TransactionContext};
+
+/// Fields of `TransactionContext` that clients can set, see `ContextPolicy`
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    strum_macros::EnumIter,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum ContextField {
+    /// Reads at an earlier transaction id
+    SnapshotTimestamp,
+    Role,
+    Clone,
+    Timeout,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Clients may not set `{0}`, it is decided by the database's context policy")]
+pub struct ContextOverrideRejected(pub ContextField);
+
+/// Server-side defaults for the `TransactionContext` of every transaction, so that consistency policy is decided
+/// by the database rather than by each client. Fields a client leaves unset get the default, a client that sets a
+/// field it is not allowed to override is rejected with `ContextOverrideRejected`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextPolicy {
+    /// Role of transactions that do not set one, e.g. a role restricted by row policies
+    pub default_role: Option<String>,
+    /// How long the request manager waits for a transaction that does not set a timeout
+    pub default_timeout: Duration,
+    /// Fields clients may set, every field by default
+    pub overridable: HashSet<ContextField>,
+}
+
+impl Default for ContextPolicy {
+    fn default() -> Self {
+        Self {
+            default_role: None,
+            default_timeout: Duration::from_secs(30),
+            overridable: ContextField::iter().collect(),
+        }
+    }
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl ContextPolicy {
+    pub fn set_default_role(mut self, default_role: String) -> Self {
+        self.default_role = Some(default_role);
+        self
+    }
+
+    pub fn set_default_timeout(mut self, default_timeout: Duration) -> Self {
+        self.default_timeout = default_timeout;
+        self
+    }
+
+    pub fn set_overridable(mut self, fields: impl IntoIterator<Item = ContextField>) -> Self {
+        self.overridable = fields.into_iter().collect();
+        self
+    }
+
+    /// Fills in the defaults, the returned context always has a timeout
+    pub fn apply(
+        &self,
+        mut context: TransactionContext,
+    ) -> Result<TransactionContext, ContextOverrideRejected> {
+        let set_fields = [
+            (
+                ContextField::SnapshotTimestamp,
+                matches!(
+                    context.snapshot_timestamp,
+                    SnapshotTimestamp::AtTransactionId(_)
+                ),
+            ),
+            (ContextField::Role, context.role.is_some()),
+            (ContextField::Clone, context.clone.is_some()),
+            (ContextField::Timeout, context.timeout.is_some()),
+        ];
+
+        for (field, is_set) in set_fields {
+            if is_set && !self.overridable.contains(&field) {
+                return Err(ContextOverrideRejected(field));
+            }
+        }
+
+        if context.role.is_none() {
+            context.role = self.default_role.clone();
+        }
+
+        context.timeout = context.timeout.or(Some(self.default_timeout));
+
+        Ok(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::consts::TransactionId;
+
+    use super::*;
+
+    #[test]
+    fn defaults_fill_unset_fields_and_overrides_are_allowlisted() {
+        let policy = ContextPolicy::default()
+            .set_default_role("tenant-x".to_string())
+            .set_default_timeout(Duration::from_secs(5))
+            .set_overridable([ContextField::Timeout]);
+
+        let context = policy.apply(TransactionContext::default()).unwrap();
+
+        assert_eq!(context.role, Some("tenant-x".to_string()));
+        assert_eq!(context.timeout, Some(Duration::from_secs(5)));
+
+        let context = policy
+            .apply(TransactionContext::default().set_timeout(Duration::from_secs(1)))
+            .unwrap();
+
+        assert_eq!(context.timeout, Some(Duration::from_secs(1)));
+
+        assert_eq!(
+            policy
+                .apply(TransactionContext::default().set_role(Some("admin".to_string())))
+                .err(),
+            Some(ContextOverrideRejected(ContextField::Role))
+        );
+
+        assert_eq!(
+            policy
+                .apply(TransactionContext::new(SnapshotTimestamp::AtTransactionId(
+                    TransactionId::new_first_transaction()
+                )))
+                .err(),
+            Some(ContextOverrideRejected(ContextField::SnapshotTimestamp))
+        );
+    }
+}
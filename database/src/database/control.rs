@@ -1,15 +1,55 @@
 use oneshot::Sender;
 
-use crate::consts::consts::TransactionId;
+#[cfg(feature = "index-advisor")]
+use super::table::index_advisor::Recommendation;
+
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    model::statement::Statement,
+    persistence::{
+        backup::SnapshotArchive,
+        export::encrypt_export,
+        intent::IntentOperation,
+        snapshot::StorageFeature,
+        snapshot_diff::{DiffSource, SnapshotDiff},
+        storage::StorageResult,
+        transaction::{Transaction, TransactionStatus},
+    },
+};
 
 use super::{
-    commands::{Control, DatabaseCommandResponse, ShutdownRequest},
-    database::Database,
+    activity::RequestId,
+    availability::ThreadAvailability,
+    backfill::IndexBackfillProgress,
+    clones::TableClone,
+    commands::{
+        Control, DatabaseCommandResponse, DatabaseCommandTransactionResponse, MaintenanceTask,
+        ShutdownRequest,
+    },
+    database::{ApplyMode, Database},
+    hooks::LifecycleEvent,
+    operations::{OperationId, OperationStatus},
     orchestrator::DatabasePauseEvent,
+    pause::PauseOperation,
+    prepared::PreparedTransaction,
     request_manager::RequestManager,
+    scheduler::JobDefinition,
+    table::{
+        index::IndexedField,
+        index_advisor::IndexAdvice,
+        policy::{FieldMask, RowPolicy},
+        prepared_query::PreparedQueryDefinition,
+        query::{query, QueryPersonData},
+        row::PersonVersion,
+        view::ViewDefinition,
+    },
     utils::crash::{crash_database, DatabaseCrash},
 };
-use std::{thread, time::Duration};
+use std::{
+    collections::BTreeSet,
+    thread,
+    time::{Duration, Instant},
+};
 
 pub enum DatabaseControlAction {
     Continue,
@@ -37,7 +77,62 @@ impl<'a> ControlContext<'a> {
             Control::Shutdown(r) => self.shutdown(r),
             Control::PauseDatabase(r) => self.pause(r),
             Control::ResetDatabase => self.reset(),
+            Control::TruncateTable => self.truncate_table(),
+            Control::RenameIdPrefix {
+                from,
+                to,
+                chunk_size,
+            } => self.rename_id_prefix(from, to, chunk_size),
             Control::SnapshotDatabase => self.snapshot(),
+            Control::CompactWal => self.compact_wal(),
+            Control::CutoverMigration => self.cutover_migration(),
+            Control::VerifySnapshot { shadow_table } => self.verify_snapshot(shadow_table),
+            Control::Export { recipients } => self.export(recipients),
+            Control::DownloadSnapshot => self.download_snapshot(),
+            Control::WriteAttachment(bytes) => self.write_attachment(bytes),
+            Control::VacuumAttachments(min_age) => self.vacuum_attachments(min_age),
+            Control::SquashHistory => self.squash_history(),
+            Control::CreateView(definition) => self.create_view(definition),
+            Control::DropView(name) => self.drop_view(name),
+            Control::CloneAtTransaction {
+                name,
+                transaction_id,
+            } => self.clone_at_transaction(name, transaction_id),
+            Control::DropClone(name) => self.drop_clone(name),
+            Control::ListClones => self.list_clones(),
+            Control::CreatePolicy(policy) => self.create_policy(policy),
+            Control::DropPolicy(name) => self.drop_policy(name),
+            Control::ListPolicies => self.list_policies(),
+            Control::CreatePreparedQuery(definition) => self.create_prepared_query(definition),
+            Control::DropPreparedQuery(name) => self.drop_prepared_query(name),
+            Control::ListPreparedQueries => self.list_prepared_queries(),
+            Control::ExplainQuery(query) => self.explain_query(query),
+            Control::IndexAdvisor => self.index_advisor(),
+            Control::CreateIndex(field) => self.create_index(field),
+            Control::BackfillIndex(field) => self.backfill_index(field),
+            Control::BackfillIndexBatch(field) => self.backfill_index_batch(field),
+            Control::ListIndexBackfills => self.list_index_backfills(),
+            Control::ListRollbackAudit(limit) => self.list_rollback_audit(limit),
+            Control::ListPurgeAudit(limit) => self.list_purge_audit(limit),
+            Control::ListEnabledFeatures => self.list_enabled_features(),
+            Control::ScheduleJob(definition) => self.schedule_job(definition),
+            Control::ListJobs => self.list_jobs(),
+            Control::CancelJob(name) => self.cancel_job(name),
+            Control::ListActiveRequests => self.list_active_requests(),
+            Control::KillRequest(request_id) => self.kill_request(request_id),
+            Control::EnterMaintenance { duration, task } => self.enter_maintenance(duration, task),
+            Control::PrepareTransaction {
+                global_id,
+                statements,
+            } => self.prepare_transaction(global_id, statements),
+            Control::CommitPrepared(global_id) => self.commit_prepared(global_id),
+            Control::AbortPrepared(global_id) => self.abort_prepared(global_id),
+            Control::ListPrepared => self.list_prepared(),
+            Control::AddCommittedListener(listener) => self.add_committed_listener(listener),
+            Control::DiffSnapshots { from, to, diff } => self.diff_snapshots(from, to, diff),
+            Control::StartOperation(control) => self.start_operation(*control),
+            Control::OperationStatus(id) => self.operation_status(id),
+            Control::WatchOperation(id, subscriber) => self.watch_operation(id, subscriber),
         }
     }
 
@@ -48,6 +143,82 @@ impl<'a> ControlContext<'a> {
             .expect("Requester should not be dropped");
     }
 
+    /// Acknowledges the caller with the operation before the control runs, the control's response is kept for
+    /// the caller to read, see `Operations`
+    pub fn start_operation(self, control: Control) -> DatabaseControlAction {
+        let kind: &'static str = (&control).into();
+
+        if !control.is_operation() {
+            self.send_response(DatabaseCommandResponse::control_error(&format!(
+                "{} can not run as an operation",
+                kind
+            )));
+
+            return DatabaseControlAction::Continue;
+        }
+
+        let ControlContext {
+            resolver,
+            thread_id,
+            database,
+            database_request_managers,
+            transaction_timestamp,
+        } = self;
+
+        let started = database.operations.start(kind);
+        let id = started.id;
+
+        let _ = resolver.send(DatabaseCommandResponse::control_operation(started));
+
+        let (operation_resolver, operation_response) = oneshot::channel();
+
+        let action = ControlContext {
+            resolver: operation_resolver,
+            thread_id,
+            database,
+            database_request_managers,
+            transaction_timestamp,
+        }
+        .run(control);
+
+        let response = match operation_response.recv() {
+            Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(response)) => Some(response),
+            _ => None,
+        };
+
+        log::info!(operation_id = id.0, thread_id; "Operation {} finished", kind);
+
+        database.operations.complete(id, response);
+
+        action
+    }
+
+    pub fn operation_status(self, id: OperationId) -> DatabaseControlAction {
+        let response = match self.database.operations.status(id) {
+            Some(status) => DatabaseCommandResponse::control_operation(status),
+            None => DatabaseCommandResponse::control_error(&format!("Unknown operation {}", id)),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn watch_operation(
+        self,
+        id: OperationId,
+        subscriber: flume::Sender<OperationStatus>,
+    ) -> DatabaseControlAction {
+        let response = match self.database.operations.subscribe(id, subscriber) {
+            true => DatabaseCommandResponse::control_success(&format!("Watching operation {}", id)),
+            false => DatabaseCommandResponse::control_error(&format!("Unknown operation {}", id)),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
     pub fn sleep(self, duration: Duration) -> DatabaseControlAction {
         thread::sleep(duration);
 
@@ -63,51 +234,9 @@ impl<'a> ControlContext<'a> {
     }
 
     pub fn database_stats(self) -> DatabaseControlAction {
-        let current_transaction_id = (
-            "CurrentTransactionID".to_string(),
-            self.transaction_timestamp.to_string(),
-        );
-
-        let wal_size = (
-            "WALSize".to_string(),
-            self.database
-                .persistence
-                .transaction_wal
-                .get_wal_size()
-                .to_string(),
-        );
-
-        let row_count = (
-            "RowCount".to_string(),
-            self.database.person_table.person_rows.len().to_string(),
-        );
-
-        let database_threads = (
-            "DatabaseThreads".to_string(),
-            self.database.database_options.threads.to_string(),
-        );
-
-        let database_thread_index = (
-            "DatabaseThreadIndex".to_string(),
-            self.thread_id.to_string(),
-        );
-
-        let engine = self
+        let info = self
             .database
-            .database_options
-            .storage_engine
-            .get_engine_info_stats();
-
-        let info = vec![
-            row_count,
-            wal_size,
-            current_transaction_id,
-            database_threads,
-            database_thread_index,
-        ]
-        .into_iter()
-        .chain(engine.into_iter())
-        .collect::<Vec<(String, String)>>();
+            .stats(Some(self.thread_id), &self.transaction_timestamp);
 
         self.send_response(DatabaseCommandResponse::control_info(info));
 
@@ -118,6 +247,12 @@ impl<'a> ControlContext<'a> {
         // The DB thread that received the shutdown request is responsible for ensuring all the other threads shutdown.
         let response = match request {
             ShutdownRequest::Coordinator => {
+                // Runs while the workers are still up, e.g. so the embedder can deregister from service discovery
+                self.database
+                    .database_options
+                    .hooks
+                    .run(LifecycleEvent::Shutdown);
+
                 // Send request to every DB thread, telling them to shutdown / stop working,
                 //  'send_shutdown_request' is a blocking call, so we will wait for all threads to shutdown
                 for rm in self.database_request_managers {
@@ -126,6 +261,13 @@ impl<'a> ControlContext<'a> {
                         .expect("Should respond to shutdown request");
                 }
 
+                // Transactions recorded since the last interval would otherwise be lost
+                if let Some(stats) = &self.database.statement_stats {
+                    if let Err(e) = stats.persist() {
+                        log::warn!("Unable to persist the statement statistics: {}", e);
+                    }
+                }
+
                 // Once we have successfully shutdown all threads, report success to the caller
                 DatabaseCommandResponse::control_success(&format!(
                     "[Thread: {}] Successfully shutdown database",
@@ -152,34 +294,68 @@ impl<'a> ControlContext<'a> {
             thread_id
         ));
 
+        // Set before responding, so requests are not routed to this thread once the pause is acknowledged
+        self.database
+            .availability
+            .set(thread_id, ThreadAvailability::Paused);
+
         self.send_response(response);
 
         // Blocking wait for `DatabasePauseEvent` to be dropped
         let _ = resume.recv();
 
-        log::info!("[Thread - {}] Successfully resumed thread", thread_id);
+        log::info!(thread_id; "Successfully resumed thread");
 
         DatabaseControlAction::Continue
     }
 
     /// Resets the filesystem and any in-memory state.
     ///
-    /// ⚠️ The caller is responsible for stopping the database or else
-    /// it may end up in an inconsistent state. If a reset happens
-    /// at the same time as a write it is possible that a part of the write is erased
+    /// Writes that run at the same time are ordered around the reset: the workers are paused, so a write is
+    /// either applied before the reset or waits for it to finish, and the commits that are still being written
+    /// to the WAL are waited for. A write is never partially erased, nor written to the WAL after it was reset
     pub fn reset(self) -> DatabaseControlAction {
         // Note, because we have paused the database we should not get ANY deadlocks
         //  concurrency issues
-        let database_pause = &DatabasePauseEvent::new(self.database_request_managers);
+        let database_pause = DatabasePauseEvent::new(
+            self.database_request_managers,
+            &self.database.pauses,
+            PauseOperation::Reset,
+        );
+
+        // A commit that is written after the WAL is reset would survive a restart without the rest of the state
+        self.database
+            .persistence
+            .transaction_wal
+            .wait_for_commits(&database_pause);
 
         let dropped_row_count = self.database.person_table.person_rows.len();
 
+        // Nothing has been deleted yet, if the intent cannot be written the reset is abandoned
+        let intent = match self.database.persistence.snapshot_manager.begin_intent(
+            IntentOperation::Reset,
+            self.transaction_timestamp.clone(),
+            self.database.database_options.clock.now_ms(),
+        ) {
+            Ok(intent) => intent,
+            Err(e) => {
+                drop(database_pause);
+
+                self.send_response(DatabaseCommandResponse::control_error(&format!(
+                    "Unable to record the reset intent, nothing was reset: {}",
+                    e
+                )));
+
+                return DatabaseControlAction::Continue;
+            }
+        };
+
         // Resets tx id, scrubs wal
         let flush_transactions_from_disk_result = self
             .database
             .persistence
             .transaction_wal
-            .flush_transactions(database_pause);
+            .flush_transactions(&database_pause);
 
         if let Err(e) = flush_transactions_from_disk_result {
             crash_database(DatabaseCrash::InconsistentStorageFromReset(e));
@@ -191,6 +367,12 @@ impl<'a> ControlContext<'a> {
             .transaction_wal
             .set_current_transaction_id(TransactionId::new_first_transaction());
 
+        // Before the publisher cursor is removed, transaction ids start over
+        #[cfg(feature = "publisher")]
+        if let Some(publisher) = &self.database.publisher {
+            publisher.reset();
+        }
+
         // Clean out snapshot and transaction log
         let result = self.database.persistence.reset();
 
@@ -199,50 +381,232 @@ impl<'a> ControlContext<'a> {
         }
 
         // Resets the in-memory persons table
-        self.database.person_table.reset(database_pause);
+        self.database.person_table.reset(&database_pause);
+
+        // Jobs are persisted in the metadata which has been cleaned out
+        self.database.scheduler.reset();
+
+        // Clones would otherwise hold rows that no longer exist
+        self.database.clones.reset();
+        self.database.prepared.reset();
+        self.database.quotas.reset();
+
+        if let Some(audit) = &self.database.rollback_audit {
+            audit.reset();
+        }
+
+        self.database.purge_audit.reset();
+
+        if let Some(stats) = &self.database.statement_stats {
+            stats.reset();
+        }
+
+        if let Some(store) = self.database.person_table.attachment_store() {
+            store.reset();
+        }
+
+        // The metadata has been cleaned out, the WAL is still written with the enabled features
+        if let Err(e) = self.database.persistence.snapshot_manager.record_features() {
+            crash_database(DatabaseCrash::InconsistentStorageFromReset(e));
+        }
+
+        if let Err(e) = self
+            .database
+            .persistence
+            .snapshot_manager
+            .complete_intent(intent)
+        {
+            crash_database(DatabaseCrash::InconsistentStorageFromReset(e));
+        }
+
+        // Storage is empty again, an operation interrupted before the database started no longer matters
+        *self.database.interrupted.lock().unwrap() = None;
+
+        // Hooks run once the other threads have resumed
+        drop(database_pause);
 
         let response = DatabaseCommandResponse::control_success(&format!(
             "Successfully reset database, dropped: {} rows",
             dropped_row_count
         ));
 
+        let database = self.database;
+
         self.send_response(response);
 
+        database.database_options.hooks.run(LifecycleEvent::Reset {
+            dropped_rows: dropped_row_count,
+        });
+
         DatabaseControlAction::Continue
     }
 
-    pub fn snapshot(self) -> DatabaseControlAction {
-        // Note, because we have paused the database we should not get ANY deadlocks
-        //  concurrency issues
-        let database_reset_guard = &DatabasePauseEvent::new(&self.database_request_managers);
+    /// Commits a transaction that removes every person that is live at the control's transaction, the caller
+    /// receives the results like for any other transaction. The database is not paused, if a concurrent
+    /// transaction removes one of the people first the truncate rolls back and can be retried
+    pub fn truncate_table(self) -> DatabaseControlAction {
+        let database = self.database;
+        let transaction_id = self.transaction_timestamp.clone();
+        let resolver = self.resolver;
 
-        let table = &self.database.person_table;
+        let statements: Vec<Statement> = query(&database.person_table, &transaction_id)
+            .into_iter()
+            .map(|person| Statement::Remove(person.id))
+            .collect();
 
-        // Persist current state to disk
-        let snapshot_request = self.database.persistence.snapshot_manager.create_snapshot(
-            database_reset_guard,
-            table,
-            self.transaction_timestamp.clone(),
-        );
+        // Rows held by a prepared two-phase commit transaction cannot be mutated until it is resolved
+        let locks = database.prepared.locks();
 
-        if let Err(e) = snapshot_request {
-            let _ = self
-                .resolver
-                .send(DatabaseCommandResponse::control_error(&format!(
-                    "Failed to create snapshot database is now inconsistent: {}",
-                    e
-                )));
+        match locks.check(&statements) {
+            Ok(()) => {
+                database.apply_transaction_as(
+                    transaction_id,
+                    statements,
+                    TransactionStatus::Committed,
+                    ApplyMode::Request(resolver),
+                    &FieldMask::default(),
+                    // Removing people cannot violate a constraint
+                    None,
+                );
+            }
+            Err(message) => {
+                let _ = resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                    DatabaseCommandTransactionResponse::Rollback(message),
+                ));
+            }
+        }
+
+        DatabaseControlAction::Continue
+    }
+
+    /// The ids are those that are live when the control starts. A chunk that rolls back (e.g. an id is already
+    /// taken or a row was removed since) stops the rename, running it again renames the remaining ids
+    pub fn rename_id_prefix(
+        self,
+        from: String,
+        to: String,
+        chunk_size: Option<usize>,
+    ) -> DatabaseControlAction {
+        if from.is_empty() || from == to {
+            self.send_response(DatabaseCommandResponse::control_error(
+                "The prefix to rename must not be empty or the same as the new prefix",
+            ));
+
+            return DatabaseControlAction::Continue;
+        }
+
+        // Without chunks the ids cannot change until every rename has been applied
+        let database_pause = chunk_size.is_none().then(|| {
+            DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::RenameIdPrefix,
+            )
+        });
+
+        let database = self.database;
+
+        let renames: Vec<Statement> = query(&database.person_table, &self.transaction_timestamp)
+            .into_iter()
+            .filter(|person| person.id.0.starts_with(&from))
+            .map(|person| {
+                let renamed = EntityId(format!("{}{}", to, &person.id.0[from.len()..]));
+
+                Statement::Rename(person.id, renamed)
+            })
+            .collect();
+
+        let total = renames.len();
+        let mut renamed = 0;
+        let mut transaction_id = self.transaction_timestamp.clone();
+        let mut transactions = vec![];
+        let mut failure = None;
+
+        for chunk in renames.chunks(chunk_size.unwrap_or(total).max(1)) {
+            if !transactions.is_empty() {
+                transaction_id = database
+                    .persistence
+                    .transaction_wal
+                    .get_increment_current_transaction_id();
+            }
+
+            match database.commit_control_transaction(transaction_id.clone(), chunk.to_vec()) {
+                Ok(()) => {
+                    renamed += chunk.len();
+                    transactions.push(transaction_id.clone());
+
+                    log::info!(
+                        transaction_id = transaction_id.to_number();
+                        "Renamed {}/{} ids from {} to {}",
+                        renamed,
+                        total,
+                        from,
+                        to
+                    );
+                }
+                Err(message) => {
+                    failure = Some(message);
+                    break;
+                }
+            }
+        }
+
+        drop(database_pause);
+
+        let response = match failure {
+            None => DatabaseCommandResponse::control_info(vec![
+                ("Renamed".to_string(), renamed.to_string()),
+                ("Transactions".to_string(), transactions.len().to_string()),
+                (
+                    "LastTransactionId".to_string(),
+                    transactions
+                        .last()
+                        .map(|id| id.to_string())
+                        .unwrap_or("None".to_string()),
+                ),
+            ]),
+            Some(message) => DatabaseCommandResponse::control_error(&format!(
+                "Renamed {} of {} ids in {} transactions, the next chunk rolled back: {}",
+                renamed,
+                total,
+                transactions.len(),
+                message
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn snapshot(mut self) -> DatabaseControlAction {
+        // The snapshot's WAL flush would replace the intent of the interrupted operation
+        if let Some(intent) = self.database.interrupted_operation() {
+            self.send_response(DatabaseCommandResponse::control_error(&format!(
+                "Snapshots are refused until the database is reset: {}",
+                intent
+            )));
 
-            crash_database(DatabaseCrash::InconsistentStorageFromSnapshot(e));
+            return DatabaseControlAction::Continue;
         }
 
-        let flush_transactions = self
+        // Note, because we have paused the database we should not get ANY deadlocks
+        //  concurrency issues
+        let database_reset_guard = DatabasePauseEvent::new(
+            self.database_request_managers,
+            &self.database.pauses,
+            PauseOperation::Snapshot,
+        );
+
+        // Transactions that drew a later id than this request can commit before the other threads pause, the
+        //  snapshot is taken at an id drawn once they have paused so it covers everything the WAL flush drops
+        self.transaction_timestamp = self
             .database
             .persistence
             .transaction_wal
-            .flush_transactions(database_reset_guard);
+            .get_increment_current_transaction_id();
 
-        let flush_transactions_count = match flush_transactions {
+        let flush_transactions_count = match self.persist_snapshot(&database_reset_guard) {
             Ok(t) => t,
             Err(e) => {
                 let _ = self
@@ -256,11 +620,1290 @@ impl<'a> ControlContext<'a> {
             }
         };
 
+        // Hooks run once the other threads have resumed
+        drop(database_reset_guard);
+
         let response = DatabaseCommandResponse::control_success(&format!(
             "Successfully created snapshot: compressed {} txs",
             flush_transactions_count
         ));
 
+        let (database, transaction_id) = (self.database, self.transaction_timestamp.clone());
+
+        self.send_response(response);
+
+        database
+            .database_options
+            .hooks
+            .run(LifecycleEvent::Snapshot {
+                transaction_id,
+                flushed_transactions: flush_transactions_count,
+            });
+
+        DatabaseControlAction::Continue
+    }
+
+    /// Unlike a snapshot the other threads keep running, transactions committed while the WAL is rewritten
+    /// wait for the rewrite before they are written
+    pub fn compact_wal(self) -> DatabaseControlAction {
+        let persistence = &self.database.persistence;
+
+        let result = persistence
+            .snapshot_manager
+            .snapshot_transaction_id()
+            .and_then(|snapshot_transaction_id| match snapshot_transaction_id {
+                Some(snapshot_transaction_id) => persistence
+                    .transaction_wal
+                    .compact(&snapshot_transaction_id)
+                    .map(|dropped| (dropped, Some(snapshot_transaction_id))),
+                None => Ok((0, None)),
+            });
+
+        let response = match result {
+            Ok((dropped, Some(snapshot_transaction_id))) => {
+                DatabaseCommandResponse::control_success(&format!(
+                    "Successfully compacted WAL: dropped {} txs covered by the snapshot at transaction {}",
+                    dropped, snapshot_transaction_id
+                ))
+            }
+            Ok((_, None)) => DatabaseCommandResponse::control_success(
+                "There is no snapshot to compact the WAL against",
+            ),
+            // The log is swapped in with a rename, if compaction fails the previous log is left as is
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Failed to compact WAL: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    /// The other threads keep running, their writes wait on the storage lock while the engines are switched
+    pub fn cutover_migration(self) -> DatabaseControlAction {
+        let response = match self.database.persistence.cutover_migration() {
+            Ok(target) => {
+                log::info!(
+                    "Migration: cut over to the {} engine, restart the database with it as its storage engine",
+                    target
+                );
+
+                DatabaseCommandResponse::control_success(&format!(
+                    "Successfully cut over to the {} engine",
+                    target
+                ))
+            }
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Failed to cut over storage engines: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    /// The prepare record is written to the WAL, the response is sent once it is durable
+    pub fn prepare_transaction(
+        self,
+        global_id: String,
+        statements: Vec<Statement>,
+    ) -> DatabaseControlAction {
+        let database = self.database;
+        let transaction_id = self.transaction_timestamp.clone();
+
+        let transaction = PreparedTransaction {
+            global_id: global_id.clone(),
+            transaction_id: transaction_id.clone(),
+            statements: statements.clone(),
+        };
+
+        let prepared = database.prepared.prepare(transaction, |statements| {
+            database.validate_transaction(&transaction_id, statements)
+        });
+
+        match prepared {
+            Ok(()) => database.persistence.transaction_wal.write(
+                transaction_id,
+                statements,
+                TransactionStatus::Prepared(global_id.clone()),
+                DatabaseCommandResponse::control_success(&format!(
+                    "Successfully prepared transaction {}",
+                    global_id
+                )),
+                ApplyMode::Request(self.resolver),
+            ),
+            Err(e) => self.send_response(DatabaseCommandResponse::control_error(&format!(
+                "Failed to prepare transaction {}: {}",
+                global_id, e
+            ))),
+        }
+
+        DatabaseControlAction::Continue
+    }
+
+    /// The statements are applied while the rows are still locked, the caller receives the transaction response
+    /// once the commit record is durable
+    pub fn commit_prepared(self, global_id: String) -> DatabaseControlAction {
+        let database = self.database;
+        let transaction_id = self.transaction_timestamp.clone();
+        let resolver = self.resolver;
+
+        database
+            .prepared
+            .resolve(&global_id, |transaction| match transaction {
+                Some(transaction) => {
+                    let response = database.apply_transaction_as(
+                        transaction_id,
+                        transaction.statements,
+                        TransactionStatus::CommitPrepared(global_id.clone()),
+                        ApplyMode::Request(resolver),
+                        &FieldMask::default(),
+                        // The constraints were checked when the transaction was prepared
+                        None,
+                    );
+
+                    // The rows were locked since the prepare, the statements should always apply
+                    if let DatabaseCommandTransactionResponse::Rollback(message) = response {
+                        log::error!(
+                            "Prepared transaction {} failed to commit: {}",
+                            global_id,
+                            message
+                        );
+                    }
+                }
+                None => {
+                    let _ = resolver.send(DatabaseCommandResponse::control_error(&format!(
+                        "Transaction {} is not prepared",
+                        global_id
+                    )));
+                }
+            });
+
+        DatabaseControlAction::Continue
+    }
+    /// Without a durable abort record the transaction is in-doubt again after a restart, the coordinator aborts it
+    /// again as it has no commit decision for it
+    pub fn abort_prepared(self, global_id: String) -> DatabaseControlAction {
+        let database = self.database;
+        let transaction_id = self.transaction_timestamp.clone();
+        let resolver = self.resolver;
+
+        database
+            .prepared
+            .resolve(&global_id, |transaction| match transaction {
+                Some(_) => database.persistence.transaction_wal.write(
+                    transaction_id,
+                    vec![],
+                    TransactionStatus::AbortPrepared(global_id.clone()),
+                    DatabaseCommandResponse::control_success(&format!(
+                        "Successfully aborted transaction {}",
+                        global_id
+                    )),
+                    ApplyMode::Request(resolver),
+                ),
+                None => {
+                    let _ = resolver.send(DatabaseCommandResponse::control_error(&format!(
+                        "Transaction {} is not prepared",
+                        global_id
+                    )));
+                }
+            });
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_prepared(self) -> DatabaseControlAction {
+        let prepared = self
+            .database
+            .prepared
+            .list()
+            .into_iter()
+            .map(|transaction| {
+                let rows = transaction
+                    .statements
+                    .iter()
+                    .flat_map(|statement| statement.mutated_ids())
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                (transaction.global_id, rows)
+            })
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(prepared));
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn add_committed_listener(
+        self,
+        listener: flume::Sender<Transaction>,
+    ) -> DatabaseControlAction {
+        self.database
+            .persistence
+            .transaction_wal
+            .add_committed_listener(listener);
+
+        self.send_response(DatabaseCommandResponse::control_success(
+            "Added committed listener",
+        ));
+
+        DatabaseControlAction::Continue
+    }
+
+    /// Writes the current state of the table to storage and flushes the WAL, returns the number of flushed transactions
+    fn persist_snapshot(&self, database_pause: &DatabasePauseEvent) -> StorageResult<usize> {
+        self.database.persistence.snapshot_manager.create_snapshot(
+            database_pause,
+            &self.database.person_table,
+            self.transaction_timestamp.clone(),
+            self.database.prepared.list(),
+        )?;
+
+        let snapshot_manager = &self.database.persistence.snapshot_manager;
+
+        let intent = snapshot_manager.begin_intent(
+            IntentOperation::WalFlush,
+            self.transaction_timestamp.clone(),
+            self.database.database_options.clock.now_ms(),
+        )?;
+
+        let flushed = self
+            .database
+            .persistence
+            .transaction_wal
+            .flush_transactions(database_pause)?;
+
+        snapshot_manager.complete_intent(intent)?;
+
+        Ok(flushed)
+    }
+
+    /// Queues incoming transactions while the maintenance task runs, the task runs once every in-flight request
+    /// has finished. Queued transactions are replayed on the worker threads afterwards
+    ///
+    /// Note: the database is paused while the task runs, requests that arrive during the task wait in the worker
+    /// channels rather than the bounded maintenance queue
+    pub fn enter_maintenance(
+        self,
+        duration: Duration,
+        task: Option<MaintenanceTask>,
+    ) -> DatabaseControlAction {
+        if !self.database.maintenance.begin() {
+            self.send_response(DatabaseCommandResponse::control_error(
+                "Database is already in maintenance mode",
+            ));
+
+            return DatabaseControlAction::Continue;
+        }
+
+        let started = Instant::now();
+
+        log::info!(
+            thread_id = self.thread_id;
+            "Entered maintenance mode for up to {}ms",
+            duration.as_millis()
+        );
+
+        let task_result = match task {
+            Some(MaintenanceTask::Snapshot) if self.database.interrupted_operation().is_some() => {
+                "skipped snapshot, snapshots are refused until the database is reset".to_string()
+            }
+            Some(MaintenanceTask::Snapshot) => {
+                // Pausing waits for the request each worker thread is running to finish
+                let database_pause = &DatabasePauseEvent::new(
+                    self.database_request_managers,
+                    &self.database.pauses,
+                    PauseOperation::MaintenanceSnapshot,
+                );
+
+                match self.persist_snapshot(database_pause) {
+                    Ok(flushed) => format!("created snapshot, compressed {} txs", flushed),
+                    Err(e) => {
+                        let _ =
+                            self.resolver
+                                .send(DatabaseCommandResponse::control_error(&format!(
+                                    "Failed to create snapshot database is now inconsistent: {}",
+                                    e
+                                )));
+
+                        crash_database(DatabaseCrash::InconsistentStorageFromSnapshot(e));
+                    }
+                }
+            }
+            None => {
+                thread::sleep(duration);
+                "held maintenance window".to_string()
+            }
+        };
+
+        let elapsed = started.elapsed();
+
+        if elapsed > duration {
+            log::warn!(
+                thread_id = self.thread_id;
+                "Maintenance took {}ms, exceeding the requested window of {}ms",
+                elapsed.as_millis(),
+                duration.as_millis()
+            );
+        }
+
+        let backlog = self.database.maintenance.end();
+        let replayed = backlog.requests.len();
+
+        // The current thread does not have a request manager for itself, replay on the other worker threads
+        for (request, request_manager) in backlog
+            .requests
+            .into_iter()
+            .zip(self.database_request_managers.iter().cycle())
+        {
+            request_manager.forward(request);
+        }
+
+        let response = DatabaseCommandResponse::control_success(&format!(
+            "Maintenance finished in {}ms: {}, replayed {} queued requests, rejected {}",
+            elapsed.as_millis(),
+            task_result,
+            replayed,
+            backlog.rejected
+        ));
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    /// Validates the latest snapshot without restoring it into the live table, discrepancies
+    /// are reported back to the caller as info rather than crashing the database
+    pub fn verify_snapshot(self, shadow_table: bool) -> DatabaseControlAction {
+        // Pausing ensures a snapshot is not being written while we are reading it
+        let database_pause = &DatabasePauseEvent::new(
+            self.database_request_managers,
+            &self.database.pauses,
+            PauseOperation::VerifySnapshot,
+        );
+
+        let live_table = match shadow_table {
+            true => Some(&self.database.person_table),
+            false => None,
+        };
+
+        let verification = self
+            .database
+            .persistence
+            .snapshot_manager
+            .verify_snapshot(database_pause, live_table);
+
+        let response = match verification {
+            Ok(v) => DatabaseCommandResponse::control_info(v.to_info()),
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Failed to read snapshot for verification: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn export(self, recipients: Vec<String>) -> DatabaseControlAction {
+        let versions = {
+            // Pausing ensures the export is a consistent view of the table, the pause is released before
+            //  encrypting so that other threads are not blocked on it
+            let _database_pause = DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::Export,
+            );
+
+            self.database
+                .person_table
+                .query_statement(Statement::ListLatestVersions, &self.transaction_timestamp)
+                .expect("Should always be able to list latest versions")
+                .list_version()
+        };
+
+        let response = match encrypt_export(&versions, &recipients) {
+            Ok(export) => DatabaseCommandResponse::control_success(&export),
+            Err(e) => DatabaseCommandResponse::control_error(&e.to_string()),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn download_snapshot(self) -> DatabaseControlAction {
+        let archive = {
+            // Pausing ensures no transaction is appended to the WAL while it is read, so the tail matches the
+            //  snapshot. The pause is released before encoding the archive
+            let _database_pause = DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::SnapshotDownload,
+            );
+
+            let storage = self.database.persistence.get_storage();
+            let mut storage = storage.lock().unwrap();
+
+            SnapshotArchive::read(&mut *storage)
+        };
+
+        let response = match archive {
+            Ok(archive) => DatabaseCommandResponse::control_success(&archive.to_ndjson()),
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Unable to download the snapshot: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn diff_snapshots(
+        self,
+        from: DiffSource,
+        to: DiffSource,
+        diff: flume::Sender<SnapshotDiff>,
+    ) -> DatabaseControlAction {
+        let sides = {
+            // Pausing ensures a snapshot is not pruned while it is read, and that both sides are read at the same
+            //  transaction if they are both live
+            let _database_pause = DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::SnapshotDiff,
+            );
+
+            self.read_diff_source(&from)
+                .and_then(|before| Ok((before, self.read_diff_source(&to)?)))
+        };
+
+        let response = match sides {
+            Ok((before, after)) => {
+                let status = format!(
+                    "Diffing {} versions against {} versions",
+                    before.len(),
+                    after.len()
+                );
+
+                let _ = diff.send(SnapshotDiff::new(before, after));
+
+                DatabaseCommandResponse::control_success(&status)
+            }
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Unable to diff the snapshots: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    fn read_diff_source(&self, source: &DiffSource) -> Result<Vec<PersonVersion>, String> {
+        match source {
+            DiffSource::Live => Ok(self
+                .database
+                .person_table
+                .query_statement(Statement::ListLatestVersions, &self.transaction_timestamp)
+                .expect("Should always be able to list latest versions")
+                .list_version()),
+            DiffSource::Snapshot(key) => {
+                match self
+                    .database
+                    .persistence
+                    .snapshot_manager
+                    .read_snapshot(key)
+                {
+                    Ok(Some(versions)) => Ok(versions),
+                    Ok(None) => Err(format!("Snapshot {} does not exist", key)),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        }
+    }
+
+    pub fn write_attachment(self, bytes: Vec<u8>) -> DatabaseControlAction {
+        let response = match self.database.person_table.attachment_store() {
+            Some(store) => match store.write(bytes) {
+                Ok(digest) => DatabaseCommandResponse::control_success(&digest),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Unable to write the attachment: {}",
+                    e
+                )),
+            },
+            None => DatabaseCommandResponse::control_error("Attachments are not available"),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn vacuum_attachments(self, min_age: Duration) -> DatabaseControlAction {
+        let Some(store) = self.database.person_table.attachment_store() else {
+            self.send_response(DatabaseCommandResponse::control_error(
+                "Attachments are not available",
+            ));
+
+            return DatabaseControlAction::Continue;
+        };
+
+        let report = {
+            // Pausing ensures no version is added or rolled back while the references are collected
+            let _database_pause = DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::AttachmentVacuum,
+            );
+
+            let referenced = self
+                .database
+                .person_table
+                .person_rows
+                .iter()
+                .flat_map(|row| row.value().read().unwrap().attachment_digests())
+                .collect();
+
+            store.vacuum(&referenced, min_age)
+        };
+
+        let response = match report {
+            Ok(report) => DatabaseCommandResponse::control_info(vec![
+                ("Referenced".to_string(), report.referenced.to_string()),
+                ("Deleted".to_string(), report.deleted.to_string()),
+                ("DeletedBytes".to_string(), report.deleted_bytes.to_string()),
+                ("Retained".to_string(), report.retained.to_string()),
+            ]),
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Unable to vacuum the attachments: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn squash_history(self) -> DatabaseControlAction {
+        let Some(squash) = self.database.person_table.history_squash() else {
+            self.send_response(DatabaseCommandResponse::control_error(
+                "History squashing is not configured",
+            ));
+
+            return DatabaseControlAction::Continue;
+        };
+
+        let report = {
+            let database_pause = DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::HistorySquash,
+            );
+
+            // Transactions that drew a later id than this request can commit before the other threads pause
+            let committed_transaction_id = self
+                .database
+                .persistence
+                .transaction_wal
+                .get_increment_current_transaction_id();
+
+            self.database.person_table.squash_history(
+                &database_pause,
+                squash,
+                &committed_transaction_id,
+            )
+        };
+
+        let response = DatabaseCommandResponse::control_info(vec![
+            ("Rows".to_string(), report.rows.to_string()),
+            (
+                "SquashedVersions".to_string(),
+                report.squashed_versions.to_string(),
+            ),
+        ]);
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn create_view(self, definition: ViewDefinition) -> DatabaseControlAction {
+        // Pausing ensures no transaction commits between populating the view and it being
+        //  registered, otherwise the view would miss the transaction
+        let _database_pause = DatabasePauseEvent::new(
+            self.database_request_managers,
+            &self.database.pauses,
+            PauseOperation::CreateView,
+        );
+
+        let name = definition.name.clone();
+        let table = &self.database.person_table;
+
+        let people = query(table, &self.transaction_timestamp);
+
+        table
+            .views
+            .create(definition, people, &self.transaction_timestamp);
+
+        let response = match self.save_view_definitions() {
+            Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                "Successfully created view: {}",
+                name
+            )),
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Created view {}, but failed to persist its definition: {}",
+                name, e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn drop_view(self, name: String) -> DatabaseControlAction {
+        let response = match self.database.person_table.views.drop_view(&name) {
+            true => match self.save_view_definitions() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Successfully dropped view: {}",
+                    name
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Dropped view {}, but failed to persist the change: {}",
+                    name, e
+                )),
+            },
+            false => {
+                DatabaseCommandResponse::control_error(&format!("View does not exist: {}", name))
+            }
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    /// The clone is copied without pausing the database, transactions committed while it is copied are not
+    /// visible to it as it only reads versions up to the transaction
+    pub fn clone_at_transaction(
+        self,
+        name: String,
+        transaction_id: TransactionId,
+    ) -> DatabaseControlAction {
+        if transaction_id > self.transaction_timestamp {
+            let response = DatabaseCommandResponse::control_error(&format!(
+                "Cannot clone at transaction {}, the current transaction is {}",
+                transaction_id, self.transaction_timestamp
+            ));
+
+            self.send_response(response);
+
+            return DatabaseControlAction::Continue;
+        }
+
+        let clone = TableClone::new(&self.database.person_table, transaction_id);
+
+        let response = DatabaseCommandResponse::control_success(&format!(
+            "Successfully created clone: {} at transaction {} with {} rows",
+            name,
+            clone.transaction_id,
+            clone.row_count()
+        ));
+
+        self.database.clones.insert(name, clone);
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn drop_clone(self, name: String) -> DatabaseControlAction {
+        let response = match self.database.clones.drop_clone(&name) {
+            true => DatabaseCommandResponse::control_success(&format!(
+                "Successfully dropped clone: {}",
+                name
+            )),
+            false => {
+                DatabaseCommandResponse::control_error(&format!("Clone does not exist: {}", name))
+            }
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_clones(self) -> DatabaseControlAction {
+        let clones = self
+            .database
+            .clones
+            .list()
+            .into_iter()
+            .map(|(name, transaction_id, row_count)| {
+                (
+                    name,
+                    format!("transaction {}: {} rows", transaction_id, row_count),
+                )
+            })
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(clones));
+
+        DatabaseControlAction::Continue
+    }
+
+    fn save_view_definitions(&self) -> StorageResult<()> {
+        self.database
+            .persistence
+            .snapshot_manager
+            .save_view_definitions(self.database.person_table.views.definitions())
+    }
+
+    pub fn create_policy(self, policy: RowPolicy) -> DatabaseControlAction {
+        let name = policy.name.clone();
+
+        self.database.person_table.policies.create(policy);
+
+        let response = match self.save_policies() {
+            Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                "Successfully created policy: {}",
+                name
+            )),
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Created policy {}, but failed to persist it: {}",
+                name, e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn drop_policy(self, name: String) -> DatabaseControlAction {
+        let response = match self.database.person_table.policies.drop_policy(&name) {
+            true => match self.save_policies() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Successfully dropped policy: {}",
+                    name
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Dropped policy {}, but failed to persist the change: {}",
+                    name, e
+                )),
+            },
+            false => {
+                DatabaseCommandResponse::control_error(&format!("Policy does not exist: {}", name))
+            }
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_policies(self) -> DatabaseControlAction {
+        let policies = self
+            .database
+            .person_table
+            .policies
+            .definitions()
+            .into_iter()
+            .map(|policy| {
+                (
+                    policy.name,
+                    format!("role '{}': {:?}", policy.role, policy.predicate),
+                )
+            })
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(policies));
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn explain_query(self, query: Option<QueryPersonData>) -> DatabaseControlAction {
+        let person_table = &self.database.person_table;
+
+        let explain = person_table.plan(&query).explain(&person_table.indexes);
+
+        self.send_response(DatabaseCommandResponse::control_info(explain));
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn index_advisor(self) -> DatabaseControlAction {
+        let person_table = &self.database.person_table;
+
+        let advice = person_table.advisor.advise(&person_table.indexes);
+
+        #[cfg(feature = "index-advisor")]
+        let created = self.create_recommended_indexes(&advice);
+        #[cfg(not(feature = "index-advisor"))]
+        let created = None;
+
+        let info = advice
+            .iter()
+            .map(IndexAdvice::to_info)
+            .chain(created)
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(info));
+
+        DatabaseControlAction::Continue
+    }
+
+    /// Creates the recommended indexes if `DatabaseOptions::set_auto_create_indexes` is set, the created indexes
+    /// are returned as KV information
+    #[cfg(feature = "index-advisor")]
+    fn create_recommended_indexes(&self, advice: &[IndexAdvice]) -> Option<(String, String)> {
+        if !self.database.database_options.auto_create_indexes {
+            return None;
+        }
+
+        let recommended: Vec<IndexedField> = advice
+            .iter()
+            .filter(|advice| advice.recommendation == Recommendation::Create)
+            .map(|advice| advice.field)
+            .collect();
+
+        if recommended.is_empty() {
+            return None;
+        }
+
+        let database_pause = DatabasePauseEvent::new(
+            self.database_request_managers,
+            &self.database.pauses,
+            PauseOperation::CreateIndex,
+        );
+
+        for field in &recommended {
+            self.database
+                .person_table
+                .create_index(*field, &database_pause);
+        }
+
+        if let Err(e) = self.save_indexes() {
+            log::warn!("Unable to persist the created indexes: {}", e);
+        }
+
+        let created: Vec<&str> = recommended.iter().map(IndexedField::name).collect();
+
+        log::info!("Index advisor created indexes on {}", created.join(", "));
+
+        Some(("CreatedIndexes".to_string(), created.join(", ")))
+    }
+
+    pub fn create_index(self, field: IndexedField) -> DatabaseControlAction {
+        let created = {
+            // Pausing ensures no row is purged while its versions are added to the index
+            let database_pause = DatabasePauseEvent::new(
+                self.database_request_managers,
+                &self.database.pauses,
+                PauseOperation::CreateIndex,
+            );
+
+            self.database
+                .person_table
+                .create_index(field, &database_pause)
+        };
+
+        let response = match created {
+            true => match self.save_indexes() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Created an index on {}",
+                    field
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Created an index on {}, but failed to persist it: {}",
+                    field, e
+                )),
+            },
+            false => {
+                DatabaseCommandResponse::control_error(&format!("{} is already indexed", field))
+            }
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    /// Unlike `create_index` the database is not paused, new versions are added to the index straight away and
+    /// the existing rows are added in batches by the backfill's thread. The planner uses the index once the
+    /// backfill has completed
+    pub fn backfill_index(self, field: IndexedField) -> DatabaseControlAction {
+        let table = &self.database.person_table;
+
+        let response = match table.indexes.start_building(&field) {
+            true => {
+                self.database.index_backfills.begin(
+                    field,
+                    table.person_rows.len(),
+                    self.database.database_options.index_backfill.clone(),
+                );
+
+                match self.save_indexes() {
+                    Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                        "Backfilling an index on {}",
+                        field
+                    )),
+                    Err(e) => DatabaseCommandResponse::control_error(&format!(
+                        "Backfilling an index on {}, but failed to persist it: {}",
+                        field, e
+                    )),
+                }
+            }
+            false => {
+                DatabaseCommandResponse::control_error(&format!("{} is already indexed", field))
+            }
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn backfill_index_batch(self, field: IndexedField) -> DatabaseControlAction {
+        let backfills = &self.database.index_backfills;
+        let cursor = backfills.cursor(&field);
+
+        let (rows, cursor) = self.database.person_table.backfill_index(
+            &field,
+            cursor.as_ref(),
+            self.database.database_options.index_backfill.batch_size,
+        );
+
+        backfills.advance(&field, rows, cursor);
+
+        self.send_response(DatabaseCommandResponse::control_success(&format!(
+            "Added {} rows to the index on {}",
+            rows, field
+        )));
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_index_backfills(self) -> DatabaseControlAction {
+        let info = self
+            .database
+            .index_backfills
+            .list()
+            .iter()
+            .map(IndexBackfillProgress::to_info)
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(info));
+
+        DatabaseControlAction::Continue
+    }
+
+    fn save_indexes(&self) -> StorageResult<()> {
+        self.database
+            .persistence
+            .snapshot_manager
+            .save_indexes(self.database.person_table.indexes.created())
+    }
+
+    pub fn list_rollback_audit(self, limit: usize) -> DatabaseControlAction {
+        let response = match &self.database.rollback_audit {
+            Some(audit) => match audit.load(limit) {
+                Ok(records) => DatabaseCommandResponse::control_info(
+                    records
+                        .into_iter()
+                        .map(|record| (record.transaction_id.to_string(), record.to_string()))
+                        .collect(),
+                ),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Unable to read the rollback audit: {}",
+                    e
+                )),
+            },
+            None => DatabaseCommandResponse::control_error(
+                "The rollback audit is disabled, see `DatabaseOptions::set_rollback_audit`",
+            ),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_purge_audit(self, limit: usize) -> DatabaseControlAction {
+        let response = match self.database.purge_audit.load(limit) {
+            Ok(records) => DatabaseCommandResponse::control_info(
+                records
+                    .into_iter()
+                    .map(|record| (record.transaction_id.to_string(), record.to_string()))
+                    .collect(),
+            ),
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Unable to read the purge audit: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_enabled_features(self) -> DatabaseControlAction {
+        let snapshot_manager = &self.database.persistence.snapshot_manager;
+
+        let response = match snapshot_manager.recorded_features() {
+            Ok(recorded) => {
+                let enabled = snapshot_manager.enabled_features();
+
+                let names: BTreeSet<String> = StorageFeature::SUPPORTED
+                    .iter()
+                    .map(|feature| feature.as_str().to_string())
+                    .chain(recorded.iter().cloned())
+                    .collect();
+
+                DatabaseCommandResponse::control_info(
+                    names
+                        .into_iter()
+                        .map(|name| {
+                            let status = format!(
+                                "recorded: {}, enabled: {}, supported: {}",
+                                recorded.contains(&name),
+                                enabled.contains(&name),
+                                StorageFeature::from_name(&name).is_some()
+                            );
+
+                            (name, status)
+                        })
+                        .collect(),
+                )
+            }
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Unable to read the recorded storage features: {}",
+                e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    fn save_policies(&self) -> StorageResult<()> {
+        self.database
+            .persistence
+            .snapshot_manager
+            .save_policies(self.database.person_table.policies.definitions())
+    }
+
+    /// Prepared queries are validated once when they are registered, no pause is needed as they do not hold
+    /// any rows
+    pub fn create_prepared_query(
+        self,
+        definition: PreparedQueryDefinition,
+    ) -> DatabaseControlAction {
+        let name = definition.name.clone();
+
+        let response = match self
+            .database
+            .person_table
+            .prepared_queries
+            .create(definition)
+        {
+            Ok(()) => match self.save_prepared_queries() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Successfully created prepared query: {}",
+                    name
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Created prepared query {}, but failed to persist it: {}",
+                    name, e
+                )),
+            },
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Invalid prepared query {}: {}",
+                name, e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn drop_prepared_query(self, name: String) -> DatabaseControlAction {
+        let response = match self
+            .database
+            .person_table
+            .prepared_queries
+            .drop_query(&name)
+        {
+            true => match self.save_prepared_queries() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Successfully dropped prepared query: {}",
+                    name
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Dropped prepared query {}, but failed to persist the change: {}",
+                    name, e
+                )),
+            },
+            false => DatabaseCommandResponse::control_error(&format!(
+                "Prepared query does not exist: {}",
+                name
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_prepared_queries(self) -> DatabaseControlAction {
+        let queries = self
+            .database
+            .person_table
+            .prepared_queries
+            .definitions()
+            .into_iter()
+            .map(|definition| {
+                let parameters = definition
+                    .parameters
+                    .iter()
+                    .map(|parameter| format!("{}: {:?}", parameter.name, parameter.field))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                (
+                    definition.name,
+                    format!("({}) {:?}", parameters, definition.query),
+                )
+            })
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(queries));
+
+        DatabaseControlAction::Continue
+    }
+
+    fn save_prepared_queries(&self) -> StorageResult<()> {
+        self.database
+            .persistence
+            .snapshot_manager
+            .save_prepared_queries(self.database.person_table.prepared_queries.definitions())
+    }
+
+    pub fn schedule_job(self, definition: JobDefinition) -> DatabaseControlAction {
+        let name = definition.name.clone();
+
+        let response = match self.database.scheduler.schedule(definition) {
+            Ok(_) => match self.save_jobs() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Successfully scheduled job: {}",
+                    name
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Scheduled job {}, but failed to persist it: {}",
+                    name, e
+                )),
+            },
+            Err(e) => DatabaseCommandResponse::control_error(&format!(
+                "Unable to schedule job {}: {}",
+                name, e
+            )),
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn list_jobs(self) -> DatabaseControlAction {
+        let jobs = self
+            .database
+            .scheduler
+            .list()
+            .into_iter()
+            .map(|(definition, next_run)| {
+                let next_run = next_run
+                    .map(|n| n.to_rfc3339())
+                    .unwrap_or_else(|| "Never".to_string());
+
+                (
+                    definition.name,
+                    format!(
+                        "{:?} on '{}', next run: {}",
+                        definition.action, definition.schedule, next_run
+                    ),
+                )
+            })
+            .collect();
+
+        self.send_response(DatabaseCommandResponse::control_info(jobs));
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn cancel_job(self, name: String) -> DatabaseControlAction {
+        let response = match self.database.scheduler.cancel(&name) {
+            true => match self.save_jobs() {
+                Ok(_) => DatabaseCommandResponse::control_success(&format!(
+                    "Successfully cancelled job: {}",
+                    name
+                )),
+                Err(e) => DatabaseCommandResponse::control_error(&format!(
+                    "Cancelled job {}, but failed to persist the change: {}",
+                    name, e
+                )),
+            },
+            false => {
+                DatabaseCommandResponse::control_error(&format!("Job does not exist: {}", name))
+            }
+        };
+
+        self.send_response(response);
+
+        DatabaseControlAction::Continue
+    }
+
+    fn save_jobs(&self) -> StorageResult<()> {
+        self.database
+            .persistence
+            .snapshot_manager
+            .save_jobs(self.database.scheduler.definitions())
+    }
+
+    pub fn list_active_requests(self) -> DatabaseControlAction {
+        let report = self.database.activity.report();
+
+        self.send_response(DatabaseCommandResponse::control_activity(report));
+
+        DatabaseControlAction::Continue
+    }
+
+    pub fn kill_request(self, request_id: RequestId) -> DatabaseControlAction {
+        let response = match self.database.activity.kill(request_id) {
+            true => DatabaseCommandResponse::control_success(&format!(
+                "Successfully requested cancellation of request: {}",
+                request_id
+            )),
+            false => DatabaseCommandResponse::control_error(&format!(
+                "Request is not running: {}",
+                request_id
+            )),
+        };
+
         self.send_response(response);
 
         DatabaseControlAction::Continue
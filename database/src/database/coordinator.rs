@@ -0,0 +1,54 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Commit decisions of the two-phase commit coordinator (see `ShardRouter`), one global transaction id per line.
+/// A decision is durable before any participant commits, a prepared transaction without a decision is presumed
+/// aborted
+///
+/// TODO: decisions are never removed, the log could be trimmed once every participant has committed
+pub struct CoordinatorLog {
+    file: Mutex<File>,
+    committed: Mutex<HashSet<String>>,
+}
+
+impl CoordinatorLog {
+    /// Opens (or creates) the log, previous decisions are read so in-doubt transactions can be resolved
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let committed = match fs::read_to_string(&path) {
+            Ok(contents) => contents.lines().map(|line| line.to_string()).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e),
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+            committed: Mutex::new(committed),
+        })
+    }
+
+    pub fn log_commit(&self, global_id: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+
+        writeln!(file, "{}", global_id)?;
+        file.sync_all()?;
+
+        self.committed.lock().unwrap().insert(global_id.to_string());
+
+        Ok(())
+    }
+
+    pub fn is_committed(&self, global_id: &str) -> bool {
+        self.committed.lock().unwrap().contains(global_id)
+    }
+}
@@ -1,20 +1,64 @@
+#[cfg(feature = "publisher")]
+use super::publisher::Publisher;
 use super::{
+    activity::{ActivityTracker, CancellationToken},
+    audit::{PurgeAudit, PurgeRecord, RollbackAudit, RollbackRecord},
+    availability::{ThreadAvailability, WorkerAvailability},
+    backfill::IndexBackfills,
+    capture::RequestCapture,
+    clones::TableClones,
     commands::{DatabaseCommandRequest, DatabaseCommandTransactionResponse},
+    ids::IdGenerator,
+    maintenance::MaintenanceQueue,
+    operations::Operations,
     options::DatabaseOptions,
+    pause::PauseTracker,
+    prepared::{PreparedTransaction, PreparedTransactions},
+    queue_wait::QueueWaitTracker,
+    quota::QuotaTracker,
+    replay::ReplayConflict,
+    request_log::RequestLog,
     request_manager::RequestManager,
-    table::table::PersonTable,
+    scheduler::Scheduler,
+    shadow::ShadowReads,
+    statement_stats::StatementStats,
+    table::{
+        attachment::AttachmentStore,
+        cold::ColdVersionStore,
+        constraint::ConstraintTiming,
+        planner::ReadPath,
+        policy::FieldMask,
+        query::query,
+        table::{ApplyErrors, PersonTable, ReadOptions},
+    },
+    tags::TagThrottle,
+    warmup::{log_warmup, warm_up_threads, WarmupReport},
 };
 use crate::{
     consts::consts::TransactionId,
     database::{
-        commands::{DatabaseCommand, DatabaseCommandResponse, SnapshotTimestamp},
+        commands::{
+            DatabaseCommand, DatabaseCommandResponse, SnapshotTimestamp, TransactionContext,
+        },
         control::{ControlContext, DatabaseControlAction},
     },
     model::statement::{Statement, StatementResult},
-    persistence::persistence::Persistence,
+    persistence::{
+        backup::restore_from_backup,
+        checkpoint::ReplayCheckpoint,
+        diagnostics::ReplayConflictReport,
+        intent::IntentRecord,
+        persistence::Persistence,
+        storage::{file::durability_self_test, replication::StorageReplication, StorageEngine},
+        transaction::{Transaction, TransactionStatus, TransactionWriteMode},
+    },
 };
 use num_format::{Locale, ToFormattedString};
-use std::{sync::Arc, thread, time::Instant};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
 
 // TODO: This is a part of the transaction_wal, should be moved there
 enum CommitStatus {
@@ -22,6 +66,14 @@ enum CommitStatus {
     Rollback(String),
 }
 
+/// Why a worker thread's control loop returned
+enum WorkerExit {
+    Shutdown,
+    /// The worker dropped its request and is started again, see `ChaosOptions::worker_restart_probability`
+    #[cfg_attr(not(feature = "chaos"), allow(dead_code))]
+    Restart,
+}
+
 /// Transactions can be created from a client submitting a request or from a restore operation
 pub enum ApplyMode {
     /// Return the result of the transaction to the client
@@ -34,14 +86,112 @@ pub struct Database {
     pub(super) person_table: PersonTable,
     pub(super) database_options: DatabaseOptions,
     pub(super) persistence: Persistence,
+    pub(super) scheduler: Scheduler,
+    pub(super) activity: ActivityTracker,
+    pub(super) clones: TableClones,
+    pub(super) prepared: PreparedTransactions,
+    pub(super) quotas: QuotaTracker,
+    pub(super) tag_throttle: TagThrottle,
+    pub(super) request_log: RequestLog,
+    pub(super) shadow_reads: Option<ShadowReads>,
+    pub(super) rollback_audit: Option<RollbackAudit>,
+    pub(super) purge_audit: PurgeAudit,
+    pub(super) statement_stats: Option<StatementStats>,
+    pub(super) request_capture: Option<RequestCapture>,
+    #[cfg(feature = "publisher")]
+    pub(super) publisher: Option<Publisher>,
+    pub(super) queue_wait: QueueWaitTracker,
+    pub(super) pauses: PauseTracker,
+    pub(super) availability: WorkerAvailability,
+    pub(super) maintenance: MaintenanceQueue,
+    pub(super) operations: Operations,
+    pub(super) index_backfills: IndexBackfills,
+    /// A destructive operation that was interrupted before the database stopped, writes are refused until the
+    /// database is reset, see `SnapshotManager::begin_intent`
+    pub(super) interrupted: Mutex<Option<IntentRecord>>,
 }
 
 impl Database {
     pub fn new(options: DatabaseOptions) -> Self {
+        let persistence = Persistence::new(options.clone());
+
+        let person_table = match options.hot_versions {
+            Some(hot_versions) => PersonTable::new_with_cold_store(
+                ColdVersionStore::new(persistence.get_storage(), hot_versions)
+                    .set_field_cipher(persistence.get_field_cipher()),
+            ),
+            None => PersonTable::new(),
+        }
+        .set_paranoid_checks(options.paranoid_checks)
+        .set_conflict_resolution(options.conflict_resolution.clone())
+        .set_unique_email(options.unique_email)
+        .set_history_squash(options.history_squash.clone())
+        .set_attachment_store(Some(Arc::new(AttachmentStore::new(
+            persistence.get_storage(),
+            options.clock.clone(),
+        ))));
+
+        let queue_wait = QueueWaitTracker::new(options.worker_threads(), options.queue_wait_slo);
+        let pauses = PauseTracker::new(options.pause_warn_threshold);
+        let availability = WorkerAvailability::new(options.worker_threads());
+        let maintenance = MaintenanceQueue::new(options.maintenance_queue_limit);
+        let request_log = RequestLog::new(options.request_log_sampling.clone());
+        let shadow_reads = options.shadow_reads.clone().map(ShadowReads::new);
+        let quotas = QuotaTracker::new(options.quotas.clone(), options.clock.clone());
+        let tag_throttle = TagThrottle::new(options.tag_limits.clone());
+        let rollback_audit = options
+            .rollback_audit
+            .clone()
+            .map(|audit| RollbackAudit::new(persistence.get_storage(), audit));
+        let purge_audit = PurgeAudit::new(persistence.get_storage());
+        let statement_stats = options
+            .statement_stats
+            .clone()
+            .map(|stats| StatementStats::new(persistence.get_storage(), stats, options.clock.clone()));
+
+        // A capture is a debugging aid, the database starts without it if the file cannot be created
+        let request_capture = options.request_capture.clone().and_then(|capture| {
+            RequestCapture::start(capture)
+                .map_err(|e| log::error!("Unable to capture requests: {}", e))
+                .ok()
+        });
+
+        #[cfg(feature = "publisher")]
+        let publisher = options.publisher.clone().map(|publisher| {
+            let publisher = Publisher::new(persistence.get_storage(), publisher);
+
+            persistence
+                .transaction_wal
+                .add_committed_listener(publisher.sender());
+
+            publisher
+        });
+
         Self {
-            person_table: PersonTable::new(),
-            persistence: Persistence::new(options.clone()),
+            person_table,
+            persistence,
+            scheduler: Scheduler::new(options.clock.clone()),
             database_options: options,
+            activity: ActivityTracker::default(),
+            clones: TableClones::default(),
+            prepared: PreparedTransactions::default(),
+            quotas,
+            tag_throttle,
+            queue_wait,
+            pauses,
+            availability,
+            interrupted: Mutex::new(None),
+            maintenance,
+            operations: Operations::default(),
+            index_backfills: IndexBackfills::default(),
+            request_log,
+            shadow_reads,
+            rollback_audit,
+            purge_audit,
+            statement_stats,
+            request_capture,
+            #[cfg(feature = "publisher")]
+            publisher,
         }
     }
 
@@ -55,15 +205,11 @@ impl Database {
         receiver: flume::Receiver<DatabaseCommandRequest>,
         database_request_managers: Vec<RequestManager>,
         database: Arc<Self>,
-    ) {
+    ) -> WorkerExit {
         let database_request_managers = &database_request_managers;
 
         loop {
-            let DatabaseCommandRequest {
-                command,
-                resolver,
-                transaction_context,
-            } = match receiver.recv() {
+            let request = match receiver.recv() {
                 Ok(request) => request,
                 Err(e) => {
                     log::error!("Failed to receive data from channel {}", e);
@@ -71,6 +217,36 @@ impl Database {
                 }
             };
 
+            // Transactions received during maintenance are replayed once it is done, see `Control::EnterMaintenance`
+            let Some(DatabaseCommandRequest {
+                command,
+                resolver,
+                transaction_context,
+                enqueued_at,
+                // Releases the slot of a limited tag once the request is done, see `TagThrottle`
+                permit: _permit,
+            }) = database.maintenance.intercept(request)
+            else {
+                continue;
+            };
+
+            database.queue_wait.record(thread_id, enqueued_at.elapsed());
+
+            // Controls are left alone, other threads wait on them (e.g. pauses and shutdowns)
+            #[cfg(feature = "chaos")]
+            if let (Some(chaos), DatabaseCommand::Transaction(_)) =
+                (&database.database_options.chaos, &command)
+            {
+                chaos.inject_worker_delay(thread_id);
+
+                if chaos.inject_worker_restart() {
+                    log::warn!(thread_id; "Chaos: dropping request and restarting worker");
+
+                    // The resolver is dropped with the request, the requester sees the request fail
+                    return WorkerExit::Restart;
+                }
+            }
+
             // Clock time of the transaction, we include a transaction id in all requests
             //  this clock time is stored in an atomic so it is unique across threads
             let transaction_timestamp = database
@@ -79,10 +255,24 @@ impl Database {
                 .get_increment_current_transaction_id()
                 .clone();
 
-            log::info!(
-                "[Thread: {}. TxId: {}] Received request: {}",
+            let started = Instant::now();
+            let kind = command.kind();
+
+            // The request is listed as active until the end of this iteration, see `Control::ListActiveRequests`
+            let mut activity = database.activity.start(
                 thread_id,
-                transaction_timestamp,
+                &transaction_timestamp,
+                &command,
+                transaction_context.client_id.clone(),
+                transaction_context.tag.clone(),
+            );
+
+            // Finished transactions are logged at info depending on the sampling, see `RequestLog`
+            log::debug!(
+                thread_id,
+                request_id = activity.request_id().0,
+                transaction_id = transaction_timestamp.to_number();
+                "Received request: {}",
                 command.log_format()
             );
 
@@ -97,48 +287,338 @@ impl Database {
                         transaction_timestamp,
                     };
 
-                    match control_context.run(control) {
+                    // Requests are routed to other threads until the control is done, see `WorkerAvailability`
+                    database
+                        .availability
+                        .set(thread_id, ThreadAvailability::ExecutingControl);
+
+                    let action = control_context.run(control);
+
+                    database
+                        .availability
+                        .set(thread_id, ThreadAvailability::Healthy);
+
+                    match action {
                         DatabaseControlAction::Continue => {
                             continue;
                         }
                         DatabaseControlAction::Exit => {
-                            return;
+                            return WorkerExit::Shutdown;
                         }
                     }
                 }
             };
 
+            if let Some(request_capture) = &database.request_capture {
+                request_capture.record(&transaction_statements, &transaction_context, enqueued_at);
+            }
+
             // If all statements are read, only use the reader lock
             let contains_mutation = transaction_statements
                 .iter()
                 .any(|statement| statement.is_mutation());
 
-            match contains_mutation {
-                true => {
-                    // Runs in 'async' mode, once the transaction is committed to the WAL the response database response is sent
-                    let _ = database.apply_transaction(
-                        transaction_timestamp,
-                        transaction_statements,
-                        ApplyMode::Request(resolver),
+            // Rolled back mutations are recorded if the rollback audit is enabled, see `RollbackAudit`
+            let audited_kinds = (contains_mutation && database.rollback_audit.is_some())
+                .then(|| RollbackRecord::statement_kinds(&transaction_statements));
+
+            // Finished transactions are aggregated per statement kind if enabled, see `StatementStats`
+            let statement_kinds = database.statement_stats_kinds(&transaction_statements);
+
+            // Committed purges are recorded in the purge audit, see `PurgeAudit`
+            let purged_ids = PurgeRecord::purged_ids(&transaction_statements);
+
+            let role = transaction_context.role.as_deref();
+
+            let read_options = database.read_options(role, activity.cancellation().clone());
+
+            // Reads inside of a mutation transaction are not filtered by row policies, so they are rejected
+            let reads_bypass_policies = contains_mutation
+                && read_options.visibility.is_restricted()
+                && transaction_statements
+                    .iter()
+                    .any(|statement| !statement.is_mutation());
+
+            // Backstop for requests that did not go through a request manager with the limits, e.g. forwarded ones
+            if let Err(limit_exceeded) = database
+                .database_options
+                .limits
+                .check(&transaction_statements)
+            {
+                let response = DatabaseCommandTransactionResponse::LimitExceeded(limit_exceeded);
+
+                let _ = resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                    response.clone(),
+                ));
+
+                activity.set_rolled_back();
+                database.audit_rollback(
+                    &transaction_timestamp,
+                    &transaction_context,
+                    audited_kinds,
+                    &response,
+                );
+                database.record_statement_stats(&statement_kinds, &response, started.elapsed());
+                database.request_log.record(
+                    thread_id,
+                    activity.request_id(),
+                    &transaction_timestamp,
+                    &kind,
+                    &response,
+                    started.elapsed(),
+                );
+
+                continue;
+            }
+
+            // Every request counts towards its tenant's request rate, mutations reserve their row and WAL usage
+            let quota_check = database.quotas.check(role, &transaction_statements, || {
+                database.count_visible_rows(&transaction_timestamp, &read_options)
+            });
+
+            let quota_charge = match quota_check {
+                Ok(quota_charge) => quota_charge,
+                Err(quota_exceeded) => {
+                    let response =
+                        DatabaseCommandTransactionResponse::QuotaExceeded(quota_exceeded);
+
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    activity.set_rolled_back();
+                    database.audit_rollback(
+                        &transaction_timestamp,
+                        &transaction_context,
+                        audited_kinds,
+                        &response,
+                    );
+                    database.record_statement_stats(&statement_kinds, &response, started.elapsed());
+                    database.request_log.record(
+                        thread_id,
+                        activity.request_id(),
+                        &transaction_timestamp,
+                        &kind,
+                        &response,
+                        started.elapsed(),
                     );
+
+                    continue;
+                }
+            };
+
+            let interrupted = contains_mutation
+                .then(|| database.interrupted_operation())
+                .flatten();
+
+            let ad_hoc_query = database.database_options.prepared_queries_only
+                && transaction_statements
+                    .iter()
+                    .any(|statement| statement.is_ad_hoc_query());
+
+            // Polling clients send the version of their previous result, an unchanged table is not read again
+            let not_modified = match &transaction_context.if_unchanged_since {
+                Some(version)
+                    if !contains_mutation
+                        && transaction_context.clone.is_none()
+                        && matches!(
+                            transaction_context.snapshot_timestamp,
+                            SnapshotTimestamp::Latest
+                        ) =>
+                {
+                    database.person_table.watermark.unchanged_since(version)
+                }
+                _ => false,
+            };
+
+            let response = match contains_mutation {
+                _ if ad_hoc_query => {
+                    let response = DatabaseCommandTransactionResponse::Rollback(
+                        "Ad-hoc queries are disabled, filter people with a prepared query"
+                            .to_string(),
+                    );
+
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    response
+                }
+                false if not_modified => {
+                    let response = DatabaseCommandTransactionResponse::NotModified(
+                        transaction_context
+                            .if_unchanged_since
+                            .clone()
+                            .expect("Version is checked by the match guard"),
+                    );
+
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    response
+                }
+                true if interrupted.is_some() => {
+                    let response = DatabaseCommandTransactionResponse::Rollback(format!(
+                        "Writes are refused until the database is reset: {}",
+                        interrupted.expect("Interrupted operation is checked by the match guard")
+                    ));
+
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    response
+                }
+                true if transaction_context.clone.is_some() => {
+                    let response = DatabaseCommandTransactionResponse::Rollback(
+                        "Clones are read-only, mutations must be sent to the live table"
+                            .to_string(),
+                    );
+
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    response
+                }
+                true if reads_bypass_policies => {
+                    let response = DatabaseCommandTransactionResponse::Rollback(
+                        "Roles with row policies cannot read inside of a transaction with mutations"
+                            .to_string(),
+                    );
+
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    response
+                }
+                true => {
+                    // Rows held by a prepared two-phase commit transaction cannot be mutated until it is resolved
+                    let locks = database.prepared.locks();
+
+                    match locks.check(&transaction_statements) {
+                        Ok(()) if transaction_context.dry_run => {
+                            let response = database.dry_run_transaction(
+                                &transaction_timestamp,
+                                &transaction_statements,
+                                &read_options.mask,
+                                database
+                                    .person_table
+                                    .constraint_timing(transaction_context.defer_constraints),
+                            );
+
+                            let _ = resolver.send(
+                                DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                                    response.clone(),
+                                ),
+                            );
+
+                            response
+                        }
+                        // Runs in 'async' mode, once the transaction is committed to the WAL the response database response is sent
+                        Ok(()) => database.apply_transaction_as(
+                            transaction_timestamp.clone(),
+                            transaction_statements,
+                            TransactionStatus::Committed,
+                            ApplyMode::Request(resolver),
+                            &read_options.mask,
+                            database
+                                .person_table
+                                .constraint_timing(transaction_context.defer_constraints),
+                        ),
+                        Err(message) => {
+                            let response = DatabaseCommandTransactionResponse::Rollback(message);
+
+                            let _ = resolver.send(
+                                DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                                    response.clone(),
+                                ),
+                            );
+
+                            response
+                        }
+                    }
                 }
                 false => {
                     // By default we run a single statement transaction, this would just use the 'latest' timestamp
                     //  though when we are running as a long-lived transaction we use the snapshot timestamp from
                     //  the transaction begin
-                    let query_transaction_id = match transaction_context.snapshot_timestamp {
-                        SnapshotTimestamp::AtTransactionId(snapshot_id) => snapshot_id,
-                        SnapshotTimestamp::Latest => transaction_timestamp,
+                    let query_transaction_id = match &transaction_context.snapshot_timestamp {
+                        SnapshotTimestamp::AtTransactionId(snapshot_id) => snapshot_id.clone(),
+                        SnapshotTimestamp::Latest => transaction_timestamp.clone(),
                     };
 
-                    let response =
-                        database.query_transaction(&query_transaction_id, transaction_statements);
+                    let response = match &transaction_context.clone {
+                        // Clones are frozen, reads always run at the transaction the clone was taken at
+                        Some(name) => match database.clones.get(name) {
+                            Some(clone) => database.query_table(
+                                &clone.table,
+                                &clone.transaction_id,
+                                transaction_statements,
+                                &read_options,
+                            ),
+                            None => DatabaseCommandTransactionResponse::Rollback(format!(
+                                "Clone does not exist: {}",
+                                name
+                            )),
+                        },
+                        None => database.query_transaction(
+                            &query_transaction_id,
+                            transaction_statements,
+                            &read_options,
+                        ),
+                    };
 
-                    let _ = resolver.send(
-                        DatabaseCommandResponse::DatabaseCommandTransactionResponse(response),
-                    );
+                    let _ =
+                        resolver.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                            response.clone(),
+                        ));
+
+                    response
                 }
             };
+
+            if let DatabaseCommandTransactionResponse::Rollback(_)
+            | DatabaseCommandTransactionResponse::DryRun(_) = response
+            {
+                activity.set_rolled_back();
+
+                if let Some(quota_charge) = quota_charge {
+                    database.quotas.refund(quota_charge);
+                }
+            }
+
+            database.audit_rollback(
+                &transaction_timestamp,
+                &transaction_context,
+                audited_kinds,
+                &response,
+            );
+            database.audit_purges(
+                &transaction_timestamp,
+                &transaction_context,
+                purged_ids,
+                &response,
+            );
+
+            // Mutations are timed until they are handed to the WAL, not until they are durable
+            database.request_log.record(
+                thread_id,
+                activity.request_id(),
+                &transaction_timestamp,
+                &kind,
+                &response,
+                started.elapsed(),
+            );
+            database.record_statement_stats(&statement_kinds, &response, started.elapsed());
         }
     }
 
@@ -157,11 +637,44 @@ impl Database {
             if we are unable to it means we cannot durably write and thus, need to panic"#,
         );
 
+        if self.database_options.durability_self_test {
+            self.log_durability_self_test();
+        }
+
+        if let Some(backup) = &self.database_options.restore_from_backup {
+            self.restore_from_backup(backup);
+        }
+
+        self.check_interrupted_intent();
+
+        if self.database_options.restore {
+            self.check_storage_features();
+        }
+
+        self.persistence.snapshot_manager.record_features().expect(
+            "Should always be able to record the enabled storage features before writing with them",
+        );
+
         if self.database_options.restore {
             let now = Instant::now();
 
+            self.check_snapshot_compatibility();
+
+            // A restore that was killed part way resumes from its checkpoint, see `ReplayCheckpoint`
+            let checkpoint = match self.persistence.snapshot_manager.load_replay_checkpoint() {
+                Ok(checkpoint) => checkpoint,
+                Err(e) => {
+                    log::warn!(
+                        "Unable to read the replay checkpoint, replaying from the snapshot: {}",
+                        e
+                    );
+
+                    None
+                }
+            };
+
             // Call chain -> snapshot_manager -> person_table
-            let (snapshot_count, metadata) = self
+            let (snapshot_count, metadata, archived_transactions) = self
                 .persistence
                 .snapshot_manager
                 .restore_snapshot(&self.person_table)
@@ -174,34 +687,193 @@ impl Database {
                 .transaction_wal
                 .set_current_transaction_id(metadata.current_transaction_id.clone());
 
-            let restored_transactions = self.persistence.transaction_wal.restore()
+            let restored_transactions = self.persistence.transaction_wal.restore(archived_transactions)
                 .expect(r#"Once persistence has been initialized there should be no issues restoring state from storage"#);
 
             let restored_transaction_count = restored_transactions.len();
 
+            let had_checkpoint = checkpoint.is_some();
+
+            let resume_position = checkpoint.and_then(|checkpoint| {
+                let position = checkpoint
+                    .resume_position(&metadata.current_transaction_id, &restored_transactions)?;
+
+                log::info!(
+                    "Resuming the replay from the checkpoint at transaction {}, skipping {} transactions",
+                    checkpoint.transaction_id,
+                    position + 1
+                );
+
+                checkpoint.restore_into(&self.person_table);
+
+                Some(position)
+            });
+
+            // Two-phase commit transactions that were in-doubt at the snapshot
+            for transaction in metadata.prepared {
+                self.prepared.insert(transaction);
+            }
+
+            // Prepared transactions stay in-doubt until a commit / abort record resolves them, they are resolved in
+            //  WAL order before the committed transactions are replayed
+            let mut committed_transactions = vec![];
+            // Committed transactions that are already applied to the checkpoint's rows
+            let mut checkpointed_transactions = 0;
+
+            let mut latest_transaction_id = metadata.current_transaction_id.clone();
+
+            for (position, transaction) in restored_transactions.into_iter().enumerate() {
+                // Set the current transaction id to the latest transaction id we are applying, commits of
+                //  concurrent workers can reach the WAL out of id order
+                if transaction.id > latest_transaction_id {
+                    latest_transaction_id = transaction.id.clone();
+
+                    self.persistence
+                        .transaction_wal
+                        .set_current_transaction_id(transaction.id.clone());
+                }
+
+                match &transaction.status {
+                    TransactionStatus::Committed => {}
+                    TransactionStatus::Prepared(global_id) => {
+                        self.prepared.insert(PreparedTransaction {
+                            global_id: global_id.clone(),
+                            transaction_id: transaction.id.clone(),
+                            statements: transaction.statements.clone(),
+                        });
+
+                        continue;
+                    }
+                    TransactionStatus::CommitPrepared(global_id) => {
+                        self.prepared.remove(global_id);
+                    }
+                    TransactionStatus::AbortPrepared(global_id) => {
+                        self.prepared.remove(global_id);
+
+                        continue;
+                    }
+                }
+
+                if resume_position.is_some_and(|resume_position| position <= resume_position) {
+                    checkpointed_transactions += 1;
+                }
+
+                committed_transactions.push(transaction);
+            }
+
             // Then add states from the transaction log
-            for transaction in restored_transactions {
-                // Set the current transaction id to the transaction id we are applying
-                self.persistence
-                    .transaction_wal
-                    .set_current_transaction_id(transaction.id.clone());
-
-                let apply_transaction_result = self.apply_transaction(
-                    transaction.id,
-                    transaction.statements,
-                    ApplyMode::Restore,
+            let replay_threads = self.database_options.replay_threads();
+            let checkpoint_interval = self.database_options.replay_checkpoint_interval;
+
+            let save_checkpoint = |transaction: &Transaction| {
+                let checkpoint = ReplayCheckpoint::new(
+                    &self.person_table,
+                    metadata.current_transaction_id.clone(),
+                    transaction.id.clone(),
                 );
 
-                if let DatabaseCommandTransactionResponse::Rollback(rollback_message) =
-                    apply_transaction_result
+                match self
+                    .persistence
+                    .snapshot_manager
+                    .save_replay_checkpoint(checkpoint)
                 {
+                    Ok(()) => {
+                        log::info!("Checkpointed the replay at transaction {}", transaction.id)
+                    }
+                    Err(e) => log::warn!("Unable to checkpoint the replay: {}", e),
+                }
+            };
+
+            let replayed_transactions = &committed_transactions[checkpointed_transactions..];
+
+            match self.replay_transactions_checkpointed(
+                replayed_transactions,
+                replay_threads,
+                checkpoint_interval,
+                save_checkpoint,
+            ) {
+                Ok(partitions) => log::debug!(
+                    "Replayed {} transactions in {} partitions with {} threads",
+                    replayed_transactions.len(),
+                    partitions,
+                    replay_threads
+                ),
+                Err(ReplayConflict {
+                    index,
+                    rollback_message,
+                }) => {
+                    let transaction = &replayed_transactions[index];
+
+                    let report = ReplayConflictReport::new(
+                        &self.person_table,
+                        &transaction.id,
+                        &transaction.statements,
+                        rollback_message,
+                    );
+
+                    let saved = match self
+                        .persistence
+                        .snapshot_manager
+                        .save_replay_conflict(report.clone())
+                    {
+                        Ok(()) => {
+                            "the full report was saved to the replay_conflict blob".to_string()
+                        }
+                        Err(e) => format!("unable to save the full report: {}", e),
+                    };
+
                     panic!(
-                        "All committed transactions should be replayable on startup: {}",
-                        rollback_message
+                        "All committed transactions should be replayable on startup: {}, {}",
+                        report, saved
                     );
                 }
             }
 
+            // Transactions after the cursor may not have been published before the database stopped
+            #[cfg(feature = "publisher")]
+            if let Some(publisher) = &self.publisher {
+                match publisher.catch_up(&metadata.current_transaction_id, &committed_transactions) {
+                    Ok(queued) => log::info!("Publishing {} replayed transactions", queued),
+                    Err(e) => log::error!("Unable to read the publisher cursor, replayed transactions are not published: {}", e),
+                }
+            }
+
+            // The replay has completed, a restart replays from the snapshot again
+            if had_checkpoint
+                || checkpoint_interval
+                    .is_some_and(|interval| replayed_transactions.len() > interval)
+            {
+                if let Err(e) = self.persistence.snapshot_manager.clear_replay_checkpoint() {
+                    log::warn!("Unable to clear the replay checkpoint: {}", e);
+                }
+            }
+
+            self.restore_views();
+            self.restore_policies();
+            self.restore_prepared_queries();
+            self.restore_indexes();
+
+            if let Some(verify_restore) = &self.database_options.verify_restore {
+                self.run_restore_verification(verify_restore);
+            }
+
+            let in_doubt = self.prepared.list();
+
+            if !in_doubt.is_empty() {
+                log::warn!(
+                    "{} prepared transactions are in-doubt, their rows are locked until the coordinator resolves them",
+                    in_doubt.len()
+                );
+            }
+
+            for job in metadata.jobs {
+                let name = job.name.clone();
+
+                if let Err(e) = self.scheduler.schedule(job) {
+                    log::warn!("Unable to restore job {}: {}", name, e);
+                }
+            }
+
             log::info!(
                 "✅ Successful Restore [Duration: {}ms]",
                 now.elapsed().as_millis(),
@@ -226,6 +898,26 @@ impl Database {
             log::info!("✅ Restore is turned off, cleaning up any previous state");
         }
 
+        // Only a fresh database is seeded, a restored one already has its people
+        let seed = match self.person_table.person_rows.is_empty() {
+            true => self.database_options.seed.clone(),
+            false => {
+                if self.database_options.seed.is_some() {
+                    log::info!("Skipping the seed, the database is not empty");
+                }
+
+                None
+            }
+        };
+
+        let warmup = self.database_options.warmup.clone();
+        let warmup_started_at = Instant::now();
+        let mut warmup_report = WarmupReport::default();
+
+        if let Some(warmup) = &warmup {
+            self.preload_rows(warmup.preload_rows, &mut warmup_report);
+        }
+
         /*
            Channel strategy:
            - We create a channel per database thread, this acts as sort of thread work queue
@@ -236,14 +928,22 @@ impl Database {
         let mut tx_channels = vec![];
         let mut rx_channels = vec![];
 
-        for _ in 0..self.database_options.threads {
+        for _ in 0..self.database_options.worker_threads() {
             let (tx, rx) = flume::unbounded::<DatabaseCommandRequest>();
 
             tx_channels.push(tx);
             rx_channels.push(rx);
         }
 
+        let worker_channels = tx_channels.clone();
+        let worker_pools = self.database_options.worker_pools();
+        let availability = self.availability.clone();
+        let limits = self.database_options.limits.clone();
+        let context_policy = self.database_options.context_policy.clone();
+        let tag_throttle = self.tag_throttle.clone();
+        let ids = IdGenerator::new(self.database_options.id_generation.clone());
         let database_arc = Arc::new(self);
+        let mut workers = vec![];
 
         for (thread_index, database_rx_channel) in rx_channels.into_iter().enumerate() {
             let database_arc = database_arc.clone();
@@ -258,35 +958,463 @@ impl Database {
             // Remove the current threads' request manager, as we will not need to call ourselves
             request_managers.remove(thread_index);
 
+            // Named after the pool so debuggers and `top -H` tell the workers apart
+            let thread_name = match worker_pools {
+                Some((write_threads, _)) if thread_index < write_threads => {
+                    format!("Write Worker {}", thread_index)
+                }
+                Some(_) => format!("Read Worker {}", thread_index),
+                None => format!("Worker {}", thread_index),
+            };
+
+            #[cfg(feature = "thread-tuning")]
+            let placement = database_arc
+                .database_options
+                .thread_tuning
+                .as_ref()
+                .map(|thread_tuning| thread_tuning.workers.clone());
+
             // Spawn a new thread for each request
-            thread::spawn(move || {
-                Database::start_thread(
+            let worker = thread::Builder::new().name(thread_name).spawn(move || {
+                #[cfg(feature = "thread-tuning")]
+                if let Some(placement) = placement {
+                    placement.apply_to_current_thread();
+                }
+
+                while let WorkerExit::Restart = Database::start_thread(
                     thread_index,
-                    database_rx_channel,
-                    request_managers,
-                    database_arc,
-                );
+                    database_rx_channel.clone(),
+                    request_managers.clone(),
+                    database_arc.clone(),
+                ) {
+                    log::warn!(thread_id = thread_index; "Restarting worker");
+                }
+
+                // Requests that are still queued are dropped without being run and requests sent from now on fail
+                //  right away. The last worker to exit drops (and closes) the database
+                database_rx_channel.drain();
+                drop(database_rx_channel);
+                drop(database_arc);
             });
+
+            workers.push(worker.expect("Should be able to spawn the worker thread"));
         }
 
-        return RequestManager::new(tx_channels);
+        // Mutations contend on the row locks, they can run on a smaller pool than reads, see `set_read_threads`
+        let request_manager = match worker_pools {
+            Some((write_threads, _)) => {
+                let read_channels = tx_channels.split_off(write_threads);
+
+                RequestManager::new_with_pools(tx_channels, read_channels)
+            }
+            None => RequestManager::new(tx_channels),
+        }
+        .set_availability(availability)
+        .set_transaction_limits(limits)
+        .set_context_policy(context_policy)
+        .set_tag_throttle(tag_throttle)
+        .set_id_generator(ids)
+        .set_inline_reads(
+            database_arc
+                .database_options
+                .inline_reads
+                .then(|| Arc::downgrade(&database_arc)),
+        )
+        .set_workers(workers);
+
+        if let Some(warmup) = warmup {
+            warm_up_threads(
+                &worker_channels,
+                warmup.transactions_per_thread,
+                &mut warmup_report,
+            );
+
+            log_warmup(&warmup_report, warmup_started_at);
+        }
+
+        if let Some(seed) = seed {
+            let report = seed
+                .apply(&request_manager)
+                .expect("Should be able to apply the seed to a fresh database");
+
+            log::info!(
+                "🌱 Seeded             [People: {}, Transactions: {}]",
+                report.people,
+                report.transactions
+            );
+        }
+
+        if let Some(capture_replay) = database_arc.database_options.capture_replay.clone() {
+            let replay_request_manager = request_manager.without_workers();
+
+            thread::Builder::new()
+                .name("lineagedb-capture-replay".to_string())
+                .spawn(move || {
+                    let report = capture_replay.replay(&replay_request_manager);
+
+                    log::info!(
+                        "🎥 Replayed capture   [Requests: {}, Commits: {}, Rollbacks: {}, Failures: {}, Elapsed: {:.2}s]",
+                        report.requests,
+                        report.commits,
+                        report.rollbacks,
+                        report.failures,
+                        report.elapsed.as_secs_f64()
+                    );
+                })
+                .expect("Should be able to spawn the capture replay thread");
+        }
+
+        // The scheduler must not own the worker threads, otherwise they would only be joined once it exits
+        database_arc
+            .scheduler
+            .start(request_manager.without_workers());
+
+        database_arc
+            .index_backfills
+            .start(request_manager.without_workers());
+
+        #[cfg(feature = "publisher")]
+        if let Some(publisher) = &database_arc.publisher {
+            publisher.start();
+        }
+
+        return request_manager;
+    }
+
+    /// Panics if the data directory requires a storage feature this database does not support or has not enabled,
+    /// reading it would misread (and later overwrite) the data written with the feature
+    fn check_storage_features(&self) {
+        let result = self
+            .persistence
+            .snapshot_manager
+            .check_features()
+            .expect(r#"Once persistence has been initialized there should be no issues reading the snapshot metadata"#);
+
+        if let Err(e) = result {
+            panic!("Unable to open the data directory: {}", e);
+        }
+    }
+
+    /// Panics if the latest snapshot was written by an incompatible database configuration, unless the
+    /// compatibility check has been overridden
+    fn check_snapshot_compatibility(&self) {
+        let compatibility = self
+            .persistence
+            .snapshot_manager
+            .check_compatibility()
+            .expect(r#"Once persistence has been initialized there should be no issues reading the snapshot metadata"#);
+
+        match compatibility {
+            Ok(Some(snapshot)) => {
+                if snapshot.threads != self.database_options.worker_threads() {
+                    log::info!(
+                        "Snapshot was written by a database with {} threads, running with {}",
+                        snapshot.threads,
+                        self.database_options.worker_threads()
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) if self.database_options.ignore_snapshot_compatibility => {
+                log::warn!("Ignoring incompatible snapshot: {}", e);
+            }
+            Err(e) => panic!(
+                "Unable to restore snapshot: {}. Set ignore snapshot compatibility to restore anyway",
+                e
+            ),
+        }
+    }
+
+    /// Copies the backup into the storage engine so that the restore starts from it, panics if the backup
+    /// cannot be copied as the database would otherwise start without the data the operator asked for
+    fn restore_from_backup(&self, backup: &StorageEngine) {
+        let now = Instant::now();
+
+        let mut backup_storage = backup.build(
+            &self.database_options,
+            self.persistence.get_storage_latency(),
+            Arc::new(StorageReplication::default()),
+        );
+        let storage = self.persistence.get_storage();
+        let mut storage = storage.lock().unwrap();
+
+        match restore_from_backup(backup_storage.as_mut(), &mut *storage) {
+            Ok(report) if report.already_restored => {
+                log::info!("🗄️  Storage engine already contains the {} backup", backup)
+            }
+            Ok(report) => log::info!(
+                "🗄️  Restored {} backup ({} blobs, {} transactions) in {}ms",
+                backup,
+                report.blobs,
+                report.transactions,
+                now.elapsed().as_millis()
+            ),
+            Err(e) => panic!("Unable to restore from the {} backup: {}", backup, e),
+        }
+    }
+
+    /// A reset or WAL flush that did not finish leaves storage half-finished, e.g. a WAL that was only partially
+    /// deleted. Reads are still served so that the state can be inspected, writes are refused
+    fn check_interrupted_intent(&self) {
+        let intent = self
+            .persistence
+            .snapshot_manager
+            .load_interrupted_intent()
+            .expect(
+            "Should always be able to read the intent record once persistence has been initialized",
+        );
+
+        if let Some(intent) = intent {
+            log::error!(
+                "A destructive operation was interrupted, writes are refused until the database is reset: {}",
+                intent
+            );
+
+            *self.interrupted.lock().unwrap() = Some(intent);
+        }
+    }
+
+    /// The destructive operation that was interrupted before the database stopped, if any
+    pub(super) fn interrupted_operation(&self) -> Option<IntentRecord> {
+        self.interrupted.lock().unwrap().clone()
+    }
+
+    /// Rebuilds the materialized views from the restored table, only view definitions are persisted
+    fn restore_views(&self) {
+        let definitions = self
+            .persistence
+            .snapshot_manager
+            .load_view_definitions()
+            .expect(r#"Once persistence has been initialized there should be no issues restoring state from storage"#);
+
+        let transaction_id = self
+            .persistence
+            .transaction_wal
+            .get_increment_current_transaction_id();
+
+        for definition in definitions {
+            let people = query(&self.person_table, &transaction_id);
+
+            self.person_table
+                .views
+                .create(definition, people, &transaction_id);
+        }
+    }
+
+    fn restore_policies(&self) {
+        let policies = self
+            .persistence
+            .snapshot_manager
+            .load_policies()
+            .expect(r#"Once persistence has been initialized there should be no issues restoring state from storage"#);
+
+        for policy in policies {
+            self.person_table.policies.create(policy);
+        }
+    }
+
+    fn restore_prepared_queries(&self) {
+        let definitions = self
+            .persistence
+            .snapshot_manager
+            .load_prepared_queries()
+            .expect(r#"Once persistence has been initialized there should be no issues restoring state from storage"#);
+
+        for definition in definitions {
+            let name = definition.name.clone();
+
+            // Definitions are validated before they are persisted
+            if let Err(e) = self.person_table.prepared_queries.create(definition) {
+                log::error!("Unable to restore prepared query {}: {}", name, e);
+            }
+        }
+    }
+
+    /// Indexes are rebuilt from the restored table while nothing else is running, a backfill that was interrupted
+    /// by a restart completes here
+    fn restore_indexes(&self) {
+        let fields = self
+            .persistence
+            .snapshot_manager
+            .load_indexes()
+            .expect(r#"Once persistence has been initialized there should be no issues restoring state from storage"#);
+
+        for field in fields {
+            if self.person_table.indexes.start_building(&field) {
+                self.person_table.backfill_index(&field, None, usize::MAX);
+            }
+        }
+    }
+
+    /// Measures the sync latency of the WAL storage so operators can see the commit latency floor
+    fn log_durability_self_test(&self) {
+        const DURABILITY_SELF_TEST_SAMPLES: usize = 20;
+
+        let (StorageEngine::File(file_options), TransactionWriteMode::File(mode)) = (
+            &self.database_options.storage_engine,
+            &self.database_options.write_mode,
+        ) else {
+            log::info!("💾 Durability self-test skipped, only supported for file storage");
+            return;
+        };
+
+        if !mode.is_durable() {
+            log::info!(
+                "💾 Durability self-test skipped, {:?} does not sync the WAL",
+                mode
+            );
+            return;
+        }
+
+        match durability_self_test(file_options, mode, DURABILITY_SELF_TEST_SAMPLES) {
+            Ok(report) => log::info!("💾 Durability self-test {}", report),
+            Err(e) => log::warn!("💾 Durability self-test failed: {}", e),
+        }
+    }
+
+    /// Rows and fields the role may read
+    fn read_options(&self, role: Option<&str>, cancellation: CancellationToken) -> ReadOptions {
+        ReadOptions {
+            cancellation,
+            visibility: self.person_table.policies.visibility(role),
+            mask: match &self.database_options.field_encryption {
+                Some(field_encryption) if field_encryption.is_masked(role) => {
+                    FieldMask::new(field_encryption.sensitive_fields.clone())
+                }
+                _ => FieldMask::default(),
+            },
+            read_path: ReadPath::Planned,
+        }
+    }
+
+    /// Runs a single `Get` on the caller's thread, see `DatabaseOptions::set_inline_reads`. None if the read has
+    /// to be queued for a worker, e.g. it reads a clone, the database is paused or in maintenance, or the read is
+    /// captured or shadowed
+    pub(super) fn inline_read(
+        &self,
+        statements: &[Statement],
+        transaction_context: &TransactionContext,
+    ) -> Option<DatabaseCommandTransactionResponse> {
+        let [Statement::Get(_)] = statements else {
+            return None;
+        };
+
+        if transaction_context.clone.is_some()
+            || transaction_context.if_unchanged_since.is_some()
+            || self.request_capture.is_some()
+            || self.shadow_reads.is_some()
+            || self.maintenance.is_active()
+        {
+            return None;
+        }
+
+        // Held until the read is done, a pause waits for it before the world is stopped
+        let _inline_read = self.pauses.try_inline_read()?;
+
+        let started = Instant::now();
+        let transaction_id = self
+            .persistence
+            .transaction_wal
+            .get_increment_current_transaction_id();
+        let role = transaction_context.role.as_deref();
+        let read_options = self.read_options(role, CancellationToken::default());
+        let statement_kinds = self.statement_stats_kinds(statements);
+
+        let response = match self.quotas.check(role, statements, || {
+            self.count_visible_rows(&transaction_id, &read_options)
+        }) {
+            Ok(_) => {
+                let query_transaction_id = match &transaction_context.snapshot_timestamp {
+                    SnapshotTimestamp::AtTransactionId(snapshot_id) => snapshot_id.clone(),
+                    SnapshotTimestamp::Latest => transaction_id,
+                };
+
+                self.query_transaction(&query_transaction_id, statements.to_vec(), &read_options)
+            }
+            Err(quota_exceeded) => DatabaseCommandTransactionResponse::QuotaExceeded(quota_exceeded),
+        };
+
+        self.record_statement_stats(&statement_kinds, &response, started.elapsed());
+
+        Some(response)
     }
 
     pub fn query_transaction(
         &self,
         query_latest_transaction_id: &TransactionId,
         statements: Vec<Statement>,
+        read_options: &ReadOptions,
+    ) -> DatabaseCommandTransactionResponse {
+        self.query_table(
+            &self.person_table,
+            query_latest_transaction_id,
+            statements,
+            read_options,
+        )
+    }
+
+    /// Rows visible to the request's row policies, seeds the row quota of a tenant
+    fn count_visible_rows(
+        &self,
+        transaction_id: &TransactionId,
+        read_options: &ReadOptions,
+    ) -> usize {
+        match self.person_table.query_statement_with_options(
+            Statement::List(None),
+            transaction_id,
+            read_options,
+        ) {
+            Ok(StatementResult::List(people)) => people.len(),
+            _ => 0,
+        }
+    }
+
+    /// Runs read-only statements against a table, either the live table or a clone. System tables are the same
+    /// for both
+    fn query_table(
+        &self,
+        table: &PersonTable,
+        query_latest_transaction_id: &TransactionId,
+        statements: Vec<Statement>,
+        read_options: &ReadOptions,
     ) -> DatabaseCommandTransactionResponse {
         let mut statement_results: Vec<StatementResult> = Vec::new();
 
         for statement in statements {
-            let statement_result = self
-                .person_table
-                .query_statement(statement, query_latest_transaction_id);
+            let shadowed = self
+                .shadow_reads
+                .as_ref()
+                .filter(|shadow_reads| shadow_reads.is_shadowed(&statement))
+                .map(|shadow_reads| (shadow_reads, statement.clone()));
+
+            let statement_result = match statement {
+                Statement::QuerySystemTable(name) if read_options.visibility.is_restricted() => {
+                    Err(ApplyErrors::SystemTableRestrictedByPolicy(name).to_string())
+                }
+                Statement::QuerySystemTable(name) => self
+                    .query_system_table(&name, query_latest_transaction_id)
+                    .map(StatementResult::SystemTable),
+                statement => table
+                    .query_statement_with_options(
+                        statement,
+                        query_latest_transaction_id,
+                        read_options,
+                    )
+                    .map_err(|e| e.to_string()),
+            };
 
-            // A 'not found' returns a transaction rollback error. This type of error message is confusing:
-            // 1. A caller just doing a get is using an implicit transactions, why do they get a rollback message
-            // 2. The caller is going to want a response to say the item was not found
+            if let Some((shadow_reads, statement)) = shadowed {
+                shadow_reads.compare(
+                    table,
+                    statement,
+                    query_latest_transaction_id,
+                    read_options,
+                    &statement_result,
+                );
+            }
+
+            // A missing row is a result (see `StatementResult::NotFound`), only errors such as an unknown view or a
+            //  read restricted by a policy roll back the transaction
             match statement_result {
                 Ok(statement_result) => statement_results.push(statement_result),
                 Err(err) => {
@@ -303,6 +1431,28 @@ impl Database {
         applying_transaction_id: TransactionId,
         statements: Vec<Statement>,
         mode: ApplyMode,
+        mask: &FieldMask,
+    ) -> DatabaseCommandTransactionResponse {
+        self.apply_transaction_as(
+            applying_transaction_id,
+            statements,
+            TransactionStatus::Committed,
+            mode,
+            mask,
+            None,
+        )
+    }
+
+    /// Applies the statements and writes a WAL record with the status, e.g. `TransactionStatus::CommitPrepared`.
+    /// The table's constraints are only checked if `constraints` is set, see `PersonTable::constraint_timing`
+    pub(super) fn apply_transaction_as(
+        &self,
+        applying_transaction_id: TransactionId,
+        statements: Vec<Statement>,
+        wal_status: TransactionStatus,
+        mode: ApplyMode,
+        mask: &FieldMask,
+        constraints: Option<ConstraintTiming>,
     ) -> DatabaseCommandTransactionResponse {
         let mut status = CommitStatus::Commit;
 
@@ -320,10 +1470,22 @@ impl Database {
 
             match apply_result {
                 Ok(statement_result) => {
+                    let checked = match constraints {
+                        Some(ConstraintTiming::Immediate) => self
+                            .person_table
+                            .check_constraints(&statement.mutated_ids()),
+                        _ => Ok(()),
+                    };
+
+                    // The statement was applied, it is rolled back with the others if it violates a constraint
                     statement_stack.push(StatementAndResult {
                         statement,
                         result: statement_result,
                     });
+
+                    if let Err(err_string) = checked {
+                        status = CommitStatus::Rollback(format!("{}", err_string));
+                    }
                 }
                 Err(err_string) => {
                     status = CommitStatus::Rollback(format!("{}", err_string));
@@ -331,23 +1493,54 @@ impl Database {
             }
         }
 
+        if let (CommitStatus::Commit, Some(ConstraintTiming::Deferred)) = (&status, constraints) {
+            if let Err(err_string) = self.check_deferred_constraints(&statements) {
+                status = CommitStatus::Rollback(format!("{}", err_string));
+            }
+        }
+
         match status {
             CommitStatus::Commit => {
                 if let ApplyMode::Request(_) = &mode {
-                    log::info!("✅ Committed: [TX: {}]", &applying_transaction_id);
+                    log::debug!(
+                        transaction_id = applying_transaction_id.to_number();
+                        "✅ Committed"
+                    );
                 }
 
                 let action_result_stack: Vec<StatementResult> = statement_stack
                     .into_iter()
-                    .map(|action_and_result| action_and_result.result)
+                    .map(|action_and_result| mask.mask_result(action_and_result.result))
                     .collect();
 
                 let response = DatabaseCommandTransactionResponse::Commit(action_result_stack);
 
+                self.person_table
+                    .apply_views(&statements, &applying_transaction_id);
+
+                self.person_table
+                    .spill_cold_versions(&statements, &applying_transaction_id);
+
+                self.person_table
+                    .complete_purges(&statements, &applying_transaction_id);
+
+                self.person_table
+                    .complete_outbox(&statements, &applying_transaction_id);
+
+                if let ApplyMode::Request(_) = &mode {
+                    self.person_table.stamp_modified(
+                        &statements,
+                        self.database_options.clock.now_ms() as u64,
+                    );
+                }
+
+                self.person_table.reclaim_retired_versions();
+
                 // Send the TX off, and increment the transaction id -- Refactor this out
-                self.persistence.transaction_wal.commit(
+                self.persistence.transaction_wal.write(
                     applying_transaction_id,
                     statements,
+                    wal_status,
                     DatabaseCommandResponse::DatabaseCommandTransactionResponse(response.clone()),
                     mode,
                 );
@@ -356,7 +1549,10 @@ impl Database {
             }
             CommitStatus::Rollback(error_status) => {
                 if let ApplyMode::Request(_) = &mode {
-                    log::info!("⚠️  Rolled back: [TX: {}]", &applying_transaction_id);
+                    log::debug!(
+                        transaction_id = applying_transaction_id.to_number();
+                        "⚠️  Rolled back"
+                    );
                 }
 
                 // TODO: Write a test to ensure that we rollback in the correct order
@@ -365,9 +1561,15 @@ impl Database {
                     result: _,
                 } in statement_stack.into_iter().rev()
                 {
-                    self.person_table.apply_rollback(statement)
+                    self.person_table
+                        .apply_rollback(statement, &applying_transaction_id)
                 }
 
+                self.person_table
+                    .check_rollback(&statements, &applying_transaction_id);
+
+                self.person_table.reclaim_retired_versions();
+
                 // Rollbacks are not committed to the WAL so we can just return the response
                 if let ApplyMode::Request(resolver) = mode {
                     let _ =
@@ -382,25 +1584,207 @@ impl Database {
     }
 }
 
+/// Once the last worker thread has exited the threads the database started are joined. The scheduler is closed
+/// first as its jobs send requests, then the WAL so the publisher receives every commit before it is closed
+impl Drop for Database {
+    fn drop(&mut self) {
+        self.scheduler.close();
+        self.persistence.transaction_wal.close();
+
+        #[cfg(feature = "publisher")]
+        if let Some(publisher) = &self.publisher {
+            publisher.close();
+        }
+    }
+}
+
+impl Database {
+    /// Commits a transaction on behalf of a control and waits until it is durable, e.g. a chunk of
+    /// `Control::RenameIdPrefix`. Rows held by a prepared two-phase commit transaction cannot be mutated
+    pub(super) fn commit_control_transaction(
+        &self,
+        transaction_id: TransactionId,
+        statements: Vec<Statement>,
+    ) -> Result<(), String> {
+        self.prepared.locks().check(&statements)?;
+
+        let (resolver, response) = oneshot::channel();
+
+        self.apply_transaction_as(
+            transaction_id,
+            statements,
+            TransactionStatus::Committed,
+            ApplyMode::Request(resolver),
+            &FieldMask::default(),
+            self.person_table.constraint_timing(false),
+        );
+
+        match response.recv() {
+            Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::Commit(_),
+            )) => Ok(()),
+            Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::Rollback(message)
+                | DatabaseCommandTransactionResponse::Status(message),
+            )) => Err(message),
+            Ok(response) => Err(format!("Unexpected response {:?}", response)),
+            Err(_) => Err("The transaction was dropped before it was durable".to_string()),
+        }
+    }
+
+    /// Applies the statements and rolls them back, the table is left unchanged and nothing is written to the WAL.
+    /// See `TransactionContext::set_dry_run`
+    pub(super) fn dry_run_transaction(
+        &self,
+        transaction_id: &TransactionId,
+        statements: &[Statement],
+        mask: &FieldMask,
+        constraints: Option<ConstraintTiming>,
+    ) -> DatabaseCommandTransactionResponse {
+        let mut applied: Vec<Statement> = vec![];
+        let mut results = vec![];
+        let mut rollback = None;
+
+        for statement in statements {
+            match self
+                .person_table
+                .apply(statement.clone(), transaction_id.clone())
+            {
+                Ok(result) => {
+                    applied.push(statement.clone());
+                    results.push(mask.mask_result(result));
+
+                    if let Some(ConstraintTiming::Immediate) = constraints {
+                        if let Err(err) = self
+                            .person_table
+                            .check_constraints(&statement.mutated_ids())
+                        {
+                            rollback = Some(format!("{}", err));
+                            break;
+                        }
+                    }
+                }
+                Err(err) => {
+                    rollback = Some(format!("{}", err));
+                    break;
+                }
+            }
+        }
+
+        if let (None, Some(ConstraintTiming::Deferred)) = (&rollback, constraints) {
+            if let Err(err) = self.check_deferred_constraints(statements) {
+                rollback = Some(format!("{}", err));
+            }
+        }
+
+        for statement in applied.into_iter().rev() {
+            self.person_table.apply_rollback(statement, transaction_id)
+        }
+
+        self.person_table.check_rollback(statements, transaction_id);
+
+        match rollback {
+            Some(reason) => DatabaseCommandTransactionResponse::Rollback(reason),
+            None => DatabaseCommandTransactionResponse::DryRun(results),
+        }
+    }
+
+    /// Checks that the statements apply (e.g. a prepared transaction), the table is left unchanged
+    pub(super) fn validate_transaction(
+        &self,
+        transaction_id: &TransactionId,
+        statements: &[Statement],
+    ) -> Result<(), String> {
+        let mut applied: Vec<Statement> = vec![];
+        let mut result = Ok(());
+
+        for statement in statements {
+            match self
+                .person_table
+                .apply(statement.clone(), transaction_id.clone())
+            {
+                Ok(_) => applied.push(statement.clone()),
+                Err(err) => {
+                    result = Err(format!("{}", err));
+                    break;
+                }
+            }
+        }
+
+        // The prepared rows are locked, their final state is checked as the commit does not check it again
+        if result.is_ok() {
+            result = self
+                .check_deferred_constraints(statements)
+                .map_err(|err| format!("{}", err));
+        }
+
+        for statement in applied.into_iter().rev() {
+            self.person_table.apply_rollback(statement, transaction_id)
+        }
+
+        self.person_table.check_rollback(statements, transaction_id);
+
+        result
+    }
+
+    /// Checks every row the transaction mutated, once all of its statements have been applied
+    fn check_deferred_constraints(&self, statements: &[Statement]) -> Result<(), ApplyErrors> {
+        let mut ids: Vec<_> = statements
+            .iter()
+            .flat_map(|statement| statement.mutated_ids())
+            .collect();
+
+        ids.sort();
+        ids.dedup();
+
+        self.person_table.check_constraints(&ids)
+    }
+}
+
 #[cfg(test)]
 mod test_struct_methods {
     use super::*;
+    #[cfg(feature = "postgres")]
     use crate::persistence::{
         storage::{postgres::PostgresOptions, StorageEngine},
         transaction::{TransactionFileWriteMode, TransactionWriteMode},
     };
 
     impl Database {
+        #[cfg(feature = "postgres")]
         pub fn new_test_other_storage() -> Self {
             let options = DatabaseOptions::default()
                 .set_storage_engine(StorageEngine::Postgres(PostgresOptions::new_test()))
                 .set_restore(false)
                 .set_sync_file_write(TransactionWriteMode::File(TransactionFileWriteMode::Sync));
 
+            let persistence = Persistence::new(options.clone());
+
             Self {
                 person_table: PersonTable::new(),
-                persistence: Persistence::new(options.clone()),
+                queue_wait: QueueWaitTracker::new(options.worker_threads(), options.queue_wait_slo),
+                pauses: PauseTracker::new(options.pause_warn_threshold),
+                availability: WorkerAvailability::new(options.worker_threads()),
+                interrupted: Mutex::new(None),
+                maintenance: MaintenanceQueue::new(options.maintenance_queue_limit),
+                operations: Operations::default(),
+                index_backfills: IndexBackfills::default(),
+                request_log: RequestLog::new(options.request_log_sampling.clone()),
+                shadow_reads: None,
+                rollback_audit: None,
+                purge_audit: PurgeAudit::new(persistence.get_storage()),
+                statement_stats: None,
+                request_capture: None,
+                #[cfg(feature = "publisher")]
+                publisher: None,
+                quotas: QuotaTracker::new(options.quotas.clone(), options.clock.clone()),
+                tag_throttle: TagThrottle::new(options.tag_limits.clone()),
+                persistence,
+                scheduler: Scheduler::new(options.clock.clone()),
                 database_options: options,
+                activity: ActivityTracker::default(),
+                clones: TableClones::default(),
+                prepared: PreparedTransactions::default(),
             }
         }
 
@@ -643,6 +2027,9 @@ mod tests {
                     id: EntityId(thread_id.to_string()),
                     full_name: "Test".to_string(),
                     email: Some(format!("Email-{}", thread_id)),
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
                 })
             };
 
@@ -652,6 +2039,7 @@ mod tests {
                     UpdatePersonData {
                         full_name: UpdateStatement::Set(index.to_string()),
                         email: UpdateStatement::Set(format!("Email-{}{}", thread, index)),
+                        ..UpdatePersonData::default()
                     },
                 );
             };
@@ -679,6 +2067,9 @@ mod tests {
                     id: EntityId::new(),
                     full_name: "Test".to_string(),
                     email: Some(Uuid::new_v4().to_string()),
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
                 })
             };
 
@@ -703,6 +2094,9 @@ mod tests {
                     id: EntityId(thread_id.to_string()),
                     full_name: "Test".to_string(),
                     email: Some(Uuid::new_v4().to_string()),
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
                 })
             };
 
@@ -731,6 +2125,9 @@ mod tests {
                     id: EntityId(thread_id.to_string()),
                     full_name: "Test".to_string(),
                     email: Some(Uuid::new_v4().to_string()),
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
                 })
             };
 
@@ -738,6 +2135,7 @@ mod tests {
                 return Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Any,
                     email: QueryMatch::Any,
+                    ..QueryPersonData::default()
                 }));
             };
 
@@ -764,6 +2162,7 @@ pub mod test_utils {
             commands::{DatabaseCommandTransactionResponse, TransactionContext},
             database::Database,
             request_manager::{RequestManager, TaskStatementResponse},
+            table::policy::FieldMask,
         },
         model::statement::{Statement, StatementResult},
     };
@@ -941,6 +2340,11 @@ pub mod test_utils {
             .transaction_wal
             .get_increment_current_transaction_id();
 
-        database.apply_transaction(next_timestamp, statements, ApplyMode::Restore)
+        database.apply_transaction(
+            next_timestamp,
+            statements,
+            ApplyMode::Restore,
+            &FieldMask::default(),
+        )
     }
 }
@@ -0,0 +1,168 @@
+use std::{
+    fs, io,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::persistence::{
+    storage::{file::FileOptions, StorageEngine},
+    transaction::{TransactionFileWriteMode, TransactionWriteMode},
+};
+
+use super::{
+    database::Database,
+    ids::IdGeneration,
+    options::DatabaseOptions,
+    request_manager::{RequestManager, RequestManagerError},
+};
+
+/// An isolated, in-process database for integration tests. Storage lives in a temporary directory of its own, once
+/// the fixture is dropped the database is shut down and the directory is removed
+///
+/// The fixture dereferences to its `RequestManager`, e.g. `TestDatabase::new().send_add(..)`
+pub struct TestDatabase {
+    request_manager: RequestManager,
+    options: DatabaseOptions,
+    data_dir: PathBuf,
+}
+
+impl TestDatabase {
+    /// Starts a database with `TestDatabase::default_options`
+    pub fn new() -> Self {
+        Self::with_options(Self::default_options())
+    }
+
+    /// Starts a database with the options, the storage engine is replaced with file storage in a new temporary
+    /// directory
+    pub fn with_options(options: DatabaseOptions) -> Self {
+        let data_dir = std::env::temp_dir()
+            .join("lineagedb")
+            .join(Uuid::new_v4().to_string());
+
+        let options =
+            options.set_storage_engine(StorageEngine::File(FileOptions::new(data_dir.clone())));
+
+        Self {
+            request_manager: Database::new(options.clone()).run(),
+            options,
+            data_dir,
+        }
+    }
+
+    /// Two worker threads, nothing to restore on the first start and a WAL that is buffered by the OS, so a
+    /// restart (see `TestDatabase::restart`) restores every committed transaction without paying for an fsync.
+    /// MVCC invariants are checked on every read and rollback and ids from `next_id` are seeded, so every run
+    /// generates the same ids
+    pub fn default_options() -> DatabaseOptions {
+        DatabaseOptions::default()
+            .set_restore(false)
+            .set_threads(2)
+            .set_sync_file_write(TransactionWriteMode::File(
+                TransactionFileWriteMode::OSBuffered,
+            ))
+            .set_paranoid_checks(true)
+            .set_id_generation(IdGeneration::Seeded(0))
+    }
+
+    pub fn request_manager(&self) -> &RequestManager {
+        &self.request_manager
+    }
+
+    /// The directory the database stores its snapshots and WAL in, removed once the fixture is dropped
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Shuts the database down and starts it again on the same storage, restoring it. Every clone of the request
+    /// manager is re-pointed at the restarted database
+    pub fn restart(&self) -> Result<String, RequestManagerError> {
+        self.request_manager
+            .restart(self.options.clone().set_restore(true))
+    }
+}
+
+impl Default for TestDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for TestDatabase {
+    type Target = RequestManager;
+
+    fn deref(&self) -> &Self::Target {
+        &self.request_manager
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        // The database may already have been shut down by the test, its threads are joined either way so nothing
+        //  writes to the directory once it is removed
+        let _ = self.request_manager.close();
+
+        match fs::remove_dir_all(&self.data_dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!(
+                "Unable to remove the test database directory {}: {}",
+                self.data_dir.display(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::{commands::TransactionContext, ids::IdGenerator},
+        model::person::Person,
+    };
+
+    use super::*;
+
+    #[test]
+    fn restarts_and_cleans_up() {
+        let database = TestDatabase::new();
+        let data_dir = database.data_dir().to_path_buf();
+
+        let person = database
+            .send_add(
+                Person {
+                    id: database.next_id(),
+                    full_name: "Fixture".to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                },
+                TransactionContext::default(),
+            )
+            .expect("should not timeout");
+
+        // Seeded ids are the same in every run
+        assert_eq!(
+            person.id,
+            IdGenerator::new(IdGeneration::Seeded(0)).next_id()
+        );
+
+        // Committed transactions survive a restart, the seeded ids do not start over
+        database.restart().expect("should restart");
+        assert_ne!(database.next_id(), person.id);
+
+        let restored = database
+            .send_get(person.id.clone(), TransactionContext::default())
+            .expect("should not timeout");
+
+        assert_eq!(restored, Some(person));
+        assert!(data_dir.exists());
+
+        // Dropping the fixture shuts the database down and removes its storage
+        drop(database);
+
+        assert!(!data_dir.exists());
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fmt, sync::Arc, thread, time::Duration};
+
+use crate::consts::consts::TransactionId;
+
+/// Control-plane events that embedders can react to, e.g. to flush application caches or to notify
+/// service discovery
+#[derive(Clone, Debug, PartialEq)]
+pub enum LifecycleEvent {
+    /// A snapshot was promoted, the transaction id is the one the snapshot was taken at
+    Snapshot {
+        transaction_id: TransactionId,
+        flushed_transactions: usize,
+    },
+    /// The database was reset, every row was dropped
+    Reset { dropped_rows: usize },
+    /// The database is about to shut down, the worker threads are still running
+    Shutdown,
+}
+
+pub type LifecycleHook = Arc<dyn Fn(&LifecycleEvent) + Send + Sync>;
+
+/// Hooks registered with `DatabaseOptions::set_on_snapshot`, `set_on_reset` and `set_on_shutdown`.
+///
+/// Hooks run on their own thread once the database has resumed, the worker that ran the control waits at most
+/// the timeout for the hook. A hook that hangs is left running in the background and one that panics is logged,
+/// neither can wedge the database
+#[derive(Clone)]
+pub struct LifecycleHooks {
+    pub on_snapshot: Option<LifecycleHook>,
+    pub on_reset: Option<LifecycleHook>,
+    pub on_shutdown: Option<LifecycleHook>,
+    pub timeout: Duration,
+}
+
+impl Default for LifecycleHooks {
+    fn default() -> Self {
+        Self {
+            on_snapshot: None,
+            on_reset: None,
+            on_shutdown: None,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl fmt::Debug for LifecycleHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LifecycleHooks")
+            .field("on_snapshot", &self.on_snapshot.is_some())
+            .field("on_reset", &self.on_reset.is_some())
+            .field("on_shutdown", &self.on_shutdown.is_some())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl LifecycleHooks {
+    /// Runs the hook registered for the event, if any
+    pub fn run(&self, event: LifecycleEvent) {
+        let hook = match &event {
+            LifecycleEvent::Snapshot { .. } => &self.on_snapshot,
+            LifecycleEvent::Reset { .. } => &self.on_reset,
+            LifecycleEvent::Shutdown => &self.on_shutdown,
+        };
+
+        let Some(hook) = hook.clone() else {
+            return;
+        };
+
+        let (done_tx, done_rx) = flume::bounded::<()>(1);
+        let description = format!("{:?}", event);
+
+        // The sender is dropped without sending if the hook panics
+        thread::spawn(move || {
+            hook(&event);
+            let _ = done_tx.send(());
+        });
+
+        match done_rx.recv_timeout(self.timeout) {
+            Ok(()) => {}
+            Err(flume::RecvTimeoutError::Timeout) => log::warn!(
+                "Lifecycle hook for {} did not finish within {:?}, leaving it running in the background",
+                description,
+                self.timeout
+            ),
+            Err(flume::RecvTimeoutError::Disconnected) => {
+                log::error!("Lifecycle hook for {} panicked", description)
+            }
+        }
+    }
+}
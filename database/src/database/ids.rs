@@ -0,0 +1,115 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use uuid::{Builder, Uuid};
+
+use crate::consts::consts::EntityId;
+
+/// How entity ids are generated, see `DatabaseOptions::set_id_generation`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum IdGeneration {
+    /// Random UUIDv4 ids
+    #[default]
+    Random,
+    /// UUIDv4 ids from a generator seeded with the value. Runs with the same seed generate the same ids in the same
+    /// order, so bulk tests and simulation runs are reproducible
+    Seeded(u64),
+}
+
+/// Generates entity ids, clones share the same sequence
+///
+/// Seeded ids are only reproducible if they are requested in the same order, e.g. from a single thread
+#[derive(Clone, Debug)]
+pub struct IdGenerator {
+    generation: IdGeneration,
+    state: Arc<AtomicU64>,
+}
+
+/// Increment of the SplitMix64 state, the generator is not cryptographic but its output does not depend on the
+/// version of any dependency
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl IdGenerator {
+    pub fn new(generation: IdGeneration) -> Self {
+        let seed = match &generation {
+            IdGeneration::Random => 0,
+            IdGeneration::Seeded(seed) => *seed,
+        };
+
+        Self {
+            generation,
+            state: Arc::new(AtomicU64::new(seed)),
+        }
+    }
+
+    pub fn generation(&self) -> &IdGeneration {
+        &self.generation
+    }
+
+    pub fn next_id(&self) -> EntityId {
+        let uuid = match self.generation {
+            IdGeneration::Random => Uuid::new_v4(),
+            IdGeneration::Seeded(_) => {
+                let mut bytes = [0u8; 16];
+
+                bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+                bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+
+                Builder::from_random_bytes(bytes).into_uuid()
+            }
+        };
+
+        EntityId(uuid.to_string())
+    }
+
+    /// SplitMix64, see https://prng.di.unimi.it/splitmix64.c
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(GOLDEN_GAMMA, Ordering::SeqCst)
+            .wrapping_add(GOLDEN_GAMMA);
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Default for IdGenerator {
+    fn default() -> Self {
+        Self::new(IdGeneration::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(generator: &IdGenerator) -> Vec<EntityId> {
+        (0..3).map(|_| generator.next_id()).collect()
+    }
+
+    #[test]
+    fn seeded_ids_are_reproducible() {
+        let first = ids(&IdGenerator::new(IdGeneration::Seeded(42)));
+
+        assert_eq!(first, ids(&IdGenerator::new(IdGeneration::Seeded(42))));
+        assert_ne!(first, ids(&IdGenerator::new(IdGeneration::Seeded(43))));
+
+        // The ids are valid UUIDv4s and unique within the sequence
+        for id in &first {
+            assert_eq!(Uuid::parse_str(&id.0).unwrap().get_version_num(), 4);
+        }
+
+        assert_ne!(first[0], first[1]);
+
+        // Clones continue the sequence instead of starting it over
+        let generator = IdGenerator::new(IdGeneration::Seeded(42));
+        let clone = generator.clone();
+
+        assert_eq!(generator.next_id(), first[0]);
+        assert_eq!(clone.next_id(), first[1]);
+    }
+}
@@ -0,0 +1,69 @@
+use thiserror::Error;
+
+use crate::model::statement::Statement;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LimitExceeded {
+    #[error("Transaction has {count} statements, the limit is {limit}")]
+    Statements { count: usize, limit: usize },
+
+    #[error("Statement {index} is {bytes} bytes serialized, the limit is {limit} bytes")]
+    StatementBytes {
+        index: usize,
+        bytes: usize,
+        limit: usize,
+    },
+}
+
+/// Caps on the size of a transaction, so a single request cannot monopolize a worker and the WAL. Checked by the
+/// request manager before the transaction is queued and by the worker as a backstop, see
+/// `DatabaseOptions::set_transaction_limits`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransactionLimits {
+    pub max_statements: Option<usize>,
+    /// Size of a statement once serialized to JSON, i.e. roughly the size it takes up in the WAL
+    pub max_statement_bytes: Option<usize>,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl TransactionLimits {
+    pub fn set_max_statements(mut self, max_statements: usize) -> Self {
+        self.max_statements = Some(max_statements);
+        self
+    }
+
+    pub fn set_max_statement_bytes(mut self, max_statement_bytes: usize) -> Self {
+        self.max_statement_bytes = Some(max_statement_bytes);
+        self
+    }
+
+    pub fn check(&self, statements: &[Statement]) -> Result<(), LimitExceeded> {
+        if let Some(limit) = self.max_statements {
+            if statements.len() > limit {
+                return Err(LimitExceeded::Statements {
+                    count: statements.len(),
+                    limit,
+                });
+            }
+        }
+
+        // Statements are only serialized when there is a limit to check them against
+        if let Some(limit) = self.max_statement_bytes {
+            for (index, statement) in statements.iter().enumerate() {
+                let bytes = serde_json::to_vec(statement)
+                    .expect("Statements should always serialize")
+                    .len();
+
+                if bytes > limit {
+                    return Err(LimitExceeded::StatementBytes {
+                        index,
+                        bytes,
+                        limit,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
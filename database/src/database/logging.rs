@@ -0,0 +1,469 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::kv::{self, Key, Value, VisitSource};
+
+/// Events queued for export, more are dropped until the collector catches up
+const EXPORT_QUEUE: usize = 4096;
+
+/// Most events sent to the collector in one request
+const EXPORT_BATCH: usize = 512;
+
+/// How long an event waits for its batch to fill up before it is exported
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How log lines are written to stderr, see `--log-format`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// One line per event, fields follow the message as `key=value`
+    #[default]
+    Text,
+    /// One JSON object per line, fields are top level keys
+    Json,
+}
+
+/// Logging configuration shared by the servers. The logger is started before the config file is read, so these are
+/// only read from the command line or `LINEAGEDB_` environment variables. The level is set with `RUST_LOG`
+/// [default: info]
+///
+/// Events carry their ids as fields with the same name everywhere: `thread_id` (worker thread), `transaction_id`,
+/// `request_id` (see `Control::KillRequest`), `operation_id` and `entity_id`. HTTP requests carry `trace_id` and
+/// `span_id` when they are sent with a W3C `traceparent` header
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct LoggingConfig {
+    /// Format of the log lines on stderr [default: text]
+    #[clap(long, env = "LINEAGEDB_LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// `host:port` of an OpenTelemetry collector, log events are also exported to its OTLP/HTTP endpoint
+    /// (`/v1/logs`, JSON encoded). Plain HTTP only
+    #[clap(long, env = "LINEAGEDB_OTLP_LOGS_ENDPOINT")]
+    pub otlp_logs_endpoint: Option<String>,
+}
+
+impl LoggingConfig {
+    /// Installs the global logger, `service` is the `service.name` of exported events. Call `log::logger().flush()`
+    /// before exiting so that queued events are exported
+    pub fn init(&self, service: &str) {
+        let mut builder =
+            env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"));
+
+        match self.log_format.unwrap_or_default() {
+            LogFormat::Text => builder.format(|buf, record| {
+                write!(
+                    buf,
+                    "[{} {:<5} {}] {}",
+                    buf.timestamp(),
+                    buf.default_styled_level(record.level()),
+                    record.target(),
+                    record.args()
+                )?;
+
+                for (key, value) in LogEvent::fields(record) {
+                    match value {
+                        // Strings are quoted if they would not read back as a single value
+                        serde_json::Value::String(value)
+                            if !value
+                                .contains(|c: char| c.is_whitespace() || c == '"' || c == '=') =>
+                        {
+                            write!(buf, " {}={}", key, value)?
+                        }
+                        value => write!(buf, " {}={}", key, value)?,
+                    }
+                }
+
+                writeln!(buf)
+            }),
+            LogFormat::Json => builder
+                .format(|buf, record| writeln!(buf, "{}", LogEvent::from_record(record).to_json())),
+        };
+
+        let logger = builder.build();
+        let max_level = logger.filter();
+
+        let logger: Box<dyn log::Log> = match &self.otlp_logs_endpoint {
+            Some(endpoint) => Box::new(OtlpExporter::start(
+                logger,
+                endpoint.clone(),
+                service.to_string(),
+            )),
+            None => Box::new(logger),
+        };
+
+        log::set_boxed_logger(logger).expect("The logger should only be initialized once");
+        log::set_max_level(max_level);
+    }
+}
+
+/// Collects the key-values of a record
+struct Fields(Vec<(String, serde_json::Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_u64() {
+            value.into()
+        } else if let Some(value) = value.to_i64() {
+            value.into()
+        } else if let Some(value) = value.to_f64() {
+            value.into()
+        } else if let Some(value) = value.to_bool() {
+            value.into()
+        } else {
+            value.to_string().into()
+        };
+
+        self.0.push((key.to_string(), value));
+
+        Ok(())
+    }
+}
+
+/// A log record with its fields, written as a JSON line or exported as an OTLP log record
+#[derive(Debug)]
+struct LogEvent {
+    timestamp: SystemTime,
+    level: log::Level,
+    target: String,
+    message: String,
+    thread_name: Option<String>,
+    fields: Vec<(String, serde_json::Value)>,
+}
+
+impl LogEvent {
+    fn fields(record: &log::Record) -> Vec<(String, serde_json::Value)> {
+        let mut fields = Fields(vec![]);
+
+        // Collecting the fields cannot fail
+        let _ = record.key_values().visit(&mut fields);
+
+        fields.0
+    }
+
+    fn from_record(record: &log::Record) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            thread_name: thread::current().name().map(str::to_string),
+            fields: Self::fields(record),
+        }
+    }
+
+    fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == key)
+            .and_then(|(_, value)| value.as_str())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+
+        object.insert(
+            "timestamp".to_string(),
+            chrono::DateTime::<chrono::Utc>::from(self.timestamp)
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+                .into(),
+        );
+        object.insert("level".to_string(), self.level.as_str().into());
+        object.insert("target".to_string(), self.target.clone().into());
+        object.insert("message".to_string(), self.message.clone().into());
+
+        if let Some(thread_name) = &self.thread_name {
+            object.insert("thread_name".to_string(), thread_name.clone().into());
+        }
+
+        for (key, value) in &self.fields {
+            object.insert(key.clone(), value.clone());
+        }
+
+        serde_json::Value::Object(object)
+    }
+
+    /// See https://opentelemetry.io/docs/specs/otel/logs/data-model, the trace context is taken from the `trace_id`
+    /// and `span_id` fields
+    fn to_otlp(&self) -> serde_json::Value {
+        let severity_number = match self.level {
+            log::Level::Trace => 1,
+            log::Level::Debug => 5,
+            log::Level::Info => 9,
+            log::Level::Warn => 13,
+            log::Level::Error => 17,
+        };
+
+        let mut attributes = vec![otlp_attribute("target", &self.target.clone().into())];
+
+        if let Some(thread_name) = &self.thread_name {
+            attributes.push(otlp_attribute("thread_name", &thread_name.clone().into()));
+        }
+
+        attributes.extend(
+            self.fields
+                .iter()
+                .filter(|(key, _)| key != "trace_id" && key != "span_id")
+                .map(|(key, value)| otlp_attribute(key, value)),
+        );
+
+        let time = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string();
+
+        let mut record = serde_json::json!({
+            "timeUnixNano": time,
+            "observedTimeUnixNano": time,
+            "severityNumber": severity_number,
+            "severityText": self.level.as_str(),
+            "body": { "stringValue": self.message },
+            "attributes": attributes,
+        });
+
+        if let Some(trace_id) = self.field("trace_id") {
+            record["traceId"] = trace_id.into();
+        }
+
+        if let Some(span_id) = self.field("span_id") {
+            record["spanId"] = span_id.into();
+        }
+
+        record
+    }
+}
+
+fn otlp_attribute(key: &str, value: &serde_json::Value) -> serde_json::Value {
+    let value = match value {
+        // 64 bit integers are strings in the JSON encoding of OTLP
+        serde_json::Value::Number(number) if number.is_f64() => {
+            serde_json::json!({ "doubleValue": number })
+        }
+        serde_json::Value::Number(number) => serde_json::json!({ "intValue": number.to_string() }),
+        serde_json::Value::Bool(value) => serde_json::json!({ "boolValue": value }),
+        serde_json::Value::String(value) => serde_json::json!({ "stringValue": value }),
+        value => serde_json::json!({ "stringValue": value.to_string() }),
+    };
+
+    serde_json::json!({ "key": key, "value": value })
+}
+
+/// Body of an OTLP/HTTP export request
+fn otlp_logs_request(service: &str, events: &[LogEvent]) -> serde_json::Value {
+    serde_json::json!({
+        "resourceLogs": [{
+            "resource": {
+                "attributes": [otlp_attribute("service.name", &service.into())],
+            },
+            "scopeLogs": [{
+                "scope": { "name": "lineagedb" },
+                "logRecords": events.iter().map(LogEvent::to_otlp).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+enum ExportMessage {
+    Event(LogEvent),
+    /// Exports the queued events and then responds
+    Flush(oneshot::Sender<()>),
+}
+
+/// Writes events with the env logger and exports them to an OpenTelemetry collector from a background thread, see
+/// `LoggingConfig::otlp_logs_endpoint`. Export failures are written to stderr, they are not logged so that they do
+/// not queue more events
+struct OtlpExporter {
+    logger: env_logger::Logger,
+    sender: flume::Sender<ExportMessage>,
+}
+
+impl OtlpExporter {
+    fn start(logger: env_logger::Logger, endpoint: String, service: String) -> Self {
+        let (sender, receiver) = flume::bounded(EXPORT_QUEUE);
+
+        thread::Builder::new()
+            .name("OTLP Log Exporter".to_string())
+            .spawn(move || export_events(receiver, &endpoint, &service))
+            .expect("Should be able to spawn the OTLP log exporter thread");
+
+        Self { logger, sender }
+    }
+}
+
+impl log::Log for OtlpExporter {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.logger.matches(record) {
+            return;
+        }
+
+        self.logger.log(record);
+
+        let _ = self
+            .sender
+            .try_send(ExportMessage::Event(LogEvent::from_record(record)));
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+
+        let (flushed, wait) = oneshot::channel();
+
+        if self.sender.send(ExportMessage::Flush(flushed)).is_ok() {
+            let _ = wait.recv_timeout(EXPORT_TIMEOUT);
+        }
+    }
+}
+
+fn export_events(receiver: flume::Receiver<ExportMessage>, endpoint: &str, service: &str) {
+    while let Ok(message) = receiver.recv() {
+        let mut events = vec![];
+        let mut flushed = None;
+
+        let deadline = Instant::now() + EXPORT_INTERVAL;
+        let mut message = Some(message);
+
+        while let Some(next) = message.take() {
+            match next {
+                ExportMessage::Event(event) => events.push(event),
+                ExportMessage::Flush(sender) => {
+                    flushed = Some(sender);
+                    break;
+                }
+            }
+
+            if events.len() < EXPORT_BATCH {
+                message = receiver.recv_deadline(deadline).ok();
+            }
+        }
+
+        if !events.is_empty() {
+            if let Err(e) = post_logs(endpoint, &otlp_logs_request(service, &events)) {
+                eprintln!(
+                    "Unable to export {} log events to {}: {}",
+                    events.len(),
+                    endpoint,
+                    e
+                );
+            }
+        }
+
+        if let Some(flushed) = flushed {
+            let _ = flushed.send(());
+        }
+    }
+}
+
+fn post_logs(endpoint: &str, request: &serde_json::Value) -> io::Result<()> {
+    let body = serde_json::to_vec(request)?;
+
+    let mut stream = TcpStream::connect(endpoint)?;
+    stream.set_read_timeout(Some(EXPORT_TIMEOUT))?;
+    stream.set_write_timeout(Some(EXPORT_TIMEOUT))?;
+
+    write!(
+        stream,
+        "POST /v1/logs HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        endpoint,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    let status = response.lines().next().unwrap_or_default();
+
+    if status
+        .split(' ')
+        .nth(1)
+        .map_or(true, |code| !code.starts_with('2'))
+    {
+        return Err(io::Error::new(io::ErrorKind::Other, status.to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(level: log::Level, fields: &[(&str, Value)]) -> LogEvent {
+        LogEvent::from_record(
+            &log::Record::builder()
+                .level(level)
+                .target("database")
+                .args(format_args!("Committed"))
+                .key_values(&fields)
+                .build(),
+        )
+    }
+
+    #[test]
+    fn json_lines_have_the_fields_at_the_top_level() {
+        let line = event(
+            log::Level::Info,
+            &[
+                ("transaction_id", Value::from(12usize)),
+                ("entity_id", Value::from("abc")),
+                ("duration_ms", Value::from(1.5)),
+            ],
+        )
+        .to_json();
+
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "database");
+        assert_eq!(line["message"], "Committed");
+        assert_eq!(line["transaction_id"], 12);
+        assert_eq!(line["entity_id"], "abc");
+        assert_eq!(line["duration_ms"], 1.5);
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn otlp_records_carry_the_trace_context() {
+        let request = otlp_logs_request(
+            "lineagedb",
+            &[event(
+                log::Level::Warn,
+                &[
+                    ("trace_id", Value::from("4bf92f3577b34da6a3ce929d0e0e4736")),
+                    ("span_id", Value::from("00f067aa0ba902b7")),
+                    ("request_id", Value::from(7usize)),
+                ],
+            )],
+        );
+
+        let resource_logs = &request["resourceLogs"][0];
+        assert_eq!(
+            resource_logs["resource"]["attributes"][0]["value"]["stringValue"],
+            "lineagedb"
+        );
+
+        let record = &resource_logs["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(record["severityNumber"], 13);
+        assert_eq!(record["severityText"], "WARN");
+        assert_eq!(record["body"]["stringValue"], "Committed");
+        assert_eq!(record["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(record["spanId"], "00f067aa0ba902b7");
+
+        let attributes = record["attributes"].as_array().unwrap();
+        assert!(attributes.contains(&serde_json::json!({
+            "key": "request_id",
+            "value": { "intValue": "7" }
+        })));
+        assert!(!attributes
+            .iter()
+            .any(|attribute| attribute["key"] == "trace_id"));
+    }
+}
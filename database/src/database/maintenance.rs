@@ -0,0 +1,156 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use super::commands::{DatabaseCommand, DatabaseCommandRequest, DatabaseCommandResponse};
+
+/// Holds the transactions that arrive while the database is in maintenance mode, see `Control::EnterMaintenance`
+///
+/// Unlike a pause the worker threads keep receiving requests, transactions are parked here (up to `limit`) and
+/// replayed once the maintenance is done. Control commands are never queued so that the maintenance itself
+/// can pause the worker threads
+pub struct MaintenanceQueue {
+    /// Set while the database is in maintenance mode
+    queue: Mutex<Option<MaintenanceWindow>>,
+    limit: usize,
+}
+
+#[derive(Default)]
+struct MaintenanceWindow {
+    requests: VecDeque<DatabaseCommandRequest>,
+    rejected: usize,
+}
+
+/// Requests that were held during a maintenance window
+pub struct MaintenanceBacklog {
+    pub requests: Vec<DatabaseCommandRequest>,
+    /// Number of transactions that were rolled back because the queue was full
+    pub rejected: usize,
+}
+
+impl MaintenanceQueue {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            queue: Mutex::new(None),
+            limit,
+        }
+    }
+
+    /// Starts queuing transactions, returns false if the database is already in maintenance mode
+    pub fn begin(&self) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+
+        if queue.is_some() {
+            return false;
+        }
+
+        *queue = Some(MaintenanceWindow::default());
+
+        true
+    }
+
+    /// Stops queuing transactions and returns the requests that should be replayed in the order they arrived
+    pub fn end(&self) -> MaintenanceBacklog {
+        let window = self.queue.lock().unwrap().take().unwrap_or_default();
+
+        MaintenanceBacklog {
+            requests: window.requests.into(),
+            rejected: window.rejected,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.queue.lock().unwrap().is_some()
+    }
+
+    /// Returns the request if it should be run now. Transactions received during maintenance are queued, once
+    /// the queue is full they are rolled back instead of waiting for the maintenance to finish
+    pub fn intercept(&self, request: DatabaseCommandRequest) -> Option<DatabaseCommandRequest> {
+        if let DatabaseCommand::Control(_) = request.command {
+            return Some(request);
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+
+        let Some(window) = queue.as_mut() else {
+            return Some(request);
+        };
+
+        if window.requests.len() < self.limit {
+            window.requests.push_back(request);
+            return None;
+        }
+
+        window.rejected += 1;
+
+        let _ = request
+            .resolver
+            .send(DatabaseCommandResponse::transaction_rollback(
+                "Database is in maintenance mode and the request queue is full",
+            ));
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{
+        database::commands::{Control, DatabaseCommandTransactionResponse, TransactionContext},
+        model::statement::Statement,
+    };
+
+    use super::*;
+
+    fn request(
+        command: DatabaseCommand,
+    ) -> (
+        DatabaseCommandRequest,
+        oneshot::Receiver<DatabaseCommandResponse>,
+    ) {
+        let (resolver, receiver) = oneshot::channel();
+
+        let request = DatabaseCommandRequest {
+            command,
+            resolver,
+            transaction_context: TransactionContext::default(),
+            enqueued_at: Instant::now(),
+            permit: None,
+        };
+
+        (request, receiver)
+    }
+
+    #[test]
+    fn queues_transactions_up_to_the_limit() {
+        let maintenance = MaintenanceQueue::new(1);
+
+        let (list, _) = request(DatabaseCommand::Transaction(vec![Statement::List(None)]));
+        assert!(maintenance.intercept(list).is_some());
+
+        assert!(maintenance.begin());
+        assert!(!maintenance.begin());
+
+        let (first, _) = request(DatabaseCommand::Transaction(vec![Statement::List(None)]));
+        assert!(maintenance.intercept(first).is_none());
+
+        let (second, second_response) =
+            request(DatabaseCommand::Transaction(vec![Statement::List(None)]));
+        assert!(maintenance.intercept(second).is_none());
+        assert!(matches!(
+            second_response.recv(),
+            Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::Rollback(_)
+            ))
+        ));
+
+        // Controls are never queued
+        let (stats, _) = request(DatabaseCommand::Control(Control::DatabaseStats));
+        assert!(maintenance.intercept(stats).is_some());
+
+        let backlog = maintenance.end();
+        assert_eq!(backlog.requests.len(), 1);
+        assert_eq!(backlog.rejected, 1);
+        assert!(!maintenance.is_active());
+    }
+}
@@ -1,8 +1,49 @@
+pub mod activity;
+pub mod admin;
+pub mod audit;
+pub(crate) mod availability;
+pub mod backfill;
+pub mod capture;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod clock;
+pub mod clones;
 pub mod commands;
+pub mod config;
+pub mod context_policy;
 pub mod control;
+pub(crate) mod coordinator;
 pub mod database;
+pub mod fixtures;
+pub mod hooks;
+pub mod ids;
+pub mod limits;
+pub mod logging;
+pub mod maintenance;
+pub mod operations;
 pub mod options;
-pub mod orchestrator;
+pub(crate) mod orchestrator;
+pub mod pause;
+pub mod prepared;
+pub mod protocol;
+#[cfg(feature = "publisher")]
+pub mod publisher;
+pub(crate) mod queue_wait;
+pub mod quota;
+pub(crate) mod replay;
+pub mod request_log;
 pub mod request_manager;
+pub mod restore_verification;
+pub mod scheduler;
+pub mod seed;
+pub mod shadow;
+pub mod shard;
+pub mod statement_stats;
+pub mod system;
 pub mod table;
+pub mod tags;
+#[cfg(feature = "thread-tuning")]
+pub mod thread_tuning;
 pub mod utils;
+pub mod warmup;
+pub mod watch;
@@ -0,0 +1,207 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::commands::DatabaseCommandControlResponse;
+
+/// Finished operations that are kept for clients to poll, the oldest are dropped first
+const RETAINED_OPERATIONS: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OperationId(pub usize);
+
+impl std::fmt::Display for OperationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperationState {
+    Running,
+    /// The response the control would have returned to the caller, including errors
+    Completed(Box<DatabaseCommandControlResponse>),
+    /// The control finished without a response, e.g. the worker running it panicked
+    Abandoned,
+}
+
+/// A long running control started with `Control::StartOperation`
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperationStatus {
+    pub id: OperationId,
+    /// The control's name, e.g. `SnapshotDatabase`
+    pub kind: String,
+    /// Time the operation has been running for, or took once it has finished
+    pub elapsed: Duration,
+    pub state: OperationState,
+}
+
+impl OperationStatus {
+    pub fn is_finished(&self) -> bool {
+        self.state != OperationState::Running
+    }
+}
+
+struct Operation {
+    kind: &'static str,
+    started_at: Instant,
+    finished: Option<(Duration, OperationState)>,
+    /// Notified once the operation finishes, see `Control::WatchOperation`
+    subscribers: Vec<flume::Sender<OperationStatus>>,
+}
+
+impl Operation {
+    fn status(&self, id: OperationId) -> OperationStatus {
+        let (elapsed, state) = match &self.finished {
+            Some((elapsed, state)) => (*elapsed, state.clone()),
+            None => (self.started_at.elapsed(), OperationState::Running),
+        };
+
+        OperationStatus {
+            id,
+            kind: self.kind.to_string(),
+            elapsed,
+            state,
+        }
+    }
+}
+
+/// Controls that can take minutes (e.g. a snapshot of a large table) run as operations, the caller is
+/// acknowledged with an operation id straight away rather than holding a response channel that may time out
+/// while the control is still running
+#[derive(Default)]
+pub struct Operations {
+    next_id: AtomicUsize,
+    operations: Mutex<BTreeMap<OperationId, Operation>>,
+}
+
+impl Operations {
+    pub fn start(&self, kind: &'static str) -> OperationStatus {
+        let id = OperationId(self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let operation = Operation {
+            kind,
+            started_at: Instant::now(),
+            finished: None,
+            subscribers: vec![],
+        };
+        let status = operation.status(id);
+
+        self.operations.lock().unwrap().insert(id, operation);
+
+        status
+    }
+
+    /// Records the control's response, `None` if it did not respond
+    pub fn complete(&self, id: OperationId, response: Option<DatabaseCommandControlResponse>) {
+        let mut operations = self.operations.lock().unwrap();
+
+        if let Some(operation) = operations.get_mut(&id) {
+            let state = match response {
+                Some(response) => OperationState::Completed(Box::new(response)),
+                None => OperationState::Abandoned,
+            };
+
+            operation.finished = Some((operation.started_at.elapsed(), state));
+
+            let status = operation.status(id);
+
+            for subscriber in operation.subscribers.drain(..) {
+                let _ = subscriber.send(status.clone());
+            }
+        }
+
+        let finished: Vec<OperationId> = operations
+            .iter()
+            .filter(|(_, operation)| operation.finished.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in finished.iter().take(finished.len().saturating_sub(RETAINED_OPERATIONS)) {
+            operations.remove(id);
+        }
+    }
+
+    /// None if the operation is unknown or was finished long enough ago to have been dropped
+    pub fn status(&self, id: OperationId) -> Option<OperationStatus> {
+        self.operations
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|operation| operation.status(id))
+    }
+
+    /// Sends the status once the operation has finished, straight away if it already has. Returns false if the
+    /// operation is unknown
+    pub fn subscribe(&self, id: OperationId, subscriber: flume::Sender<OperationStatus>) -> bool {
+        let mut operations = self.operations.lock().unwrap();
+
+        let Some(operation) = operations.get_mut(&id) else {
+            return false;
+        };
+
+        match operation.finished.is_some() {
+            true => {
+                let _ = subscriber.send(operation.status(id));
+            }
+            false => operation.subscribers.push(subscriber),
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribers_are_notified_once_finished() {
+        let operations = Operations::default();
+
+        let started = operations.start("SnapshotDatabase");
+        let (sender, receiver) = flume::unbounded();
+
+        assert!(operations.subscribe(started.id, sender));
+        assert!(receiver.try_recv().is_err());
+        assert!(!operations.status(started.id).unwrap().is_finished());
+
+        operations.complete(
+            started.id,
+            Some(DatabaseCommandControlResponse::Success("Done".to_string())),
+        );
+
+        let finished = receiver.try_recv().unwrap();
+
+        assert_eq!(
+            finished.state,
+            OperationState::Completed(Box::new(DatabaseCommandControlResponse::Success(
+                "Done".to_string()
+            )))
+        );
+        assert_eq!(operations.status(started.id), Some(finished));
+        assert!(!operations.subscribe(OperationId(0), flume::unbounded().0));
+    }
+
+    #[test]
+    fn only_recent_finished_operations_are_kept() {
+        let operations = Operations::default();
+
+        let running = operations.start("SquashHistory");
+        let ids: Vec<OperationId> = (0..RETAINED_OPERATIONS + 1)
+            .map(|_| operations.start("SnapshotDatabase").id)
+            .collect();
+
+        for id in &ids {
+            operations.complete(*id, None);
+        }
+
+        assert_eq!(operations.status(ids[0]), None);
+        assert!(operations.status(ids[1]).is_some());
+        assert!(operations.status(running.id).is_some());
+    }
+}
@@ -1,10 +1,41 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
+use thiserror::Error;
 use uuid::Uuid;
 
+#[cfg(feature = "chaos")]
+use super::chaos::ChaosOptions;
+#[cfg(feature = "publisher")]
+use super::publisher::PublisherOptions;
+#[cfg(feature = "thread-tuning")]
+use super::thread_tuning::ThreadTuningOptions;
+use super::{
+    audit::RollbackAuditOptions,
+    backfill::IndexBackfillOptions,
+    capture::{CaptureReplay, ReplayPacing, RequestCaptureOptions},
+    clock::{Clock, SystemClock},
+    context_policy::ContextPolicy,
+    hooks::{LifecycleEvent, LifecycleHooks},
+    ids::IdGeneration,
+    limits::TransactionLimits,
+    quota::Quota,
+    request_log::RequestLogSampling,
+    restore_verification::RestoreVerificationOptions,
+    seed::Seed,
+    shadow::ShadowReadOptions,
+    statement_stats::StatementStatsOptions,
+    table::{conflict::ConflictResolution, constraint::ConstraintTiming, squash::HistorySquash},
+    tags::TagLimit,
+    warmup::WarmupOptions,
+};
 use crate::persistence::{
-    storage::StorageEngine,
-    transaction::{TransactionFileWriteMode, TransactionWriteMode},
+    field_encryption::FieldEncryptionOptions,
+    parquet::ParquetExportTarget,
+    pre_commit::PreCommitHook,
+    snapshot_shards::SnapshotSharding,
+    storage::{file::FileOptions, network::StorageTimeouts, validate_namespace, StorageEngine},
+    transaction::{TransactionFileWriteMode, TransactionWriteMode, UnknownStatementPolicy},
+    wal_batching::AdaptiveBatchingOptions,
 };
 
 #[derive(Debug, Clone)]
@@ -12,7 +43,62 @@ pub struct DatabaseOptions {
     pub restore: bool,
     pub write_mode: TransactionWriteMode,
     pub storage_engine: StorageEngine,
+    pub migrate_to: Option<StorageEngine>,
+    pub restore_from_backup: Option<StorageEngine>,
     pub threads: usize,
+    pub read_threads: Option<usize>,
+    pub write_threads: Option<usize>,
+    pub replay_threads: Option<usize>,
+    pub replay_checkpoint_interval: Option<usize>,
+    pub durability_self_test: bool,
+    pub hot_versions: Option<usize>,
+    pub retained_snapshots: usize,
+    pub archive_wal: bool,
+    pub pipeline_wal: bool,
+    pub adaptive_wal_batching: Option<AdaptiveBatchingOptions>,
+    pub unknown_statements: UnknownStatementPolicy,
+    pub parquet_export: Option<ParquetExportTarget>,
+    pub snapshot_sharding: Option<SnapshotSharding>,
+    pub queue_wait_slo: Option<Duration>,
+    pub pause_warn_threshold: Option<Duration>,
+    pub maintenance_queue_limit: usize,
+    pub paranoid_checks: bool,
+    pub prepared_queries_only: bool,
+    pub conflict_resolution: ConflictResolution,
+    pub unique_email: Option<ConstraintTiming>,
+    pub history_squash: Option<HistorySquash>,
+    pub index_backfill: IndexBackfillOptions,
+    pub inline_reads: bool,
+    pub id_generation: IdGeneration,
+    pub clock: Arc<dyn Clock>,
+    pub ignore_snapshot_compatibility: bool,
+    pub field_encryption: Option<FieldEncryptionOptions>,
+    pub request_log_sampling: RequestLogSampling,
+    pub shadow_reads: Option<ShadowReadOptions>,
+    /// Quotas keyed by tenant (role)
+    pub quotas: HashMap<String, Quota>,
+    /// Limits keyed by request tag
+    pub tag_limits: HashMap<String, TagLimit>,
+    pub hooks: LifecycleHooks,
+    pub pre_commit_hook: Option<PreCommitHook>,
+    pub limits: TransactionLimits,
+    pub storage_timeouts: StorageTimeouts,
+    pub warmup: Option<WarmupOptions>,
+    pub seed: Option<Seed>,
+    pub request_capture: Option<RequestCaptureOptions>,
+    pub capture_replay: Option<CaptureReplay>,
+    pub context_policy: ContextPolicy,
+    pub rollback_audit: Option<RollbackAuditOptions>,
+    pub statement_stats: Option<StatementStatsOptions>,
+    pub verify_restore: Option<RestoreVerificationOptions>,
+    #[cfg(feature = "chaos")]
+    pub chaos: Option<ChaosOptions>,
+    #[cfg(feature = "publisher")]
+    pub publisher: Option<PublisherOptions>,
+    #[cfg(feature = "thread-tuning")]
+    pub thread_tuning: Option<ThreadTuningOptions>,
+    #[cfg(feature = "index-advisor")]
+    pub auto_create_indexes: bool,
 }
 
 // Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
@@ -36,23 +122,851 @@ impl DatabaseOptions {
         self
     }
 
+    /// Defines a backup that is copied into the storage engine on startup, before the restore. The backup is the
+    /// storage of another database and may be stored by a different engine, see `restore_from_backup`. Restarting
+    /// with the backup still set only works until the restored database takes its first snapshot
+    pub fn set_restore_from_backup(mut self, backup: StorageEngine) -> Self {
+        self.restore_from_backup = Some(backup);
+        self
+    }
+
+    /// Defines a storage engine the database migrates to, writes are mirrored to it until a snapshot has been
+    /// written to both engines. The database can then be cut over to it, see `Control::CutoverMigration`
+    pub fn set_migrate_to(mut self, migrate_to: StorageEngine) -> Self {
+        self.migrate_to = Some(migrate_to);
+        self
+    }
+
     pub fn set_threads(mut self, threads: usize) -> Self {
         self.threads = threads;
         self
     }
+
+    /// Defines the size of the pool of worker threads that run read-only transactions. If the read or the write
+    /// pool is set the workers are split into separate pools, the pool that is not set has `threads` workers
+    pub fn set_read_threads(mut self, read_threads: usize) -> Self {
+        self.read_threads = Some(read_threads);
+        self
+    }
+
+    /// Defines the size of the pool of worker threads that run transactions with mutations, see `set_read_threads`
+    pub fn set_write_threads(mut self, write_threads: usize) -> Self {
+        self.write_threads = Some(write_threads);
+        self
+    }
+
+    /// Sizes of the (write, read) worker pools, none if reads and writes share the workers
+    pub fn worker_pools(&self) -> Option<(usize, usize)> {
+        if self.read_threads.is_none() && self.write_threads.is_none() {
+            return None;
+        }
+
+        Some((
+            self.write_threads.unwrap_or(self.threads).max(1),
+            self.read_threads.unwrap_or(self.threads).max(1),
+        ))
+    }
+
+    /// Defines how many threads replay the WAL on startup, transactions that touch disjoint rows are replayed in
+    /// parallel while the transactions of each row keep their WAL order. Defaults to the number of worker threads
+    pub fn set_replay_threads(mut self, replay_threads: usize) -> Self {
+        self.replay_threads = Some(replay_threads);
+        self
+    }
+
+    /// Defines how many replayed transactions there are between checkpoints of the replay, a restore that is
+    /// killed part way resumes from the latest checkpoint instead of the snapshot, see `ReplayCheckpoint`. Each
+    /// checkpoint writes the latest version of every row, so it is only worth it for very long replays
+    pub fn set_replay_checkpoint_interval(mut self, replay_checkpoint_interval: usize) -> Self {
+        self.replay_checkpoint_interval = Some(replay_checkpoint_interval);
+        self
+    }
+
+    pub fn replay_threads(&self) -> usize {
+        self.replay_threads.unwrap_or_else(|| self.worker_threads())
+    }
+
+    /// Total number of worker threads across the pools
+    pub fn worker_threads(&self) -> usize {
+        match self.worker_pools() {
+            Some((write_threads, read_threads)) => write_threads + read_threads,
+            None => self.threads,
+        }
+    }
+
+    /// Defines whether we should measure and log the sync latency of the WAL storage on startup,
+    /// this is the commit latency floor that the storage imposes
+    pub fn set_durability_self_test(mut self, durability_self_test: bool) -> Self {
+        self.durability_self_test = durability_self_test;
+        self
+    }
+
+    /// Defines how many of the most recent versions of a row are kept in memory, older versions are
+    /// spilled to the storage engine and loaded lazily for historical reads
+    pub fn set_hot_versions(mut self, hot_versions: usize) -> Self {
+        self.hot_versions = Some(hot_versions);
+        self
+    }
+
+    /// Defines how many snapshots are kept, a restore rolls back to an older snapshot if the newest is corrupt
+    pub fn set_retained_snapshots(mut self, retained_snapshots: usize) -> Self {
+        self.retained_snapshots = retained_snapshots;
+        self
+    }
+
+    /// Defines whether the WAL is archived with each snapshot instead of only being flushed, a restore that rolls
+    /// back to an older snapshot replays the archives so that no committed transaction is lost
+    pub fn set_archive_wal(mut self, archive_wal: bool) -> Self {
+        self.archive_wal = archive_wal;
+        self
+    }
+
+    /// Defines whether the WAL serializes the next batch of commits while the previous batch is being written and
+    /// synced, instead of handling one batch at a time. This hides the latency of network storage engines (e.g. S3
+    /// or DynamoDB), commits are still only responded to once they are durable and in commit order
+    pub fn set_pipeline_wal(mut self, pipeline_wal: bool) -> Self {
+        self.pipeline_wal = pipeline_wal;
+        self
+    }
+
+    /// Defines a commit latency target the WAL sizes its batches for, larger batches spread the cost of a slow
+    /// sync over more commits and smaller batches keep each write short. Otherwise up to 51 commits are written
+    /// at once. The chosen limit is reported in the database's stats as `WALBatchLimit`
+    pub fn set_adaptive_wal_batching(
+        mut self,
+        adaptive_wal_batching: AdaptiveBatchingOptions,
+    ) -> Self {
+        self.adaptive_wal_batching = Some(adaptive_wal_batching);
+        self
+    }
+
+    /// Defines what a restore does with a WAL statement written by a newer build, e.g. after rolling back an
+    /// upgrade. By default the restore fails, `UnknownStatementPolicy::Skip` replays the rest of the WAL instead
+    pub fn set_unknown_statement_policy(
+        mut self,
+        unknown_statements: UnknownStatementPolicy,
+    ) -> Self {
+        self.unknown_statements = unknown_statements;
+        self
+    }
+
+    /// Defines where the WAL is exported to as Parquet when a snapshot flushes it, so that the change history
+    /// can be analyzed without touching the live database, see `transactions_to_parquet`
+    pub fn set_parquet_export(mut self, target: ParquetExportTarget) -> Self {
+        self.parquet_export = Some(target);
+        self
+    }
+
+    /// Defines whether snapshots are split into shards that are uploaded concurrently, which shortens snapshots on
+    /// object stores where a single upload is limited in bandwidth
+    pub fn set_snapshot_sharding(mut self, snapshot_sharding: SnapshotSharding) -> Self {
+        self.snapshot_sharding = Some(snapshot_sharding);
+        self
+    }
+
+    /// Defines whether we should restore a snapshot that was written by an incompatible database configuration,
+    /// e.g. a different serialization format or table schema version
+    pub fn set_ignore_snapshot_compatibility(
+        mut self,
+        ignore_snapshot_compatibility: bool,
+    ) -> Self {
+        self.ignore_snapshot_compatibility = ignore_snapshot_compatibility;
+        self
+    }
+
+    /// Defines which fields are encrypted in the WAL, snapshots and cold version storage, and which roles
+    /// can read them. Other roles receive masked values
+    pub fn set_field_encryption(mut self, field_encryption: FieldEncryptionOptions) -> Self {
+        self.field_encryption = Some(field_encryption);
+        self
+    }
+
+    /// Defines how long a request may wait for a worker thread before a warning is logged
+    pub fn set_queue_wait_slo(mut self, queue_wait_slo: Duration) -> Self {
+        self.queue_wait_slo = Some(queue_wait_slo);
+        self
+    }
+
+    /// Defines how long a control operation (e.g. a snapshot or reset) may pause the worker threads before a
+    /// warning is logged
+    pub fn set_pause_warn_threshold(mut self, pause_warn_threshold: Duration) -> Self {
+        self.pause_warn_threshold = Some(pause_warn_threshold);
+        self
+    }
+
+    /// Defines how many transactions are queued while the database is in maintenance mode, transactions
+    /// beyond the limit are rolled back
+    pub fn set_maintenance_queue_limit(mut self, maintenance_queue_limit: usize) -> Self {
+        self.maintenance_queue_limit = maintenance_queue_limit;
+        self
+    }
+
+    /// Defines which finished transactions are logged at info along with their statement kinds, row count,
+    /// transaction id and duration, e.g. a fraction of them or only slow ones
+    pub fn set_request_log_sampling(mut self, request_log_sampling: RequestLogSampling) -> Self {
+        self.request_log_sampling = request_log_sampling;
+        self
+    }
+
+    /// Defines the quota of a tenant, i.e. requests that run as the role. Requests that exceed it fail with
+    /// `QuotaExceeded`, requests without a role or of a role without a quota are not limited
+    /// Defines a fraction of the list and prepared query reads that are duplicated to a second read path (by
+    /// default a full scan) and compared with the planned read, discrepancies are logged, see `ShadowReads`
+    pub fn set_shadow_reads(mut self, shadow_reads: ShadowReadOptions) -> Self {
+        self.shadow_reads = Some(shadow_reads);
+        self
+    }
+
+    pub fn set_quota(mut self, tenant: String, quota: Quota) -> Self {
+        self.quotas.insert(tenant, quota);
+        self
+    }
+
+    /// Limits the requests tagged with the tag (see `TransactionContext::set_tag`), e.g. so a bulk import cannot
+    /// crowd out interactive requests. Requests wait in the request manager until they are within the limit
+    pub fn set_tag_limit(mut self, tag: String, limit: TagLimit) -> Self {
+        self.tag_limits.insert(tag, limit);
+        self
+    }
+
+    /// Defines the maximum number of statements per transaction and the maximum serialized size of a statement,
+    /// larger transactions are rejected with `LimitExceeded`. There are no limits by default
+    pub fn set_transaction_limits(mut self, limits: TransactionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Defines how long the S3, DynamoDB and Postgres engines have to complete an operation before it fails with
+    /// `StorageError::Timeout`. A timed out WAL write crashes the database like any other failed WAL write
+    pub fn set_storage_timeouts(mut self, storage_timeouts: StorageTimeouts) -> Self {
+        self.storage_timeouts = storage_timeouts;
+        self
+    }
+
+    /// Defines work done on startup, after the restore and before the request manager is returned, so that the
+    /// first requests are served with warm caches, see `WarmupOptions`
+    pub fn set_warmup(mut self, warmup: WarmupOptions) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
+
+    /// Defines people (and their history) added on startup if the database is empty once it has restored, see
+    /// `Seed`
+    pub fn set_seed(mut self, seed: Seed) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Defines whether the transactions the database receives are recorded to a file for a while after it starts,
+    /// e.g. to replay a production issue locally, see `RequestCapture`
+    pub fn set_request_capture(mut self, request_capture: RequestCaptureOptions) -> Self {
+        self.request_capture = Some(request_capture);
+        self
+    }
+
+    /// Defines captured requests that are replayed once the database has started (after the seed), see
+    /// `CaptureReplay`. The replay runs in the background, the request manager is returned right away
+    pub fn set_capture_replay(mut self, capture_replay: CaptureReplay) -> Self {
+        self.capture_replay = Some(capture_replay);
+        self
+    }
+
+    /// Defines the defaults filled into the `TransactionContext` of every transaction and which fields clients may
+    /// set themselves, see `ContextPolicy`
+    pub fn set_context_policy(mut self, context_policy: ContextPolicy) -> Self {
+        self.context_policy = context_policy;
+        self
+    }
+
+    /// Defines whether the restored table is compared against a table re-derived from storage before the database
+    /// starts, see `Database::verify_restore`
+    pub fn set_verify_restore(mut self, verify_restore: RestoreVerificationOptions) -> Self {
+        self.verify_restore = Some(verify_restore);
+        self
+    }
+
+    /// Defines whether rolled back transactions with mutations are recorded in an audit stream next to the WAL,
+    /// the audit is never replayed, see `RollbackAudit`
+    pub fn set_rollback_audit(mut self, rollback_audit: RollbackAuditOptions) -> Self {
+        self.rollback_audit = Some(rollback_audit);
+        self
+    }
+
+    /// Defines whether per statement kind counters and latencies are aggregated and persisted, so they survive
+    /// restarts, see `StatementStats`
+    pub fn set_statement_stats(mut self, statement_stats: StatementStatsOptions) -> Self {
+        self.statement_stats = Some(statement_stats);
+        self
+    }
+
+    /// Defines a hook that runs once a snapshot has been promoted and the database has resumed, see `LifecycleHooks`
+    pub fn set_on_snapshot(
+        mut self,
+        hook: impl Fn(&LifecycleEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_snapshot = Some(Arc::new(hook));
+        self
+    }
+
+    /// Defines a hook that runs once the database has been reset and has resumed, see `LifecycleHooks`
+    pub fn set_on_reset(mut self, hook: impl Fn(&LifecycleEvent) + Send + Sync + 'static) -> Self {
+        self.hooks.on_reset = Some(Arc::new(hook));
+        self
+    }
+
+    /// Defines a hook that runs when shutdown begins, before the worker threads are stopped, see `LifecycleHooks`
+    pub fn set_on_shutdown(
+        mut self,
+        hook: impl Fn(&LifecycleEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.on_shutdown = Some(Arc::new(hook));
+        self
+    }
+
+    /// Defines how long the database waits for a lifecycle hook before it moves on without it
+    pub fn set_hook_timeout(mut self, timeout: Duration) -> Self {
+        self.hooks.timeout = timeout;
+        self
+    }
+
+    /// Defines a hook that must succeed before a commit is acknowledged, e.g. to write the transactions to a
+    /// second storage engine, see `PreCommitHook`
+    pub fn set_pre_commit_hook(mut self, pre_commit_hook: PreCommitHook) -> Self {
+        self.pre_commit_hook = Some(pre_commit_hook);
+        self
+    }
+
+    /// Publishes the mutations of committed transactions as CloudEvents, see `Publisher`
+    #[cfg(feature = "publisher")]
+    pub fn set_publisher(mut self, publisher: PublisherOptions) -> Self {
+        self.publisher = Some(publisher);
+        self
+    }
+
+    /// Pins the worker and WAL threads to CPUs and sets their priority, see `ThreadTuningOptions`
+    #[cfg(feature = "thread-tuning")]
+    pub fn set_thread_tuning(mut self, thread_tuning: ThreadTuningOptions) -> Self {
+        self.thread_tuning = Some(thread_tuning);
+        self
+    }
+
+    /// Defines whether the index advisor creates the indexes it recommends each time it runs, see `IndexAdvisor`
+    #[cfg(feature = "index-advisor")]
+    pub fn set_auto_create_indexes(mut self, auto_create_indexes: bool) -> Self {
+        self.auto_create_indexes = auto_create_indexes;
+        self
+    }
+
+    /// Defines which failures are injected at random while the database runs, meant for soak tests
+    #[cfg(feature = "chaos")]
+    pub fn set_chaos(mut self, chaos: ChaosOptions) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Defines whether reads and rollbacks assert the MVCC invariants of the rows they touch, e.g. no version
+    /// is visible beyond the snapshot and version ids are strictly increasing. This is slow and meant for testing
+    pub fn set_paranoid_checks(mut self, paranoid_checks: bool) -> Self {
+        self.paranoid_checks = paranoid_checks;
+        self
+    }
+
+    /// Defines whether the table can only be filtered with prepared queries, transactions that list people with an
+    /// ad-hoc query are rolled back. Lists without a query, gets and scans are still allowed
+    pub fn set_prepared_queries_only(mut self, prepared_queries_only: bool) -> Self {
+        self.prepared_queries_only = prepared_queries_only;
+        self
+    }
+
+    /// Defines how a divergent version of a row, found by replication or an import, is resolved against the
+    /// current version, see `Statement::ResolveConflict`. Replayed conflicts are resolved again on restore, so the
+    /// resolution should not change between restarts
+    pub fn set_conflict_resolution(mut self, conflict_resolution: ConflictResolution) -> Self {
+        self.conflict_resolution = conflict_resolution;
+        self
+    }
+
+    /// Defines that two live people cannot share an email, a transaction that would commit a duplicate is rolled
+    /// back. With `ConstraintTiming::Deferred` the constraint is only checked at commit, otherwise a transaction
+    /// can defer it with `TransactionContext::set_defer_constraints`. Replayed transactions are not checked again
+    pub fn set_unique_email(mut self, timing: ConstraintTiming) -> Self {
+        self.unique_email = Some(timing);
+        self
+    }
+
+    /// Defines how the history of the table's rows is squashed by `RequestManager::send_squash_history_request`,
+    /// e.g. for rows that automated systems update thousands of times a day. Squashed versions can no longer be
+    /// read, a read at one of their transactions returns the version before it
+    pub fn set_history_squash(mut self, history_squash: HistorySquash) -> Self {
+        self.history_squash = Some(history_squash);
+        self
+    }
+
+    /// Defines how fast `RequestManager::send_backfill_index_request` adds the existing rows to a new index
+    pub fn set_index_backfill(mut self, index_backfill: IndexBackfillOptions) -> Self {
+        self.index_backfill = index_backfill;
+        self
+    }
+
+    /// Defines whether single `Get` statements of an embedded database are run on the caller's thread rather than
+    /// queued for a worker, which saves the channel round trip. Reads that need the worker (e.g. of a clone, of a
+    /// limited tag or while the database is paused) are still queued, writes always are. Inline reads are not
+    /// listed as active requests nor sampled by the request log
+    pub fn set_inline_reads(mut self, inline_reads: bool) -> Self {
+        self.inline_reads = inline_reads;
+        self
+    }
+
+    /// Defines how the ids handed out by `RequestManager::next_id` are generated. Seeded ids make tests and
+    /// simulation runs reproducible, e.g. so that snapshots can be compared byte-for-byte across runs
+    pub fn set_id_generation(mut self, id_generation: IdGeneration) -> Self {
+        self.id_generation = id_generation;
+        self
+    }
+
+    /// Defines where wall clock time is read from, e.g. a `MockClock` so that audits, attachment retention,
+    /// quotas and scheduled jobs can be tested deterministically
+    pub fn set_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 impl Default for DatabaseOptions {
     fn default() -> Self {
         Self {
             write_mode: TransactionWriteMode::File(TransactionFileWriteMode::Sync),
-            storage_engine: StorageEngine::File(PathBuf::from("data")),
+            storage_engine: StorageEngine::File(FileOptions::new(PathBuf::from("data"))),
+            migrate_to: None,
+            restore_from_backup: None,
             restore: true,
             threads: 2,
+            read_threads: None,
+            write_threads: None,
+            replay_threads: None,
+            replay_checkpoint_interval: None,
+            durability_self_test: false,
+            hot_versions: None,
+            retained_snapshots: 3,
+            archive_wal: false,
+            pipeline_wal: false,
+            adaptive_wal_batching: None,
+            unknown_statements: UnknownStatementPolicy::default(),
+            parquet_export: None,
+            snapshot_sharding: None,
+            queue_wait_slo: None,
+            pause_warn_threshold: None,
+            maintenance_queue_limit: 10_000,
+            paranoid_checks: false,
+            prepared_queries_only: false,
+            conflict_resolution: ConflictResolution::default(),
+            unique_email: None,
+            history_squash: None,
+            index_backfill: IndexBackfillOptions::default(),
+            inline_reads: false,
+            id_generation: IdGeneration::default(),
+            clock: Arc::new(SystemClock),
+            ignore_snapshot_compatibility: false,
+            field_encryption: None,
+            request_log_sampling: RequestLogSampling::All,
+            shadow_reads: None,
+            quotas: HashMap::new(),
+            tag_limits: HashMap::new(),
+            hooks: LifecycleHooks::default(),
+            pre_commit_hook: None,
+            limits: TransactionLimits::default(),
+            storage_timeouts: StorageTimeouts::default(),
+            warmup: None,
+            seed: None,
+            request_capture: None,
+            capture_replay: None,
+            context_policy: ContextPolicy::default(),
+            rollback_audit: None,
+            statement_stats: None,
+            verify_restore: None,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            #[cfg(feature = "publisher")]
+            publisher: None,
+            #[cfg(feature = "thread-tuning")]
+            thread_tuning: None,
+            #[cfg(feature = "index-advisor")]
+            auto_create_indexes: false,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OptionsError {
+    #[error("`{0}` must be at least 1")]
+    ZeroThreads(&'static str),
+
+    #[error("`restore` requires a WAL, the write mode is Off. Disable restore or enable the WAL")]
+    RestoreWithoutWal,
+
+    #[error("`archive_wal` requires a WAL, the write mode is Off")]
+    ArchiveWalWithoutWal,
+
+    #[error("`parquet_export` requires a WAL, the write mode is Off")]
+    ParquetExportWithoutWal,
+
+    #[error("`retained_snapshots` must be at least 1")]
+    ZeroRetainedSnapshots,
+
+    #[error("`hot_versions` must be at least 1, every row keeps its latest version in memory")]
+    ZeroHotVersions,
+
+    #[error("Invalid storage engine `{0}`: {1}")]
+    InvalidStorageEngine(&'static str, String),
+
+    #[error("`migrate_to` must be a different storage engine than `storage_engine`")]
+    MigrateToSameEngine,
+
+    #[error(
+        "`restore_from_backup` requires `restore`, the backup would be copied but never restored"
+    )]
+    RestoreFromBackupWithoutRestore,
+
+    #[error("`verify_restore` requires `restore`, there would be nothing to verify")]
+    VerifyRestoreWithoutRestore,
+
+    #[error("`request_log_sampling` rate must be between 0 and 1, got: {0}")]
+    InvalidSampleRate(f64),
+
+    #[error("`shadow_reads` sample rate must be between 0 and 1, got: {0}")]
+    InvalidShadowSampleRate(f64),
+
+    #[error("`{0}` must be at least 1")]
+    ZeroLimit(&'static str),
+
+    #[error("`capture_replay` speed must be greater than 0, got: {0}")]
+    InvalidReplaySpeed(f64),
+
+    #[error("Storage timeout `{0}` must be greater than zero")]
+    ZeroStorageTimeout(&'static str),
+
+    #[error("The default timeout of the context policy must be greater than zero")]
+    ZeroDefaultTimeout,
+
+    #[error("Invalid thread tuning `{0}`: {1}")]
+    InvalidThreadTuning(&'static str, String),
+}
+
+/// Required fields of a storage engine, `key` is the option the engine was set with
+fn validate_storage_engine(key: &'static str, engine: &StorageEngine) -> Result<(), OptionsError> {
+    let empty_field = match engine {
+        StorageEngine::File(options) if options.base_dir.as_os_str().is_empty() => {
+            Some("the base directory is empty")
+        }
+        #[cfg(feature = "s3")]
+        StorageEngine::S3(options) if options.bucket.is_empty() => Some("the S3 bucket is empty"),
+        #[cfg(feature = "dynamodb")]
+        StorageEngine::DynamoDB(options) if options.table.is_empty() => {
+            Some("the DynamoDB table is empty")
+        }
+        #[cfg(feature = "postgres")]
+        StorageEngine::Postgres(options) if options.host.is_empty() => {
+            Some("the Postgres host is empty")
+        }
+        #[cfg(feature = "postgres")]
+        StorageEngine::Postgres(options) if options.database.is_empty() => {
+            Some("the Postgres database is empty")
         }
+        #[cfg(feature = "postgres")]
+        StorageEngine::Postgres(options) if options.user.is_empty() => {
+            Some("the Postgres user is empty")
+        }
+        _ => None,
+    };
+
+    if let Some(message) = empty_field {
+        return Err(OptionsError::InvalidStorageEngine(key, message.to_string()));
+    }
+
+    match engine.get_namespace().map(validate_namespace) {
+        Some(Err(message)) => Err(OptionsError::InvalidStorageEngine(key, message)),
+        _ => Ok(()),
     }
 }
 
+impl DatabaseOptions {
+    /// Checks the options and the combinations of options, the setters accept any value
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        for (key, threads) in [
+            ("threads", Some(self.threads)),
+            ("read_threads", self.read_threads),
+            ("write_threads", self.write_threads),
+            ("replay_threads", self.replay_threads),
+        ] {
+            if threads == Some(0) {
+                return Err(OptionsError::ZeroThreads(key));
+            }
+        }
+
+        if self.write_mode == TransactionWriteMode::Off {
+            if self.restore {
+                return Err(OptionsError::RestoreWithoutWal);
+            }
+
+            if self.archive_wal {
+                return Err(OptionsError::ArchiveWalWithoutWal);
+            }
+
+            if self.parquet_export.is_some() {
+                return Err(OptionsError::ParquetExportWithoutWal);
+            }
+        }
+
+        if self.retained_snapshots == 0 {
+            return Err(OptionsError::ZeroRetainedSnapshots);
+        }
+
+        if self.hot_versions == Some(0) {
+            return Err(OptionsError::ZeroHotVersions);
+        }
+
+        validate_storage_engine("storage_engine", &self.storage_engine)?;
+
+        if let Some(migrate_to) = &self.migrate_to {
+            validate_storage_engine("migrate_to", migrate_to)?;
+
+            if std::mem::discriminant(migrate_to) == std::mem::discriminant(&self.storage_engine) {
+                return Err(OptionsError::MigrateToSameEngine);
+            }
+        }
+
+        if let Some(backup) = &self.restore_from_backup {
+            validate_storage_engine("restore_from_backup", backup)?;
+
+            if !self.restore {
+                return Err(OptionsError::RestoreFromBackupWithoutRestore);
+            }
+        }
+
+        if self.verify_restore.is_some() && !self.restore {
+            return Err(OptionsError::VerifyRestoreWithoutRestore);
+        }
+
+        for (key, limit) in [
+            ("max_statements", self.limits.max_statements),
+            ("max_statement_bytes", self.limits.max_statement_bytes),
+            (
+                "rollback_audit.segment_size",
+                self.rollback_audit.as_ref().map(|audit| audit.segment_size),
+            ),
+            (
+                "rollback_audit.retained_segments",
+                self.rollback_audit
+                    .as_ref()
+                    .map(|audit| audit.retained_segments),
+            ),
+            (
+                "snapshot_sharding.shards",
+                self.snapshot_sharding
+                    .as_ref()
+                    .map(|sharding| sharding.shards),
+            ),
+            (
+                "snapshot_sharding.parallelism",
+                self.snapshot_sharding
+                    .as_ref()
+                    .map(|sharding| sharding.parallelism),
+            ),
+            (
+                "request_capture.max_requests",
+                self.request_capture
+                    .as_ref()
+                    .and_then(|capture| capture.max_requests),
+            ),
+        ] {
+            if limit == Some(0) {
+                return Err(OptionsError::ZeroLimit(key));
+            }
+        }
+
+        // A tag that can never run would only time out
+        if self
+            .tag_limits
+            .values()
+            .any(|limit| limit.max_concurrent == Some(0))
+        {
+            return Err(OptionsError::ZeroLimit("tag_limit.max_concurrent"));
+        }
+
+        if let Some(CaptureReplay {
+            pacing: ReplayPacing::Accelerated(speed),
+            ..
+        }) = &self.capture_replay
+        {
+            if !speed.is_finite() || *speed <= 0.0 {
+                return Err(OptionsError::InvalidReplaySpeed(*speed));
+            }
+        }
+
+        if self.replay_checkpoint_interval == Some(0) {
+            return Err(OptionsError::ZeroLimit("replay_checkpoint_interval"));
+        }
+
+        if matches!(&self.history_squash, Some(squash) if squash.window == 0) {
+            return Err(OptionsError::ZeroLimit("history_squash.window"));
+        }
+
+        if self.index_backfill.batch_size == 0 {
+            return Err(OptionsError::ZeroLimit("index_backfill.batch_size"));
+        }
+
+        #[cfg(feature = "publisher")]
+        if let Some(publisher) = &self.publisher {
+            if publisher.batch_size == 0 {
+                return Err(OptionsError::ZeroLimit("publisher.batch_size"));
+            }
+        }
+
+        #[cfg(feature = "thread-tuning")]
+        if let Some(thread_tuning) = &self.thread_tuning {
+            for (key, placement) in [
+                ("workers", &thread_tuning.workers),
+                ("wal", &thread_tuning.wal),
+            ] {
+                placement
+                    .validate()
+                    .map_err(|e| OptionsError::InvalidThreadTuning(key, e.to_string()))?;
+            }
+        }
+
+        for (key, timeout) in [
+            ("transaction_write", self.storage_timeouts.transaction_write),
+            ("blob", self.storage_timeouts.blob),
+            ("control", self.storage_timeouts.control),
+        ] {
+            if timeout.is_zero() {
+                return Err(OptionsError::ZeroStorageTimeout(key));
+            }
+        }
+
+        if self.context_policy.default_timeout.is_zero() {
+            return Err(OptionsError::ZeroDefaultTimeout);
+        }
+
+        if let RequestLogSampling::Rate(rate) = self.request_log_sampling {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(OptionsError::InvalidSampleRate(rate));
+            }
+        }
+
+        if let Some(shadow_reads) = &self.shadow_reads {
+            if !(0.0..=1.0).contains(&shadow_reads.sample_rate) {
+                return Err(OptionsError::InvalidShadowSampleRate(
+                    shadow_reads.sample_rate,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Same setters as `DatabaseOptions`, though `build` validates the options instead of leaving invalid ones to
+/// fail at runtime, see `DatabaseOptions::validate`
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseOptionsBuilder {
+    options: DatabaseOptions,
+}
+
+impl From<DatabaseOptions> for DatabaseOptionsBuilder {
+    fn from(options: DatabaseOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl DatabaseOptionsBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build(self) -> Result<DatabaseOptions, OptionsError> {
+        self.options.validate()?;
+
+        Ok(self.options)
+    }
+}
+
+/// Forwards setters of `DatabaseOptions` to the builder
+macro_rules! forward_setters {
+    ($($(#[$meta:meta])* $setter:ident($($arg:ident: $ty:ty),*);)*) => {
+        impl DatabaseOptionsBuilder {
+            $(
+                $(#[$meta])*
+                pub fn $setter(mut self, $($arg: $ty),*) -> Self {
+                    self.options = self.options.$setter($($arg),*);
+                    self
+                }
+            )*
+        }
+    };
+}
+
+forward_setters! {
+    set_restore(restore: bool);
+    set_sync_file_write(write_mode: TransactionWriteMode);
+    set_storage_engine(storage_engine: StorageEngine);
+    set_migrate_to(migrate_to: StorageEngine);
+    set_restore_from_backup(backup: StorageEngine);
+    set_threads(threads: usize);
+    set_read_threads(read_threads: usize);
+    set_write_threads(write_threads: usize);
+    set_replay_threads(replay_threads: usize);
+    set_replay_checkpoint_interval(replay_checkpoint_interval: usize);
+    set_durability_self_test(durability_self_test: bool);
+    set_hot_versions(hot_versions: usize);
+    set_retained_snapshots(retained_snapshots: usize);
+    set_archive_wal(archive_wal: bool);
+    set_pipeline_wal(pipeline_wal: bool);
+    set_adaptive_wal_batching(adaptive_wal_batching: AdaptiveBatchingOptions);
+    set_unknown_statement_policy(unknown_statements: UnknownStatementPolicy);
+    set_parquet_export(target: ParquetExportTarget);
+    set_snapshot_sharding(snapshot_sharding: SnapshotSharding);
+    set_ignore_snapshot_compatibility(ignore_snapshot_compatibility: bool);
+    set_field_encryption(field_encryption: FieldEncryptionOptions);
+    set_queue_wait_slo(queue_wait_slo: Duration);
+    set_pause_warn_threshold(pause_warn_threshold: Duration);
+    set_maintenance_queue_limit(maintenance_queue_limit: usize);
+    set_request_log_sampling(request_log_sampling: RequestLogSampling);
+    set_shadow_reads(shadow_reads: ShadowReadOptions);
+    set_quota(tenant: String, quota: Quota);
+    set_tag_limit(tag: String, limit: TagLimit);
+    set_transaction_limits(limits: TransactionLimits);
+    set_storage_timeouts(storage_timeouts: StorageTimeouts);
+    set_warmup(warmup: WarmupOptions);
+    set_seed(seed: Seed);
+    set_request_capture(request_capture: RequestCaptureOptions);
+    set_capture_replay(capture_replay: CaptureReplay);
+    set_context_policy(context_policy: ContextPolicy);
+    set_rollback_audit(rollback_audit: RollbackAuditOptions);
+    set_statement_stats(statement_stats: StatementStatsOptions);
+    set_verify_restore(verify_restore: RestoreVerificationOptions);
+    set_on_snapshot(hook: impl Fn(&LifecycleEvent) + Send + Sync + 'static);
+    set_on_reset(hook: impl Fn(&LifecycleEvent) + Send + Sync + 'static);
+    set_on_shutdown(hook: impl Fn(&LifecycleEvent) + Send + Sync + 'static);
+    set_hook_timeout(timeout: Duration);
+    set_pre_commit_hook(pre_commit_hook: PreCommitHook);
+    set_paranoid_checks(paranoid_checks: bool);
+    set_prepared_queries_only(prepared_queries_only: bool);
+    set_conflict_resolution(conflict_resolution: ConflictResolution);
+    set_unique_email(timing: ConstraintTiming);
+    set_history_squash(history_squash: HistorySquash);
+    set_index_backfill(index_backfill: IndexBackfillOptions);
+    set_inline_reads(inline_reads: bool);
+    set_id_generation(id_generation: IdGeneration);
+    set_clock(clock: Arc<dyn Clock>);
+    #[cfg(feature = "chaos")]
+    set_chaos(chaos: ChaosOptions);
+    #[cfg(feature = "publisher")]
+    set_publisher(publisher: PublisherOptions);
+    #[cfg(feature = "thread-tuning")]
+    set_thread_tuning(thread_tuning: ThreadTuningOptions);
+}
+
 #[cfg(test)]
 impl DatabaseOptions {
     pub fn new_test() -> Self {
@@ -61,10 +975,11 @@ impl DatabaseOptions {
             .collect();
 
         let options = DatabaseOptions::default()
-            .set_storage_engine(StorageEngine::File(database_dir))
+            .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
             .set_restore(false)
             .set_threads(2)
-            .set_sync_file_write(TransactionWriteMode::Off);
+            .set_sync_file_write(TransactionWriteMode::Off)
+            .set_paranoid_checks(true);
 
         return options;
     }
@@ -77,7 +992,7 @@ impl DatabaseOptions {
             .collect();
 
         let options = DatabaseOptions::default()
-            .set_storage_engine(StorageEngine::File(database_dir))
+            .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
             .set_restore(false)
             .set_threads(2)
             .set_sync_file_write(TransactionWriteMode::Off);
@@ -85,3 +1000,88 @@ impl DatabaseOptions {
         return options;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_rejects_invalid_combinations() {
+        let valid = DatabaseOptionsBuilder::new()
+            .set_restore(false)
+            .set_sync_file_write(TransactionWriteMode::Off)
+            .set_threads(4)
+            .build()
+            .unwrap();
+
+        assert_eq!(valid.threads, 4);
+
+        let invalid = [
+            (
+                DatabaseOptionsBuilder::new().set_threads(0),
+                OptionsError::ZeroThreads("threads"),
+            ),
+            (
+                DatabaseOptionsBuilder::new().set_sync_file_write(TransactionWriteMode::Off),
+                OptionsError::RestoreWithoutWal,
+            ),
+            (
+                DatabaseOptionsBuilder::new()
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(PathBuf::new()))),
+                OptionsError::InvalidStorageEngine(
+                    "storage_engine",
+                    "the base directory is empty".to_string(),
+                ),
+            ),
+            (
+                DatabaseOptionsBuilder::new().set_storage_engine(
+                    StorageEngine::File(FileOptions::new(PathBuf::from("data")))
+                        .set_namespace("../prod".to_string()),
+                ),
+                OptionsError::InvalidStorageEngine(
+                    "storage_engine",
+                    "the namespace '../prod' must only contain ASCII letters, digits, '-' and '_'"
+                        .to_string(),
+                ),
+            ),
+            (
+                DatabaseOptionsBuilder::from(DatabaseOptions::new_test()).set_migrate_to(
+                    StorageEngine::File(FileOptions::new(PathBuf::from("other"))),
+                ),
+                OptionsError::MigrateToSameEngine,
+            ),
+            (
+                DatabaseOptionsBuilder::new()
+                    .set_restore(false)
+                    .set_restore_from_backup(StorageEngine::File(FileOptions::new(PathBuf::from(
+                        "backup",
+                    )))),
+                OptionsError::RestoreFromBackupWithoutRestore,
+            ),
+            (
+                DatabaseOptionsBuilder::new()
+                    .set_restore(false)
+                    .set_verify_restore(RestoreVerificationOptions::default()),
+                OptionsError::VerifyRestoreWithoutRestore,
+            ),
+        ];
+
+        for (builder, expected) in invalid {
+            assert_eq!(builder.build().err(), Some(expected));
+        }
+
+        #[cfg(feature = "s3")]
+        assert_eq!(
+            DatabaseOptionsBuilder::new()
+                .set_storage_engine(StorageEngine::S3(
+                    crate::persistence::storage::s3::S3Options::new(String::new())
+                ))
+                .build()
+                .err(),
+            Some(OptionsError::InvalidStorageEngine(
+                "storage_engine",
+                "the S3 bucket is empty".to_string(),
+            ))
+        );
+    }
+}
@@ -1,14 +1,30 @@
+use std::{sync::RwLockWriteGuard, time::Instant};
+
 use flume::Sender;
 
-use super::request_manager::RequestManager;
+use super::{
+    pause::{PauseOperation, PauseTracker},
+    request_manager::RequestManager,
+};
 
 // Is there a way to replace this with a barrier synchronization?
-pub struct DatabasePauseEvent {
+pub struct DatabasePauseEvent<'a> {
     resume_txs: Vec<Sender<()>>,
+    operation: PauseOperation,
+    tracker: &'a PauseTracker,
+    started: Instant,
+    paused: Instant,
+    /// Released after the workers are resumed, see `PauseTracker::try_inline_read`
+    _inline_reads: RwLockWriteGuard<'a, ()>,
 }
 
-impl DatabasePauseEvent {
-    pub fn new(database_request_managers: &Vec<RequestManager>) -> Self {
+impl<'a> DatabasePauseEvent<'a> {
+    pub fn new(
+        database_request_managers: &Vec<RequestManager>,
+        tracker: &'a PauseTracker,
+        operation: PauseOperation,
+    ) -> Self {
+        let started = Instant::now();
         let mut resume_txs = vec![];
 
         // Send request to every DB thread, telling them to shutdown / stop working
@@ -27,12 +43,23 @@ impl DatabasePauseEvent {
                 .expect("Should respond to pause request");
         }
 
-        Self { resume_txs }
+        // Reads on the callers' threads are not paused with the workers, they are waited for
+        let inline_reads = tracker.stop_inline_reads();
+
+        Self {
+            resume_txs,
+            operation,
+            tracker,
+            started,
+            // Every thread has acknowledged the pause, the world is stopped from here on
+            paused: Instant::now(),
+            _inline_reads: inline_reads,
+        }
     }
 }
 
 // TODO: We should turn this into a guard
-impl Drop for DatabasePauseEvent {
+impl Drop for DatabasePauseEvent<'_> {
     fn drop(&mut self) {
         let resume_txs = &self.resume_txs;
 
@@ -40,5 +67,11 @@ impl Drop for DatabasePauseEvent {
         for resume_tx in resume_txs {
             let _ = resume_tx.send(());
         }
+
+        self.tracker.record(
+            self.operation,
+            self.paused.duration_since(self.started),
+            self.paused.elapsed(),
+        );
     }
 }
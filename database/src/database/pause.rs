@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{Display, Formatter},
+    sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
+};
+
+/// Number of recent pauses per operation used to calculate the rolling pause percentiles
+const WINDOW_SIZE: usize = 128;
+
+/// Control operations that stop the world with a `DatabasePauseEvent`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PauseOperation {
+    Snapshot,
+    Reset,
+    MaintenanceSnapshot,
+    VerifySnapshot,
+    Export,
+    SnapshotDownload,
+    CreateView,
+    AttachmentVacuum,
+    HistorySquash,
+    SnapshotDiff,
+    CreateIndex,
+    RenameIdPrefix,
+}
+
+impl Display for PauseOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let operation = match self {
+            PauseOperation::Snapshot => "Snapshot",
+            PauseOperation::Reset => "Reset",
+            PauseOperation::MaintenanceSnapshot => "MaintenanceSnapshot",
+            PauseOperation::VerifySnapshot => "VerifySnapshot",
+            PauseOperation::Export => "Export",
+            PauseOperation::SnapshotDownload => "SnapshotDownload",
+            PauseOperation::CreateView => "CreateView",
+            PauseOperation::AttachmentVacuum => "AttachmentVacuum",
+            PauseOperation::HistorySquash => "HistorySquash",
+            PauseOperation::SnapshotDiff => "SnapshotDiff",
+            PauseOperation::CreateIndex => "CreateIndex",
+            PauseOperation::RenameIdPrefix => "RenameIdPrefix",
+        };
+
+        write!(f, "{}", operation)
+    }
+}
+
+#[derive(Default)]
+struct PauseWindow {
+    count: u64,
+    max_stopped: Duration,
+    /// Rolling window of (time to acquire the pause, time the world was stopped)
+    pauses: VecDeque<(Duration, Duration)>,
+}
+
+/// Tracks how long control operations take to pause every worker thread and how long the worker threads stay
+/// paused. Requests are not served while the world is stopped, so long pauses show up in the tail latency
+pub struct PauseTracker {
+    windows: Mutex<BTreeMap<PauseOperation, PauseWindow>>,
+    /// When set, a warning is logged for each pause that stopped the world for longer than this threshold
+    warn_threshold: Option<Duration>,
+    /// Reads run on the caller's thread (see `DatabaseOptions::set_inline_reads`) hold the gate for reading, a
+    /// pause holds it for writing once every worker has paused
+    inline_reads: RwLock<()>,
+}
+
+impl PauseTracker {
+    pub fn new(warn_threshold: Option<Duration>) -> Self {
+        Self {
+            windows: Mutex::new(BTreeMap::new()),
+            warn_threshold,
+            inline_reads: RwLock::new(()),
+        }
+    }
+
+    /// None while the world is stopped, the read is sent to a worker instead
+    pub fn try_inline_read(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        self.inline_reads.try_read().ok()
+    }
+
+    /// Waits for the running inline reads to finish, no other inline read starts until the guard is dropped
+    pub fn stop_inline_reads(&self) -> RwLockWriteGuard<'_, ()> {
+        self.inline_reads.write().unwrap()
+    }
+
+    /// Records the time a control operation waited for every worker thread to pause (acquire) and the time the
+    /// worker threads were paused for (stopped)
+    pub fn record(&self, operation: PauseOperation, acquire: Duration, stopped: Duration) {
+        match self.warn_threshold {
+            Some(threshold) if stopped > threshold => log::warn!(
+                "[{}] Database was paused for {:.2}ms (acquired in {:.2}ms), exceeds threshold of {:.2}ms",
+                operation,
+                stopped.as_secs_f64() * 1000.0,
+                acquire.as_secs_f64() * 1000.0,
+                threshold.as_secs_f64() * 1000.0
+            ),
+            _ => log::debug!(
+                "[{}] Database was paused for {:.2}ms (acquired in {:.2}ms)",
+                operation,
+                stopped.as_secs_f64() * 1000.0,
+                acquire.as_secs_f64() * 1000.0
+            ),
+        }
+
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(operation).or_default();
+
+        window.count += 1;
+        window.max_stopped = window.max_stopped.max(stopped);
+
+        if window.pauses.len() == WINDOW_SIZE {
+            window.pauses.pop_front();
+        }
+
+        window.pauses.push_back((acquire, stopped));
+    }
+
+    /// Count, rolling p99 acquire and stopped durations, and the longest pause of each operation that has
+    /// paused the database
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        let windows = self.windows.lock().unwrap();
+
+        windows
+            .iter()
+            .flat_map(|(operation, window)| {
+                let acquire = p99(window.pauses.iter().map(|(acquire, _)| *acquire));
+                let stopped = p99(window.pauses.iter().map(|(_, stopped)| *stopped));
+
+                vec![
+                    (
+                        format!("PauseCount[{}]", operation),
+                        window.count.to_string(),
+                    ),
+                    (format!("PauseAcquireP99Ms[{}]", operation), to_ms(acquire)),
+                    (format!("PauseStoppedP99Ms[{}]", operation), to_ms(stopped)),
+                    (
+                        format!("PauseStoppedMaxMs[{}]", operation),
+                        to_ms(window.max_stopped),
+                    ),
+                ]
+            })
+            .collect()
+    }
+}
+
+fn p99(durations: impl Iterator<Item = Duration>) -> Duration {
+    let mut durations = durations.collect::<Vec<Duration>>();
+    durations.sort();
+
+    let index = ((durations.len() as f64 * 0.99).ceil() as usize).saturating_sub(1);
+
+    durations.get(index).copied().unwrap_or_default()
+}
+
+fn to_ms(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(tracker: &PauseTracker, key: &str) -> String {
+        tracker
+            .get_stats()
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .unwrap()
+    }
+
+    #[test]
+    fn reports_pauses_per_operation() {
+        let tracker = PauseTracker::new(Some(Duration::from_millis(50)));
+
+        assert!(tracker.get_stats().is_empty());
+
+        for stopped in 1..=100 {
+            tracker.record(
+                PauseOperation::Snapshot,
+                Duration::from_millis(1),
+                Duration::from_millis(stopped),
+            );
+        }
+
+        tracker.record(
+            PauseOperation::Reset,
+            Duration::from_millis(2),
+            Duration::from_millis(3),
+        );
+
+        assert_eq!(stat(&tracker, "PauseCount[Snapshot]"), "100");
+        assert_eq!(stat(&tracker, "PauseAcquireP99Ms[Snapshot]"), "1.000");
+        assert_eq!(stat(&tracker, "PauseStoppedP99Ms[Snapshot]"), "99.000");
+        assert_eq!(stat(&tracker, "PauseStoppedMaxMs[Snapshot]"), "100.000");
+        assert_eq!(stat(&tracker, "PauseCount[Reset]"), "1");
+        assert_eq!(stat(&tracker, "PauseStoppedMaxMs[Reset]"), "3.000");
+
+        // Old samples fall out of the window, the maximum is kept
+        for _ in 0..WINDOW_SIZE {
+            tracker.record(
+                PauseOperation::Snapshot,
+                Duration::from_millis(1),
+                Duration::from_millis(1),
+            );
+        }
+
+        assert_eq!(stat(&tracker, "PauseStoppedP99Ms[Snapshot]"), "1.000");
+        assert_eq!(stat(&tracker, "PauseStoppedMaxMs[Snapshot]"), "100.000");
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::BTreeMap,
+    sync::{RwLock, RwLockReadGuard},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{consts::consts::TransactionId, model::statement::Statement};
+
+/// Phase one of a two-phase commit, the statements were validated and their rows are locked until the
+/// coordinator commits or aborts the global transaction
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PreparedTransaction {
+    /// Id the coordinator assigned to the transaction, shared by every participant
+    pub global_id: String,
+    /// Transaction id of the prepare record
+    pub transaction_id: TransactionId,
+    pub statements: Vec<Statement>,
+}
+
+/// Transactions that are prepared but not yet committed or aborted (in-doubt), see `Control::PrepareTransaction`
+///
+/// Prepared transactions are stored in the WAL and the snapshot metadata so they survive restarts. Mutations of
+/// a row held by a prepared transaction are rolled back, which guarantees the prepared statements still apply
+/// once the coordinator commits
+#[derive(Default)]
+pub struct PreparedTransactions {
+    prepared: RwLock<BTreeMap<String, PreparedTransaction>>,
+}
+
+impl PreparedTransactions {
+    /// Mutations hold the locks while they are applied, prepares wait for running mutations so a row cannot
+    /// change between a prepare validating it and locking it
+    pub fn locks(&self) -> PreparedLocks<'_> {
+        PreparedLocks(self.prepared.read().unwrap())
+    }
+
+    /// Validates and locks the statements' rows, nothing is locked if the validation fails
+    pub fn prepare(
+        &self,
+        transaction: PreparedTransaction,
+        validate: impl FnOnce(&[Statement]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut prepared = self.prepared.write().unwrap();
+
+        if prepared.contains_key(&transaction.global_id) {
+            return Err(format!(
+                "Transaction {} is already prepared",
+                transaction.global_id
+            ));
+        }
+
+        if let Some(message) = Self::find_lock(&prepared, &transaction.statements) {
+            return Err(message);
+        }
+
+        validate(&transaction.statements)?;
+
+        prepared.insert(transaction.global_id.clone(), transaction);
+
+        Ok(())
+    }
+
+    /// Removes the prepared transaction and runs `resolve` (e.g. applying its statements) before other
+    /// mutations can touch its rows. `resolve` receives `None` if the transaction is not prepared
+    pub fn resolve<T>(
+        &self,
+        global_id: &str,
+        resolve: impl FnOnce(Option<PreparedTransaction>) -> T,
+    ) -> T {
+        let mut prepared = self.prepared.write().unwrap();
+
+        resolve(prepared.remove(global_id))
+    }
+
+    /// Used when restoring from the snapshot metadata and the WAL
+    pub fn insert(&self, transaction: PreparedTransaction) {
+        self.prepared
+            .write()
+            .unwrap()
+            .insert(transaction.global_id.clone(), transaction);
+    }
+
+    pub fn remove(&self, global_id: &str) -> Option<PreparedTransaction> {
+        self.prepared.write().unwrap().remove(global_id)
+    }
+
+    pub fn list(&self) -> Vec<PreparedTransaction> {
+        self.prepared.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn reset(&self) {
+        self.prepared.write().unwrap().clear();
+    }
+
+    fn find_lock(
+        prepared: &BTreeMap<String, PreparedTransaction>,
+        statements: &[Statement],
+    ) -> Option<String> {
+        let ids = statements
+            .iter()
+            .flat_map(|statement| statement.mutated_ids());
+
+        for id in ids {
+            let holder = prepared.values().find(|transaction| {
+                transaction
+                    .statements
+                    .iter()
+                    .any(|statement| statement.mutated_ids().contains(&id))
+            });
+
+            if let Some(holder) = holder {
+                return Some(format!(
+                    "Row {} is locked by prepared transaction {}",
+                    id, holder.global_id
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+/// Rows held by prepared transactions, see `PreparedTransactions::locks`
+pub struct PreparedLocks<'a>(RwLockReadGuard<'a, BTreeMap<String, PreparedTransaction>>);
+
+impl PreparedLocks<'_> {
+    /// Fails if a statement mutates a row held by a prepared transaction
+    pub fn check(&self, statements: &[Statement]) -> Result<(), String> {
+        match PreparedTransactions::find_lock(&self.0, statements) {
+            Some(message) => Err(message),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{consts::consts::EntityId, model::person::Person};
+
+    use super::*;
+
+    #[test]
+    fn prepared_rows_are_locked() {
+        let prepared = PreparedTransactions::default();
+        let person = Person::new("Prepared".to_string(), None);
+
+        let transaction = PreparedTransaction {
+            global_id: "gtx".to_string(),
+            transaction_id: TransactionId(1),
+            statements: vec![Statement::Add(person.clone())],
+        };
+
+        // Failed validations do not lock
+        assert!(prepared
+            .prepare(transaction.clone(), |_| Err("invalid".to_string()))
+            .is_err());
+        assert!(prepared.list().is_empty());
+
+        prepared.prepare(transaction, |_| Ok(())).unwrap();
+
+        assert!(prepared
+            .locks()
+            .check(&[Statement::Remove(person.id.clone())])
+            .is_err());
+        assert!(prepared
+            .locks()
+            .check(&[Statement::Remove(EntityId::new())])
+            .is_ok());
+
+        assert_eq!(
+            prepared.resolve("gtx", |t| t.map(|t| t.global_id)),
+            Some("gtx".to_string())
+        );
+        assert!(prepared
+            .locks()
+            .check(&[Statement::Remove(person.id)])
+            .is_ok());
+    }
+}
@@ -0,0 +1,322 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use strum::{IntoEnumIterator, VariantNames};
+use thiserror::Error;
+
+use crate::model::statement::{Statement, StatementResult};
+
+/// Version of the client protocol, incremented when a change is not backwards compatible
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version the database still serves, older clients are rejected during negotiation
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// How results are serialized for the client
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    strum_macros::EnumIter,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum SerializationFormat {
+    Json,
+}
+
+/// Optional functionality, a client that was built against a newer database only uses the features that
+/// negotiation returned
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    strum_macros::EnumIter,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+pub enum Feature {
+    /// `Statement::ListPage` with cursors
+    Pagination,
+    /// Reads at an earlier transaction id, see `SnapshotTimestamp`
+    TimeTravel,
+    /// `Statement::Lineage`, renames, merges and splits
+    Lineage,
+    /// Materialized views, see `ViewDefinition`
+    Views,
+    /// `Statement::QuerySystemTable`
+    SystemTables,
+    /// Two-phase commit, see `Control::PrepareTransaction`
+    PreparedTransactions,
+    /// Zero-copy clones of the table, see `Control::CloneAtTransaction`
+    Clones,
+    /// Scheduled jobs, see `JobDefinition`
+    Jobs,
+    /// Row policies, see `RowPolicy`
+    RowPolicies,
+    /// Prepared queries, see `PreparedQueryDefinition`
+    PreparedQueries,
+}
+
+/// What the database supports, advertised to clients so they can degrade gracefully
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    /// Kinds of statements the database runs, e.g. `Add` or `ListPage`
+    pub statements: Vec<String>,
+    pub formats: Vec<SerializationFormat>,
+    pub features: Vec<Feature>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            statements: Statement::VARIANTS
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect(),
+            formats: SerializationFormat::iter().collect(),
+            features: Feature::iter().collect(),
+        }
+    }
+}
+
+/// Sent by a client to start a session. Formats and features are names rather than enums, so a client built
+/// against a newer database can list ones this database does not know about
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClientHello {
+    /// Newest protocol version the client speaks
+    pub protocol_version: u32,
+    /// Formats the client can read, in order of preference, e.g. `Json`
+    #[serde(default)]
+    pub formats: Vec<String>,
+    /// Features the client would like to use, e.g. `Pagination`
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+/// What the client and the database agreed on
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Negotiated {
+    pub protocol_version: u32,
+    pub format: SerializationFormat,
+    /// Features the client asked for that the database supports, the client should not use the others
+    pub features: Vec<Feature>,
+    /// Features the client asked for that the database does not support
+    pub unsupported_features: Vec<String>,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum NegotiationError {
+    #[error(
+        "Protocol version {client} is not supported, the database supports versions {min} to {max}"
+    )]
+    UnsupportedProtocolVersion { client: u32, min: u32, max: u32 },
+
+    #[error("None of the serialization formats are supported, the database supports: {0:?}")]
+    UnsupportedFormat(Vec<SerializationFormat>),
+}
+
+impl Capabilities {
+    /// Agrees on the newest protocol version both sides speak, the client's most preferred format and the
+    /// features both sides support. Clients that do not list formats get the first format of the database
+    pub fn negotiate(&self, hello: &ClientHello) -> Result<Negotiated, NegotiationError> {
+        if hello.protocol_version < self.min_protocol_version {
+            return Err(NegotiationError::UnsupportedProtocolVersion {
+                client: hello.protocol_version,
+                min: self.min_protocol_version,
+                max: self.protocol_version,
+            });
+        }
+
+        let format = match hello.formats.is_empty() {
+            true => self.formats.first(),
+            false => hello.formats.iter().find_map(|format| {
+                self.formats
+                    .iter()
+                    .find(|supported| supported.to_string() == *format)
+            }),
+        }
+        .copied()
+        .ok_or_else(|| NegotiationError::UnsupportedFormat(self.formats.clone()))?;
+
+        let mut features = vec![];
+        let mut unsupported_features = vec![];
+
+        for requested in &hello.features {
+            match requested.parse::<Feature>() {
+                Ok(feature) if self.features.contains(&feature) => features.push(feature),
+                _ => unsupported_features.push(requested.clone()),
+            }
+        }
+
+        Ok(Negotiated {
+            protocol_version: hello.protocol_version.min(self.protocol_version),
+            format,
+            features,
+            unsupported_features,
+        })
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StatementFrameError {
+    #[error("Invalid statements: {0}")]
+    InvalidJson(String),
+
+    #[error("A transaction needs at least one statement")]
+    Empty,
+}
+
+/// Parses a JSON statement frame, either a single statement or an array of statements run as one transaction, e.g.
+/// `{"Get":"luke"}` or `[{"Remove":"luke"},{"Count":null}]`. Statements are decoded with their serde derive, so a
+/// statement added to the database is available to every client that speaks the frame without a new mapping
+pub fn decode_statements(frame: &str) -> Result<Vec<Statement>, StatementFrameError> {
+    let statements = match frame.trim_start().starts_with('[') {
+        true => serde_json::from_str::<Vec<Statement>>(frame),
+        false => serde_json::from_str::<Statement>(frame).map(|statement| vec![statement]),
+    }
+    .map_err(|e| StatementFrameError::InvalidJson(e.to_string()))?;
+
+    match statements.is_empty() {
+        true => Err(StatementFrameError::Empty),
+        false => Ok(statements),
+    }
+}
+
+/// Counterpart of `decode_statements`, the results are a JSON array in the order of the statements
+pub fn encode_results(results: &[StatementResult]) -> String {
+    serde_json::to_string(results).expect("statement results are always serializable")
+}
+
+/// Proto3 definition of the statement frame, generated from the `Statement` and `StatementResult` enums. Each
+/// variant is a field of a `oneof` whose payload is the JSON encoding of the variant, field numbers follow the
+/// declaration order so new variants must be added at the end of the enums, see `tests/statement_proto.rs`
+pub fn statement_proto() -> String {
+    let oneof = |message: &str, variants: &[&str]| {
+        let mut definition = format!("message {} {{\n  oneof kind {{\n", message);
+
+        for (index, variant) in variants.iter().enumerate() {
+            let _ = writeln!(
+                definition,
+                "    string {} = {};",
+                to_snake_case(variant),
+                index + 1
+            );
+        }
+
+        definition.push_str("  }\n}\n");
+        definition
+    };
+
+    [
+        "syntax = \"proto3\";\n\npackage lineagedb;\n".to_string(),
+        "// Generated by `statement_proto`, payloads are the JSON encoding of the variant\n"
+            .to_string(),
+        oneof("Statement", Statement::VARIANTS),
+        oneof("StatementResult", StatementResult::VARIANTS),
+        "message Transaction {\n  repeated Statement statements = 1;\n}\n".to_string(),
+        "message TransactionResult {\n  repeated StatementResult results = 1;\n}\n".to_string(),
+    ]
+    .join("\n")
+}
+
+fn to_snake_case(variant: &str) -> String {
+    let mut name = String::new();
+
+    for (index, c) in variant.chars().enumerate() {
+        if c.is_uppercase() && index > 0 {
+            name.push('_');
+        }
+
+        name.push(c.to_ascii_lowercase());
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_down_to_the_common_protocol() {
+        let capabilities = Capabilities {
+            features: vec![Feature::Pagination],
+            ..Capabilities::default()
+        };
+
+        assert!(capabilities.statements.contains(&"ListPage".to_string()));
+
+        // A newer client is served at the database's version and told which features to avoid
+        let negotiated = capabilities
+            .negotiate(&ClientHello {
+                protocol_version: PROTOCOL_VERSION + 1,
+                formats: vec!["Msgpack".to_string(), "Json".to_string()],
+                features: vec![
+                    "Pagination".to_string(),
+                    "Views".to_string(),
+                    "Subscriptions".to_string(),
+                ],
+            })
+            .unwrap();
+
+        assert_eq!(negotiated.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(negotiated.format, SerializationFormat::Json);
+        assert_eq!(negotiated.features, vec![Feature::Pagination]);
+        assert_eq!(
+            negotiated.unsupported_features,
+            vec!["Views".to_string(), "Subscriptions".to_string()]
+        );
+
+        let too_old = ClientHello {
+            protocol_version: MIN_PROTOCOL_VERSION - 1,
+            formats: vec![],
+            features: vec![],
+        };
+
+        assert!(matches!(
+            capabilities.negotiate(&too_old),
+            Err(NegotiationError::UnsupportedProtocolVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_single_statements_and_transactions() {
+        let single = decode_statements(r#"{"Get":"luke"}"#).unwrap();
+
+        assert!(matches!(single.as_slice(), [Statement::Get(id)] if id.0 == "luke"));
+
+        let transaction = decode_statements(r#"[{"Remove":"luke"},{"Count":null}]"#).unwrap();
+
+        assert!(matches!(
+            transaction.as_slice(),
+            [Statement::Remove(_), Statement::Count(None)]
+        ));
+
+        assert!(matches!(
+            decode_statements("[]"),
+            Err(StatementFrameError::Empty)
+        ));
+        assert!(matches!(
+            decode_statements(r#"{"Teleport":"luke"}"#),
+            Err(StatementFrameError::InvalidJson(_))
+        ));
+
+        assert_eq!(
+            encode_results(&[StatementResult::Count(2), StatementResult::Exists(true)]),
+            r#"[{"Count":2},{"Exists":true}]"#
+        );
+    }
+}
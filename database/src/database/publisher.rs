@@ -0,0 +1,809 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    consts::consts::TransactionId,
+    model::statement::Statement,
+    persistence::{
+        storage::{ReadBlobState, Storage, StorageError, StorageResult},
+        transaction::Transaction,
+    },
+};
+
+/// A committed mutation as a CloudEvent (https://cloudevents.io, structured JSON mode). Events of a transaction are
+/// numbered, so `id` is unique and stable across redeliveries and consumers can drop duplicates
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CloudEvent {
+    pub specversion: String,
+    pub id: String,
+    pub source: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The first row the statement mutated
+    pub subject: Option<String>,
+    pub datacontenttype: String,
+    /// Extension attribute, the transaction the statement was committed in
+    pub lineagedbtransaction: String,
+    /// The statement as it is written to the WAL
+    pub data: serde_json::Value,
+}
+
+impl CloudEvent {
+    /// Events of the mutations of a transaction, reads and sequences are not published
+    pub fn from_transaction(source: &str, transaction: &Transaction) -> Vec<CloudEvent> {
+        transaction
+            .statements
+            .iter()
+            .enumerate()
+            .filter(|(_, statement)| !statement.mutated_ids().is_empty())
+            .map(|(index, statement)| {
+                CloudEvent::from_statement(source, &transaction.id, index, statement)
+            })
+            .collect()
+    }
+
+    fn from_statement(
+        source: &str,
+        transaction_id: &TransactionId,
+        index: usize,
+        statement: &Statement,
+    ) -> CloudEvent {
+        let kind: &'static str = statement.into();
+
+        CloudEvent {
+            specversion: "1.0".to_string(),
+            id: format!("{}-{}", transaction_id, index),
+            source: source.to_string(),
+            event_type: format!("com.lineagedb.{}", kind.to_lowercase()),
+            subject: statement.mutated_ids().first().map(|id| id.to_string()),
+            datacontenttype: "application/json".to_string(),
+            lineagedbtransaction: transaction_id.to_string(),
+            data: serde_json::to_value(statement).unwrap(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PublishError {
+    #[error("Unable to reach the broker: {0}")]
+    Connection(#[from] io::Error),
+
+    #[error("The broker rejected the events: {0}")]
+    Rejected(String),
+}
+
+/// Where events are published, `publish` returns once the broker has accepted every event of the batch. A batch
+/// that fails is retried as a whole, so a sink can see an event more than once
+pub trait EventSink: Send {
+    fn publish(&mut self, events: &[CloudEvent]) -> Result<(), PublishError>;
+}
+
+/// Publishes to a subject with the NATS core protocol, the events are persisted if a JetStream stream is bound to
+/// the subject. Plain TCP only, TLS and authentication are not supported
+pub struct NatsSink {
+    address: String,
+    subject: String,
+    timeout: Duration,
+    connection: Option<(BufReader<TcpStream>, TcpStream)>,
+}
+
+impl NatsSink {
+    pub fn new(address: String, subject: String, timeout: Duration) -> Self {
+        Self {
+            address,
+            subject,
+            timeout,
+            connection: None,
+        }
+    }
+
+    fn connect(&self) -> Result<(BufReader<TcpStream>, TcpStream), PublishError> {
+        let mut stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut greeting = String::new();
+        reader.read_line(&mut greeting)?;
+
+        if !greeting.starts_with("INFO") {
+            return Err(PublishError::Rejected(format!(
+                "Unexpected greeting: {}",
+                greeting.trim_end()
+            )));
+        }
+
+        stream.write_all(
+            b"CONNECT {\"verbose\":false,\"pedantic\":false,\"name\":\"lineagedb\"}\r\n",
+        )?;
+
+        Ok((reader, stream))
+    }
+
+    fn publish_on(
+        (reader, stream): &mut (BufReader<TcpStream>, TcpStream),
+        subject: &str,
+        events: &[CloudEvent],
+    ) -> Result<(), PublishError> {
+        let mut buffer = vec![];
+
+        for event in events {
+            let payload = serde_json::to_vec(event).unwrap();
+
+            write!(buffer, "PUB {} {}\r\n", subject, payload.len())?;
+            buffer.extend(payload);
+            buffer.extend(b"\r\n");
+        }
+
+        // The server handles the messages of a connection in order, so the PONG means every PUB was accepted
+        buffer.extend(b"PING\r\n");
+        stream.write_all(&buffer)?;
+
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+
+            if reader.read_line(&mut line)? == 0 {
+                return Err(PublishError::Rejected(
+                    "The connection was closed".to_string(),
+                ));
+            }
+
+            match line.trim_end() {
+                "PONG" => return Ok(()),
+                "PING" => stream.write_all(b"PONG\r\n")?,
+                error if error.starts_with("-ERR") => {
+                    return Err(PublishError::Rejected(error.to_string()))
+                }
+                // e.g. INFO updates of the cluster
+                _ => {}
+            }
+        }
+    }
+}
+
+impl EventSink for NatsSink {
+    fn publish(&mut self, events: &[CloudEvent]) -> Result<(), PublishError> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+
+        // A connection that failed is dropped, the retry reconnects
+        NatsSink::publish_on(&mut connection, &self.subject, events)?;
+
+        self.connection = Some(connection);
+
+        Ok(())
+    }
+}
+
+/// Publishes to a topic through the Kafka REST proxy (v2 API), events are keyed by their subject so that the events
+/// of a row stay in order. Plain HTTP only
+pub struct KafkaRestSink {
+    address: String,
+    topic: String,
+    timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct KafkaRestOffset {
+    error_code: Option<i64>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KafkaRestResponse {
+    offsets: Vec<KafkaRestOffset>,
+}
+
+impl KafkaRestSink {
+    pub fn new(address: String, topic: String, timeout: Duration) -> Self {
+        Self {
+            address,
+            topic,
+            timeout,
+        }
+    }
+}
+
+impl EventSink for KafkaRestSink {
+    fn publish(&mut self, events: &[CloudEvent]) -> Result<(), PublishError> {
+        let records: Vec<serde_json::Value> = events
+            .iter()
+            .map(|event| serde_json::json!({ "key": event.subject, "value": event }))
+            .collect();
+
+        let body = serde_json::to_vec(&serde_json::json!({ "records": records })).unwrap();
+
+        let mut stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        write!(
+            stream,
+            "POST /topics/{} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/vnd.kafka.json.v2+json\r\nAccept: application/vnd.kafka.v2+json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.topic,
+            self.address,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        let status = head.lines().next().unwrap_or_default();
+
+        if status
+            .split(' ')
+            .nth(1)
+            .map_or(true, |code| !code.starts_with('2'))
+        {
+            return Err(PublishError::Rejected(format!("{} {}", status, body)));
+        }
+
+        // The proxy answers 200 even if some records failed, a chunked body is not decoded so only the status counts
+        if let Ok(response) = serde_json::from_str::<KafkaRestResponse>(body) {
+            if let Some(failed) = response
+                .offsets
+                .iter()
+                .find(|offset| offset.error_code.is_some())
+            {
+                return Err(PublishError::Rejected(
+                    failed.error.clone().unwrap_or_default(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Shares an embedder's sink, see `PublisherSink::Custom`
+struct SharedSink(Arc<Mutex<dyn EventSink>>);
+
+impl EventSink for SharedSink {
+    fn publish(&mut self, events: &[CloudEvent]) -> Result<(), PublishError> {
+        self.0.lock().unwrap().publish(events)
+    }
+}
+
+#[derive(Clone)]
+pub enum PublisherSink {
+    /// `host:port` of a NATS server and the subject to publish to
+    Nats { address: String, subject: String },
+    /// `host:port` of a Kafka REST proxy and the topic to publish to
+    KafkaRest { address: String, topic: String },
+    /// e.g. a Kafka producer of the embedding application
+    Custom(Arc<Mutex<dyn EventSink>>),
+}
+
+impl fmt::Debug for PublisherSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublisherSink::Nats { address, subject } => f
+                .debug_struct("Nats")
+                .field("address", address)
+                .field("subject", subject)
+                .finish(),
+            PublisherSink::KafkaRest { address, topic } => f
+                .debug_struct("KafkaRest")
+                .field("address", address)
+                .field("topic", topic)
+                .finish(),
+            PublisherSink::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl PublisherSink {
+    fn build(&self, timeout: Duration) -> Box<dyn EventSink> {
+        match self {
+            PublisherSink::Nats { address, subject } => {
+                Box::new(NatsSink::new(address.clone(), subject.clone(), timeout))
+            }
+            PublisherSink::KafkaRest { address, topic } => {
+                Box::new(KafkaRestSink::new(address.clone(), topic.clone(), timeout))
+            }
+            PublisherSink::Custom(sink) => Box::new(SharedSink(sink.clone())),
+        }
+    }
+}
+
+/// Defines where committed transactions are published, see `Publisher`
+#[derive(Debug, Clone)]
+pub struct PublisherOptions {
+    pub sink: PublisherSink,
+    /// The CloudEvents `source` of every event
+    pub source: String,
+    /// Transactions published per batch
+    pub batch_size: usize,
+    /// Timeout of a single publish to the broker
+    pub timeout: Duration,
+    /// Failed batches are retried with an exponential backoff of up to this long
+    pub max_backoff: Duration,
+}
+
+impl PublisherOptions {
+    pub fn new(sink: PublisherSink) -> Self {
+        Self {
+            sink,
+            source: "lineagedb".to_string(),
+            batch_size: 100,
+            timeout: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+
+    pub fn set_source(mut self, source: String) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn set_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn set_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+}
+
+const CURSOR_PATH: &str = "publisher/cursor";
+
+#[derive(Default)]
+struct PublisherStats {
+    published_events: AtomicUsize,
+    failed_publishes: AtomicUsize,
+}
+
+/// Publishes the mutations of committed transactions as CloudEvents, so that other systems can integrate without
+/// polling the database. Transactions are handed over by the WAL once they are durable and published in commit
+/// order by a background thread.
+///
+/// Delivery is at-least-once: the cursor (the last published transaction) is written to storage after the broker
+/// accepted a batch, and transactions after the cursor are published again when the WAL is replayed on startup.
+/// Transactions that were flushed into a snapshot before they were published cannot be replayed, a restore warns
+/// if the cursor is behind the snapshot
+pub struct Publisher {
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    options: PublisherOptions,
+    /// Dropped by `close`, the publishing thread exits once every sender has been dropped
+    sender: Mutex<Option<flume::Sender<Transaction>>>,
+    receiver: Mutex<Option<flume::Receiver<Transaction>>>,
+    /// Incremented by a reset, a batch only writes the cursor if there was no reset while it was published
+    generation: Arc<Mutex<usize>>,
+    stats: Arc<PublisherStats>,
+    /// Set by `close`, a failed batch is not retried once the publisher is closed
+    closed: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Publisher {
+    pub fn new(storage: Arc<Mutex<dyn Storage + Sync + Send>>, options: PublisherOptions) -> Self {
+        let (sender, receiver) = flume::unbounded();
+
+        Self {
+            storage,
+            options,
+            sender: Mutex::new(Some(sender)),
+            receiver: Mutex::new(Some(receiver)),
+            generation: Arc::new(Mutex::new(0)),
+            stats: Arc::new(PublisherStats::default()),
+            closed: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Committed transactions, see `TransactionWAL::add_committed_listener`
+    pub fn sender(&self) -> flume::Sender<Transaction> {
+        self.sender
+            .lock()
+            .unwrap()
+            .clone()
+            .expect("The publisher is only closed once the database is dropped")
+    }
+
+    /// Queues the replayed transactions the cursor has not reached, must be called before `start`
+    pub fn catch_up(
+        &self,
+        snapshot_transaction_id: &TransactionId,
+        transactions: &[Transaction],
+    ) -> StorageResult<usize> {
+        let cursor = read_cursor(&self.storage)?;
+
+        if let Some(cursor) = &cursor {
+            // The snapshot's own transaction id is the snapshot request, not a transaction in it
+            if cursor.to_number() + 1 < snapshot_transaction_id.to_number() {
+                log::warn!(
+                    "The publisher cursor ({}) is behind the snapshot ({}), transactions in between may not have been published",
+                    cursor,
+                    snapshot_transaction_id
+                );
+            }
+        }
+
+        let sender = self.sender();
+        let mut queued = 0;
+
+        for transaction in transactions {
+            if cursor
+                .as_ref()
+                .map_or(true, |cursor| &transaction.id > cursor)
+            {
+                let _ = sender.send(transaction.clone());
+                queued += 1;
+            }
+        }
+
+        Ok(queued)
+    }
+
+    /// Starts the publishing thread, which exits once the publisher has been closed (see `close`) and the WAL has
+    /// dropped its committed listener
+    pub fn start(&self) {
+        let Some(receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+
+        let storage = self.storage.clone();
+        let options = self.options.clone();
+        let generation = self.generation.clone();
+        let stats = self.stats.clone();
+        let closed = self.closed.clone();
+
+        let thread = thread::Builder::new()
+            .name("Publisher".to_string())
+            .spawn(move || {
+                let mut sink = options.sink.build(options.timeout);
+
+                while let Ok(first) = receiver.recv() {
+                    let batch_generation = *generation.lock().unwrap();
+
+                    let batch: Vec<Transaction> = std::iter::once(first)
+                        .chain(
+                            receiver
+                                .try_iter()
+                                .take(options.batch_size.saturating_sub(1)),
+                        )
+                        .collect();
+
+                    let events: Vec<CloudEvent> = batch
+                        .iter()
+                        .flat_map(|transaction| {
+                            CloudEvent::from_transaction(&options.source, transaction)
+                        })
+                        .collect();
+
+                    let mut backoff = Duration::from_millis(100);
+
+                    while !events.is_empty() {
+                        match sink.publish(&events) {
+                            Ok(()) => break,
+                            // The batch is published again after a restart, as the cursor has not moved
+                            Err(_) if closed.load(Ordering::SeqCst) => return,
+                            Err(e) => {
+                                stats.failed_publishes.fetch_add(1, Ordering::Relaxed);
+
+                                log::warn!(
+                                    "Unable to publish {} events, retrying in {:?}: {}",
+                                    events.len(),
+                                    backoff,
+                                    e
+                                );
+
+                                thread::sleep(backoff);
+                                backoff = (backoff * 2).min(options.max_backoff);
+                            }
+                        }
+                    }
+
+                    stats
+                        .published_events
+                        .fetch_add(events.len(), Ordering::Relaxed);
+
+                    let generation = generation.lock().unwrap();
+
+                    if *generation == batch_generation {
+                        let cursor = &batch.last().expect("A batch is never empty").id;
+
+                        if let Err(e) = write_cursor(&storage, cursor) {
+                            // The batch is published again after a restart
+                            log::warn!("Unable to save the publisher cursor: {}", e);
+                        }
+                    }
+                }
+            });
+
+        *self.thread.lock().unwrap() =
+            Some(thread.expect("Should be able to spawn the Publisher thread"));
+    }
+
+    /// Publishes the queued transactions and waits for the publishing thread to exit, the WAL must be closed first
+    /// (see `TransactionWAL::close`). A batch that fails to publish is not retried
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.sender.lock().unwrap().take();
+
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            if thread.join().is_err() {
+                log::error!("The publisher thread panicked");
+            }
+        }
+    }
+
+    /// The database is about to be reset, transaction ids start over so the cursor must not be written again by a
+    /// batch that was committed before the reset
+    pub fn reset(&self) {
+        *self.generation.lock().unwrap() += 1;
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        let cursor = match read_cursor(&self.storage) {
+            Ok(Some(cursor)) => cursor.to_string(),
+            Ok(None) => "None".to_string(),
+            Err(e) => format!("Unavailable ({})", e),
+        };
+
+        vec![
+            (
+                "PublishedEvents".to_string(),
+                self.stats
+                    .published_events
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            ),
+            (
+                "FailedPublishes".to_string(),
+                self.stats
+                    .failed_publishes
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            ),
+            ("PublisherCursor".to_string(), cursor),
+        ]
+    }
+}
+
+fn read_cursor(
+    storage: &Arc<Mutex<dyn Storage + Sync + Send>>,
+) -> StorageResult<Option<TransactionId>> {
+    match storage.lock().unwrap().read_blob(CURSOR_PATH.to_string())? {
+        ReadBlobState::Found(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e))),
+        ReadBlobState::NotFound => Ok(None),
+    }
+}
+
+fn write_cursor(
+    storage: &Arc<Mutex<dyn Storage + Sync + Send>>,
+    cursor: &TransactionId,
+) -> StorageResult<()> {
+    storage
+        .lock()
+        .unwrap()
+        .write_blob(CURSOR_PATH.to_string(), serde_json::to_vec(cursor).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Instant};
+
+    use uuid::Uuid;
+
+    use crate::{
+        database::{
+            commands::{ShutdownRequest, TransactionContext},
+            database::Database,
+            options::DatabaseOptions,
+            table::row::{UpdatePersonData, UpdateStatement},
+        },
+        model::person::Person,
+        persistence::storage::{file::FileOptions, StorageEngine},
+    };
+
+    use super::*;
+
+    /// Fails the first `failures` publishes
+    #[derive(Default)]
+    struct RecordingSink {
+        failures: usize,
+        events: Vec<CloudEvent>,
+    }
+
+    impl EventSink for RecordingSink {
+        fn publish(&mut self, events: &[CloudEvent]) -> Result<(), PublishError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+
+                return Err(PublishError::Rejected("unavailable".to_string()));
+            }
+
+            self.events.extend_from_slice(events);
+
+            Ok(())
+        }
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let started_at = Instant::now();
+
+        while !condition() {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(10),
+                "timed out waiting for the publisher"
+            );
+
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn nats_sink_waits_for_the_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // A NATS server that acknowledges the PING once it has read every PUB
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"INFO {}\r\n").unwrap();
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut lines = vec![];
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+
+                if line == "PING\r\n" {
+                    stream.write_all(b"PONG\r\n").unwrap();
+                    return lines;
+                }
+
+                lines.push(line);
+            }
+        });
+
+        let transaction = Transaction {
+            id: TransactionId(7),
+            statements: vec![
+                Statement::Add(Person::new("Nats".to_string(), None)),
+                Statement::NextVal("orders".to_string()),
+            ],
+            status: crate::persistence::transaction::TransactionStatus::Committed,
+        };
+
+        let events = CloudEvent::from_transaction("test", &transaction);
+        assert_eq!(events.len(), 1);
+
+        NatsSink::new(address, "people".to_string(), Duration::from_secs(5))
+            .publish(&events)
+            .unwrap();
+
+        let lines = server.join().unwrap();
+
+        assert!(lines[0].starts_with("CONNECT "), "{}", lines[0]);
+        assert!(lines[1].starts_with("PUB people "), "{}", lines[1]);
+        assert_eq!(
+            serde_json::from_str::<CloudEvent>(lines[2].trim_end()).unwrap(),
+            events[0]
+        );
+    }
+
+    #[test]
+    fn publishes_committed_mutations_at_least_once() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let sink = Arc::new(Mutex::new(RecordingSink {
+            failures: 1,
+            ..RecordingSink::default()
+        }));
+
+        let options = |sink: Arc<Mutex<RecordingSink>>| {
+            DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                .set_publisher(
+                    PublisherOptions::new(PublisherSink::Custom(sink))
+                        .set_max_backoff(Duration::from_millis(10)),
+                )
+        };
+
+        let request_manager = Database::new(options(sink.clone()).set_restore(false)).run();
+
+        let person = request_manager
+            .send_add(
+                Person::new("Published".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("should not timeout");
+
+        request_manager
+            .send_update(
+                person.id.clone(),
+                UpdatePersonData {
+                    full_name: UpdateStatement::NoChanges,
+                    email: UpdateStatement::Set("published@example.com".to_string()),
+                    ..UpdatePersonData::default()
+                },
+                TransactionContext::default(),
+            )
+            .expect("should not timeout");
+
+        // Reads are not published
+        request_manager
+            .send_get(person.id.clone(), TransactionContext::default())
+            .expect("should not timeout");
+
+        // The first publish fails and is retried
+        wait_for(|| sink.lock().unwrap().events.len() == 2);
+
+        let events = sink.lock().unwrap().events.clone();
+
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.event_type.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["com.lineagedb.add", "com.lineagedb.update"]
+        );
+        assert_eq!(events[0].subject, Some(person.id.to_string()));
+        assert_ne!(events[0].id, events[1].id);
+
+        wait_for(|| {
+            let stats = request_manager.send_info_request().unwrap();
+
+            stats.contains(&(
+                "PublisherCursor".to_string(),
+                events[1].lineagedbtransaction.clone(),
+            )) && stats.contains(&("FailedPublishes".to_string(), "1".to_string()))
+        });
+
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .expect("should shut down");
+
+        // The replayed transactions are behind the cursor, so only new transactions are published after a restart
+        let restarted_sink = Arc::new(Mutex::new(RecordingSink::default()));
+        let request_manager = Database::new(options(restarted_sink.clone())).run();
+
+        request_manager
+            .send_single_statement(
+                Statement::Remove(person.id.clone()),
+                TransactionContext::default(),
+            )
+            .expect("should not timeout");
+
+        wait_for(|| !restarted_sink.lock().unwrap().events.is_empty());
+
+        let events = restarted_sink.lock().unwrap().events.clone();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "com.lineagedb.remove");
+
+        let _ = request_manager.send_shutdown_request(ShutdownRequest::Coordinator);
+    }
+}
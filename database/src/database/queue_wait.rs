@@ -0,0 +1,97 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+/// Number of recent requests per worker thread used to calculate the rolling queue wait percentiles
+const WINDOW_SIZE: usize = 1024;
+
+/// Tracks how long requests wait in a worker thread's channel before the worker picks them up.
+/// Queue time is not part of the request execution time, so without this a backed up worker is invisible
+pub struct QueueWaitTracker {
+    /// Rolling window of queue waits, one per worker thread
+    windows: Vec<Mutex<VecDeque<Duration>>>,
+    /// When set, a warning is logged for each request that waited longer than this threshold
+    slo: Option<Duration>,
+}
+
+impl QueueWaitTracker {
+    pub fn new(threads: usize, slo: Option<Duration>) -> Self {
+        Self {
+            windows: (0..threads)
+                .map(|_| Mutex::new(VecDeque::with_capacity(WINDOW_SIZE)))
+                .collect(),
+            slo,
+        }
+    }
+
+    /// Records the time a request spent queued for a worker thread
+    pub fn record(&self, thread_id: usize, queue_wait: Duration) {
+        if let Some(slo) = self.slo {
+            if queue_wait > slo {
+                log::warn!(
+                    thread_id;
+                    "Request waited {:.2}ms in the queue, exceeds SLO of {:.2}ms",
+                    queue_wait.as_secs_f64() * 1000.0,
+                    slo.as_secs_f64() * 1000.0
+                );
+            }
+        }
+
+        let Some(window) = self.windows.get(thread_id) else {
+            return;
+        };
+
+        let mut window = window.lock().unwrap();
+
+        if window.len() == WINDOW_SIZE {
+            window.pop_front();
+        }
+
+        window.push_back(queue_wait);
+    }
+
+    /// The 99th percentile queue wait over the recent requests of a worker thread, none if the thread has not
+    /// received any requests
+    pub fn p99(&self, thread_id: usize) -> Option<Duration> {
+        let window = self.windows.get(thread_id)?.lock().unwrap();
+
+        if window.is_empty() {
+            return None;
+        }
+
+        let mut waits = window.iter().copied().collect::<Vec<Duration>>();
+        waits.sort();
+
+        let index = ((waits.len() as f64 * 0.99).ceil() as usize).saturating_sub(1);
+
+        Some(waits[index])
+    }
+
+    pub fn threads(&self) -> usize {
+        self.windows.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn p99_over_rolling_window() {
+        let tracker = QueueWaitTracker::new(2, None);
+
+        assert_eq!(tracker.p99(0), None);
+
+        for wait in 1..=100 {
+            tracker.record(0, Duration::from_millis(wait));
+        }
+
+        assert_eq!(tracker.p99(0), Some(Duration::from_millis(99)));
+        assert_eq!(tracker.p99(1), None);
+
+        // Old samples fall out of the window
+        for _ in 0..WINDOW_SIZE {
+            tracker.record(0, Duration::from_millis(1));
+        }
+
+        assert_eq!(tracker.p99(0), Some(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::model::statement::Statement;
+
+use super::clock::Clock;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Limits for a single tenant, a tenant is the role a request runs as (see `TransactionContext::role`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Quota {
+    pub max_rows: Option<usize>,
+    pub max_wal_bytes_per_day: Option<usize>,
+    pub max_requests_per_second: Option<usize>,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl Quota {
+    /// Rows the tenant can have, adds beyond the limit are rolled back. Removes always succeed
+    pub fn set_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Bytes of statements the tenant can write to the WAL per (UTC) day
+    pub fn set_max_wal_bytes_per_day(mut self, max_wal_bytes_per_day: usize) -> Self {
+        self.max_wal_bytes_per_day = Some(max_wal_bytes_per_day);
+        self
+    }
+
+    /// Requests (reads and mutations) the tenant can make per second
+    pub fn set_max_requests_per_second(mut self, max_requests_per_second: usize) -> Self {
+        self.max_requests_per_second = Some(max_requests_per_second);
+        self
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum QuotaExceeded {
+    #[error("Tenant {tenant} exceeded its quota of {limit} rows")]
+    Rows { tenant: String, limit: usize },
+
+    #[error("Tenant {tenant} exceeded its quota of {limit} WAL bytes per day")]
+    WalBytes { tenant: String, limit: usize },
+
+    #[error("Tenant {tenant} exceeded its quota of {limit} requests per second")]
+    RequestRate { tenant: String, limit: usize },
+}
+
+struct TenantUsage {
+    /// Counted the first time the tenant mutates, then adjusted by each committed mutation
+    rows: Option<usize>,
+    /// Day (since the epoch) that `wal_bytes` were written on
+    wal_day: u64,
+    wal_bytes: usize,
+    window_started: Instant,
+    window_requests: usize,
+    rejections: usize,
+}
+
+impl TenantUsage {
+    fn new(wal_day: u64) -> Self {
+        Self {
+            rows: None,
+            wal_day,
+            wal_bytes: 0,
+            window_started: Instant::now(),
+            window_requests: 0,
+            rejections: 0,
+        }
+    }
+}
+
+/// Days since the unix epoch, the WAL quota is reset at midnight UTC
+fn today(clock: &dyn Clock) -> u64 {
+    (clock.now_ms() / 1_000) as u64 / SECONDS_PER_DAY
+}
+
+/// Change in the number of rows once the statements are committed
+fn row_delta(statements: &[Statement]) -> isize {
+    statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Add(_) => 1,
+            Statement::Remove(_) | Statement::Merge(_, _, _) => -1,
+            Statement::Split(_, people) => people.len() as isize - 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Approximates the bytes the statements take in the WAL
+fn wal_bytes(statements: &[Statement]) -> usize {
+    serde_json::to_vec(statements)
+        .map(|bytes| bytes.len())
+        .unwrap_or_default()
+}
+
+/// Usage reserved by a mutation that passed its tenant's quota check, refunded if the mutation rolls back
+#[derive(Debug)]
+pub struct QuotaCharge {
+    tenant: String,
+    row_delta: isize,
+    wal_bytes: usize,
+}
+
+/// Enforces soft per tenant quotas in the worker threads, so that a single tenant cannot starve the others.
+/// Quotas are soft: usage is kept in memory and row counts are approximate, e.g. rows added by other tenants
+/// that the tenant's row policies can see are only counted once the tenant's usage is recounted after a reset
+pub struct QuotaTracker {
+    quotas: HashMap<String, Quota>,
+    usage: Mutex<HashMap<String, TenantUsage>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl QuotaTracker {
+    pub fn new(quotas: HashMap<String, Quota>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            quotas,
+            usage: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+
+    /// Counts the request against the tenant's request rate and checks whether its mutations fit within the
+    /// row and WAL quotas. `count_rows` returns the rows the tenant currently has, it is only called once per
+    /// tenant. Mutations reserve their usage, the returned charge is refunded if they roll back
+    pub fn check(
+        &self,
+        tenant: Option<&str>,
+        statements: &[Statement],
+        count_rows: impl FnOnce() -> usize,
+    ) -> Result<Option<QuotaCharge>, QuotaExceeded> {
+        let Some((tenant, quota)) = tenant.and_then(|t| self.quotas.get_key_value(t)) else {
+            return Ok(None);
+        };
+
+        let today = today(self.clock.as_ref());
+
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage
+            .entry(tenant.clone())
+            .or_insert_with(|| TenantUsage::new(today));
+
+        let result = Self::check_usage(tenant, quota, usage, statements, count_rows, today);
+
+        if let Err(e) = &result {
+            log::debug!("Quota: {}", e);
+            usage.rejections += 1;
+        }
+
+        result
+    }
+
+    fn check_usage(
+        tenant: &str,
+        quota: &Quota,
+        usage: &mut TenantUsage,
+        statements: &[Statement],
+        count_rows: impl FnOnce() -> usize,
+        today: u64,
+    ) -> Result<Option<QuotaCharge>, QuotaExceeded> {
+        if let Some(limit) = quota.max_requests_per_second {
+            if usage.window_started.elapsed() >= Duration::from_secs(1) {
+                usage.window_started = Instant::now();
+                usage.window_requests = 0;
+            }
+
+            if usage.window_requests >= limit {
+                return Err(QuotaExceeded::RequestRate {
+                    tenant: tenant.to_string(),
+                    limit,
+                });
+            }
+
+            usage.window_requests += 1;
+        }
+
+        if !statements.iter().any(|statement| statement.is_mutation()) {
+            return Ok(None);
+        }
+
+        let charge = QuotaCharge {
+            tenant: tenant.to_string(),
+            row_delta: row_delta(statements),
+            wal_bytes: wal_bytes(statements),
+        };
+
+        if let Some(limit) = quota.max_rows {
+            let rows = *usage.rows.get_or_insert_with(count_rows);
+
+            if charge.row_delta > 0 && rows.saturating_add_signed(charge.row_delta) > limit {
+                return Err(QuotaExceeded::Rows {
+                    tenant: tenant.to_string(),
+                    limit,
+                });
+            }
+        }
+
+        if let Some(limit) = quota.max_wal_bytes_per_day {
+            if usage.wal_day != today {
+                usage.wal_day = today;
+                usage.wal_bytes = 0;
+            }
+
+            if usage.wal_bytes + charge.wal_bytes > limit {
+                return Err(QuotaExceeded::WalBytes {
+                    tenant: tenant.to_string(),
+                    limit,
+                });
+            }
+        }
+
+        // Reserved before the mutation runs, so the tenant's next request sees it even if it is handled by another worker
+        if let Some(rows) = &mut usage.rows {
+            *rows = rows.saturating_add_signed(charge.row_delta);
+        }
+
+        usage.wal_bytes += charge.wal_bytes;
+
+        Ok(Some(charge))
+    }
+
+    /// Releases the usage of a mutation that rolled back
+    pub fn refund(&self, charge: QuotaCharge) {
+        let mut usage = self.usage.lock().unwrap();
+
+        // The usage is cleared when the database is reset
+        let Some(usage) = usage.get_mut(&charge.tenant) else {
+            return;
+        };
+
+        if let Some(rows) = &mut usage.rows {
+            *rows = rows.saturating_add_signed(-charge.row_delta);
+        }
+
+        usage.wal_bytes = usage.wal_bytes.saturating_sub(charge.wal_bytes);
+    }
+
+    /// Usage and rejections per tenant, reported in the database stats
+    pub fn stats(&self) -> Vec<(String, String)> {
+        let usage = self.usage.lock().unwrap();
+
+        let mut tenants: Vec<&String> = usage.keys().collect();
+        tenants.sort();
+
+        tenants
+            .into_iter()
+            .flat_map(|tenant| {
+                let usage = &usage[tenant];
+
+                [
+                    usage
+                        .rows
+                        .map(|rows| (format!("QuotaRows[{}]", tenant), rows.to_string())),
+                    Some((
+                        format!("QuotaWalBytesToday[{}]", tenant),
+                        usage.wal_bytes.to_string(),
+                    )),
+                    Some((
+                        format!("QuotaRejections[{}]", tenant),
+                        usage.rejections.to_string(),
+                    )),
+                ]
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Usage is recounted after a reset
+    pub fn reset(&self) {
+        self.usage.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use crate::{
+        consts::consts::EntityId,
+        database::clock::{MockClock, SystemClock},
+        model::person::Person,
+    };
+
+    use super::*;
+
+    #[test]
+    fn enforces_tenant_quotas() {
+        let tracker = QuotaTracker::new(
+            HashMap::from([
+                (
+                    "rows".to_string(),
+                    Quota::default()
+                        .set_max_rows(2)
+                        .set_max_wal_bytes_per_day(10_000),
+                ),
+                (
+                    "rate".to_string(),
+                    Quota::default().set_max_requests_per_second(2),
+                ),
+            ]),
+            Arc::new(SystemClock),
+        );
+
+        let add = || vec![Statement::Add(Person::new("Quota".to_string(), None))];
+
+        // The tenant starts with one row
+        tracker.check(Some("rows"), &add(), || 1).unwrap();
+
+        assert_eq!(
+            tracker.check(Some("rows"), &add(), || unreachable!()).err(),
+            Some(QuotaExceeded::Rows {
+                tenant: "rows".to_string(),
+                limit: 2
+            })
+        );
+
+        // Removes free up rows and are never rejected
+        let remove = vec![Statement::Remove(EntityId::new())];
+        tracker.check(Some("rows"), &remove, || 0).unwrap();
+
+        // Rolled back mutations are refunded
+        let charge = tracker.check(Some("rows"), &add(), || 0).unwrap();
+        tracker.refund(charge.unwrap());
+
+        let large = vec![Statement::Add(Person::new("x".repeat(20_000), None))];
+        assert!(matches!(
+            tracker.check(Some("rows"), &large, || 0),
+            Err(QuotaExceeded::WalBytes { .. })
+        ));
+
+        let read = vec![Statement::Get(EntityId::new())];
+        tracker.check(Some("rate"), &read, || 0).unwrap();
+        tracker.check(Some("rate"), &read, || 0).unwrap();
+        assert!(matches!(
+            tracker.check(Some("rate"), &read, || 0),
+            Err(QuotaExceeded::RequestRate { .. })
+        ));
+
+        // Requests without a quota are not limited
+        for _ in 0..10 {
+            tracker.check(None, &add(), || 0).unwrap();
+            tracker.check(Some("other"), &add(), || 0).unwrap();
+        }
+
+        let stats = tracker.stats();
+        assert!(stats.contains(&("QuotaRows[rows]".to_string(), "1".to_string())));
+        assert!(stats.contains(&("QuotaRejections[rows]".to_string(), "2".to_string())));
+        assert!(stats.contains(&("QuotaRejections[rate]".to_string(), "1".to_string())));
+    }
+
+    #[test]
+    fn wal_quota_resets_at_midnight() {
+        let clock = Arc::new(MockClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(SECONDS_PER_DAY - 1),
+        ));
+        let tracker = QuotaTracker::new(
+            HashMap::from([(
+                "wal".to_string(),
+                Quota::default().set_max_wal_bytes_per_day(15_000),
+            )]),
+            clock.clone(),
+        );
+
+        let large = || vec![Statement::Add(Person::new("x".repeat(10_000), None))];
+
+        tracker.check(Some("wal"), &large(), || 0).unwrap();
+        assert!(matches!(
+            tracker.check(Some("wal"), &large(), || 0),
+            Err(QuotaExceeded::WalBytes { .. })
+        ));
+
+        clock.advance(Duration::from_secs(1));
+
+        tracker.check(Some("wal"), &large(), || 0).unwrap();
+    }
+}
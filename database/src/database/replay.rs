@@ -0,0 +1,361 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    consts::consts::EntityId, model::statement::Statement, persistence::transaction::Transaction,
+};
+
+use super::{
+    commands::DatabaseCommandTransactionResponse,
+    database::{ApplyMode, Database},
+    table::policy::FieldMask,
+};
+
+/// What a statement depends on when it is replayed, transactions that share a key are replayed in WAL order.
+/// Reads are included as a read of a row that does not exist yet fails the transaction
+#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+enum ReplayKey {
+    Entity(EntityId),
+    Sequence(String),
+    /// Acknowledging a message fails if the transaction that enqueued it has not been replayed
+    Outbox,
+}
+
+fn replay_keys(statement: &Statement) -> Vec<ReplayKey> {
+    let entity = |id: &EntityId| ReplayKey::Entity(id.clone());
+
+    match statement {
+        Statement::Get(id)
+        | Statement::GetVersion(id, _)
+        | Statement::Exists(id)
+        | Statement::GetAttachment(id, _)
+        | Statement::Lineage(id)
+        | Statement::History(id, _)
+        | Statement::Meta(id) => vec![entity(id)],
+        Statement::NextVal(name) => vec![ReplayKey::Sequence(name.clone())],
+        Statement::Enqueue(_, _) | Statement::AckOutbox(_) => vec![ReplayKey::Outbox],
+        statement => statement.mutated_ids().into_iter().map(entity).collect(),
+    }
+}
+
+/// Groups transactions (by index) into partitions that share no replay keys, so that the partitions commute.
+/// Each partition is in WAL order and the partitions are ordered largest first
+pub(super) fn partition(transactions: &[Transaction]) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..transactions.len()).collect();
+
+    fn find(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+
+        index
+    }
+
+    // The last transaction that used each key, a transaction joins the partition of every key it uses
+    let mut last_used: HashMap<ReplayKey, usize> = HashMap::new();
+
+    for (index, transaction) in transactions.iter().enumerate() {
+        for key in transaction.statements.iter().flat_map(replay_keys) {
+            if let Some(previous) = last_used.insert(key, index) {
+                let root = find(&mut parents, previous);
+                let own_root = find(&mut parents, index);
+
+                parents[root] = own_root;
+            }
+        }
+    }
+
+    let mut partitions: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for index in 0..transactions.len() {
+        let root = find(&mut parents, index);
+
+        partitions.entry(root).or_default().push(index);
+    }
+
+    let mut partitions: Vec<Vec<usize>> = partitions.into_values().collect();
+
+    // Largest first, so that a large partition does not start last and hold up the replay
+    partitions.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+    partitions
+}
+
+/// A transaction that could not be replayed, `index` is its position in the replayed transactions
+#[derive(Debug, PartialEq)]
+pub(super) struct ReplayConflict {
+    pub index: usize,
+    pub rollback_message: String,
+}
+
+impl Database {
+    /// Replays committed transactions, partitions of transactions that touch disjoint rows (see `partition`) are
+    /// replayed by up to `threads` threads in parallel. A partition stops at its first conflict, the earliest
+    /// conflict in WAL order is returned
+    pub(super) fn replay_transactions(
+        &self,
+        transactions: &[Transaction],
+        threads: usize,
+    ) -> Result<usize, ReplayConflict> {
+        let partitions = partition(transactions);
+        let next_partition = AtomicUsize::new(0);
+        let conflicts: Mutex<Vec<ReplayConflict>> = Mutex::new(vec![]);
+
+        let replay_partitions = || {
+            while let Some(partition) =
+                partitions.get(next_partition.fetch_add(1, Ordering::Relaxed))
+            {
+                for &index in partition {
+                    let transaction = &transactions[index];
+
+                    let result = self.apply_transaction(
+                        transaction.id.clone(),
+                        transaction.statements.clone(),
+                        ApplyMode::Restore,
+                        &FieldMask::default(),
+                    );
+
+                    if let DatabaseCommandTransactionResponse::Rollback(rollback_message) = result {
+                        conflicts.lock().unwrap().push(ReplayConflict {
+                            index,
+                            rollback_message,
+                        });
+
+                        break;
+                    }
+                }
+            }
+        };
+
+        thread::scope(|scope| {
+            for _ in 1..threads.min(partitions.len()) {
+                scope.spawn(replay_partitions);
+            }
+
+            replay_partitions();
+        });
+
+        match conflicts
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .min_by_key(|conflict| conflict.index)
+        {
+            Some(conflict) => Err(conflict),
+            None => Ok(partitions.len()),
+        }
+    }
+
+    /// Same as `replay_transactions`, though the transactions are replayed in chunks of `checkpoint_interval`.
+    /// Once a chunk has been replayed `checkpoint` is called with its last transaction, unless it is the last
+    /// chunk. Returns the total number of partitions
+    pub(super) fn replay_transactions_checkpointed(
+        &self,
+        transactions: &[Transaction],
+        threads: usize,
+        checkpoint_interval: Option<usize>,
+        mut checkpoint: impl FnMut(&Transaction),
+    ) -> Result<usize, ReplayConflict> {
+        let Some(checkpoint_interval) = checkpoint_interval else {
+            return self.replay_transactions(transactions, threads);
+        };
+
+        let mut partitions = 0;
+
+        for (chunk_index, chunk) in transactions.chunks(checkpoint_interval).enumerate() {
+            let offset = chunk_index * checkpoint_interval;
+
+            partitions += self
+                .replay_transactions(chunk, threads)
+                .map_err(|conflict| ReplayConflict {
+                    index: offset + conflict.index,
+                    ..conflict
+                })?;
+
+            if offset + chunk.len() < transactions.len() {
+                checkpoint(chunk.last().expect("Chunks are never empty"));
+            }
+        }
+
+        Ok(partitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        consts::consts::TransactionId,
+        database::{
+            options::DatabaseOptions,
+            table::row::{UpdatePersonData, UpdateStatement},
+        },
+        model::{person::Person, statement::StatementResult},
+        persistence::transaction::TransactionStatus,
+    };
+
+    use super::*;
+
+    fn transaction(id: TransactionId, statements: Vec<Statement>) -> Transaction {
+        Transaction {
+            id,
+            statements,
+            status: TransactionStatus::Committed,
+        }
+    }
+
+    #[test]
+    fn partitions_by_the_rows_touched() {
+        let [a, b, c] = ["a", "b", "c"].map(|name| Person::new(name.to_string(), None));
+        let id = TransactionId::new_first_transaction();
+
+        let transactions = vec![
+            transaction(id.clone(), vec![Statement::Add(a.clone())]),
+            transaction(id.clone(), vec![Statement::Add(b.clone())]),
+            transaction(id.clone(), vec![Statement::Add(c.clone())]),
+            transaction(id.clone(), vec![Statement::Get(a.id.clone())]),
+            // Joins the partitions of `b` and `c`
+            transaction(
+                id.clone(),
+                vec![Statement::Merge(b.id.clone(), c.id.clone(), vec![])],
+            ),
+            transaction(id.clone(), vec![Statement::NextVal("orders".to_string())]),
+            transaction(id, vec![Statement::NextVal("orders".to_string())]),
+        ];
+
+        assert_eq!(
+            partition(&transactions),
+            vec![vec![1, 2, 4], vec![0, 3], vec![5, 6]]
+        );
+    }
+
+    #[test]
+    fn parallel_replay_matches_sequential_replay() {
+        // Given a history where each person is added, updated and some are merged into each other
+        let source = Database::new(DatabaseOptions::new_test());
+
+        let people: Vec<Person> = (0..50)
+            .map(|index| Person::new(format!("Person {}", index), None))
+            .collect();
+
+        let mut transactions = vec![];
+
+        let mut record = |statements: Vec<Statement>| {
+            let id = source
+                .persistence
+                .transaction_wal
+                .get_increment_current_transaction_id();
+
+            let response = source.apply_transaction(
+                id.clone(),
+                statements.clone(),
+                ApplyMode::Restore,
+                &FieldMask::default(),
+            );
+
+            assert!(matches!(
+                response,
+                DatabaseCommandTransactionResponse::Commit(_)
+            ));
+
+            transactions.push(transaction(id, statements));
+        };
+
+        for person in &people {
+            record(vec![Statement::Add(person.clone())]);
+        }
+
+        for person in &people {
+            record(vec![Statement::Update(
+                person.id.clone(),
+                UpdatePersonData {
+                    full_name: UpdateStatement::NoChanges,
+                    email: UpdateStatement::Set(format!("{}@example.com", person.full_name)),
+                    ..UpdatePersonData::default()
+                },
+            )]);
+        }
+
+        for pair in people.chunks(2).take(10) {
+            record(vec![Statement::Merge(
+                pair[0].id.clone(),
+                pair[1].id.clone(),
+                vec![],
+            )]);
+        }
+
+        // When the history is replayed by several threads
+        let replayed = Database::new(DatabaseOptions::new_test());
+
+        let partitions = replayed
+            .replay_transactions(&transactions, 4)
+            .expect("every transaction should replay");
+
+        assert_eq!(partitions, 40);
+
+        // Then every row ends up with the same versions as the original database
+        let versions = |database: &Database| {
+            let result = database.person_table.query_statement(
+                Statement::ListLatestVersions,
+                &TransactionId::new_highest_transaction(),
+            );
+
+            match result {
+                Ok(StatementResult::ListVersion(mut versions)) => {
+                    versions.sort_by(|a, b| a.id.cmp(&b.id));
+                    versions
+                }
+                _ => panic!("should be a list of versions"),
+            }
+        };
+
+        assert_eq!(versions(&replayed), versions(&source));
+    }
+
+    #[test]
+    fn checkpoints_between_chunks() {
+        let people: Vec<Person> = (0..5)
+            .map(|index| Person::new(format!("Person {}", index), None))
+            .collect();
+
+        let mut transactions: Vec<Transaction> = people
+            .iter()
+            .enumerate()
+            .map(|(index, person)| {
+                transaction(
+                    TransactionId(index + 1),
+                    vec![Statement::Add(person.clone())],
+                )
+            })
+            .collect();
+
+        let mut checkpoints = vec![];
+
+        Database::new(DatabaseOptions::new_test())
+            .replay_transactions_checkpointed(&transactions, 2, Some(2), |transaction| {
+                checkpoints.push(transaction.id.clone())
+            })
+            .expect("every transaction should replay");
+
+        // The last chunk completes the replay, it is not checkpointed
+        assert_eq!(checkpoints, vec![TransactionId(2), TransactionId(4)]);
+
+        // Conflicts are reported by their position in every transaction, not in their chunk
+        transactions.push(transaction(
+            TransactionId(6),
+            vec![Statement::Add(people[0].clone())],
+        ));
+
+        let conflict = Database::new(DatabaseOptions::new_test())
+            .replay_transactions_checkpointed(&transactions, 2, Some(2), |_| {})
+            .unwrap_err();
+
+        assert_eq!(conflict.index, 5);
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use crate::consts::consts::TransactionId;
+
+use super::{activity::RequestId, commands::DatabaseCommandTransactionResponse};
+
+/// Which transactions are logged once they finish, see `DatabaseOptions::set_request_log_sampling`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestLogSampling {
+    /// Every transaction is logged
+    All,
+    /// A random fraction of transactions is logged, between 0 (none) and 1 (all)
+    Rate(f64),
+    /// Only transactions that took longer than the duration are logged
+    SlowerThan(Duration),
+}
+
+/// Logs a line per finished transaction with its statement kinds, outcome, row count, ids and duration as fields
+///
+/// Logging every transaction at info is expensive under load, the sampling decides which transactions are logged.
+/// Every transaction is still logged at debug when it is received
+pub struct RequestLog {
+    sampling: RequestLogSampling,
+}
+
+impl RequestLog {
+    pub fn new(sampling: RequestLogSampling) -> Self {
+        Self { sampling }
+    }
+
+    pub fn is_sampled(&self, elapsed: Duration) -> bool {
+        match self.sampling {
+            RequestLogSampling::All => true,
+            RequestLogSampling::Rate(rate) => rand::random::<f64>() < rate,
+            RequestLogSampling::SlowerThan(threshold) => elapsed > threshold,
+        }
+    }
+
+    pub fn record(
+        &self,
+        thread_id: usize,
+        request_id: RequestId,
+        transaction_id: &TransactionId,
+        kind: &str,
+        response: &DatabaseCommandTransactionResponse,
+        elapsed: Duration,
+    ) {
+        if !log::log_enabled!(log::Level::Info) || !self.is_sampled(elapsed) {
+            return;
+        }
+
+        let (outcome, rows) = match response {
+            DatabaseCommandTransactionResponse::Commit(results) => {
+                ("commit", results.iter().map(|r| r.row_count()).sum())
+            }
+            DatabaseCommandTransactionResponse::Rollback(_) => ("rollback", 0),
+            DatabaseCommandTransactionResponse::Status(_) => ("status", 0),
+            DatabaseCommandTransactionResponse::QuotaExceeded(_) => ("quota_exceeded", 0),
+            DatabaseCommandTransactionResponse::LimitExceeded(_) => ("limit_exceeded", 0),
+            DatabaseCommandTransactionResponse::ContextOverrideRejected(_) => {
+                ("context_override_rejected", 0)
+            }
+            DatabaseCommandTransactionResponse::NotModified(_) => ("not_modified", 0),
+            DatabaseCommandTransactionResponse::DryRun(_) => ("dry_run", 0),
+            DatabaseCommandTransactionResponse::Throttled(_) => ("throttled", 0),
+        };
+
+        log::info!(
+            thread_id,
+            request_id = request_id.0,
+            transaction_id = transaction_id.to_number(),
+            kind,
+            outcome,
+            rows,
+            duration_ms = elapsed.as_secs_f64() * 1000.0;
+            "Finished transaction"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling() {
+        let fast = Duration::from_millis(1);
+        let slow = Duration::from_millis(100);
+
+        assert!(RequestLog::new(RequestLogSampling::All).is_sampled(fast));
+        assert!(RequestLog::new(RequestLogSampling::Rate(1.0)).is_sampled(fast));
+        assert!(!RequestLog::new(RequestLogSampling::Rate(0.0)).is_sampled(slow));
+
+        let slow_only = RequestLog::new(RequestLogSampling::SlowerThan(Duration::from_millis(10)));
+        assert!(!slow_only.is_sampled(fast));
+        assert!(slow_only.is_sampled(slow));
+    }
+}
@@ -1,23 +1,60 @@
+use arrow_array::RecordBatch;
 use core::panic;
 use rand::{seq::SliceRandom, thread_rng};
-use std::{ops::Deref, sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, Deref, Range},
+    sync::{Arc, Mutex, RwLock, Weak},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
 use crate::{
-    consts::consts::{EntityId, VersionId},
+    consts::consts::{EntityId, TransactionId, VersionId},
     model::{
-        person::Person,
+        person::{Attachment, Person},
         statement::{Statement, StatementResult},
     },
+    persistence::snapshot_diff::{DiffSource, SnapshotDiff},
 };
 
 use super::{
+    activity::{ActivityReport, RequestId},
+    availability::WorkerAvailability,
     commands::{
         Control, DatabaseCommand, DatabaseCommandControlResponse, DatabaseCommandRequest,
-        DatabaseCommandResponse, DatabaseCommandTransactionResponse, ShutdownRequest,
-        TransactionContext,
+        DatabaseCommandResponse, DatabaseCommandTransactionResponse, MaintenanceTask,
+        ShutdownRequest, TransactionContext,
+    },
+    context_policy::{ContextOverrideRejected, ContextPolicy},
+    database::Database,
+    ids::IdGenerator,
+    limits::{LimitExceeded, TransactionLimits},
+    operations::{OperationId, OperationStatus},
+    options::DatabaseOptions,
+    protocol::Capabilities,
+    quota::QuotaExceeded,
+    scheduler::JobDefinition,
+    system::SystemTable,
+    table::{
+        arrow::people_to_record_batch,
+        attachment::AttachmentContent,
+        history::{HistoryPage, HistoryRequest},
+        index::IndexedField,
+        meta::EntityMeta,
+        outbox::{OutboxId, OutboxMessage},
+        pagination::{Page, PageRequest},
+        policy::RowPolicy,
+        prepared_query::PreparedQueryDefinition,
+        query::QueryPersonData,
+        query_builder::PersonQuery,
+        row::{PersonVersion, UpdateAttachmentStatement, UpdatePersonData},
+        view::{ViewDefinition, ViewResult},
+        watermark::TableVersion,
     },
-    table::{query::QueryPersonData, row::UpdatePersonData},
+    tags::{TagThrottle, TagThrottled},
+    watch::EntityWatch,
 };
 
 /// Converts the database command hierarchy into a simple string, this is an easy interface to work with
@@ -38,6 +75,36 @@ pub enum RequestManagerError {
     /// From control commands
     #[error("Database Error Status: {0}")]
     DatabaseErrorStatus(String),
+
+    /// The request was dropped without being run, e.g. because the database is restarting (see
+    /// `RequestManager::restart`) or a worker restarted. The request can be retried
+    #[error("Database is restarting, retry the request")]
+    DatabaseRestarting,
+
+    /// From tenants that exceeded their quota, see `DatabaseOptions::set_quota`
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(QuotaExceeded),
+
+    /// From transactions that are too large, see `DatabaseOptions::set_transaction_limits`
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(LimitExceeded),
+
+    /// From transactions that set a context field the database decides, see `DatabaseOptions::set_context_policy`
+    #[error("{0}")]
+    ContextOverrideRejected(ContextOverrideRejected),
+
+    /// From requests that timed out waiting for their tag's limit, see `DatabaseOptions::set_tag_limit`
+    #[error("Throttled: {0}")]
+    Throttled(TagThrottled),
+}
+
+/// Outcome of one transaction of `RequestManager::send_chunked_transaction`
+#[derive(Debug)]
+pub struct TransactionChunk {
+    /// Index of the chunk's first statement in the statements that were sent
+    pub start: usize,
+    pub len: usize,
+    pub result: Result<Vec<StatementResult>, RequestManagerError>,
 }
 
 #[allow(dead_code)]
@@ -70,8 +137,137 @@ impl Deref for RequestManager {
 /// We want to have a single request manager instance that can be shared or sent across multiple threads.
 /// The way we can do this without poluting every consumer with an Arc<RequestManager> is to use the Deref trait
 pub struct RequestManagerInner {
-    database_sender: Vec<flume::Sender<DatabaseCommandRequest>>,
+    handle: RequestManagerHandle,
     sender_strategy: SenderSelectionStrategy,
+    /// Worker threads of the database, only the request manager returned by `Database::run` (and its clones) owns
+    /// them. Once the last clone is dropped the database is shut down and the workers are joined
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Drop for RequestManagerInner {
+    fn drop(&mut self) {
+        let workers = std::mem::take(self.workers.get_mut().unwrap());
+
+        if workers.is_empty() {
+            return;
+        }
+
+        let shutdown = match &*self.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => RequestManager::from_channels(channels.clone())
+                .send_shutdown_request(ShutdownRequest::Coordinator),
+            DatabaseChannels::Restarting => Err(RequestManagerError::DatabaseRestarting),
+        };
+
+        join_workers(workers, &shutdown);
+    }
+}
+
+/// Waits for the worker threads to exit. If the shutdown timed out the workers that are still running are left
+/// running, they may never exit
+fn join_workers(workers: Vec<JoinHandle<()>>, shutdown: &Result<String, RequestManagerError>) {
+    let timed_out = matches!(shutdown, Err(RequestManagerError::DatabaseTimeout));
+
+    for worker in workers {
+        if timed_out && !worker.is_finished() {
+            log::warn!("Worker thread did not shut down, leaving it running in the background");
+            continue;
+        }
+
+        if worker.join().is_err() {
+            log::error!("Worker thread panicked");
+        }
+    }
+}
+
+enum DatabaseChannels {
+    Running(WorkerChannels),
+    /// Requests fail with `RequestManagerError::DatabaseRestarting` until the handle is re-pointed
+    Restarting,
+}
+
+/// Channels of the worker threads, see `DatabaseOptions::set_read_threads`
+#[derive(Clone)]
+struct WorkerChannels {
+    senders: Vec<flume::Sender<DatabaseCommandRequest>>,
+    /// If set the workers are split into pools, workers from this index on only run read-only transactions and
+    /// the workers before it run transactions with mutations. Controls can run on any worker
+    read_pool_start: Option<usize>,
+    /// If set, requests are not routed to workers that are paused or executing a control
+    availability: Option<WorkerAvailability>,
+    /// Transactions that exceed the limits are rejected before they are queued
+    limits: TransactionLimits,
+    /// Applied to the context of every transaction before it is queued
+    context_policy: ContextPolicy,
+    /// See `RequestManager::next_id`
+    ids: IdGenerator,
+    /// Requests of limited tags wait for their tag's limit before they are queued
+    tag_throttle: Option<TagThrottle>,
+    /// If set, single reads run on the caller's thread, see `DatabaseOptions::set_inline_reads`. Weak so the
+    /// database is still dropped once its workers exit
+    inline_reads: Option<Weak<Database>>,
+}
+
+impl WorkerChannels {
+    /// Indexes of the workers that can run the command
+    fn pool(&self, command: &DatabaseCommand) -> Range<usize> {
+        match (self.read_pool_start, command) {
+            (Some(read_pool_start), DatabaseCommand::Transaction(statements)) => {
+                match statements.iter().any(|statement| statement.is_mutation()) {
+                    true => 0..read_pool_start,
+                    false => read_pool_start..self.senders.len(),
+                }
+            }
+            _ => 0..self.senders.len(),
+        }
+    }
+
+    /// Indexes of the workers in the pool that are available. If none are, e.g. the whole pool is paused, the
+    /// request is queued on the pool anyway and runs once a worker is available again
+    fn available(&self, pool: Range<usize>) -> Vec<usize> {
+        let Some(availability) = &self.availability else {
+            return pool.collect();
+        };
+
+        let available = pool
+            .clone()
+            .filter(|thread_id| availability.is_available(*thread_id))
+            .collect::<Vec<usize>>();
+
+        match available.is_empty() {
+            true => pool.collect(),
+            false => available,
+        }
+    }
+}
+
+/// The worker channels that a request manager (and each of its clones) sends requests to. Embedded users that
+/// restart the database in-process re-point the handle at the new database, see `RequestManager::restart`
+pub struct RequestManagerHandle(RwLock<DatabaseChannels>);
+
+impl RequestManagerHandle {
+    /// Atomically switches every clone of the request manager to the worker channels of another request manager,
+    /// e.g. the one returned by `Database::run` for the restarted database
+    pub fn repoint(&self, request_manager: &RequestManager) {
+        let channels = match &*request_manager.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => DatabaseChannels::Running(channels.clone()),
+            DatabaseChannels::Restarting => DatabaseChannels::Restarting,
+        };
+
+        *self.0.write().unwrap() = channels;
+    }
+
+    pub fn is_restarting(&self) -> bool {
+        matches!(&*self.0.read().unwrap(), DatabaseChannels::Restarting)
+    }
+
+    /// Requests fail with `RequestManagerError::DatabaseRestarting` until the handle is re-pointed, returns a
+    /// request manager for the previous channels (e.g. to shut the previous database down)
+    fn begin_restart(&self) -> Option<RequestManager> {
+        match std::mem::replace(&mut *self.0.write().unwrap(), DatabaseChannels::Restarting) {
+            DatabaseChannels::Running(channels) => Some(RequestManager::from_channels(channels)),
+            DatabaseChannels::Restarting => None,
+        }
+    }
 }
 
 /// Goal of the request manager is to provide a simple interface for interacting with the database
@@ -95,35 +291,278 @@ pub struct RequestManagerInner {
 ///     the database is owned by the database threads via an Arc<Database>. Once those threads return (exit) the database is dropped
 impl RequestManager {
     pub fn new(database_sender: Vec<flume::Sender<DatabaseCommandRequest>>) -> Self {
+        Self::from_channels(WorkerChannels {
+            senders: database_sender,
+            read_pool_start: None,
+            availability: None,
+            limits: TransactionLimits::default(),
+            context_policy: ContextPolicy::default(),
+            ids: IdGenerator::default(),
+            tag_throttle: None,
+            inline_reads: None,
+        })
+    }
+
+    /// Mutations are sent to the write senders and read-only transactions to the read senders
+    pub fn new_with_pools(
+        write_senders: Vec<flume::Sender<DatabaseCommandRequest>>,
+        read_senders: Vec<flume::Sender<DatabaseCommandRequest>>,
+    ) -> Self {
+        let read_pool_start = write_senders.len();
+
+        Self::from_channels(WorkerChannels {
+            senders: write_senders.into_iter().chain(read_senders).collect(),
+            read_pool_start: Some(read_pool_start),
+            availability: None,
+            limits: TransactionLimits::default(),
+            context_policy: ContextPolicy::default(),
+            ids: IdGenerator::default(),
+            tag_throttle: None,
+            inline_reads: None,
+        })
+    }
+
+    /// Transactions that exceed the limits are rejected without being queued, the workers check them as well
+    pub fn set_transaction_limits(self, limits: TransactionLimits) -> Self {
+        if let DatabaseChannels::Running(channels) = &mut *self.handle.0.write().unwrap() {
+            channels.limits = limits;
+        }
+
+        self
+    }
+
+    /// Defaults are filled into the context of each transaction, contexts that set a field clients may not
+    /// override are rejected without being queued
+    pub fn set_context_policy(self, context_policy: ContextPolicy) -> Self {
+        if let DatabaseChannels::Running(channels) = &mut *self.handle.0.write().unwrap() {
+            channels.context_policy = context_policy;
+        }
+
+        self
+    }
+
+    /// Ids handed out by `next_id` are generated by the generator, see `DatabaseOptions::set_id_generation`
+    pub fn set_id_generator(self, ids: IdGenerator) -> Self {
+        if let DatabaseChannels::Running(channels) = &mut *self.handle.0.write().unwrap() {
+            channels.ids = ids;
+        }
+
+        self
+    }
+
+    /// A new id for an entity, e.g. for a person that is about to be added. Ids are random unless the database
+    /// was configured with seeded ids (see `DatabaseOptions::set_id_generation`), seeded ids continue their
+    /// sequence across restarts
+    pub fn next_id(&self) -> EntityId {
+        match &*self.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => channels.ids.next_id(),
+            DatabaseChannels::Restarting => EntityId::new(),
+        }
+    }
+
+    /// Requests of a limited tag wait until the tag is below its limit before they are queued, see `TagLimit`
+    pub fn set_tag_throttle(self, tag_throttle: TagThrottle) -> Self {
+        if let DatabaseChannels::Running(channels) = &mut *self.handle.0.write().unwrap() {
+            channels.tag_throttle = Some(tag_throttle);
+        }
+
+        self
+    }
+
+    /// Single reads are run against the database on the caller's thread when it is safe to, see
+    /// `Database::inline_read`
+    pub(super) fn set_inline_reads(self, database: Option<Weak<Database>>) -> Self {
+        if let DatabaseChannels::Running(channels) = &mut *self.handle.0.write().unwrap() {
+            channels.inline_reads = database;
+        }
+
+        self
+    }
+
+    /// Whether a worker that can run the command has an empty queue, see `TagPriority::Background`
+    fn has_idle_worker(&self, command: &DatabaseCommand) -> bool {
+        match &*self.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => channels
+                .available(channels.pool(command))
+                .into_iter()
+                .any(|index| channels.senders[index].is_empty()),
+            // The request fails once it is sent
+            DatabaseChannels::Restarting => true,
+        }
+    }
+
+    /// Requests are only routed to workers that are available, the index of a sender is its thread id
+    pub fn set_availability(self, availability: WorkerAvailability) -> Self {
+        if let DatabaseChannels::Running(channels) = &mut *self.handle.0.write().unwrap() {
+            channels.availability = Some(availability);
+        }
+
+        self
+    }
+
+    /// The request manager owns the worker threads, see `close`
+    pub(super) fn set_workers(self, workers: Vec<JoinHandle<()>>) -> Self {
+        *self.workers.lock().unwrap() = workers;
+
+        self
+    }
+
+    /// A request manager for the same workers that does not own them, e.g. for a thread of the database itself
+    pub(super) fn without_workers(&self) -> Self {
+        let channels = match &*self.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => DatabaseChannels::Running(channels.clone()),
+            DatabaseChannels::Restarting => DatabaseChannels::Restarting,
+        };
+
+        Self::from_database_channels(channels)
+    }
+
+    fn id_generator(&self) -> IdGenerator {
+        match &*self.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => channels.ids.clone(),
+            DatabaseChannels::Restarting => IdGenerator::default(),
+        }
+    }
+
+    fn from_channels(channels: WorkerChannels) -> Self {
+        Self::from_database_channels(DatabaseChannels::Running(channels))
+    }
+
+    fn from_database_channels(channels: DatabaseChannels) -> Self {
         Self(Arc::new(RequestManagerInner {
-            database_sender: database_sender,
+            handle: RequestManagerHandle(RwLock::new(channels)),
             sender_strategy: SenderSelectionStrategy::new_round_robin(),
+            workers: Mutex::new(vec![]),
         }))
     }
 
-    fn get_sender(&self) -> &flume::Sender<DatabaseCommandRequest> {
-        let selected_sender = match &self.sender_strategy {
+    pub fn handle(&self) -> &RequestManagerHandle {
+        &self.handle
+    }
+
+    /// What the database supports, clients negotiate a session with `Capabilities::negotiate`
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Shuts the database down and starts a new one with the options, e.g. for embedded users that reconfigure
+    /// the database. Every clone of the request manager is re-pointed at the new database, requests sent in the
+    /// meantime fail with `RequestManagerError::DatabaseRestarting`
+    pub fn restart(&self, options: DatabaseOptions) -> Result<String, RequestManagerError> {
+        let Some(previous) = self.handle.begin_restart() else {
+            return Err(RequestManagerError::DatabaseRestarting);
+        };
+
+        let shutdown = previous.send_shutdown_request(ShutdownRequest::Coordinator);
+
+        // The previous database could already have been shut down, the new one is started either way
+        if let Err(e) = &shutdown {
+            log::warn!("Unable to shut down the database before restarting: {}", e);
+        }
+
+        // The previous WAL has been written before the restarted database restores it
+        join_workers(
+            std::mem::take(&mut *self.workers.lock().unwrap()),
+            &shutdown,
+        );
+
+        let id_generation = options.id_generation.clone();
+        let mut restarted = Database::new(options).run();
+
+        // Seeded ids would otherwise start over and collide with the ids that were already handed out
+        if previous.id_generator().generation() == &id_generation {
+            restarted = restarted.set_id_generator(previous.id_generator());
+        }
+
+        self.handle.repoint(&restarted);
+        *self.workers.lock().unwrap() = std::mem::take(&mut *restarted.workers.lock().unwrap());
+
+        shutdown
+    }
+
+    /// Shuts the database down and waits for its threads to exit, including the WAL, scheduler, publisher and
+    /// network storage threads. Requests fail with `RequestManagerError::DatabaseErrorStatus` once it is closed
+    ///
+    /// Dropping the last clone of the request manager returned by `Database::run` closes the database as well
+    pub fn close(&self) -> Result<String, RequestManagerError> {
+        let shutdown = self.send_shutdown_request(ShutdownRequest::Coordinator);
+
+        join_workers(
+            std::mem::take(&mut *self.workers.lock().unwrap()),
+            &shutdown,
+        );
+
+        shutdown
+    }
+
+    /// Sends the request to one of the worker threads. If the request cannot be sent it is dropped, so its
+    /// requester sees `RequestManagerError::DatabaseRestarting`
+    fn send_to_worker(&self, request: DatabaseCommandRequest) -> Result<(), RequestManagerError> {
+        let channels = self.handle.0.read().unwrap();
+
+        let DatabaseChannels::Running(channels) = &*channels else {
+            return Err(RequestManagerError::DatabaseRestarting);
+        };
+
+        // Rejected requests are resolved right away, the requester sees `RequestManagerError::LimitExceeded`
+        if let DatabaseCommand::Transaction(statements) = &request.command {
+            if let Err(limit_exceeded) = channels.limits.check(statements) {
+                let _ = request.resolver.send(
+                    DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                        DatabaseCommandTransactionResponse::LimitExceeded(limit_exceeded),
+                    ),
+                );
+
+                return Ok(());
+            }
+        }
+
+        let candidates = channels.available(channels.pool(&request.command));
+
+        self.select_sender(&channels.senders, &candidates)
+            .send(request)
+            .map_err(|e| {
+                log::error!("{}", e);
+
+                // The likely result of this error is that the database has shut down, which will
+                //  result in the database sender channel being closed. The other possible error is that
+                //  the channel has been overloaded, though we do not bound
+                RequestManagerError::DatabaseErrorStatus(
+                    "Request failed, this is likely due to the database being shutdown".to_string(),
+                )
+            })
+    }
+
+    /// Picks one of the candidate senders, candidates are indexes into the senders
+    fn select_sender<'a>(
+        &self,
+        database_sender: &'a [flume::Sender<DatabaseCommandRequest>],
+        candidates: &[usize],
+    ) -> &'a flume::Sender<DatabaseCommandRequest> {
+        let selected_index = match &self.sender_strategy {
             SenderSelectionStrategy::Random => {
                 let mut rng = thread_rng();
-                self.database_sender.choose(&mut rng)
+                candidates.choose(&mut rng).copied()
             }
             // Ideally this strategy would assign work to a channel where the length is 0 and the thread is idle.
             // This is challenging, because we can have an empty channel but the thread is still processing a request.
             //
             // Is it possible to have the request_manager keep track of the number of requests in flight? Yes,
             //  though our async interface makes this hard.
-            SenderSelectionStrategy::ShortestQueueFirst => self
-                .database_sender
+            SenderSelectionStrategy::ShortestQueueFirst => candidates
                 .iter()
-                .min_by_key(|sender| sender.len()),
+                .copied()
+                .min_by_key(|index| database_sender[*index].len()),
             SenderSelectionStrategy::RoundRobin(counter) => {
-                let index = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-                    % self.database_sender.len();
-                self.database_sender.get(index)
+                let index =
+                    counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed) % candidates.len();
+                candidates.get(index).copied()
             }
         };
 
-        selected_sender.expect("There should always be a sender")
+        selected_index
+            .and_then(|index| database_sender.get(index))
+            .expect("There should always be a sender")
     }
 
     // -- Entity Methods: Async Task --
@@ -169,6 +608,76 @@ impl RequestManager {
         TaskListResponse::send(self, query, transaction_context)
     }
 
+    pub fn send_list_page_task(
+        &self,
+        query: Option<QueryPersonData>,
+        page_request: PageRequest,
+        transaction_context: TransactionContext,
+    ) -> TaskListPageResponse {
+        TaskListPageResponse::send(self, query, page_request, transaction_context)
+    }
+
+    pub fn send_query_view_task(
+        &self,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> TaskQueryViewResponse {
+        TaskQueryViewResponse::send(self, name, transaction_context)
+    }
+
+    pub fn send_execute_prepared_query_task(
+        &self,
+        name: String,
+        values: BTreeMap<String, String>,
+        transaction_context: TransactionContext,
+    ) -> TaskExecutePreparedQueryResponse {
+        TaskExecutePreparedQueryResponse::send(self, name, values, transaction_context)
+    }
+
+    pub fn send_get_many_at_transaction_task(
+        &self,
+        ids: Vec<EntityId>,
+        at: TransactionId,
+        transaction_context: TransactionContext,
+    ) -> TaskGetManyAtTransactionResponse {
+        TaskGetManyAtTransactionResponse::send(self, ids, at, transaction_context)
+    }
+
+    pub fn send_scan_task(
+        &self,
+        start: Bound<EntityId>,
+        end: Bound<EntityId>,
+        limit: Option<usize>,
+        transaction_context: TransactionContext,
+    ) -> TaskScanResponse {
+        TaskScanResponse::send(self, start, end, limit, transaction_context)
+    }
+
+    pub fn send_history_task(
+        &self,
+        id: EntityId,
+        request: HistoryRequest,
+        transaction_context: TransactionContext,
+    ) -> TaskHistoryResponse {
+        TaskHistoryResponse::send(self, id, request, transaction_context)
+    }
+
+    pub fn send_query_system_table_task(
+        &self,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> TaskQuerySystemTableResponse {
+        TaskQuerySystemTableResponse::send(self, name, transaction_context)
+    }
+
+    pub fn send_next_val_task(
+        &self,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> TaskNextValResponse {
+        TaskNextValResponse::send(self, name, transaction_context)
+    }
+
     // -- Entity Methods: Sync --
     pub fn send_add(
         &self,
@@ -214,587 +723,5519 @@ impl RequestManager {
         self.send_list_task(query, transaction_context).get()
     }
 
-    /// Convenience method to send a single statement to the database and returns the response
-    ///
-    /// The reason this method exists is because it's a common pattern to send a single statement to the database and get a single response back
-    pub fn send_single_statement(
+    /// Same as `send_list`, but the people are returned as an Arrow record batch for analytical reads, see
+    /// `people_to_record_batch`
+    pub fn send_list_arrow(
         &self,
-        statement: Statement,
+        query: Option<QueryPersonData>,
         transaction_context: TransactionContext,
-    ) -> Result<StatementResult, RequestManagerError> {
-        let single_statement_result = self
-            .send_transaction(vec![statement], transaction_context)?
-            .pop()
-            .expect("single a statement should generate single response");
+    ) -> Result<RecordBatch, RequestManagerError> {
+        let people = self.send_list(query, transaction_context)?;
 
-        return Ok(single_statement_result);
+        Ok(people_to_record_batch(&people))
     }
 
-    /// Sends a set of statements to the database and returns the response
-    pub fn send_transaction_task(
+    /// Same as `send_list`, but the list is not run if the table is unchanged since the version of a previous
+    /// result. Reads from a clone or an earlier snapshot are always run
+    pub fn send_list_if_changed(
         &self,
-        statements: Vec<Statement>,
+        query: Option<QueryPersonData>,
+        if_unchanged_since: Option<TableVersion>,
         transaction_context: TransactionContext,
-    ) -> TaskStatementResponse {
-        TaskStatementResponse::send(self, statements, transaction_context)
+    ) -> Result<ConditionalRead<Vec<Person>>, RequestManagerError> {
+        let response = send_request(
+            self,
+            vec![Statement::TableVersion, Statement::List(query)],
+            transaction_context.set_if_unchanged_since(if_unchanged_since),
+        );
+
+        let command_result = map_response(response.receiver.recv_timeout(response.timeout))?;
+
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::NotModified(version),
+            ) => Ok(ConditionalRead::NotModified(version)),
+            DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::Commit(mut action_results),
+            ) => {
+                let people = action_results
+                    .pop()
+                    .expect("list statement should generate a response")
+                    .list();
+                let version = action_results
+                    .pop()
+                    .expect("table version statement should generate a response")
+                    .table_version();
+
+                Ok(ConditionalRead::Modified(people, version))
+            }
+            _ => panic!(
+                "Transaction commands should always return a commit, rollback or not modified"
+            ),
+        }
     }
 
-    pub fn send_transaction(
+    pub fn send_list_page(
         &self,
-        statements: Vec<Statement>,
+        query: Option<QueryPersonData>,
+        page_request: PageRequest,
         transaction_context: TransactionContext,
-    ) -> Result<Vec<StatementResult>, RequestManagerError> {
-        self.send_transaction_task(statements, transaction_context)
+    ) -> Result<Page, RequestManagerError> {
+        self.send_list_page_task(query, page_request, transaction_context)
             .get()
     }
 
-    // -- Control Methods --
+    /// Runs a query built with `Query::person`, a limited query returns the first page of the matching people
+    pub fn send_query(
+        &self,
+        query: PersonQuery,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<Person>, RequestManagerError> {
+        match query.get_limit() {
+            Some(limit) => self
+                .send_list_page(
+                    Some(query.into()),
+                    PageRequest {
+                        cursor: None,
+                        limit,
+                    },
+                    transaction_context,
+                )
+                .map(|page| page.people),
+            None => self.send_list(Some(query.into()), transaction_context),
+        }
+    }
 
-    /// Sends a shutdown request to the database and returns the database's response
-    pub fn send_shutdown_request(
+    pub fn send_query_view(
         &self,
-        request: ShutdownRequest,
-    ) -> Result<String, RequestManagerError> {
-        return self.send_control(Control::Shutdown(request));
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Result<ViewResult, RequestManagerError> {
+        self.send_query_view_task(name, transaction_context).get()
     }
 
-    pub fn send_pause_request(
+    /// Runs a prepared query with the parameter values, see `Statement::ExecutePreparedQuery`
+    pub fn send_execute_prepared_query(
         &self,
-        resume: flume::Receiver<()>,
-    ) -> Result<String, RequestManagerError> {
-        return self.send_control(Control::PauseDatabase(resume));
+        name: String,
+        values: BTreeMap<String, String>,
+        transaction_context: TransactionContext,
+    ) -> Result<ViewResult, RequestManagerError> {
+        self.send_execute_prepared_query_task(name, values, transaction_context)
+            .get()
     }
 
-    /// Resets the database to a clean state
-    pub fn send_reset_request(&self) -> Result<String, RequestManagerError> {
-        return self.send_control(Control::ResetDatabase);
+    /// Returns the rows of a system table, see `Statement::QuerySystemTable`
+    /// Returns the people as of the transaction, see `Statement::GetManyAtTransaction`
+    pub fn send_get_many_at_transaction(
+        &self,
+        ids: Vec<EntityId>,
+        at: TransactionId,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<Person>, RequestManagerError> {
+        self.send_get_many_at_transaction_task(ids, at, transaction_context)
+            .get()
     }
 
-    pub fn send_info_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
-        let command_result =
-            self.send_database_command(DatabaseCommand::Control(Control::DatabaseStats))?;
+    /// Returns the people with an id in the range ordered by id, see `Statement::Scan`
+    pub fn send_scan(
+        &self,
+        start: Bound<EntityId>,
+        end: Bound<EntityId>,
+        limit: Option<usize>,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<Person>, RequestManagerError> {
+        self.send_scan_task(start, end, limit, transaction_context)
+            .get()
+    }
 
-        // TODO: Clean this logic up, as we are now able to return success, info and error
-        match command_result {
-            DatabaseCommandResponse::DatabaseCommandControlResponse(
-                DatabaseCommandControlResponse::Info(i),
-            ) => Ok(i),
-            _ => panic!("Controls should always return a success, info or error status"),
-        }
+    /// Returns a page of the versions of a person, see `Statement::History`. None if the person does not exist
+    pub fn send_history(
+        &self,
+        id: EntityId,
+        request: HistoryRequest,
+        transaction_context: TransactionContext,
+    ) -> Result<Option<HistoryPage>, RequestManagerError> {
+        self.send_history_task(id, request, transaction_context)
+            .get()
     }
 
-    pub fn send_snapshot_request(&self) -> Result<String, RequestManagerError> {
-        return self.send_control(Control::SnapshotDatabase);
+    /// Returns every version of a person and the ids it was renamed or merged from, see `Statement::Lineage`.
+    /// None if the person does not exist
+    pub fn send_lineage(
+        &self,
+        id: EntityId,
+        transaction_context: TransactionContext,
+    ) -> Result<Option<Vec<PersonVersion>>, RequestManagerError> {
+        self.send_single_statement(Statement::Lineage(id), transaction_context)
+            .map(|result| result.found().map(StatementResult::list_version))
     }
 
-    pub fn send_sleep_request(&self, duration: Duration) -> Result<String, RequestManagerError> {
-        return self.send_control(Control::Sleep(duration));
+    /// Whether the person exists, without returning the person
+    pub fn send_exists(
+        &self,
+        id: EntityId,
+        transaction_context: TransactionContext,
+    ) -> Result<bool, RequestManagerError> {
+        self.send_single_statement(Statement::Exists(id), transaction_context)
+            .map(StatementResult::exists)
     }
 
-    // -- Internal methods --
-    fn send_control(&self, control: Control) -> Result<String, RequestManagerError> {
-        let command_result = self.send_database_command(DatabaseCommand::Control(control))?;
+    /// Version count and first / last modification of the person, without returning its history
+    pub fn send_meta(
+        &self,
+        id: EntityId,
+        transaction_context: TransactionContext,
+    ) -> Result<Option<EntityMeta>, RequestManagerError> {
+        self.send_single_statement(Statement::Meta(id), transaction_context)
+            .map(StatementResult::meta)
+    }
 
-        match command_result {
-            DatabaseCommandResponse::DatabaseCommandControlResponse(
-                DatabaseCommandControlResponse::Success(s),
-            ) => Ok(s),
-            _ => panic!("Controls should always return a success, info or error status"),
-        }
+    /// The number of people that match the query, without returning the people
+    pub fn send_count(
+        &self,
+        query: Option<QueryPersonData>,
+        transaction_context: TransactionContext,
+    ) -> Result<usize, RequestManagerError> {
+        self.send_single_statement(Statement::Count(query), transaction_context)
+            .map(StatementResult::count)
     }
 
-    /// Generic method to send a transaction or control command to the database and returns the response. For type safety this method should not be used
-    /// use the more specific methods instead. These specific methods ensure that the correct response is returned
-    fn send_database_command(
+    /// Irreversibly removes the person and every version of it, see `Statement::Purge`. Returns the number of
+    /// versions that were removed
+    pub fn send_purge(
         &self,
-        database_request: DatabaseCommand,
-    ) -> Result<DatabaseCommandResponse, RequestManagerError> {
-        let (response_sender, response_receiver) = oneshot::channel::<DatabaseCommandResponse>();
+        id: EntityId,
+        transaction_context: TransactionContext,
+    ) -> Result<usize, RequestManagerError> {
+        self.send_single_statement(Statement::Purge(id), transaction_context)
+            .map(StatementResult::purged)
+    }
 
-        let request = DatabaseCommandRequest {
-            resolver: response_sender,
-            command: database_request,
-            transaction_context: TransactionContext::default(),
+    /// Writes the bytes to the attachment store and attaches them to the person under the name, an attachment
+    /// with the same name is replaced. The bytes are written even if the update rolls back, see
+    /// `send_vacuum_attachments_request`
+    pub fn send_attach(
+        &self,
+        id: EntityId,
+        name: String,
+        content_type: Option<String>,
+        bytes: Vec<u8>,
+        transaction_context: TransactionContext,
+    ) -> Result<Person, RequestManagerError> {
+        let size = bytes.len() as u64;
+        let digest = self.send_control(Control::WriteAttachment(bytes))?;
+
+        let attachment = Attachment {
+            name,
+            digest,
+            size,
+            content_type,
         };
 
-        // Sends the request to the database worker, database will response
-        //  on the response_receiver once it's finished processing it's request
-        let send_result = self.get_sender().send(request);
+        self.send_update(
+            id,
+            UpdatePersonData {
+                attachments: UpdateAttachmentStatement::Attach(attachment),
+                ..UpdatePersonData::default()
+            },
+            transaction_context,
+        )
+    }
 
-        if let Err(e) = send_result {
-            log::error!("{}", e);
+    /// Removes the attachment from the person, earlier versions keep referencing it
+    pub fn send_detach(
+        &self,
+        id: EntityId,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Result<Person, RequestManagerError> {
+        self.send_update(
+            id,
+            UpdatePersonData {
+                attachments: UpdateAttachmentStatement::Detach(name),
+                ..UpdatePersonData::default()
+            },
+            transaction_context,
+        )
+    }
 
-            // The likely result of this error is that the database has shut down, which will
-            //  result in the database sender channel being closed. The other possible error is that
-            //  the channel has been overloaded, though we do not bound
-            return Err(RequestManagerError::DatabaseErrorStatus(
-                "Request failed, this is likely due to the database being shutdown".to_string(),
-            ));
-        }
+    /// None if the person does not exist or has no attachment with the name
+    pub fn send_get_attachment(
+        &self,
+        id: EntityId,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Result<Option<AttachmentContent>, RequestManagerError> {
+        self.send_single_statement(Statement::GetAttachment(id, name), transaction_context)
+            .map(|result| result.found().and_then(StatementResult::attachment))
+    }
 
-        // If the database is large it can take > 30 seconds to reset
-        let response = response_receiver.recv_timeout(Duration::from_secs(60));
+    pub fn send_query_system_table(
+        &self,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Result<SystemTable, RequestManagerError> {
+        self.send_query_system_table_task(name, transaction_context)
+            .get()
+    }
 
-        map_response(response)
+    /// Returns the next value of a named sequence, see `Statement::NextVal`
+    pub fn send_next_val(
+        &self,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Result<u64, RequestManagerError> {
+        self.send_next_val_task(name, transaction_context).get()
     }
 
-    #[allow(dead_code)]
-    fn send_database_command_task(&self, database_request: DatabaseCommand) -> TaskCommandResponse {
-        let (response_sender, response_receiver) = oneshot::channel::<DatabaseCommandResponse>();
+    /// Returns at most `limit` pending outbox messages, oldest first. Messages are enqueued with
+    /// `Statement::Enqueue` in the transaction that makes the changes they describe
+    pub fn send_read_outbox(
+        &self,
+        topic: Option<String>,
+        limit: usize,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<OutboxMessage>, RequestManagerError> {
+        self.send_single_statement(Statement::ReadOutbox(topic, limit), transaction_context)
+            .map(StatementResult::outbox)
+    }
 
-        let request = DatabaseCommandRequest {
-            resolver: response_sender,
-            command: database_request,
-            transaction_context: TransactionContext::default(),
-        };
+    /// Acknowledges outbox messages once they have been handled, see `Statement::AckOutbox`. Fails (and nothing is
+    /// acknowledged) if one of the messages has already been acknowledged, e.g. by another consumer
+    pub fn send_ack_outbox(
+        &self,
+        ids: Vec<OutboxId>,
+        transaction_context: TransactionContext,
+    ) -> Result<(), RequestManagerError> {
+        self.send_single_statement(Statement::AckOutbox(ids), transaction_context)
+            .map(|_| ())
+    }
 
-        self.get_sender().send(request).unwrap();
+    /// Convenience method to send a single statement to the database and returns the response
+    ///
+    /// The reason this method exists is because it's a common pattern to send a single statement to the database and get a single response back
+    pub fn send_single_statement(
+        &self,
+        statement: Statement,
+        transaction_context: TransactionContext,
+    ) -> Result<StatementResult, RequestManagerError> {
+        let single_statement_result = self
+            .send_transaction(vec![statement], transaction_context)?
+            .pop()
+            .expect("single a statement should generate single response");
 
-        TaskCommandResponse::send(response_receiver)
+        return Ok(single_statement_result);
     }
-}
 
-fn map_response(
-    response: Result<DatabaseCommandResponse, oneshot::RecvTimeoutError>,
-) -> Result<DatabaseCommandResponse, RequestManagerError> {
-    match response {
-        // Transaction commands
-        Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(transaction_response)) => {
-            match transaction_response {
-                DatabaseCommandTransactionResponse::Commit(statement_result) => {
-                    Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
-                        DatabaseCommandTransactionResponse::Commit(statement_result),
-                    ))
-                }
-                DatabaseCommandTransactionResponse::Rollback(s) => {
-                    Err(RequestManagerError::TransactionRollback(s))
-                }
-                DatabaseCommandTransactionResponse::Status(s) => {
-                    Err(RequestManagerError::TransactionStatus(s))
-                }
-            }
-        }
-        // Control commands
-        Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(control_response)) => {
-            match control_response {
-                DatabaseCommandControlResponse::Success(s) => {
-                    Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(
-                        DatabaseCommandControlResponse::Success(s),
-                    ))
-                }
-                DatabaseCommandControlResponse::Info(s) => {
-                    Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(
-                        DatabaseCommandControlResponse::Info(s),
-                    ))
-                }
-                DatabaseCommandControlResponse::Error(s) => {
-                    Err(RequestManagerError::DatabaseErrorStatus(s))
-                }
-            }
-        }
-        // Issues with the channel
-        Err(oneshot::RecvTimeoutError::Timeout) => Err(RequestManagerError::DatabaseTimeout),
-        Err(oneshot::RecvTimeoutError::Disconnected) => panic!("Processor exited"),
+    /// Sends a set of statements to the database and returns the response
+    pub fn send_transaction_task(
+        &self,
+        statements: Vec<Statement>,
+        transaction_context: TransactionContext,
+    ) -> TaskStatementResponse {
+        TaskStatementResponse::send(self, statements, transaction_context)
     }
-}
 
-fn send_request(
-    request_manager: &RequestManager,
-    statement: Vec<Statement>,
-    transaction_context: TransactionContext,
-) -> oneshot::Receiver<DatabaseCommandResponse> {
-    let (response_sender, response_receiver) = oneshot::channel::<DatabaseCommandResponse>();
+    pub fn send_transaction(
+        &self,
+        statements: Vec<Statement>,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<StatementResult>, RequestManagerError> {
+        self.send_transaction_task(statements, transaction_context)
+            .get()
+    }
 
-    let request = DatabaseCommandRequest {
-        resolver: response_sender,
-        command: DatabaseCommand::Transaction(statement),
-        transaction_context,
-    };
+    /// Runs the transaction as a dry run, returns the results it would commit with or the reason it would roll
+    /// back. See `TransactionContext::set_dry_run`
+    pub fn send_dry_run(
+        &self,
+        statements: Vec<Statement>,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<StatementResult>, RequestManagerError> {
+        self.send_transaction(statements, transaction_context.set_dry_run(true))
+    }
 
-    request_manager.get_sender().send(request).unwrap();
+    /// Splits the statements into transactions of at most `chunk_size` statements and runs them one after the
+    /// other, so a bulk import does not monopolize a worker. Chunks commit independently, a failed chunk does not
+    /// roll back the chunks before it and does not stop the chunks after it
+    pub fn send_chunked_transaction(
+        &self,
+        statements: Vec<Statement>,
+        chunk_size: usize,
+        transaction_context: TransactionContext,
+    ) -> Vec<TransactionChunk> {
+        let chunk_size = chunk_size.max(1);
+        let mut chunks = vec![];
+        let mut statements = statements.into_iter().peekable();
+        let mut start = 0;
+
+        while statements.peek().is_some() {
+            let chunk: Vec<Statement> = statements.by_ref().take(chunk_size).collect();
+            let len = chunk.len();
+
+            chunks.push(TransactionChunk {
+                start,
+                len,
+                result: self.send_transaction(chunk, transaction_context.clone()),
+            });
+
+            start += len;
+        }
 
-    response_receiver
-}
+        chunks
+    }
 
-fn get_statement(
-    response: &oneshot::Receiver<DatabaseCommandResponse>,
-) -> Result<Vec<StatementResult>, RequestManagerError> {
-    let response = response.recv_timeout(Duration::from_secs(30));
+    // -- Control Methods --
 
-    let command_result = map_response(response)?;
+    /// Sends a shutdown request to the database and returns the database's response
+    pub fn send_shutdown_request(
+        &self,
+        request: ShutdownRequest,
+    ) -> Result<String, RequestManagerError> {
+        return self.send_control(Control::Shutdown(request));
+    }
 
-    match command_result {
-        DatabaseCommandResponse::DatabaseCommandTransactionResponse(
-            DatabaseCommandTransactionResponse::Commit(action_results),
-        ) => Ok(action_results),
-        _ => panic!("Transaction commands should always return a commit or rollback"),
+    pub fn send_pause_request(
+        &self,
+        resume: flume::Receiver<()>,
+    ) -> Result<String, RequestManagerError> {
+        return self.send_control(Control::PauseDatabase(resume));
     }
-}
 
-pub trait Wait {
-    fn wait(&self);
-}
+    /// Resets the database to a clean state
+    pub fn send_reset_request(&self) -> Result<String, RequestManagerError> {
+        return self.send_control(Control::ResetDatabase);
+    }
 
-pub struct TaskCommandResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    /// Removes every person in a single transaction, the history of the table is kept (see
+    /// `Control::TruncateTable`). Returns the number of people that were removed
+    pub fn send_truncate_request(&self) -> Result<usize, RequestManagerError> {
+        let command_result =
+            self.send_database_command(DatabaseCommand::Control(Control::TruncateTable))?;
 
-impl TaskCommandResponse {
-    pub fn send(response: oneshot::Receiver<DatabaseCommandResponse>) -> Self {
-        Self { response }
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::Commit(results),
+            ) => Ok(results.len()),
+            _ => panic!("Truncating the table should return the transaction's results"),
+        }
     }
 
-    pub fn get(&self) -> Result<DatabaseCommandResponse, RequestManagerError> {
-        let response = self.response.recv_timeout(Duration::from_secs(30));
+    /// Renames every person whose id starts with `from` to the same id starting with `to`, either in a single
+    /// transaction under a pause or in transactions of `chunk_size` renames (see `Control::RenameIdPrefix`).
+    /// Returns the number of renamed ids and the transactions they were committed in
+    pub fn send_rename_id_prefix_request(
+        &self,
+        from: String,
+        to: String,
+        chunk_size: Option<usize>,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::RenameIdPrefix {
+            from,
+            to,
+            chunk_size,
+        })
+    }
 
-        map_response(response)
+    pub fn send_info_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::DatabaseStats)
     }
-}
 
-impl Wait for TaskCommandResponse {
-    fn wait(&self) {
-        self.get().expect("Should not timeout");
+    /// Validates the latest snapshot, returns a report of the verification including any discrepancies
+    pub fn send_verify_snapshot_request(
+        &self,
+        shadow_table: bool,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::VerifySnapshot { shadow_table })
     }
-}
 
-pub struct TaskStatementResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    pub fn send_snapshot_request(&self) -> Result<String, RequestManagerError> {
+        return self.send_control(Control::SnapshotDatabase);
+    }
 
-impl TaskStatementResponse {
-    pub fn send(
-        request_manager: &RequestManager,
-        statement: Vec<Statement>,
-        transaction_context: TransactionContext,
-    ) -> Self {
-        Self {
-            response: send_request(request_manager, statement, transaction_context),
-        }
+    /// Drops the transactions covered by the latest snapshot from the WAL, see `Control::CompactWal`
+    pub fn send_compact_wal_request(&self) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CompactWal)
     }
 
-    pub fn get(&self) -> Result<Vec<StatementResult>, RequestManagerError> {
-        get_statement(&self.response)
+    /// Switches storage to the engine the database is migrating to, see `Control::CutoverMigration`
+    pub fn send_cutover_migration_request(&self) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CutoverMigration)
     }
-}
 
-impl Wait for TaskStatementResponse {
-    fn wait(&self) {
-        self.get().expect("Should not timeout");
+    /// Creates (or replaces) a materialized view
+    pub fn send_create_view_request(
+        &self,
+        definition: ViewDefinition,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CreateView(definition))
     }
-}
 
-pub struct TaskAddResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    pub fn send_drop_view_request(&self, name: String) -> Result<String, RequestManagerError> {
+        self.send_control(Control::DropView(name))
+    }
 
-impl TaskAddResponse {
-    pub fn send(
-        request_manager: &RequestManager,
-        person: Person,
-        transaction_context: TransactionContext,
-    ) -> Self {
-        Self {
-            response: send_request(
-                request_manager,
-                vec![Statement::Add(person)],
-                transaction_context,
-            ),
-        }
+    /// Creates (or replaces) a read-only clone of the table as of the transaction, reads are sent to the clone
+    /// with `TransactionContext::set_clone`
+    pub fn send_clone_at_transaction_request(
+        &self,
+        name: String,
+        transaction_id: TransactionId,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CloneAtTransaction {
+            name,
+            transaction_id,
+        })
     }
 
-    pub fn get(&self) -> Result<Person, RequestManagerError> {
-        get_statement(&self.response).and_then(|mut action_result| {
-            Ok(action_result
-                .pop()
-                .expect("single a statement should generate single response")
-                .single())
-        })
+    pub fn send_drop_clone_request(&self, name: String) -> Result<String, RequestManagerError> {
+        self.send_control(Control::DropClone(name))
     }
-}
 
-impl Wait for TaskAddResponse {
-    fn wait(&self) {
-        self.get().expect("Should not timeout");
+    /// Returns the clones, keyed by clone name
+    pub fn send_list_clones_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListClones)
     }
-}
 
-pub struct TaskUpdateResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    /// Creates (or replaces) a row security policy, requests that run as the policy's role can only
+    /// read the rows that match one of the role's policies
+    pub fn send_create_policy_request(
+        &self,
+        policy: RowPolicy,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CreatePolicy(policy))
+    }
 
-impl TaskUpdateResponse {
-    fn send(
-        request_manager: &RequestManager,
-        id: EntityId,
-        person_update: UpdatePersonData,
-        transaction_context: TransactionContext,
-    ) -> Self {
-        Self {
-            response: send_request(
-                request_manager,
-                vec![Statement::Update(id, person_update)],
-                transaction_context,
-            ),
+    pub fn send_drop_policy_request(&self, name: String) -> Result<String, RequestManagerError> {
+        self.send_control(Control::DropPolicy(name))
+    }
+
+    /// Registers (or replaces) a prepared query, the definition is validated before it is registered
+    pub fn send_create_prepared_query_request(
+        &self,
+        definition: PreparedQueryDefinition,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CreatePreparedQuery(definition))
+    }
+
+    pub fn send_drop_prepared_query_request(
+        &self,
+        name: String,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::DropPreparedQuery(name))
+    }
+
+    /// Returns the prepared queries, keyed by name
+    pub fn send_list_prepared_queries_request(
+        &self,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListPreparedQueries)
+    }
+
+    /// Returns the row security policies, keyed by policy name
+    pub fn send_list_policies_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListPolicies)
+    }
+
+    /// Returns how the list query would be scanned (full or index scan) and the statistics the choice was based on
+    pub fn send_explain_query_request(
+        &self,
+        query: Option<QueryPersonData>,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ExplainQuery(query))
+    }
+
+    /// Returns the fields queries filter on and whether an index is recommended for each, keyed by field. The
+    /// recommended indexes are created if `DatabaseOptions::set_auto_create_indexes` is set
+    pub fn send_index_advisor_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::IndexAdvisor)
+    }
+
+    /// Creates an index on a field that is not indexed by default, the database is paused while the existing
+    /// versions are indexed
+    pub fn send_create_index_request(
+        &self,
+        field: IndexedField,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CreateIndex(field))
+    }
+
+    /// Creates an index on a field that is not indexed by default without pausing the database, the existing
+    /// versions are indexed in the background. Queries use the index once the backfill has completed, see
+    /// `send_list_index_backfills_request`
+    pub fn send_backfill_index_request(
+        &self,
+        field: IndexedField,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::BackfillIndex(field))
+    }
+
+    pub(crate) fn send_backfill_index_batch_request(
+        &self,
+        field: IndexedField,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::BackfillIndexBatch(field))
+    }
+
+    /// Returns the progress of the index backfills, keyed by field
+    pub fn send_list_index_backfills_request(
+        &self,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListIndexBackfills)
+    }
+
+    /// Returns the latest rolled back transactions (newest first), keyed by transaction id
+    pub fn send_list_rollback_audit_request(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListRollbackAudit(limit))
+    }
+
+    /// Returns the latest purges (newest first), keyed by transaction id
+    pub fn send_list_purge_audit_request(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListPurgeAudit(limit))
+    }
+
+    /// Returns whether each storage feature is recorded for the data directory, enabled and supported, keyed by
+    /// feature name
+    pub fn send_list_enabled_features_request(
+        &self,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListEnabledFeatures)
+    }
+
+    /// Schedules (or replaces) a recurring job
+    pub fn send_schedule_job_request(
+        &self,
+        definition: JobDefinition,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::ScheduleJob(definition))
+    }
+
+    /// Returns the scheduled jobs, keyed by job name
+    pub fn send_list_jobs_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListJobs)
+    }
+
+    pub fn send_cancel_job_request(&self, name: String) -> Result<String, RequestManagerError> {
+        self.send_control(Control::CancelJob(name))
+    }
+
+    /// Returns the requests currently running on each worker and per client counters
+    pub fn send_list_active_requests(&self) -> Result<ActivityReport, RequestManagerError> {
+        let command_result =
+            self.send_database_command(DatabaseCommand::Control(Control::ListActiveRequests))?;
+
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandControlResponse(
+                DatabaseCommandControlResponse::Activity(report),
+            ) => Ok(report),
+            _ => panic!("Controls should always return a success, info or error status"),
         }
     }
 
-    pub fn get(&self) -> Result<Person, RequestManagerError> {
-        get_statement(&self.response).and_then(|mut action_result| {
-            Ok(action_result
-                .pop()
-                .expect("single a statement should generate single response")
-                .single())
+    /// Starts a long running control (see `Control::is_operation`) and returns straight away, the control's
+    /// response is read with `send_operation_status_request` or `wait_for_operation`
+    pub fn send_start_operation_request(
+        &self,
+        control: Control,
+    ) -> Result<OperationStatus, RequestManagerError> {
+        self.send_control_operation(Control::StartOperation(Box::new(control)))
+    }
+
+    pub fn send_operation_status_request(
+        &self,
+        id: OperationId,
+    ) -> Result<OperationStatus, RequestManagerError> {
+        self.send_control_operation(Control::OperationStatus(id))
+    }
+
+    /// Waits up to `timeout` for the operation to finish, the wait does not hold a worker
+    pub fn wait_for_operation(
+        &self,
+        id: OperationId,
+        timeout: Duration,
+    ) -> Result<OperationStatus, RequestManagerError> {
+        let (sender, receiver) = flume::bounded(1);
+
+        self.send_control(Control::WatchOperation(id, sender))?;
+
+        receiver.recv_timeout(timeout).map_err(|e| match e {
+            flume::RecvTimeoutError::Timeout => RequestManagerError::DatabaseTimeout,
+            flume::RecvTimeoutError::Disconnected => RequestManagerError::DatabaseRestarting,
         })
     }
-}
 
-impl Wait for TaskUpdateResponse {
-    fn wait(&self) {
-        self.get().expect("Should not timeout");
+    /// Returns an ASCII armored age file containing the latest version of every row, only the holders of the
+    /// recipients' private keys can decrypt it
+    pub fn send_export_request(
+        &self,
+        recipients: Vec<String>,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::Export { recipients })
     }
-}
 
-pub struct TaskGetResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    /// The latest snapshot and the WAL tail as a newline delimited JSON `SnapshotArchive`, the database must have
+    /// taken a snapshot
+    pub fn send_download_snapshot_request(&self) -> Result<String, RequestManagerError> {
+        self.send_control(Control::DownloadSnapshot)
+    }
 
-impl TaskGetResponse {
-    pub fn send(
-        request_manager: &RequestManager,
-        id: EntityId,
-        transaction_context: TransactionContext,
-    ) -> Self {
-        Self {
-            response: send_request(
-                request_manager,
-                vec![Statement::Get(id)],
-                transaction_context,
-            ),
-        }
+    /// The people added, removed or changed between two snapshots (or a snapshot and the live table), in id
+    /// order. The diffs are computed as the returned iterator is read, see `SnapshotDiff`
+    pub fn send_snapshot_diff_request(
+        &self,
+        from: DiffSource,
+        to: DiffSource,
+    ) -> Result<SnapshotDiff, RequestManagerError> {
+        let (sender, receiver) = flume::bounded(1);
+
+        self.send_control(Control::DiffSnapshots {
+            from,
+            to,
+            diff: sender,
+        })?;
+
+        receiver
+            .recv()
+            .map_err(|_| RequestManagerError::DatabaseRestarting)
     }
 
-    pub fn get(&self) -> Result<Option<Person>, RequestManagerError> {
-        get_statement(&self.response).and_then(|mut action_result| {
-            Ok(action_result
-                .pop()
-                .expect("single a statement should generate single response")
-                .get_single())
+    /// Deletes the attachments that no version references and that were written at least `min_age` ago, younger
+    /// attachments may belong to an update that has not committed yet. Returns how many were deleted and kept
+    pub fn send_vacuum_attachments_request(
+        &self,
+        min_age: Duration,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::VacuumAttachments(min_age))
+    }
+
+    /// Squashes the history of the table's rows with the configured `HistorySquash`, the database is paused while
+    /// the rows are squashed. Returns how many rows and versions were squashed
+    pub fn send_squash_history_request(
+        &self,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::SquashHistory)
+    }
+
+    /// Cancels a running request, see `send_list_active_requests` for request ids
+    pub fn send_kill_request(&self, request_id: RequestId) -> Result<String, RequestManagerError> {
+        self.send_control(Control::KillRequest(request_id))
+    }
+
+    /// Queues incoming transactions while the maintenance task runs and replays them afterwards, see
+    /// `Control::EnterMaintenance`. Without a task the maintenance window is held for `duration`
+    pub fn send_enter_maintenance_request(
+        &self,
+        duration: Duration,
+        task: Option<MaintenanceTask>,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::EnterMaintenance { duration, task })
+    }
+
+    /// Phase one of a two-phase commit, validates the statements and locks their rows until the transaction is
+    /// committed or aborted. The prepare is durable once this returns, see `Control::PrepareTransaction`
+    pub fn send_prepare_request(
+        &self,
+        global_id: String,
+        statements: Vec<Statement>,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::PrepareTransaction {
+            global_id,
+            statements,
         })
     }
-}
 
-pub struct TaskGetVersionResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    /// Applies a prepared transaction, returns the results of its statements
+    pub fn send_commit_prepared_request(
+        &self,
+        global_id: String,
+    ) -> Result<Vec<StatementResult>, RequestManagerError> {
+        let command_result = self
+            .send_database_command(DatabaseCommand::Control(Control::CommitPrepared(global_id)))?;
 
-impl TaskGetVersionResponse {
-    pub fn send(
-        request_manager: &RequestManager,
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                DatabaseCommandTransactionResponse::Commit(results),
+            ) => Ok(results),
+            _ => {
+                panic!("Committing a prepared transaction should return the transaction's results")
+            }
+        }
+    }
+
+    pub fn send_abort_prepared_request(
+        &self,
+        global_id: String,
+    ) -> Result<String, RequestManagerError> {
+        self.send_control(Control::AbortPrepared(global_id))
+    }
+
+    /// Returns the prepared (in-doubt) transactions and the rows they lock, keyed by global transaction id
+    pub fn send_list_prepared_request(&self) -> Result<Vec<(String, String)>, RequestManagerError> {
+        self.send_control_info(Control::ListPrepared)
+    }
+
+    pub fn send_sleep_request(&self, duration: Duration) -> Result<String, RequestManagerError> {
+        return self.send_control(Control::Sleep(duration));
+    }
+
+    /// Yields every committed version change of the person from now on, see `EntityWatch`
+    pub fn watch_entity(
+        &self,
         id: EntityId,
-        version_id: VersionId,
         transaction_context: TransactionContext,
-    ) -> Self {
-        Self {
-            response: send_request(
-                request_manager,
-                vec![Statement::GetVersion(id, version_id)],
-                transaction_context,
-            ),
+    ) -> Result<EntityWatch, RequestManagerError> {
+        let (sender, receiver) = flume::unbounded();
+
+        self.send_control(Control::AddCommittedListener(sender))?;
+
+        Ok(EntityWatch::new(
+            id,
+            receiver,
+            self.clone(),
+            transaction_context,
+        ))
+    }
+
+    // -- Internal methods --
+    fn send_control_info(
+        &self,
+        control: Control,
+    ) -> Result<Vec<(String, String)>, RequestManagerError> {
+        let command_result = self.send_database_command(DatabaseCommand::Control(control))?;
+
+        // TODO: Clean this logic up, as we are now able to return success, info and error
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandControlResponse(
+                DatabaseCommandControlResponse::Info(i),
+            ) => Ok(i),
+            _ => panic!("Controls should always return a success, info or error status"),
         }
     }
 
-    pub fn get(&self) -> Result<Option<Person>, RequestManagerError> {
-        get_statement(&self.response).and_then(|mut action_result| {
-            Ok(action_result
-                .pop()
-                .expect("single a statement should generate single response")
-                .get_single())
-        })
+    fn send_control_operation(
+        &self,
+        control: Control,
+    ) -> Result<OperationStatus, RequestManagerError> {
+        let command_result = self.send_database_command(DatabaseCommand::Control(control))?;
+
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandControlResponse(
+                DatabaseCommandControlResponse::Operation(status),
+            ) => Ok(status),
+            _ => panic!("Operation controls should always return an operation or error status"),
+        }
     }
-}
 
-impl Wait for TaskGetVersionResponse {
-    fn wait(&self) {
-        self.get().expect("Should not timeout");
+    fn send_control(&self, control: Control) -> Result<String, RequestManagerError> {
+        let command_result = self.send_database_command(DatabaseCommand::Control(control))?;
+
+        match command_result {
+            DatabaseCommandResponse::DatabaseCommandControlResponse(
+                DatabaseCommandControlResponse::Success(s),
+            ) => Ok(s),
+            _ => panic!("Controls should always return a success, info or error status"),
+        }
     }
-}
 
-pub struct TaskListResponse {
-    response: oneshot::Receiver<DatabaseCommandResponse>,
-}
+    /// Generic method to send a transaction or control command to the database and returns the response. For type safety this method should not be used
+    /// use the more specific methods instead. These specific methods ensure that the correct response is returned
+    fn send_database_command(
+        &self,
+        database_request: DatabaseCommand,
+    ) -> Result<DatabaseCommandResponse, RequestManagerError> {
+        let (response_sender, response_receiver) = oneshot::channel::<DatabaseCommandResponse>();
 
-impl TaskListResponse {
-    pub fn send(
-        request_manager: &RequestManager,
-        query: Option<QueryPersonData>,
-        transaction_context: TransactionContext,
-    ) -> Self {
-        Self {
-            response: send_request(
-                request_manager,
-                vec![Statement::List(query)],
-                transaction_context,
-            ),
+        let request = DatabaseCommandRequest {
+            resolver: response_sender,
+            command: database_request,
+            transaction_context: TransactionContext::default(),
+            enqueued_at: Instant::now(),
+            permit: None,
+        };
+
+        // Sends the request to the database worker, database will response
+        //  on the response_receiver once it's finished processing it's request
+        self.send_to_worker(request)?;
+
+        // If the database is large it can take > 30 seconds to reset
+        let response = response_receiver.recv_timeout(Duration::from_secs(60));
+
+        map_response(response)
+    }
+
+    /// Sends an existing request to the database, e.g. to replay the requests queued during maintenance
+    pub(super) fn forward(&self, request: DatabaseCommandRequest) {
+        if let Err(e) = self.send_to_worker(request) {
+            log::error!("Failed to forward request: {}", e);
+        }
+    }
+
+    #[allow(dead_code)]
+    fn send_database_command_task(&self, database_request: DatabaseCommand) -> TaskCommandResponse {
+        let (response_sender, response_receiver) = oneshot::channel::<DatabaseCommandResponse>();
+
+        let request = DatabaseCommandRequest {
+            resolver: response_sender,
+            command: database_request,
+            transaction_context: TransactionContext::default(),
+            enqueued_at: Instant::now(),
+            permit: None,
+        };
+
+        // A request that cannot be sent is dropped, the task resolves with the error
+        let _ = self.send_to_worker(request);
+
+        TaskCommandResponse::send(response_receiver)
+    }
+}
+
+fn map_response(
+    response: Result<DatabaseCommandResponse, oneshot::RecvTimeoutError>,
+) -> Result<DatabaseCommandResponse, RequestManagerError> {
+    match response {
+        // Transaction commands
+        Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(transaction_response)) => {
+            match transaction_response {
+                DatabaseCommandTransactionResponse::Commit(statement_result) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                        DatabaseCommandTransactionResponse::Commit(statement_result),
+                    ))
+                }
+                DatabaseCommandTransactionResponse::Rollback(s) => {
+                    Err(RequestManagerError::TransactionRollback(s))
+                }
+                DatabaseCommandTransactionResponse::Status(s) => {
+                    Err(RequestManagerError::TransactionStatus(s))
+                }
+                DatabaseCommandTransactionResponse::QuotaExceeded(e) => {
+                    Err(RequestManagerError::QuotaExceeded(e))
+                }
+                DatabaseCommandTransactionResponse::LimitExceeded(e) => {
+                    Err(RequestManagerError::LimitExceeded(e))
+                }
+                DatabaseCommandTransactionResponse::ContextOverrideRejected(e) => {
+                    Err(RequestManagerError::ContextOverrideRejected(e))
+                }
+                DatabaseCommandTransactionResponse::Throttled(e) => {
+                    Err(RequestManagerError::Throttled(e))
+                }
+                // Only conditional reads are answered with not modified, see `send_list_if_changed`
+                DatabaseCommandTransactionResponse::NotModified(version) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                        DatabaseCommandTransactionResponse::NotModified(version),
+                    ))
+                }
+                DatabaseCommandTransactionResponse::DryRun(statement_result) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                        DatabaseCommandTransactionResponse::DryRun(statement_result),
+                    ))
+                }
+            }
+        }
+        // Control commands
+        Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(control_response)) => {
+            match control_response {
+                DatabaseCommandControlResponse::Success(s) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(
+                        DatabaseCommandControlResponse::Success(s),
+                    ))
+                }
+                DatabaseCommandControlResponse::Info(s) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(
+                        DatabaseCommandControlResponse::Info(s),
+                    ))
+                }
+                DatabaseCommandControlResponse::Activity(report) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(
+                        DatabaseCommandControlResponse::Activity(report),
+                    ))
+                }
+                DatabaseCommandControlResponse::Operation(status) => {
+                    Ok(DatabaseCommandResponse::DatabaseCommandControlResponse(
+                        DatabaseCommandControlResponse::Operation(status),
+                    ))
+                }
+                DatabaseCommandControlResponse::Error(s) => {
+                    Err(RequestManagerError::DatabaseErrorStatus(s))
+                }
+            }
+        }
+        // Issues with the channel
+        Err(oneshot::RecvTimeoutError::Timeout) => Err(RequestManagerError::DatabaseTimeout),
+        Err(oneshot::RecvTimeoutError::Disconnected) => {
+            Err(RequestManagerError::DatabaseRestarting)
+        }
+    }
+}
+
+/// Response to a transaction that has been sent, the timeout is decided by the context policy
+/// The result of a read that is only run if the table has changed, see `RequestManager::send_list_if_changed`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionalRead<T> {
+    /// The table is unchanged since the version, the caller's previous result is still current
+    NotModified(TableVersion),
+    /// The result and the version of the table it reflects
+    Modified(T, TableVersion),
+}
+
+pub struct PendingResponse {
+    receiver: oneshot::Receiver<DatabaseCommandResponse>,
+    timeout: Duration,
+}
+
+fn send_request(
+    request_manager: &RequestManager,
+    statement: Vec<Statement>,
+    transaction_context: TransactionContext,
+) -> PendingResponse {
+    let (response_sender, response_receiver) = oneshot::channel::<DatabaseCommandResponse>();
+
+    let (context_policy, tag_throttle, inline_reads) =
+        match &*request_manager.handle.0.read().unwrap() {
+            DatabaseChannels::Running(channels) => (
+                channels.context_policy.clone(),
+                channels.tag_throttle.clone(),
+                channels.inline_reads.clone(),
+            ),
+            DatabaseChannels::Restarting => (ContextPolicy::default(), None, None),
+        };
+
+    let timeout = transaction_context
+        .timeout
+        .unwrap_or(context_policy.default_timeout);
+
+    // Rejected requests are resolved right away, the requester sees `RequestManagerError::ContextOverrideRejected`
+    let transaction_context = match context_policy.apply(transaction_context) {
+        Ok(transaction_context) => transaction_context,
+        Err(rejected) => {
+            let _ =
+                response_sender.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                    DatabaseCommandTransactionResponse::ContextOverrideRejected(rejected),
+                ));
+
+            return PendingResponse {
+                receiver: response_receiver,
+                timeout,
+            };
+        }
+    };
+
+    // Reads of a limited tag are queued, the tag's limit protects the workers other reads would run on
+    let inline_read = inline_reads
+        .filter(|_| tag_throttle.is_none() || transaction_context.tag.is_none())
+        .and_then(|database| database.upgrade())
+        .and_then(|database| database.inline_read(&statement, &transaction_context));
+
+    if let Some(response) = inline_read {
+        let _ = response_sender.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+            response,
+        ));
+
+        return PendingResponse {
+            receiver: response_receiver,
+            timeout,
+        };
+    }
+
+    let command = DatabaseCommand::Transaction(statement);
+    let throttled_at = Instant::now();
+
+    // Requests of a limited tag wait on the sending thread, the time they waited counts towards their timeout
+    let permit = match &tag_throttle {
+        Some(tag_throttle) => {
+            tag_throttle.acquire(transaction_context.tag.as_deref(), timeout, || {
+                request_manager.has_idle_worker(&command)
+            })
+        }
+        None => Ok(None),
+    };
+
+    let timeout = timeout.saturating_sub(throttled_at.elapsed());
+
+    let permit = match permit {
+        Ok(permit) => permit,
+        Err(throttled) => {
+            let _ =
+                response_sender.send(DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+                    DatabaseCommandTransactionResponse::Throttled(throttled),
+                ));
+
+            return PendingResponse {
+                receiver: response_receiver,
+                timeout,
+            };
+        }
+    };
+
+    let request = DatabaseCommandRequest {
+        resolver: response_sender,
+        command,
+        transaction_context,
+        enqueued_at: Instant::now(),
+        permit,
+    };
+
+    // A request that cannot be sent is dropped, the task resolves with `DatabaseRestarting`
+    if let Err(e) = request_manager.send_to_worker(request) {
+        log::warn!("Request was not sent: {}", e);
+    }
+
+    PendingResponse {
+        receiver: response_receiver,
+        timeout,
+    }
+}
+
+fn get_statement(response: &PendingResponse) -> Result<Vec<StatementResult>, RequestManagerError> {
+    let response = response.receiver.recv_timeout(response.timeout);
+
+    let command_result = map_response(response)?;
+
+    match command_result {
+        // A dry run answers with the results the transaction would have committed with
+        DatabaseCommandResponse::DatabaseCommandTransactionResponse(
+            DatabaseCommandTransactionResponse::Commit(action_results)
+            | DatabaseCommandTransactionResponse::DryRun(action_results),
+        ) => Ok(action_results),
+        _ => panic!("Transaction commands should always return a commit or rollback"),
+    }
+}
+
+pub trait Wait {
+    fn wait(&self);
+}
+
+pub struct TaskCommandResponse {
+    response: oneshot::Receiver<DatabaseCommandResponse>,
+}
+
+impl TaskCommandResponse {
+    pub fn send(response: oneshot::Receiver<DatabaseCommandResponse>) -> Self {
+        Self { response }
+    }
+
+    pub fn get(&self) -> Result<DatabaseCommandResponse, RequestManagerError> {
+        let response = self.response.recv_timeout(Duration::from_secs(30));
+
+        map_response(response)
+    }
+}
+
+impl Wait for TaskCommandResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskStatementResponse {
+    response: PendingResponse,
+}
+
+impl TaskStatementResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        statement: Vec<Statement>,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(request_manager, statement, transaction_context),
+        }
+    }
+
+    pub fn get(&self) -> Result<Vec<StatementResult>, RequestManagerError> {
+        get_statement(&self.response)
+    }
+}
+
+impl Wait for TaskStatementResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskAddResponse {
+    response: PendingResponse,
+}
+
+impl TaskAddResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        person: Person,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::Add(person)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Person, RequestManagerError> {
+        get_statement(&self.response).and_then(|mut action_result| {
+            Ok(action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .single())
+        })
+    }
+}
+
+impl Wait for TaskAddResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskUpdateResponse {
+    response: PendingResponse,
+}
+
+impl TaskUpdateResponse {
+    fn send(
+        request_manager: &RequestManager,
+        id: EntityId,
+        person_update: UpdatePersonData,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::Update(id, person_update)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Person, RequestManagerError> {
+        get_statement(&self.response).and_then(|mut action_result| {
+            Ok(action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .single())
+        })
+    }
+}
+
+impl Wait for TaskUpdateResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskGetResponse {
+    response: PendingResponse,
+}
+
+impl TaskGetResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        id: EntityId,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::Get(id)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Option<Person>, RequestManagerError> {
+        get_statement(&self.response).and_then(|mut action_result| {
+            Ok(action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .get_single())
+        })
+    }
+}
+
+pub struct TaskGetVersionResponse {
+    response: PendingResponse,
+}
+
+impl TaskGetVersionResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        id: EntityId,
+        version_id: VersionId,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::GetVersion(id, version_id)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Option<Person>, RequestManagerError> {
+        get_statement(&self.response).and_then(|mut action_result| {
+            Ok(action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .get_single())
+        })
+    }
+}
+
+impl Wait for TaskGetVersionResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskListResponse {
+    response: PendingResponse,
+}
+
+impl TaskListResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        query: Option<QueryPersonData>,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::List(query)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Vec<Person>, RequestManagerError> {
+        get_statement(&self.response).and_then(|mut action_result| {
+            Ok(action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .list())
+        })
+    }
+}
+
+impl Wait for TaskListResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskListPageResponse {
+    response: PendingResponse,
+}
+
+impl TaskListPageResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        query: Option<QueryPersonData>,
+        page_request: PageRequest,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::ListPage(query, page_request)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Page, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .page()
+        })
+    }
+}
+
+impl Wait for TaskListPageResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskGetManyAtTransactionResponse {
+    response: PendingResponse,
+}
+
+impl TaskGetManyAtTransactionResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        ids: Vec<EntityId>,
+        at: TransactionId,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::GetManyAtTransaction(ids, at)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Vec<Person>, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .list()
+        })
+    }
+}
+
+impl Wait for TaskGetManyAtTransactionResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskScanResponse {
+    response: PendingResponse,
+}
+
+impl TaskScanResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        start: Bound<EntityId>,
+        end: Bound<EntityId>,
+        limit: Option<usize>,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::Scan { start, end, limit }],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Vec<Person>, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .list()
+        })
+    }
+}
+
+impl Wait for TaskScanResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskHistoryResponse {
+    response: PendingResponse,
+}
+
+impl TaskHistoryResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        id: EntityId,
+        request: HistoryRequest,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::History(id, request)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<Option<HistoryPage>, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .found()
+                .map(StatementResult::history_page)
+        })
+    }
+}
+
+impl Wait for TaskHistoryResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskQueryViewResponse {
+    response: PendingResponse,
+}
+
+impl TaskQueryViewResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::QueryView(name)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<ViewResult, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .view()
+        })
+    }
+}
+
+impl Wait for TaskQueryViewResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskExecutePreparedQueryResponse {
+    response: PendingResponse,
+}
+
+impl TaskExecutePreparedQueryResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        name: String,
+        values: BTreeMap<String, String>,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::ExecutePreparedQuery(name, values)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<ViewResult, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .view()
+        })
+    }
+}
+
+impl Wait for TaskExecutePreparedQueryResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskQuerySystemTableResponse {
+    response: PendingResponse,
+}
+
+impl TaskQuerySystemTableResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::QuerySystemTable(name)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<SystemTable, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .system_table()
+        })
+    }
+}
+
+impl Wait for TaskQuerySystemTableResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+pub struct TaskNextValResponse {
+    response: PendingResponse,
+}
+
+impl TaskNextValResponse {
+    pub fn send(
+        request_manager: &RequestManager,
+        name: String,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            response: send_request(
+                request_manager,
+                vec![Statement::NextVal(name)],
+                transaction_context,
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Result<u64, RequestManagerError> {
+        get_statement(&self.response).map(|mut action_result| {
+            action_result
+                .pop()
+                .expect("single a statement should generate single response")
+                .sequence_value()
+        })
+    }
+}
+
+impl Wait for TaskNextValResponse {
+    fn wait(&self) {
+        self.get().expect("Should not timeout");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, OnceLock},
+        time::{Duration, Instant},
+    };
+
+    use uuid::Uuid;
+
+    use crate::{
+        consts::consts::{EntityId, VersionId},
+        database::{
+            availability::{ThreadAvailability, WorkerAvailability},
+            commands::{
+                Control, DatabaseCommand, DatabaseCommandControlResponse, DatabaseCommandRequest,
+                DatabaseCommandResponse, MaintenanceTask, ShutdownRequest, SnapshotTimestamp,
+                TransactionContext,
+            },
+            context_policy::{ContextField, ContextOverrideRejected, ContextPolicy},
+            database::Database,
+            hooks::LifecycleEvent,
+            limits::{LimitExceeded, TransactionLimits},
+            operations::{OperationId, OperationState},
+            options::DatabaseOptions,
+            quota::{Quota, QuotaExceeded},
+            request_manager::{ConditionalRead, RequestManager, RequestManagerError},
+            table::{
+                constraint::ConstraintTiming,
+                history::HistoryRequest,
+                policy::{PolicyPredicate, RowPolicy},
+                query::{QueryAddressData, QueryMatch, QueryPersonData},
+                row::{PersonVersionState, UpdatePersonData, UpdateStatement},
+                squash::HistorySquash,
+                watermark::TableVersion,
+            },
+            tags::TagLimit,
+        },
+        model::{
+            person::Person,
+            statement::{Statement, StatementResult},
+        },
+    };
+
+    #[test]
+    fn sync() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let action_result = request_manager
+            .send_single_statement(
+                Statement::Add(Person {
+                    id: EntityId::new(),
+                    full_name: "Test".to_string(),
+                    email: Some(Uuid::new_v4().to_string()),
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                }),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        assert_eq!(action_result.single().full_name, "Test");
+    }
+
+    #[test]
+    fn reads_of_missing_rows_do_not_roll_back() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let missing = EntityId::new();
+        let person = Person::new("Test".to_string(), None);
+
+        let results = request_manager
+            .send_transaction(
+                vec![
+                    Statement::Get(missing.clone()),
+                    Statement::Lineage(missing.clone()),
+                    Statement::History(missing.clone(), HistoryRequest::new(10)),
+                    Statement::Add(person.clone()),
+                ],
+                TransactionContext::default(),
+            )
+            .expect("Reads of missing rows should not roll back the transaction");
+
+        assert_eq!(
+            results,
+            vec![
+                StatementResult::GetSingle(None),
+                StatementResult::NotFound(missing.clone()),
+                StatementResult::NotFound(missing.clone()),
+                StatementResult::Single(person.clone()),
+            ]
+        );
+
+        assert_eq!(
+            request_manager
+                .send_lineage(missing.clone(), TransactionContext::default())
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            request_manager
+                .send_history(
+                    missing,
+                    HistoryRequest::new(10),
+                    TransactionContext::default()
+                )
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            request_manager
+                .send_get(person.id.clone(), TransactionContext::default())
+                .unwrap(),
+            Some(person)
+        );
+    }
+
+    #[test]
+    fn attachments_are_stored_once_and_vacuumed_when_unreferenced() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let person = request_manager
+            .send_add(
+                Person::new("Test".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        let attach = |name: &str, bytes: &[u8]| {
+            request_manager.send_attach(
+                person.id.clone(),
+                name.to_string(),
+                Some("text/plain".to_string()),
+                bytes.to_vec(),
+                TransactionContext::default(),
+            )
+        };
+
+        attach("avatar", b"first").unwrap();
+        let attached = attach("avatar", b"second").unwrap();
+
+        // Attaching under an existing name replaces the attachment
+        assert_eq!(attached.attachments.len(), 1);
+        assert_eq!(attached.attachments[0].size, 6);
+
+        let content = request_manager
+            .send_get_attachment(
+                person.id.clone(),
+                "avatar".to_string(),
+                TransactionContext::default(),
+            )
+            .unwrap()
+            .expect("The attachment should exist");
+
+        assert_eq!(content.bytes, b"second".to_vec());
+        assert_eq!(
+            request_manager
+                .send_get_attachment(
+                    person.id.clone(),
+                    "missing".to_string(),
+                    TransactionContext::default()
+                )
+                .unwrap(),
+            None
+        );
+
+        // The update rolls back, the written payload is not referenced by any version
+        assert!(request_manager
+            .send_attach(
+                EntityId::new(),
+                "avatar".to_string(),
+                None,
+                b"orphan".to_vec(),
+                TransactionContext::default(),
+            )
+            .is_err());
+
+        let detached = request_manager
+            .send_detach(
+                person.id.clone(),
+                "avatar".to_string(),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        assert!(detached.attachments.is_empty());
+
+        let report = |min_age: Duration| -> Vec<(String, String)> {
+            request_manager
+                .send_vacuum_attachments_request(min_age)
+                .unwrap()
+        };
+
+        // The orphan is too young to be vacuumed
+        assert!(
+            report(Duration::from_secs(3600)).contains(&("Deleted".to_string(), "0".to_string()))
+        );
+
+        // Earlier versions still reference both payloads after the detach
+        let vacuumed = report(Duration::ZERO);
+
+        assert!(vacuumed.contains(&("Referenced".to_string(), "2".to_string())));
+        assert!(vacuumed.contains(&("Deleted".to_string(), "1".to_string())));
+        assert!(vacuumed.contains(&("DeletedBytes".to_string(), "6".to_string())));
+    }
+
+    #[test]
+    fn exists_and_count_do_not_return_rows() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let first = request_manager
+            .send_add(
+                Person::new("First".to_string(), Some("first@x.com".to_string())),
+                TransactionContext::default(),
+            )
+            .unwrap();
+        let second = request_manager
+            .send_add(
+                Person::new("Second".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        let by_email = || {
+            Some(QueryPersonData {
+                email: QueryMatch::Value("first@x.com".to_string()),
+                ..QueryPersonData::default()
+            })
+        };
+
+        assert!(request_manager
+            .send_exists(first.id.clone(), TransactionContext::default())
+            .unwrap());
+        assert!(!request_manager
+            .send_exists(EntityId::new(), TransactionContext::default())
+            .unwrap());
+        assert_eq!(
+            request_manager
+                .send_count(None, TransactionContext::default())
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            request_manager
+                .send_count(by_email(), TransactionContext::default())
+                .unwrap(),
+            1
+        );
+
+        request_manager
+            .send_transaction(
+                vec![Statement::Remove(second.id.clone())],
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        assert!(!request_manager
+            .send_exists(second.id, TransactionContext::default())
+            .unwrap());
+        assert_eq!(
+            request_manager
+                .send_count(None, TransactionContext::default())
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn long_controls_run_as_operations() {
+        let request_manager = Database::new(DatabaseOptions::new_test()).run();
+
+        let started = request_manager
+            .send_start_operation_request(Control::SnapshotDatabase)
+            .unwrap();
+
+        assert_eq!(started.kind, "SnapshotDatabase");
+
+        let finished = request_manager
+            .wait_for_operation(started.id, Duration::from_secs(30))
+            .unwrap();
+
+        assert!(matches!(
+            &finished.state,
+            OperationState::Completed(response)
+                if matches!(**response, DatabaseCommandControlResponse::Success(_))
+        ));
+        assert_eq!(
+            request_manager
+                .send_operation_status_request(started.id)
+                .unwrap(),
+            finished
+        );
+
+        // Short controls are answered directly
+        assert!(matches!(
+            request_manager.send_start_operation_request(Control::ListJobs),
+            Err(RequestManagerError::DatabaseErrorStatus(_))
+        ));
+        assert!(matches!(
+            request_manager.send_operation_status_request(OperationId(0)),
+            Err(RequestManagerError::DatabaseErrorStatus(_))
+        ));
+    }
+
+    #[test]
+    fn meta_tracks_versions_without_reading_history() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let person = request_manager
+            .send_add(
+                Person::new("Meta".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        let meta = || {
+            request_manager
+                .send_meta(person.id.clone(), TransactionContext::default())
+                .unwrap()
+                .expect("the person exists")
+        };
+
+        let added = meta();
+
+        for full_name in ["Meta 2", "Meta 3"] {
+            request_manager
+                .send_update(
+                    person.id.clone(),
+                    UpdatePersonData {
+                        full_name: UpdateStatement::Set(full_name.to_string()),
+                        ..UpdatePersonData::default()
+                    },
+                    TransactionContext::default(),
+                )
+                .unwrap();
+        }
+
+        let updated = meta();
+
+        assert_eq!(updated.version_count, 3);
+        assert_eq!(updated.first_transaction_id, added.first_transaction_id);
+        assert!(updated.last_transaction_id > added.last_transaction_id);
+        assert!(updated.last_modified_ms >= added.last_modified_ms);
+        assert!(updated.last_modified_ms.is_some());
+        assert!(!updated.deleted);
+
+        // The time of older versions is not known
+        let at_add = request_manager
+            .send_meta(
+                person.id.clone(),
+                TransactionContext::new(SnapshotTimestamp::AtTransactionId(
+                    added.last_transaction_id.clone(),
+                )),
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(at_add.version_count, 1);
+        assert_eq!(at_add.last_modified_ms, None);
+
+        request_manager
+            .send_transaction(
+                vec![Statement::Remove(person.id.clone())],
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        let removed = meta();
+
+        assert_eq!(removed.version_count, 4);
+        assert!(removed.deleted);
+
+        assert_eq!(
+            request_manager
+                .send_meta(EntityId::new(), TransactionContext::default())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn built_queries_are_run() {
+        use crate::database::table::query_builder::Query;
+
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        for (full_name, email) in [
+            ("Dale", Some("dale@x.com")),
+            ("Dana", Some("dana@x.com")),
+            ("Daniel", None),
+            ("Adam", Some("adam@x.com")),
+        ] {
+            request_manager
+                .send_add(
+                    Person::new(full_name.to_string(), email.map(str::to_string)),
+                    TransactionContext::default(),
+                )
+                .unwrap();
+        }
+
+        let query = Query::person()
+            .full_name()
+            .starts_with("Da")
+            .email()
+            .not_null();
+
+        let mut people: Vec<String> = request_manager
+            .send_query(query.clone(), TransactionContext::default())
+            .unwrap()
+            .into_iter()
+            .map(|person| person.full_name)
+            .collect();
+        people.sort();
+
+        assert_eq!(people, vec!["Dale", "Dana"]);
+        assert_eq!(
+            request_manager
+                .send_query(query.limit(1), TransactionContext::default())
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn watched_rows_yield_their_committed_versions() {
+        use crate::database::watch::EntityWatchError;
+
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let dale = Person::new("Dale".to_string(), None);
+        let other = Person::new("Other".to_string(), None);
+
+        let mut watch = request_manager
+            .watch_entity(dale.id.clone(), TransactionContext::default())
+            .unwrap();
+
+        request_manager
+            .send_add(dale.clone(), TransactionContext::default())
+            .unwrap();
+        request_manager
+            .send_add(other.clone(), TransactionContext::default())
+            .unwrap();
+        request_manager
+            .send_update(
+                dale.id.clone(),
+                UpdatePersonData {
+                    full_name: UpdateStatement::Set("Dale Salter".to_string()),
+                    ..UpdatePersonData::default()
+                },
+                TransactionContext::default(),
+            )
+            .unwrap();
+        request_manager
+            .send_single_statement(
+                Statement::Remove(dale.id.clone()),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        let mut states = vec![];
+
+        for _ in 0..3 {
+            let version = watch.next_timeout(Duration::from_secs(5)).unwrap();
+
+            assert_eq!(version.id, dale.id);
+            states.push(version.state);
+        }
+
+        assert!(
+            matches!(&states[0], PersonVersionState::State(person) if person.full_name == "Dale")
+        );
+        assert!(
+            matches!(&states[1], PersonVersionState::State(person) if person.full_name == "Dale Salter")
+        );
+        assert!(matches!(states[2], PersonVersionState::Delete));
+
+        // Changes to other rows are not yielded
+        assert!(matches!(
+            watch.next_timeout(Duration::from_millis(100)),
+            Err(EntityWatchError::Timeout)
+        ));
+
+        request_manager.close().unwrap();
+
+        assert!(watch.next().is_none());
+    }
+
+    #[test]
+    fn index_advisor_recommends_and_creates_indexes() {
+        use crate::{database::table::index::IndexedField, model::person::Address};
+
+        let request_manager = Database::new(DatabaseOptions::new_test()).run();
+
+        // Given a table where each person lives in a different city
+        for i in 0..20 {
+            let person = Person {
+                address: Some(Address {
+                    city: Some(format!("City {}", i)),
+                    ..Address::default()
+                }),
+                ..Person::new(format!("Person {}", i), None)
+            };
+
+            request_manager
+                .send_add(person, TransactionContext::default())
+                .unwrap();
+        }
+
+        let by_city = QueryPersonData {
+            address: QueryAddressData {
+                city: QueryMatch::Value("City 3".to_string()),
+                ..Default::default()
+            },
+            ..QueryPersonData::default()
+        };
+
+        let list_by_city = || match request_manager
+            .send_single_statement(
+                Statement::List(Some(by_city.clone())),
+                TransactionContext::default(),
+            )
+            .unwrap()
+        {
+            StatementResult::List(people) => people,
+            result => panic!("Unexpected result {:?}", result),
+        };
+
+        // When the city is looked up often, the advisor recommends an index
+        for _ in 0..10 {
+            assert_eq!(list_by_city().len(), 1);
+        }
+
+        let advice = request_manager.send_index_advisor_request().unwrap();
+
+        assert_eq!(advice.len(), 1);
+        assert_eq!(advice[0].0, "address.city");
+        assert!(advice[0]
+            .1
+            .starts_with("Create: 10 value lookups (10 full scans)"));
+
+        // Then once it is created the lookups read the index
+        request_manager
+            .send_create_index_request(IndexedField::City)
+            .unwrap();
+
+        let explain = request_manager
+            .send_explain_query_request(Some(by_city.clone()))
+            .unwrap();
+
+        assert!(explain.contains(&("Scan".to_string(), "Index(City)".to_string())));
+        assert_eq!(list_by_city()[0].full_name, "Person 3");
+        assert!(request_manager.send_index_advisor_request().unwrap()[0]
+            .1
+            .starts_with("Indexed"));
+
+        // And indexes cannot be created twice
+        assert!(matches!(
+            request_manager.send_create_index_request(IndexedField::City),
+            Err(RequestManagerError::DatabaseErrorStatus(_))
+        ));
+        assert!(matches!(
+            request_manager.send_create_index_request(IndexedField::Email),
+            Err(RequestManagerError::DatabaseErrorStatus(_))
+        ));
+    }
+
+    #[test]
+    fn inline_reads_do_not_wait_for_busy_workers() {
+        let request_manager = Database::new(
+            DatabaseOptions::new_test()
+                .set_threads(1)
+                .set_inline_reads(true),
+        )
+        .run();
+
+        let person = request_manager
+            .send_add(
+                Person::new("Test".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        let busy_request_manager = request_manager.clone();
+        let busy = std::thread::spawn(move || {
+            busy_request_manager
+                .send_sleep_request(Duration::from_secs(1))
+                .unwrap();
+        });
+
+        // The only worker is sleeping, the get is run on this thread instead
+        std::thread::sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+
+        assert_eq!(
+            request_manager
+                .send_get(person.id.clone(), TransactionContext::default())
+                .unwrap(),
+            Some(person.clone())
+        );
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        // Reads of a clone need a worker
+        assert!(request_manager
+            .send_get(
+                person.id,
+                TransactionContext::default().set_clone(Some("missing".to_string()))
+            )
+            .is_err());
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        busy.join().unwrap();
+    }
+
+    #[test]
+    fn conditional_list_is_not_run_while_unchanged() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let list = |since: Option<TableVersion>| {
+            request_manager
+                .send_list_if_changed(None, since, TransactionContext::default())
+                .expect("Should not timeout")
+        };
+
+        request_manager
+            .send_add(
+                Person::new("Test".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        let ConditionalRead::Modified(people, version) = list(None) else {
+            panic!("An unconditional read should be run");
+        };
+
+        assert_eq!(people.len(), 1);
+        assert_eq!(
+            list(Some(version.clone())),
+            ConditionalRead::NotModified(version.clone())
+        );
+
+        // Reads and sequences do not modify the table
+        request_manager
+            .send_single_statement(
+                Statement::NextVal("ids".to_string()),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+        assert_eq!(
+            list(Some(version.clone())),
+            ConditionalRead::NotModified(version.clone())
+        );
+
+        request_manager
+            .send_add(
+                Person::new("Other".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        let ConditionalRead::Modified(people, changed) = list(Some(version.clone())) else {
+            panic!("The table has been modified");
+        };
+
+        assert_eq!(people.len(), 2);
+        assert_ne!(changed, version);
+
+        // Reads at an earlier snapshot are always run
+        assert!(matches!(
+            request_manager
+                .send_list_if_changed(
+                    None,
+                    Some(changed),
+                    TransactionContext::new(SnapshotTimestamp::AtTransactionId(
+                        version.transaction_id
+                    )),
+                )
+                .expect("Should not timeout"),
+            ConditionalRead::Modified(_, _)
+        ));
+    }
+
+    #[test]
+    fn task_command() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let person = Person {
+            id: EntityId::new(),
+            full_name: "Test".to_string(),
+            email: Some(Uuid::new_v4().to_string()),
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
+        };
+
+        let task = request_manager.send_database_command_task(DatabaseCommand::Transaction(vec![
+            Statement::Add(person.clone()),
+        ]));
+
+        let action_result = task.get().expect("Should not timeout");
+
+        assert_eq!(
+            action_result,
+            DatabaseCommandResponse::transaction_commit(vec![StatementResult::Single(person)])
+        );
+    }
+
+    #[test]
+    fn task_statement() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let task = request_manager.send_transaction_task(
+            vec![Statement::Add(Person {
+                id: EntityId::new(),
+                full_name: "Test".to_string(),
+                email: Some(Uuid::new_v4().to_string()),
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
+            })],
+            TransactionContext::default(),
+        );
+
+        let action_result = task.get().expect("Should not timeout");
+
+        assert_eq!(action_result.len(), 1);
+    }
+
+    #[test]
+    fn task_add() {
+        let options = DatabaseOptions::new_test().set_threads(1);
+
+        let request_manager = Database::new(options).run();
+
+        let person = Person {
+            id: EntityId::new(),
+            full_name: "Test".to_string(),
+            email: Some(Uuid::new_v4().to_string()),
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
+        };
+
+        let added_person = request_manager
+            .send_add_task(person.clone(), TransactionContext::default())
+            .get()
+            .expect("should not timeout");
+
+        assert_eq!(added_person, person);
+    }
+
+    #[test]
+    fn list_active_requests() {
+        let options = DatabaseOptions::new_test().set_threads(2);
+
+        let request_manager = Database::new(options).run();
+
+        // Client counters are recorded once a request finishes
+        request_manager
+            .send_add(
+                Person::new_test(),
+                TransactionContext::default().set_client_id(Some("client".to_string())),
+            )
+            .expect("Should not timeout");
+
+        // Keep one of the workers busy
+        let sleeping_request_manager = request_manager.clone();
+        let sleeping = std::thread::spawn(move || {
+            sleeping_request_manager
+                .send_sleep_request(std::time::Duration::from_secs(1))
+                .expect("Should not timeout")
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let report = request_manager
+            .send_list_active_requests()
+            .expect("Should not timeout");
+
+        let kinds: Vec<&str> = report
+            .active_requests
+            .iter()
+            .map(|r| r.kind.as_str())
+            .collect();
+
+        assert!(kinds.contains(&"Sleep"));
+        assert!(kinds.contains(&"ListActiveRequests"));
+
+        assert_eq!(report.clients.len(), 1);
+        assert_eq!(report.clients[0].client_id, "client");
+        assert_eq!(report.clients[0].requests, 1);
+
+        sleeping.join().unwrap();
+    }
+
+    #[test]
+    fn transactions_are_routed_to_their_pool() {
+        let (write_sender, write_receiver) = flume::unbounded();
+        let (read_sender, read_receiver) = flume::unbounded();
+
+        let request_manager = RequestManager::new_with_pools(vec![write_sender], vec![read_sender]);
+
+        let _ = request_manager.send_add_task(Person::new_test(), TransactionContext::default());
+        let _ = request_manager.send_get_task(EntityId::new(), TransactionContext::default());
+        let _ = request_manager.send_transaction_task(
+            vec![
+                Statement::Get(EntityId::new()),
+                Statement::Remove(EntityId::new()),
+            ],
+            TransactionContext::default(),
+        );
+
+        let kinds = |receiver: &flume::Receiver<DatabaseCommandRequest>| {
+            receiver
+                .try_iter()
+                .map(|request| request.command.kind())
+                .collect::<Vec<String>>()
+        };
+
+        // A transaction with any mutation runs on the write pool
+        assert_eq!(kinds(&write_receiver), vec!["Add", "Get, Remove"]);
+        assert_eq!(kinds(&read_receiver), vec!["Get"]);
+
+        // Both pools serve requests once the database runs
+        let request_manager = Database::new(
+            DatabaseOptions::new_test()
+                .set_write_threads(1)
+                .set_read_threads(2),
+        )
+        .run();
+
+        let person = request_manager
+            .send_add(Person::new_test(), TransactionContext::default())
+            .expect("Should not timeout");
+
+        assert_eq!(
+            request_manager
+                .send_get(person.id.clone(), TransactionContext::default())
+                .expect("Should not timeout"),
+            Some(person)
+        );
+
+        let stats = request_manager
+            .send_info_request()
+            .expect("Should not timeout");
+
+        assert!(stats.contains(&("DatabaseThreads".to_string(), "3".to_string())));
+        assert!(stats.contains(&("WriteThreads".to_string(), "1".to_string())));
+    }
+
+    #[test]
+    fn transactions_over_the_limits_are_rejected() {
+        let options = DatabaseOptions::new_test().set_transaction_limits(
+            TransactionLimits::default()
+                .set_max_statements(2)
+                .set_max_statement_bytes(256),
+        );
+
+        let request_manager = Database::new(options).run();
+
+        let too_many = request_manager.send_transaction(
+            (0..3).map(|_| Statement::Add(Person::new_test())).collect(),
+            TransactionContext::default(),
+        );
+
+        assert!(matches!(
+            too_many,
+            Err(RequestManagerError::LimitExceeded(
+                LimitExceeded::Statements { count: 3, limit: 2 }
+            ))
+        ));
+
+        let too_large = request_manager.send_add(
+            Person::new("x".repeat(1024), None),
+            TransactionContext::default(),
+        );
+
+        assert!(matches!(
+            too_large,
+            Err(RequestManagerError::LimitExceeded(
+                LimitExceeded::StatementBytes { index: 0, .. }
+            ))
+        ));
+
+        // Nothing was applied, transactions within the limits still run
+        assert!(request_manager
+            .send_list(None, TransactionContext::default())
+            .expect("Should not timeout")
+            .is_empty());
+
+        request_manager
+            .send_add(Person::new_test(), TransactionContext::default())
+            .expect("Should not timeout");
+    }
+
+    #[test]
+    fn chunked_transactions_commit_independently() {
+        let options = DatabaseOptions::new_test()
+            .set_transaction_limits(TransactionLimits::default().set_max_statement_bytes(256));
+
+        let request_manager = Database::new(options).run();
+
+        let mut statements: Vec<Statement> = (0..5)
+            .map(|i| Statement::Add(Person::new(format!("Person {}", i), None)))
+            .collect();
+
+        // Only the chunk with the oversized statement is rejected
+        statements[3] = Statement::Add(Person::new("x".repeat(1024), None));
+
+        let chunks =
+            request_manager.send_chunked_transaction(statements, 2, TransactionContext::default());
+
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| (chunk.start, chunk.len))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (2, 2), (4, 1)]
+        );
+
+        assert!(chunks[0].result.is_ok());
+        assert!(matches!(
+            chunks[1].result,
+            Err(RequestManagerError::LimitExceeded(
+                LimitExceeded::StatementBytes { index: 1, .. }
+            ))
+        ));
+        assert!(chunks[2].result.is_ok());
+
+        assert_eq!(
+            request_manager
+                .send_list(None, TransactionContext::default())
+                .expect("Should not timeout")
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn dry_runs_report_without_committing() {
+        let request_manager = Database::new(DatabaseOptions::new_test()).run();
+
+        let existing = request_manager
+            .send_add(
+                Person::new("Existing".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        let new_person = Person::new("New".to_string(), None);
+
+        // The results are the ones the transaction would commit with
+        let results = request_manager
+            .send_dry_run(
+                vec![
+                    Statement::Add(new_person.clone()),
+                    Statement::Remove(existing.id.clone()),
+                ],
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].clone().single(), new_person);
+
+        // A dry run that would roll back reports why
+        assert!(matches!(
+            request_manager.send_dry_run(
+                vec![Statement::Remove(EntityId("missing".to_string()))],
+                TransactionContext::default(),
+            ),
+            Err(RequestManagerError::TransactionRollback(_))
+        ));
+
+        // Nothing was committed
+        assert_eq!(
+            request_manager
+                .send_list(None, TransactionContext::default())
+                .expect("Should not timeout"),
+            vec![existing]
+        );
+    }
+
+    #[test]
+    fn unique_email_can_be_deferred_to_commit() {
+        let request_manager = Database::new(
+            DatabaseOptions::new_test().set_unique_email(ConstraintTiming::Immediate),
+        )
+        .run();
+
+        let add = |name: &str, email: &str| {
+            request_manager.send_add(
+                Person::new(name.to_string(), Some(email.to_string())),
+                TransactionContext::default(),
+            )
+        };
+
+        let set_email = |id: &EntityId, email: &str| {
+            Statement::Update(
+                id.clone(),
+                UpdatePersonData {
+                    email: UpdateStatement::Set(email.to_string()),
+                    ..UpdatePersonData::default()
+                },
+            )
+        };
+
+        let luke = add("Luke", "luke@jedi.org").unwrap();
+        let leia = add("Leia", "leia@rebellion.org").unwrap();
+
+        assert!(matches!(
+            add("Impostor", "luke@jedi.org"),
+            Err(RequestManagerError::TransactionRollback(_))
+        ));
+
+        let swap = vec![
+            set_email(&luke.id, "leia@rebellion.org"),
+            set_email(&leia.id, "luke@jedi.org"),
+        ];
+
+        // Mid-swap both share an email, an immediate check rolls the swap back
+        assert!(matches!(
+            request_manager.send_transaction(swap.clone(), TransactionContext::default()),
+            Err(RequestManagerError::TransactionRollback(_))
+        ));
+
+        // A dry run reports the same as the commit would
+        assert!(request_manager
+            .send_dry_run(
+                swap.clone(),
+                TransactionContext::default().set_defer_constraints(true)
+            )
+            .is_ok());
+
+        request_manager
+            .send_transaction(
+                swap,
+                TransactionContext::default().set_defer_constraints(true),
+            )
+            .expect("The final state is valid");
+
+        assert_eq!(
+            request_manager
+                .send_get(luke.id.clone(), TransactionContext::default())
+                .unwrap()
+                .unwrap()
+                .email,
+            Some("leia@rebellion.org".to_string())
+        );
+
+        // A deferred check still rejects a violation that is committed
+        assert!(matches!(
+            request_manager.send_transaction(
+                vec![set_email(&leia.id, "leia@rebellion.org")],
+                TransactionContext::default().set_defer_constraints(true),
+            ),
+            Err(RequestManagerError::TransactionRollback(_))
+        ));
+
+        // The email of a deleted person can be reused
+        request_manager
+            .send_transaction(
+                vec![Statement::Remove(luke.id.clone())],
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        assert!(add("Rey", "leia@rebellion.org").is_ok());
+    }
+
+    #[test]
+    fn lifecycle_hooks_run_once_the_database_resumes() {
+        let (event_tx, event_rx) = flume::unbounded::<LifecycleEvent>();
+        let shutdown_tx = event_tx.clone();
+
+        // The snapshot hook reads from the database, this would block if it ran while the database is paused
+        let hook_request_manager = Arc::new(OnceLock::<RequestManager>::new());
+        let snapshot_request_manager = hook_request_manager.clone();
+
+        let options = DatabaseOptions::new_test()
+            .set_hook_timeout(Duration::from_secs(5))
+            .set_on_snapshot(move |event| {
+                snapshot_request_manager
+                    .get()
+                    .expect("Request manager is set before the snapshot")
+                    .send_list(None, TransactionContext::default())
+                    .expect("Should not timeout");
+
+                let _ = event_tx.send(event.clone());
+            })
+            .set_on_shutdown(move |event| {
+                let _ = shutdown_tx.send(event.clone());
+            });
+
+        let request_manager = Database::new(options).run();
+        let _ = hook_request_manager.set(request_manager.clone());
+
+        request_manager
+            .send_snapshot_request()
+            .expect("Should not timeout");
+
+        assert!(matches!(
+            event_rx.recv_timeout(Duration::from_secs(5)),
+            Ok(LifecycleEvent::Snapshot { .. })
+        ));
+
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .expect("Should not timeout");
+
+        assert_eq!(event_rx.try_recv(), Ok(LifecycleEvent::Shutdown));
+
+        // A hook that hangs is left behind once it times out
+        let request_manager = Database::new(
+            DatabaseOptions::new_test()
+                .set_hook_timeout(Duration::from_millis(50))
+                .set_on_reset(|_| std::thread::sleep(Duration::from_secs(60))),
+        )
+        .run();
+
+        let started = Instant::now();
+
+        request_manager
+            .send_reset_request()
+            .expect("Should not timeout");
+
+        request_manager
+            .send_add(Person::new_test(), TransactionContext::default())
+            .expect("Should not timeout");
+
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn requests_skip_unavailable_threads() {
+        let (first_sender, first_receiver) = flume::unbounded();
+        let (second_sender, second_receiver) = flume::unbounded();
+
+        let availability = WorkerAvailability::new(2);
+        let request_manager = RequestManager::new(vec![first_sender, second_sender])
+            .set_availability(availability.clone());
+
+        // e.g. the first thread is parked on a snapshot
+        availability.set(0, ThreadAvailability::ExecutingControl);
+
+        for _ in 0..4 {
+            let _ = request_manager.send_get_task(EntityId::new(), TransactionContext::default());
+        }
+
+        assert_eq!(first_receiver.len(), 0);
+        assert_eq!(second_receiver.len(), 4);
+
+        // Requests are still queued when every thread is unavailable, they run once the pause ends
+        availability.set(1, ThreadAvailability::Paused);
+
+        for _ in 0..4 {
+            let _ = request_manager.send_get_task(EntityId::new(), TransactionContext::default());
+        }
+
+        assert_eq!(first_receiver.len() + second_receiver.len(), 8);
+        assert_eq!(first_receiver.len(), 2);
+    }
+
+    #[test]
+    fn system_tables_expose_internal_state() {
+        let request_manager = Database::new(DatabaseOptions::new_test()).run();
+
+        request_manager
+            .send_add(Person::new_test(), TransactionContext::default())
+            .expect("Should not timeout");
+
+        let query = |name: &str| {
+            request_manager.send_query_system_table(name.to_string(), TransactionContext::default())
+        };
+
+        let stats = query("system.stats").expect("Should not timeout");
+        assert_eq!(stats.columns, vec!["name", "value"]);
+        assert!(stats
+            .rows
+            .contains(&vec!["RowCount".to_string(), "1".to_string()]));
+
+        // The query itself is an active request
+        let requests = query("system.requests").expect("Should not timeout");
+        assert!(requests
+            .column("kind")
+            .unwrap()
+            .contains(&"QuerySystemTable"));
+
+        let wal = query("system.wal").expect("Should not timeout");
+        assert!(wal
+            .rows
+            .contains(&vec!["WriteMode".to_string(), "Off".to_string()]));
+
+        request_manager
+            .send_snapshot_request()
+            .expect("Should not timeout");
+
+        let snapshots = query("system.snapshots").expect("Should not timeout");
+        assert_eq!(snapshots.rows.len(), 1);
+
+        assert!(query("system.unknown").is_err());
+
+        // Like views, system tables cannot be read by a role with row policies
+        request_manager
+            .send_create_policy_request(RowPolicy {
+                name: "x_domain".to_string(),
+                role: "tenant".to_string(),
+                predicate: PolicyPredicate::EmailDomain("x.com".to_string()),
+            })
+            .expect("Should not timeout");
+
+        assert!(request_manager
+            .send_query_system_table(
+                "system.stats".to_string(),
+                TransactionContext::default().set_role(Some("tenant".to_string()))
+            )
+            .is_err());
+
+        // System tables cannot be read within a mutation
+        assert!(request_manager
+            .send_transaction(
+                vec![
+                    Statement::Add(Person::new("System".to_string(), None)),
+                    Statement::QuerySystemTable("system.stats".to_string()),
+                ],
+                TransactionContext::default()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn context_policy_decides_the_role() {
+        let options = DatabaseOptions::new_test().set_context_policy(
+            ContextPolicy::default()
+                .set_default_role("tenant".to_string())
+                .set_overridable([ContextField::Timeout]),
+        );
+
+        let request_manager = Database::new(options).run();
+
+        request_manager
+            .send_create_policy_request(RowPolicy {
+                name: "x_domain".to_string(),
+                role: "tenant".to_string(),
+                predicate: PolicyPredicate::EmailDomain("x.com".to_string()),
+            })
+            .expect("Should not timeout");
+
+        for email in ["a@x.com", "b@y.com"] {
+            request_manager
+                .send_add(
+                    Person::new(email.to_string(), Some(email.to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("Should not timeout");
+        }
+
+        // Requests without a role run as the default role
+        let people = request_manager
+            .send_list(
+                None,
+                TransactionContext::default().set_timeout(Duration::from_secs(5)),
+            )
+            .expect("Should not timeout");
+
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].email, Some("a@x.com".to_string()));
+
+        assert!(matches!(
+            request_manager.send_list(
+                None,
+                TransactionContext::default().set_role(Some("admin".to_string()))
+            ),
+            Err(RequestManagerError::ContextOverrideRejected(
+                ContextOverrideRejected(ContextField::Role)
+            ))
+        ));
+    }
+
+    #[test]
+    fn tenants_are_limited_by_their_quota() {
+        let options = DatabaseOptions::new_test()
+            .set_quota("tenant".to_string(), Quota::default().set_max_rows(1));
+
+        let request_manager = Database::new(options).run();
+        let as_tenant = || TransactionContext::default().set_role(Some("tenant".to_string()));
+
+        request_manager
+            .send_add(Person::new("Tenant".to_string(), None), as_tenant())
+            .expect("Should not timeout");
+
+        let error = request_manager
+            .send_add(Person::new("Tenant".to_string(), None), as_tenant())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            error,
+            RequestManagerError::QuotaExceeded(QuotaExceeded::Rows { limit: 1, .. })
+        ));
+
+        // Other tenants are not affected
+        request_manager
+            .send_add(
+                Person::new("Tenant".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        let stats = request_manager.send_info_request().unwrap();
+        assert!(stats.contains(&("QuotaRejections[tenant]".to_string(), "1".to_string())));
+    }
+
+    #[test]
+    fn tagged_requests_are_limited() {
+        let options = DatabaseOptions::new_test().set_threads(2).set_tag_limit(
+            "batch".to_string(),
+            TagLimit::default().set_max_concurrent(1),
+        );
+
+        let request_manager = Database::new(options).run();
+        let tagged = || TransactionContext::default().set_tag(Some("batch".to_string()));
+
+        // Tagged requests wait for each other rather than fail
+        let tasks = (0..4)
+            .map(|_| {
+                let request_manager = request_manager.clone();
+
+                std::thread::spawn(move || {
+                    request_manager
+                        .send_add(Person::new("Batch".to_string(), None), tagged())
+                        .expect("Should not timeout")
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.join().unwrap();
+        }
+
+        // The slot is released once the worker is done with the request, which can be just after it was answered
+        let released = || {
+            request_manager
+                .send_info_request()
+                .unwrap()
+                .contains(&("TagInFlight[batch]".to_string(), "0".to_string()))
+        };
+
+        for _ in 0..100 {
+            if released() {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let stats = request_manager.send_info_request().unwrap();
+        assert!(stats.contains(&("TagInFlight[batch]".to_string(), "0".to_string())));
+        assert!(stats.contains(&("TagTimedOut[batch]".to_string(), "0".to_string())));
+
+        let report = request_manager
+            .send_list_active_requests()
+            .expect("Should not timeout");
+
+        assert_eq!(report.tags.len(), 1);
+        assert_eq!(report.tags[0].tag, "batch");
+        assert_eq!(report.tags[0].requests, 4);
+    }
+
+    #[test]
+    fn squashing_keeps_the_first_and_last_version_of_a_window() {
+        let options = DatabaseOptions::new_test().set_history_squash(HistorySquash::new(1000));
+
+        let request_manager = Database::new(options).run();
+
+        let person = request_manager
+            .send_add(
+                Person::new("Squash".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        for update in 1..=5 {
+            request_manager
+                .send_update(
+                    person.id.clone(),
+                    UpdatePersonData {
+                        full_name: UpdateStatement::Set(format!("Squash {}", update)),
+                        email: UpdateStatement::NoChanges,
+                        ..UpdatePersonData::default()
+                    },
+                    TransactionContext::default(),
+                )
+                .expect("Should not timeout");
+        }
+
+        let report = request_manager
+            .send_squash_history_request()
+            .expect("Should not timeout");
+        assert!(report.contains(&("SquashedVersions".to_string(), "4".to_string())));
+
+        let history = request_manager
+            .send_history(
+                person.id.clone(),
+                HistoryRequest::new(10),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout")
+            .expect("Person should exist");
+
+        let full_names: Vec<String> = history
+            .versions
+            .iter()
+            .map(|version| version.get_person().unwrap().full_name)
+            .collect();
+        assert_eq!(full_names, vec!["Squash", "Squash 5"]);
+
+        // The remaining versions are renumbered
+        let latest = request_manager
+            .send_get_version(person.id, VersionId(2), TransactionContext::default())
+            .expect("Should not timeout");
+        assert_eq!(latest.unwrap().full_name, "Squash 5");
+    }
+
+    #[test]
+    fn truncate_keeps_history() {
+        let request_manager = Database::new(DatabaseOptions::new_test()).run();
+
+        let people: Vec<Person> = (0..3)
+            .map(|index| {
+                request_manager
+                    .send_add(
+                        Person::new(format!("Truncate {}", index), None),
+                        TransactionContext::default(),
+                    )
+                    .expect("Should not timeout")
+            })
+            .collect();
+
+        assert_eq!(request_manager.send_truncate_request().unwrap(), 3);
+        assert_eq!(
+            request_manager
+                .send_list(None, TransactionContext::default())
+                .unwrap(),
+            vec![]
+        );
+
+        // Unlike a reset the people are deleted like any other transaction, their versions are kept
+        let lineage = request_manager
+            .send_lineage(people[0].id.clone(), TransactionContext::default())
+            .unwrap()
+            .expect("Person should exist");
+
+        assert_eq!(lineage.len(), 2);
+        assert_eq!(lineage[1].state, PersonVersionState::Delete);
+        assert!(lineage[1].transaction_id > lineage[0].transaction_id);
+
+        assert_eq!(
+            request_manager
+                .send_get_version(
+                    people[0].id.clone(),
+                    VersionId(1),
+                    TransactionContext::default()
+                )
+                .unwrap(),
+            Some(people[0].clone())
+        );
+
+        // Only the people added since are removed by the next truncate
+        request_manager
+            .send_add(people[1].clone(), TransactionContext::default())
+            .expect("Should not timeout");
+
+        assert_eq!(request_manager.send_truncate_request().unwrap(), 1);
+        assert_eq!(request_manager.send_truncate_request().unwrap(), 0);
+    }
+
+    #[test]
+    fn id_prefixes_are_renamed_with_their_lineage() {
+        let request_manager = Database::new(DatabaseOptions::new_test()).run();
+
+        for id in [
+            "tenantA:1",
+            "tenantA:2",
+            "tenantA:3",
+            "tenantA:4",
+            "tenantB:3",
+        ] {
+            request_manager
+                .send_add(
+                    Person {
+                        id: EntityId(id.to_string()),
+                        ..Person::new(id.to_string(), None)
+                    },
+                    TransactionContext::default(),
+                )
+                .expect("Should not timeout");
+        }
+
+        let get = |id: &str| {
+            request_manager
+                .send_get(EntityId(id.to_string()), TransactionContext::default())
+                .unwrap()
+        };
+
+        // A single transaction, one taken id rolls back every rename
+        assert!(matches!(
+            request_manager.send_rename_id_prefix_request(
+                "tenantA:".to_string(),
+                "tenantB:".to_string(),
+                None
+            ),
+            Err(RequestManagerError::DatabaseErrorStatus(_))
+        ));
+        assert!(get("tenantA:1").is_some());
+        assert!(get("tenantB:1").is_none());
+
+        let report = request_manager
+            .send_rename_id_prefix_request("tenantA:".to_string(), "tenantC:".to_string(), Some(3))
+            .unwrap();
+
+        assert!(report.contains(&("Renamed".to_string(), "4".to_string())));
+        assert!(report.contains(&("Transactions".to_string(), "2".to_string())));
+
+        assert!(get("tenantA:1").is_none());
+        assert_eq!(get("tenantC:4").unwrap().full_name, "tenantA:4");
+        assert!(get("tenantB:3").is_some());
+
+        // The renamed row's history follows the rename back to the old id
+        let lineage = request_manager
+            .send_lineage(
+                EntityId("tenantC:1".to_string()),
+                TransactionContext::default(),
+            )
+            .unwrap()
+            .expect("Person should exist");
+
+        assert_eq!(lineage.len(), 3);
+
+        // Nothing is left to rename
+        assert!(request_manager
+            .send_rename_id_prefix_request("tenantA:".to_string(), "tenantC:".to_string(), None)
+            .unwrap()
+            .contains(&("Renamed".to_string(), "0".to_string())));
+    }
+
+    #[test]
+    fn maintenance_queues_and_replays_transactions() {
+        let options = DatabaseOptions::new_test().set_threads(2);
+
+        let request_manager = Database::new(options).run();
+
+        let window = std::time::Duration::from_millis(500);
+
+        let maintenance_request_manager = request_manager.clone();
+        let maintenance = std::thread::spawn(move || {
+            maintenance_request_manager
+                .send_enter_maintenance_request(window, None)
+                .expect("Should not timeout")
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+
+        // Transactions are held until the maintenance window is over, then all of them are committed
+        let tasks = (0..4)
+            .map(|_| {
+                request_manager.send_add_task(
+                    Person::new("Maintenance".to_string(), None),
+                    TransactionContext::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.get().expect("Queued transaction should be replayed");
+        }
+
+        assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+
+        let response = maintenance.join().unwrap();
+        assert!(response.contains("rejected 0"), "{}", response);
+
+        let response = request_manager
+            .send_enter_maintenance_request(window, Some(MaintenanceTask::Snapshot))
+            .expect("Should not timeout");
+        assert!(response.contains("created snapshot"), "{}", response);
+    }
+
+    #[test]
+    fn clone_serves_frozen_reads() {
+        let options = DatabaseOptions::new_test().set_threads(2);
+
+        let request_manager = Database::new(options).run();
+
+        let person = request_manager
+            .send_add(Person::new_test(), TransactionContext::default())
+            .expect("Should not timeout");
+
+        let (_, current_transaction_id) = request_manager
+            .send_info_request()
+            .expect("Should not timeout")
+            .into_iter()
+            .find(|(key, _)| key == "CurrentTransactionID")
+            .unwrap();
+
+        let response = request_manager
+            .send_clone_at_transaction_request(
+                "analytics".to_string(),
+                current_transaction_id.parse::<i32>().unwrap().into(),
+            )
+            .expect("Should not timeout");
+        assert!(response.contains("with 1 rows"), "{}", response);
+
+        // Writes after the clone is taken are only visible on the live table
+        request_manager
+            .send_add(
+                Person::new("Later".to_string(), None),
+                TransactionContext::default(),
+            )
+            .expect("Should not timeout");
+
+        let clone_context =
+            || TransactionContext::default().set_clone(Some("analytics".to_string()));
+
+        let people = request_manager
+            .send_list(None, clone_context())
+            .expect("Should not timeout");
+        assert_eq!(people, vec![person.clone()]);
+
+        let live_people = request_manager
+            .send_list(None, TransactionContext::default())
+            .expect("Should not timeout");
+        assert_eq!(live_people.len(), 2);
+
+        // Clones are read-only
+        assert!(request_manager
+            .send_add(Person::new("Write".to_string(), None), clone_context())
+            .is_err());
+
+        request_manager
+            .send_drop_clone_request("analytics".to_string())
+            .expect("Should not timeout");
+
+        assert!(request_manager.send_list(None, clone_context()).is_err());
+    }
+
+    mod with_storage {
+        use std::{collections::BTreeMap, path::PathBuf};
+
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        use crate::{
+            consts::consts::{TransactionId, VersionId},
+            database::{
+                audit::RollbackAuditOptions,
+                clock::MockClock,
+                commands::ShutdownRequest,
+                request_manager::RequestManager,
+                scheduler::{JobAction, JobDefinition},
+                statement_stats::StatementStatsOptions,
+                table::{
+                    policy::{PolicyPredicate, RowPolicy},
+                    prepared_query::{
+                        ParameterField, PreparedQueryDefinition, QueryOrder, QueryParameter,
+                    },
+                    query::{QueryMatch, QueryPersonData},
+                    row::{PersonVersion, PersonVersionState, UpdatePersonData, UpdateStatement},
+                    view::{PersonField, ViewDefinition},
+                },
+            },
+            persistence::{
+                backup::SnapshotArchive,
+                checkpoint::ReplayCheckpoint,
+                field_encryption::FieldEncryptionOptions,
+                intent::IntentOperation,
+                parquet::ParquetExportTarget,
+                persistence::Persistence,
+                pre_commit::{PreCommitFailure, PreCommitHook},
+                snapshot_diff::{DiffSource, EntityDiff, FieldChange},
+                snapshot_shards::SnapshotSharding,
+                storage::{
+                    custom::CustomStorage,
+                    file::{FileLayout, FileOptions, FileStorage},
+                    ReadBlobState, Storage, StorageEngine, StorageResult,
+                },
+                transaction::{
+                    Transaction, TransactionFileWriteMode, TransactionWriteMode,
+                    UnknownStatementPolicy,
+                },
+            },
+        };
+
+        use super::*;
+
+        #[test]
+        fn restores_from_a_backup() {
+            let new_dir = || -> PathBuf {
+                ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                    .iter()
+                    .collect()
+            };
+
+            let backup = StorageEngine::File(FileOptions::new(new_dir()));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(backup.clone())
+                    .set_restore(false),
+            )
+            .run();
+
+            let snapshotted = request_manager
+                .send_add(
+                    Person::new("Snapshotted".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should snapshot");
+
+            let logged = request_manager
+                .send_add(
+                    Person::new("Logged".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .expect("should shut down");
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(new_dir())))
+                .set_restore_from_backup(backup);
+
+            let request_manager = Database::new(options.clone()).run();
+
+            for person in [&snapshotted, &logged] {
+                assert_eq!(
+                    request_manager
+                        .send_get(person.id.clone(), TransactionContext::default())
+                        .expect("should not timeout"),
+                    Some(person.clone())
+                );
+            }
+
+            // The storage engine already contains the backup, so restarting with the same options is fine
+            request_manager
+                .restart(options)
+                .expect("should shut down the previous database");
+
+            assert_eq!(
+                request_manager
+                    .send_get(logged.id.clone(), TransactionContext::default())
+                    .expect("should not timeout"),
+                Some(logged)
+            );
+        }
+
+        #[test]
+        fn pre_commit_hook_runs_before_commits_are_acknowledged() {
+            let dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let synced = Arc::new(std::sync::Mutex::new(Vec::<TransactionId>::new()));
+            let failing = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+            let hook_synced = synced.clone();
+            let hook_failing = failing.clone();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(dir)))
+                .set_pre_commit_hook(
+                    PreCommitHook::new(move |transactions| {
+                        if hook_failing.load(std::sync::atomic::Ordering::Relaxed) {
+                            return Err("Secondary unavailable".to_string());
+                        }
+
+                        hook_synced.lock().unwrap().extend(
+                            transactions
+                                .iter()
+                                .map(|transaction| transaction.id.clone()),
+                        );
+
+                        Ok(())
+                    })
+                    .set_on_failure(PreCommitFailure::Reject),
+                )
+                .set_restore(false);
+
+            let request_manager = Database::new(options).run();
+
+            let person = request_manager
+                .send_add(
+                    Person::new("Synced".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            assert_eq!(synced.lock().unwrap().len(), 1);
+
+            failing.store(true, std::sync::atomic::Ordering::Relaxed);
+
+            assert!(matches!(
+                request_manager.send_add(
+                    Person::new("Unsynced".to_string(), None),
+                    TransactionContext::default()
+                ),
+                Err(RequestManagerError::TransactionStatus(_))
+            ));
+            assert_eq!(synced.lock().unwrap().len(), 1);
+
+            // The rejected transaction is already applied, the callers only lose the acknowledgement
+            assert_eq!(
+                request_manager
+                    .send_list(None, TransactionContext::default())
+                    .expect("should not timeout")
+                    .len(),
+                2
+            );
+            assert!(request_manager
+                .send_get(person.id, TransactionContext::default())
+                .expect("should not timeout")
+                .is_some());
+
+            request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .expect("should not timeout");
+        }
+
+        #[test]
+        fn pipelined_wal_restores_every_commit() {
+            let dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(dir)))
+                .set_pipeline_wal(true)
+                .set_restore(false);
+
+            let request_manager = Database::new(options.clone()).run();
+
+            let handles = (0..4)
+                .map(|thread| {
+                    let request_manager = request_manager.clone();
+                    std::thread::spawn(move || {
+                        for index in 0..25 {
+                            request_manager
+                                .send_add(
+                                    Person::new(format!("Pipelined {} {}", thread, index), None),
+                                    TransactionContext::default(),
+                                )
+                                .expect("should not timeout");
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for handle in handles {
+                handle.join().expect("should not panic");
+            }
+
+            request_manager
+                .restart(options.set_restore(true))
+                .expect("should shut down the previous database");
+
+            assert_eq!(
+                request_manager
+                    .send_list(None, TransactionContext::default())
+                    .expect("should not timeout")
+                    .len(),
+                100
+            );
+
+            request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .expect("should shut down");
+        }
+
+        #[test]
+        fn downloads_a_snapshot_from_a_running_database() {
+            let new_dir = || -> PathBuf {
+                ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                    .iter()
+                    .collect()
+            };
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(new_dir())))
+                    .set_restore(false),
+            )
+            .run();
+
+            // A database that never took a snapshot has nothing to download
+            assert!(request_manager.send_download_snapshot_request().is_err());
+
+            let snapshotted = request_manager
+                .send_add(
+                    Person::new("Snapshotted".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should snapshot");
+
+            let logged = request_manager
+                .send_add(
+                    Person::new("Logged".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            let download = request_manager
+                .send_download_snapshot_request()
+                .expect("should download");
+
+            request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .expect("should shut down");
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(new_dir())));
+
+            let report = SnapshotArchive::from_ndjson(&download)
+                .expect("should decode")
+                .restore_into(
+                    &mut *Persistence::new(options.clone())
+                        .get_storage()
+                        .lock()
+                        .unwrap(),
+                )
+                .expect("should restore");
+
+            assert_eq!(report.transactions, 1);
+
+            let request_manager = Database::new(options).run();
+
+            for person in [&snapshotted, &logged] {
+                assert_eq!(
+                    request_manager
+                        .send_get(person.id.clone(), TransactionContext::default())
+                        .expect("should not timeout"),
+                    Some(person.clone())
+                );
+            }
+
+            // A truncated download misses the metadata line
+            let truncated = &download[..download.trim_end().rfind('\n').unwrap()];
+            assert!(SnapshotArchive::from_ndjson(truncated).is_err());
+        }
+
+        #[test]
+        fn audits_rolled_back_transactions() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_rollback_audit(RollbackAuditOptions::default());
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+            let missing = Person::new("Missing".to_string(), None);
+
+            let result = request_manager.send_update(
+                missing.id.clone(),
+                UpdatePersonData {
+                    full_name: UpdateStatement::Set("Found".to_string()),
+                    email: UpdateStatement::NoChanges,
+                    ..UpdatePersonData::default()
+                },
+                TransactionContext::default().set_role(Some("ops".to_string())),
+            );
+            assert!(result.is_err());
+
+            // Transactions without mutations are not audited, even when they are rolled back
+            assert!(request_manager
+                .send_query_view("missing".to_string(), TransactionContext::default())
+                .is_err());
+
+            request_manager
+                .restart(options.set_restore(true))
+                .expect("should shut down the previous database");
+
+            // The audit survives a restart, and the rolled back update was not replayed
+            let records = request_manager
+                .send_list_rollback_audit_request(10)
+                .expect("should list the audit");
+
+            assert_eq!(records.len(), 1);
+            assert!(records[0].1.contains("role: ops"), "{}", records[0].1);
+            assert!(records[0].1.contains("[Update]"), "{}", records[0].1);
+
+            assert_eq!(
+                request_manager
+                    .send_get(missing.id, TransactionContext::default())
+                    .unwrap(),
+                None
+            );
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn statement_stats_survive_a_restart() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            // Only persisted on shutdown
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_statement_stats(
+                    StatementStatsOptions::default()
+                        .set_persist_interval(Duration::from_secs(3600)),
+                );
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+            let person = Person::new_test();
+
+            request_manager
+                .send_add(person.clone(), TransactionContext::default())
+                .unwrap();
+
+            for _ in 0..3 {
+                request_manager
+                    .send_get(person.id.clone(), TransactionContext::default())
+                    .unwrap();
+            }
+
+            // A rejected add is counted as a rollback
+            assert!(request_manager
+                .send_add(person, TransactionContext::default())
+                .is_err());
+
+            request_manager
+                .restart(options.set_restore(true))
+                .expect("should shut down the previous database");
+
+            let stats: BTreeMap<String, String> = request_manager
+                .send_info_request()
+                .unwrap()
+                .into_iter()
+                .collect();
+
+            assert_eq!(stats["StatementCount[Get]"], "3");
+            assert_eq!(stats["StatementCount[Add]"], "2");
+            assert_eq!(stats["StatementCommits[Add]"], "1");
+            assert_eq!(stats["StatementRollbacks[Add]"], "1");
+            assert!(stats.contains_key("StatementLatencyP99Ms[Get]"));
+            assert!(stats.contains_key("StatementStatsSinceMs"));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn indexes_are_backfilled_and_rebuilt_after_a_restart() {
+            use crate::{
+                database::{backfill::IndexBackfillOptions, table::index::IndexedField},
+                model::person::Address,
+            };
+
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_index_backfill(
+                    IndexBackfillOptions::default()
+                        .set_batch_size(3)
+                        .set_batch_pause(Duration::from_millis(1)),
+                );
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+            for i in 0..10 {
+                let person = Person {
+                    address: Some(Address {
+                        city: Some(format!("City {}", i)),
+                        ..Address::default()
+                    }),
+                    ..Person::new(format!("Person {}", i), None)
+                };
+
+                request_manager
+                    .send_add(person, TransactionContext::default())
+                    .unwrap();
+            }
+
+            let by_city = Some(QueryPersonData {
+                address: QueryAddressData {
+                    city: QueryMatch::Value("City 3".to_string()),
+                    ..Default::default()
+                },
+                ..QueryPersonData::default()
+            });
+
+            request_manager
+                .send_backfill_index_request(IndexedField::City)
+                .unwrap();
+
+            assert!(matches!(
+                request_manager.send_backfill_index_request(IndexedField::City),
+                Err(RequestManagerError::DatabaseErrorStatus(_))
+            ));
+
+            let completed = || {
+                request_manager
+                    .send_list_index_backfills_request()
+                    .unwrap()
+                    .iter()
+                    .any(|(field, progress)| {
+                        field == "address.city" && progress.starts_with("Completed [Rows: 10/10")
+                    })
+            };
+
+            for _ in 0..500 {
+                if completed() {
+                    break;
+                }
+
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            assert!(completed());
+
+            let index_scan = ("Scan".to_string(), "Index(City)".to_string());
+
+            assert!(request_manager
+                .send_explain_query_request(by_city.clone())
+                .unwrap()
+                .contains(&index_scan));
+
+            // The index is rebuilt from the restored table
+            request_manager
+                .restart(options.set_restore(true))
+                .expect("should shut down the previous database");
+
+            assert!(request_manager
+                .send_explain_query_request(by_city)
+                .unwrap()
+                .contains(&index_scan));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn purges_are_replayed_and_drop_older_snapshots() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_archive_wal(true);
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+            let forgotten = request_manager
+                .send_add(
+                    Person::new("Forgotten".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+            let kept = request_manager
+                .send_add(
+                    Person::new("Kept".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_update(
+                    forgotten.id.clone(),
+                    UpdatePersonData {
+                        full_name: UpdateStatement::Set("Forgotten Again".to_string()),
+                        ..UpdatePersonData::default()
+                    },
+                    TransactionContext::default(),
+                )
+                .expect("should update");
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should snapshot");
+
+            let versions = request_manager
+                .send_purge(
+                    forgotten.id.clone(),
+                    TransactionContext::default().set_role(Some("dpo".to_string())),
+                )
+                .expect("should purge");
+
+            assert_eq!(versions, 2);
+
+            // Nothing is left to purge a second time
+            assert!(request_manager
+                .send_purge(forgotten.id.clone(), TransactionContext::default())
+                .is_err());
+
+            // The snapshot still holds the person, the purge in the WAL erases them again
+            request_manager
+                .restart(options.clone().set_restore(true))
+                .expect("should shut down the previous database");
+
+            // Unlike a delete there is no version left to read, not even the lineage
+            assert_eq!(
+                request_manager
+                    .send_lineage(forgotten.id.clone(), TransactionContext::default())
+                    .unwrap(),
+                None
+            );
+            assert_eq!(
+                request_manager
+                    .send_get(kept.id.clone(), TransactionContext::default())
+                    .unwrap(),
+                Some(kept)
+            );
+
+            // The older snapshot and the WAL it flushes still hold the person, neither is kept
+            request_manager
+                .send_snapshot_request()
+                .expect("should snapshot");
+
+            let snapshots = request_manager
+                .send_query_system_table(
+                    "system.snapshots".to_string(),
+                    TransactionContext::default(),
+                )
+                .expect("should list the snapshots");
+
+            assert_eq!(snapshots.rows.len(), 1);
+            assert_eq!(snapshots.column("wal_archive").unwrap(), vec![""]);
+
+            let records = request_manager
+                .send_list_purge_audit_request(10)
+                .expect("should list the audit");
+
+            assert_eq!(records.len(), 1);
+            assert!(records[0].1.contains("role: dpo"), "{}", records[0].1);
+            assert!(records[0].1.contains("versions: 2"), "{}", records[0].1);
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn restart_repoints_every_clone() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)));
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+            let clone = request_manager.clone();
+
+            let person = clone
+                .send_add(
+                    Person::new("Restarted".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .restart(options.set_restore(true))
+                .expect("should shut down the previous database");
+
+            // The clone sends to the restarted database
+            assert_eq!(
+                clone
+                    .send_get(person.id.clone(), TransactionContext::default())
+                    .expect("should not timeout"),
+                Some(person)
+            );
+
+            let restarted = request_manager.handle().begin_restart().unwrap();
+
+            assert!(matches!(
+                clone.send_add(
+                    Person::new("Restarting".to_string(), None),
+                    TransactionContext::default()
+                ),
+                Err(RequestManagerError::DatabaseRestarting)
+            ));
+            assert!(matches!(
+                clone.send_info_request(),
+                Err(RequestManagerError::DatabaseRestarting)
+            ));
+
+            request_manager.handle().repoint(&restarted);
+            assert!(!clone.handle().is_restarting());
+
+            let _ = clone
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn close_joins_every_thread() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let database = Database::new(
+                DatabaseOptions::default()
+                    .set_restore(false)
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir))),
+            );
+
+            // The storage is shared by the database and its WAL thread
+            let storage = Arc::downgrade(&database.persistence.get_storage());
+
+            let request_manager = database.run();
+            let clone = request_manager.clone();
+
+            request_manager
+                .send_add(
+                    Person::new("Closed".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager.close().expect("should shut down");
+
+            // Once close returns the workers have dropped the database and every thread holding the storage has
+            //  exited
+            assert!(storage.upgrade().is_none());
+            assert!(matches!(
+                clone.send_info_request(),
+                Err(RequestManagerError::DatabaseErrorStatus(_))
+            ));
+        }
+
+        /// Counts the threads of the whole test process, run on its own with `--ignored --test-threads=1`
+        #[test]
+        #[ignore = "counts the threads of the process, other tests must not run concurrently"]
+        #[cfg(target_os = "linux")]
+        fn thread_count_returns_to_baseline() {
+            let thread_count = || std::fs::read_dir("/proc/self/task").unwrap().count();
+            let baseline = thread_count();
+
+            for _ in 0..3 {
+                let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                    .iter()
+                    .collect();
+
+                let request_manager = Database::new(
+                    DatabaseOptions::default()
+                        .set_restore(false)
+                        .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir))),
+                )
+                .run();
+
+                request_manager
+                    .send_add(
+                        Person::new("Leak".to_string(), None),
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout");
+
+                assert!(thread_count() > baseline);
+
+                // Dropping the last clone closes the database
+                drop(request_manager);
+
+                assert_eq!(thread_count(), baseline);
+            }
+        }
+
+        #[test]
+        fn with_storage_file() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            test_restore_with_engine(StorageEngine::File(FileOptions::new(database_dir)));
+        }
+
+        #[test]
+        fn with_storage_file_separate_directories() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let snapshot_dir = database_dir.join("snapshots");
+            let wal_dirs = vec![
+                database_dir.join("wal-primary"),
+                database_dir.join("wal-mirror"),
+            ];
+
+            let engine = StorageEngine::File(
+                FileOptions::new(database_dir.join("base"))
+                    .set_snapshot_dir(snapshot_dir.clone())
+                    .set_wal_dirs(wal_dirs.clone())
+                    .set_layout(FileLayout::DirectoryPerTable),
+            );
+
+            let request_manager_initial = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false)
+                    .set_sync_file_write(TransactionWriteMode::File(
+                        TransactionFileWriteMode::Sync,
+                    )),
+            )
+            .run();
+
+            let snapshot_person = request_manager_initial
+                .send_add(Person::new_test(), TransactionContext::default())
+                .expect("should not timeout");
+
+            request_manager_initial
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            let wal_person = request_manager_initial
+                .send_add(
+                    Person::new("WAL".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            let _ = request_manager_initial
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // Snapshot blobs are nested under the table, metadata stays at the root
+            assert!(snapshot_dir
+                .join("person")
+                .join(promoted_snapshot_key(&snapshot_dir))
+                .exists());
+            assert!(snapshot_dir.join("metadata").exists());
+
+            // Every WAL directory should contain the same transactions
+            let wal_contents: Vec<String> = wal_dirs
+                .iter()
+                .map(|dir| std::fs::read_to_string(dir.join("transaction_log.json")).unwrap())
+                .collect();
+
+            assert_eq!(wal_contents[0], wal_contents[1]);
+
+            // -- Restore from disk
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            for person in [snapshot_person, wal_person] {
+                let restored_person = request_manager_restored
+                    .send_get(person.id.clone(), TransactionContext::default())
+                    .expect("should not timeout");
+
+                assert_eq!(restored_person, Some(person));
+            }
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn scheduled_jobs_run_when_the_clock_is_advanced() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            // 2024-01-01T00:30:00Z
+            let clock = Arc::new(MockClock::new(
+                std::time::UNIX_EPOCH + Duration::from_secs(1_704_069_000),
+            ));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                    .set_restore(false)
+                    .set_clock(clock.clone()),
+            )
+            .run();
+
+            request_manager
+                .send_add(Person::new_test(), TransactionContext::default())
+                .expect("should not timeout");
+
+            // Hourly, the first run is due at 01:00 by the clock
+            request_manager
+                .send_schedule_job_request(JobDefinition {
+                    name: "snapshot".to_string(),
+                    schedule: "0 0 * * * *".to_string(),
+                    action: JobAction::Snapshot,
+                })
+                .expect("should not timeout");
+
+            let snapshotted = || {
+                Persistence::open_read_only(DatabaseOptions::default().set_storage_engine(
+                    StorageEngine::File(FileOptions::new(database_dir.clone())),
+                ))
+                .list_snapshots()
+                .unwrap()
+                .len()
+                    == 1
+            };
+
+            std::thread::sleep(Duration::from_millis(200));
+            assert!(!snapshotted());
+
+            clock.advance(Duration::from_secs(30 * 60));
+
+            let started = std::time::Instant::now();
+
+            while !snapshotted() {
+                assert!(started.elapsed() < Duration::from_secs(5));
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn verify_snapshot_detects_corruption() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                    .set_restore(false),
+            )
+            .run();
+
+            for index in 0..3 {
+                request_manager
+                    .send_add(
+                        Person::new(index.to_string(), None),
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout");
+            }
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            // Writes after the snapshot should not count against the shadow table
+            request_manager
+                .send_add(
+                    Person::new("3".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            let verification = request_manager
+                .send_verify_snapshot_request(true)
+                .expect("should not timeout");
+
+            assert!(verification.contains(&("Valid".to_string(), "true".to_string())));
+            assert!(verification.contains(&("ShadowRowCount".to_string(), "3".to_string())));
+            assert!(verification.contains(&("LiveRowCount".to_string(), "3".to_string())));
+
+            // Corrupt the snapshot on disk
+            std::fs::write(
+                database_dir.join(promoted_snapshot_key(&database_dir)),
+                "[]",
+            )
+            .unwrap();
+
+            let verification = request_manager
+                .send_verify_snapshot_request(true)
+                .expect("should not timeout");
+
+            assert!(verification.contains(&("Valid".to_string(), "false".to_string())));
+            assert!(verification
+                .iter()
+                .any(|(k, v)| k == "Discrepancy" && v.starts_with("Checksum mismatch")));
+            assert!(verification
+                .iter()
+                .any(|(k, v)| k == "Discrepancy" && v.starts_with("Record count mismatch")));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn cold_versions_are_loaded_lazily() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                    .set_sync_file_write(TransactionWriteMode::Off)
+                    .set_restore(false)
+                    .set_hot_versions(2),
+            )
+            .run();
+
+            let person = request_manager
+                .send_add(
+                    Person::new("0".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            for index in 1..10 {
+                request_manager
+                    .send_update(
+                        person.id.clone(),
+                        UpdatePersonData {
+                            full_name: UpdateStatement::Set(index.to_string()),
+                            email: UpdateStatement::NoChanges,
+                            ..UpdatePersonData::default()
+                        },
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout");
+            }
+
+            // Older versions should have been spilled to storage
+            assert!(database_dir
+                .join("cold_versions")
+                .join(person.id.to_string())
+                .join("0")
+                .exists());
+
+            // Every version should still be readable, whether it is in memory or in storage
+            for index in 0..10 {
+                let version = request_manager
+                    .send_get_version(
+                        person.id.clone(),
+                        VersionId(index + 1),
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout")
+                    .expect("version should exist");
+
+                assert_eq!(version.full_name, index.to_string());
+            }
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn views_are_maintained_and_restored() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let engine = StorageEngine::File(FileOptions::new(database_dir));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false),
+            )
+            .run();
+
+            let existing = request_manager
+                .send_add(
+                    Person::new("existing".to_string(), Some("email".to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_create_view_request(ViewDefinition {
+                    name: "with_email".to_string(),
+                    query: Some(QueryPersonData {
+                        full_name: QueryMatch::Any,
+                        email: QueryMatch::NotNull,
+                        ..QueryPersonData::default()
+                    }),
+                    projection: vec![PersonField::FullName],
+                })
+                .expect("should not timeout");
+
+            // Rows committed after the view is created are applied incrementally
+            let added = request_manager
+                .send_add(
+                    Person::new("added".to_string(), Some("email".to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_add(
+                    Person::new("no email".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            let view = request_manager
+                .send_query_view("with_email".to_string(), TransactionContext::default())
+                .expect("should not timeout");
+
+            let mut names: Vec<Option<String>> =
+                view.rows.iter().map(|r| r.full_name.clone()).collect();
+            names.sort();
+
+            assert_eq!(
+                names,
+                vec![
+                    Some(added.full_name.clone()),
+                    Some(existing.full_name.clone())
+                ]
+            );
+            assert!(view.rows.iter().all(|r| r.email.is_none()));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // -- Restore, the view should be rebuilt from its persisted definition
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            let restored_view = request_manager_restored
+                .send_query_view("with_email".to_string(), TransactionContext::default())
+                .expect("should not timeout");
+
+            assert_eq!(restored_view.rows.len(), 2);
+
+            request_manager_restored
+                .send_drop_view_request("with_email".to_string())
+                .expect("should not timeout");
+
+            assert!(request_manager_restored
+                .send_query_view("with_email".to_string(), TransactionContext::default())
+                .is_err());
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn row_policies_restrict_reads_and_are_restored() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let engine = StorageEngine::File(FileOptions::new(database_dir));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false),
+            )
+            .run();
+
+            let visible = request_manager
+                .send_add(
+                    Person::new("x".to_string(), Some("someone@x.com".to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            let hidden = request_manager
+                .send_add(
+                    Person::new("y".to_string(), Some("someone@y.com".to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            request_manager
+                .send_create_policy_request(RowPolicy {
+                    name: "x_domain".to_string(),
+                    role: "tenant_x".to_string(),
+                    predicate: PolicyPredicate::EmailDomain("x.com".to_string()),
+                })
+                .expect("should not timeout");
+
+            let as_tenant = || TransactionContext::default().set_role(Some("tenant_x".to_string()));
+
+            assert_eq!(
+                request_manager
+                    .send_list(None, as_tenant())
+                    .expect("should not timeout"),
+                vec![visible.clone()]
+            );
+            assert_eq!(
+                request_manager
+                    .send_get(hidden.id.clone(), as_tenant())
+                    .expect("should not timeout"),
+                None
+            );
+
+            // Requests without a role are not restricted
+            assert_eq!(
+                request_manager
+                    .send_list(None, TransactionContext::default())
+                    .expect("should not timeout")
+                    .len(),
+                2
+            );
+
+            // Reads inside of a mutation transaction are not filtered, so they are rejected
+            assert!(request_manager
+                .send_transaction(
+                    vec![
+                        Statement::Add(Person::new("z".to_string(), None)),
+                        Statement::Get(hidden.id.clone()),
+                    ],
+                    as_tenant(),
+                )
+                .is_err());
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // -- Restore, the policy should still be enforced
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            assert_eq!(
+                request_manager_restored
+                    .send_get(hidden.id.clone(), as_tenant())
+                    .expect("should not timeout"),
+                None
+            );
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn prepared_queries_are_executed_and_restored() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let engine = StorageEngine::File(FileOptions::new(database_dir));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false)
+                    .set_prepared_queries_only(true),
+            )
+            .run();
+
+            for (full_name, email) in [("b", "one@x.com"), ("a", "one@x.com"), ("c", "two@x.com")] {
+                request_manager
+                    .send_add(
+                        Person::new(full_name.to_string(), Some(email.to_string())),
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout");
+            }
+
+            let definition = PreparedQueryDefinition {
+                name: "by_email".to_string(),
+                query: QueryPersonData::default(),
+                parameters: vec![QueryParameter {
+                    name: "email".to_string(),
+                    field: ParameterField::Email,
+                }],
+                projection: vec![PersonField::FullName],
+                order: Some(QueryOrder {
+                    field: PersonField::FullName,
+                    descending: false,
+                }),
+            };
+
+            request_manager
+                .send_create_prepared_query_request(definition.clone())
+                .expect("should create prepared query");
+
+            // Parameters are validated when the query is registered
+            let mut invalid = definition.clone();
+            invalid.parameters.push(invalid.parameters[0].clone());
+
+            assert!(request_manager
+                .send_create_prepared_query_request(invalid)
+                .is_err());
+
+            let values = BTreeMap::from([("email".to_string(), "one@x.com".to_string())]);
+
+            let execute = |request_manager: &RequestManager| {
+                request_manager
+                    .send_execute_prepared_query(
+                        "by_email".to_string(),
+                        values.clone(),
+                        TransactionContext::default(),
+                    )
+                    .expect("should execute")
+                    .rows
+                    .into_iter()
+                    .map(|row| (row.full_name, row.email))
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(
+                execute(&request_manager),
+                vec![(Some("a".to_string()), None), (Some("b".to_string()), None)]
+            );
+
+            assert!(request_manager
+                .send_execute_prepared_query(
+                    "by_email".to_string(),
+                    BTreeMap::new(),
+                    TransactionContext::default(),
+                )
+                .is_err());
+
+            // Ad-hoc filters are rejected, unfiltered lists are not
+            assert!(request_manager
+                .send_list(
+                    Some(QueryPersonData {
+                        email: QueryMatch::Value("one@x.com".to_string()),
+                        ..QueryPersonData::default()
+                    }),
+                    TransactionContext::default(),
+                )
+                .is_err());
+            assert_eq!(
+                request_manager
+                    .send_list(None, TransactionContext::default())
+                    .expect("should not timeout")
+                    .len(),
+                3
+            );
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // -- Restore, the prepared query should still be registered
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            assert_eq!(execute(&request_manager_restored).len(), 2);
+
+            request_manager_restored
+                .send_drop_prepared_query_request("by_email".to_string())
+                .expect("should drop prepared query");
+
+            assert!(request_manager_restored
+                .send_list_prepared_queries_request()
+                .expect("should not timeout")
+                .is_empty());
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn sequences_are_restored_from_snapshot_and_wal() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let engine = StorageEngine::File(FileOptions::new(database_dir));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false),
+            )
+            .run();
+
+            let next_val = |request_manager: &RequestManager, name: &str| {
+                request_manager
+                    .send_next_val(name.to_string(), TransactionContext::default())
+                    .expect("should not timeout")
+            };
+
+            assert_eq!(next_val(&request_manager, "orders"), 1);
+            assert_eq!(next_val(&request_manager, "orders"), 2);
+            assert_eq!(next_val(&request_manager, "invoices"), 1);
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            // Replayed from the WAL
+            assert_eq!(next_val(&request_manager, "orders"), 3);
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            assert_eq!(next_val(&request_manager_restored, "orders"), 4);
+            assert_eq!(next_val(&request_manager_restored, "invoices"), 2);
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn outbox_messages_are_acknowledged_once_and_restored() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let engine = StorageEngine::File(FileOptions::new(database_dir));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false),
+            )
+            .run();
+
+            let person = Person::new("Outbox".to_string(), None);
+
+            let enqueue =
+                |payload: &str| Statement::Enqueue("people".to_string(), payload.to_string());
+
+            let read = |request_manager: &RequestManager| {
+                request_manager
+                    .send_read_outbox(None, 10, TransactionContext::default())
+                    .expect("should not timeout")
+            };
+
+            // The message is enqueued with the mutation it describes, a rolled back transaction enqueues nothing
+            request_manager
+                .send_transaction(
+                    vec![Statement::Add(person.clone()), enqueue("added")],
+                    TransactionContext::default(),
+                )
+                .expect("should commit");
+
+            assert!(request_manager
+                .send_transaction(
+                    vec![enqueue("rolled back"), Statement::Add(person.clone())],
+                    TransactionContext::default(),
+                )
+                .is_err());
+
+            let messages = read(&request_manager);
+
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].payload, "added");
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            request_manager
+                .send_transaction(
+                    vec![Statement::Remove(person.id.clone()), enqueue("removed")],
+                    TransactionContext::default(),
+                )
+                .expect("should commit");
+
+            // Each message can only be acknowledged once
+            request_manager
+                .send_ack_outbox(vec![messages[0].id.clone()], TransactionContext::default())
+                .expect("should acknowledge");
+
+            assert!(request_manager
+                .send_ack_outbox(vec![messages[0].id.clone()], TransactionContext::default())
+                .is_err());
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // The snapshot's message was acknowledged in the WAL, the WAL's message is still pending
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            let messages = read(&request_manager_restored);
+
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].payload, "removed");
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn replay_conflict_is_reported() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_restore(false);
+
+            let person = Person::new("Conflict".to_string(), None);
+
+            let request_manager = Database::new(options.clone()).run();
+
+            request_manager
+                .send_add(person.clone(), TransactionContext::default())
+                .expect("should not timeout");
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // Duplicate the committed add in the WAL, replaying the copy conflicts with the original
+            let storage = Persistence::new(options.clone()).get_storage();
+            let transactions = storage.lock().unwrap().transaction_load().unwrap();
+            storage
+                .lock()
+                .unwrap()
+                .transaction_write(transactions[0].as_bytes())
+                .unwrap();
+
+            let restore_options = options.set_restore(true);
+
+            let restore = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                Database::new(restore_options.clone()).run()
+            }));
+
+            assert!(restore.is_err());
+
+            let report = Persistence::new(restore_options)
+                .snapshot_manager
+                .load_replay_conflict()
+                .unwrap()
+                .expect("Report should be saved");
+
+            assert_eq!(report.statement_index, Some(0));
+            assert_eq!(report.rows.len(), 1);
+            assert_eq!(report.rows[0].id, person.id);
+            assert_eq!(report.rows[0].current_state, Some(person));
+            assert_eq!(report.rows[0].versions.len(), 1);
+        }
+
+        #[test]
+        fn restore_resumes_from_the_replay_checkpoint() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_restore(false);
+
+            let people = ["Snapshotted", "Checkpointed", "Replayed"]
+                .map(|full_name| Person::new(full_name.to_string(), None));
+
+            let request_manager = Database::new(options.clone()).run();
+
+            request_manager
+                .send_add(people[0].clone(), TransactionContext::default())
+                .expect("should not timeout");
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            for person in &people[1..] {
+                request_manager
+                    .send_add(person.clone(), TransactionContext::default())
+                    .expect("should not timeout");
+            }
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // Given a restore that was killed once it had checkpointed the first transaction after the snapshot
+            let persistence = Persistence::new(options.clone());
+
+            let snapshot_transaction_id = persistence
+                .snapshot_manager
+                .snapshot_transaction_id()
+                .unwrap()
+                .expect("a snapshot was taken");
+
+            let wal = persistence
+                .get_storage()
+                .lock()
+                .unwrap()
+                .transaction_load()
+                .unwrap();
+            let checkpointed =
+                Transaction::from_wal(&wal[0], UnknownStatementPolicy::Fail).unwrap();
+
+            let version = |person: &Person, transaction_id: &TransactionId| PersonVersion {
+                id: person.id.clone(),
+                state: PersonVersionState::State(person.clone()),
+                version: VersionId(1),
+                transaction_id: transaction_id.clone(),
+                lineage: None,
+            };
+
+            let mut from_checkpoint = people[1].clone();
+            from_checkpoint.full_name = "From checkpoint".to_string();
+
+            persistence
+                .snapshot_manager
+                .save_replay_checkpoint(ReplayCheckpoint {
+                    snapshot_transaction_id: snapshot_transaction_id.clone(),
+                    transaction_id: checkpointed.id.clone(),
+                    versions: vec![
+                        version(&people[0], &snapshot_transaction_id),
+                        version(&from_checkpoint, &checkpointed.id),
+                    ],
+                    sequences: BTreeMap::new(),
+                    outbox: vec![],
+                    latest_purge: None,
+                })
+                .unwrap();
+
+            let restore_options = options.set_restore(true).set_replay_checkpoint_interval(1);
+
+            let get = |request_manager: &RequestManager, person: &Person| {
+                request_manager
+                    .send_get(person.id.clone(), TransactionContext::default())
+                    .expect("should not timeout")
+            };
+
+            // The checkpointed transaction is not replayed again, its row is read from the checkpoint
+            let request_manager = Database::new(restore_options.clone()).run();
+
+            assert_eq!(get(&request_manager, &people[0]), Some(people[0].clone()));
+            assert_eq!(get(&request_manager, &people[1]), Some(from_checkpoint));
+            assert_eq!(get(&request_manager, &people[2]), Some(people[2].clone()));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // Once the restore has completed the checkpoint is cleared, the next restore replays the snapshot
+            assert!(Persistence::new(restore_options.clone())
+                .snapshot_manager
+                .load_replay_checkpoint()
+                .unwrap()
+                .is_none());
+
+            let request_manager = Database::new(restore_options).run();
+
+            assert_eq!(get(&request_manager, &people[1]), Some(people[1].clone()));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn interrupted_intent_refuses_writes_until_reset() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)));
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+            let person = request_manager
+                .send_add(
+                    Person::new("Interrupted".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            // A completed snapshot leaves a completed intent behind
+            request_manager.send_snapshot_request().unwrap();
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            let snapshot_manager = Persistence::new(options.clone()).snapshot_manager;
+
+            assert_eq!(snapshot_manager.load_interrupted_intent().unwrap(), None);
+
+            // Simulate a crash part way through a WAL flush
+            snapshot_manager
+                .begin_intent(IntentOperation::WalFlush, TransactionId(5), 0)
+                .unwrap();
+
+            let request_manager = Database::new(options.clone()).run();
+
+            // Reads are still served, writes and snapshots are refused
+            assert_eq!(
+                request_manager
+                    .send_get(person.id.clone(), TransactionContext::default())
+                    .unwrap(),
+                Some(person)
+            );
+
+            let write = request_manager.send_add(
+                Person::new("Refused".to_string(), None),
+                TransactionContext::default(),
+            );
+
+            assert!(
+                matches!(&write, Err(RequestManagerError::TransactionRollback(message)) if message.contains("WAL flush started at transaction 5")),
+                "{:?}",
+                write
+            );
+            assert!(request_manager.send_snapshot_request().is_err());
+
+            let stats = request_manager.send_info_request().unwrap();
+            assert!(stats.iter().any(|(key, _)| key == "InterruptedOperation"));
+
+            // A reset leaves storage consistent again
+            request_manager.send_reset_request().unwrap();
+
+            request_manager
+                .send_add(
+                    Person::new("Accepted".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("Writes should be accepted after a reset");
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            assert_eq!(
+                Persistence::new(options)
+                    .snapshot_manager
+                    .load_interrupted_intent()
+                    .unwrap(),
+                None
+            );
+        }
+
+        #[test]
+        fn resets_do_not_leave_part_of_a_concurrent_write() {
+            /// Slow WAL writes, so commits queue up behind the write in progress when a reset starts
+            struct SlowWal(FileStorage);
+
+            impl Storage for SlowWal {
+                fn init(&mut self) -> StorageResult<()> {
+                    self.0.init()
+                }
+
+                fn reset_database(&mut self) -> StorageResult<()> {
+                    self.0.reset_database()
+                }
+
+                fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+                    self.0.write_blob(path, bytes)
+                }
+
+                fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+                    self.0.read_blob(path)
+                }
+
+                fn delete_blob(&self, path: String) -> StorageResult<()> {
+                    self.0.delete_blob(path)
+                }
+
+                fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+                    self.0.transaction_write(transaction)
+                }
+
+                fn transaction_write_batch(
+                    &mut self,
+                    transactions: &[Vec<u8>],
+                ) -> StorageResult<()> {
+                    std::thread::sleep(Duration::from_millis(5));
+
+                    self.0.transaction_write_batch(transactions)
+                }
+
+                fn transaction_sync(&self) -> StorageResult<()> {
+                    self.0.transaction_sync()
+                }
+
+                fn transaction_flush(&mut self) -> StorageResult<()> {
+                    self.0.transaction_flush()
+                }
+
+                fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+                    self.0.transaction_load()
+                }
+            }
+
+            let dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let storage = CustomStorage::new("slow-wal".to_string(), move |_| {
+                Box::new(SlowWal(FileStorage::new(
+                    FileOptions::new(dir.clone()),
+                    TransactionWriteMode::File(TransactionFileWriteMode::Sync),
+                )))
+            });
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::Custom(storage))
+                .set_threads(4)
+                .set_pipeline_wal(true);
+
+            let request_manager = Database::new(options.clone()).run();
+
+            let names = |request_manager: &RequestManager| {
+                let mut names = request_manager
+                    .send_list(None, TransactionContext::default())
+                    .expect("Should not timeout")
+                    .into_iter()
+                    .map(|person| person.full_name)
+                    .collect::<Vec<_>>();
+
+                names.sort();
+                names
+            };
+
+            // Only a write that is erased by the last reset can survive a restart, so every round resets once
+            for round in 0..20 {
+                let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+                // Every transaction adds a pair of people, a reset may only drop both of them
+                let writers = (0..16)
+                    .map(|writer| {
+                        let request_manager = request_manager.clone();
+                        let stop = stop.clone();
+
+                        std::thread::spawn(move || {
+                            let mut pair = 0;
+
+                            while !stop.load(std::sync::atomic::Ordering::SeqCst) {
+                                let statements = ["a", "b"]
+                                    .iter()
+                                    .map(|half| {
+                                        Statement::Add(Person::new(
+                                            format!("{}-{}-{}-{}", round, writer, pair, half),
+                                            None,
+                                        ))
+                                    })
+                                    .collect();
+
+                                request_manager
+                                    .send_transaction(statements, TransactionContext::default())
+                                    .expect("Should not timeout");
+
+                                pair += 1;
+                            }
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                std::thread::sleep(Duration::from_millis(20));
+
+                request_manager
+                    .send_reset_request()
+                    .expect("Should not timeout");
+
+                std::thread::sleep(Duration::from_millis(20));
+
+                stop.store(true, std::sync::atomic::Ordering::SeqCst);
+
+                for writer in writers {
+                    writer.join().unwrap();
+                }
+
+                let before_restart = names(&request_manager);
+
+                for name in &before_restart {
+                    let (pair, _) = name.rsplit_once('-').unwrap();
+
+                    assert!(before_restart.contains(&format!("{}-a", pair)), "{}", name);
+                    assert!(before_restart.contains(&format!("{}-b", pair)), "{}", name);
+                }
+
+                // Nothing that was reset is written to the WAL afterwards and replayed
+                request_manager
+                    .restart(options.clone())
+                    .expect("Should not timeout");
+
+                assert_eq!(names(&request_manager), before_restart);
+            }
+        }
+
+        #[test]
+        fn snapshot_exports_the_flushed_wal_as_parquet() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+            let export_dir = database_dir.join("parquet");
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)))
+                .set_parquet_export(ParquetExportTarget::Directory(export_dir.clone()))
+                .set_restore(false);
+
+            let request_manager = Database::new(options).run();
+
+            for name in ["A", "B"] {
+                request_manager
+                    .send_add(
+                        Person::new(name.to_string(), None),
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout");
+            }
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            let files = std::fs::read_dir(&export_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect::<Vec<_>>();
+
+            assert_eq!(files.len(), 1);
+
+            let reader =
+                SerializedFileReader::new(std::fs::File::open(&files[0]).unwrap()).unwrap();
+
+            assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        }
+
+        #[test]
+        fn compact_wal_drops_transactions_covered_by_the_snapshot() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)));
+
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+            let snapshotted = request_manager
+                .send_add(
+                    Person::new("Snapshot".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            let covered_transaction = Persistence::new(options.clone())
+                .get_storage()
+                .lock()
+                .unwrap()
+                .transaction_load()
+                .unwrap()[0]
+                .clone();
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            // The snapshot replaces the WAL file, open it once the snapshot has been taken
+            let storage = Persistence::new(options.clone()).get_storage();
+
+            let wal_person = request_manager
+                .send_add(
+                    Person::new("WAL".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
+
+            // Mimics a snapshot that was written without its WAL being flushed, replaying the covered
+            //  transaction on top of the snapshot would conflict
+            storage
+                .lock()
+                .unwrap()
+                .transaction_write(covered_transaction.as_bytes())
+                .unwrap();
+
+            let status = request_manager
+                .send_compact_wal_request()
+                .expect("should not timeout");
+
+            assert!(status.contains("dropped 1 txs"), "{}", status);
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            assert_eq!(storage.lock().unwrap().transaction_load().unwrap().len(), 1);
+
+            let request_manager = Database::new(options.set_restore(true)).run();
+
+            let people = request_manager
+                .send_list(None, TransactionContext::default())
+                .expect("should not timeout");
+
+            assert_eq!(people.len(), 2);
+            assert!(people.contains(&snapshotted));
+            assert!(people.contains(&wal_person));
         }
-    }
 
-    pub fn get(&self) -> Result<Vec<Person>, RequestManagerError> {
-        get_statement(&self.response).and_then(|mut action_result| {
-            Ok(action_result
-                .pop()
-                .expect("single a statement should generate single response")
-                .list())
-        })
-    }
-}
+        #[test]
+        fn migration_converges_and_cuts_over() {
+            let data_dir = |name: &str| -> PathBuf {
+                ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string(), name]
+                    .iter()
+                    .collect()
+            };
 
-impl Wait for TaskListResponse {
-    fn wait(&self) {
-        self.get().expect("Should not timeout");
-    }
-}
+            let target = StorageEngine::File(FileOptions::new(data_dir("target")));
 
-#[cfg(test)]
-mod tests {
-    use uuid::Uuid;
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(data_dir("current"))))
+                .set_migrate_to(target.clone())
+                .set_restore(false);
 
-    use crate::{
-        consts::consts::EntityId,
-        database::{
-            commands::{DatabaseCommand, DatabaseCommandResponse, TransactionContext},
-            database::Database,
-            options::DatabaseOptions,
-        },
-        model::{
-            person::Person,
-            statement::{Statement, StatementResult},
-        },
-    };
+            let request_manager = Database::new(options).run();
 
-    #[test]
-    fn sync() {
-        let options = DatabaseOptions::new_test().set_threads(1);
+            let migration_phase = || {
+                request_manager
+                    .send_info_request()
+                    .expect("should not timeout")
+                    .into_iter()
+                    .find(|(key, _)| key == "MigrationPhase")
+                    .map(|(_, value)| value)
+            };
 
-        let request_manager = Database::new(options).run();
+            let before_migration = request_manager
+                .send_add(
+                    Person::new("Before".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
 
-        let action_result = request_manager
-            .send_single_statement(
-                Statement::Add(Person {
-                    id: EntityId::new(),
-                    full_name: "Test".to_string(),
-                    email: Some(Uuid::new_v4().to_string()),
-                }),
-                TransactionContext::default(),
-            )
-            .expect("Should not timeout");
+            // The target only has the transactions written since the migration started
+            assert_eq!(migration_phase(), Some("DualWrite".to_string()));
+            assert!(request_manager.send_cutover_migration_request().is_err());
 
-        assert_eq!(action_result.single().full_name, "Test");
-    }
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
 
-    #[test]
-    fn task_command() {
-        let options = DatabaseOptions::new_test().set_threads(1);
+            assert_eq!(migration_phase(), Some("Converged".to_string()));
 
-        let request_manager = Database::new(options).run();
+            request_manager
+                .send_cutover_migration_request()
+                .expect("should not timeout");
 
-        let person = Person {
-            id: EntityId::new(),
-            full_name: "Test".to_string(),
-            email: Some(Uuid::new_v4().to_string()),
-        };
+            let after_cutover = request_manager
+                .send_add(
+                    Person::new("After".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
 
-        let task = request_manager.send_database_command_task(DatabaseCommand::Transaction(vec![
-            Statement::Add(person.clone()),
-        ]));
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
 
-        let action_result = task.get().expect("Should not timeout");
+            let request_manager =
+                Database::new(DatabaseOptions::default().set_storage_engine(target)).run();
 
-        assert_eq!(
-            action_result,
-            DatabaseCommandResponse::transaction_commit(vec![StatementResult::Single(person)])
-        );
-    }
+            let people = request_manager
+                .send_list(None, TransactionContext::default())
+                .expect("should not timeout");
 
-    #[test]
-    fn task_statement() {
-        let options = DatabaseOptions::new_test().set_threads(1);
+            assert_eq!(people.len(), 2);
+            assert!(people.contains(&before_migration));
+            assert!(people.contains(&after_cutover));
+        }
 
-        let request_manager = Database::new(options).run();
+        #[test]
+        fn sensitive_fields_are_encrypted_and_masked() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
 
-        let task = request_manager.send_transaction_task(
-            vec![Statement::Add(Person {
-                id: EntityId::new(),
-                full_name: "Test".to_string(),
-                email: Some(Uuid::new_v4().to_string()),
-            })],
-            TransactionContext::default(),
-        );
+            let engine = StorageEngine::File(FileOptions::new(database_dir.clone()));
 
-        let action_result = task.get().expect("Should not timeout");
+            let field_encryption = FieldEncryptionOptions::new(
+                "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+                vec![PersonField::Email],
+            )
+            .unwrap()
+            .set_authorized_roles(vec!["support".to_string()]);
 
-        assert_eq!(action_result.len(), 1);
-    }
+            let options = DatabaseOptions::default()
+                .set_storage_engine(engine)
+                .set_field_encryption(field_encryption);
 
-    #[test]
-    fn task_add() {
-        let options = DatabaseOptions::new_test().set_threads(1);
+            let request_manager = Database::new(options.clone().set_restore(false)).run();
 
-        let request_manager = Database::new(options).run();
+            let snapshotted = request_manager
+                .send_add(
+                    Person::new("snapshot".to_string(), Some("snapshot@x.com".to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
 
-        let person = Person {
-            id: EntityId::new(),
-            full_name: "Test".to_string(),
-            email: Some(Uuid::new_v4().to_string()),
-        };
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
 
-        let added_person = request_manager
-            .send_add_task(person.clone(), TransactionContext::default())
-            .get()
-            .expect("should not timeout");
+            let in_wal = request_manager
+                .send_add(
+                    Person::new("wal".to_string(), Some("wal@x.com".to_string())),
+                    TransactionContext::default(),
+                )
+                .expect("should not timeout");
 
-        assert_eq!(added_person, person);
-    }
+            let as_role =
+                |role: &str| TransactionContext::default().set_role(Some(role.to_string()));
+
+            // Unauthorized roles receive masked values, authorized roles receive the decrypted values
+            assert_eq!(
+                request_manager
+                    .send_get(in_wal.id.clone(), as_role("analyst"))
+                    .expect("should not timeout")
+                    .unwrap()
+                    .email,
+                Some("****@x.com".to_string())
+            );
+            assert_eq!(
+                request_manager
+                    .send_get(in_wal.id.clone(), as_role("support"))
+                    .expect("should not timeout")
+                    .unwrap()
+                    .email,
+                in_wal.email
+            );
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
 
-    mod with_storage {
-        use std::path::PathBuf;
+            // Neither the snapshot nor the WAL contain the plaintext values
+            for file in [
+                promoted_snapshot_key(&database_dir),
+                "transaction_log.json".to_string(),
+            ] {
+                let contents = std::fs::read_to_string(database_dir.join(&file)).unwrap();
 
-        use crate::{
-            database::commands::ShutdownRequest,
-            persistence::{
-                storage::{
-                    dynamodb::DynamoOptions, postgres::PostgresOptions, s3::S3Options,
-                    StorageEngine,
-                },
-                transaction::{TransactionFileWriteMode, TransactionWriteMode},
-            },
-        };
+                assert!(!contents.contains("@x.com"), "{} is not encrypted", file);
+            }
 
-        use super::*;
+            // -- Restore, values are decrypted from both the snapshot and the WAL
+            let request_manager_restored = Database::new(options.set_restore(true)).run();
+
+            for person in [snapshotted, in_wal] {
+                assert_eq!(
+                    request_manager_restored
+                        .send_get(person.id.clone(), TransactionContext::default())
+                        .expect("should not timeout"),
+                    Some(person)
+                );
+            }
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
 
         #[test]
-        fn with_storage_file() {
+        fn scheduled_jobs_run_and_are_restored() {
             let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
                 .iter()
                 .collect();
 
-            test_restore_with_engine(StorageEngine::File(database_dir));
+            let engine = StorageEngine::File(FileOptions::new(database_dir.clone()));
+
+            let request_manager = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine.clone())
+                    .set_restore(false),
+            )
+            .run();
+
+            assert!(request_manager
+                .send_schedule_job_request(JobDefinition {
+                    name: "invalid".to_string(),
+                    schedule: "not a schedule".to_string(),
+                    action: JobAction::Snapshot,
+                })
+                .is_err());
+
+            request_manager
+                .send_add(Person::new_test(), TransactionContext::default())
+                .expect("should not timeout");
+
+            // Snapshot every second
+            request_manager
+                .send_schedule_job_request(JobDefinition {
+                    name: "snapshot".to_string(),
+                    schedule: "* * * * * *".to_string(),
+                    action: JobAction::Snapshot,
+                })
+                .expect("should not timeout");
+
+            std::thread::sleep(std::time::Duration::from_millis(2500));
+
+            assert!(database_dir
+                .join(promoted_snapshot_key(&database_dir))
+                .exists());
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // -- Restore, the job should be rescheduled from the metadata
+            let request_manager_restored = Database::new(
+                DatabaseOptions::default()
+                    .set_storage_engine(engine)
+                    .set_restore(true),
+            )
+            .run();
+
+            let jobs = request_manager_restored
+                .send_list_jobs_request()
+                .expect("should not timeout");
+
+            assert_eq!(jobs.len(), 1);
+            assert_eq!(jobs[0].0, "snapshot");
+
+            request_manager_restored
+                .send_cancel_job_request("snapshot".to_string())
+                .expect("should not timeout");
+
+            assert!(request_manager_restored
+                .send_list_jobs_request()
+                .expect("should not timeout")
+                .is_empty());
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
         }
 
         #[test]
         #[ignore = "CI will not be set up for running Postgres"]
+        #[cfg(feature = "postgres")]
         fn with_storage_pg() {
+            use crate::persistence::storage::postgres::PostgresOptions;
+
             test_restore_with_engine(StorageEngine::Postgres(PostgresOptions::new_test()));
         }
 
         #[test]
         #[ignore = "CI will not be set up for running S3"]
+        #[cfg(feature = "s3")]
         fn with_storage_s3() {
+            use crate::persistence::storage::s3::S3Options;
+
             test_restore_with_engine(StorageEngine::S3(S3Options::new_test()));
         }
 
         #[test]
         #[ignore = "CI will not be set up for running DynamoDB"]
+        #[cfg(feature = "dynamodb")]
         fn with_storage_ddb() {
+            use crate::persistence::storage::dynamodb::DynamoOptions;
+
             test_restore_with_engine(StorageEngine::DynamoDB(DynamoOptions::new_test()));
         }
 
@@ -810,6 +6251,9 @@ mod tests {
                 id: EntityId::new(),
                 full_name: "Test".to_string(),
                 email: Some(Uuid::new_v4().to_string()),
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
             };
 
             // Write #1
@@ -827,6 +6271,9 @@ mod tests {
                         id: EntityId::new(),
                         full_name: "Test".to_string(),
                         email: Some(Uuid::new_v4().to_string()),
+                        address: None,
+                        phone_numbers: vec![],
+                        attachments: vec![],
                     },
                     TransactionContext::default(),
                 )
@@ -860,5 +6307,250 @@ mod tests {
                 .send_shutdown_request(ShutdownRequest::Coordinator)
                 .unwrap();
         }
+
+        #[test]
+        fn corrupt_snapshot_rolls_back_to_previous() {
+            for archive_wal in [false, true] {
+                let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                    .iter()
+                    .collect();
+
+                let options = DatabaseOptions::default()
+                    .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                    .set_retained_snapshots(2)
+                    .set_archive_wal(archive_wal)
+                    .set_restore(false);
+
+                let request_manager = Database::new(options.clone()).run();
+
+                let mut people = vec![];
+                let mut snapshot_keys = vec![];
+
+                for index in 0..3 {
+                    people.push(
+                        request_manager
+                            .send_add(
+                                Person::new(index.to_string(), None),
+                                TransactionContext::default(),
+                            )
+                            .expect("should not timeout"),
+                    );
+
+                    request_manager
+                        .send_snapshot_request()
+                        .expect("should not timeout");
+
+                    snapshot_keys.push(promoted_snapshot_key(&database_dir));
+                }
+
+                let _ = request_manager
+                    .send_shutdown_request(ShutdownRequest::Coordinator)
+                    .unwrap();
+
+                // Only the two most recent snapshots are retained
+                assert!(!database_dir.join(&snapshot_keys[0]).exists());
+                assert!(database_dir.join(&snapshot_keys[1]).exists());
+
+                // A crash mid-write of the newest snapshot
+                std::fs::write(database_dir.join(&snapshot_keys[2]), "[{").unwrap();
+
+                // -- Restore, rolls back to the previous snapshot
+                let request_manager_restored = Database::new(options.set_restore(true)).run();
+
+                for person in &people[..2] {
+                    assert_eq!(
+                        request_manager_restored
+                            .send_get(person.id.clone(), TransactionContext::default())
+                            .expect("should not timeout"),
+                        Some(person.clone())
+                    );
+                }
+
+                // Was only in the corrupt snapshot, the WAL was flushed when it was written. Unless it was archived
+                let restored = request_manager_restored
+                    .send_get(people[2].id.clone(), TransactionContext::default());
+
+                match archive_wal {
+                    true => assert_eq!(restored.unwrap(), Some(people[2].clone())),
+                    false => assert_eq!(restored.unwrap(), None),
+                }
+
+                let _ = request_manager_restored
+                    .send_shutdown_request(ShutdownRequest::Coordinator)
+                    .unwrap();
+            }
+        }
+
+        #[test]
+        fn sharded_snapshot_is_restored() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                .set_snapshot_sharding(SnapshotSharding::new(3).set_parallelism(2))
+                .set_restore(false);
+
+            let request_manager = Database::new(options.clone()).run();
+
+            let people: Vec<Person> = (0..20)
+                .map(|index| {
+                    request_manager
+                        .send_add(
+                            Person::new(index.to_string(), None),
+                            TransactionContext::default(),
+                        )
+                        .expect("should not timeout")
+                })
+                .collect();
+
+            request_manager
+                .send_snapshot_request()
+                .expect("should not timeout");
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+
+            // The shards are recorded in the metadata and the snapshot is not written as a single blob
+            let key = promoted_snapshot_key(&database_dir);
+            let metadata: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(database_dir.join("metadata")).unwrap())
+                    .unwrap();
+
+            let shards = metadata["snapshots"][0]["shards"].as_array().unwrap();
+
+            assert_eq!(shards.len(), 3);
+            assert!(!database_dir.join(&key).exists());
+            assert!(database_dir.join(format!("{}-shard-0", key)).exists());
+
+            let request_manager_restored = Database::new(options.set_restore(true)).run();
+
+            for person in &people {
+                assert_eq!(
+                    request_manager_restored
+                        .send_get(person.id.clone(), TransactionContext::default())
+                        .expect("should not timeout"),
+                    Some(person.clone())
+                );
+            }
+
+            let _ = request_manager_restored
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        #[test]
+        fn snapshots_are_diffed() {
+            let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+                .iter()
+                .collect();
+
+            let options = DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir.clone())))
+                .set_restore(false);
+
+            let request_manager = Database::new(options).run();
+
+            let dale = request_manager
+                .send_add(
+                    Person::new("Dale".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .unwrap();
+            let removed = request_manager
+                .send_add(
+                    Person::new("Removed".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .unwrap();
+
+            request_manager.send_snapshot_request().unwrap();
+            let before = promoted_snapshot_key(&database_dir);
+
+            request_manager
+                .send_update(
+                    dale.id.clone(),
+                    UpdatePersonData {
+                        email: UpdateStatement::Set("dale@x.com".to_string()),
+                        ..UpdatePersonData::default()
+                    },
+                    TransactionContext::default(),
+                )
+                .unwrap();
+            request_manager
+                .send_single_statement(
+                    Statement::Remove(removed.id.clone()),
+                    TransactionContext::default(),
+                )
+                .unwrap();
+
+            request_manager.send_snapshot_request().unwrap();
+            let after = promoted_snapshot_key(&database_dir);
+
+            let added = request_manager
+                .send_add(
+                    Person::new("Added".to_string(), None),
+                    TransactionContext::default(),
+                )
+                .unwrap();
+
+            let mut diffs: Vec<EntityDiff> = request_manager
+                .send_snapshot_diff_request(
+                    DiffSource::Snapshot(before.clone()),
+                    DiffSource::Snapshot(after.clone()),
+                )
+                .unwrap()
+                .collect();
+            diffs.sort_by(|a, b| a.id().cmp(b.id()));
+
+            let mut expected = vec![
+                EntityDiff::Changed {
+                    id: dale.id.clone(),
+                    fields: vec![FieldChange {
+                        field: "email".to_string(),
+                        before: serde_json::Value::Null,
+                        after: serde_json::json!("dale@x.com"),
+                    }],
+                },
+                EntityDiff::Removed { person: removed },
+            ];
+            expected.sort_by(|a, b| a.id().cmp(b.id()));
+
+            assert_eq!(diffs, expected);
+
+            // Only the person added since the snapshot differs from the live table
+            let diffs: Vec<EntityDiff> = request_manager
+                .send_snapshot_diff_request(DiffSource::Snapshot(after), DiffSource::Live)
+                .unwrap()
+                .collect();
+
+            assert_eq!(diffs, vec![EntityDiff::Added { person: added }]);
+
+            assert!(matches!(
+                request_manager.send_snapshot_diff_request(
+                    DiffSource::Snapshot("snapshot-missing".to_string()),
+                    DiffSource::Live
+                ),
+                Err(RequestManagerError::DatabaseErrorStatus(_))
+            ));
+
+            let _ = request_manager
+                .send_shutdown_request(ShutdownRequest::Coordinator)
+                .unwrap();
+        }
+
+        /// Key of the snapshot the metadata in the directory promotes
+        fn promoted_snapshot_key(metadata_dir: &std::path::Path) -> String {
+            let metadata: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(metadata_dir.join("metadata")).unwrap())
+                    .unwrap();
+
+            metadata["snapshots"][0]["key"]
+                .as_str()
+                .expect("a snapshot has been promoted")
+                .to_string()
+        }
     }
 }
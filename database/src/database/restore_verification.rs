@@ -0,0 +1,330 @@
+use std::fmt;
+
+use rand::seq::IteratorRandom;
+
+use crate::{
+    consts::consts::EntityId,
+    persistence::{storage::StorageResult, transaction::TransactionStatus},
+};
+
+use super::{database::Database, table::table::PersonTable};
+
+/// Defines the verification pass that runs after a restore, see `Database::verify_restore`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestoreVerificationOptions {
+    /// Rows compared against the re-derived table, every row is compared if the table is smaller. With 0 only the
+    /// row counts and sequences are compared
+    pub sample_size: usize,
+    /// Refuses to start the database if a row does not match, otherwise the mismatches are only logged
+    pub fail_on_mismatch: bool,
+}
+
+impl Default for RestoreVerificationOptions {
+    fn default() -> Self {
+        Self {
+            sample_size: 1000,
+            fail_on_mismatch: true,
+        }
+    }
+}
+
+impl RestoreVerificationOptions {
+    pub fn set_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    pub fn set_fail_on_mismatch(mut self, fail_on_mismatch: bool) -> Self {
+        self.fail_on_mismatch = fail_on_mismatch;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreVerification {
+    pub replayed_transactions: usize,
+    pub sampled_rows: usize,
+    pub mismatches: Vec<String>,
+}
+
+impl RestoreVerification {
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for RestoreVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[ReplayedTransactions: {}, SampledRows: {}, Mismatches: {}]",
+            self.replayed_transactions,
+            self.sampled_rows,
+            self.mismatches.len()
+        )?;
+
+        for mismatch in &self.mismatches {
+            write!(f, "\n  - {}", mismatch)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Re-derives the table from storage into a shadow table and compares a random sample of rows (every version)
+    /// against the restored table, a safety net for serialization and replay bugs. The snapshot and WAL are read
+    /// again and the WAL is replayed one transaction at a time, independently of the (parallel) replay of the
+    /// restore. Sequences and row counts are compared as well.
+    ///
+    /// The shadow table holds every row in memory until the verification finishes, and the restored table must not
+    /// be written to while it runs
+    pub(super) fn verify_restore(
+        &self,
+        options: &RestoreVerificationOptions,
+    ) -> StorageResult<RestoreVerification> {
+        let shadow_table = PersonTable::new()
+            .set_conflict_resolution(self.database_options.conflict_resolution.clone());
+        let mut verification = RestoreVerification::default();
+
+        let (_, _, archived_transactions) = self
+            .persistence
+            .snapshot_manager
+            .restore_snapshot(&shadow_table)?;
+
+        let transactions = self
+            .persistence
+            .transaction_wal
+            .restore(archived_transactions)?;
+
+        // Prepared transactions are applied by their commit record, see `Database::run`
+        for transaction in transactions.into_iter().filter(|transaction| {
+            matches!(
+                transaction.status,
+                TransactionStatus::Committed | TransactionStatus::CommitPrepared(_)
+            )
+        }) {
+            for statement in transaction.statements {
+                if let Err(e) = shadow_table.apply(statement, transaction.id.clone()) {
+                    verification.mismatches.push(format!(
+                        "Transaction {} does not replay onto the shadow table: {}",
+                        transaction.id, e
+                    ));
+
+                    return Ok(verification);
+                }
+            }
+
+            verification.replayed_transactions += 1;
+        }
+
+        let live_rows = self.person_table.person_rows.len();
+        let shadow_rows = shadow_table.person_rows.len();
+
+        if live_rows != shadow_rows {
+            verification.mismatches.push(format!(
+                "Row count mismatch, restored table: {}, shadow table: {}",
+                live_rows, shadow_rows
+            ));
+        }
+
+        let live_sequences = self.person_table.sequences.values();
+        let shadow_sequences = shadow_table.sequences.values();
+
+        if live_sequences != shadow_sequences {
+            verification.mismatches.push(format!(
+                "Sequence mismatch, restored table: {:?}, shadow table: {:?}",
+                live_sequences, shadow_sequences
+            ));
+        }
+
+        let sample: Vec<EntityId> = shadow_table
+            .person_rows
+            .iter()
+            .map(|row| row.key().clone())
+            .choose_multiple(&mut rand::thread_rng(), options.sample_size);
+
+        verification.sampled_rows = sample.len();
+
+        for id in sample {
+            let shadow_versions = shadow_table
+                .person_rows
+                .get(&id)
+                .map(|row| row.value().read().unwrap().history());
+
+            let live_versions = self
+                .person_table
+                .person_rows
+                .get(&id)
+                .map(|row| row.value().read().unwrap().history());
+
+            match (live_versions, shadow_versions) {
+                (Some(live), Some(shadow)) if live == shadow => {}
+                (Some(live), Some(shadow)) => verification.mismatches.push(format!(
+                    "Row {} differs, restored versions: {:?}, shadow versions: {:?}",
+                    id, live, shadow
+                )),
+                (None, _) => verification
+                    .mismatches
+                    .push(format!("Row {} is missing from the restored table", id)),
+                (Some(_), None) => {}
+            }
+        }
+
+        Ok(verification)
+    }
+
+    /// Runs `verify_restore` and logs the result, the database is not started if a row does not match and the
+    /// options require it
+    pub(super) fn run_restore_verification(&self, options: &RestoreVerificationOptions) {
+        let verification = match self.verify_restore(options) {
+            Ok(verification) => verification,
+            Err(e) if options.fail_on_mismatch => {
+                panic!("Unable to verify the restore: {}", e)
+            }
+            Err(e) => {
+                log::error!("Unable to verify the restore: {}", e);
+                return;
+            }
+        };
+
+        if verification.is_valid() {
+            log::info!("✅ Restore verified {}", verification);
+        } else if options.fail_on_mismatch {
+            panic!(
+                "The restored table does not match the table re-derived from storage {}",
+                verification
+            );
+        } else {
+            log::error!(
+                "The restored table does not match the table re-derived from storage {}",
+                verification
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::{
+        consts::consts::TransactionId,
+        database::{
+            commands::{ShutdownRequest, TransactionContext},
+            options::DatabaseOptions,
+            table::row::{UpdatePersonData, UpdateStatement},
+        },
+        model::{person::Person, statement::Statement},
+        persistence::storage::{file::FileOptions, StorageEngine},
+    };
+
+    use super::*;
+
+    #[test]
+    fn detects_rows_that_differ_from_storage() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let options = DatabaseOptions::default()
+            .set_storage_engine(StorageEngine::File(FileOptions::new(database_dir)));
+
+        // Given a database with rows in both the snapshot and the WAL
+        let request_manager = Database::new(options.clone().set_restore(false)).run();
+
+        let people: Vec<Person> = (0..3)
+            .map(|index| {
+                request_manager
+                    .send_add(
+                        Person::new(format!("Verified {}", index), None),
+                        TransactionContext::default(),
+                    )
+                    .expect("should not timeout")
+            })
+            .collect();
+
+        request_manager
+            .send_snapshot_request()
+            .expect("should snapshot");
+
+        request_manager
+            .send_update(
+                people[0].id.clone(),
+                UpdatePersonData {
+                    full_name: UpdateStatement::NoChanges,
+                    email: UpdateStatement::Set("verified@example.com".to_string()),
+                    ..UpdatePersonData::default()
+                },
+                TransactionContext::default(),
+            )
+            .expect("should not timeout");
+
+        request_manager
+            .send_next_val("orders".to_string(), TransactionContext::default())
+            .expect("should not timeout");
+
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .expect("should shut down");
+
+        // When the database is restarted with the verification, it starts
+        let verify = RestoreVerificationOptions::default();
+
+        let request_manager =
+            Database::new(options.clone().set_verify_restore(verify.clone())).run();
+
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .expect("should shut down");
+
+        // Then a restored row that was changed without going through storage is reported
+        let database = Database::new(options);
+        database.persistence.init().unwrap();
+
+        let (_, _, archived_transactions) = database
+            .persistence
+            .snapshot_manager
+            .restore_snapshot(&database.person_table)
+            .unwrap();
+
+        for transaction in database
+            .persistence
+            .transaction_wal
+            .restore(archived_transactions)
+            .unwrap()
+        {
+            for statement in transaction.statements {
+                database
+                    .person_table
+                    .apply(statement, transaction.id.clone())
+                    .unwrap();
+            }
+        }
+
+        let verification = database.verify_restore(&verify).unwrap();
+
+        assert!(verification.is_valid(), "{}", verification);
+        assert_eq!(verification.sampled_rows, 3);
+        assert_eq!(verification.replayed_transactions, 2);
+
+        database
+            .person_table
+            .apply(
+                Statement::Remove(people[1].id.clone()),
+                TransactionId::new_highest_transaction(),
+            )
+            .unwrap();
+
+        let verification = database.verify_restore(&verify).unwrap();
+
+        assert_eq!(verification.mismatches.len(), 1, "{}", verification);
+        assert!(
+            verification.mismatches[0].contains(&people[1].id.to_string()),
+            "{}",
+            verification
+        );
+    }
+}
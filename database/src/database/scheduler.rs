@@ -0,0 +1,260 @@
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    clock::{Clock, SystemClock},
+    request_manager::RequestManager,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum JobAction {
+    /// Snapshots the database, compressing the WAL
+    Snapshot,
+    /// Logs the database stats
+    DatabaseStats,
+    /// Drops the transactions covered by the latest snapshot from the WAL, without pausing the database
+    CompactWal,
+    /// Squashes the history of the table's rows, see `DatabaseOptions::set_history_squash`
+    SquashHistory,
+    /// Logs the index advisor's report, and creates the recommended indexes if
+    /// `DatabaseOptions::set_auto_create_indexes` is set
+    IndexAdvisor,
+}
+
+/// A recurring action that the database runs on a cron schedule
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JobDefinition {
+    pub name: String,
+    /// Cron expression including seconds, e.g. `0 0 * * * *` runs at the start of every hour (UTC)
+    pub schedule: String,
+    pub action: JobAction,
+}
+
+#[derive(Error, Debug)]
+pub enum ScheduleJobError {
+    #[error("Invalid schedule '{0}': {1}")]
+    InvalidSchedule(String, String),
+}
+
+struct ScheduledJob {
+    definition: JobDefinition,
+    schedule: Schedule,
+    next_run: Option<DateTime<Utc>>,
+}
+
+/// Runs jobs on a dedicated thread, jobs are sent to the database through a request manager like any other
+/// client so they are subject to the same synchronization as requests made externally
+pub struct Scheduler {
+    jobs: Arc<Mutex<BTreeMap<String, ScheduledJob>>>,
+    /// Jobs are due by the clock's time, the scheduler is woken whenever a mock clock is moved
+    clock: Arc<dyn Clock>,
+    /// Wakes the scheduler thread so it can recalculate when the next job is due
+    wake_sender: flume::Sender<()>,
+    wake_receiver: flume::Receiver<()>,
+    /// Set by `close`, the scheduler thread exits once it is woken
+    closed: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl Scheduler {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let (wake_sender, wake_receiver) = flume::unbounded();
+
+        clock.subscribe(wake_sender.clone());
+
+        Self {
+            jobs: Arc::new(Mutex::new(BTreeMap::new())),
+            clock,
+            wake_sender,
+            wake_receiver,
+            closed: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Schedules a job, replacing any job with the same name
+    pub fn schedule(&self, definition: JobDefinition) -> Result<(), ScheduleJobError> {
+        let schedule = Schedule::from_str(&definition.schedule).map_err(|e| {
+            ScheduleJobError::InvalidSchedule(definition.schedule.clone(), e.to_string())
+        })?;
+
+        let next_run = schedule.after(&self.clock.now_utc()).next();
+
+        self.jobs.lock().unwrap().insert(
+            definition.name.clone(),
+            ScheduledJob {
+                definition,
+                schedule,
+                next_run,
+            },
+        );
+
+        let _ = self.wake_sender.send(());
+
+        Ok(())
+    }
+
+    /// Returns whether a job was cancelled
+    pub fn cancel(&self, name: &str) -> bool {
+        let cancelled = self.jobs.lock().unwrap().remove(name).is_some();
+
+        let _ = self.wake_sender.send(());
+
+        cancelled
+    }
+
+    /// Returns every job and when it will next run
+    pub fn list(&self) -> Vec<(JobDefinition, Option<DateTime<Utc>>)> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|job| (job.definition.clone(), job.next_run))
+            .collect()
+    }
+
+    pub fn definitions(&self) -> Vec<JobDefinition> {
+        self.list()
+            .into_iter()
+            .map(|(definition, _)| definition)
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        self.jobs.lock().unwrap().clear();
+
+        let _ = self.wake_sender.send(());
+    }
+
+    /// Starts the scheduler thread, the thread exits once the scheduler is closed (see `close`) or dropped
+    pub fn start(&self, request_manager: RequestManager) {
+        let jobs = self.jobs.clone();
+        let wake_receiver = self.wake_receiver.clone();
+        let closed = self.closed.clone();
+        let clock = self.clock.clone();
+
+        let thread = thread::spawn(move || loop {
+            if closed.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let now = clock.now_utc();
+
+            for job in take_due_jobs(&jobs, &now) {
+                run_job(&request_manager, &job);
+            }
+
+            let next_run = jobs
+                .lock()
+                .unwrap()
+                .values()
+                .filter_map(|job| job.next_run)
+                .min();
+
+            let wake = match next_run {
+                Some(next_run) => {
+                    let timeout = (next_run - clock.now_utc()).to_std().unwrap_or_default();
+
+                    match wake_receiver.recv_timeout(timeout) {
+                        Err(flume::RecvTimeoutError::Disconnected) => Err(()),
+                        _ => Ok(()),
+                    }
+                }
+                None => wake_receiver.recv().map_err(|_| ()),
+            };
+
+            // The scheduler has been dropped along with the database
+            if wake.is_err() {
+                return;
+            }
+        });
+
+        *self.thread.lock().unwrap() = Some(thread);
+    }
+
+    /// Stops the scheduler thread and waits for it to exit, a job that is running is finished first
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        let _ = self.wake_sender.send(());
+
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            if thread.join().is_err() {
+                log::error!("The scheduler thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Returns the jobs that are due and moves them on to their next run
+fn take_due_jobs(
+    jobs: &Mutex<BTreeMap<String, ScheduledJob>>,
+    now: &DateTime<Utc>,
+) -> Vec<JobDefinition> {
+    let mut due_jobs = vec![];
+
+    for job in jobs.lock().unwrap().values_mut() {
+        if matches!(job.next_run, Some(next_run) if &next_run <= now) {
+            job.next_run = job.schedule.after(now).next();
+            due_jobs.push(job.definition.clone());
+        }
+    }
+
+    due_jobs
+}
+
+fn run_job(request_manager: &RequestManager, job: &JobDefinition) {
+    let result = match job.action {
+        JobAction::Snapshot => request_manager.send_snapshot_request(),
+        JobAction::CompactWal => request_manager.send_compact_wal_request(),
+        JobAction::SquashHistory => request_manager.send_squash_history_request().map(|report| {
+            report
+                .into_iter()
+                .map(|(key, value)| format!("[{}] {}", key, value))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }),
+        JobAction::IndexAdvisor => request_manager.send_index_advisor_request().map(|report| {
+            report
+                .into_iter()
+                .map(|(key, value)| format!("[{}] {}", key, value))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }),
+        JobAction::DatabaseStats => request_manager.send_info_request().map(|info| {
+            info.into_iter()
+                .map(|(key, value)| format!("[{}] {}", key, value))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }),
+    };
+
+    match result {
+        Ok(status) => log::info!("⏰ Ran job {}: {}", job.name, status),
+        Err(e) => log::warn!("⏰ Job {} failed: {}", job.name, e),
+    }
+}
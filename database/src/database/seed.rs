@@ -0,0 +1,319 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    consts::consts::EntityId,
+    model::{person::Person, statement::Statement},
+};
+
+use super::{
+    commands::TransactionContext,
+    request_manager::{RequestManager, RequestManagerError},
+    table::{
+        row::{UpdateListStatement, UpdatePersonData, UpdateStatement},
+        view::PersonField,
+    },
+};
+
+/// People added per transaction, a large fixture does not monopolize a worker
+const SEED_CHUNK_SIZE: usize = 500;
+
+#[derive(Error, Debug)]
+pub enum SeedError {
+    #[error("Unable to read seed file: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid JSON seed file: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid TOML seed file: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Seed files must be .json or .toml, got: {0}")]
+    UnknownFormat(String),
+
+    #[error("Unable to apply the seed: {0}")]
+    Apply(#[from] RequestManagerError),
+}
+
+/// A mutation of the seed's history. Unlike statements (see `Statement`) mutations are written the same way in
+/// JSON and TOML, e.g. `{ op = "update", id = "luke", email = "luke@rebellion.org" }`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SeedMutation {
+    Add(Person),
+    /// Sets the fields that are given, the other fields are unchanged
+    Update {
+        id: EntityId,
+        full_name: Option<String>,
+        email: Option<String>,
+        phone_numbers: Option<Vec<String>>,
+    },
+    Remove {
+        id: EntityId,
+    },
+    Rename {
+        from: EntityId,
+        to: EntityId,
+    },
+    /// Merges `from` into `into`, `fields` are taken from `from`
+    Merge {
+        from: EntityId,
+        into: EntityId,
+        #[serde(default)]
+        fields: Vec<PersonField>,
+    },
+}
+
+impl SeedMutation {
+    pub fn to_statement(&self) -> Statement {
+        let set = |value: &Option<String>| match value {
+            Some(value) => UpdateStatement::Set(value.clone()),
+            None => UpdateStatement::NoChanges,
+        };
+
+        match self {
+            SeedMutation::Add(person) => Statement::Add(person.clone()),
+            SeedMutation::Update {
+                id,
+                full_name,
+                email,
+                phone_numbers,
+            } => Statement::Update(
+                id.clone(),
+                UpdatePersonData {
+                    full_name: set(full_name),
+                    email: set(email),
+                    phone_numbers: match phone_numbers {
+                        Some(phone_numbers) => UpdateListStatement::Set(phone_numbers.clone()),
+                        None => UpdateListStatement::NoChanges,
+                    },
+                    ..Default::default()
+                },
+            ),
+            SeedMutation::Remove { id } => Statement::Remove(id.clone()),
+            SeedMutation::Rename { from, to } => Statement::Rename(from.clone(), to.clone()),
+            SeedMutation::Merge { from, into, fields } => {
+                Statement::Merge(from.clone(), into.clone(), fields.clone())
+            }
+        }
+    }
+}
+
+/// A transaction of the seed's history
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SeedTransaction {
+    pub mutations: Vec<SeedMutation>,
+}
+
+/// People (and the mutations that build up their version history) loaded into a fresh database, for tests,
+/// benches and demos, see `DatabaseOptions::set_seed`
+///
+/// ```toml
+/// [[people]]
+/// id = "luke"
+/// full_name = "Luke Skywalker"
+///
+/// [[history]]
+/// mutations = [{ op = "update", id = "luke", email = "luke@rebellion.org" }]
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Seed {
+    /// Added before the history
+    #[serde(default)]
+    pub people: Vec<Person>,
+    /// Applied in order once the people are added, each transaction is a version of the people it mutates
+    #[serde(default)]
+    pub history: Vec<SeedTransaction>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SeedReport {
+    pub people: usize,
+    pub transactions: usize,
+}
+
+impl Seed {
+    pub fn new(people: Vec<Person>) -> Self {
+        Seed {
+            people,
+            history: vec![],
+        }
+    }
+
+    /// `count` people with the ids `0..count` and the full name `full_name`
+    pub fn generated(count: usize, full_name: &str) -> Self {
+        Seed::new(
+            (0..count)
+                .map(|i| Person {
+                    id: EntityId(i.to_string()),
+                    full_name: full_name.to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                })
+                .collect(),
+        )
+    }
+
+    pub fn add_history(mut self, mutations: Vec<SeedMutation>) -> Self {
+        self.history.push(SeedTransaction { mutations });
+        self
+    }
+
+    /// Reads a JSON or TOML seed file, the format is chosen by the file's extension
+    pub fn from_file(path: &Path) -> Result<Self, SeedError> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        if !matches!(extension, Some("json") | Some("toml")) {
+            return Err(SeedError::UnknownFormat(path.display().to_string()));
+        }
+
+        let contents = fs::read_to_string(path)?;
+
+        match extension {
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            _ => Ok(toml::from_str(&contents)?),
+        }
+    }
+
+    /// Adds the people and applies the history, stops at the first transaction that rolls back
+    pub fn apply(&self, request_manager: &RequestManager) -> Result<SeedReport, SeedError> {
+        let mut report = SeedReport::default();
+
+        for people in self.people.chunks(SEED_CHUNK_SIZE) {
+            request_manager.send_transaction(
+                people.iter().cloned().map(Statement::Add).collect(),
+                TransactionContext::default(),
+            )?;
+
+            report.people += people.len();
+            report.transactions += 1;
+        }
+
+        for transaction in &self.history {
+            request_manager.send_transaction(
+                transaction
+                    .mutations
+                    .iter()
+                    .map(SeedMutation::to_statement)
+                    .collect(),
+                TransactionContext::default(),
+            )?;
+
+            report.transactions += 1;
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::database::fixtures::TestDatabase;
+
+    use super::*;
+
+    const TOML_SEED: &str = r#"
+[[people]]
+id = "luke"
+full_name = "Luke Skywalker"
+
+[[history]]
+mutations = [{ op = "update", id = "luke", email = "luke@rebellion.org" }]
+
+[[history]]
+mutations = [{ op = "rename", from = "luke", to = "skywalker" }]
+"#;
+
+    fn write_seed(extension: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}.{}", Uuid::new_v4(), extension));
+
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn seeds_people_and_history_from_toml() {
+        let path = write_seed("toml", TOML_SEED);
+        let seed = Seed::from_file(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let database = TestDatabase::new();
+
+        assert_eq!(
+            seed.apply(&database).unwrap(),
+            SeedReport {
+                people: 1,
+                transactions: 3
+            }
+        );
+
+        let person = database
+            .send_get(
+                EntityId("skywalker".to_string()),
+                TransactionContext::default(),
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(person.email, Some("luke@rebellion.org".to_string()));
+
+        // The lineage follows the rename back to the add and the update
+        let lineage = database
+            .send_single_statement(
+                Statement::Lineage(EntityId("skywalker".to_string())),
+                TransactionContext::default(),
+            )
+            .unwrap()
+            .list_version();
+
+        assert!(lineage.len() >= 3, "{:?}", lineage);
+    }
+
+    #[test]
+    fn only_fresh_databases_are_seeded() {
+        let database = TestDatabase::with_options(
+            TestDatabase::default_options().set_seed(Seed::generated(3, "Seeded")),
+        );
+
+        let count = || {
+            database
+                .send_list(None, TransactionContext::default())
+                .unwrap()
+                .len()
+        };
+
+        assert_eq!(count(), 3);
+
+        // The restored people are not seeded again
+        database.restart().unwrap();
+
+        assert_eq!(count(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_seeds() {
+        let path = write_seed(
+            "json",
+            r#"{ "history": [{ "mutations": [{ "op": "get", "id": "luke" }] }] }"#,
+        );
+        let result = Seed::from_file(&path);
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(result, Err(SeedError::Json(_))));
+
+        assert!(matches!(
+            Seed::from_file(Path::new("seed.yaml")),
+            Err(SeedError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    consts::consts::TransactionId,
+    model::statement::{Statement, StatementResult},
+};
+
+use super::table::{
+    planner::ReadPath,
+    table::{PersonTable, ReadOptions},
+};
+
+/// Read statements that are duplicated to a second read path, see `DatabaseOptions::set_shadow_reads`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowReadOptions {
+    /// Fraction of the shadowed statements that are duplicated, between 0 (none) and 1 (all)
+    pub sample_rate: f64,
+    /// The path the duplicates are read through, the users are always answered by the planned path
+    pub read_path: ReadPath,
+}
+
+impl ShadowReadOptions {
+    pub fn new(sample_rate: f64) -> Self {
+        Self {
+            sample_rate,
+            read_path: ReadPath::FullScan,
+        }
+    }
+}
+
+/// Duplicates a sample of the read statements to a second read path and compares the results, so that a rewrite
+/// of a query engine path (e.g. a new index) can be rolled out while the users are still answered by the
+/// existing one. Discrepancies are logged and counted in the database stats
+pub struct ShadowReads {
+    options: ShadowReadOptions,
+    compared: AtomicUsize,
+    mismatched: AtomicUsize,
+}
+
+impl ShadowReads {
+    pub fn new(options: ShadowReadOptions) -> Self {
+        Self {
+            options,
+            compared: AtomicUsize::new(0),
+            mismatched: AtomicUsize::new(0),
+        }
+    }
+
+    /// Only the statements whose results depend on the read path are shadowed
+    pub fn is_shadowed(&self, statement: &Statement) -> bool {
+        matches!(
+            statement,
+            Statement::List(_) | Statement::Count(_) | Statement::ExecutePreparedQuery(_, _)
+        ) && rand::random::<f64>() < self.options.sample_rate
+    }
+
+    /// Reads the statement through the shadow path and compares it to the result the request was answered with
+    pub fn compare(
+        &self,
+        table: &PersonTable,
+        statement: Statement,
+        transaction_id: &TransactionId,
+        read_options: &ReadOptions,
+        result: &Result<StatementResult, String>,
+    ) {
+        let shadow_options = ReadOptions {
+            read_path: self.options.read_path,
+            ..read_options.clone()
+        };
+
+        let kind: &'static str = (&statement).into();
+
+        let shadow_result = table
+            .query_statement_with_options(statement, transaction_id, &shadow_options)
+            .map_err(|e| e.to_string());
+
+        // A request that was cancelled in between reads is not a discrepancy
+        if read_options.cancellation.is_cancelled() {
+            return;
+        }
+
+        self.compared.fetch_add(1, Ordering::Relaxed);
+
+        if shadow_result != *result {
+            self.mismatched.fetch_add(1, Ordering::Relaxed);
+
+            log::warn!(
+                transaction_id = transaction_id.to_number();
+                "🔀 Shadow read of {} through {:?} differs. Planned: {}, shadow: {}",
+                kind,
+                self.options.read_path,
+                describe(result),
+                describe(&shadow_result)
+            );
+        }
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "ShadowReadsCompared".to_string(),
+                self.compared.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "ShadowReadsMismatched".to_string(),
+                self.mismatched.load(Ordering::Relaxed).to_string(),
+            ),
+        ]
+    }
+}
+
+/// Row count or error of a result, the rows themselves are not logged
+fn describe(result: &Result<StatementResult, String>) -> String {
+    match result {
+        Ok(result) => format!("{} rows", result.row_count()),
+        Err(e) => format!("error '{}'", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        consts::consts::EntityId,
+        database::table::query::{QueryMatch, QueryPersonData},
+        model::person::Person,
+    };
+
+    use super::*;
+
+    fn table_with(names: &[&str]) -> PersonTable {
+        let table = PersonTable::new();
+
+        for (i, name) in names.iter().enumerate() {
+            table
+                .apply(
+                    Statement::Add(Person {
+                        id: EntityId(i.to_string()),
+                        full_name: name.to_string(),
+                        email: None,
+                        address: None,
+                        phone_numbers: vec![],
+                        attachments: vec![],
+                    }),
+                    TransactionId(1),
+                )
+                .unwrap();
+        }
+
+        table
+    }
+
+    #[test]
+    fn counts_shadow_reads_that_differ() {
+        let names: Vec<String> = (0..50).map(|i| format!("Person {}", i)).collect();
+        let table = table_with(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        let shadow = ShadowReads::new(ShadowReadOptions::new(1.0));
+
+        let statement = Statement::List(Some(QueryPersonData {
+            full_name: QueryMatch::Value("Person 7".to_string()),
+            ..Default::default()
+        }));
+
+        assert!(shadow.is_shadowed(&statement));
+        assert!(!shadow.is_shadowed(&Statement::Get(EntityId("7".to_string()))));
+
+        let read_options = ReadOptions::default();
+        let result = table
+            .query_statement_with_options(statement.clone(), &TransactionId(1), &read_options)
+            .map_err(|e| e.to_string());
+
+        // The index and the full scan agree
+        shadow.compare(
+            &table,
+            statement.clone(),
+            &TransactionId(1),
+            &read_options,
+            &result,
+        );
+
+        // A planned read that lost a row is a discrepancy
+        shadow.compare(
+            &table,
+            statement,
+            &TransactionId(1),
+            &read_options,
+            &Ok(StatementResult::List(vec![])),
+        );
+
+        assert_eq!(
+            shadow.get_stats(),
+            vec![
+                ("ShadowReadsCompared".to_string(), "2".to_string()),
+                ("ShadowReadsMismatched".to_string(), "1".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,719 @@
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    consts::consts::EntityId,
+    database::table::{query::QueryPersonData, row::UpdatePersonData},
+    model::{
+        person::Person,
+        statement::{Statement, StatementResult},
+    },
+};
+
+use super::{
+    commands::TransactionContext,
+    coordinator::CoordinatorLog,
+    request_manager::{RequestManager, RequestManagerError},
+};
+
+#[derive(Error, Debug)]
+pub enum ShardRouterError {
+    #[error("There are no shards in the shard map")]
+    NoShards,
+
+    #[error("Shard {0} is already in the shard map")]
+    DuplicateShard(String),
+
+    #[error("Shard {0} is not in the shard map")]
+    UnknownShard(String),
+
+    /// Without a coordinator log transactions run on a single shard, a single statement always does
+    #[error("Transaction touches ids owned by shards {0} and {1}")]
+    CrossShardTransaction(String, String),
+
+    #[error("{0} statements are not routed by id, use the router's list methods instead")]
+    UnroutableStatement(&'static str),
+
+    #[error("Shard {0}: {1}")]
+    Shard(String, RequestManagerError),
+
+    #[error("Unable to write the commit decision to the coordinator log: {0}")]
+    CoordinatorLog(std::io::Error),
+
+    #[error("Recovering in-doubt transactions requires a coordinator log")]
+    NoCoordinatorLog,
+}
+
+/// Stable across processes and releases (unlike `DefaultHasher`), every router must place ids the same way
+fn ring_position(key: &str) -> u64 {
+    // FNV-1a
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Consistent hashing ring of shard names. Each shard owns the hash ranges that end at one of its virtual
+/// nodes, adding or removing a shard only moves the ids in the ranges it gains or loses
+#[derive(Debug, Clone)]
+pub struct ShardMap {
+    ring: BTreeMap<u64, String>,
+    virtual_nodes: usize,
+}
+
+impl ShardMap {
+    /// More virtual nodes spread ids more evenly between the shards
+    pub fn new(virtual_nodes: usize) -> Self {
+        Self {
+            ring: BTreeMap::new(),
+            virtual_nodes: virtual_nodes.max(1),
+        }
+    }
+
+    pub fn add_shard(&mut self, name: &str) {
+        for node in 0..self.virtual_nodes {
+            self.ring.insert(
+                ring_position(&format!("{}#{}", name, node)),
+                name.to_string(),
+            );
+        }
+    }
+
+    pub fn remove_shard(&mut self, name: &str) {
+        self.ring.retain(|_, shard| shard != name);
+    }
+
+    /// The shard that owns the id, the first virtual node at or after the id's position wrapping around the ring
+    pub fn owner(&self, id: &EntityId) -> Option<&str> {
+        let position = ring_position(&id.0);
+
+        self.ring
+            .range(position..)
+            .chain(self.ring.range(..position))
+            .next()
+            .map(|(_, shard)| shard.as_str())
+    }
+}
+
+impl Default for ShardMap {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+/// Routes requests to independent databases (shards) by id. Reads and writes of an id go to the shard that owns
+/// it, lists are sent to every shard and gathered. Mirrors the `RequestManager` entity methods so it can stand in
+/// for one as a proxy client
+///
+/// Note: rebalancing moves the latest state of each row, the version history stays on the previous shard
+pub struct ShardRouter {
+    map: ShardMap,
+    shards: HashMap<String, RequestManager>,
+    coordinator_log: Option<CoordinatorLog>,
+}
+
+impl ShardRouter {
+    pub fn new(map: ShardMap) -> Self {
+        Self {
+            map,
+            shards: HashMap::new(),
+            coordinator_log: None,
+        }
+    }
+
+    /// Enables transactions that span shards, they are committed with a two-phase commit
+    pub fn set_coordinator_log(mut self, coordinator_log: CoordinatorLog) -> Self {
+        self.coordinator_log = Some(coordinator_log);
+        self
+    }
+
+    pub fn shards(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.shards.keys().collect();
+        names.sort();
+        names
+    }
+
+    /// Adds a shard to the shard map and moves the rows it now owns from the other shards, returns the number of
+    /// rows that were moved
+    pub fn add_shard(
+        &mut self,
+        name: String,
+        request_manager: RequestManager,
+    ) -> Result<usize, ShardRouterError> {
+        if self.shards.contains_key(&name) {
+            return Err(ShardRouterError::DuplicateShard(name));
+        }
+
+        self.map.add_shard(&name);
+        self.shards.insert(name.clone(), request_manager);
+
+        let mut moved = 0;
+
+        for shard in self.shards.keys().filter(|shard| **shard != name) {
+            moved += self.rebalance(shard)?;
+        }
+
+        Ok(moved)
+    }
+
+    /// Removes a shard from the shard map once its rows have been moved to their new owners, returns the
+    /// removed shard's request manager and the number of rows that were moved
+    pub fn remove_shard(
+        &mut self,
+        name: &str,
+    ) -> Result<(RequestManager, usize), ShardRouterError> {
+        if !self.shards.contains_key(name) {
+            return Err(ShardRouterError::UnknownShard(name.to_string()));
+        }
+
+        if self.shards.len() == 1 {
+            return Err(ShardRouterError::NoShards);
+        }
+
+        self.map.remove_shard(name);
+
+        let moved = self.rebalance(name)?;
+
+        let request_manager = self
+            .shards
+            .remove(name)
+            .expect("Shard should exist, it was checked above");
+
+        Ok((request_manager, moved))
+    }
+
+    /// Exports the rows of the shard that are owned by other shards and imports them into their owner. Rows are
+    /// added to their owner before they are removed from the shard so they stay readable while they are moved
+    fn rebalance(&self, shard: &str) -> Result<usize, ShardRouterError> {
+        let rows = self
+            .shard(shard)?
+            .send_list(None, TransactionContext::default())
+            .map_err(|e| ShardRouterError::Shard(shard.to_string(), e))?;
+
+        let mut moved = 0;
+
+        for person in rows {
+            let (owner, owner_request_manager) = self.route(&person.id)?;
+
+            if owner == shard {
+                continue;
+            }
+
+            let id = person.id.clone();
+
+            owner_request_manager
+                .send_add(person, TransactionContext::default())
+                .map_err(|e| ShardRouterError::Shard(owner.to_string(), e))?;
+
+            self.shard(shard)?
+                .send_single_statement(Statement::Remove(id), TransactionContext::default())
+                .map_err(|e| ShardRouterError::Shard(shard.to_string(), e))?;
+
+            moved += 1;
+        }
+
+        if moved > 0 {
+            log::info!("Rebalanced {} rows off shard {}", moved, shard);
+        }
+
+        Ok(moved)
+    }
+
+    fn shard(&self, name: &str) -> Result<&RequestManager, ShardRouterError> {
+        self.shards
+            .get(name)
+            .ok_or_else(|| ShardRouterError::UnknownShard(name.to_string()))
+    }
+
+    /// The owning shard's name and request manager
+    fn route(&self, id: &EntityId) -> Result<(&str, &RequestManager), ShardRouterError> {
+        let owner = self.map.owner(id).ok_or(ShardRouterError::NoShards)?;
+
+        Ok((owner, self.shard(owner)?))
+    }
+
+    fn send_routed<T>(
+        &self,
+        id: &EntityId,
+        send: impl FnOnce(&RequestManager) -> Result<T, RequestManagerError>,
+    ) -> Result<T, ShardRouterError> {
+        let (owner, request_manager) = self.route(id)?;
+
+        send(request_manager).map_err(|e| ShardRouterError::Shard(owner.to_string(), e))
+    }
+
+    pub fn send_add(
+        &self,
+        person: Person,
+        transaction_context: TransactionContext,
+    ) -> Result<Person, ShardRouterError> {
+        let id = person.id.clone();
+
+        self.send_routed(&id, |rm| rm.send_add(person, transaction_context))
+    }
+
+    pub fn send_update(
+        &self,
+        id: EntityId,
+        person_update: UpdatePersonData,
+        transaction_context: TransactionContext,
+    ) -> Result<Person, ShardRouterError> {
+        self.send_routed(&id.clone(), |rm| {
+            rm.send_update(id, person_update, transaction_context)
+        })
+    }
+
+    pub fn send_get(
+        &self,
+        id: EntityId,
+        transaction_context: TransactionContext,
+    ) -> Result<Option<Person>, ShardRouterError> {
+        self.send_routed(&id.clone(), |rm| rm.send_get(id, transaction_context))
+    }
+
+    /// Scatter-gathers the list, every shard is queried concurrently. People are ordered by id
+    pub fn send_list(
+        &self,
+        query: Option<QueryPersonData>,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<Person>, ShardRouterError> {
+        let tasks: Vec<_> = self
+            .shards()
+            .into_iter()
+            .map(|name| {
+                let task =
+                    self.shards[name].send_list_task(query.clone(), transaction_context.clone());
+
+                (name, task)
+            })
+            .collect();
+
+        let mut people = vec![];
+
+        for (name, task) in tasks {
+            people.extend(
+                task.get()
+                    .map_err(|e| ShardRouterError::Shard(name.to_string(), e))?,
+            );
+        }
+
+        people.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(people)
+    }
+
+    /// Runs the statements on the shard that owns the ids they touch. If the statements span shards and a
+    /// coordinator log is set they are committed atomically with a two-phase commit, see `CoordinatorLog`.
+    /// Statements that are not keyed by an id (e.g. lists, views and sequences) cannot be routed
+    ///
+    /// Note: two-phase commits run with the default transaction context on each shard
+    pub fn send_transaction(
+        &self,
+        statements: Vec<Statement>,
+        transaction_context: TransactionContext,
+    ) -> Result<Vec<StatementResult>, ShardRouterError> {
+        // Statement indexes grouped by the shard that owns them
+        let mut participants: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+
+        for (index, statement) in statements.iter().enumerate() {
+            participants
+                .entry(self.statement_owner(statement)?)
+                .or_default()
+                .push(index);
+        }
+
+        let shards: Vec<&str> = participants.keys().copied().collect();
+
+        match (shards.as_slice(), &self.coordinator_log) {
+            ([], _) => Err(ShardRouterError::NoShards),
+            ([owner], _) => self
+                .shard(owner)?
+                .send_transaction(statements, transaction_context)
+                .map_err(|e| ShardRouterError::Shard(owner.to_string(), e)),
+            ([first, second, ..], None) => Err(ShardRouterError::CrossShardTransaction(
+                first.to_string(),
+                second.to_string(),
+            )),
+            (_, Some(coordinator_log)) => {
+                self.two_phase_commit(coordinator_log, statements, participants)
+            }
+        }
+    }
+
+    /// The shard that owns every id the statement touches, a statement cannot be split between shards
+    fn statement_owner(&self, statement: &Statement) -> Result<&str, ShardRouterError> {
+        let ids = match statement {
+            Statement::Get(id)
+            | Statement::GetVersion(id, _)
+            | Statement::Exists(id)
+            | Statement::GetAttachment(id, _)
+            | Statement::Lineage(id)
+            | Statement::History(id, _)
+            | Statement::Meta(id) => vec![id],
+            Statement::GetManyAtTransaction(ids, _) => ids.iter().collect(),
+            Statement::List(_)
+            | Statement::Count(_)
+            | Statement::ListPage(_, _)
+            | Statement::Scan { .. }
+            | Statement::ListLatestVersions
+            | Statement::QueryView(_)
+            | Statement::ExecutePreparedQuery(_, _)
+            | Statement::TableVersion
+            | Statement::QuerySystemTable(_)
+            | Statement::NextVal(_)
+            | Statement::Enqueue(_, _)
+            | Statement::ReadOutbox(_, _)
+            | Statement::AckOutbox(_) => {
+                return Err(ShardRouterError::UnroutableStatement(statement.into()))
+            }
+            _ => statement.mutated_ids(),
+        };
+
+        let mut owner: Option<&str> = None;
+
+        for id in ids {
+            let (shard, _) = self.route(id)?;
+
+            match owner {
+                Some(owner) if owner != shard => {
+                    return Err(ShardRouterError::CrossShardTransaction(
+                        owner.to_string(),
+                        shard.to_string(),
+                    ))
+                }
+                _ => owner = Some(shard),
+            }
+        }
+
+        owner.ok_or(ShardRouterError::NoShards)
+    }
+
+    /// Prepares the statements on every participant, logs the commit decision and then commits each participant.
+    /// If a prepare fails the prepared participants are aborted. Once the decision is logged the transaction is
+    /// committed, participants that fail to commit are resolved by `recover`
+    fn two_phase_commit(
+        &self,
+        coordinator_log: &CoordinatorLog,
+        statements: Vec<Statement>,
+        participants: BTreeMap<&str, Vec<usize>>,
+    ) -> Result<Vec<StatementResult>, ShardRouterError> {
+        let global_id = Uuid::new_v4().to_string();
+
+        let abort = |prepared: &[&str]| {
+            for shard in prepared {
+                if let Err(e) = self.shards[*shard].send_abort_prepared_request(global_id.clone()) {
+                    log::warn!(
+                        "Unable to abort transaction {} on shard {}, it is aborted on recovery: {}",
+                        global_id,
+                        shard,
+                        e
+                    );
+                }
+            }
+        };
+
+        let mut prepared: Vec<&str> = vec![];
+
+        for (shard, indexes) in &participants {
+            let shard_statements = indexes
+                .iter()
+                .map(|index| statements[*index].clone())
+                .collect();
+
+            let result = self
+                .shard(shard)?
+                .send_prepare_request(global_id.clone(), shard_statements);
+
+            if let Err(e) = result {
+                abort(&prepared);
+                return Err(ShardRouterError::Shard(shard.to_string(), e));
+            }
+
+            prepared.push(shard);
+        }
+
+        if let Err(e) = coordinator_log.log_commit(&global_id) {
+            abort(&prepared);
+            return Err(ShardRouterError::CoordinatorLog(e));
+        }
+
+        let mut results: Vec<(usize, StatementResult)> = vec![];
+
+        for (shard, indexes) in participants {
+            let shard_results = self
+                .shard(shard)?
+                .send_commit_prepared_request(global_id.clone())
+                .map_err(|e| ShardRouterError::Shard(shard.to_string(), e))?;
+
+            results.extend(indexes.into_iter().zip(shard_results));
+        }
+
+        results.sort_by_key(|(index, _)| *index);
+
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+
+    /// Resolves the in-doubt (prepared) transactions on every shard, e.g. after the router or a shard restarted.
+    /// Transactions with a commit decision in the coordinator log are committed, the others are aborted. Returns
+    /// the number of resolved transactions
+    pub fn recover(&self) -> Result<usize, ShardRouterError> {
+        let coordinator_log = self
+            .coordinator_log
+            .as_ref()
+            .ok_or(ShardRouterError::NoCoordinatorLog)?;
+
+        let mut resolved = 0;
+
+        for shard in self.shards() {
+            let request_manager = &self.shards[shard];
+
+            let in_doubt = request_manager
+                .send_list_prepared_request()
+                .map_err(|e| ShardRouterError::Shard(shard.to_string(), e))?;
+
+            for (global_id, _) in in_doubt {
+                let result = match coordinator_log.is_committed(&global_id) {
+                    true => request_manager
+                        .send_commit_prepared_request(global_id.clone())
+                        .map(|_| "committed"),
+                    false => request_manager
+                        .send_abort_prepared_request(global_id.clone())
+                        .map(|_| "aborted"),
+                };
+
+                let outcome = result.map_err(|e| ShardRouterError::Shard(shard.to_string(), e))?;
+
+                log::info!(
+                    "Recovered in-doubt transaction {} on shard {}: {}",
+                    global_id,
+                    shard,
+                    outcome
+                );
+
+                resolved += 1;
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        database::{commands::ShutdownRequest, database::Database, options::DatabaseOptions},
+        persistence::storage::{file::FileOptions, StorageEngine},
+    };
+
+    use super::*;
+
+    #[test]
+    fn adding_a_shard_only_moves_ids_to_it() {
+        let mut map = ShardMap::default();
+        map.add_shard("a");
+        map.add_shard("b");
+
+        let ids: Vec<EntityId> = (0..1_000).map(|_| EntityId::new()).collect();
+        let before: Vec<String> = ids
+            .iter()
+            .map(|id| map.owner(id).unwrap().to_string())
+            .collect();
+
+        map.add_shard("c");
+
+        for (id, previous_owner) in ids.iter().zip(before) {
+            let owner = map.owner(id).unwrap();
+            assert!(owner == previous_owner || owner == "c");
+        }
+    }
+
+    #[test]
+    fn routes_and_rebalances_rows() {
+        let mut router = ShardRouter::new(ShardMap::default());
+
+        for name in ["a", "b"] {
+            router
+                .add_shard(
+                    name.to_string(),
+                    Database::new(DatabaseOptions::new_test()).run(),
+                )
+                .unwrap();
+        }
+
+        let people: Vec<Person> = (0..100)
+            .map(|i| {
+                router
+                    .send_add(
+                        Person::new(format!("Person {}", i), None),
+                        TransactionContext::default(),
+                    )
+                    .unwrap()
+            })
+            .collect();
+
+        let count_rows = |router: &ShardRouter| {
+            router
+                .send_list(None, TransactionContext::default())
+                .unwrap()
+                .len()
+        };
+
+        assert_eq!(count_rows(&router), 100);
+
+        let moved = router
+            .add_shard(
+                "c".to_string(),
+                Database::new(DatabaseOptions::new_test()).run(),
+            )
+            .unwrap();
+
+        assert!(moved > 0);
+        assert_eq!(count_rows(&router), 100);
+
+        let (_, moved) = router.remove_shard("a").unwrap();
+
+        assert!(moved > 0);
+        assert_eq!(count_rows(&router), 100);
+
+        for person in &people {
+            assert_eq!(
+                router
+                    .send_get(person.id.clone(), TransactionContext::default())
+                    .unwrap()
+                    .as_ref(),
+                Some(person)
+            );
+        }
+
+        let cross_shard = people
+            .iter()
+            .find(|person| router.map.owner(&person.id) != router.map.owner(&people[0].id))
+            .unwrap();
+
+        let error = router
+            .send_transaction(
+                vec![
+                    Statement::Get(people[0].id.clone()),
+                    Statement::Get(cross_shard.id.clone()),
+                ],
+                TransactionContext::default(),
+            )
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            error,
+            ShardRouterError::CrossShardTransaction(_, _)
+        ));
+    }
+
+    #[test]
+    fn cross_shard_transactions_commit_and_recover() {
+        let dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let shard_options = || {
+            DatabaseOptions::default()
+                .set_storage_engine(StorageEngine::File(FileOptions::new(dir.join("a"))))
+        };
+
+        let mut router = ShardRouter::new(ShardMap::default())
+            .set_coordinator_log(CoordinatorLog::open(dir.join("coordinator")).unwrap());
+
+        router
+            .add_shard(
+                "a".to_string(),
+                Database::new(shard_options().set_restore(false)).run(),
+            )
+            .unwrap();
+        router
+            .add_shard(
+                "b".to_string(),
+                Database::new(DatabaseOptions::new_test()).run(),
+            )
+            .unwrap();
+
+        let person_on = |shard: &str| loop {
+            let person = Person::new(format!("Owned by {}", shard), None);
+
+            if router.map.owner(&person.id) == Some(shard) {
+                return person;
+            }
+        };
+
+        let (on_a, on_b) = (person_on("a"), person_on("b"));
+
+        let results = router
+            .send_transaction(
+                vec![Statement::Add(on_b.clone()), Statement::Add(on_a.clone())],
+                TransactionContext::default(),
+            )
+            .unwrap();
+
+        assert!(matches!(&results[0], StatementResult::Single(p) if p == &on_b));
+        assert!(matches!(&results[1], StatementResult::Single(p) if p == &on_a));
+
+        // -- In-doubt transactions on shard a, one with a commit decision (stored in the snapshot) and one without
+        let (committed, aborted) = (person_on("a"), person_on("a"));
+        let shard_a = &router.shards["a"];
+
+        shard_a
+            .send_prepare_request(
+                "committed".to_string(),
+                vec![Statement::Add(committed.clone())],
+            )
+            .unwrap();
+        router
+            .coordinator_log
+            .as_ref()
+            .unwrap()
+            .log_commit("committed")
+            .unwrap();
+        shard_a.send_snapshot_request().unwrap();
+
+        shard_a
+            .send_prepare_request("aborted".to_string(), vec![Statement::Add(aborted.clone())])
+            .unwrap();
+
+        // Prepared rows are locked
+        assert!(shard_a
+            .send_add(aborted.clone(), TransactionContext::default())
+            .is_err());
+
+        shard_a
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .unwrap();
+
+        router.shards.insert(
+            "a".to_string(),
+            Database::new(shard_options().set_restore(true)).run(),
+        );
+
+        assert_eq!(
+            router.shards["a"]
+                .send_list_prepared_request()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(router.recover().unwrap(), 2);
+        assert!(router.shards["a"]
+            .send_list_prepared_request()
+            .unwrap()
+            .is_empty());
+
+        let get = |id: &EntityId| router.send_get(id.clone(), TransactionContext::default());
+
+        assert_eq!(get(&on_a.id).unwrap(), Some(on_a.clone()));
+        assert_eq!(get(&on_b.id).unwrap(), Some(on_b.clone()));
+        assert_eq!(get(&committed.id).unwrap(), Some(committed.clone()));
+        // The aborted row was never added
+        assert_eq!(get(&aborted.id).unwrap(), None);
+
+        let _ = router.shards["a"].send_shutdown_request(ShutdownRequest::Coordinator);
+    }
+}
@@ -0,0 +1,417 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::statement::Statement,
+    persistence::storage::{ReadBlobState, Storage, StorageError, StorageResult},
+};
+
+use super::{clock::Clock, commands::DatabaseCommandTransactionResponse, database::Database};
+
+/// Defines how often the statement statistics are persisted, see `StatementStats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementStatsOptions {
+    /// The statistics are written once a transaction finishes this long after the previous write, and on shutdown
+    pub persist_interval: Duration,
+}
+
+impl Default for StatementStatsOptions {
+    fn default() -> Self {
+        Self {
+            persist_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl StatementStatsOptions {
+    pub fn set_persist_interval(mut self, persist_interval: Duration) -> Self {
+        self.persist_interval = persist_interval;
+        self
+    }
+}
+
+/// Latency buckets, bucket `i` holds latencies below `2^i` microseconds and the last bucket everything slower
+const LATENCY_BUCKETS: usize = 28;
+
+fn bucket(latency: Duration) -> usize {
+    let micros = latency.as_micros();
+
+    match micros {
+        0 => 0,
+        micros => ((u128::BITS - micros.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1),
+    }
+}
+
+/// Counters and a latency summary of the transactions that contained a kind of statement. Latencies are kept in
+/// buckets rather than samples so that the summary has a fixed size no matter how long the history is
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatementKindStats {
+    /// Statements of the kind, a transaction can contain several
+    pub statements: u64,
+    pub committed_transactions: u64,
+    /// Transactions that were rolled back or rejected, e.g. by a quota
+    pub rolled_back_transactions: u64,
+    pub total_latency_us: u64,
+    pub max_latency_us: u64,
+    latency_buckets: Vec<u64>,
+}
+
+impl Default for StatementKindStats {
+    fn default() -> Self {
+        Self {
+            statements: 0,
+            committed_transactions: 0,
+            rolled_back_transactions: 0,
+            total_latency_us: 0,
+            max_latency_us: 0,
+            latency_buckets: vec![0; LATENCY_BUCKETS],
+        }
+    }
+}
+
+impl StatementKindStats {
+    pub fn transactions(&self) -> u64 {
+        self.committed_transactions + self.rolled_back_transactions
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        match self.transactions() {
+            0 => None,
+            transactions => Some(Duration::from_micros(self.total_latency_us / transactions)),
+        }
+    }
+
+    /// Percentile (0 to 1) of the latencies, rounded up to the bucket's upper bound and capped at the max latency
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let transactions = self.transactions();
+
+        if transactions == 0 {
+            return None;
+        }
+
+        let rank = ((transactions as f64 * percentile).ceil() as u64).max(1);
+        let mut seen = 0;
+
+        for (index, count) in self.latency_buckets.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                let upper_bound_us = 1u64 << index;
+
+                return Some(Duration::from_micros(
+                    upper_bound_us.min(self.max_latency_us),
+                ));
+            }
+        }
+
+        Some(Duration::from_micros(self.max_latency_us))
+    }
+
+    fn record(&mut self, statements: u64, committed: bool, latency: Duration) {
+        let latency_us = latency.as_micros().min(u64::MAX as u128) as u64;
+
+        self.statements += statements;
+
+        if committed {
+            self.committed_transactions += 1;
+        } else {
+            self.rolled_back_transactions += 1;
+        }
+
+        self.total_latency_us = self.total_latency_us.saturating_add(latency_us);
+        self.max_latency_us = self.max_latency_us.max(latency_us);
+
+        // Blobs written by a version with fewer buckets are padded
+        self.latency_buckets.resize(LATENCY_BUCKETS, 0);
+        self.latency_buckets[bucket(latency)] += 1;
+    }
+}
+
+/// The persisted statistics, keyed by statement kind
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct StatementStatsSnapshot {
+    /// Milliseconds since the unix epoch of the first recorded transaction
+    pub since_ms: Option<u128>,
+    pub kinds: BTreeMap<String, StatementKindStats>,
+}
+
+struct StatsState {
+    snapshot: StatementStatsSnapshot,
+    persisted_at: Instant,
+    /// Transactions were recorded since the statistics were last written
+    dirty: bool,
+}
+
+/// Aggregates the count, outcome and latency of the transactions per statement kind. Unlike the rolling stats,
+/// e.g. the queue wait, they are persisted to their own blob and reloaded on startup, so the history outlives
+/// restarts and deploys. They are never replayed on restore and are dropped when the database is reset
+pub struct StatementStats {
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    options: StatementStatsOptions,
+    clock: Arc<dyn Clock>,
+    /// Loaded from storage on first use, storage is not initialized when the stats are created
+    state: Mutex<Option<StatsState>>,
+}
+
+const STATEMENT_STATS_PATH: &str = "statement_stats";
+
+impl StatementStats {
+    pub fn new(
+        storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+        options: StatementStatsOptions,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            storage,
+            options,
+            clock,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Records a finished transaction under each statement kind it contained
+    pub fn record(
+        &self,
+        statement_kinds: &[&'static str],
+        committed: bool,
+        latency: Duration,
+    ) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let state = self.loaded(&mut state)?;
+
+        let snapshot = &mut state.snapshot;
+
+        snapshot.since_ms.get_or_insert_with(|| self.clock.now_ms());
+
+        let mut kinds = statement_kinds.to_vec();
+        kinds.sort_unstable();
+
+        for chunk in kinds.chunk_by(|a, b| a == b) {
+            snapshot
+                .kinds
+                .entry(chunk[0].to_string())
+                .or_default()
+                .record(chunk.len() as u64, committed, latency);
+        }
+
+        state.dirty = true;
+
+        if state.persisted_at.elapsed() >= self.options.persist_interval {
+            self.write(state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the statistics if transactions were recorded since the last write, e.g. before shutting down
+    pub fn persist(&self) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.as_mut() {
+            Some(state) if state.dirty => self.write(state),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn snapshot(&self) -> StorageResult<StatementStatsSnapshot> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(self.loaded(&mut state)?.snapshot.clone())
+    }
+
+    /// Count, outcome and latency summary of each statement kind
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        let snapshot = match self.snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(e) => return vec![("StatementStatsError".to_string(), e.to_string())],
+        };
+
+        let as_ms = |latency: Option<Duration>| {
+            format!("{:.3}", latency.unwrap_or_default().as_secs_f64() * 1000.0)
+        };
+
+        snapshot
+            .since_ms
+            .map(|since_ms| ("StatementStatsSinceMs".to_string(), since_ms.to_string()))
+            .into_iter()
+            .chain(snapshot.kinds.iter().flat_map(|(kind, stats)| {
+                [
+                    ("StatementCount", stats.statements.to_string()),
+                    ("StatementCommits", stats.committed_transactions.to_string()),
+                    (
+                        "StatementRollbacks",
+                        stats.rolled_back_transactions.to_string(),
+                    ),
+                    ("StatementLatencyMeanMs", as_ms(stats.mean_latency())),
+                    ("StatementLatencyP50Ms", as_ms(stats.percentile(0.5))),
+                    ("StatementLatencyP99Ms", as_ms(stats.percentile(0.99))),
+                    (
+                        "StatementLatencyMaxMs",
+                        as_ms(Some(Duration::from_micros(stats.max_latency_us))),
+                    ),
+                ]
+                .map(|(name, value)| (format!("{}[{}]", name, kind), value))
+            }))
+            .collect()
+    }
+
+    /// The stats blob has been removed with the rest of storage, e.g. the database was reset
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = None;
+    }
+
+    fn loaded<'a>(&self, state: &'a mut Option<StatsState>) -> StorageResult<&'a mut StatsState> {
+        if state.is_none() {
+            let snapshot = match self
+                .storage
+                .lock()
+                .unwrap()
+                .read_blob(STATEMENT_STATS_PATH.to_string())?
+            {
+                ReadBlobState::Found(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?,
+                ReadBlobState::NotFound => StatementStatsSnapshot::default(),
+            };
+
+            *state = Some(StatsState {
+                snapshot,
+                persisted_at: Instant::now(),
+                dirty: false,
+            });
+        }
+
+        Ok(state.as_mut().expect("The state has just been loaded"))
+    }
+
+    fn write(&self, state: &mut StatsState) -> StorageResult<()> {
+        // Not retried until the next interval, a failing storage is not written on every transaction
+        state.persisted_at = Instant::now();
+
+        self.storage.lock().unwrap().write_blob(
+            STATEMENT_STATS_PATH.to_string(),
+            serde_json::to_vec(&state.snapshot).unwrap(),
+        )?;
+
+        state.dirty = false;
+
+        Ok(())
+    }
+}
+
+impl Database {
+    /// The statement kinds to record once the transaction finishes, none if the statement stats are disabled
+    pub(super) fn statement_stats_kinds(
+        &self,
+        statements: &[Statement],
+    ) -> Option<Vec<&'static str>> {
+        self.statement_stats
+            .as_ref()
+            .map(|_| statements.iter().map(<&'static str>::from).collect())
+    }
+
+    pub(super) fn record_statement_stats(
+        &self,
+        statement_kinds: &Option<Vec<&'static str>>,
+        response: &DatabaseCommandTransactionResponse,
+        latency: Duration,
+    ) {
+        let (Some(stats), Some(statement_kinds)) = (&self.statement_stats, statement_kinds) else {
+            return;
+        };
+
+        let committed = match response {
+            DatabaseCommandTransactionResponse::Commit(_)
+            | DatabaseCommandTransactionResponse::NotModified(_) => true,
+            DatabaseCommandTransactionResponse::Rollback(_)
+            | DatabaseCommandTransactionResponse::Status(_)
+            | DatabaseCommandTransactionResponse::QuotaExceeded(_)
+            | DatabaseCommandTransactionResponse::LimitExceeded(_)
+            | DatabaseCommandTransactionResponse::ContextOverrideRejected(_)
+            | DatabaseCommandTransactionResponse::DryRun(_)
+            | DatabaseCommandTransactionResponse::Throttled(_) => false,
+        };
+
+        if let Err(e) = stats.record(statement_kinds, committed, latency) {
+            log::warn!("Unable to record the statement statistics: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::{
+        database::clock::SystemClock,
+        persistence::{
+            storage::file::{FileOptions, FileStorage},
+            transaction::TransactionWriteMode,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn survives_a_restart() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let mut storage =
+            FileStorage::new(FileOptions::new(database_dir), TransactionWriteMode::Off);
+        storage.init().unwrap();
+
+        let storage: Arc<Mutex<dyn Storage + Sync + Send>> = Arc::new(Mutex::new(storage));
+
+        // Only written on shutdown
+        let options =
+            StatementStatsOptions::default().set_persist_interval(Duration::from_secs(3600));
+
+        let stats = StatementStats::new(storage.clone(), options.clone(), Arc::new(SystemClock));
+
+        for latency_ms in 1..=100 {
+            stats
+                .record(
+                    &["Get", "Get", "Add"],
+                    latency_ms != 100,
+                    Duration::from_millis(latency_ms),
+                )
+                .unwrap();
+        }
+
+        assert!(
+            StatementStats::new(storage.clone(), options.clone(), Arc::new(SystemClock))
+                .snapshot()
+                .unwrap()
+                .kinds
+                .is_empty()
+        );
+
+        stats.persist().unwrap();
+
+        let reloaded = StatementStats::new(storage, options, Arc::new(SystemClock))
+            .snapshot()
+            .unwrap();
+        let get = &reloaded.kinds["Get"];
+
+        assert!(reloaded.since_ms.is_some());
+        assert_eq!(reloaded.kinds.len(), 2);
+        assert_eq!(get.statements, 200);
+        assert_eq!(get.committed_transactions, 99);
+        assert_eq!(get.rolled_back_transactions, 1);
+        assert_eq!(get.max_latency_us, 100_000);
+        assert_eq!(get.mean_latency(), Some(Duration::from_micros(50_500)));
+
+        // 50ms falls into the bucket up to 2^16us, the slowest transaction is capped at the max
+        assert_eq!(get.percentile(0.5), Some(Duration::from_micros(65_536)));
+        assert_eq!(get.percentile(1.0), Some(Duration::from_millis(100)));
+        assert_eq!(reloaded.kinds["Add"].statements, 100);
+    }
+}
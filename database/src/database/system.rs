@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{consts::consts::TransactionId, persistence::transaction::TransactionWriteMode};
+
+use super::database::Database;
+
+/// Read-only tables of the database's internal state, queried with `Statement::QuerySystemTable`
+pub const SYSTEM_TABLES: [&str; 4] = [
+    "system.stats",
+    "system.requests",
+    "system.snapshots",
+    "system.wal",
+];
+
+/// Rows of a system table, every value is formatted as a string
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SystemTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl SystemTable {
+    fn new(columns: &[&str], rows: Vec<Vec<String>>) -> Self {
+        Self {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            rows,
+        }
+    }
+
+    fn from_info(info: Vec<(String, String)>) -> Self {
+        Self::new(
+            &["name", "value"],
+            info.into_iter()
+                .map(|(name, value)| vec![name, value])
+                .collect(),
+        )
+    }
+
+    /// Values of a column, none if the table does not have the column
+    pub fn column(&self, name: &str) -> Option<Vec<&str>> {
+        let index = self.columns.iter().position(|column| column == name)?;
+
+        Some(self.rows.iter().map(|row| row[index].as_str()).collect())
+    }
+}
+
+impl Database {
+    /// Reported by `Control::DatabaseStats` and the `system.stats` table. The thread index is only reported to
+    /// controls, a system table is not specific to the worker thread that reads it
+    pub(super) fn stats(
+        &self,
+        thread_id: Option<usize>,
+        transaction_id: &TransactionId,
+    ) -> Vec<(String, String)> {
+        let current_transaction_id = (
+            "CurrentTransactionID".to_string(),
+            transaction_id.to_string(),
+        );
+
+        let wal_size = (
+            "WALSize".to_string(),
+            self.persistence.transaction_wal.get_wal_size().to_string(),
+        );
+
+        // The batch limit the WAL chose, see `DatabaseOptions::set_adaptive_wal_batching`
+        let wal_batching = self.persistence.transaction_wal.get_batching_stats();
+
+        let row_count = (
+            "RowCount".to_string(),
+            self.person_table.person_rows.len().to_string(),
+        );
+
+        let statistics = self.person_table.statistics.snapshot();
+
+        let table_statistics = vec![
+            ("LiveRowCount".to_string(), statistics.live_rows.to_string()),
+            (
+                "DeletedRowCount".to_string(),
+                statistics.deleted_rows.to_string(),
+            ),
+            (
+                "VersionCount".to_string(),
+                statistics.total_versions.to_string(),
+            ),
+            (
+                "AverageVersionsPerRow".to_string(),
+                format!("{:.2}", statistics.average_versions_per_row()),
+            ),
+        ];
+
+        let database_threads = (
+            "DatabaseThreads".to_string(),
+            self.database_options.worker_threads().to_string(),
+        );
+
+        let worker_pools = self
+            .database_options
+            .worker_pools()
+            .map(|(write_threads, read_threads)| {
+                vec![
+                    ("WriteThreads".to_string(), write_threads.to_string()),
+                    ("ReadThreads".to_string(), read_threads.to_string()),
+                ]
+            })
+            .unwrap_or_default();
+
+        let database_thread_index =
+            thread_id.map(|thread_id| ("DatabaseThreadIndex".to_string(), thread_id.to_string()));
+
+        // Rolling p99 of how long requests waited for each worker thread to pick them up
+        let queue_wait = (0..self.queue_wait.threads()).filter_map(|thread_id| {
+            self.queue_wait.p99(thread_id).map(|p99| {
+                (
+                    format!("QueueWaitP99Ms[{}]", thread_id),
+                    format!("{:.3}", p99.as_secs_f64() * 1000.0),
+                )
+            })
+        });
+
+        // Requests are not routed to threads that are paused or executing a control
+        let availability = (0..self.availability.threads()).map(|thread_id| {
+            (
+                format!("ThreadAvailability[{}]", thread_id),
+                self.availability.get(thread_id).to_string(),
+            )
+        });
+
+        let engine = self.database_options.storage_engine.get_engine_info_stats();
+
+        // Rolling p50 and p99 of each operation of a network storage engine
+        let storage_latency = self.persistence.get_storage_latency().get_stats();
+
+        // Pending copies and lag of the engine's replica, e.g. the DR bucket of S3
+        let storage_replication = self.persistence.get_storage_replication().get_stats();
+
+        let migration = self
+            .persistence
+            .get_migration()
+            .map(|(target, phase)| {
+                vec![
+                    ("MigrationTarget".to_string(), target.to_string()),
+                    ("MigrationPhase".to_string(), phase.to_string()),
+                ]
+            })
+            .unwrap_or_default();
+
+        let interrupted = self
+            .interrupted_operation()
+            .map(|intent| ("InterruptedOperation".to_string(), intent.to_string()));
+
+        #[cfg(feature = "publisher")]
+        let publisher = self
+            .publisher
+            .as_ref()
+            .map(|publisher| publisher.get_stats())
+            .unwrap_or_default();
+        #[cfg(not(feature = "publisher"))]
+        let publisher = vec![];
+
+        vec![
+            row_count,
+            wal_size,
+            current_transaction_id,
+            database_threads,
+        ]
+        .into_iter()
+        .chain(wal_batching)
+        .chain(worker_pools)
+        .chain(database_thread_index)
+        .chain(table_statistics)
+        .chain(self.person_table.planner.get_stats())
+        .chain(
+            self.statement_stats
+                .as_ref()
+                .map(|statement_stats| statement_stats.get_stats())
+                .unwrap_or_default(),
+        )
+        .chain(
+            self.shadow_reads
+                .as_ref()
+                .map(|shadow_reads| shadow_reads.get_stats())
+                .unwrap_or_default(),
+        )
+        .chain(
+            self.request_capture
+                .as_ref()
+                .map(|request_capture| request_capture.get_stats())
+                .unwrap_or_default(),
+        )
+        .chain(queue_wait)
+        .chain(self.pauses.get_stats())
+        .chain(availability)
+        .chain(engine)
+        .chain(storage_latency)
+        .chain(storage_replication)
+        .chain(migration)
+        .chain(interrupted)
+        .chain(self.quotas.stats())
+        .chain(self.tag_throttle.stats())
+        .chain(publisher)
+        .collect::<Vec<(String, String)>>()
+    }
+
+    pub(super) fn query_system_table(
+        &self,
+        name: &str,
+        transaction_id: &TransactionId,
+    ) -> Result<SystemTable, String> {
+        let table = match name {
+            "system.stats" => SystemTable::from_info(self.stats(None, transaction_id)),
+            "system.requests" => SystemTable::new(
+                &[
+                    "request_id",
+                    "thread_id",
+                    "transaction_id",
+                    "kind",
+                    "client_id",
+                    "elapsed_ms",
+                ],
+                self.activity
+                    .report()
+                    .active_requests
+                    .into_iter()
+                    .map(|request| {
+                        vec![
+                            request.request_id.to_string(),
+                            request.thread_id.to_string(),
+                            request.transaction_id.to_string(),
+                            request.kind,
+                            request.client_id.unwrap_or_default(),
+                            request.elapsed.as_millis().to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            "system.snapshots" => SystemTable::new(
+                &[
+                    "key",
+                    "transaction_id",
+                    "record_count",
+                    "checksum",
+                    "shards",
+                    "wal_archive",
+                ],
+                self.persistence
+                    .snapshot_manager
+                    .list_snapshots()
+                    .map_err(|e| format!("Unable to read the snapshot catalog: {}", e))?
+                    .into_iter()
+                    .map(|record| {
+                        vec![
+                            record.key,
+                            record.transaction_id.to_string(),
+                            record.record_count.to_string(),
+                            format!("{:08x}", record.checksum),
+                            record.shards.len().to_string(),
+                            record.wal_archive.unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            "system.wal" => {
+                let write_mode = match &self.database_options.write_mode {
+                    TransactionWriteMode::File(mode) => format!("{:?}", mode),
+                    TransactionWriteMode::Off => "Off".to_string(),
+                };
+
+                SystemTable::from_info(vec![
+                    (
+                        "WALSize".to_string(),
+                        self.persistence.transaction_wal.get_wal_size().to_string(),
+                    ),
+                    ("WriteMode".to_string(), write_mode),
+                    (
+                        "ArchiveWAL".to_string(),
+                        self.database_options.archive_wal.to_string(),
+                    ),
+                    (
+                        "CurrentTransactionID".to_string(),
+                        transaction_id.to_string(),
+                    ),
+                ])
+            }
+            _ => {
+                return Err(format!(
+                    "System table does not exist: {}, system tables are: {}",
+                    name,
+                    SYSTEM_TABLES.join(", ")
+                ))
+            }
+        };
+
+        Ok(table)
+    }
+}
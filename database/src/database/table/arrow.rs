@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{ListBuilder, StringBuilder},
+    ArrayRef, RecordBatch,
+};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use crate::model::person::Person;
+
+/// Columns of the record batches people are read into, the address is flattened into its parts
+pub fn people_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("full_name", DataType::Utf8, false),
+        Field::new("email", DataType::Utf8, true),
+        Field::new("street", DataType::Utf8, true),
+        Field::new("city", DataType::Utf8, true),
+        Field::new("country", DataType::Utf8, true),
+        Field::new(
+            "phone_numbers",
+            DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true))),
+            false,
+        ),
+    ])
+}
+
+/// Converts people into a single record batch, so that analytical engines (e.g. DuckDB or DataFusion) can
+/// aggregate a snapshot of the table without going through the row-at-a-time list API
+pub fn people_to_record_batch(people: &[Person]) -> RecordBatch {
+    let mut id = StringBuilder::new();
+    let mut full_name = StringBuilder::new();
+    let mut email = StringBuilder::new();
+    let mut street = StringBuilder::new();
+    let mut city = StringBuilder::new();
+    let mut country = StringBuilder::new();
+    let mut phone_numbers = ListBuilder::new(StringBuilder::new());
+
+    for person in people {
+        let address = person.address.as_ref();
+
+        id.append_value(&person.id.0);
+        full_name.append_value(&person.full_name);
+        email.append_option(person.email.as_ref());
+        street.append_option(address.and_then(|address| address.street.as_ref()));
+        city.append_option(address.and_then(|address| address.city.as_ref()));
+        country.append_option(address.and_then(|address| address.country.as_ref()));
+
+        for phone_number in &person.phone_numbers {
+            phone_numbers.values().append_value(phone_number);
+        }
+        phone_numbers.append(true);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(id.finish()),
+        Arc::new(full_name.finish()),
+        Arc::new(email.finish()),
+        Arc::new(street.finish()),
+        Arc::new(city.finish()),
+        Arc::new(country.finish()),
+        Arc::new(phone_numbers.finish()),
+    ];
+
+    RecordBatch::try_new(Arc::new(people_schema()), columns)
+        .expect("The columns are built from the schema")
+}
+
+/// Serializes the record batch in the Arrow IPC streaming format, readable with e.g. `pyarrow.ipc.open_stream`
+pub fn record_batch_to_ipc(batch: &RecordBatch) -> Result<Vec<u8>, ArrowError> {
+    let mut writer = StreamWriter::try_new(vec![], &batch.schema())?;
+
+    writer.write(batch)?;
+
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, Array};
+    use arrow_ipc::reader::StreamReader;
+
+    use crate::{consts::consts::EntityId, model::person::Address};
+
+    use super::*;
+
+    #[test]
+    fn people_round_trip_through_ipc() {
+        let people = vec![
+            Person {
+                id: EntityId("a".to_string()),
+                full_name: "Alice".to_string(),
+                email: Some("alice@example.com".to_string()),
+                address: Some(Address {
+                    street: None,
+                    city: Some("Seattle".to_string()),
+                    country: None,
+                }),
+                phone_numbers: vec!["1".to_string(), "2".to_string()],
+                attachments: vec![],
+            },
+            Person {
+                id: EntityId("b".to_string()),
+                full_name: "Bob".to_string(),
+                email: None,
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
+            },
+        ];
+
+        let bytes = record_batch_to_ipc(&people_to_record_batch(&people)).unwrap();
+
+        let batches = StreamReader::try_new(bytes.as_slice(), None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let city = batch.column_by_name("city").unwrap();
+        let city: Vec<Option<&str>> = city.as_string::<i32>().iter().collect();
+        assert_eq!(city, vec![Some("Seattle"), None]);
+
+        let phone_numbers = batch
+            .column_by_name("phone_numbers")
+            .unwrap()
+            .as_list::<i32>();
+        assert_eq!(phone_numbers.value(0).len(), 2);
+        assert_eq!(phone_numbers.value(1).len(), 0);
+    }
+}
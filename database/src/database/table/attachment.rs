@@ -0,0 +1,204 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::clock::Clock,
+    model::person::Attachment,
+    persistence::storage::{ReadBlobState, Storage, StorageError, StorageResult},
+};
+
+/// The bytes of an attachment, see `Statement::GetAttachment`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AttachmentContent {
+    pub attachment: Attachment,
+    pub bytes: Vec<u8>,
+}
+
+/// A stored payload, whether any version references it is only known once the table is scanned
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct StoredBlob {
+    size: u64,
+    /// Milliseconds since the unix epoch, rewriting the same bytes refreshes it
+    written_at_ms: u128,
+}
+
+/// Outcome of `AttachmentStore::vacuum`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VacuumReport {
+    /// Payloads referenced by at least one version
+    pub referenced: usize,
+    /// Unreferenced payloads that were deleted
+    pub deleted: usize,
+    /// Bytes freed by the deleted payloads
+    pub deleted_bytes: u64,
+    /// Unreferenced payloads that are younger than the minimum age, they may belong to a transaction that has
+    /// not committed yet
+    pub retained: usize,
+}
+
+/// Stores the bytes of attachments as blobs keyed by their SHA-256 digest, so a payload attached to several
+/// people (or several times) is only stored once. Row versions only hold the digest, payloads are written before
+/// the update that attaches them and are deleted by `vacuum` once no version references them
+pub struct AttachmentStore {
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    /// Payloads are aged by the clock's time
+    clock: Arc<dyn Clock>,
+    /// Every stored payload keyed by digest, loaded from storage on first use as storage is not initialized when
+    /// the store is created
+    manifest: Mutex<Option<BTreeMap<String, StoredBlob>>>,
+}
+
+impl fmt::Debug for AttachmentStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttachmentStore").finish()
+    }
+}
+
+const MANIFEST_PATH: &str = "attachments/manifest";
+
+fn blob_path(digest: &str) -> String {
+    format!("attachments/{}", digest)
+}
+
+pub fn digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+impl AttachmentStore {
+    pub fn new(storage: Arc<Mutex<dyn Storage + Sync + Send>>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            storage,
+            clock,
+            manifest: Mutex::new(None),
+        }
+    }
+
+    /// Writes the payload and returns its digest, bytes that are already stored are not written again
+    pub fn write(&self, bytes: Vec<u8>) -> StorageResult<String> {
+        let digest = digest(&bytes);
+        let size = bytes.len() as u64;
+
+        let mut manifest = self.manifest.lock().unwrap();
+        let manifest = self.loaded(&mut manifest)?;
+
+        if !manifest.contains_key(&digest) {
+            self.storage
+                .lock()
+                .unwrap()
+                .write_blob(blob_path(&digest), bytes)?;
+        }
+
+        manifest.insert(
+            digest.clone(),
+            StoredBlob {
+                size,
+                written_at_ms: self.clock.now_ms(),
+            },
+        );
+
+        self.write_manifest(manifest)?;
+
+        Ok(digest)
+    }
+
+    pub fn read(&self, digest: &str) -> StorageResult<Option<Vec<u8>>> {
+        match self.storage.lock().unwrap().read_blob(blob_path(digest))? {
+            ReadBlobState::Found(bytes) => Ok(Some(bytes)),
+            ReadBlobState::NotFound => Ok(None),
+        }
+    }
+
+    /// Deletes the payloads that are not `referenced` and were written at least `min_age` ago. Payloads are
+    /// written before the transaction that attaches them commits, younger payloads are kept so that a payload is
+    /// not deleted from under a transaction that is about to reference it
+    pub fn vacuum(
+        &self,
+        referenced: &HashSet<String>,
+        min_age: Duration,
+    ) -> StorageResult<VacuumReport> {
+        let mut manifest = self.manifest.lock().unwrap();
+        let manifest = self.loaded(&mut manifest)?;
+
+        let cutoff = self.clock.now_ms().saturating_sub(min_age.as_millis());
+        let mut report = VacuumReport::default();
+        let mut unreferenced = vec![];
+
+        for (digest, blob) in manifest.iter() {
+            if referenced.contains(digest) {
+                report.referenced += 1;
+            } else if blob.written_at_ms > cutoff {
+                report.retained += 1;
+            } else {
+                unreferenced.push(digest.clone());
+            }
+        }
+
+        // The manifest is written first, a payload that could not be deleted is only wasted space
+        let deleted: Vec<(String, StoredBlob)> = unreferenced
+            .into_iter()
+            .filter_map(|digest| manifest.remove_entry(&digest))
+            .collect();
+
+        self.write_manifest(manifest)?;
+
+        for (digest, blob) in deleted {
+            if let Err(e) = self.storage.lock().unwrap().delete_blob(blob_path(&digest)) {
+                log::warn!("Unable to delete attachment {}: {}", digest, e);
+                continue;
+            }
+
+            report.deleted += 1;
+            report.deleted_bytes += blob.size;
+        }
+
+        Ok(report)
+    }
+
+    /// The attachment blobs have been removed with the rest of storage, e.g. the database was reset
+    pub fn reset(&self) {
+        *self.manifest.lock().unwrap() = None;
+    }
+
+    fn loaded<'a>(
+        &self,
+        manifest: &'a mut Option<BTreeMap<String, StoredBlob>>,
+    ) -> StorageResult<&'a mut BTreeMap<String, StoredBlob>> {
+        if manifest.is_none() {
+            let loaded = match self
+                .storage
+                .lock()
+                .unwrap()
+                .read_blob(MANIFEST_PATH.to_string())?
+            {
+                ReadBlobState::Found(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?,
+                ReadBlobState::NotFound => BTreeMap::new(),
+            };
+
+            *manifest = Some(loaded);
+        }
+
+        Ok(manifest
+            .as_mut()
+            .expect("The manifest has just been loaded"))
+    }
+
+    fn write_manifest(&self, manifest: &BTreeMap<String, StoredBlob>) -> StorageResult<()> {
+        self.storage.lock().unwrap().write_blob(
+            MANIFEST_PATH.to_string(),
+            serde_json::to_vec(manifest).unwrap(),
+        )
+    }
+}
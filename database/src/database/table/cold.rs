@@ -0,0 +1,122 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    consts::consts::EntityId,
+    persistence::{
+        field_encryption::FieldCipher,
+        storage::{ReadBlobState, Storage, StorageError, StorageResult},
+    },
+};
+
+use super::row::PersonVersion;
+
+/// Spills old row versions to the storage engine so that rows with long histories do not need to be
+/// held in memory. The most recent versions of a row are kept "hot" in memory, older versions are
+/// written as "cold" chunks (one blob per chunk) and loaded lazily when a historical read needs them
+pub struct ColdVersionStore {
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    hot_versions: usize,
+    /// If set, sensitive fields are encrypted in the cold chunks
+    field_cipher: Option<Arc<FieldCipher>>,
+}
+
+impl fmt::Debug for ColdVersionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ColdVersionStore")
+            .field("hot_versions", &self.hot_versions)
+            .finish()
+    }
+}
+
+impl ColdVersionStore {
+    pub fn new(storage: Arc<Mutex<dyn Storage + Sync + Send>>, hot_versions: usize) -> Self {
+        Self {
+            storage,
+            // A row always needs its current version in memory
+            hot_versions: hot_versions.max(1),
+            field_cipher: None,
+        }
+    }
+
+    pub fn set_field_cipher(mut self, field_cipher: Option<Arc<FieldCipher>>) -> Self {
+        self.field_cipher = field_cipher;
+        self
+    }
+
+    /// Number of versions that are kept in memory per row
+    pub fn hot_versions(&self) -> usize {
+        self.hot_versions
+    }
+
+    pub fn write_chunk(
+        &self,
+        id: &EntityId,
+        chunk_index: usize,
+        versions: &[PersonVersion],
+    ) -> StorageResult<()> {
+        let bytes = match &self.field_cipher {
+            Some(cipher) => serde_json::to_vec(
+                &versions
+                    .iter()
+                    .map(|version| cipher.encrypt_version(version.clone()))
+                    .collect::<Vec<PersonVersion>>(),
+            ),
+            None => serde_json::to_vec(versions),
+        }
+        .unwrap();
+
+        self.storage
+            .lock()
+            .unwrap()
+            .write_blob(chunk_path(id, chunk_index), bytes)
+    }
+
+    pub fn read_chunk(
+        &self,
+        id: &EntityId,
+        chunk_index: usize,
+    ) -> StorageResult<Vec<PersonVersion>> {
+        let path = chunk_path(id, chunk_index);
+
+        let result = self.storage.lock().unwrap().read_blob(path.clone())?;
+
+        match result {
+            ReadBlobState::Found(bytes) => {
+                let versions: Vec<PersonVersion> = serde_json::from_slice(&bytes)
+                    .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?;
+
+                match &self.field_cipher {
+                    Some(cipher) => versions
+                        .into_iter()
+                        .map(|version| cipher.decrypt_version(version))
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e))),
+                    None => Ok(versions),
+                }
+            }
+            // Chunks are written before the versions are dropped from memory, so a missing chunk means storage
+            //  has been modified underneath us
+            ReadBlobState::NotFound => Err(StorageError::UnableToReadBlob(anyhow!(
+                "Cold version chunk does not exist: {}",
+                path
+            ))),
+        }
+    }
+
+    /// Deletes a chunk, used once the row it belongs to has been purged (see `Statement::Purge`)
+    pub fn delete_chunk(&self, id: &EntityId, chunk_index: usize) -> StorageResult<()> {
+        self.storage
+            .lock()
+            .unwrap()
+            .delete_blob(chunk_path(id, chunk_index))
+    }
+}
+
+fn chunk_path(id: &EntityId, chunk_index: usize) -> String {
+    format!("cold_versions/{}/{}", id, chunk_index)
+}
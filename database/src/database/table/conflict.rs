@@ -0,0 +1,192 @@
+use std::{fmt, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::person::Person;
+
+use super::row::PersonVersionState;
+
+/// A hybrid logical clock reading of the replica that wrote a version. Readings are ordered by physical time,
+/// then by the logical counter and finally by node, so every replica agrees on which of two writes is later
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HybridTimestamp {
+    /// Milliseconds since the unix epoch
+    pub physical_ms: u64,
+    pub logical: u32,
+    pub node: String,
+}
+
+impl HybridTimestamp {
+    pub fn new(physical_ms: u64, logical: u32, node: String) -> Self {
+        Self {
+            physical_ms,
+            logical,
+            node,
+        }
+    }
+}
+
+/// One side of a conflict, the state of the row and when it was written
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DivergentVersion {
+    pub state: PersonVersionState,
+    pub timestamp: HybridTimestamp,
+}
+
+/// A version of a row that diverged from the local row, found by replication or an import, see
+/// `Statement::ResolveConflict`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Conflict {
+    /// When the current local version was written, as tracked by the replication or import that found the
+    /// conflict. Local versions do not carry a timestamp of their own
+    pub local_timestamp: HybridTimestamp,
+    pub remote: DivergentVersion,
+}
+
+/// Both sides of a resolved conflict and the strategy that resolved it, recorded in the lineage of the
+/// resolved version so the losing side is never silently dropped
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConflictRecord {
+    pub strategy: String,
+    pub local: DivergentVersion,
+    pub remote: DivergentVersion,
+}
+
+/// Resolves a conflict in a way the built-in strategies do not, see `ConflictResolution::Custom`
+///
+/// Conflicts are resolved again when the WAL is replayed, so the resolver must be deterministic
+pub trait ConflictResolver: Send + Sync {
+    fn resolve(&self, local: &DivergentVersion, remote: &DivergentVersion) -> PersonVersionState;
+}
+
+/// How the table resolves two divergent versions of the same row, see `Statement::ResolveConflict`
+///
+/// The strategy is not persisted, restoring with a different strategy can resolve replayed conflicts differently
+#[derive(Clone, Default)]
+pub enum ConflictResolution {
+    /// The side with the later timestamp wins as a whole, including deletes
+    #[default]
+    LastWriterWins,
+    /// Fields are taken from the later side, except that a field only one side has a value for keeps it. A
+    /// delete is resolved like `LastWriterWins`
+    FieldMerge,
+    Custom(Arc<dyn ConflictResolver>),
+}
+
+impl fmt::Debug for ConflictResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl ConflictResolution {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictResolution::LastWriterWins => "LastWriterWins",
+            ConflictResolution::FieldMerge => "FieldMerge",
+            ConflictResolution::Custom(_) => "Custom",
+        }
+    }
+
+    pub fn resolve(
+        &self,
+        local: &DivergentVersion,
+        remote: &DivergentVersion,
+    ) -> PersonVersionState {
+        // Ties cannot happen between replicas as the node is part of the timestamp
+        let (earlier, later) = match local.timestamp <= remote.timestamp {
+            true => (local, remote),
+            false => (remote, local),
+        };
+
+        match self {
+            ConflictResolution::LastWriterWins => later.state.clone(),
+            ConflictResolution::FieldMerge => match (&earlier.state, &later.state) {
+                (PersonVersionState::State(earlier), PersonVersionState::State(later)) => {
+                    PersonVersionState::State(Person {
+                        id: later.id.clone(),
+                        full_name: later.full_name.clone(),
+                        email: later.email.clone().or_else(|| earlier.email.clone()),
+                        address: later.address.clone().or_else(|| earlier.address.clone()),
+                        phone_numbers: match later.phone_numbers.is_empty() {
+                            true => earlier.phone_numbers.clone(),
+                            false => later.phone_numbers.clone(),
+                        },
+                        attachments: match later.attachments.is_empty() {
+                            true => earlier.attachments.clone(),
+                            false => later.attachments.clone(),
+                        },
+                    })
+                }
+                (_, state) => state.clone(),
+            },
+            ConflictResolution::Custom(resolver) => resolver.resolve(local, remote),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(full_name: &str, email: Option<&str>, physical_ms: u64) -> DivergentVersion {
+        let person = Person::new_test();
+
+        DivergentVersion {
+            state: PersonVersionState::State(Person {
+                full_name: full_name.to_string(),
+                email: email.map(str::to_string),
+                ..person
+            }),
+            timestamp: HybridTimestamp::new(physical_ms, 0, "replica".to_string()),
+        }
+    }
+
+    struct KeepLocal;
+
+    impl ConflictResolver for KeepLocal {
+        fn resolve(&self, local: &DivergentVersion, _: &DivergentVersion) -> PersonVersionState {
+            local.state.clone()
+        }
+    }
+
+    #[test]
+    fn resolves_by_strategy() {
+        let local = version("Local", Some("local@example.com"), 2);
+        let remote = version("Remote", None, 3);
+
+        let resolve = |resolution: ConflictResolution| match resolution.resolve(&local, &remote) {
+            PersonVersionState::State(person) => person,
+            PersonVersionState::Delete => panic!("should resolve to a person"),
+        };
+
+        // The later remote side wins as a whole
+        let resolved = resolve(ConflictResolution::LastWriterWins);
+        assert_eq!(resolved.full_name, "Remote");
+        assert_eq!(resolved.email, None);
+
+        // The later remote side wins, except for the email only the local side has
+        let resolved = resolve(ConflictResolution::FieldMerge);
+        assert_eq!(resolved.full_name, "Remote");
+        assert_eq!(resolved.email.as_deref(), Some("local@example.com"));
+
+        let resolved = resolve(ConflictResolution::Custom(Arc::new(KeepLocal)));
+        assert_eq!(resolved.full_name, "Local");
+
+        // A later delete wins under every built-in strategy
+        let deleted = DivergentVersion {
+            state: PersonVersionState::Delete,
+            timestamp: HybridTimestamp::new(4, 0, "replica".to_string()),
+        };
+
+        for resolution in [
+            ConflictResolution::LastWriterWins,
+            ConflictResolution::FieldMerge,
+        ] {
+            assert_eq!(
+                resolution.resolve(&local, &deleted),
+                PersonVersionState::Delete
+            );
+        }
+    }
+}
@@ -0,0 +1,20 @@
+/// When a constraint of the table is checked, see `DatabaseOptions::set_unique_email`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ConstraintTiming {
+    /// After each statement, the first statement that violates the constraint rolls the transaction back
+    #[default]
+    Immediate,
+    /// Once every statement of the transaction has been applied. The statements may violate the constraint on the
+    /// way (e.g. two people swapping emails) as long as the transaction does not commit a violation
+    Deferred,
+}
+
+impl ConstraintTiming {
+    /// The timing of a transaction, an immediate constraint is deferred by `TransactionContext::set_defer_constraints`
+    pub fn for_transaction(self, defer_constraints: bool) -> Self {
+        match defer_constraints {
+            true => ConstraintTiming::Deferred,
+            false => self,
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::consts::consts::{EntityId, TransactionId};
+
+use super::{
+    policy::Visibility,
+    row::PersonVersion,
+    table::PersonTable,
+    view::{project, PersonField},
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum HistoryCursorParseError {
+    #[error("History cursor is malformed, expected <transaction_id>:<transaction_id>, got: {0}")]
+    Malformed(String),
+
+    #[error("History cursor has an invalid transaction id: {0}")]
+    InvalidTransactionId(String),
+}
+
+/// Pins the history of a person to a snapshot and remembers the transaction id of the last version returned.
+/// Versions committed after the first page are not visible, so pages do not skip or repeat versions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryCursor {
+    pub snapshot_transaction_id: TransactionId,
+    pub last_transaction_id: TransactionId,
+}
+
+/// History cursors are handed to clients as an opaque string, format: `<transaction_id>:<transaction_id>`
+impl fmt::Display for HistoryCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.snapshot_transaction_id, self.last_transaction_id
+        )
+    }
+}
+
+impl FromStr for HistoryCursor {
+    type Err = HistoryCursorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (snapshot_transaction_id, last_transaction_id) = s
+            .split_once(':')
+            .ok_or_else(|| HistoryCursorParseError::Malformed(s.to_string()))?;
+
+        let parse = |transaction_id: &str| {
+            transaction_id
+                .parse::<usize>()
+                .map(TransactionId)
+                .map_err(|_| {
+                    HistoryCursorParseError::InvalidTransactionId(transaction_id.to_string())
+                })
+        };
+
+        Ok(HistoryCursor {
+            snapshot_transaction_id: parse(snapshot_transaction_id)?,
+            last_transaction_id: parse(last_transaction_id)?,
+        })
+    }
+}
+
+/// Which versions of a person's history are returned, see `Statement::History`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryRequest {
+    /// Only versions committed at or after the transaction id
+    pub from_transaction_id: Option<TransactionId>,
+    /// Only versions committed at or before the transaction id
+    pub to_transaction_id: Option<TransactionId>,
+    /// Only versions that changed one of the fields compared to the version before them, adds and deletes always
+    /// match. If empty, every version matches
+    pub changed_fields: Vec<PersonField>,
+    /// Where to continue from, if none we start from the oldest (or newest) version at the current snapshot
+    pub cursor: Option<HistoryCursor>,
+    /// Maximum number of versions to return in the page
+    pub limit: usize,
+    pub newest_first: bool,
+}
+
+impl HistoryRequest {
+    /// Every version, oldest first
+    pub fn new(limit: usize) -> Self {
+        HistoryRequest {
+            from_transaction_id: None,
+            to_transaction_id: None,
+            changed_fields: vec![],
+            cursor: None,
+            limit,
+            newest_first: false,
+        }
+    }
+
+    pub fn set_transaction_range(
+        mut self,
+        from_transaction_id: Option<TransactionId>,
+        to_transaction_id: Option<TransactionId>,
+    ) -> Self {
+        self.from_transaction_id = from_transaction_id;
+        self.to_transaction_id = to_transaction_id;
+        self
+    }
+
+    pub fn set_changed_fields(mut self, changed_fields: Vec<PersonField>) -> Self {
+        self.changed_fields = changed_fields;
+        self
+    }
+
+    pub fn set_cursor(mut self, cursor: Option<HistoryCursor>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub fn set_newest_first(mut self, newest_first: bool) -> Self {
+        self.newest_first = newest_first;
+        self
+    }
+
+    fn in_range(&self, transaction_id: &TransactionId) -> bool {
+        let after_cursor = match &self.cursor {
+            Some(cursor) if self.newest_first => transaction_id < &cursor.last_transaction_id,
+            Some(cursor) => transaction_id > &cursor.last_transaction_id,
+            None => true,
+        };
+
+        after_cursor
+            && self
+                .from_transaction_id
+                .as_ref()
+                .map_or(true, |from| transaction_id >= from)
+            && self
+                .to_transaction_id
+                .as_ref()
+                .map_or(true, |to| transaction_id <= to)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryPage {
+    pub versions: Vec<PersonVersion>,
+    /// If none, there are no more versions to return
+    pub next_cursor: Option<HistoryCursor>,
+}
+
+/// Returns a page of the versions of a single row ordered by transaction id, unlike `lineage` renames and merges
+/// are not followed. Returns none if the row does not exist
+pub fn history_page(
+    table: &PersonTable,
+    id: &EntityId,
+    request: HistoryRequest,
+    transaction_id: &TransactionId,
+    visibility: &Visibility,
+) -> Option<HistoryPage> {
+    let row = table.person_rows.get(id)?;
+
+    let snapshot_transaction_id = match &request.cursor {
+        Some(cursor) => cursor.snapshot_transaction_id.clone(),
+        None => transaction_id.clone(),
+    };
+
+    let history = row.value().read().unwrap().history();
+
+    // Whether a version changed a field depends on the version before it, even if that version is out of range
+    let mut previous = None;
+    let mut versions: Vec<PersonVersion> = history
+        .into_iter()
+        .filter(|version| version.transaction_id <= snapshot_transaction_id)
+        .filter(|version| {
+            let current = version.get_person();
+
+            let changed = match (&previous, &current) {
+                (Some(previous), Some(current)) => {
+                    request.changed_fields.is_empty()
+                        || project(previous, &request.changed_fields)
+                            != project(current, &request.changed_fields)
+                }
+                _ => true,
+            };
+
+            previous = current;
+
+            changed
+        })
+        .filter(|version| request.in_range(&version.transaction_id))
+        .filter(|version| match visibility.is_restricted() {
+            true => version
+                .get_person()
+                .is_some_and(|person| visibility.can_see(&person)),
+            false => true,
+        })
+        .collect();
+
+    if request.newest_first {
+        versions.reverse();
+    }
+
+    // Take one more than the limit, this tells us whether there is another page
+    versions.truncate(request.limit + 1);
+
+    let next_cursor = match versions.len() > request.limit {
+        true => {
+            versions.truncate(request.limit);
+
+            versions.last().map(|version| HistoryCursor {
+                snapshot_transaction_id: snapshot_transaction_id.clone(),
+                last_transaction_id: version.transaction_id.clone(),
+            })
+        }
+        false => None,
+    };
+
+    Some(HistoryPage {
+        versions,
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::table::row::{UpdatePersonData, UpdateStatement},
+        model::{person::Person, statement::Statement},
+    };
+
+    use super::*;
+
+    fn transaction_ids(page: &HistoryPage) -> Vec<usize> {
+        page.versions
+            .iter()
+            .map(|version| version.transaction_id.to_number())
+            .collect()
+    }
+
+    #[test]
+    fn history_cursor_round_trip() {
+        let cursor = HistoryCursor {
+            snapshot_transaction_id: TransactionId(10),
+            last_transaction_id: TransactionId(4),
+        };
+
+        assert_eq!(cursor.to_string().parse::<HistoryCursor>(), Ok(cursor));
+
+        assert_eq!(
+            "10:abc".parse::<HistoryCursor>(),
+            Err(HistoryCursorParseError::InvalidTransactionId(
+                "abc".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn pages_filters_and_ranges() {
+        // Given a person whose name changes on odd transactions and email on even transactions
+        let table = PersonTable::new();
+        let id = EntityId("a".to_string());
+
+        table
+            .apply(
+                Statement::Add(Person {
+                    id: id.clone(),
+                    full_name: "0".to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                }),
+                TransactionId(1),
+            )
+            .unwrap();
+
+        for transaction_id in 2..=10 {
+            let update = match transaction_id % 2 {
+                1 => UpdatePersonData {
+                    full_name: UpdateStatement::Set(transaction_id.to_string()),
+                    ..Default::default()
+                },
+                _ => UpdatePersonData {
+                    email: UpdateStatement::Set(transaction_id.to_string()),
+                    ..Default::default()
+                },
+            };
+
+            table
+                .apply(
+                    Statement::Update(id.clone(), update),
+                    TransactionId(transaction_id),
+                )
+                .unwrap();
+        }
+
+        let page = |request: HistoryRequest, at: usize| {
+            history_page(
+                &table,
+                &id,
+                request,
+                &TransactionId(at),
+                &Visibility::unrestricted(),
+            )
+            .unwrap()
+        };
+
+        // Pages follow the snapshot of the first page, later versions are not returned
+        let first = page(HistoryRequest::new(4), 8);
+        assert_eq!(transaction_ids(&first), vec![1, 2, 3, 4]);
+
+        let second = page(HistoryRequest::new(4).set_cursor(first.next_cursor), 10);
+        assert_eq!(transaction_ids(&second), vec![5, 6, 7, 8]);
+        assert_eq!(second.next_cursor, None);
+
+        // Only versions that changed the name, the add always matches
+        let names = page(
+            HistoryRequest::new(10).set_changed_fields(vec![PersonField::FullName]),
+            10,
+        );
+        assert_eq!(transaction_ids(&names), vec![1, 3, 5, 7, 9]);
+
+        // Newest first within a transaction range
+        let newest = HistoryRequest::new(2)
+            .set_transaction_range(Some(TransactionId(3)), Some(TransactionId(8)))
+            .set_newest_first(true);
+
+        let first = page(newest.clone(), 10);
+        assert_eq!(transaction_ids(&first), vec![8, 7]);
+
+        let second = page(newest.set_cursor(first.next_cursor), 10);
+        assert_eq!(transaction_ids(&second), vec![6, 5]);
+
+        // Rows that do not exist have no history
+        assert!(history_page(
+            &table,
+            &EntityId("b".to_string()),
+            HistoryRequest::new(1),
+            &TransactionId(10),
+            &Visibility::unrestricted(),
+        )
+        .is_none());
+    }
+}
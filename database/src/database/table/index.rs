@@ -0,0 +1,396 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crossbeam_skiplist::{SkipMap, SkipSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{consts::consts::EntityId, model::person::Person};
+
+use super::striped::StripedCounter;
+
+/// A field of the person table that can be indexed, the full name and email are always indexed while the
+/// others are only indexed once the index is created, see `Control::CreateIndex`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexedField {
+    FullName,
+    Email,
+    Street,
+    City,
+    Country,
+    PhoneNumber,
+}
+
+impl IndexedField {
+    pub const ALL: [IndexedField; 6] = [
+        IndexedField::FullName,
+        IndexedField::Email,
+        IndexedField::Street,
+        IndexedField::City,
+        IndexedField::Country,
+        IndexedField::PhoneNumber,
+    ];
+
+    /// The field's path in a query, e.g. `address.city`
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexedField::FullName => "full_name",
+            IndexedField::Email => "email",
+            IndexedField::Street => "address.street",
+            IndexedField::City => "address.city",
+            IndexedField::Country => "address.country",
+            IndexedField::PhoneNumber => "phone_number",
+        }
+    }
+
+    /// The values of the field the person is indexed under, a person has one entry per phone number
+    fn values<'a>(&self, person: &'a Person) -> Vec<&'a str> {
+        let address = person.address.as_ref();
+
+        match self {
+            IndexedField::FullName => vec![person.full_name.as_str()],
+            IndexedField::Email => person.email.as_deref().into_iter().collect(),
+            IndexedField::Street => address
+                .and_then(|a| a.street.as_deref())
+                .into_iter()
+                .collect(),
+            IndexedField::City => address
+                .and_then(|a| a.city.as_deref())
+                .into_iter()
+                .collect(),
+            IndexedField::Country => address
+                .and_then(|a| a.country.as_deref())
+                .into_iter()
+                .collect(),
+            IndexedField::PhoneNumber => person.phone_numbers.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+impl fmt::Display for IndexedField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Secondary index of the rows that have (or had) a value in a field. Entries are only ever added, an update
+/// leaves the row under its previous value as older snapshots can still read it, so the candidates of a lookup
+/// have to be re-checked against the version that is visible to the reader
+#[derive(Default)]
+pub struct FieldIndex {
+    postings: SkipMap<String, SkipSet<EntityId>>,
+    entries: StripedCounter,
+}
+
+/// A point in time copy of the index statistics
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexStatistics {
+    /// Number of distinct values (cardinality) in the index
+    pub distinct_values: usize,
+    /// Number of (value, row) pairs in the index
+    pub entries: usize,
+}
+
+impl IndexStatistics {
+    pub fn average_rows_per_value(&self) -> f64 {
+        match self.distinct_values {
+            0 => 0.0,
+            distinct_values => self.entries as f64 / distinct_values as f64,
+        }
+    }
+}
+
+impl FieldIndex {
+    pub fn insert(&self, value: &str, id: &EntityId) {
+        let posting = self
+            .postings
+            .get_or_insert_with(value.to_string(), SkipSet::new);
+
+        if !posting.value().contains(id) {
+            posting.value().insert(id.clone());
+            self.entries.add(1);
+        }
+    }
+
+    /// Removes the row from the value's posting, unlike updates a purge (see `Statement::Purge`) removes the
+    /// row's values from the index
+    pub fn remove(&self, value: &str, id: &EntityId) {
+        let Some(posting) = self.postings.get(value) else {
+            return;
+        };
+
+        if posting.value().remove(id).is_some() {
+            self.entries.sub(1);
+        }
+
+        // The value itself may be personal data, so an empty posting is removed rather than kept. A row that was
+        //  added to the posting while it was removed is added back
+        if posting.value().is_empty() && posting.remove() {
+            for id in posting.value().iter() {
+                self.entries.sub(1);
+                self.insert(value, id.value());
+            }
+        }
+    }
+
+    /// Rows that have (or had) the value, see `FieldIndex`
+    pub fn candidates(&self, value: &str) -> Vec<EntityId> {
+        match self.postings.get(value) {
+            Some(posting) => posting
+                .value()
+                .iter()
+                .map(|id| id.value().clone())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Upper bound of the rows a lookup of the value returns
+    pub fn estimate(&self, value: &str) -> usize {
+        self.postings
+            .get(value)
+            .map(|posting| posting.value().len())
+            .unwrap_or(0)
+    }
+
+    pub fn statistics(&self) -> IndexStatistics {
+        IndexStatistics {
+            distinct_values: self.postings.len(),
+            entries: self.entries.get(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.postings.clear();
+        self.entries.reset();
+    }
+}
+
+const INDEX_ABSENT: u8 = 0;
+const INDEX_BUILDING: u8 = 1;
+const INDEX_READY: u8 = 2;
+
+/// An index that is only maintained once it has been created. While it is built it is maintained but not
+/// used by the planner, as it does not have the existing rows yet
+#[derive(Default)]
+struct OptionalIndex {
+    state: AtomicU8,
+    index: FieldIndex,
+}
+
+/// The indexed fields of the person table, maintained as versions are added
+#[derive(Default)]
+pub struct PersonIndexes {
+    full_name: FieldIndex,
+    email: FieldIndex,
+    street: OptionalIndex,
+    city: OptionalIndex,
+    country: OptionalIndex,
+    phone_number: OptionalIndex,
+}
+
+impl PersonIndexes {
+    fn optional(&self, field: &IndexedField) -> Option<&OptionalIndex> {
+        match field {
+            IndexedField::FullName | IndexedField::Email => None,
+            IndexedField::Street => Some(&self.street),
+            IndexedField::City => Some(&self.city),
+            IndexedField::Country => Some(&self.country),
+            IndexedField::PhoneNumber => Some(&self.phone_number),
+        }
+    }
+
+    /// The indexes that new versions are added to, including the indexes that are being built
+    fn maintained(&self) -> impl Iterator<Item = (IndexedField, &FieldIndex)> {
+        IndexedField::ALL.into_iter().filter_map(|field| {
+            let maintained = self.optional(&field).map_or(true, |optional| {
+                optional.state.load(Ordering::Acquire) != INDEX_ABSENT
+            });
+
+            maintained.then(|| (field, self.get(&field)))
+        })
+    }
+
+    pub fn insert(&self, person: &Person) {
+        for (field, index) in self.maintained() {
+            for value in field.values(person) {
+                index.insert(value, &person.id);
+            }
+        }
+    }
+
+    pub fn remove(&self, person: &Person) {
+        for (field, index) in self.maintained() {
+            for value in field.values(person) {
+                index.remove(value, &person.id);
+            }
+        }
+    }
+
+    /// The field's index, an optional index that has not been created is empty
+    pub fn get(&self, field: &IndexedField) -> &FieldIndex {
+        match field {
+            IndexedField::FullName => &self.full_name,
+            IndexedField::Email => &self.email,
+            field => {
+                &self
+                    .optional(field)
+                    .expect("Field has an optional index")
+                    .index
+            }
+        }
+    }
+
+    /// Whether the planner can use the field's index
+    pub fn is_ready(&self, field: &IndexedField) -> bool {
+        self.optional(field).map_or(true, |optional| {
+            optional.state.load(Ordering::Acquire) == INDEX_READY
+        })
+    }
+
+    /// Starts maintaining an optional index, the existing versions have to be added with `add_existing`
+    /// before `mark_ready`. False if the field is already indexed
+    pub fn start_building(&self, field: &IndexedField) -> bool {
+        self.optional(field).is_some_and(|optional| {
+            optional
+                .state
+                .compare_exchange(
+                    INDEX_ABSENT,
+                    INDEX_BUILDING,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+        })
+    }
+
+    /// Adds an existing version to an index that is being built
+    pub fn add_existing(&self, field: &IndexedField, person: &Person) {
+        let index = self.get(field);
+
+        for value in field.values(person) {
+            index.insert(value, &person.id);
+        }
+    }
+
+    pub fn mark_ready(&self, field: &IndexedField) {
+        if let Some(optional) = self.optional(field) {
+            optional.state.store(INDEX_READY, Ordering::Release);
+        }
+    }
+
+    /// The optional indexes that have been created (or are being built)
+    pub fn created(&self) -> Vec<IndexedField> {
+        IndexedField::ALL
+            .into_iter()
+            .filter(|field| {
+                self.optional(field)
+                    .is_some_and(|optional| optional.state.load(Ordering::Acquire) != INDEX_ABSENT)
+            })
+            .collect()
+    }
+
+    /// Empties the indexes, created indexes are kept and filled again as the rows are added back
+    pub fn reset(&self) {
+        for field in IndexedField::ALL {
+            self.get(&field).reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_previous_values() {
+        let indexes = PersonIndexes::default();
+
+        let person = Person::new("Luke".to_string(), Some("luke@jedi.org".to_string()));
+
+        indexes.insert(&person);
+        indexes.insert(&Person {
+            full_name: "Luke Skywalker".to_string(),
+            ..person.clone()
+        });
+        // Inserting the same version again is a no-op
+        indexes.insert(&person);
+
+        let full_name = indexes.get(&IndexedField::FullName);
+
+        assert_eq!(full_name.candidates("Luke"), vec![person.id.clone()]);
+        assert_eq!(
+            full_name.candidates("Luke Skywalker"),
+            vec![person.id.clone()]
+        );
+        assert_eq!(full_name.estimate("Leia"), 0);
+        assert_eq!(
+            full_name.statistics(),
+            IndexStatistics {
+                distinct_values: 2,
+                entries: 2
+            }
+        );
+        assert_eq!(
+            indexes.get(&IndexedField::Email).estimate("luke@jedi.org"),
+            1
+        );
+
+        indexes.remove(&person);
+
+        assert!(full_name.candidates("Luke").is_empty());
+        assert_eq!(full_name.statistics().entries, 1);
+        assert_eq!(
+            indexes.get(&IndexedField::Email).estimate("luke@jedi.org"),
+            0
+        );
+
+        indexes.reset();
+
+        assert_eq!(full_name.statistics().entries, 0);
+        assert!(full_name.candidates("Luke").is_empty());
+    }
+
+    #[test]
+    fn optional_indexes_are_only_maintained_once_created() {
+        let indexes = PersonIndexes::default();
+
+        let person = Person {
+            phone_numbers: vec!["1".to_string(), "2".to_string()],
+            ..Person::new("Luke".to_string(), None)
+        };
+
+        indexes.insert(&person);
+
+        assert!(!indexes.is_ready(&IndexedField::PhoneNumber));
+        assert_eq!(indexes.get(&IndexedField::PhoneNumber).estimate("1"), 0);
+
+        // While the index is built, new versions are added but the planner cannot use it yet
+        assert!(indexes.start_building(&IndexedField::PhoneNumber));
+        assert!(!indexes.start_building(&IndexedField::PhoneNumber));
+        assert!(!indexes.is_ready(&IndexedField::PhoneNumber));
+
+        let other = Person {
+            phone_numbers: vec!["1".to_string()],
+            ..Person::new("Leia".to_string(), None)
+        };
+
+        indexes.insert(&other);
+        indexes.add_existing(&IndexedField::PhoneNumber, &person);
+        indexes.mark_ready(&IndexedField::PhoneNumber);
+
+        let phone_number = indexes.get(&IndexedField::PhoneNumber);
+
+        assert!(indexes.is_ready(&IndexedField::PhoneNumber));
+        assert_eq!(phone_number.estimate("1"), 2);
+        assert_eq!(phone_number.candidates("2"), vec![person.id.clone()]);
+        assert_eq!(indexes.created(), vec![IndexedField::PhoneNumber]);
+
+        // Resets keep the created indexes
+        indexes.reset();
+
+        assert_eq!(phone_number.estimate("1"), 0);
+        assert!(indexes.is_ready(&IndexedField::PhoneNumber));
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{
+    index::{IndexedField, PersonIndexes},
+    planner::{QueryPlan, Scan, INDEX_LOOKUP_COST},
+    query::{QueryMatch, QueryPersonData},
+};
+
+/// Value lookups of a field before the advisor has an opinion on it, a handful of ad hoc queries should not
+/// create an index
+const MIN_VALUE_LOOKUPS: usize = 10;
+
+/// How the planned list and count queries filtered on a field
+#[derive(Default)]
+struct FieldUsage {
+    /// Queries that matched the field on a value, the only match an index can serve
+    value_lookups: AtomicUsize,
+    /// Value lookups that read the whole table
+    full_scans: AtomicUsize,
+    /// Queries that matched the field on a prefix or (not) null
+    other_filters: AtomicUsize,
+    /// Rows the value lookups returned, and the rows of the table at the time
+    rows_returned: AtomicUsize,
+    table_rows: AtomicUsize,
+}
+
+/// A point in time copy of a field's usage
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldUsageSnapshot {
+    pub value_lookups: usize,
+    pub full_scans: usize,
+    pub other_filters: usize,
+    pub rows_returned: usize,
+    pub table_rows: usize,
+}
+
+impl FieldUsageSnapshot {
+    /// Fraction of the table the value lookups returned, the lower the more an index helps. When a query filters
+    /// on several fields this is the selectivity of the whole query, so it is a lower bound for the field
+    pub fn selectivity(&self) -> f64 {
+        match self.table_rows {
+            0 => 1.0,
+            table_rows => self.rows_returned as f64 / table_rows as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recommendation {
+    /// The field is looked up by value often enough and selectively enough for an index to beat a full scan
+    Create,
+    /// The field is already indexed
+    Indexed,
+    /// Lookups return too much of the table for an index to beat a full scan
+    NotSelective,
+    /// Not enough value lookups to tell
+    TooFewLookups,
+    /// The field is only matched on prefixes or (not) null, which the indexes cannot serve
+    NotIndexable,
+}
+
+/// The advice for a field that queries filter on
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexAdvice {
+    pub field: IndexedField,
+    pub usage: FieldUsageSnapshot,
+    pub recommendation: Recommendation,
+}
+
+impl IndexAdvice {
+    /// The advice as a KV pair, see `Control::IndexAdvisor`
+    pub fn to_info(&self) -> (String, String) {
+        (
+            self.field.name().to_string(),
+            format!(
+                "{:?}: {} value lookups ({} full scans), {} other filters, {:.2}% of rows returned",
+                self.recommendation,
+                self.usage.value_lookups,
+                self.usage.full_scans,
+                self.usage.other_filters,
+                self.usage.selectivity() * 100.0,
+            ),
+        )
+    }
+}
+
+/// Tracks which fields list and count queries filter on and how selective the lookups are, and recommends the
+/// indexes to create from it, see `Control::IndexAdvisor`
+#[derive(Default)]
+pub struct IndexAdvisor {
+    usage: [FieldUsage; IndexedField::ALL.len()],
+}
+
+fn predicate<'a>(query: &'a QueryPersonData, field: &IndexedField) -> &'a QueryMatch {
+    match field {
+        IndexedField::FullName => &query.full_name,
+        IndexedField::Email => &query.email,
+        IndexedField::Street => &query.address.street,
+        IndexedField::City => &query.address.city,
+        IndexedField::Country => &query.address.country,
+        IndexedField::PhoneNumber => &query.phone_number,
+    }
+}
+
+impl IndexAdvisor {
+    fn usage(&self, field: &IndexedField) -> &FieldUsage {
+        let position = IndexedField::ALL
+            .iter()
+            .position(|f| f == field)
+            .expect("Every field is in ALL");
+
+        &self.usage[position]
+    }
+
+    /// Counts a planned query that returned `rows_returned` rows
+    pub fn record(&self, query: &QueryPersonData, plan: &QueryPlan, rows_returned: usize) {
+        for field in IndexedField::ALL {
+            let usage = self.usage(&field);
+
+            match predicate(query, &field) {
+                QueryMatch::Value(_) => {
+                    usage.value_lookups.fetch_add(1, Ordering::Relaxed);
+                    usage
+                        .rows_returned
+                        .fetch_add(rows_returned, Ordering::Relaxed);
+                    usage
+                        .table_rows
+                        .fetch_add(plan.table_rows, Ordering::Relaxed);
+
+                    if plan.scan == Scan::Full {
+                        usage.full_scans.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                QueryMatch::Prefix(_) | QueryMatch::Null | QueryMatch::NotNull => {
+                    usage.other_filters.fetch_add(1, Ordering::Relaxed);
+                }
+                QueryMatch::Any => {}
+            }
+        }
+    }
+
+    /// Advice for every field that was filtered on, in field order
+    pub fn advise(&self, indexes: &PersonIndexes) -> Vec<IndexAdvice> {
+        IndexedField::ALL
+            .into_iter()
+            .filter_map(|field| {
+                let usage = self.usage(&field);
+
+                let usage = FieldUsageSnapshot {
+                    value_lookups: usage.value_lookups.load(Ordering::Relaxed),
+                    full_scans: usage.full_scans.load(Ordering::Relaxed),
+                    other_filters: usage.other_filters.load(Ordering::Relaxed),
+                    rows_returned: usage.rows_returned.load(Ordering::Relaxed),
+                    table_rows: usage.table_rows.load(Ordering::Relaxed),
+                };
+
+                if usage.value_lookups == 0 && usage.other_filters == 0 {
+                    return None;
+                }
+
+                let recommendation = if indexes.is_ready(&field) {
+                    Recommendation::Indexed
+                } else if usage.value_lookups == 0 {
+                    Recommendation::NotIndexable
+                } else if usage.value_lookups < MIN_VALUE_LOOKUPS {
+                    Recommendation::TooFewLookups
+                } else if usage.selectivity() * (INDEX_LOOKUP_COST as f64) < 1.0 {
+                    Recommendation::Create
+                } else {
+                    Recommendation::NotSelective
+                };
+
+                Some(IndexAdvice {
+                    field,
+                    usage,
+                    recommendation,
+                })
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        for usage in &self.usage {
+            usage.value_lookups.store(0, Ordering::Relaxed);
+            usage.full_scans.store(0, Ordering::Relaxed);
+            usage.other_filters.store(0, Ordering::Relaxed);
+            usage.rows_returned.store(0, Ordering::Relaxed);
+            usage.table_rows.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn by_city(city: &str) -> QueryPersonData {
+        let mut query = QueryPersonData::default();
+        query.address.city = QueryMatch::Value(city.to_string());
+        query
+    }
+
+    fn full_scan(table_rows: usize) -> QueryPlan {
+        QueryPlan {
+            scan: Scan::Full,
+            estimated_rows: table_rows,
+            table_rows,
+        }
+    }
+
+    #[test]
+    fn recommends_indexes_for_selective_lookups() {
+        let advisor = IndexAdvisor::default();
+        let indexes = PersonIndexes::default();
+
+        let mut by_email_prefix = QueryPersonData::default();
+        by_email_prefix.email = QueryMatch::Prefix("luke".to_string());
+
+        for _ in 0..MIN_VALUE_LOOKUPS {
+            advisor.record(&by_city("Tatooine"), &full_scan(1000), 2);
+            advisor.record(&by_email_prefix, &full_scan(1000), 1);
+        }
+
+        let mut by_country = QueryPersonData::default();
+        by_country.address.country = QueryMatch::Value("Naboo".to_string());
+
+        advisor.record(&by_country, &full_scan(1000), 500);
+
+        let advice = advisor.advise(&indexes);
+
+        let recommendations: Vec<(IndexedField, Recommendation)> = advice
+            .iter()
+            .map(|advice| (advice.field, advice.recommendation))
+            .collect();
+
+        assert_eq!(
+            recommendations,
+            vec![
+                (IndexedField::Email, Recommendation::Indexed),
+                (IndexedField::City, Recommendation::Create),
+                (IndexedField::Country, Recommendation::TooFewLookups),
+            ]
+        );
+        assert_eq!(advice[1].usage.full_scans, MIN_VALUE_LOOKUPS);
+        assert_eq!(advice[1].usage.selectivity(), 0.002);
+
+        assert!(indexes.start_building(&IndexedField::City));
+        indexes.mark_ready(&IndexedField::City);
+
+        assert_eq!(
+            advisor.advise(&indexes)[1].recommendation,
+            Recommendation::Indexed
+        );
+
+        advisor.reset();
+
+        assert!(advisor.advise(&indexes).is_empty());
+    }
+}
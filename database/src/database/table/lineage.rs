@@ -0,0 +1,131 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::consts::consts::{EntityId, TransactionId};
+
+use super::{row::PersonVersion, table::PersonTable};
+
+/// Every version of a person at the snapshot, following renames and merges to the rows the person was known
+/// by before and after, see `Lineage`. Returns none if the row does not exist
+///
+/// Versions are ordered by transaction id, when a transaction links two rows the version that hands the person
+/// off (e.g. the delete of a rename) comes before the version that continues it
+pub fn lineage(
+    table: &PersonTable,
+    id: &EntityId,
+    transaction_id: &TransactionId,
+) -> Option<Vec<PersonVersion>> {
+    if !table.person_rows.contains_key(id) {
+        return None;
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut pending = VecDeque::from([id.clone()]);
+    let mut versions = vec![];
+
+    while let Some(id) = pending.pop_front() {
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+
+        let Some(row) = table.person_rows.get(&id) else {
+            continue;
+        };
+
+        let history = row.value().read().unwrap().history();
+
+        for version in history
+            .into_iter()
+            .filter(|version| &version.transaction_id <= transaction_id)
+        {
+            // Links are only followed if they were written at or before the snapshot
+            pending.extend(version.predecessor_id().cloned());
+            pending.extend(version.successor_ids().into_iter().cloned());
+
+            versions.push(version);
+        }
+    }
+
+    versions.sort_by_key(|version| {
+        (
+            version.transaction_id.to_number(),
+            version.predecessor_id().is_some(),
+            version.id.clone(),
+        )
+    });
+
+    Some(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{person::Person, statement::Statement};
+
+    use super::*;
+
+    #[test]
+    fn follows_renames_and_merges() {
+        let table = PersonTable::new();
+
+        let person = Person {
+            id: EntityId("a".to_string()),
+            full_name: "Person".to_string(),
+            email: None,
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
+        };
+        let duplicate = Person {
+            id: EntityId("b".to_string()),
+            full_name: "Duplicate".to_string(),
+            email: None,
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
+        };
+        let renamed_id = EntityId("c".to_string());
+
+        let first = TransactionId::new_first_transaction();
+        let second = first.increment();
+        let third = second.increment();
+
+        let statements = [
+            (Statement::Add(person.clone()), &first),
+            (Statement::Add(duplicate.clone()), &first),
+            (
+                Statement::Rename(person.id.clone(), renamed_id.clone()),
+                &second,
+            ),
+            (
+                Statement::Merge(duplicate.id.clone(), renamed_id.clone(), vec![]),
+                &third,
+            ),
+        ];
+
+        for (statement, transaction_id) in statements {
+            table.apply(statement, transaction_id.clone()).unwrap();
+        }
+
+        // Every id in the lineage returns the same timeline
+        let timeline = lineage(&table, &person.id, &third).unwrap();
+
+        let ids: Vec<(&str, usize)> = timeline
+            .iter()
+            .map(|version| (version.id.0.as_str(), version.transaction_id.to_number()))
+            .collect();
+
+        assert_eq!(
+            ids,
+            vec![("a", 1), ("b", 1), ("a", 2), ("c", 2), ("b", 3), ("c", 3)]
+        );
+
+        assert_eq!(lineage(&table, &duplicate.id, &third), Some(timeline));
+
+        // Merges after the snapshot are not followed
+        assert_eq!(lineage(&table, &renamed_id, &second).unwrap().len(), 3);
+
+        assert_eq!(
+            lineage(&table, &EntityId("missing".to_string()), &third),
+            None
+        );
+    }
+}
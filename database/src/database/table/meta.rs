@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::consts::consts::{EntityId, TransactionId};
+
+/// Metadata of a row, read without cloning its versions, see `Statement::Meta`. Lets clients show when a row
+/// was last updated and flag rows that change often without fetching their history
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EntityMeta {
+    pub id: EntityId,
+    /// Number of versions, including versions that have been spilled to storage
+    pub version_count: usize,
+    /// Transaction of the earliest version that is kept, squashed versions are not counted
+    pub first_transaction_id: TransactionId,
+    pub last_transaction_id: TransactionId,
+    /// Wall clock time of the last change in milliseconds since the epoch. Only known for changes committed since
+    /// the database started, rows restored from a snapshot or the WAL have none until they are changed again
+    pub last_modified_ms: Option<u64>,
+    /// The latest version is a delete
+    pub deleted: bool,
+}
+
+/// Milliseconds since the epoch when a committed transaction last changed the row, see `EntityMeta`
+#[derive(Debug, Default)]
+pub struct LastModified(AtomicU64);
+
+impl LastModified {
+    pub fn get(&self) -> Option<u64> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            modified_ms => Some(modified_ms),
+        }
+    }
+
+    /// Only holds the row's read lock, commits on other threads keep the latest time
+    pub fn stamp(&self, modified_ms: u64) {
+        self.0.fetch_max(modified_ms, Ordering::Relaxed);
+    }
+}
+
+impl Clone for LastModified {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
@@ -1,3 +1,26 @@
+pub mod arrow;
+pub mod attachment;
+pub(crate) mod cold;
+pub mod conflict;
+pub mod constraint;
+pub mod history;
+pub mod index;
+pub mod index_advisor;
+pub mod lineage;
+pub mod meta;
+pub mod outbox;
+pub mod pagination;
+pub mod planner;
+pub mod policy;
+pub mod prepared_query;
 pub mod query;
+pub mod query_builder;
+pub mod retired;
 pub mod row;
+pub mod sequence;
+pub mod squash;
+pub mod statistics;
+pub mod striped;
 pub mod table;
+pub mod view;
+pub mod watermark;
@@ -0,0 +1,270 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{consts::consts::TransactionId, model::statement::Statement};
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Invalid outbox id, expected <transaction id>:<position>: {0}")]
+pub struct InvalidOutboxId(String);
+
+/// Identifies a message by the transaction that enqueued it and its position among the transaction's enqueues, so
+/// a replay of the WAL assigns every message the same id again
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutboxId {
+    pub transaction_id: TransactionId,
+    pub position: usize,
+}
+
+impl fmt::Display for OutboxId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.transaction_id, self.position)
+    }
+}
+
+impl FromStr for OutboxId {
+    type Err = InvalidOutboxId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidOutboxId(s.to_string());
+
+        let (transaction_id, position) = s.split_once(':').ok_or_else(invalid)?;
+
+        Ok(OutboxId {
+            transaction_id: TransactionId(transaction_id.parse().map_err(|_| invalid())?),
+            position: position.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OutboxMessage {
+    pub id: OutboxId,
+    pub topic: String,
+    /// Opaque to the database, e.g. a serialized event
+    pub payload: String,
+}
+
+#[derive(Default)]
+struct OutboxState {
+    /// Committed messages that have not been acknowledged, in enqueue order
+    pending: BTreeMap<OutboxId, OutboxMessage>,
+    /// Messages acknowledged by a transaction that has not committed yet, a rollback makes them pending again
+    acknowledging: BTreeMap<OutboxId, OutboxMessage>,
+}
+
+/// Messages enqueued in the same transaction as the mutations they describe, see `Statement::Enqueue`. A message
+/// is only visible once its transaction commits and is removed once a consumer acknowledges it, see
+/// `Statement::AckOutbox`. Both statements are written to the WAL, so pending messages survive a restart
+#[derive(Default)]
+pub struct Outbox {
+    state: Mutex<OutboxState>,
+}
+
+impl Outbox {
+    /// Pending messages in enqueue order, messages that are being acknowledged are left out
+    pub fn read(&self, topic: Option<&str>, limit: usize) -> Vec<OutboxMessage> {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .values()
+            .filter(|message| topic.map_or(true, |topic| message.topic == topic))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Takes the messages out of the pending messages until the acknowledging transaction commits. Either every
+    /// message is taken or none is, the first message that is not pending (e.g. it has already been acknowledged)
+    /// is returned
+    pub fn acknowledge(&self, ids: &[OutboxId]) -> Result<(), OutboxId> {
+        let mut state = self.state.lock().unwrap();
+
+        let mut taken = vec![];
+
+        for id in ids {
+            match state.pending.remove(id) {
+                Some(message) => taken.push(message),
+                None => {
+                    state.pending.extend(
+                        taken
+                            .into_iter()
+                            .map(|message| (message.id.clone(), message)),
+                    );
+
+                    return Err(id.clone());
+                }
+            }
+        }
+
+        state.acknowledging.extend(
+            taken
+                .into_iter()
+                .map(|message| (message.id.clone(), message)),
+        );
+
+        Ok(())
+    }
+
+    /// Makes the messages of a rolled back acknowledgement pending again
+    pub fn restore_acknowledged(&self, ids: &[OutboxId]) {
+        let mut state = self.state.lock().unwrap();
+
+        for id in ids {
+            if let Some(message) = state.acknowledging.remove(id) {
+                state.pending.insert(id.clone(), message);
+            }
+        }
+    }
+
+    /// Makes the messages enqueued by a committed transaction pending and drops the messages it acknowledged. This
+    /// should only be called once the transaction has been applied
+    pub fn complete(&self, statements: &[Statement], transaction_id: &TransactionId) {
+        // Every commit completes the outbox, most have nothing to complete and skip the lock
+        let completes = statements.iter().any(|statement| {
+            matches!(statement, Statement::Enqueue(..) | Statement::AckOutbox(..))
+        });
+
+        if !completes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        let mut position = 0;
+
+        for statement in statements {
+            match statement {
+                Statement::Enqueue(topic, payload) => {
+                    let id = OutboxId {
+                        transaction_id: transaction_id.clone(),
+                        position,
+                    };
+
+                    position += 1;
+
+                    state.pending.insert(
+                        id.clone(),
+                        OutboxMessage {
+                            id,
+                            topic: topic.clone(),
+                            payload: payload.clone(),
+                        },
+                    );
+                }
+                Statement::AckOutbox(ids) => {
+                    for id in ids {
+                        state.acknowledging.remove(id);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Every pending message, messages that are being acknowledged are included as the acknowledgement has not
+    /// committed
+    pub fn messages(&self) -> Vec<OutboxMessage> {
+        let state = self.state.lock().unwrap();
+
+        let mut messages: Vec<OutboxMessage> = state
+            .pending
+            .values()
+            .chain(state.acknowledging.values())
+            .cloned()
+            .collect();
+
+        messages.sort_by(|a, b| a.id.cmp(&b.id));
+
+        messages
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn restore(&self, messages: Vec<OutboxMessage>) {
+        let mut state = self.state.lock().unwrap();
+
+        state.acknowledging.clear();
+        state.pending = messages
+            .into_iter()
+            .map(|message| (message.id.clone(), message))
+            .collect();
+    }
+
+    pub fn reset(&self) {
+        self.restore(vec![]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enqueue(topic: &str) -> Statement {
+        Statement::Enqueue(topic.to_string(), format!("{} payload", topic))
+    }
+
+    #[test]
+    fn messages_are_acknowledged_once() {
+        let outbox = Outbox::default();
+
+        outbox.complete(
+            &[enqueue("orders"), enqueue("invoices"), enqueue("orders")],
+            &TransactionId(3),
+        );
+
+        let orders = outbox.read(Some("orders"), 10);
+        let ids: Vec<OutboxId> = orders.iter().map(|message| message.id.clone()).collect();
+
+        assert_eq!(
+            ids.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+            vec!["3:0", "3:2"]
+        );
+        assert_eq!("3:2".parse::<OutboxId>(), Ok(ids[1].clone()));
+        assert!("3".parse::<OutboxId>().is_err());
+        assert_eq!(outbox.read(None, 1).len(), 1);
+
+        // An acknowledgement that is in flight hides the messages, a rollback makes them pending again
+        outbox.acknowledge(&ids).unwrap();
+        assert_eq!(outbox.read(None, 10).len(), 1);
+        assert_eq!(outbox.messages().len(), 3);
+
+        outbox.restore_acknowledged(&ids);
+        assert_eq!(outbox.read(None, 10).len(), 3);
+
+        // A message cannot be acknowledged twice, a failed acknowledgement takes nothing
+        outbox.acknowledge(&ids).unwrap();
+        outbox.complete(&[Statement::AckOutbox(ids.clone())], &TransactionId(4));
+
+        assert_eq!(outbox.acknowledge(&ids[..1]), Err(ids[0].clone()));
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox.messages().len(), 1);
+    }
+
+    #[test]
+    fn failed_acknowledgements_take_nothing() {
+        let outbox = Outbox::default();
+
+        outbox.complete(&[enqueue("orders")], &TransactionId(1));
+
+        let pending = outbox.read(None, 10)[0].id.clone();
+        let unknown = OutboxId {
+            transaction_id: TransactionId(2),
+            position: 0,
+        };
+
+        assert_eq!(
+            outbox.acknowledge(&[pending, unknown.clone()]),
+            Err(unknown)
+        );
+        assert_eq!(outbox.len(), 1);
+    }
+}
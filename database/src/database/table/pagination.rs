@@ -0,0 +1,350 @@
+use std::{fmt, ops::Bound, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    model::person::Person,
+};
+
+use super::{
+    policy::Visibility,
+    query::{matches, QueryPersonData},
+    table::PersonTable,
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CursorParseError {
+    #[error("Cursor is malformed, expected <transaction_id>:<entity_id>, got: {0}")]
+    Malformed(String),
+
+    #[error("Cursor has an invalid transaction id: {0}")]
+    InvalidTransactionId(String),
+}
+
+/// A cursor pins a listing to a snapshot (transaction id) and remembers the last key that was returned.
+///
+/// Because reads are MVCC reads at the snapshot transaction id, writes that happen between pages
+/// are not visible, this means pages will not skip or duplicate rows while the table is under load.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub snapshot_transaction_id: TransactionId,
+    pub last_key: EntityId,
+}
+
+/// Cursors are handed to clients as an opaque string, format: `<transaction_id>:<entity_id>`
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.snapshot_transaction_id, self.last_key)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = CursorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Entity ids can contain a ':', transaction ids cannot, so we split on the first occurrence
+        let (transaction_id, last_key) = s
+            .split_once(':')
+            .ok_or_else(|| CursorParseError::Malformed(s.to_string()))?;
+
+        let transaction_id = transaction_id
+            .parse::<usize>()
+            .map_err(|_| CursorParseError::InvalidTransactionId(transaction_id.to_string()))?;
+
+        Ok(Cursor {
+            snapshot_transaction_id: TransactionId(transaction_id),
+            last_key: EntityId(last_key.to_string()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageRequest {
+    /// Where to continue from, if none we start from the beginning of the table at the current snapshot
+    pub cursor: Option<Cursor>,
+    /// Maximum number of items to return in the page
+    pub limit: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Page {
+    pub people: Vec<Person>,
+    /// If none, there are no more items to return
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Returns a page of people ordered by id, rows are read from the snapshot encoded in the cursor
+/// so that iterating through pages yields a consistent view of the table
+pub fn page(
+    table: &PersonTable,
+    query: Option<QueryPersonData>,
+    page_request: PageRequest,
+    transaction_id: &TransactionId,
+    visibility: &Visibility,
+) -> Page {
+    let (snapshot_transaction_id, lower_bound) = match page_request.cursor {
+        Some(cursor) => (
+            cursor.snapshot_transaction_id,
+            Bound::Excluded(cursor.last_key),
+        ),
+        None => (transaction_id.clone(), Bound::Unbounded),
+    };
+
+    // The skip map is ordered by key, so a range scan gives us a stable order to page through
+    let people_in_range = table
+        .person_rows
+        .range((lower_bound, Bound::Unbounded))
+        .filter_map(|v| {
+            v.value()
+                .read()
+                .unwrap()
+                .at_transaction_id(&snapshot_transaction_id)
+        })
+        .filter(|person| visibility.can_see(person))
+        .filter(|person| match &query {
+            Some(q) => matches(person, q),
+            None => true,
+        });
+
+    // Take one more than the limit, this tells us whether there is another page
+    let mut people: Vec<Person> = people_in_range.take(page_request.limit + 1).collect();
+
+    let next_cursor = match people.len() > page_request.limit {
+        true => {
+            people.truncate(page_request.limit);
+
+            people.last().map(|person| Cursor {
+                snapshot_transaction_id: snapshot_transaction_id.clone(),
+                last_key: person.id.clone(),
+            })
+        }
+        false => None,
+    };
+
+    Page {
+        people,
+        next_cursor,
+    }
+}
+
+/// Returns the people with an id in the range at the transaction id, ordered by id. Only the rows in the range are
+/// read, so scanning a small range of a large table is cheap
+pub fn scan(
+    table: &PersonTable,
+    range: (Bound<EntityId>, Bound<EntityId>),
+    limit: Option<usize>,
+    transaction_id: &TransactionId,
+    visibility: &Visibility,
+) -> Vec<Person> {
+    let people_in_range = table
+        .person_rows
+        .range(range)
+        .filter_map(|v| v.value().read().unwrap().at_transaction_id(transaction_id))
+        .filter(|person| visibility.can_see(person));
+
+    match limit {
+        Some(limit) => people_in_range.take(limit).collect(),
+        None => people_in_range.collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::statement::Statement;
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            snapshot_transaction_id: TransactionId(10),
+            last_key: EntityId("id:with:colons".to_string()),
+        };
+
+        assert_eq!(cursor.to_string().parse::<Cursor>(), Ok(cursor));
+    }
+
+    #[test]
+    fn cursor_malformed() {
+        assert_eq!(
+            "no-separator".parse::<Cursor>(),
+            Err(CursorParseError::Malformed("no-separator".to_string()))
+        );
+
+        assert_eq!(
+            "abc:1".parse::<Cursor>(),
+            Err(CursorParseError::InvalidTransactionId("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn pages_are_stable_across_mutations() {
+        // Given a table with 5 people
+        let table = PersonTable::new();
+        let mut transaction_id = TransactionId::new_first_transaction();
+
+        for index in 0..5 {
+            let person = Person {
+                id: EntityId(index.to_string()),
+                full_name: index.to_string(),
+                email: None,
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
+            };
+
+            table
+                .apply(Statement::Add(person), transaction_id.clone())
+                .unwrap();
+
+            transaction_id = transaction_id.increment();
+        }
+
+        // When we read the first page
+        let first_page = page(
+            &table,
+            None,
+            PageRequest {
+                cursor: None,
+                limit: 2,
+            },
+            &transaction_id,
+            &Visibility::unrestricted(),
+        );
+
+        assert_eq!(
+            first_page
+                .people
+                .iter()
+                .map(|p| p.id.to_string())
+                .collect::<Vec<String>>(),
+            vec!["0", "1"]
+        );
+
+        // And a row is removed and a new row is added after the first page has been read
+        transaction_id = transaction_id.increment();
+
+        table
+            .apply(
+                Statement::Remove(EntityId("2".to_string())),
+                transaction_id.clone(),
+            )
+            .unwrap();
+
+        table
+            .apply(
+                Statement::Add(Person {
+                    id: EntityId("11".to_string()),
+                    full_name: "11".to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                }),
+                transaction_id.clone(),
+            )
+            .unwrap();
+
+        // Then the remaining pages should still reflect the original snapshot
+        let mut remaining: Vec<String> = vec![];
+        let mut cursor = first_page.next_cursor;
+
+        while let Some(c) = cursor {
+            let next_page = page(
+                &table,
+                None,
+                PageRequest {
+                    cursor: Some(c),
+                    limit: 2,
+                },
+                &transaction_id,
+                &Visibility::unrestricted(),
+            );
+
+            remaining.extend(next_page.people.iter().map(|p| p.id.to_string()));
+            cursor = next_page.next_cursor;
+        }
+
+        assert_eq!(remaining, vec!["2", "3", "4"]);
+    }
+
+    #[test]
+    fn scans_a_key_range() {
+        let table = PersonTable::new();
+        let mut transaction_id = TransactionId::new_first_transaction();
+
+        for key in ["a", "b", "c", "d", "e"] {
+            let person = Person {
+                id: EntityId(key.to_string()),
+                full_name: key.to_string(),
+                email: None,
+                address: None,
+                phone_numbers: vec![],
+                attachments: vec![],
+            };
+
+            table
+                .apply(Statement::Add(person), transaction_id.clone())
+                .unwrap();
+
+            transaction_id = transaction_id.increment();
+        }
+
+        let before_remove = transaction_id.clone();
+        let removed_at = transaction_id.increment();
+
+        table
+            .apply(
+                Statement::Remove(EntityId("c".to_string())),
+                removed_at.clone(),
+            )
+            .unwrap();
+
+        let ids =
+            |start: Bound<&str>, end: Bound<&str>, limit: Option<usize>, at: &TransactionId| {
+                scan(
+                    &table,
+                    (
+                        start.map(|key| EntityId(key.to_string())),
+                        end.map(|key| EntityId(key.to_string())),
+                    ),
+                    limit,
+                    at,
+                    &Visibility::unrestricted(),
+                )
+                .into_iter()
+                .map(|person| person.id.to_string())
+                .collect::<Vec<String>>()
+            };
+
+        let latest = removed_at.increment();
+
+        // Rows between two ids, the removed row is skipped
+        assert_eq!(
+            ids(Bound::Included("b"), Bound::Excluded("e"), None, &latest),
+            vec!["b", "d"]
+        );
+
+        // The first N rows after a key
+        assert_eq!(
+            ids(Bound::Excluded("a"), Bound::Unbounded, Some(2), &latest),
+            vec!["b", "d"]
+        );
+
+        // Scans read at the transaction id, before the remove the row is still there
+        assert_eq!(
+            ids(
+                Bound::Included("b"),
+                Bound::Excluded("e"),
+                None,
+                &before_remove
+            ),
+            vec!["b", "c", "d"]
+        );
+
+        // An empty or reversed range returns nothing
+        assert!(ids(Bound::Excluded("b"), Bound::Excluded("b"), None, &latest).is_empty());
+        assert!(ids(Bound::Included("d"), Bound::Included("b"), None, &latest).is_empty());
+    }
+}
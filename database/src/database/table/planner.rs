@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{
+    index::{IndexedField, PersonIndexes},
+    query::{QueryMatch, QueryPersonData},
+    statistics::TableStatisticsSnapshot,
+};
+
+/// How many rows of a full scan a single index lookup is worth, index candidates are looked up one by one
+/// while the full scan walks the table in order
+pub(crate) const INDEX_LOOKUP_COST: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scan {
+    /// Every row in the table is read and filtered
+    Full,
+    /// Only the rows in the index under `value` are read, they are still filtered by the whole query
+    Index { field: IndexedField, value: String },
+}
+
+/// The code path list queries are read through, see `ShadowReads`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReadPath {
+    /// The scan chosen by the planner
+    #[default]
+    Planned,
+    /// Every list query is a full scan, the reference the planned scans are compared against
+    FullScan,
+}
+
+/// The scan chosen for a list query and the statistics the choice was based on
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    pub scan: Scan,
+    /// Rows the scan is expected to read
+    pub estimated_rows: usize,
+    /// Rows in the table, including deleted rows
+    pub table_rows: usize,
+}
+
+impl QueryPlan {
+    /// The plan as KV information, see `Control::ExplainQuery`
+    pub fn explain(&self, indexes: &PersonIndexes) -> Vec<(String, String)> {
+        let mut explain = vec![];
+
+        match &self.scan {
+            Scan::Full => explain.push(("Scan".to_string(), "Full".to_string())),
+            Scan::Index { field, value } => {
+                let statistics = indexes.get(field).statistics();
+
+                explain.push(("Scan".to_string(), format!("Index({:?})", field)));
+                explain.push(("IndexValue".to_string(), value.clone()));
+                explain.push((
+                    "IndexDistinctValues".to_string(),
+                    statistics.distinct_values.to_string(),
+                ));
+                explain.push((
+                    "IndexAverageRowsPerValue".to_string(),
+                    format!("{:.2}", statistics.average_rows_per_value()),
+                ));
+            }
+        }
+
+        explain.push(("EstimatedRows".to_string(), self.estimated_rows.to_string()));
+        explain.push(("TableRows".to_string(), self.table_rows.to_string()));
+
+        explain
+    }
+}
+
+/// Chooses how list queries are scanned, and counts the choices for the database stats
+#[derive(Default)]
+pub struct QueryPlanner {
+    full_scans: AtomicUsize,
+    index_scans: AtomicUsize,
+}
+
+impl QueryPlanner {
+    /// An index is used for the most selective value the query matches on, unless the table is small enough that
+    /// reading all of it is cheaper
+    pub fn plan(
+        &self,
+        statistics: &TableStatisticsSnapshot,
+        indexes: &PersonIndexes,
+        query: &Option<QueryPersonData>,
+    ) -> QueryPlan {
+        let table_rows = statistics.live_rows + statistics.deleted_rows;
+
+        let full_scan = QueryPlan {
+            scan: Scan::Full,
+            estimated_rows: table_rows,
+            table_rows,
+        };
+
+        let Some(query) = query else {
+            return full_scan;
+        };
+
+        let cheapest_index = [
+            (IndexedField::FullName, &query.full_name),
+            (IndexedField::Email, &query.email),
+            (IndexedField::Street, &query.address.street),
+            (IndexedField::City, &query.address.city),
+            (IndexedField::Country, &query.address.country),
+            (IndexedField::PhoneNumber, &query.phone_number),
+        ]
+        .into_iter()
+        .filter(|(field, _)| indexes.is_ready(field))
+        .filter_map(|(field, query_match)| match query_match {
+            QueryMatch::Value(value) => {
+                let estimated_rows = indexes.get(&field).estimate(value);
+
+                Some((field, value.clone(), estimated_rows))
+            }
+            // The indexes are keyed by the whole value, a prefix is matched by a scan
+            QueryMatch::Prefix(_) | QueryMatch::Null | QueryMatch::NotNull | QueryMatch::Any => {
+                None
+            }
+        })
+        .min_by_key(|(_, _, estimated_rows)| *estimated_rows);
+
+        match cheapest_index {
+            Some((field, value, estimated_rows))
+                if estimated_rows.saturating_mul(INDEX_LOOKUP_COST) < table_rows =>
+            {
+                QueryPlan {
+                    scan: Scan::Index { field, value },
+                    estimated_rows,
+                    table_rows,
+                }
+            }
+            _ => full_scan,
+        }
+    }
+
+    /// Counts a plan that was run
+    pub fn record(&self, plan: &QueryPlan) {
+        let counter = match plan.scan {
+            Scan::Full => &self.full_scans,
+            Scan::Index { .. } => &self.index_scans,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "ListFullScans".to_string(),
+                self.full_scans.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "ListIndexScans".to_string(),
+                self.index_scans.load(Ordering::Relaxed).to_string(),
+            ),
+        ]
+    }
+}
@@ -0,0 +1,288 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{person::Person, statement::StatementResult};
+
+use super::{
+    query::{matches, QueryPersonData},
+    row::PersonVersionState,
+    view::{PersonField, ViewRow},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PolicyPredicate {
+    /// The person matches the query
+    Query(QueryPersonData),
+    /// The person's email belongs to the domain, e.g. `x.com`
+    EmailDomain(String),
+}
+
+impl PolicyPredicate {
+    fn matches(&self, person: &Person) -> bool {
+        match self {
+            PolicyPredicate::Query(query) => matches(person, query),
+            PolicyPredicate::EmailDomain(domain) => match &person.email {
+                Some(email) => email
+                    .rsplit_once('@')
+                    .is_some_and(|(_, email_domain)| email_domain.eq_ignore_ascii_case(domain)),
+                None => false,
+            },
+        }
+    }
+}
+
+/// A named row security policy, a role that has policies can only read the rows that match at least one of them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RowPolicy {
+    pub name: String,
+    pub role: String,
+    pub predicate: PolicyPredicate,
+}
+
+/// The rows that a single request is allowed to read
+#[derive(Clone, Debug, Default)]
+pub struct Visibility(Option<Vec<PolicyPredicate>>);
+
+impl Visibility {
+    /// Every row is visible, used for requests without a role, roles without policies and internal reads
+    pub fn unrestricted() -> Self {
+        Self(None)
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn can_see(&self, person: &Person) -> bool {
+        match &self.0 {
+            Some(predicates) => predicates.iter().any(|predicate| predicate.matches(person)),
+            None => true,
+        }
+    }
+}
+
+/// Sensitive fields that are masked in the responses of a request, see `FieldEncryptionOptions`
+#[derive(Clone, Debug, Default)]
+pub struct FieldMask(Vec<PersonField>);
+
+impl FieldMask {
+    pub fn new(fields: Vec<PersonField>) -> Self {
+        Self(fields)
+    }
+
+    /// Emails keep their domain so that masked rows are still somewhat recognizable
+    fn mask_value(field: &PersonField, value: String) -> String {
+        match (field, value.rsplit_once('@')) {
+            (PersonField::Email, Some((_, domain))) => format!("****@{}", domain),
+            _ => "****".to_string(),
+        }
+    }
+
+    pub fn mask_person(&self, mut person: Person) -> Person {
+        for field in &self.0 {
+            match field {
+                PersonField::FullName => {
+                    person.full_name = Self::mask_value(field, person.full_name)
+                }
+                PersonField::Email => {
+                    person.email = person.email.map(|email| Self::mask_value(field, email))
+                }
+            }
+        }
+
+        person
+    }
+
+    fn mask_view_row(&self, mut row: ViewRow) -> ViewRow {
+        for field in &self.0 {
+            match field {
+                PersonField::FullName => {
+                    row.full_name = row.full_name.map(|v| Self::mask_value(field, v))
+                }
+                PersonField::Email => row.email = row.email.map(|v| Self::mask_value(field, v)),
+            }
+        }
+
+        row
+    }
+
+    pub fn mask_result(&self, result: StatementResult) -> StatementResult {
+        if self.0.is_empty() {
+            return result;
+        }
+
+        match result {
+            StatementResult::Single(person) => StatementResult::Single(self.mask_person(person)),
+            StatementResult::GetSingle(person) => {
+                StatementResult::GetSingle(person.map(|p| self.mask_person(p)))
+            }
+            StatementResult::List(people) => {
+                StatementResult::List(people.into_iter().map(|p| self.mask_person(p)).collect())
+            }
+            StatementResult::Page(mut page) => {
+                page.people = page
+                    .people
+                    .into_iter()
+                    .map(|p| self.mask_person(p))
+                    .collect();
+                StatementResult::Page(page)
+            }
+            // System tables do not contain people
+            result @ StatementResult::SystemTable(_) => result,
+            StatementResult::View(mut view) => {
+                view.rows = view
+                    .rows
+                    .into_iter()
+                    .map(|r| self.mask_view_row(r))
+                    .collect();
+                StatementResult::View(view)
+            }
+            StatementResult::ListVersion(versions) => StatementResult::ListVersion(
+                versions
+                    .into_iter()
+                    .map(|mut version| {
+                        if let PersonVersionState::State(person) = version.state {
+                            version.state = PersonVersionState::State(self.mask_person(person));
+                        }
+                        version
+                    })
+                    .collect(),
+            ),
+            StatementResult::HistoryPage(mut page) => {
+                page.versions = page
+                    .versions
+                    .into_iter()
+                    .map(|mut version| {
+                        if let PersonVersionState::State(person) = version.state {
+                            version.state = PersonVersionState::State(self.mask_person(person));
+                        }
+                        version
+                    })
+                    .collect();
+                StatementResult::HistoryPage(page)
+            }
+            // Sequence values, table versions, aggregates and outbox messages are not row data, attachments are not
+            //  sensitive fields
+            result @ (StatementResult::SuccessStatus(_)
+            | StatementResult::SequenceValue(_)
+            | StatementResult::TableVersion(_)
+            | StatementResult::Exists(_)
+            | StatementResult::Count(_)
+            | StatementResult::Purged(_)
+            | StatementResult::Attachment(_)
+            | StatementResult::Outbox(_)
+            | StatementResult::NotFound(_)
+            | StatementResult::Meta(_)) => result,
+        }
+    }
+}
+
+/// Row security policies of the person table, enforced at query time for requests that run as a role
+#[derive(Default)]
+pub struct RowPolicies {
+    policies: RwLock<HashMap<String, RowPolicy>>,
+}
+
+impl RowPolicies {
+    /// Creates (or replaces) a policy
+    pub fn create(&self, policy: RowPolicy) {
+        self.policies
+            .write()
+            .unwrap()
+            .insert(policy.name.clone(), policy);
+    }
+
+    /// Returns whether a policy was dropped
+    pub fn drop_policy(&self, name: &str) -> bool {
+        self.policies.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn definitions(&self) -> Vec<RowPolicy> {
+        self.policies.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn reset(&self) {
+        self.policies.write().unwrap().clear();
+    }
+
+    /// The rows visible to a role, requests without a role and roles without any policies can see every row
+    pub fn visibility(&self, role: Option<&str>) -> Visibility {
+        let Some(role) = role else {
+            return Visibility::unrestricted();
+        };
+
+        let predicates: Vec<PolicyPredicate> = self
+            .policies
+            .read()
+            .unwrap()
+            .values()
+            .filter(|policy| policy.role == role)
+            .map(|policy| policy.predicate.clone())
+            .collect();
+
+        match predicates.is_empty() {
+            true => Visibility::unrestricted(),
+            false => Visibility(Some(predicates)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::table::query::QueryMatch;
+
+    #[test]
+    fn role_only_sees_matching_rows() {
+        let policies = RowPolicies::default();
+
+        policies.create(RowPolicy {
+            name: "x_domain".to_string(),
+            role: "tenant_x".to_string(),
+            predicate: PolicyPredicate::EmailDomain("x.com".to_string()),
+        });
+
+        policies.create(RowPolicy {
+            name: "named_admin".to_string(),
+            role: "tenant_x".to_string(),
+            predicate: PolicyPredicate::Query(QueryPersonData {
+                full_name: QueryMatch::Value("Admin".to_string()),
+                email: QueryMatch::Any,
+                ..QueryPersonData::default()
+            }),
+        });
+
+        let x = Person::new("X".to_string(), Some("someone@X.com".to_string()));
+        let y = Person::new("Y".to_string(), Some("someone@y.com".to_string()));
+        let admin = Person::new("Admin".to_string(), None);
+
+        let visibility = policies.visibility(Some("tenant_x"));
+
+        assert!(visibility.is_restricted());
+        assert!(visibility.can_see(&x));
+        assert!(!visibility.can_see(&y));
+        assert!(visibility.can_see(&admin));
+
+        // Roles without policies and requests without a role are not restricted
+        assert!(!policies.visibility(Some("tenant_y")).is_restricted());
+        assert!(!policies.visibility(None).is_restricted());
+
+        assert!(policies.drop_policy("x_domain"));
+        assert!(!policies.visibility(Some("tenant_x")).can_see(&x));
+    }
+
+    #[test]
+    fn masks_sensitive_fields() {
+        let mask = FieldMask::new(vec![PersonField::Email]);
+        let person = Person::new("Name".to_string(), Some("someone@x.com".to_string()));
+
+        let masked = mask.mask_person(person.clone());
+
+        assert_eq!(masked.full_name, "Name");
+        assert_eq!(masked.email, Some("****@x.com".to_string()));
+
+        // Nothing is masked by default
+        assert_eq!(FieldMask::default().mask_person(person.clone()), person);
+    }
+}
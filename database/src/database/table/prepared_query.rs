@@ -0,0 +1,277 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::model::person::Person;
+
+use super::{
+    query::{QueryMatch, QueryPersonData},
+    view::PersonField,
+};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PreparedQueryError {
+    #[error("Parameter is declared more than once: {0}")]
+    DuplicateParameter(String),
+
+    #[error("Parameter binds a field the query already matches: {0}")]
+    ParameterBindsFixedField(String),
+
+    #[error("Missing value for parameter: {0}")]
+    MissingParameter(String),
+
+    #[error("Unknown parameter: {0}")]
+    UnknownParameter(String),
+}
+
+/// The part of the query a parameter's value is bound to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum ParameterField {
+    FullName,
+    Email,
+    Street,
+    City,
+    Country,
+    PhoneNumber,
+}
+
+impl ParameterField {
+    fn query_match<'a>(&self, query: &'a mut QueryPersonData) -> &'a mut QueryMatch {
+        match self {
+            ParameterField::FullName => &mut query.full_name,
+            ParameterField::Email => &mut query.email,
+            ParameterField::Street => &mut query.address.street,
+            ParameterField::City => &mut query.address.city,
+            ParameterField::Country => &mut query.address.country,
+            ParameterField::PhoneNumber => &mut query.phone_number,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueryParameter {
+    pub name: String,
+    pub field: ParameterField,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueryOrder {
+    pub field: PersonField,
+    pub descending: bool,
+}
+
+/// A named, parameterized query that is validated once when it is registered and then executed by name, see
+/// `Statement::ExecutePreparedQuery`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PreparedQueryDefinition {
+    pub name: String,
+    /// The fixed part of the filter, each parameter matches its field against the value it is executed with
+    pub query: QueryPersonData,
+    pub parameters: Vec<QueryParameter>,
+    /// Fields to include in each row of the result, the id is always included
+    pub projection: Vec<PersonField>,
+    /// If none, rows are ordered by id. Ties are broken by id
+    pub order: Option<QueryOrder>,
+}
+
+impl PreparedQueryDefinition {
+    pub fn validate(&self) -> Result<(), PreparedQueryError> {
+        let mut query = self.query.clone();
+
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if self.parameters[..index]
+                .iter()
+                .any(|p| p.name == parameter.name)
+            {
+                return Err(PreparedQueryError::DuplicateParameter(
+                    parameter.name.clone(),
+                ));
+            }
+
+            let field = parameter.field.query_match(&mut query);
+
+            if field != &QueryMatch::Any {
+                return Err(PreparedQueryError::ParameterBindsFixedField(
+                    parameter.name.clone(),
+                ));
+            }
+
+            // Marks the field as bound, so a second parameter on the same field is rejected
+            *field = QueryMatch::NotNull;
+        }
+
+        Ok(())
+    }
+
+    /// The query with every parameter bound to its value, every parameter needs a value
+    pub fn bind(
+        &self,
+        values: &BTreeMap<String, String>,
+    ) -> Result<QueryPersonData, PreparedQueryError> {
+        if let Some(unknown) = values
+            .keys()
+            .find(|name| !self.parameters.iter().any(|p| &p.name == *name))
+        {
+            return Err(PreparedQueryError::UnknownParameter(unknown.clone()));
+        }
+
+        let mut query = self.query.clone();
+
+        for parameter in &self.parameters {
+            let value = values
+                .get(&parameter.name)
+                .ok_or_else(|| PreparedQueryError::MissingParameter(parameter.name.clone()))?;
+
+            *parameter.field.query_match(&mut query) = QueryMatch::Value(value.clone());
+        }
+
+        Ok(query)
+    }
+
+    /// Sorts people that are already ordered by id, the sort is stable so ties stay ordered by id
+    pub fn sort(&self, people: &mut [Person]) {
+        let Some(order) = &self.order else {
+            return;
+        };
+
+        people.sort_by(|a, b| {
+            let ordering = match order.field {
+                PersonField::FullName => a.full_name.cmp(&b.full_name),
+                PersonField::Email => a.email.cmp(&b.email),
+            };
+
+            match order.descending {
+                true => ordering.reverse(),
+                false => ordering,
+            }
+        });
+    }
+}
+
+/// Prepared queries of the person table, only prepared queries can filter the table when the database is
+/// configured with `DatabaseOptions::set_prepared_queries_only`
+#[derive(Default)]
+pub struct PreparedQueries {
+    queries: RwLock<HashMap<String, PreparedQueryDefinition>>,
+}
+
+impl PreparedQueries {
+    /// Creates (or replaces) a prepared query
+    pub fn create(&self, definition: PreparedQueryDefinition) -> Result<(), PreparedQueryError> {
+        definition.validate()?;
+
+        self.queries
+            .write()
+            .unwrap()
+            .insert(definition.name.clone(), definition);
+
+        Ok(())
+    }
+
+    /// Returns whether a prepared query was dropped
+    pub fn drop_query(&self, name: &str) -> bool {
+        self.queries.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<PreparedQueryDefinition> {
+        self.queries.read().unwrap().get(name).cloned()
+    }
+
+    pub fn definitions(&self) -> Vec<PreparedQueryDefinition> {
+        self.queries.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn reset(&self) {
+        self.queries.write().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn by_email() -> PreparedQueryDefinition {
+        PreparedQueryDefinition {
+            name: "by_email".to_string(),
+            query: QueryPersonData {
+                full_name: QueryMatch::NotNull,
+                ..QueryPersonData::default()
+            },
+            parameters: vec![QueryParameter {
+                name: "email".to_string(),
+                field: ParameterField::Email,
+            }],
+            projection: vec![PersonField::FullName],
+            order: None,
+        }
+    }
+
+    #[test]
+    fn binds_parameters() {
+        let definition = by_email();
+
+        assert_eq!(definition.validate(), Ok(()));
+
+        let values = BTreeMap::from([("email".to_string(), "a@x.com".to_string())]);
+
+        assert_eq!(
+            definition.bind(&values),
+            Ok(QueryPersonData {
+                full_name: QueryMatch::NotNull,
+                email: QueryMatch::Value("a@x.com".to_string()),
+                ..QueryPersonData::default()
+            })
+        );
+
+        assert_eq!(
+            definition.bind(&BTreeMap::new()),
+            Err(PreparedQueryError::MissingParameter("email".to_string()))
+        );
+
+        let extra = BTreeMap::from([
+            ("email".to_string(), "a@x.com".to_string()),
+            ("city".to_string(), "Paris".to_string()),
+        ]);
+
+        assert_eq!(
+            definition.bind(&extra),
+            Err(PreparedQueryError::UnknownParameter("city".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        let mut duplicate = by_email();
+        duplicate.parameters.push(QueryParameter {
+            name: "email".to_string(),
+            field: ParameterField::City,
+        });
+
+        assert_eq!(
+            duplicate.validate(),
+            Err(PreparedQueryError::DuplicateParameter("email".to_string()))
+        );
+
+        let mut fixed = by_email();
+        fixed.parameters.push(QueryParameter {
+            name: "name".to_string(),
+            field: ParameterField::FullName,
+        });
+
+        assert_eq!(
+            fixed.validate(),
+            Err(PreparedQueryError::ParameterBindsFixedField(
+                "name".to_string()
+            ))
+        );
+
+        let registry = PreparedQueries::default();
+
+        assert!(registry.create(fixed).is_err());
+        assert!(registry.get("by_email").is_none());
+    }
+}
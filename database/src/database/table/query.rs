@@ -1,21 +1,42 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{consts::consts::TransactionId, model::person::Person};
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    database::activity::CancellationToken,
+    model::person::Person,
+};
 
-use super::table::PersonTable;
+use super::table::{ApplyErrors, PersonTable};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub enum QueryMatch {
     Value(String),
+    /// Values that start with the prefix, null values do not match
+    Prefix(String),
     Null,
     NotNull,
+    #[default]
     Any,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Matches each part of the address, a person without an address has every part null
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct QueryAddressData {
+    pub street: QueryMatch,
+    pub city: QueryMatch,
+    pub country: QueryMatch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct QueryPersonData {
     pub full_name: QueryMatch,
     pub email: QueryMatch,
+    /// Queries written before structured fields were introduced match any address and phone number
+    #[serde(default)]
+    pub address: QueryAddressData,
+    /// A value matches people that have the phone number, null matches people without any phone numbers
+    #[serde(default)]
+    pub phone_number: QueryMatch,
 }
 
 pub fn query(table: &PersonTable, transaction_id: &TransactionId) -> Vec<Person> {
@@ -26,47 +47,130 @@ pub fn query(table: &PersonTable, transaction_id: &TransactionId) -> Vec<Person>
         .collect();
 }
 
+/// Same as `query`, but stops scanning the table once the request has been cancelled
+pub fn query_cancellable(
+    table: &PersonTable,
+    transaction_id: &TransactionId,
+    cancellation: &CancellationToken,
+) -> Result<Vec<Person>, ApplyErrors> {
+    let mut people = vec![];
+
+    for row in table.person_rows.iter() {
+        if cancellation.is_cancelled() {
+            return Err(ApplyErrors::Cancelled);
+        }
+
+        if let Some(person) = row
+            .value()
+            .read()
+            .unwrap()
+            .at_transaction_id(transaction_id)
+        {
+            people.push(person);
+        }
+    }
+
+    Ok(people)
+}
+
+/// Same as `query_cancellable`, but only reads the `candidates` rows, e.g. the rows of an index lookup
+pub fn query_candidates_cancellable(
+    table: &PersonTable,
+    candidates: Vec<EntityId>,
+    transaction_id: &TransactionId,
+    cancellation: &CancellationToken,
+) -> Result<Vec<Person>, ApplyErrors> {
+    let mut people = vec![];
+
+    for id in candidates {
+        if cancellation.is_cancelled() {
+            return Err(ApplyErrors::Cancelled);
+        }
+
+        let Some(row) = table.person_rows.get(&id) else {
+            continue;
+        };
+
+        let person = row
+            .value()
+            .read()
+            .unwrap()
+            .at_transaction_id(transaction_id);
+
+        if let Some(person) = person {
+            people.push(person);
+        }
+    }
+
+    Ok(people)
+}
+
 pub fn filter(people: Vec<Person>, query: QueryPersonData) -> Vec<Person> {
     let filtered_people = people
         .into_iter()
-        .filter(|person| {
-            match &query.full_name {
-                QueryMatch::Value(full_name) => {
-                    if &person.full_name != full_name {
-                        return false;
-                    }
-                }
-                QueryMatch::Any => {}
-                // Fullname is not nullable, this check is static
-                QueryMatch::NotNull => {}
-                QueryMatch::Null => return false,
-            }
+        .filter(|person| matches(person, &query))
+        .collect();
 
-            match &query.email {
-                QueryMatch::Value(email) => match &person.email {
-                    Some(person_email) => {
-                        if person_email != email {
-                            return false;
-                        }
-                    }
-                    None => return false,
-                },
-                QueryMatch::Null => {
-                    if person.email.is_some() {
-                        return false;
-                    }
-                }
-                QueryMatch::NotNull => {
-                    if person.email.is_none() {
-                        return false;
-                    }
-                }
-                QueryMatch::Any => {}
+    return filtered_people;
+}
+
+/// Whether a single person satisfies the query
+pub fn matches(person: &Person, query: &QueryPersonData) -> bool {
+    match &query.full_name {
+        QueryMatch::Value(full_name) => {
+            if &person.full_name != full_name {
+                return false;
+            }
+        }
+        QueryMatch::Prefix(prefix) => {
+            if !person.full_name.starts_with(prefix.as_str()) {
+                return false;
             }
+        }
+        QueryMatch::Any => {}
+        // Fullname is not nullable, this check is static
+        QueryMatch::NotNull => {}
+        QueryMatch::Null => return false,
+    }
 
-            return true;
-        })
-        .collect();
+    if !matches_optional(person.email.as_ref(), &query.email) {
+        return false;
+    }
 
-    return filtered_people;
+    let address = person.address.as_ref();
+
+    if !matches_optional(
+        address.and_then(|a| a.street.as_ref()),
+        &query.address.street,
+    ) || !matches_optional(address.and_then(|a| a.city.as_ref()), &query.address.city)
+        || !matches_optional(
+            address.and_then(|a| a.country.as_ref()),
+            &query.address.country,
+        )
+    {
+        return false;
+    }
+
+    match &query.phone_number {
+        QueryMatch::Value(phone_number) => person.phone_numbers.contains(phone_number),
+        QueryMatch::Prefix(prefix) => person
+            .phone_numbers
+            .iter()
+            .any(|phone_number| phone_number.starts_with(prefix.as_str())),
+        QueryMatch::Null => person.phone_numbers.is_empty(),
+        QueryMatch::NotNull => !person.phone_numbers.is_empty(),
+        QueryMatch::Any => true,
+    }
+}
+
+fn matches_optional(value: Option<&String>, query: &QueryMatch) -> bool {
+    match query {
+        QueryMatch::Value(expected) => value == Some(expected),
+        QueryMatch::Prefix(prefix) => {
+            value.map_or(false, |value| value.starts_with(prefix.as_str()))
+        }
+        QueryMatch::Null => value.is_none(),
+        QueryMatch::NotNull => value.is_some(),
+        QueryMatch::Any => true,
+    }
 }
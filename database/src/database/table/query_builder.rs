@@ -0,0 +1,256 @@
+use std::marker::PhantomData;
+
+use crate::model::statement::Statement;
+
+use super::{
+    pagination::PageRequest,
+    query::{QueryMatch, QueryPersonData},
+};
+
+/// Entry point of the query builder, e.g.
+/// `Query::person().full_name().starts_with("Da").email().not_null().limit(50)`
+pub struct Query;
+
+impl Query {
+    pub fn person() -> PersonQuery {
+        PersonQuery::default()
+    }
+}
+
+/// A query of people built one field at a time, compiles to a `QueryPersonData`. Fields that are not matched
+/// match anything, matching a field again replaces the previous match. Run it with `RequestManager::send_query`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PersonQuery {
+    query: QueryPersonData,
+    limit: Option<usize>,
+}
+
+impl PersonQuery {
+    pub fn full_name(self) -> FieldQuery<FullName> {
+        FieldQuery::new(self)
+    }
+
+    pub fn email(self) -> FieldQuery<Email> {
+        FieldQuery::new(self)
+    }
+
+    pub fn street(self) -> FieldQuery<Street> {
+        FieldQuery::new(self)
+    }
+
+    pub fn city(self) -> FieldQuery<City> {
+        FieldQuery::new(self)
+    }
+
+    pub fn country(self) -> FieldQuery<Country> {
+        FieldQuery::new(self)
+    }
+
+    /// Matches any of the person's phone numbers, null matches people without phone numbers
+    pub fn phone_number(self) -> FieldQuery<PhoneNumber> {
+        FieldQuery::new(self)
+    }
+
+    /// Returns at most `limit` people, ordered by id
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn get_limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn query_data(&self) -> &QueryPersonData {
+        &self.query
+    }
+
+    /// `Statement::List`, or the first page of `Statement::ListPage` if the query is limited
+    pub fn to_statement(&self) -> Statement {
+        match self.limit {
+            Some(limit) => Statement::ListPage(
+                Some(self.query.clone()),
+                PageRequest {
+                    cursor: None,
+                    limit,
+                },
+            ),
+            None => Statement::List(Some(self.query.clone())),
+        }
+    }
+
+    /// `Statement::Count` of the people the query matches, the limit does not apply
+    pub fn to_count_statement(&self) -> Statement {
+        Statement::Count(Some(self.query.clone()))
+    }
+}
+
+impl From<PersonQuery> for QueryPersonData {
+    fn from(query: PersonQuery) -> Self {
+        query.query
+    }
+}
+
+/// A field of `QueryPersonData` that the builder can match on
+pub trait QueryField {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch;
+}
+
+/// A field that can be null, full names are always set so they cannot be matched against null
+pub trait NullableField: QueryField {}
+
+pub struct FullName;
+pub struct Email;
+pub struct Street;
+pub struct City;
+pub struct Country;
+pub struct PhoneNumber;
+
+impl QueryField for FullName {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch {
+        &mut query.full_name
+    }
+}
+
+impl QueryField for Email {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch {
+        &mut query.email
+    }
+}
+
+impl QueryField for Street {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch {
+        &mut query.address.street
+    }
+}
+
+impl QueryField for City {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch {
+        &mut query.address.city
+    }
+}
+
+impl QueryField for Country {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch {
+        &mut query.address.country
+    }
+}
+
+impl QueryField for PhoneNumber {
+    fn query_match(query: &mut QueryPersonData) -> &mut QueryMatch {
+        &mut query.phone_number
+    }
+}
+
+impl NullableField for Email {}
+impl NullableField for Street {}
+impl NullableField for City {}
+impl NullableField for Country {}
+impl NullableField for PhoneNumber {}
+
+/// A field of the query that is being matched, every match returns the query to continue building it
+pub struct FieldQuery<F: QueryField> {
+    query: PersonQuery,
+    field: PhantomData<F>,
+}
+
+impl<F: QueryField> FieldQuery<F> {
+    fn new(query: PersonQuery) -> Self {
+        Self {
+            query,
+            field: PhantomData,
+        }
+    }
+
+    fn matching(mut self, query_match: QueryMatch) -> PersonQuery {
+        *F::query_match(&mut self.query.query) = query_match;
+        self.query
+    }
+
+    pub fn equals(self, value: impl Into<String>) -> PersonQuery {
+        self.matching(QueryMatch::Value(value.into()))
+    }
+
+    pub fn starts_with(self, prefix: impl Into<String>) -> PersonQuery {
+        self.matching(QueryMatch::Prefix(prefix.into()))
+    }
+}
+
+impl<F: NullableField> FieldQuery<F> {
+    pub fn is_null(self) -> PersonQuery {
+        self.matching(QueryMatch::Null)
+    }
+
+    pub fn not_null(self) -> PersonQuery {
+        self.matching(QueryMatch::NotNull)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::table::query::{matches, QueryAddressData},
+        model::person::Person,
+    };
+
+    use super::*;
+
+    #[test]
+    fn compiles_to_query_data() {
+        let query = Query::person()
+            .full_name()
+            .starts_with("Da")
+            .email()
+            .not_null()
+            .city()
+            .equals("Sydney")
+            .limit(50);
+
+        assert_eq!(
+            query.query_data(),
+            &QueryPersonData {
+                full_name: QueryMatch::Prefix("Da".to_string()),
+                email: QueryMatch::NotNull,
+                address: QueryAddressData {
+                    city: QueryMatch::Value("Sydney".to_string()),
+                    ..Default::default()
+                },
+                ..QueryPersonData::default()
+            }
+        );
+
+        assert!(matches!(
+            query.to_statement(),
+            Statement::ListPage(Some(_), PageRequest { limit: 50, .. })
+        ));
+        assert!(matches!(
+            Query::person().email().is_null().to_statement(),
+            Statement::List(Some(_))
+        ));
+
+        // Matching a field again replaces the previous match
+        let query = Query::person().email().is_null().email().equals("a@x.com");
+        assert_eq!(
+            query.query_data().email,
+            QueryMatch::Value("a@x.com".to_string())
+        );
+    }
+
+    #[test]
+    fn prefixes_match_the_start_of_the_value() {
+        let dale = Person::new("Dale".to_string(), Some("dale@x.com".to_string()));
+        let query: QueryPersonData = Query::person()
+            .full_name()
+            .starts_with("Da")
+            .email()
+            .starts_with("dale@")
+            .into();
+
+        assert!(matches(&dale, &query));
+        assert!(!matches(
+            &Person::new("Adam".to_string(), Some("dale@x.com".to_string())),
+            &query
+        ));
+        assert!(!matches(&Person::new("Dan".to_string(), None), &query));
+    }
+}
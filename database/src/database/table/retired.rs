@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::consts::consts::TransactionId;
+
+/// Transactions start at 1, so 0 is never a transaction
+const NOT_RETIRED: usize = 0;
+
+/// The transaction whose version of a row has been rolled back, but has not been removed from the row yet.
+///
+/// A rollback only holds the row's read lock, so it does not wait on (or block) concurrent readers. It retires
+/// the version instead, every read skips it, and the version is reclaimed once the row's write lock is free, see
+/// `PersonTable::reclaim_retired_versions`. A row has at most one retired version at a time
+#[derive(Debug, Default)]
+pub struct RetiredVersion(AtomicUsize);
+
+impl RetiredVersion {
+    pub fn get(&self) -> Option<TransactionId> {
+        match self.0.load(Ordering::Acquire) {
+            NOT_RETIRED => None,
+            transaction_id => Some(TransactionId(transaction_id)),
+        }
+    }
+
+    /// Returns false if the row already has a retired version
+    pub fn retire(&self, transaction_id: &TransactionId) -> bool {
+        self.0
+            .compare_exchange(
+                NOT_RETIRED,
+                transaction_id.to_number(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
+
+    /// Clears the retired version, only the holder of the row's write lock can reclaim it
+    pub fn take(&mut self) -> Option<TransactionId> {
+        match std::mem::replace(self.0.get_mut(), NOT_RETIRED) {
+            NOT_RETIRED => None,
+            transaction_id => Some(TransactionId(transaction_id)),
+        }
+    }
+}
+
+impl Clone for RetiredVersion {
+    fn clone(&self) -> Self {
+        Self(AtomicUsize::new(self.0.load(Ordering::Acquire)))
+    }
+}
@@ -1,11 +1,40 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::{
     consts::consts::{EntityId, TransactionId, VersionId},
-    model::person::Person,
+    database::utils::crash::{crash_database, DatabaseCrash},
+    model::person::{Attachment, Person},
+    persistence::storage::StorageResult,
+};
+
+use super::{
+    cold::ColdVersionStore,
+    conflict::{Conflict, ConflictRecord, ConflictResolution, DivergentVersion},
+    meta::{EntityMeta, LastModified},
+    retired::RetiredVersion,
+    squash::{squash_versions, HistorySquash},
+    table::ApplyErrors,
+    view::PersonField,
 };
 
-use super::table::ApplyErrors;
+/// Broken MVCC invariants, only checked when `DatabaseOptions::paranoid_checks` is enabled
+#[derive(Error, Debug)]
+pub enum InvariantViolation {
+    #[error("Version {1} of {0} was written by transaction {2}, which is after the snapshot {3}")]
+    VisibleBeyondSnapshot(EntityId, VersionId, TransactionId, TransactionId),
+
+    #[error("Versions of {0} are not strictly increasing: {1} is followed by {2}")]
+    VersionNotIncreasing(EntityId, VersionId, VersionId),
+
+    #[error("Versions of {0} are not ordered by transaction: {1} is followed by {2}")]
+    TransactionNotIncreasing(EntityId, TransactionId, TransactionId),
+
+    #[error("Version {1} of {0} was written by rolled back transaction {2}")]
+    DanglingVersion(EntityId, VersionId, TransactionId),
+}
 
 #[derive(Debug)]
 pub struct ApplyUpdateResult {
@@ -18,24 +47,159 @@ pub struct ApplyDeleteResult {
     pub previous: Person,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct UpdatePersonData {
     pub full_name: UpdateStatement,
     pub email: UpdateStatement,
+    /// Updates written before structured fields were introduced do not change them
+    #[serde(default)]
+    pub address: UpdateAddressStatement,
+    #[serde(default)]
+    pub phone_numbers: UpdateListStatement,
+    /// Updates written before attachments were introduced do not change them
+    #[serde(default)]
+    pub attachments: UpdateAttachmentStatement,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub enum UpdateStatement {
     Set(String),
     Unset,
+    #[default]
+    NoChanges,
+}
+
+impl UpdateStatement {
+    fn apply(&self, value: &mut Option<String>) {
+        match self {
+            UpdateStatement::Set(v) => *value = Some(v.clone()),
+            UpdateStatement::Unset => *value = None,
+            UpdateStatement::NoChanges => {}
+        }
+    }
+}
+
+/// Each part of the address is updated on its own
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UpdateAddressData {
+    pub street: UpdateStatement,
+    pub city: UpdateStatement,
+    pub country: UpdateStatement,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub enum UpdateAddressStatement {
+    /// Updates the parts of the address, a person without an address gets one. The address is removed if
+    /// every part of it ends up unset
+    Set(UpdateAddressData),
+    Unset,
+    #[default]
+    NoChanges,
+}
+
+/// Updates a list-valued field in place, so that clients do not have to read the list and write all of it back.
+/// A field without any elements is an empty list, it is never null
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub enum UpdateListStatement {
+    /// Replaces every element
+    Set(Vec<String>),
+    /// Appends the elements the list does not contain yet
+    Add(Vec<String>),
+    /// Removes the elements, elements the list does not contain are ignored
+    Remove(Vec<String>),
+    /// Replaces the element at the index, the update is rolled back if the index is out of bounds
+    ReplaceAt(usize, String),
+    /// Applies the statements in order, all of them are written as a single version. If one of them fails none
+    /// of them are applied
+    Many(Vec<UpdateListStatement>),
+    #[default]
     NoChanges,
 }
 
-/// Used to clean up the table if there are no versions left
-// I think it is better to have a non-optional version, and then all other versions captured in a vector
-pub enum DropRow {
-    VersionExist,
-    NoVersionsExist,
+impl UpdateListStatement {
+    /// `field` names the list in errors
+    fn apply(&self, field: &str, list: &mut Vec<String>) -> Result<(), ApplyErrors> {
+        match self {
+            UpdateListStatement::Set(elements) => *list = elements.clone(),
+            UpdateListStatement::Add(elements) => {
+                for element in elements {
+                    if !list.contains(element) {
+                        list.push(element.clone());
+                    }
+                }
+            }
+            UpdateListStatement::Remove(elements) => {
+                list.retain(|element| !elements.contains(element))
+            }
+            UpdateListStatement::ReplaceAt(index, element) => match list.get_mut(*index) {
+                Some(current) => *current = element.clone(),
+                None => {
+                    return Err(ApplyErrors::ListIndexOutOfBounds(
+                        field.to_string(),
+                        *index,
+                        list.len(),
+                    ))
+                }
+            },
+            UpdateListStatement::Many(statements) => {
+                for statement in statements {
+                    statement.apply(field, list)?;
+                }
+            }
+            UpdateListStatement::NoChanges => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Attaches or detaches a single payload, the bytes must have been written to the `AttachmentStore` first
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub enum UpdateAttachmentStatement {
+    /// Adds the attachment, an attachment with the same name is replaced
+    Attach(Attachment),
+    /// Removes the attachment with the name, the update is rolled back if there is none
+    Detach(String),
+    #[default]
+    NoChanges,
+}
+
+impl UpdateAttachmentStatement {
+    fn apply(&self, attachments: &mut Vec<Attachment>) -> Result<(), ApplyErrors> {
+        match self {
+            UpdateAttachmentStatement::Attach(attachment) => {
+                match attachments
+                    .iter_mut()
+                    .find(|current| current.name == attachment.name)
+                {
+                    Some(current) => *current = attachment.clone(),
+                    None => attachments.push(attachment.clone()),
+                }
+            }
+            UpdateAttachmentStatement::Detach(name) => {
+                let count = attachments.len();
+
+                attachments.retain(|attachment| &attachment.name != name);
+
+                if attachments.len() == count {
+                    return Err(ApplyErrors::AttachmentDoesNotExist(name.clone()));
+                }
+            }
+            UpdateAttachmentStatement::NoChanges => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// How the latest version of a row changed when a version was rolled back, see `TableStatistics::version_rolled_back`
+#[derive(Debug, PartialEq)]
+pub struct RollbackChange {
+    /// The latest version was a delete before the rollback
+    pub previous_deleted: bool,
+    /// The latest version is a delete after the rollback, none if there are no versions left. The row must be
+    /// dropped then, or we will create bugs where we think a row exists when it does not
+    pub current_deleted: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -44,12 +208,33 @@ pub enum PersonVersionState {
     Delete,
 }
 
+/// Links a version to a version of another row, so a person can be followed across id changes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Lineage {
+    /// The person was moved here from another id, see `Statement::Rename`
+    RenamedFrom(EntityId),
+    /// The person was moved to another id, only set on delete versions
+    RenamedTo(EntityId),
+    /// Another person was merged into this one, see `Statement::Merge`
+    MergedFrom(EntityId),
+    /// The person was merged into another person, only set on delete versions
+    MergedInto(EntityId),
+    /// The person was split off of another person, see `Statement::Split`
+    SplitFrom(EntityId),
+    /// The person was split into several people, only set on delete versions
+    SplitInto(Vec<EntityId>),
+    /// The version resolved a conflict with a divergent version of the same row, see `Statement::ResolveConflict`
+    ConflictResolved(Box<ConflictRecord>),
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PersonVersion {
     pub id: EntityId,
     pub state: PersonVersionState,
     pub version: VersionId, // Version Ids are re-indexed back to 1 on a restore
     pub transaction_id: TransactionId,
+    #[serde(default)]
+    pub lineage: Option<Lineage>,
 }
 
 impl PersonVersion {
@@ -59,23 +244,88 @@ impl PersonVersion {
             PersonVersionState::Delete => None,
         }
     }
+
+    /// The id the entity was known by before this version, if the version continues another row
+    pub fn predecessor_id(&self) -> Option<&EntityId> {
+        match &self.lineage {
+            Some(Lineage::RenamedFrom(id) | Lineage::MergedFrom(id) | Lineage::SplitFrom(id)) => {
+                Some(id)
+            }
+            Some(
+                Lineage::RenamedTo(_)
+                | Lineage::MergedInto(_)
+                | Lineage::SplitInto(_)
+                | Lineage::ConflictResolved(_),
+            )
+            | None => None,
+        }
+    }
+
+    /// The ids the entity is known by after this version, empty unless the entity continues in other rows
+    pub fn successor_ids(&self) -> Vec<&EntityId> {
+        match &self.lineage {
+            Some(Lineage::RenamedTo(id) | Lineage::MergedInto(id)) => vec![id],
+            Some(Lineage::SplitInto(ids)) => ids.iter().collect(),
+            Some(
+                Lineage::RenamedFrom(_)
+                | Lineage::MergedFrom(_)
+                | Lineage::SplitFrom(_)
+                | Lineage::ConflictResolved(_),
+            )
+            | None => vec![],
+        }
+    }
+}
+
+/// Versions of a row that have been spilled to storage, they are always older than the in-memory versions
+#[derive(Clone, Debug)]
+struct ColdVersions {
+    store: Arc<ColdVersionStore>,
+    chunk_count: usize,
+    version_count: usize,
+    /// Transaction of the earliest spilled version
+    first_transaction_id: TransactionId,
+}
+
+impl ColdVersions {
+    /// Loads every spilled version, earliest version first
+    fn load(&self, id: &EntityId) -> Vec<PersonVersion> {
+        let versions: StorageResult<Vec<Vec<PersonVersion>>> = (0..self.chunk_count)
+            .map(|chunk_index| self.store.read_chunk(id, chunk_index))
+            .collect();
+
+        match versions {
+            Ok(chunks) => chunks.into_iter().flatten().collect(),
+            Err(e) => crash_database(DatabaseCrash::UnreadableColdVersions(e)),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct PersonRow {
     /// Earliest versions are at beginning, latest version is last
     versions: Vec<PersonVersion>,
+    /// Older versions that have been spilled out of memory, see `spill_cold_versions`
+    cold: Option<ColdVersions>,
+    /// A version that has been rolled back but not reclaimed yet, reads skip it, see `retire_version`
+    retired: RetiredVersion,
+    /// When a committed transaction last changed the row, see `EntityMeta`
+    last_modified: LastModified,
 }
 
 impl PersonRow {
-    pub fn new(person: Person, transaction_id: TransactionId) -> Self {
+    pub fn new(person: Person, transaction_id: TransactionId, lineage: Option<Lineage>) -> Self {
         PersonRow {
             versions: vec![PersonVersion {
                 id: person.id.clone(),
                 state: PersonVersionState::State(person),
                 version: VersionId::new_first_version(),
                 transaction_id,
+                lineage,
             }],
+            cold: None,
+            retired: RetiredVersion::default(),
+            last_modified: LastModified::default(),
         }
     }
 
@@ -83,6 +333,9 @@ impl PersonRow {
     pub fn from_restore(version: PersonVersion) -> Self {
         PersonRow {
             versions: vec![version],
+            cold: None,
+            retired: RetiredVersion::default(),
+            last_modified: LastModified::default(),
         }
     }
 
@@ -91,22 +344,19 @@ impl PersonRow {
         &mut self,
         person: Person,
         transaction_id: TransactionId,
+        lineage: Option<Lineage>,
     ) -> Result<(), ApplyErrors> {
-        let current_version = self.current_version().clone();
+        self.reclaim();
 
         // Prevents adding an item that already exists
-        if &current_version.state != &PersonVersionState::Delete {
+        if self.current_version().state != PersonVersionState::Delete {
             return Err(ApplyErrors::CannotCreateWhenAlreadyExists(
                 person.id.clone(),
             ));
         }
 
         // Apply
-        self.apply_new_version(
-            &current_version,
-            PersonVersionState::State(person),
-            transaction_id,
-        );
+        self.apply_new_version(PersonVersionState::State(person), transaction_id, lineage);
 
         Ok(())
     }
@@ -117,14 +367,14 @@ impl PersonRow {
         update: UpdatePersonData,
         transaction_id: TransactionId,
     ) -> Result<ApplyUpdateResult, ApplyErrors> {
-        let previous_version = self.current_version().clone();
+        self.reclaim();
 
         // Verify
-        let previous_person = match previous_version.state.clone() {
+        let previous_person = match &self.current_version().state {
             PersonVersionState::Delete => {
                 return Err(ApplyErrors::CannotUpdateDoesNotExist(id.clone()))
             }
-            PersonVersionState::State(s) => s,
+            PersonVersionState::State(s) => s.clone(),
         };
 
         let mut current_person = previous_person.clone();
@@ -139,17 +389,33 @@ impl PersonRow {
             UpdateStatement::NoChanges => {}
         }
 
-        match &update.email {
-            UpdateStatement::Set(email) => current_person.email = Some(email.clone()),
-            UpdateStatement::Unset => current_person.email = None,
-            UpdateStatement::NoChanges => {}
+        update.email.apply(&mut current_person.email);
+
+        match &update.address {
+            UpdateAddressStatement::Set(address_update) => {
+                let mut address = current_person.address.take().unwrap_or_default();
+
+                address_update.street.apply(&mut address.street);
+                address_update.city.apply(&mut address.city);
+                address_update.country.apply(&mut address.country);
+
+                current_person.address = (!address.is_empty()).then_some(address);
+            }
+            UpdateAddressStatement::Unset => current_person.address = None,
+            UpdateAddressStatement::NoChanges => {}
         }
 
+        update
+            .phone_numbers
+            .apply("Phone Numbers", &mut current_person.phone_numbers)?;
+
+        update.attachments.apply(&mut current_person.attachments)?;
+
         // Apply
         self.apply_new_version(
-            &previous_version,
             PersonVersionState::State(current_person.clone()),
             transaction_id,
+            None,
         );
 
         Ok(ApplyUpdateResult {
@@ -162,64 +428,248 @@ impl PersonRow {
         &mut self,
         id: &EntityId,
         transaction_id: TransactionId,
+        lineage: Option<Lineage>,
     ) -> Result<ApplyDeleteResult, ApplyErrors> {
-        let current_version = self.current_version().clone();
+        self.reclaim();
 
         // Verify
-        let previous_person = match current_version.clone().state {
-            PersonVersionState::State(s) => s,
+        let previous_person = match &self.current_version().state {
+            PersonVersionState::State(s) => s.clone(),
             PersonVersionState::Delete => {
                 return Err(ApplyErrors::CannotDeleteDoesNotExist(id.clone()));
             }
         };
 
         // Apply
-        self.apply_new_version(&current_version, PersonVersionState::Delete, transaction_id);
+        self.apply_new_version(PersonVersionState::Delete, transaction_id, lineage);
 
         Ok(ApplyDeleteResult {
             previous: previous_person,
         })
     }
 
+    /// Records that `merged` was merged into this row, the fields listed in `fields` are taken from `merged`
+    /// and every other field keeps its current value
+    pub fn apply_merge(
+        &mut self,
+        id: &EntityId,
+        merged: &Person,
+        fields: &[PersonField],
+        transaction_id: TransactionId,
+    ) -> Result<Person, ApplyErrors> {
+        self.reclaim();
+
+        // Verify
+        let mut person = match &self.current_version().state {
+            PersonVersionState::State(s) => s.clone(),
+            PersonVersionState::Delete => {
+                return Err(ApplyErrors::CannotMergeDoesNotExist(id.clone()));
+            }
+        };
+
+        for field in fields {
+            match field {
+                PersonField::FullName => person.full_name = merged.full_name.clone(),
+                PersonField::Email => person.email = merged.email.clone(),
+            }
+        }
+
+        // Apply
+        self.apply_new_version(
+            PersonVersionState::State(person.clone()),
+            transaction_id,
+            Some(Lineage::MergedFrom(merged.id.clone())),
+        );
+
+        Ok(person)
+    }
+
+    /// Resolves the current version against a divergent version and records the resolution as a new version,
+    /// returns the previous and the resolved state. A version is added even if the current version wins, so the
+    /// losing side is kept in the lineage
+    pub fn apply_resolution(
+        &mut self,
+        id: &EntityId,
+        conflict: Conflict,
+        resolution: &ConflictResolution,
+        transaction_id: TransactionId,
+    ) -> Result<(PersonVersionState, PersonVersionState), ApplyErrors> {
+        self.reclaim();
+
+        let current_state = self.current_version().state.clone();
+
+        // Verify
+        if let PersonVersionState::State(person) = &conflict.remote.state {
+            if &person.id != id {
+                return Err(ApplyErrors::CannotResolveDifferentRecord(
+                    id.clone(),
+                    person.id.clone(),
+                ));
+            }
+        }
+
+        let local = DivergentVersion {
+            state: current_state.clone(),
+            timestamp: conflict.local_timestamp,
+        };
+
+        let resolved = match resolution.resolve(&local, &conflict.remote) {
+            PersonVersionState::State(person) if &person.id != id => {
+                return Err(ApplyErrors::CannotResolveDifferentRecord(
+                    id.clone(),
+                    person.id,
+                ))
+            }
+            resolved => resolved,
+        };
+
+        let record = ConflictRecord {
+            strategy: resolution.name().to_string(),
+            local,
+            remote: conflict.remote,
+        };
+
+        // Apply
+        self.apply_new_version(
+            resolved.clone(),
+            transaction_id,
+            Some(Lineage::ConflictResolved(Box::new(record))),
+        );
+
+        Ok((current_state, resolved))
+    }
+
+    /// Appends a version after the current version. Only the id and version number of the current version are
+    /// read, it is not cloned, as the row's write lock is held for the whole append
     fn apply_new_version(
         &mut self,
-        current_version: &PersonVersion,
         new_state: PersonVersionState,
         transaction_id: TransactionId,
+        lineage: Option<Lineage>,
     ) {
-        self.versions.push(PersonVersion {
+        let current_version = self.current_version();
+
+        let version = PersonVersion {
             id: current_version.id.clone(),
             state: new_state,
             version: current_version.version.increment(),
             transaction_id,
-        });
+            lineage,
+        };
+
+        self.versions.push(version);
     }
 
     pub fn current_version(&self) -> &PersonVersion {
         // A row is always created with a version AND the row should be dropped if there are no versions (see: rollback_version)
-        self.versions
-            .last()
+        //  a version is only retired if it is not the only one (see: retire_version)
+        self.visible_versions()
+            .next_back()
             .expect("Will always exist a current version, if not there is a bug")
     }
 
+    /// The in-memory versions, without the retired version
+    fn visible_versions(&self) -> impl DoubleEndedIterator<Item = &PersonVersion> {
+        let retired_index = self.retired_index();
+
+        self.versions
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| Some(*index) != retired_index)
+            .map(|(_, version)| version)
+    }
+
+    /// The retired version is the latest version the rolled back transaction wrote to the row
+    fn retired_index(&self) -> Option<usize> {
+        let transaction_id = self.retired.get()?;
+
+        self.versions
+            .iter()
+            .rposition(|version| version.transaction_id == transaction_id)
+    }
+
     pub fn current_state(&self) -> Option<Person> {
         self.current_version().get_person().clone()
     }
 
-    /// Drop row means that we have rolled back to the point where there are no versions. We must clean up the row OR we
-    ///    we will create bugs where we think a row exists when it does not
-    pub fn rollback_version(&mut self) -> (PersonVersion, DropRow) {
-        let version = self
+    /// Removes the latest version the transaction wrote to the row. It is not always the latest version of the
+    /// row, a concurrent transaction may have written a version on top of it
+    pub fn rollback_version(&mut self, transaction_id: &TransactionId) -> RollbackChange {
+        self.reclaim();
+
+        let index = self
             .versions
-            .pop()
+            .iter()
+            .rposition(|version| &version.transaction_id == transaction_id)
             .expect("should not be possible to rollback a person data without any versions");
 
-        let drop_row = match self.versions.len() {
-            0 => DropRow::NoVersionsExist,
-            _ => DropRow::VersionExist,
+        let previous_deleted = self.current_version().state == PersonVersionState::Delete;
+
+        self.remove_version(index);
+
+        // Rollbacks only ever operate on in-memory versions, the latest committed version is never spilled
+        RollbackChange {
+            previous_deleted,
+            current_deleted: self
+                .versions
+                .last()
+                .map(|version| version.state == PersonVersionState::Delete),
+        }
+    }
+
+    /// Rolls back the latest version the transaction wrote to the row without taking the row's write lock, so
+    /// readers are never blocked. The version is retired, reads skip it until it is reclaimed (see `reclaim`).
+    ///
+    /// Returns none if the version cannot be retired, either because it is the row's only version, the row has
+    /// to be dropped, or because the row already has a retired version. Use `rollback_version` then
+    pub fn retire_version(&self, transaction_id: &TransactionId) -> Option<RollbackChange> {
+        if self.versions.len() < 2 {
+            return None;
+        }
+
+        let index = self
+            .versions
+            .iter()
+            .rposition(|version| &version.transaction_id == transaction_id)?;
+
+        if !self.retired.retire(transaction_id) {
+            return None;
+        }
+
+        let is_deleted = |version: &PersonVersion| version.state == PersonVersionState::Delete;
+
+        Some(RollbackChange {
+            previous_deleted: self.versions.last().is_some_and(is_deleted),
+            current_deleted: self
+                .versions
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(version_index, _)| *version_index != index)
+                .map(|(_, version)| is_deleted(version)),
+        })
+    }
+
+    /// Removes the retired version, if there is one. Returns true if a version was reclaimed
+    pub fn reclaim(&mut self) -> bool {
+        let Some(index) = self.retired_index() else {
+            self.retired.take();
+            return false;
         };
 
-        return (version, drop_row);
+        self.retired.take();
+        self.remove_version(index);
+
+        true
+    }
+
+    /// Removes a version and renumbers the versions after it, so reads by version id keep working
+    fn remove_version(&mut self, index: usize) {
+        self.versions.remove(index);
+
+        for version in &mut self.versions[index..] {
+            version.version = VersionId(version.version.0 - 1);
+        }
     }
 
     pub fn person_at_version(
@@ -236,48 +686,601 @@ impl PersonRow {
         version_id: VersionId,
         transaction_id: &TransactionId,
     ) -> Option<PersonVersion> {
+        // Versions are 1 indexed, subtract 1 to get the correct vector index
+        let index = version_id.to_number() - 1;
+
+        let cold_versions: Vec<PersonVersion>;
+
+        let (versions, index) = match &self.cold {
+            // Only go to storage if the version has been spilled
+            Some(cold) if index < cold.version_count => {
+                cold_versions = cold.load(&self.current_version().id);
+                (&cold_versions, index)
+            }
+            Some(cold) => (&self.versions, index - cold.version_count),
+            None => (&self.versions, index),
+        };
+
         // TODO: Filter out the versions that are not committed?
-        let versions_at_snapshot = self
-            .versions
+        let versions_at_snapshot = versions
             .iter()
             .filter(|version| &version.transaction_id <= transaction_id)
             .collect::<Vec<&PersonVersion>>();
 
-        // Versions are 1 indexed, subtract 1 to get the correct vector index
-        match versions_at_snapshot.get(version_id.to_number() - 1) {
+        // The retired version keeps its version id until it is reclaimed
+        let retired = self
+            .retired_index()
+            .map(|index| &self.versions[index].version);
+
+        match versions_at_snapshot.get(index) {
+            Some(version) if retired == Some(&version.version) => None,
             Some(version) => Some((*version).clone()),
             None => None,
         }
     }
 
-    pub fn version_count(&self) -> usize {
-        self.versions.len()
+    /// Every version of the row including versions that have been spilled to storage, earliest version first
+    pub fn history(&self) -> Vec<PersonVersion> {
+        let mut versions = match &self.cold {
+            Some(cold) => cold.load(&self.current_version().id),
+            None => vec![],
+        };
+
+        versions.extend(self.visible_versions().cloned());
+
+        versions
     }
 
-    pub fn at_transaction_id(&self, transaction_id: &TransactionId) -> Option<Person> {
-        // TODO: Can optimize this with a binary search
-        for version in self.versions.iter().rev() {
-            // May contain newer uncommited versions, we want to find the closest committed version
-            if &version.transaction_id <= transaction_id {
-                return version.get_person();
+    /// Digests of the attachments referenced by any version of the row, including versions that have been
+    /// spilled to storage, see `AttachmentStore::vacuum`
+    pub fn attachment_digests(&self) -> Vec<String> {
+        let mut digests = vec![];
+
+        let mut collect = |version: &PersonVersion| {
+            if let PersonVersionState::State(person) = &version.state {
+                digests.extend(person.attachments.iter().map(|a| a.digest.clone()));
             }
+        };
+
+        if let Some(cold) = &self.cold {
+            cold.load(&self.current_version().id)
+                .iter()
+                .for_each(&mut collect);
         }
 
-        None
+        self.versions.iter().for_each(&mut collect);
+
+        digests
+    }
+
+    /// Reads every in-memory version so it is paged in and cached, returns the number of versions read
+    pub fn touch(&self) -> usize {
+        for version in &self.versions {
+            std::hint::black_box(version.clone());
+        }
+
+        self.versions.len()
+    }
+
+    /// Total number of versions, including versions that have been spilled to storage
+    pub fn version_count(&self) -> usize {
+        let cold_version_count = self.cold.as_ref().map_or(0, |cold| cold.version_count);
+
+        cold_version_count + self.visible_versions().count()
+    }
+
+    /// Metadata of the row as of the transaction, None if the row did not exist yet. Only spilled versions are
+    /// loaded from storage, and only if the transaction is older than every in-memory version
+    pub fn meta(&self, transaction_id: &TransactionId) -> Option<EntityMeta> {
+        let mut hot_versions = self
+            .visible_versions()
+            .take_while(|version| &version.transaction_id <= transaction_id);
+
+        let Some(first_hot) = hot_versions.next() else {
+            return self.cold_meta(transaction_id);
+        };
+
+        let (hot_count, last) =
+            hot_versions.fold((1, first_hot), |(count, _), version| (count + 1, version));
+        let cold_version_count = self.cold.as_ref().map_or(0, |cold| cold.version_count);
+        let first_transaction_id = match &self.cold {
+            Some(cold) => cold.first_transaction_id.clone(),
+            None => first_hot.transaction_id.clone(),
+        };
+
+        Some(EntityMeta {
+            id: last.id.clone(),
+            version_count: cold_version_count + hot_count,
+            first_transaction_id,
+            last_transaction_id: last.transaction_id.clone(),
+            // The time is of the current version, it is not known for older versions
+            last_modified_ms: match std::ptr::eq(last, self.current_version()) {
+                true => self.last_modified.get(),
+                false => None,
+            },
+            deleted: last.state == PersonVersionState::Delete,
+        })
+    }
+
+    /// Used when the transaction is older than every in-memory version
+    fn cold_meta(&self, transaction_id: &TransactionId) -> Option<EntityMeta> {
+        let cold = self.cold.as_ref()?;
+        let versions = cold.load(&self.current_version().id);
+        let visible: Vec<&PersonVersion> = versions
+            .iter()
+            .take_while(|version| &version.transaction_id <= transaction_id)
+            .collect();
+        let last = visible.last()?;
+
+        Some(EntityMeta {
+            id: last.id.clone(),
+            version_count: visible.len(),
+            first_transaction_id: cold.first_transaction_id.clone(),
+            last_transaction_id: last.transaction_id.clone(),
+            last_modified_ms: None,
+            deleted: last.state == PersonVersionState::Delete,
+        })
+    }
+
+    /// Records when a committed transaction changed the row, see `EntityMeta::last_modified_ms`
+    pub fn stamp_modified(&self, modified_ms: u64) {
+        self.last_modified.stamp(modified_ms);
+    }
+
+    pub fn at_transaction_id(&self, transaction_id: &TransactionId) -> Option<Person> {
+        self.version_at_transaction_id(transaction_id)
+            .and_then(|version| version.get_person())
+    }
+
+    /// Applies `f` to the person as of the transaction, the person is only cloned if the version was spilled to
+    /// storage. Used by reads that do not return the person, e.g. `Statement::Count`
+    pub fn map_at_transaction_id<T>(
+        &self,
+        transaction_id: &TransactionId,
+        f: impl FnOnce(&Person) -> T,
+    ) -> Option<T> {
+        match find_at_transaction_id(self.visible_versions(), transaction_id) {
+            Some(version) => match &version.state {
+                PersonVersionState::State(person) => Some(f(person)),
+                PersonVersionState::Delete => None,
+            },
+            None => self
+                .at_transaction_id(transaction_id)
+                .map(|person| f(&person)),
+        }
     }
 
     pub fn version_at_transaction_id(
         &self,
         transaction_id: &TransactionId,
     ) -> Option<PersonVersion> {
-        // Can optimize this with a binary search
-        for version in self.versions.iter().rev() {
-            // May contain newer uncommited versions, we want to find the closest committed version
-            if &version.transaction_id <= transaction_id {
-                return Some(version.clone());
+        if let Some(version) = find_at_transaction_id(self.visible_versions(), transaction_id) {
+            return Some(version.clone());
+        }
+
+        // The transaction is older than every in-memory version, the version may have been spilled
+        match &self.cold {
+            Some(cold) => {
+                find_at_transaction_id(cold.load(&self.current_version().id).iter(), transaction_id)
+                    .cloned()
+            }
+            None => None,
+        }
+    }
+
+    /// Spills the oldest versions of the row to storage once the row holds more than twice the
+    /// configured number of hot versions, leaving the most recent versions in memory.
+    ///
+    /// Only versions up to and including the latest committed version can be spilled, this guarantees
+    /// rollbacks only ever operate on in-memory versions
+    pub fn spill_cold_versions(
+        &mut self,
+        store: &Arc<ColdVersionStore>,
+        committed_transaction_id: &TransactionId,
+    ) -> StorageResult<()> {
+        self.reclaim();
+
+        let hot_versions = store.hot_versions();
+
+        if self.versions.len() < hot_versions * 2 {
+            return Ok(());
+        }
+
+        // Keep the latest committed version in memory
+        let latest_committed_index = self
+            .versions
+            .iter()
+            .rposition(|version| &version.transaction_id <= committed_transaction_id)
+            .unwrap_or(0);
+
+        let spill_count = (self.versions.len() - hot_versions).min(latest_committed_index);
+
+        if spill_count == 0 {
+            return Ok(());
+        }
+
+        let first_transaction_id = self.versions[0].transaction_id.clone();
+
+        let cold = self.cold.get_or_insert_with(|| ColdVersions {
+            store: store.clone(),
+            chunk_count: 0,
+            version_count: 0,
+            first_transaction_id,
+        });
+
+        // Versions are only dropped from memory once they have been written
+        store.write_chunk(
+            &self.versions[0].id,
+            cold.chunk_count,
+            &self.versions[..spill_count],
+        )?;
+
+        self.versions.drain(..spill_count);
+
+        cold.chunk_count += 1;
+        cold.version_count += spill_count;
+
+        Ok(())
+    }
+
+    /// Deletes the versions that have been spilled to storage, used once the row has been purged (see
+    /// `Statement::Purge`)
+    pub fn delete_cold_versions(&self) -> StorageResult<()> {
+        let Some(cold) = &self.cold else {
+            return Ok(());
+        };
+
+        for chunk_index in 0..cold.chunk_count {
+            cold.store
+                .delete_chunk(&self.current_version().id, chunk_index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Squashes the in-memory versions committed at or before the cutoff, see `HistorySquash`. Versions that
+    /// have been spilled to storage are left as is. The remaining versions are renumbered, like a restore does,
+    /// so reads by version id keep working. Returns the number of versions that were dropped
+    pub fn squash_history(&mut self, squash: &HistorySquash, cutoff: &TransactionId) -> usize {
+        self.reclaim();
+
+        let squashed = squash_versions(&mut self.versions, squash, cutoff);
+
+        if squashed > 0 {
+            let cold_version_count = self.cold.as_ref().map_or(0, |cold| cold.version_count);
+
+            for (index, version) in self.versions.iter_mut().enumerate() {
+                version.version = VersionId(cold_version_count + index + 1);
+            }
+        }
+
+        squashed
+    }
+}
+
+impl PersonRow {
+    /// Checks that the in-memory versions are ordered and that the version read at the snapshot was
+    /// written at or before it
+    pub fn check_invariants(&self, snapshot: &TransactionId) -> Result<(), InvariantViolation> {
+        let versions = self.visible_versions().collect::<Vec<_>>();
+
+        for pair in versions.windows(2) {
+            let (previous, next) = (pair[0], pair[1]);
+
+            if next.version <= previous.version {
+                return Err(InvariantViolation::VersionNotIncreasing(
+                    next.id.clone(),
+                    previous.version.clone(),
+                    next.version.clone(),
+                ));
+            }
+
+            if next.transaction_id < previous.transaction_id {
+                return Err(InvariantViolation::TransactionNotIncreasing(
+                    next.id.clone(),
+                    previous.transaction_id.clone(),
+                    next.transaction_id.clone(),
+                ));
             }
         }
 
-        None
+        // Goes through the same read path as queries
+        match self.version_at_transaction_id(snapshot) {
+            Some(version) if &version.transaction_id > snapshot => {
+                Err(InvariantViolation::VisibleBeyondSnapshot(
+                    version.id.clone(),
+                    version.version.clone(),
+                    version.transaction_id.clone(),
+                    snapshot.clone(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that none of the versions were written by a transaction that has been rolled back
+    pub fn check_rolled_back(
+        &self,
+        transaction_id: &TransactionId,
+    ) -> Result<(), InvariantViolation> {
+        match self
+            .visible_versions()
+            .find(|version| &version.transaction_id == transaction_id)
+        {
+            Some(version) => Err(InvariantViolation::DanglingVersion(
+                version.id.clone(),
+                version.version.clone(),
+                transaction_id.clone(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn find_at_transaction_id<'a>(
+    versions: impl DoubleEndedIterator<Item = &'a PersonVersion>,
+    transaction_id: &TransactionId,
+) -> Option<&'a PersonVersion> {
+    // TODO: Can optimize this with a binary search
+    //  May contain newer uncommited versions, we want to find the closest committed version
+    versions
+        .rev()
+        .find(|version| &version.transaction_id <= transaction_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::person::Address;
+
+    use super::*;
+
+    #[test]
+    fn updates_structured_fields() {
+        let person = Person::new("Name".to_string(), None)
+            .set_address(Address {
+                street: Some("1 Main St".to_string()),
+                city: Some("Sydney".to_string()),
+                country: None,
+            })
+            .set_phone_numbers(vec!["111".to_string()]);
+        let transaction_id = TransactionId::new_first_transaction();
+
+        let mut row = PersonRow::new(person.clone(), transaction_id.clone(), None);
+
+        let mut update = |update: UpdatePersonData| {
+            row.apply_update(&person.id, update, transaction_id.clone())
+                .unwrap()
+                .current
+        };
+
+        // Parts of the address are updated on their own, other fields are left alone
+        let updated = update(UpdatePersonData {
+            address: UpdateAddressStatement::Set(UpdateAddressData {
+                city: UpdateStatement::Set("Melbourne".to_string()),
+                country: UpdateStatement::Set("Australia".to_string()),
+                ..UpdateAddressData::default()
+            }),
+            phone_numbers: UpdateListStatement::Add(vec!["111".to_string(), "222".to_string()]),
+            ..UpdatePersonData::default()
+        });
+
+        assert_eq!(
+            updated.address,
+            Some(Address {
+                street: Some("1 Main St".to_string()),
+                city: Some("Melbourne".to_string()),
+                country: Some("Australia".to_string()),
+            })
+        );
+        assert_eq!(updated.phone_numbers, vec!["111", "222"]);
+        assert_eq!(updated.full_name, "Name");
+
+        let updated = update(UpdatePersonData {
+            phone_numbers: UpdateListStatement::Remove(vec!["111".to_string()]),
+            ..UpdatePersonData::default()
+        });
+
+        assert_eq!(updated.phone_numbers, vec!["222"]);
+
+        // Unsetting every part removes the address
+        let updated = update(UpdatePersonData {
+            address: UpdateAddressStatement::Set(UpdateAddressData {
+                street: UpdateStatement::Unset,
+                city: UpdateStatement::Unset,
+                country: UpdateStatement::Unset,
+            }),
+            ..UpdatePersonData::default()
+        });
+
+        assert_eq!(updated.address, None);
+    }
+
+    #[test]
+    fn list_updates_apply_in_place() {
+        let person = Person::new("Name".to_string(), None)
+            .set_phone_numbers(vec!["111".to_string(), "222".to_string()]);
+        let transaction_id = TransactionId::new_first_transaction();
+
+        let mut row = PersonRow::new(person.clone(), transaction_id.clone(), None);
+
+        let phone_numbers = |statement: UpdateListStatement| UpdatePersonData {
+            phone_numbers: statement,
+            ..UpdatePersonData::default()
+        };
+
+        let updated = row
+            .apply_update(
+                &person.id,
+                phone_numbers(UpdateListStatement::Many(vec![
+                    UpdateListStatement::ReplaceAt(0, "333".to_string()),
+                    UpdateListStatement::Add(vec!["444".to_string()]),
+                    UpdateListStatement::Remove(vec!["222".to_string()]),
+                ])),
+                transaction_id.clone(),
+            )
+            .unwrap()
+            .current;
+
+        assert_eq!(updated.phone_numbers, vec!["333", "444"]);
+
+        // A failing statement leaves the list untouched, including the statements before it
+        let result = row.apply_update(
+            &person.id,
+            phone_numbers(UpdateListStatement::Many(vec![
+                UpdateListStatement::Add(vec!["555".to_string()]),
+                UpdateListStatement::ReplaceAt(5, "666".to_string()),
+            ])),
+            transaction_id.clone(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ApplyErrors::ListIndexOutOfBounds(_, 5, 3))
+        ));
+        assert_eq!(
+            row.at_transaction_id(&transaction_id)
+                .unwrap()
+                .phone_numbers,
+            vec!["333", "444"]
+        );
+
+        // Lists are never null, updates of a person without any phone numbers do not fail
+        let mut row = PersonRow::new(
+            Person::new("Empty".to_string(), None),
+            transaction_id.clone(),
+            None,
+        );
+
+        let updated = row
+            .apply_update(
+                &person.id,
+                phone_numbers(UpdateListStatement::Many(vec![
+                    UpdateListStatement::Remove(vec!["111".to_string()]),
+                    UpdateListStatement::Add(vec!["111".to_string()]),
+                ])),
+                transaction_id,
+            )
+            .unwrap()
+            .current;
+
+        assert_eq!(updated.phone_numbers, vec!["111"]);
+    }
+
+    #[test]
+    fn reads_people_written_before_structured_fields() {
+        let person: Person =
+            serde_json::from_str(r#"{"id":"1","full_name":"Name","email":null}"#).unwrap();
+
+        assert_eq!(person.address, None);
+        assert!(person.phone_numbers.is_empty());
+
+        let update: UpdatePersonData =
+            serde_json::from_str(r#"{"full_name":{"Set":"Other"},"email":"NoChanges"}"#).unwrap();
+
+        assert!(matches!(update.address, UpdateAddressStatement::NoChanges));
+        assert!(matches!(
+            update.phone_numbers,
+            UpdateListStatement::NoChanges
+        ));
+    }
+
+    #[test]
+    fn detects_broken_invariants() {
+        let person = Person::new("Name".to_string(), None);
+        let first = TransactionId::new_first_transaction();
+        let second = first.increment();
+
+        let mut row = PersonRow::new(person.clone(), first.clone(), None);
+        row.apply_delete(&person.id, second.clone(), None).unwrap();
+
+        assert!(row.check_invariants(&first).is_ok());
+        assert!(row.check_invariants(&second).is_ok());
+        assert!(row.check_rolled_back(&second.increment()).is_ok());
+
+        // Rolling back the delete should not leave its version behind
+        assert!(matches!(
+            row.check_rolled_back(&second),
+            Err(InvariantViolation::DanglingVersion(..))
+        ));
+
+        row.rollback_version(&second);
+        assert!(row.check_rolled_back(&second).is_ok());
+
+        // Versions written out of order
+        row.versions.push(PersonVersion {
+            id: person.id.clone(),
+            state: PersonVersionState::Delete,
+            version: VersionId::new_first_version(),
+            transaction_id: second,
+            lineage: None,
+        });
+
+        assert!(matches!(
+            row.check_invariants(&first),
+            Err(InvariantViolation::VersionNotIncreasing(..))
+        ));
+    }
+
+    #[test]
+    fn retired_versions_are_skipped_until_reclaimed() {
+        let person = Person::new("One".to_string(), None);
+        let first = TransactionId::new_first_transaction();
+        let second = first.increment();
+        let third = second.increment();
+
+        let rename = |full_name: &str| UpdatePersonData {
+            full_name: UpdateStatement::Set(full_name.to_string()),
+            ..UpdatePersonData::default()
+        };
+
+        // The third transaction writes on top of the second, before the second rolls back
+        let mut row = PersonRow::new(person.clone(), first.clone(), None);
+        row.apply_update(&person.id, rename("Two"), second.clone())
+            .unwrap();
+        let three = row
+            .apply_update(&person.id, rename("Three"), third.clone())
+            .unwrap()
+            .current;
+
+        assert_eq!(
+            row.retire_version(&second),
+            Some(RollbackChange {
+                previous_deleted: false,
+                current_deleted: Some(false),
+            })
+        );
+
+        // Only one version can be retired at a time
+        assert_eq!(row.retire_version(&third), None);
+
+        assert_eq!(row.at_transaction_id(&second), Some(person.clone()));
+        assert_eq!(row.person_at_version(VersionId(2), &third), None);
+        assert_eq!(
+            row.person_at_version(VersionId(3), &third),
+            Some(three.clone())
+        );
+        assert_eq!(row.current_state(), Some(three.clone()));
+        assert_eq!(row.history().len(), 2);
+        assert_eq!(row.version_count(), 2);
+        assert!(row.check_rolled_back(&second).is_ok());
+
+        // Versions after the reclaimed version are renumbered
+        assert!(row.reclaim());
+        assert!(!row.reclaim());
+        assert_eq!(row.person_at_version(VersionId(2), &third), Some(three));
+        assert!(row.check_invariants(&third).is_ok());
+
+        assert_eq!(
+            row.rollback_version(&third),
+            RollbackChange {
+                previous_deleted: false,
+                current_deleted: Some(false),
+            }
+        );
+        assert_eq!(row.current_state(), Some(person));
+
+        // The only version cannot be retired, the row has to be dropped
+        assert_eq!(row.retire_version(&first), None);
+        assert_eq!(row.rollback_version(&first).current_deleted, None);
     }
 }
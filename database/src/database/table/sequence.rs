@@ -0,0 +1,57 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Named monotonic counters, used by clients that need server assigned ordered ids, see `Statement::NextVal`
+///
+/// Sequences are not transactional, a value taken by a transaction that is rolled back is not handed out again
+/// (until a restart, as the WAL only contains committed transactions). Sequences are created on first use and
+/// start at 1
+#[derive(Default)]
+pub struct Sequences {
+    counters: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Sequences {
+    pub fn next_val(&self, name: &str) -> u64 {
+        let mut counters = self.counters.lock().unwrap();
+
+        let counter = counters.entry(name.to_string()).or_insert(0);
+        *counter += 1;
+
+        *counter
+    }
+
+    /// The last value handed out by each sequence
+    pub fn values(&self) -> BTreeMap<String, u64> {
+        self.counters.lock().unwrap().clone()
+    }
+
+    pub fn restore(&self, values: BTreeMap<String, u64>) {
+        *self.counters.lock().unwrap() = values;
+    }
+
+    pub fn reset(&self) {
+        self.counters.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_are_independent_and_restored() {
+        let sequences = Sequences::default();
+
+        assert_eq!(sequences.next_val("orders"), 1);
+        assert_eq!(sequences.next_val("orders"), 2);
+        assert_eq!(sequences.next_val("invoices"), 1);
+
+        let restored = Sequences::default();
+        restored.restore(sequences.values());
+
+        assert_eq!(restored.next_val("orders"), 3);
+
+        restored.reset();
+        assert_eq!(restored.next_val("orders"), 1);
+    }
+}
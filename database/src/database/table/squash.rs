@@ -0,0 +1,148 @@
+use crate::consts::consts::TransactionId;
+
+use super::row::{PersonVersion, PersonVersionState};
+
+/// Bounds the history of rows that are updated far more often than their history is read, see
+/// `DatabaseOptions::set_history_squash`. Versions carry no wall clock time, so windows are measured in
+/// transactions
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySquash {
+    /// Consecutive versions of a row committed within the same window of transactions (e.g. transactions 1000 to
+    /// 1999 with a window of 1000) are squashed into the first and last version of the window
+    pub window: usize,
+    /// Versions committed within this many transactions of the latest committed transaction are kept as is, so
+    /// recent history stays exact
+    pub min_age: usize,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl HistorySquash {
+    pub fn new(window: usize) -> Self {
+        Self { window, min_age: 0 }
+    }
+
+    pub fn set_min_age(mut self, min_age: usize) -> Self {
+        self.min_age = min_age;
+        self
+    }
+
+    /// The latest transaction whose versions can be squashed, none if every version is too recent
+    pub fn cutoff(&self, committed_transaction_id: &TransactionId) -> Option<TransactionId> {
+        committed_transaction_id
+            .to_number()
+            .checked_sub(self.min_age)
+            .map(TransactionId)
+    }
+
+    fn window_of(&self, version: &PersonVersion) -> usize {
+        version.transaction_id.to_number() / self.window
+    }
+}
+
+/// Totals of a `Control::SquashHistory` run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SquashReport {
+    /// Rows that had at least one version squashed
+    pub rows: usize,
+    pub squashed_versions: usize,
+}
+
+/// Only plain updates are squashed. Deletes and versions with lineage are kept, they are what links the row to
+/// other rows and to its own gaps
+fn is_squashable(version: &PersonVersion, cutoff: &TransactionId) -> bool {
+    matches!(version.state, PersonVersionState::State(_))
+        && version.lineage.is_none()
+        && &version.transaction_id <= cutoff
+}
+
+/// Drops the versions between the first and last version of each run of squashable versions within a window,
+/// earliest version first. Returns the number of versions that were dropped, the remaining versions keep their
+/// order but are not renumbered
+pub fn squash_versions(
+    versions: &mut Vec<PersonVersion>,
+    squash: &HistorySquash,
+    cutoff: &TransactionId,
+) -> usize {
+    let before = versions.len();
+
+    let mut retained: Vec<PersonVersion> = Vec::with_capacity(versions.len());
+
+    for version in versions.drain(..) {
+        // The previous version is in the middle of a run if the version before it and the current version are in
+        //  the same run, the current version takes its place as the last version of the run
+        let is_middle = is_squashable(&version, cutoff)
+            && retained.len() >= 2
+            && retained[retained.len() - 2..].iter().all(|previous| {
+                is_squashable(previous, cutoff)
+                    && squash.window_of(previous) == squash.window_of(&version)
+            });
+
+        if is_middle {
+            retained.pop();
+        }
+
+        retained.push(version);
+    }
+
+    *versions = retained;
+
+    before - versions.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{consts::consts::VersionId, model::person::Person};
+
+    use super::*;
+
+    fn versions(transaction_ids: &[usize]) -> Vec<PersonVersion> {
+        let person = Person::new_test();
+
+        transaction_ids
+            .iter()
+            .enumerate()
+            .map(|(index, transaction_id)| PersonVersion {
+                id: person.id.clone(),
+                state: PersonVersionState::State(person.clone()),
+                version: VersionId(index + 1),
+                transaction_id: TransactionId(*transaction_id),
+                lineage: None,
+            })
+            .collect()
+    }
+
+    fn transaction_ids(versions: &[PersonVersion]) -> Vec<usize> {
+        versions
+            .iter()
+            .map(|version| version.transaction_id.to_number())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_first_and_last_version_of_each_window() {
+        let squash = HistorySquash::new(10);
+
+        let mut row = versions(&[1, 2, 3, 4, 12, 13, 14, 15, 16, 25]);
+        let squashed = squash_versions(&mut row, &squash, &TransactionId(100));
+
+        assert_eq!(squashed, 5);
+        assert_eq!(transaction_ids(&row), vec![1, 4, 12, 16, 25]);
+
+        // Squashing again does not drop anything else
+        assert_eq!(squash_versions(&mut row, &squash, &TransactionId(100)), 0);
+    }
+
+    #[test]
+    fn keeps_deletes_and_recent_versions() {
+        let squash = HistorySquash::new(10);
+
+        let mut row = versions(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        row[3].state = PersonVersionState::Delete;
+
+        // Versions after the cutoff are not squashed, a delete splits the run
+        let squashed = squash_versions(&mut row, &squash, &TransactionId(7));
+
+        assert_eq!(squashed, 2);
+        assert_eq!(transaction_ids(&row), vec![1, 3, 4, 5, 7, 8]);
+    }
+}
@@ -0,0 +1,133 @@
+use super::striped::StripedCounter;
+
+/// Table statistics that are maintained incrementally as statements are applied and rolled back,
+/// this avoids a full table scan every time they are requested. Every commit updates them, so they are striped
+#[derive(Default)]
+pub struct TableStatistics {
+    live_rows: StripedCounter,
+    deleted_rows: StripedCounter,
+    total_versions: StripedCounter,
+}
+
+/// A point in time copy of the table statistics
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatisticsSnapshot {
+    /// Rows where the latest version is a person
+    pub live_rows: usize,
+    /// Rows where the latest version is a delete (tombstone)
+    pub deleted_rows: usize,
+    /// Versions across all rows, including tombstones
+    pub total_versions: usize,
+}
+
+impl TableStatisticsSnapshot {
+    pub fn average_versions_per_row(&self) -> f64 {
+        match self.live_rows + self.deleted_rows {
+            0 => 0.0,
+            rows => self.total_versions as f64 / rows as f64,
+        }
+    }
+}
+
+impl TableStatistics {
+    pub fn snapshot(&self) -> TableStatisticsSnapshot {
+        TableStatisticsSnapshot {
+            live_rows: self.live_rows.get(),
+            deleted_rows: self.deleted_rows.get(),
+            total_versions: self.total_versions.get(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.live_rows.reset();
+        self.deleted_rows.reset();
+        self.total_versions.reset();
+    }
+
+    /// A brand new row has been added
+    pub fn row_added(&self) {
+        self.live_rows.add(1);
+        self.version_added();
+    }
+
+    /// A deleted row has been added back
+    pub fn row_revived(&self) {
+        self.deleted_rows.sub(1);
+        self.row_added();
+    }
+
+    pub fn row_deleted(&self) {
+        self.live_rows.sub(1);
+        self.deleted_rows.add(1);
+        self.version_added();
+    }
+
+    pub fn version_added(&self) {
+        self.total_versions.add(1);
+    }
+
+    /// A restored row, a restored version may either be a person or a tombstone
+    pub fn row_restored(&self, deleted: bool) {
+        match deleted {
+            true => self.deleted_rows.add(1),
+            false => self.live_rows.add(1),
+        };
+
+        self.version_added();
+    }
+
+    /// A row and all of its versions have been removed, see `Statement::Purge`
+    pub fn row_purged(&self, deleted: bool, versions: usize) {
+        match deleted {
+            true => self.deleted_rows.sub(1),
+            false => self.live_rows.sub(1),
+        };
+
+        self.total_versions.sub(versions);
+    }
+
+    /// Rolls back a purge, the row is added back with all of its versions
+    pub fn purge_rolled_back(&self, deleted: bool, versions: usize) {
+        match deleted {
+            true => self.deleted_rows.add(1),
+            false => self.live_rows.add(1),
+        };
+
+        self.total_versions.add(versions);
+    }
+
+    /// Versions were dropped from the history of rows, the latest version of a row is never squashed, see
+    /// `HistorySquash`
+    pub fn versions_squashed(&self, versions: usize) {
+        self.total_versions.sub(versions);
+    }
+
+    /// Rolls back the latest version of a row
+    ///
+    /// - `removed_deleted`, whether the version that was rolled back was a tombstone
+    /// - `current_deleted`, whether the version that is now the latest is a tombstone, none if the row was dropped
+    pub fn version_rolled_back(&self, removed_deleted: bool, current_deleted: Option<bool>) {
+        match (removed_deleted, current_deleted) {
+            // Rolled back a tombstone that followed a tombstone, see `Statement::ResolveConflict`
+            (true, Some(true)) => {}
+            // Rolled back a delete
+            (true, _) => {
+                self.deleted_rows.sub(1);
+                self.live_rows.add(1);
+            }
+            // Rolled back the add of a brand new row
+            (false, None) => {
+                self.live_rows.sub(1);
+            }
+            // Rolled back an add of a deleted row
+            (false, Some(true)) => {
+                self.live_rows.sub(1);
+                self.deleted_rows.add(1);
+            }
+            // Rolled back an update
+            (false, Some(false)) => {}
+        }
+
+        self.total_versions.sub(1);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Stripes of a striped value, enough that the worker threads rarely share one
+const STRIPES: usize = 16;
+
+/// Stripes handed out to threads so far, a thread keeps the stripe it is given first
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static STRIPE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn stripe() -> usize {
+    STRIPE.with(|stripe| match stripe.get() {
+        Some(stripe) => stripe,
+        None => {
+            let next = NEXT_STRIPE.fetch_add(1, Ordering::Relaxed) % STRIPES;
+            stripe.set(Some(next));
+            next
+        }
+    })
+}
+
+/// Keeps each stripe on its own cache line, so threads writing to different stripes do not invalidate each other
+#[derive(Default)]
+#[repr(align(128))]
+struct Padded(AtomicUsize);
+
+/// A value that is written by every commit but rarely read, e.g. the table statistics. Each thread writes to its
+/// own stripe and reads combine the stripes, so concurrent writers do not contend on a single cache line
+///
+/// Reads are not a point in time, a read that runs concurrently with writes may miss some of them
+#[derive(Default)]
+pub struct StripedCounter {
+    stripes: [Padded; STRIPES],
+}
+
+impl StripedCounter {
+    fn local(&self) -> &AtomicUsize {
+        &self.stripes[stripe()].0
+    }
+
+    pub fn add(&self, value: usize) {
+        self.local().fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// A stripe may go below zero when another thread added the value, it wraps around and the sum is still exact
+    pub fn sub(&self, value: usize) {
+        self.local().fetch_sub(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> usize {
+        self.stripes.iter().fold(0, |sum, stripe| {
+            sum.wrapping_add(stripe.0.load(Ordering::Relaxed))
+        })
+    }
+
+    pub fn reset(&self) {
+        for stripe in &self.stripes {
+            stripe.0.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The striped maximum of a value that only grows between resets, e.g. the last transaction that modified the
+/// table. Unlike `StripedCounter` it is sequentially consistent, a read sees every write that happened before it
+#[derive(Default)]
+pub struct StripedMax {
+    stripes: [Padded; STRIPES],
+}
+
+impl StripedMax {
+    pub fn update(&self, value: usize) {
+        self.stripes[stripe()].0.fetch_max(value, Ordering::SeqCst);
+    }
+
+    pub fn get(&self) -> usize {
+        self.stripes
+            .iter()
+            .map(|stripe| stripe.0.load(Ordering::SeqCst))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn reset(&self) {
+        for stripe in &self.stripes {
+            stripe.0.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn combines_the_stripes_of_every_thread() {
+        let counter = Arc::new(StripedCounter::default());
+        let max = Arc::new(StripedMax::default());
+
+        let threads: Vec<_> = (0..STRIPES * 2)
+            .map(|thread_index| {
+                let counter = counter.clone();
+                let max = max.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        counter.add(2);
+                    }
+
+                    max.update(thread_index);
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Subtracted on a stripe that did not add anything
+        counter.sub(100);
+
+        assert_eq!(counter.get(), STRIPES * 2 * 200 - 100);
+        assert_eq!(max.get(), STRIPES * 2 - 1);
+
+        counter.reset();
+        max.reset();
+
+        assert_eq!(counter.get(), 0);
+        assert_eq!(max.get(), 0);
+    }
+}
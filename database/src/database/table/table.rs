@@ -1,33 +1,51 @@
 use core::panic;
-use crossbeam_skiplist::SkipMap;
-use std::sync::RwLock;
+use crossbeam_skiplist::{SkipMap, SkipSet};
+use std::{
+    ops::Bound,
+    sync::{Arc, Mutex, RwLock},
+};
 use thiserror::Error;
 
 use crate::{
-    consts::consts::{EntityId, TransactionId, VersionId},
-    database::orchestrator::DatabasePauseEvent,
+    consts::consts::{EntityId, TransactionId},
+    database::{activity::CancellationToken, orchestrator::DatabasePauseEvent},
     model::{
-        person::Person,
+        person::{Attachment, Person},
         statement::{Statement, StatementResult},
     },
 };
 
 use super::{
-    query::{filter, query},
+    attachment::{AttachmentContent, AttachmentStore},
+    cold::ColdVersionStore,
+    conflict::{Conflict, ConflictResolution},
+    constraint::ConstraintTiming,
+    history::history_page,
+    index::{IndexedField, PersonIndexes},
+    index_advisor::IndexAdvisor,
+    lineage::lineage,
+    outbox::{Outbox, OutboxId},
+    pagination::{page, scan},
+    planner::{QueryPlan, QueryPlanner, ReadPath, Scan},
+    policy::{FieldMask, RowPolicies, Visibility},
+    prepared_query::{PreparedQueries, PreparedQueryError},
+    query::{matches, query_cancellable, query_candidates_cancellable, QueryPersonData},
     row::{
-        ApplyDeleteResult, ApplyUpdateResult, DropRow, PersonRow, PersonVersion, PersonVersionState,
+        ApplyDeleteResult, ApplyUpdateResult, Lineage, PersonRow, PersonVersion, PersonVersionState,
     },
+    sequence::Sequences,
+    squash::{HistorySquash, SquashReport},
+    statistics::TableStatistics,
+    view::{project, MaterializedViews, PersonField, ViewResult},
+    watermark::ModificationWatermark,
 };
 
 // These are examples of 'logical' errors -- https://youtu.be/5blTGTwKZPI?si=tonGUDRXr9p9tTYu&t=685
 #[derive(Error, Debug)]
 pub enum ApplyErrors {
     // CRUD - GET
-    #[error("Not found, record does not exist: {0}")]
-    CannotGetDoesNotExist(EntityId),
-
-    #[error("Not found, record does not exist at version: {0}:{1}")]
-    CannotGetAtVersionDoesNotExist(EntityId, VersionId),
+    #[error("Cannot read at transaction {0}, it is newer than the snapshot of the read: {1}")]
+    CannotGetAtFutureTransaction(TransactionId, TransactionId),
 
     // CRUD - CREATE
     #[error("Cannot create, record already exists: {0}")]
@@ -41,42 +59,302 @@ pub enum ApplyErrors {
     #[error("Cannot delete, record does not exist: {0}")]
     CannotDeleteDoesNotExist(EntityId),
 
+    // CRUD - RENAME
+    #[error("Cannot rename, record does not exist: {0}")]
+    CannotRenameDoesNotExist(EntityId),
+
+    #[error("Cannot rename, record already exists: {0}")]
+    CannotRenameWhenAlreadyExists(EntityId),
+
+    #[error("Cannot rename, ids are the same: {0}")]
+    CannotRenameToSameId(EntityId),
+
+    // CRUD - MERGE
+    #[error("Cannot merge, record does not exist: {0}")]
+    CannotMergeDoesNotExist(EntityId),
+
+    #[error("Cannot merge a record into itself: {0}")]
+    CannotMergeIntoItself(EntityId),
+
+    // CRUD - SPLIT
+    #[error("Cannot split, record does not exist: {0}")]
+    CannotSplitDoesNotExist(EntityId),
+
+    #[error("Cannot split, record already exists: {0}")]
+    CannotSplitWhenAlreadyExists(EntityId),
+
+    #[error("Cannot split, a record must be split into at least one new record: {0}")]
+    CannotSplitIntoNothing(EntityId),
+
+    // CRUD - PURGE
+    #[error("Cannot purge, record does not exist: {0}")]
+    CannotPurgeDoesNotExist(EntityId),
+
+    // CRUD - RESOLVE CONFLICT
+    #[error("Cannot resolve conflict, record does not exist: {0}")]
+    CannotResolveDoesNotExist(EntityId),
+
+    #[error("Cannot resolve conflict, the version of {1} does not belong to {0}")]
+    CannotResolveDifferentRecord(EntityId, EntityId),
+
     #[error("Cannot set field to null: {0}")]
     NotNullConstraintViolation(String),
+
+    #[error("Email is already used by {1}: {0}")]
+    UniqueEmailViolation(String, EntityId),
+
+    #[error("Cannot update {0} at index {1}, the list has {2} elements")]
+    ListIndexOutOfBounds(String, usize, usize),
+
+    // ATTACHMENTS
+    #[error("Attachment does not exist: {0}")]
+    AttachmentDoesNotExist(String),
+
+    #[error("Cannot read attachment {0}: {1}")]
+    CannotReadAttachment(String, String),
+
+    #[error("Attachments are not available on this table")]
+    AttachmentsUnavailable,
+
+    // VIEWS
+    #[error("View does not exist: {0}")]
+    ViewDoesNotExist(String),
+
+    #[error("Views cannot be queried by a role with row policies: {0}")]
+    ViewRestrictedByPolicy(String),
+
+    // PREPARED QUERIES
+    #[error("Prepared query does not exist: {0}")]
+    PreparedQueryDoesNotExist(String),
+
+    #[error("Cannot execute prepared query {0}: {1}")]
+    CannotExecutePreparedQuery(String, PreparedQueryError),
+
+    // SYSTEM TABLES
+    #[error("System tables can only be queried by read-only transactions: {0}")]
+    SystemTableInMutation(String),
+
+    #[error("System tables cannot be queried by a role with row policies: {0}")]
+    SystemTableRestrictedByPolicy(String),
+
+    // OUTBOX
+    #[error(
+        "Outbox message is not pending, it does not exist or has already been acknowledged: {0}"
+    )]
+    OutboxMessageNotPending(OutboxId),
+
+    #[error("The outbox cannot be read by a role with row policies")]
+    OutboxRestrictedByPolicy,
+
+    // REQUESTS
+    #[error("Request was cancelled")]
+    Cancelled,
+}
+
+/// Per request state that affects how reads are evaluated
+#[derive(Clone, Default)]
+pub struct ReadOptions {
+    /// Full table scans stop early once the request has been cancelled
+    pub cancellation: CancellationToken,
+    /// Rows that are not visible to the request are treated as if they do not exist
+    pub visibility: Visibility,
+    /// Sensitive fields the request is not authorized to read
+    pub mask: FieldMask,
+    /// Only a shadow read (see `ShadowReads`) is read through another path than the planned one
+    pub read_path: ReadPath,
 }
 
 pub struct PersonTable {
+    /// Each row has its own lock, so writes to different rows never wait on each other. The structures every
+    /// commit updates are striped rather than shared, see `StripedCounter`
     pub person_rows: SkipMap<EntityId, RwLock<PersonRow>>,
+    pub statistics: TableStatistics,
+    pub indexes: PersonIndexes,
+    pub planner: QueryPlanner,
+    pub advisor: IndexAdvisor,
+    pub views: MaterializedViews,
+    pub policies: RowPolicies,
+    pub prepared_queries: PreparedQueries,
+    pub watermark: ModificationWatermark,
+    pub sequences: Sequences,
+    pub outbox: Outbox,
+    /// Asserts MVCC invariants on reads and rollbacks, see `DatabaseOptions::set_paranoid_checks`
+    paranoid_checks: bool,
+    /// If set, old versions of rows are spilled to storage, see `spill_cold_versions`
+    cold_store: Option<Arc<ColdVersionStore>>,
+    /// Resolves divergent versions, see `Statement::ResolveConflict`
+    conflict_resolution: ConflictResolution,
+    /// Holds the bytes of attachments, see `Statement::GetAttachment`
+    attachment_store: Option<Arc<AttachmentStore>>,
+    /// Rows removed by a purge that has not committed yet, a rollback adds them back, see `complete_purges`
+    purging: SkipMap<EntityId, PersonRow>,
+    /// The transaction of the latest committed purge, see `SnapshotManager::create_snapshot`
+    latest_purge: RwLock<Option<TransactionId>>,
+    /// If set, two live people cannot share an email, see `DatabaseOptions::set_unique_email`
+    unique_email: Option<ConstraintTiming>,
+    /// Serializes constraint checks, see `check_constraints`
+    constraint_checks: Mutex<()>,
+    /// If set, the history of the table's rows can be squashed, see `squash_history`
+    history_squash: Option<HistorySquash>,
+    /// Rows with a rolled back version that was being read when it was retired, see `reclaim_retired_versions`
+    retired_rows: SkipSet<EntityId>,
 }
 
 impl PersonTable {
     pub fn new() -> Self {
         Self {
             person_rows: SkipMap::<EntityId, RwLock<PersonRow>>::new(),
+            statistics: TableStatistics::default(),
+            indexes: PersonIndexes::default(),
+            planner: QueryPlanner::default(),
+            advisor: IndexAdvisor::default(),
+            views: MaterializedViews::default(),
+            policies: RowPolicies::default(),
+            prepared_queries: PreparedQueries::default(),
+            watermark: ModificationWatermark::default(),
+            sequences: Sequences::default(),
+            outbox: Outbox::default(),
+            paranoid_checks: false,
+            cold_store: None,
+            conflict_resolution: ConflictResolution::default(),
+            attachment_store: None,
+            purging: SkipMap::new(),
+            latest_purge: RwLock::new(None),
+            unique_email: None,
+            constraint_checks: Mutex::new(()),
+            history_squash: None,
+            retired_rows: SkipSet::new(),
         }
     }
 
+    pub fn new_with_cold_store(cold_store: ColdVersionStore) -> Self {
+        Self {
+            cold_store: Some(Arc::new(cold_store)),
+            ..Self::new()
+        }
+    }
+
+    pub fn set_paranoid_checks(mut self, paranoid_checks: bool) -> Self {
+        self.paranoid_checks = paranoid_checks;
+        self
+    }
+
+    pub fn set_conflict_resolution(mut self, conflict_resolution: ConflictResolution) -> Self {
+        self.conflict_resolution = conflict_resolution;
+        self
+    }
+
+    pub fn set_unique_email(mut self, unique_email: Option<ConstraintTiming>) -> Self {
+        self.unique_email = unique_email;
+        self
+    }
+
+    /// When the table's constraints are checked for a transaction, none if the table has no constraints
+    pub fn constraint_timing(&self, defer_constraints: bool) -> Option<ConstraintTiming> {
+        self.unique_email
+            .map(|timing| timing.for_transaction(defer_constraints))
+    }
+
+    pub fn set_history_squash(mut self, history_squash: Option<HistorySquash>) -> Self {
+        self.history_squash = history_squash;
+        self
+    }
+
+    pub fn history_squash(&self) -> Option<&HistorySquash> {
+        self.history_squash.as_ref()
+    }
+
+    pub fn set_attachment_store(mut self, attachment_store: Option<Arc<AttachmentStore>>) -> Self {
+        self.attachment_store = attachment_store;
+        self
+    }
+
+    pub fn attachment_store(&self) -> Option<Arc<AttachmentStore>> {
+        self.attachment_store.clone()
+    }
+
+    /// The transaction of the latest committed purge, none if nothing has been purged since the table was
+    /// restored. The purge stays in the WAL, so a restore replays it
+    pub fn latest_purge(&self) -> Option<TransactionId> {
+        self.latest_purge.read().unwrap().clone()
+    }
+
     pub fn reset(&self, _: &DatabasePauseEvent) {
         for row in &self.person_rows {
             row.remove();
         }
+
+        self.statistics.reset();
+        self.indexes.reset();
+        self.advisor.reset();
+        self.views.reset();
+        self.policies.reset();
+        self.prepared_queries.reset();
+        self.watermark.reset();
+        self.sequences.reset();
+        self.outbox.reset();
+        self.purging.clear();
+        self.retired_rows.clear();
+        *self.latest_purge.write().unwrap() = None;
     }
 
     pub fn restore_table(&self, version_snapshots: Vec<PersonVersion>) {
         for version_snapshot in version_snapshots {
             let id = version_snapshot.id.clone();
 
+            self.statistics
+                .row_restored(version_snapshot.state == PersonVersionState::Delete);
+
+            if let PersonVersionState::State(person) = &version_snapshot.state {
+                self.indexes.insert(person);
+            }
+
             let person_row = PersonRow::from_restore(version_snapshot);
 
             self.person_rows.insert(id, RwLock::new(person_row));
         }
     }
 
+    /// Replaces the restored rows with the rows of a replay checkpoint, see `ReplayCheckpoint`. Only used while
+    /// restoring, before anything else reads or writes the table
+    pub fn restore_checkpoint(
+        &self,
+        version_snapshots: Vec<PersonVersion>,
+        latest_purge: Option<TransactionId>,
+    ) {
+        for row in &self.person_rows {
+            row.remove();
+        }
+
+        self.statistics.reset();
+        self.indexes.reset();
+
+        self.restore_table(version_snapshots);
+
+        *self.latest_purge.write().unwrap() = latest_purge;
+    }
+
     pub fn query_statement(
         &self,
         statement: Statement,
         transaction_id: &TransactionId,
     ) -> Result<StatementResult, ApplyErrors> {
+        self.query_statement_with_options(statement, transaction_id, &ReadOptions::default())
+    }
+
+    /// Same as `query_statement`, though the reads are bounded by the request's cancellation and row policies
+    pub fn query_statement_with_options(
+        &self,
+        statement: Statement,
+        transaction_id: &TransactionId,
+        options: &ReadOptions,
+    ) -> Result<StatementResult, ApplyErrors> {
+        let visibility = &options.visibility;
+
+        if self.paranoid_checks {
+            self.check_read_invariants(&statement, transaction_id);
+        }
+
         let action_result = match statement {
             Statement::Get(id) => {
                 let person = match &self.person_rows.get(&id) {
@@ -85,10 +363,10 @@ impl PersonTable {
                         .read()
                         .unwrap()
                         .at_transaction_id(&transaction_id),
-                    None => return Err(ApplyErrors::CannotGetDoesNotExist(id)),
+                    None => None,
                 };
 
-                StatementResult::GetSingle(person)
+                StatementResult::GetSingle(person.filter(|p| visibility.can_see(p)))
             }
             Statement::GetVersion(id, version) => {
                 let person = match &self.person_rows.get(&id) {
@@ -97,23 +375,89 @@ impl PersonTable {
                         .read()
                         .unwrap()
                         .person_at_version(version, transaction_id),
-
-                    None => return Err(ApplyErrors::CannotGetAtVersionDoesNotExist(id, version)),
+                    None => None,
                 };
 
-                StatementResult::GetSingle(person)
+                StatementResult::GetSingle(person.filter(|p| visibility.can_see(p)))
             }
-            Statement::List(query_person_data) => {
-                let mut people = query(&self, &transaction_id);
-
-                sort_list(&mut people);
+            Statement::Exists(id) => StatementResult::Exists(
+                self.person_rows
+                    .get(&id)
+                    .and_then(|row| {
+                        row.value()
+                            .read()
+                            .unwrap()
+                            .map_at_transaction_id(transaction_id, |p| visibility.can_see(p))
+                    })
+                    .unwrap_or(false),
+            ),
+            Statement::Count(query_person_data) => {
+                StatementResult::Count(self.count(query_person_data, transaction_id, options)?)
+            }
+            Statement::Meta(id) => {
+                StatementResult::Meta(self.person_rows.get(&id).and_then(|row| {
+                    let row = row.value().read().unwrap();
+
+                    // Deleted rows have no fields a policy could hide
+                    match row.map_at_transaction_id(transaction_id, |p| visibility.can_see(p)) {
+                        Some(false) => None,
+                        Some(true) | None => row.meta(transaction_id),
+                    }
+                }))
+            }
+            Statement::GetAttachment(id, name) => {
+                let person = self
+                    .person_rows
+                    .get(&id)
+                    .and_then(|row| {
+                        row.value()
+                            .read()
+                            .unwrap()
+                            .at_transaction_id(transaction_id)
+                    })
+                    .filter(|p| visibility.can_see(p));
 
-                if let Some(q) = query_person_data {
-                    people = filter(people, q)
+                match person {
+                    Some(person) => StatementResult::Attachment(
+                        match person.attachments.into_iter().find(|a| a.name == name) {
+                            Some(attachment) => Some(self.read_attachment(attachment)?),
+                            None => None,
+                        },
+                    ),
+                    None => StatementResult::NotFound(id),
+                }
+            }
+            Statement::GetManyAtTransaction(ids, at) => {
+                // Commits after the snapshot could still be in flight, the rows would not be repeatable
+                if &at > transaction_id {
+                    return Err(ApplyErrors::CannotGetAtFutureTransaction(
+                        at,
+                        transaction_id.clone(),
+                    ));
                 }
 
+                let people = ids
+                    .iter()
+                    .filter_map(|id| self.person_rows.get(id))
+                    .filter_map(|row| row.value().read().unwrap().at_transaction_id(&at))
+                    .filter(|person| visibility.can_see(person))
+                    .collect();
+
                 StatementResult::List(people)
             }
+            Statement::List(query_person_data) => {
+                StatementResult::List(self.list(query_person_data, transaction_id, options)?)
+            }
+            Statement::ListPage(query_person_data, page_request) => StatementResult::Page(page(
+                self,
+                query_person_data,
+                page_request,
+                transaction_id,
+                visibility,
+            )),
+            Statement::Scan { start, end, limit } => {
+                StatementResult::List(scan(self, (start, end), limit, transaction_id, visibility))
+            }
             Statement::ListLatestVersions => {
                 let people_at_transaction_id: Vec<PersonVersion> = self
                     .person_rows
@@ -125,16 +469,228 @@ impl PersonTable {
                             .unwrap()
                             .version_at_transaction_id(&transaction_id)
                     })
+                    .filter(|version| match visibility.is_restricted() {
+                        true => version
+                            .get_person()
+                            .is_some_and(|person| visibility.can_see(&person)),
+                        false => true,
+                    })
                     .collect();
 
                 StatementResult::ListVersion(people_at_transaction_id)
             }
-            Statement::Add(_) | Statement::Update(_, _) | Statement::Remove(_) => {
+            Statement::Lineage(id) => {
+                let Some(mut versions) = lineage(self, &id, transaction_id) else {
+                    return Ok(StatementResult::NotFound(id));
+                };
+
+                if visibility.is_restricted() {
+                    versions.retain(|version| {
+                        version
+                            .get_person()
+                            .is_some_and(|person| visibility.can_see(&person))
+                    });
+                }
+
+                StatementResult::ListVersion(versions)
+            }
+            Statement::History(id, request) => {
+                match history_page(self, &id, request, transaction_id, visibility) {
+                    Some(page) => StatementResult::HistoryPage(page),
+                    None => StatementResult::NotFound(id),
+                }
+            }
+            Statement::QueryView(name) if visibility.is_restricted() => {
+                return Err(ApplyErrors::ViewRestrictedByPolicy(name))
+            }
+            Statement::QueryView(name) => match self.views.query(&name) {
+                Some(view) => StatementResult::View(view),
+                None => return Err(ApplyErrors::ViewDoesNotExist(name)),
+            },
+            Statement::ExecutePreparedQuery(name, values) => {
+                let definition = self
+                    .prepared_queries
+                    .get(&name)
+                    .ok_or_else(|| ApplyErrors::PreparedQueryDoesNotExist(name.clone()))?;
+
+                let query = definition
+                    .bind(&values)
+                    .map_err(|e| ApplyErrors::CannotExecutePreparedQuery(name, e))?;
+
+                let mut people = self.list(Some(query), transaction_id, options)?;
+
+                definition.sort(&mut people);
+
+                StatementResult::View(ViewResult {
+                    rows: people
+                        .iter()
+                        .map(|person| project(person, &definition.projection))
+                        .collect(),
+                    freshness_transaction_id: transaction_id.clone(),
+                })
+            }
+            Statement::TableVersion => {
+                StatementResult::TableVersion(self.watermark.version(transaction_id))
+            }
+            // System tables are not a part of the person table, read-only transactions query them through the
+            //  database (see `Database::query_system_table`)
+            Statement::QuerySystemTable(name) => {
+                return Err(ApplyErrors::SystemTableInMutation(name))
+            }
+            Statement::ReadOutbox(_, _) if visibility.is_restricted() => {
+                return Err(ApplyErrors::OutboxRestrictedByPolicy)
+            }
+            Statement::ReadOutbox(topic, limit) => {
+                StatementResult::Outbox(self.outbox.read(topic.as_deref(), limit))
+            }
+            Statement::Add(_)
+            | Statement::Update(_, _)
+            | Statement::Remove(_)
+            | Statement::Rename(_, _)
+            | Statement::Merge(_, _, _)
+            | Statement::Split(_, _)
+            | Statement::ResolveConflict(_, _)
+            | Statement::Purge(_)
+            | Statement::NextVal(_)
+            | Statement::Enqueue(_, _)
+            | Statement::AckOutbox(_) => {
                 panic!("Should not be a mutation statement")
             }
         };
 
-        return Ok(action_result);
+        Ok(options.mask.mask_result(action_result))
+    }
+
+    /// People visible to the request that match the query, ordered by id
+    fn list(
+        &self,
+        query_person_data: Option<QueryPersonData>,
+        transaction_id: &TransactionId,
+        options: &ReadOptions,
+    ) -> Result<Vec<Person>, ApplyErrors> {
+        // Shadow reads are not counted in the planner stats
+        let plan = match options.read_path {
+            ReadPath::Planned => {
+                let plan = self.plan(&query_person_data);
+
+                self.planner.record(&plan);
+
+                Some(plan)
+            }
+            ReadPath::FullScan => None,
+        };
+
+        let scan = plan.as_ref().map_or(Scan::Full, |plan| plan.scan.clone());
+
+        let mut people = match scan {
+            Scan::Full => query_cancellable(self, transaction_id, &options.cancellation)?,
+            Scan::Index { field, value } => query_candidates_cancellable(
+                self,
+                self.indexes.get(&field).candidates(&value),
+                transaction_id,
+                &options.cancellation,
+            )?,
+        };
+
+        people.retain(|person| options.visibility.can_see(person));
+
+        sort_list(&mut people);
+
+        if let Some(q) = query_person_data {
+            people.retain(|person| matches(person, &q));
+
+            if let Some(plan) = &plan {
+                self.advisor.record(&q, plan, people.len());
+            }
+        }
+
+        Ok(people)
+    }
+
+    fn read_attachment(&self, attachment: Attachment) -> Result<AttachmentContent, ApplyErrors> {
+        let store = self
+            .attachment_store
+            .as_ref()
+            .ok_or(ApplyErrors::AttachmentsUnavailable)?;
+
+        match store.read(&attachment.digest) {
+            Ok(Some(bytes)) => Ok(AttachmentContent { attachment, bytes }),
+            Ok(None) => Err(ApplyErrors::CannotReadAttachment(
+                attachment.name,
+                format!("the payload {} is missing", attachment.digest),
+            )),
+            Err(e) => Err(ApplyErrors::CannotReadAttachment(
+                attachment.name,
+                e.to_string(),
+            )),
+        }
+    }
+
+    /// Same as `list`, though the matching people are counted rather than cloned into the result
+    fn count(
+        &self,
+        query_person_data: Option<QueryPersonData>,
+        transaction_id: &TransactionId,
+        options: &ReadOptions,
+    ) -> Result<usize, ApplyErrors> {
+        let plan = match options.read_path {
+            ReadPath::Planned => {
+                let plan = self.plan(&query_person_data);
+
+                self.planner.record(&plan);
+
+                Some(plan)
+            }
+            ReadPath::FullScan => None,
+        };
+
+        let scan = plan.as_ref().map_or(Scan::Full, |plan| plan.scan.clone());
+
+        let is_counted = |person: &Person| {
+            options.visibility.can_see(person)
+                && query_person_data
+                    .as_ref()
+                    .map_or(true, |query| matches(person, query))
+        };
+
+        let mut count = 0;
+
+        let mut count_row = |row: &RwLock<PersonRow>| -> Result<(), ApplyErrors> {
+            if options.cancellation.is_cancelled() {
+                return Err(ApplyErrors::Cancelled);
+            }
+
+            if let Some(true) = row
+                .read()
+                .unwrap()
+                .map_at_transaction_id(transaction_id, is_counted)
+            {
+                count += 1;
+            }
+
+            Ok(())
+        };
+
+        match scan {
+            Scan::Full => {
+                for row in self.person_rows.iter() {
+                    count_row(row.value())?;
+                }
+            }
+            Scan::Index { field, value } => {
+                for id in self.indexes.get(&field).candidates(&value) {
+                    if let Some(row) = self.person_rows.get(&id) {
+                        count_row(row.value())?;
+                    }
+                }
+            }
+        }
+
+        if let (Some(query), Some(plan)) = (&query_person_data, &plan) {
+            self.advisor.record(query, plan, count);
+        }
+
+        Ok(count)
     }
 
     // Each mutation statement can be broken up into 3 steps
@@ -146,28 +702,14 @@ impl PersonTable {
         statement: Statement,
         transaction_id: TransactionId,
     ) -> Result<StatementResult, ApplyErrors> {
+        // Sequences are not a part of the rows
+        if !statement.mutated_ids().is_empty() {
+            self.watermark.modified(&transaction_id);
+        }
+
         let action_result = match statement {
             Statement::Add(person) => {
-                let id = person.id.clone();
-                let person_to_persist = person.clone();
-
-                // We need to handle the case where someone can add an item back after it has been deleted
-                //  if it has been deleted there will already be a row.
-                match self.person_rows.get(&id) {
-                    Some(existing_person_row) => {
-                        existing_person_row
-                            .value()
-                            .write()
-                            .unwrap()
-                            .apply_add(person_to_persist, transaction_id)?;
-                    }
-                    None => {
-                        self.person_rows.insert(
-                            id.clone(),
-                            RwLock::new(PersonRow::new(person_to_persist, transaction_id)),
-                        );
-                    }
-                }
+                self.add_row(person.clone(), transaction_id, None)?;
 
                 StatementResult::Single(person)
             }
@@ -179,79 +721,826 @@ impl PersonTable {
 
                 let person_update_to_persist = update_person.clone();
 
-                let ApplyUpdateResult {
-                    current,
-                    previous: _,
-                } = person_row.value().write().unwrap().apply_update(
-                    &id,
-                    person_update_to_persist,
-                    transaction_id,
-                )?;
+                let ApplyUpdateResult {
+                    current,
+                    previous: _,
+                } = person_row.value().write().unwrap().apply_update(
+                    &id,
+                    person_update_to_persist,
+                    transaction_id,
+                )?;
+
+                self.statistics.version_added();
+                self.indexes.insert(&current);
+
+                StatementResult::Single(current)
+            }
+            Statement::Remove(id) => {
+                let person_row = self
+                    .person_rows
+                    .get(&id)
+                    .ok_or(ApplyErrors::CannotDeleteDoesNotExist(id.clone()))?;
+
+                let ApplyDeleteResult { previous } = person_row
+                    .value()
+                    .write()
+                    .unwrap()
+                    .apply_delete(&id, transaction_id, None)?;
+
+                self.statistics.row_deleted();
+
+                StatementResult::Single(previous)
+            }
+            Statement::Rename(from, to) => {
+                StatementResult::Single(self.apply_rename(from, to, transaction_id)?)
+            }
+            Statement::Merge(from, into, fields) => {
+                StatementResult::Single(self.apply_merge(from, into, &fields, transaction_id)?)
+            }
+            Statement::Split(from, people) => {
+                StatementResult::List(self.apply_split(from, people, transaction_id)?)
+            }
+            Statement::ResolveConflict(id, conflict) => {
+                StatementResult::GetSingle(self.apply_resolution(id, conflict, transaction_id)?)
+            }
+            Statement::Purge(id) => StatementResult::Purged(self.apply_purge(id)?),
+            Statement::NextVal(name) => {
+                StatementResult::SequenceValue(self.sequences.next_val(&name))
+            }
+            // The message is enqueued once the transaction commits, see `complete_outbox`
+            Statement::Enqueue(topic, _) => {
+                StatementResult::SuccessStatus(format!("Enqueued to {}", topic))
+            }
+            Statement::AckOutbox(ids) => {
+                self.outbox
+                    .acknowledge(&ids)
+                    .map_err(ApplyErrors::OutboxMessageNotPending)?;
+
+                StatementResult::SuccessStatus(format!("Acknowledged {} messages", ids.len()))
+            }
+            s @ Statement::Get(_)
+            | s @ Statement::GetVersion(_, _)
+            | s @ Statement::Exists(_)
+            | s @ Statement::Meta(_)
+            | s @ Statement::Count(_)
+            | s @ Statement::GetAttachment(_, _)
+            | s @ Statement::GetManyAtTransaction(_, _)
+            | s @ Statement::List(_)
+            | s @ Statement::ListPage(_, _)
+            | s @ Statement::Scan { .. }
+            | s @ Statement::ListLatestVersions
+            | s @ Statement::Lineage(_)
+            | s @ Statement::History(_, _)
+            | s @ Statement::QueryView(_)
+            | s @ Statement::ExecutePreparedQuery(_, _)
+            | s @ Statement::TableVersion
+            | s @ Statement::ReadOutbox(_, _) => {
+                return self.query_statement(s, &transaction_id);
+            }
+            Statement::QuerySystemTable(name) => {
+                return Err(ApplyErrors::SystemTableInMutation(name))
+            }
+        };
+
+        Ok(action_result)
+    }
+
+    pub fn apply_rollback(&self, statement: Statement, transaction_id: &TransactionId) {
+        match statement {
+            Statement::Add(person) => {
+                self.remove_mutation(person.id, transaction_id);
+            }
+            Statement::Update(id, _) | Statement::ResolveConflict(id, _) => {
+                self.remove_mutation(id, transaction_id);
+            }
+            Statement::Remove(id) => {
+                self.remove_mutation(id, transaction_id);
+            }
+            Statement::Purge(id) => {
+                self.restore_purged(id);
+            }
+            // The add is undone before the delete, the reverse of the order they were applied in
+            Statement::Rename(from, to) | Statement::Merge(from, to, _) => {
+                self.remove_mutation(to, transaction_id);
+                self.remove_mutation(from, transaction_id);
+            }
+            Statement::Split(from, people) => {
+                for person in people.into_iter().rev() {
+                    self.remove_mutation(person.id, transaction_id);
+                }
+
+                self.remove_mutation(from, transaction_id);
+            }
+            Statement::AckOutbox(ids) => {
+                self.outbox.restore_acknowledged(&ids);
+            }
+            // Sequences are not transactional, the value is skipped. Enqueued messages are only added on commit
+            Statement::NextVal(_) | Statement::Enqueue(_, _) => {}
+            Statement::Get(_)
+            | Statement::GetVersion(_, _)
+            | Statement::Exists(_)
+            | Statement::Meta(_)
+            | Statement::Count(_)
+            | Statement::GetAttachment(_, _)
+            | Statement::GetManyAtTransaction(_, _)
+            | Statement::List(_)
+            | Statement::ListPage(_, _)
+            | Statement::Scan { .. }
+            | Statement::ListLatestVersions
+            | Statement::Lineage(_)
+            | Statement::History(_, _)
+            | Statement::QueryView(_)
+            | Statement::ExecutePreparedQuery(_, _)
+            | Statement::TableVersion
+            | Statement::QuerySystemTable(_)
+            | Statement::ReadOutbox(_, _) => {}
+        }
+    }
+
+    /// Deletes the person at `from` and adds them at `to`, the versions are linked to each other through
+    /// their lineage. Both row locks are never held at the same time, if the add fails the delete is
+    /// rolled back before returning so the statement is applied either completely or not at all
+    fn apply_rename(
+        &self,
+        from: EntityId,
+        to: EntityId,
+        transaction_id: TransactionId,
+    ) -> Result<Person, ApplyErrors> {
+        if from == to {
+            return Err(ApplyErrors::CannotRenameToSameId(from));
+        }
+
+        let from_row = self
+            .person_rows
+            .get(&from)
+            .ok_or(ApplyErrors::CannotRenameDoesNotExist(from.clone()))?;
+
+        let ApplyDeleteResult { previous } = from_row
+            .value()
+            .write()
+            .unwrap()
+            .apply_delete(
+                &from,
+                transaction_id.clone(),
+                Some(Lineage::RenamedTo(to.clone())),
+            )
+            .map_err(|_| ApplyErrors::CannotRenameDoesNotExist(from.clone()))?;
+
+        self.statistics.row_deleted();
+
+        let person = Person {
+            id: to.clone(),
+            ..previous
+        };
+
+        let lineage = Some(Lineage::RenamedFrom(from.clone()));
+
+        if self
+            .add_row(person.clone(), transaction_id.clone(), lineage)
+            .is_err()
+        {
+            self.remove_mutation(from, &transaction_id);
+            return Err(ApplyErrors::CannotRenameWhenAlreadyExists(to));
+        }
+
+        Ok(person)
+    }
+
+    /// Resolves the conflict with the table's `ConflictResolution`, returns the resolved person or none if the
+    /// resolution is a delete
+    fn apply_resolution(
+        &self,
+        id: EntityId,
+        conflict: Conflict,
+        transaction_id: TransactionId,
+    ) -> Result<Option<Person>, ApplyErrors> {
+        let person_row = self
+            .person_rows
+            .get(&id)
+            .ok_or(ApplyErrors::CannotResolveDoesNotExist(id.clone()))?;
+
+        let (previous, resolved) = person_row.value().write().unwrap().apply_resolution(
+            &id,
+            conflict,
+            &self.conflict_resolution,
+            transaction_id,
+        )?;
+
+        match (previous, &resolved) {
+            (PersonVersionState::State(_), PersonVersionState::State(person)) => {
+                self.statistics.version_added();
+                self.indexes.insert(person);
+            }
+            (PersonVersionState::Delete, PersonVersionState::State(person)) => {
+                self.statistics.row_revived();
+                self.indexes.insert(person);
+            }
+            (PersonVersionState::State(_), PersonVersionState::Delete) => {
+                self.statistics.row_deleted();
+            }
+            (PersonVersionState::Delete, PersonVersionState::Delete) => {
+                self.statistics.version_added();
+            }
+        }
+
+        Ok(match resolved {
+            PersonVersionState::State(person) => Some(person),
+            PersonVersionState::Delete => None,
+        })
+    }
+
+    fn add_row(
+        &self,
+        person: Person,
+        transaction_id: TransactionId,
+        lineage: Option<Lineage>,
+    ) -> Result<(), ApplyErrors> {
+        self.indexes.insert(&person);
+
+        // We need to handle the case where someone can add an item back after it has been deleted
+        //  if it has been deleted there will already be a row.
+        match self.person_rows.get(&person.id) {
+            Some(existing_person_row) => {
+                existing_person_row.value().write().unwrap().apply_add(
+                    person,
+                    transaction_id,
+                    lineage,
+                )?;
+
+                self.statistics.row_revived();
+            }
+            None => {
+                self.person_rows.insert(
+                    person.id.clone(),
+                    RwLock::new(PersonRow::new(person, transaction_id, lineage)),
+                );
+
+                self.statistics.row_added();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the person at `from` and records the merge on the person at `into`, which takes `fields` from the
+    /// merged person. Like renames, the delete is rolled back before returning if `into` cannot be merged into
+    fn apply_merge(
+        &self,
+        from: EntityId,
+        into: EntityId,
+        fields: &[PersonField],
+        transaction_id: TransactionId,
+    ) -> Result<Person, ApplyErrors> {
+        if from == into {
+            return Err(ApplyErrors::CannotMergeIntoItself(from));
+        }
+
+        let into_row = self
+            .person_rows
+            .get(&into)
+            .ok_or(ApplyErrors::CannotMergeDoesNotExist(into.clone()))?;
+
+        let from_row = self
+            .person_rows
+            .get(&from)
+            .ok_or(ApplyErrors::CannotMergeDoesNotExist(from.clone()))?;
+
+        let ApplyDeleteResult { previous } = from_row
+            .value()
+            .write()
+            .unwrap()
+            .apply_delete(
+                &from,
+                transaction_id.clone(),
+                Some(Lineage::MergedInto(into.clone())),
+            )
+            .map_err(|_| ApplyErrors::CannotMergeDoesNotExist(from.clone()))?;
+
+        self.statistics.row_deleted();
+
+        let result = into_row.value().write().unwrap().apply_merge(
+            &into,
+            &previous,
+            fields,
+            transaction_id.clone(),
+        );
+
+        match result {
+            Ok(person) => {
+                self.statistics.version_added();
+                self.indexes.insert(&person);
+                Ok(person)
+            }
+            Err(_) => {
+                self.remove_mutation(from, &transaction_id);
+                Err(ApplyErrors::CannotMergeDoesNotExist(into))
+            }
+        }
+    }
+
+    /// Deletes the person at `from` and adds each of `people`, recording the split on every version. If any of
+    /// the people cannot be added, everything applied so far is rolled back before returning
+    fn apply_split(
+        &self,
+        from: EntityId,
+        people: Vec<Person>,
+        transaction_id: TransactionId,
+    ) -> Result<Vec<Person>, ApplyErrors> {
+        if people.is_empty() {
+            return Err(ApplyErrors::CannotSplitIntoNothing(from));
+        }
+
+        let from_row = self
+            .person_rows
+            .get(&from)
+            .ok_or(ApplyErrors::CannotSplitDoesNotExist(from.clone()))?;
+
+        let ids = people.iter().map(|person| person.id.clone()).collect();
+
+        from_row
+            .value()
+            .write()
+            .unwrap()
+            .apply_delete(&from, transaction_id.clone(), Some(Lineage::SplitInto(ids)))
+            .map_err(|_| ApplyErrors::CannotSplitDoesNotExist(from.clone()))?;
+
+        self.statistics.row_deleted();
+
+        let mut added: Vec<EntityId> = vec![];
+
+        for person in &people {
+            let lineage = Some(Lineage::SplitFrom(from.clone()));
+
+            // The person being split has just been deleted, so splitting into its own id has to be checked here
+            let result = match person.id == from || added.contains(&person.id) {
+                true => Err(ApplyErrors::CannotSplitWhenAlreadyExists(person.id.clone())),
+                false => self.add_row(person.clone(), transaction_id.clone(), lineage),
+            };
+
+            if result.is_err() {
+                for id in added.into_iter().rev() {
+                    self.remove_mutation(id, &transaction_id);
+                }
+
+                self.remove_mutation(from, &transaction_id);
+
+                return Err(ApplyErrors::CannotSplitWhenAlreadyExists(person.id.clone()));
+            }
+
+            added.push(person.id.clone());
+        }
+
+        Ok(people)
+    }
+
+    /// Creates an optional index (see `IndexedField`) from every version of every row, including the versions
+    /// spilled to storage. The database is paused so no row is purged while the index is built. False if the
+    /// field is already indexed
+    pub fn create_index(&self, field: IndexedField, _: &DatabasePauseEvent) -> bool {
+        if !self.indexes.start_building(&field) {
+            return false;
+        }
+
+        self.backfill_index(&field, None, usize::MAX);
+
+        true
+    }
+
+    /// Adds every version of up to `limit` rows after `after` (in id order) to an index that is being built (see
+    /// `PersonIndexes::start_building`), the index is marked ready once the last row has been added. Returns the
+    /// number of rows that were added and the row the next batch starts after, none once every row has been added
+    ///
+    /// Unlike `create_index` the database does not have to be paused, see `Control::BackfillIndex`
+    pub fn backfill_index(
+        &self,
+        field: &IndexedField,
+        after: Option<&EntityId>,
+        limit: usize,
+    ) -> (usize, Option<EntityId>) {
+        let start = match after {
+            Some(id) => Bound::Excluded(id.clone()),
+            None => Bound::Unbounded,
+        };
+
+        let mut rows = 0;
+        let mut last = None;
+
+        for entry in self.person_rows.range((start, Bound::Unbounded)) {
+            if rows == limit {
+                return (rows, last);
+            }
+
+            // A purge removes the row from the table before it removes the row's versions from the indexes. The
+            //  write lock holds the purge back until the versions have been added, so the purge removes them again
+            #[allow(clippy::readonly_write_lock)]
+            let row = entry.value().write().unwrap();
+
+            if !entry.is_removed() {
+                for version in row.history() {
+                    if let PersonVersionState::State(person) = &version.state {
+                        self.indexes.add_existing(field, person);
+                    }
+                }
+            }
+
+            last = Some(entry.key().clone());
+            rows += 1;
+        }
+
+        self.indexes.mark_ready(field);
+
+        (rows, None)
+    }
+
+    /// How a list query would be scanned, see `QueryPlanner`
+    pub fn plan(&self, query: &Option<QueryPersonData>) -> QueryPlan {
+        self.planner
+            .plan(&self.statistics.snapshot(), &self.indexes, query)
+    }
+
+    /// Updates the materialized views with the rows mutated by a committed transaction
+    pub fn apply_views(&self, statements: &[Statement], transaction_id: &TransactionId) {
+        if self.views.is_empty() {
+            return;
+        }
+
+        let changes: Vec<(EntityId, Option<Person>)> = statements
+            .iter()
+            .flat_map(Statement::mutated_ids)
+            .cloned()
+            .map(|id| {
+                let person = self.person_rows.get(&id).and_then(|row| {
+                    row.value()
+                        .read()
+                        .unwrap()
+                        .at_transaction_id(transaction_id)
+                });
+
+                (id, person)
+            })
+            .collect();
+
+        self.views.apply_transaction(&changes, transaction_id);
+    }
+
+    /// Spills old versions of the rows mutated by a transaction to storage, bounding the memory used
+    /// by rows with long histories. This should only be called once the transaction has been applied
+    pub fn spill_cold_versions(&self, statements: &[Statement], transaction_id: &TransactionId) {
+        let Some(cold_store) = &self.cold_store else {
+            return;
+        };
+
+        for id in statements.iter().flat_map(Statement::mutated_ids) {
+            let Some(person_row) = self.person_rows.get(id) else {
+                continue;
+            };
+
+            let result = person_row
+                .value()
+                .write()
+                .unwrap()
+                .spill_cold_versions(cold_store, transaction_id);
+
+            // Versions are only dropped from memory once they have been written, so failing to spill
+            //  is not fatal, the row will attempt to spill again on its next write
+            if let Err(e) = result {
+                log::warn!(entity_id:% = id; "Unable to spill versions to storage: {}", e);
+            }
+        }
+    }
+
+    /// Drops the rows purged by a committed transaction along with their versions that were spilled to storage,
+    /// see `Statement::Purge`. This should only be called once the transaction has been applied
+    pub fn complete_purges(&self, statements: &[Statement], transaction_id: &TransactionId) {
+        for statement in statements {
+            let Statement::Purge(id) = statement else {
+                continue;
+            };
+
+            let Some(purged) = self.purging.remove(id) else {
+                continue;
+            };
+
+            // The versions are no longer reachable, a chunk that could not be deleted is only wasted space until
+            //  the id is spilled again
+            if let Err(e) = purged.value().delete_cold_versions() {
+                log::warn!(entity_id:% = id; "Unable to delete the spilled versions: {}", e);
+            }
+
+            // Replayed partitions commit out of order, the latest purge is kept
+            let mut latest_purge = self.latest_purge.write().unwrap();
+
+            if latest_purge
+                .as_ref()
+                .map_or(true, |latest| latest < transaction_id)
+            {
+                *latest_purge = Some(transaction_id.clone());
+            }
+        }
+    }
+
+    /// Makes the messages enqueued by a committed transaction visible and drops the messages it acknowledged, see
+    /// `Outbox`. This should only be called once the transaction has been applied
+    /// Records the wall clock time on the rows the committed statements changed, see `EntityMeta`. Replayed
+    /// transactions are not stamped, the WAL does not record when they were committed
+    pub fn stamp_modified(&self, statements: &[Statement], modified_ms: u64) {
+        for id in statements.iter().flat_map(Statement::mutated_ids) {
+            if let Some(row) = self.person_rows.get(id) {
+                row.value().read().unwrap().stamp_modified(modified_ms);
+            }
+        }
+    }
+
+    pub fn complete_outbox(&self, statements: &[Statement], transaction_id: &TransactionId) {
+        self.outbox.complete(statements, transaction_id);
+    }
+
+    /// Checks the latest version of each row against the table's constraints, uncommitted versions included. A
+    /// version of another transaction that is later rolled back may still fail the check, a violation is never
+    /// committed though: checks are serialized, so of two transactions the one checked last sees the rows the
+    /// other applied
+    pub fn check_constraints(&self, ids: &[&EntityId]) -> Result<(), ApplyErrors> {
+        if self.unique_email.is_none() {
+            return Ok(());
+        }
+
+        let _checks = self.constraint_checks.lock().unwrap();
+
+        let email_index = self.indexes.get(&IndexedField::Email);
+
+        for id in ids {
+            let Some(email) = self.latest_email(id) else {
+                continue;
+            };
+
+            // The index keeps every value a row ever had, only the latest version of a candidate is a conflict
+            if let Some(owner) = email_index
+                .candidates(&email)
+                .into_iter()
+                .find(|candidate| {
+                    candidate != *id && self.latest_email(candidate).as_ref() == Some(&email)
+                })
+            {
+                return Err(ApplyErrors::UniqueEmailViolation(email, owner));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn latest_email(&self, id: &EntityId) -> Option<String> {
+        let person_row = self.person_rows.get(id)?;
+        let row = person_row.value().read().unwrap();
+
+        row.current_state()?.email
+    }
+
+    /// Squashes the history of every row, see `HistorySquash`. The database is paused so that only committed
+    /// versions are squashed and no reader sees a row while its versions are renumbered
+    pub fn squash_history(
+        &self,
+        _: &DatabasePauseEvent,
+        squash: &HistorySquash,
+        committed_transaction_id: &TransactionId,
+    ) -> SquashReport {
+        let mut report = SquashReport::default();
+
+        let Some(cutoff) = squash.cutoff(committed_transaction_id) else {
+            return report;
+        };
+
+        for row in &self.person_rows {
+            let squashed = row.value().write().unwrap().squash_history(squash, &cutoff);
+
+            if squashed > 0 {
+                report.rows += 1;
+                report.squashed_versions += squashed;
+            }
+        }
+
+        self.statistics.versions_squashed(report.squashed_versions);
+
+        report
+    }
+
+    /// Panics if a row read by the statement breaks an MVCC invariant, point reads only check the row that
+    /// is read while scans check every row
+    fn check_read_invariants(&self, statement: &Statement, transaction_id: &TransactionId) {
+        let check = |id: &EntityId, row: &RwLock<PersonRow>| {
+            if let Err(e) = row.read().unwrap().check_invariants(transaction_id) {
+                panic!("MVCC invariant violated reading {}: {}", id, e);
+            }
+        };
+
+        match statement {
+            Statement::Get(id)
+            | Statement::GetVersion(id, _)
+            | Statement::Exists(id)
+            | Statement::GetAttachment(id, _)
+            | Statement::Lineage(id)
+            | Statement::History(id, _)
+            | Statement::Meta(id) => {
+                if let Some(row) = self.person_rows.get(id) {
+                    check(row.key(), row.value());
+                }
+            }
+            Statement::GetManyAtTransaction(ids, _) => {
+                for row in ids.iter().filter_map(|id| self.person_rows.get(id)) {
+                    check(row.key(), row.value());
+                }
+            }
+            Statement::List(_)
+            | Statement::ListPage(_, _)
+            | Statement::Count(_)
+            | Statement::ListLatestVersions
+            | Statement::ExecutePreparedQuery(_, _) => {
+                for row in self.person_rows.iter() {
+                    check(row.key(), row.value());
+                }
+            }
+            Statement::Scan { start, end, .. } => {
+                for row in self.person_rows.range((start.clone(), end.clone())) {
+                    check(row.key(), row.value());
+                }
+            }
+            Statement::QueryView(_)
+            | Statement::TableVersion
+            | Statement::QuerySystemTable(_)
+            | Statement::Add(_)
+            | Statement::Update(_, _)
+            | Statement::Remove(_)
+            | Statement::Rename(_, _)
+            | Statement::Merge(_, _, _)
+            | Statement::Split(_, _)
+            | Statement::ResolveConflict(_, _)
+            | Statement::Purge(_)
+            | Statement::NextVal(_)
+            | Statement::Enqueue(_, _)
+            | Statement::ReadOutbox(_, _)
+            | Statement::AckOutbox(_) => {}
+        }
+    }
+
+    /// Panics if a rolled back transaction left a version behind in one of the rows it mutated. This should
+    /// only be called once every statement of the transaction has been rolled back
+    pub fn check_rollback(&self, statements: &[Statement], transaction_id: &TransactionId) {
+        if !self.paranoid_checks {
+            return;
+        }
+
+        for id in statements.iter().flat_map(Statement::mutated_ids) {
+            let Some(row) = self.person_rows.get(id) else {
+                continue;
+            };
+
+            let result = row
+                .value()
+                .read()
+                .unwrap()
+                .check_rolled_back(transaction_id);
 
-                StatementResult::Single(current)
+            if let Err(e) = result {
+                panic!("MVCC invariant violated after rollback: {}", e);
             }
-            Statement::Remove(id) => {
-                let person_row = self
-                    .person_rows
-                    .get(&id)
-                    .ok_or(ApplyErrors::CannotDeleteDoesNotExist(id.clone()))?;
+        }
+    }
 
-                let ApplyDeleteResult { previous } = person_row
-                    .value()
-                    .write()
-                    .unwrap()
-                    .apply_delete(&id, transaction_id)?;
+    /// Removes the row with all of its versions, the row is kept in `purging` until the transaction commits or
+    /// rolls back. Returns the number of versions that were removed
+    fn apply_purge(&self, id: EntityId) -> Result<usize, ApplyErrors> {
+        let removed = self
+            .person_rows
+            .remove(&id)
+            .ok_or(ApplyErrors::CannotPurgeDoesNotExist(id.clone()))?;
 
-                StatementResult::Single(previous)
-            }
-            s @ Statement::Get(_)
-            | s @ Statement::GetVersion(_, _)
-            | s @ Statement::List(_)
-            | s @ Statement::ListLatestVersions => {
-                return self.query_statement(s, &transaction_id);
+        let row = removed.value().read().unwrap().clone();
+
+        let versions = row.history();
+
+        for version in &versions {
+            if let PersonVersionState::State(person) = &version.state {
+                self.indexes.remove(person);
             }
-        };
+        }
 
-        Ok(action_result)
+        self.statistics.row_purged(
+            row.current_version().state == PersonVersionState::Delete,
+            versions.len(),
+        );
+
+        self.purging.insert(id, row);
+
+        Ok(versions.len())
     }
 
-    pub fn apply_rollback(&self, statement: Statement) {
-        match statement {
-            Statement::Add(person) => {
-                self.remove_mutation(person.id);
-            }
-            Statement::Update(id, _) => {
-                self.remove_mutation(id);
-            }
-            Statement::Remove(id) => {
-                self.remove_mutation(id);
+    fn restore_purged(&self, id: EntityId) {
+        let purged = self
+            .purging
+            .remove(&id)
+            .expect("should exist because there is a rollback");
+
+        let row = purged.value().clone();
+
+        let versions = row.history();
+
+        for version in &versions {
+            if let PersonVersionState::State(person) = &version.state {
+                self.indexes.insert(person);
             }
-            Statement::Get(_)
-            | Statement::GetVersion(_, _)
-            | Statement::List(_)
-            | Statement::ListLatestVersions => {}
         }
+
+        self.statistics.purge_rolled_back(
+            row.current_version().state == PersonVersionState::Delete,
+            versions.len(),
+        );
+
+        self.person_rows.insert(id, RwLock::new(row));
     }
 
     // TODO: Is there a way to centralize the logic for removing constraints? We could run into a situation
     //  where we update the logic here OR the row logic and it could get out of sync. This will likely be important
     //  for indexing as well.
-    fn remove_mutation(&self, id: EntityId) {
+    fn remove_mutation(&self, id: EntityId, transaction_id: &TransactionId) {
         let person_row = self
             .person_rows
             .get(&id)
             .expect("should exist because there is a rollback");
 
-        // Remove the version that was applied
-        let (person_version_to_remove, drop_row) =
-            person_row.value().write().unwrap().rollback_version();
+        // Retiring the version that was applied only needs the read lock, so the rollback does not wait on readers
+        let retired = person_row
+            .value()
+            .read()
+            .unwrap()
+            .retire_version(transaction_id);
+
+        let change = match retired {
+            Some(change) => {
+                // Reclaimed straight away unless the row is being read, then it is left to the next writer or sweep
+                match person_row.value().try_write() {
+                    Ok(mut row) => {
+                        row.reclaim();
+                    }
+                    Err(_) => {
+                        self.retired_rows.insert(id.clone());
+                    }
+                }
 
-        if matches!(person_version_to_remove.state, PersonVersionState::State(_)) {
-            // Note: This should only happen when we rollback an add
-            if let DropRow::NoVersionsExist = drop_row {
-                self.person_rows.remove(&id);
+                change
             }
+            None => person_row
+                .value()
+                .write()
+                .unwrap()
+                .rollback_version(transaction_id),
+        };
+
+        self.statistics
+            .version_rolled_back(change.previous_deleted, change.current_deleted);
+
+        // Note: This should only happen when we rollback an add
+        if change.current_deleted.is_none() {
+            self.person_rows.remove(&id);
+        }
+    }
+
+    /// Reclaims the rolled back versions that could not be reclaimed when they were retired because the row was
+    /// being read, see `RetiredVersion`. Rows that are still being read are left for the next sweep, this never
+    /// waits on a reader. Returns the number of versions that were reclaimed
+    pub fn reclaim_retired_versions(&self) -> usize {
+        let mut reclaimed = 0;
+
+        let ids: Vec<EntityId> = self
+            .retired_rows
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+
+        for id in ids {
+            // Removed first, so a version retired while the row is being reclaimed is not lost
+            self.retired_rows.remove(&id);
+
+            let Some(person_row) = self.person_rows.get(&id) else {
+                continue;
+            };
+
+            match person_row.value().try_write() {
+                Ok(mut row) => {
+                    if row.reclaim() {
+                        reclaimed += 1;
+                    }
+                }
+                Err(_) => {
+                    self.retired_rows.insert(id);
+                }
+            };
         }
+
+        reclaimed
+    }
+
+    /// Rows with a retired version that is waiting to be reclaimed, see `reclaim_retired_versions`
+    pub fn retired_row_count(&self) -> usize {
+        self.retired_rows.len()
     }
 
     #[cfg(test)]
@@ -274,7 +1563,10 @@ fn sort_list(people: &mut Vec<Person>) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::table::row::{UpdatePersonData, UpdateStatement};
+    use crate::{
+        consts::consts::VersionId,
+        database::table::row::{UpdatePersonData, UpdateStatement},
+    };
 
     // TODO:
     //  - There should be a better way of comparing lists of a default sort (sort_list)
@@ -285,7 +1577,10 @@ mod tests {
 
         /// Simplest possible cases -- does not need to handle any problems with versioning
         mod add {
-            use crate::database::table::query::{QueryMatch, QueryPersonData};
+            use crate::{
+                database::table::query::{QueryAddressData, QueryMatch, QueryPersonData},
+                model::person::Address,
+            };
 
             use super::*;
 
@@ -326,6 +1621,7 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Value("1".to_string()),
                     email: QueryMatch::Any,
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should only get the rows with "1"
@@ -349,6 +1645,7 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Value("2".to_string()),
                     email: QueryMatch::Value("2".to_string()),
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should only get back the rows with "2" for email and full name
@@ -372,6 +1669,7 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Any,
                     email: QueryMatch::Null,
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should only get back the rows with null
@@ -395,6 +1693,7 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Any,
                     email: QueryMatch::Value("1".to_string()),
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should only get items that have an email of "1", which there are none
@@ -418,11 +1717,80 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Any,
                     email: QueryMatch::NotNull,
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should only get items that have an email, which there is 1
                 list_test(seed_actions, list_action, vec![seed_data[0].clone()]);
             }
+
+            #[test]
+            fn filter_structured_fields() {
+                // Given there is a table with people with and without an address and phone numbers
+                let seed_data = vec![
+                    Person::new("1".to_string(), None)
+                        .set_address(Address {
+                            city: Some("Sydney".to_string()),
+                            ..Address::default()
+                        })
+                        .set_phone_numbers(vec!["111".to_string(), "222".to_string()]),
+                    Person::new("2".to_string(), None).set_address(Address {
+                        city: Some("Perth".to_string()),
+                        country: Some("Australia".to_string()),
+                        ..Address::default()
+                    }),
+                    Person::new("3".to_string(), None),
+                ];
+
+                let seed_actions: Vec<Statement> = seed_data
+                    .iter()
+                    .map(|person| Statement::Add(person.clone()))
+                    .collect();
+
+                let by_address = |address: QueryAddressData| {
+                    Statement::List(Some(QueryPersonData {
+                        address,
+                        ..QueryPersonData::default()
+                    }))
+                };
+
+                // Parts of the address match on their own, people without an address have null parts
+                list_test(
+                    seed_actions.clone(),
+                    by_address(QueryAddressData {
+                        city: QueryMatch::Value("Sydney".to_string()),
+                        ..QueryAddressData::default()
+                    }),
+                    vec![seed_data[0].clone()],
+                );
+                list_test(
+                    seed_actions.clone(),
+                    by_address(QueryAddressData {
+                        country: QueryMatch::Null,
+                        ..QueryAddressData::default()
+                    }),
+                    vec![seed_data[0].clone(), seed_data[2].clone()],
+                );
+
+                let by_phone_number = |phone_number: QueryMatch| {
+                    Statement::List(Some(QueryPersonData {
+                        phone_number,
+                        ..QueryPersonData::default()
+                    }))
+                };
+
+                // A value matches any of the phone numbers
+                list_test(
+                    seed_actions.clone(),
+                    by_phone_number(QueryMatch::Value("222".to_string())),
+                    vec![seed_data[0].clone()],
+                );
+                list_test(
+                    seed_actions,
+                    by_phone_number(QueryMatch::Null),
+                    vec![seed_data[1].clone(), seed_data[2].clone()],
+                );
+            }
         }
 
         mod update {
@@ -445,6 +1813,7 @@ mod tests {
                         UpdatePersonData {
                             full_name: UpdateStatement::Set("2".to_string()),
                             email: UpdateStatement::NoChanges,
+                            ..UpdatePersonData::default()
                         },
                     ),
                 ];
@@ -472,6 +1841,7 @@ mod tests {
                         UpdatePersonData {
                             full_name: UpdateStatement::Set("2".to_string()),
                             email: UpdateStatement::NoChanges,
+                            ..UpdatePersonData::default()
                         },
                     ),
                 ];
@@ -484,6 +1854,7 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Value("2".to_string()),
                     email: QueryMatch::Any,
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should get the updated item back
@@ -502,6 +1873,7 @@ mod tests {
                         UpdatePersonData {
                             full_name: UpdateStatement::Set("2".to_string()),
                             email: UpdateStatement::NoChanges,
+                            ..UpdatePersonData::default()
                         },
                     ),
                 ];
@@ -510,6 +1882,7 @@ mod tests {
                 let list_action = Statement::List(Some(QueryPersonData {
                     full_name: QueryMatch::Value("1".to_string()),
                     email: QueryMatch::Any,
+                    ..QueryPersonData::default()
                 }));
 
                 // Then we should get no items back
@@ -554,6 +1927,7 @@ mod tests {
             let list_action = Statement::List(Some(QueryPersonData {
                 full_name: QueryMatch::Any,
                 email: QueryMatch::Any,
+                ..QueryPersonData::default()
             }));
 
             // Then we should get no items back
@@ -594,6 +1968,7 @@ mod tests {
                         state: PersonVersionState::State(person),
                         version: VersionId(1),
                         transaction_id: TransactionId(1),
+                        lineage: None,
                     })
                 );
             }
@@ -622,6 +1997,7 @@ mod tests {
                         state: PersonVersionState::State(person),
                         version: VersionId(1),
                         transaction_id: TransactionId(1),
+                        lineage: None,
                     })
                 );
 
@@ -632,6 +2008,7 @@ mod tests {
                         state: PersonVersionState::State(updated_person),
                         version: VersionId(2),
                         transaction_id: TransactionId(2),
+                        lineage: None,
                     })
                 );
             }
@@ -664,6 +2041,7 @@ mod tests {
                         state: PersonVersionState::State(add_person),
                         version: VersionId(1),
                         transaction_id: TransactionId(1),
+                        lineage: None,
                     })
                 );
 
@@ -674,6 +2052,7 @@ mod tests {
                         state: PersonVersionState::State(updated_person.clone()),
                         version: VersionId(2),
                         transaction_id: TransactionId(2),
+                        lineage: None,
                     })
                 );
 
@@ -684,6 +2063,7 @@ mod tests {
                         state: PersonVersionState::Delete,
                         version: VersionId(3),
                         transaction_id: TransactionId(3),
+                        lineage: None,
                     })
                 );
             }
@@ -799,6 +2179,174 @@ mod tests {
 
                 assert!(person_v3.is_none());
             }
+
+            #[test]
+            fn get_many_at_transaction_reads_one_horizon() {
+                let table = PersonTable::new();
+                let person = |id: &str, full_name: &str| Person {
+                    id: EntityId(id.to_string()),
+                    full_name: full_name.to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                };
+
+                // Given two people added at transaction 1 and 2
+                let first = TransactionId::new_first_transaction();
+                let horizon = first.increment();
+
+                table
+                    .apply(Statement::Add(person("a", "A")), first.clone())
+                    .unwrap();
+                table
+                    .apply(Statement::Add(person("b", "B")), horizon.clone())
+                    .unwrap();
+
+                // And one of them is updated after the horizon
+                let latest = horizon.increment();
+
+                table
+                    .apply(
+                        Statement::Update(
+                            EntityId("a".to_string()),
+                            UpdatePersonData {
+                                full_name: UpdateStatement::Set("A2".to_string()),
+                                email: UpdateStatement::NoChanges,
+                                ..UpdatePersonData::default()
+                            },
+                        ),
+                        latest.clone(),
+                    )
+                    .unwrap();
+
+                let ids = vec![
+                    EntityId("b".to_string()),
+                    EntityId("missing".to_string()),
+                    EntityId("a".to_string()),
+                ];
+
+                // Then both rows are read as of the horizon, in the requested order and without the missing id
+                let people = table
+                    .query_statement(
+                        Statement::GetManyAtTransaction(ids.clone(), horizon.clone()),
+                        &latest,
+                    )
+                    .unwrap()
+                    .list();
+
+                assert_eq!(people, vec![person("b", "B"), person("a", "A")]);
+
+                // And the horizon cannot be newer than the snapshot of the read
+                assert!(matches!(
+                    table.query_statement(
+                        Statement::GetManyAtTransaction(ids, latest.increment()),
+                        &latest,
+                    ),
+                    Err(ApplyErrors::CannotGetAtFutureTransaction(_, _))
+                ));
+            }
+        }
+    }
+
+    mod statistics {
+        use crate::database::table::statistics::TableStatisticsSnapshot;
+
+        use super::*;
+
+        fn stats(
+            live_rows: usize,
+            deleted_rows: usize,
+            total_versions: usize,
+        ) -> TableStatisticsSnapshot {
+            TableStatisticsSnapshot {
+                live_rows,
+                deleted_rows,
+                total_versions,
+            }
+        }
+
+        #[test]
+        fn maintained_across_apply_and_rollback() {
+            let table = PersonTable::new();
+            let mut transaction_id = TransactionId::new_first_transaction();
+
+            let mut apply = |statement: Statement| {
+                table
+                    .apply(statement.clone(), transaction_id.clone())
+                    .unwrap();
+                let applied = (statement, transaction_id.clone());
+                transaction_id = transaction_id.increment();
+                applied
+            };
+
+            let person_1 = Person::new("1".to_string(), None);
+            let person_2 = Person::new("2".to_string(), None);
+
+            // Add two rows, update one and remove the other
+            apply(Statement::Add(person_1.clone()));
+            apply(Statement::Add(person_2.clone()));
+            apply(Statement::Update(
+                person_1.id.clone(),
+                UpdatePersonData {
+                    full_name: UpdateStatement::Set("updated".to_string()),
+                    email: UpdateStatement::NoChanges,
+                    ..UpdatePersonData::default()
+                },
+            ));
+            apply(Statement::Remove(person_2.id.clone()));
+
+            assert_eq!(table.statistics.snapshot(), stats(1, 1, 4));
+            assert_eq!(table.statistics.snapshot().average_versions_per_row(), 2.0);
+
+            // Adding a deleted row back revives it, rolling it back deletes it again
+            let revive = apply(Statement::Add(person_2.clone()));
+            assert_eq!(table.statistics.snapshot(), stats(2, 0, 5));
+
+            table.apply_rollback(revive.0, &revive.1);
+            assert_eq!(table.statistics.snapshot(), stats(1, 1, 4));
+
+            // Rolling back a delete
+            let remove = apply(Statement::Remove(person_1.id.clone()));
+            assert_eq!(table.statistics.snapshot(), stats(0, 2, 5));
+
+            table.apply_rollback(remove.0, &remove.1);
+            assert_eq!(table.statistics.snapshot(), stats(1, 1, 4));
+
+            // Rolling back a brand new row drops it
+            let add = apply(Statement::Add(Person::new("3".to_string(), None)));
+            table.apply_rollback(add.0, &add.1);
+            assert_eq!(table.statistics.snapshot(), stats(1, 1, 4));
+
+            // Purging a deleted row drops every version, rolling it back restores them
+            let purge = apply(Statement::Purge(person_2.id.clone()));
+            assert_eq!(table.statistics.snapshot(), stats(1, 0, 2));
+
+            table.apply_rollback(purge.0, &purge.1);
+            assert_eq!(table.statistics.snapshot(), stats(1, 1, 4));
+        }
+
+        #[test]
+        fn restored_from_snapshot() {
+            let table = PersonTable::new();
+
+            let version = |person: &Person, state: PersonVersionState| PersonVersion {
+                id: person.id.clone(),
+                state,
+                version: VersionId::new_first_version(),
+                transaction_id: TransactionId::new_first_transaction(),
+                lineage: None,
+            };
+
+            let person_1 = Person::new("1".to_string(), None);
+            let person_2 = Person::new("2".to_string(), None);
+
+            table.restore_table(vec![
+                version(&person_1, PersonVersionState::State(person_1.clone())),
+                version(&person_2, PersonVersionState::Delete),
+            ]);
+
+            assert_eq!(table.statistics.snapshot(), stats(1, 1, 2));
         }
     }
 
@@ -900,6 +2448,560 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cancelled_list_returns_error() {
+        // Given a table with a person in it
+        let mut table = PersonTable::new();
+        let (_, next_transaction_id) = add_test_person_to_empty_database(&mut table);
+
+        // When the request listing the table has been cancelled
+        let cancellation = CancellationToken::default();
+        cancellation.cancel();
+
+        let result = table.query_statement_with_options(
+            Statement::List(None),
+            &next_transaction_id,
+            &ReadOptions {
+                cancellation,
+                ..ReadOptions::default()
+            },
+        );
+
+        // Then the scan stops with an error
+        assert!(matches!(result, Err(ApplyErrors::Cancelled)));
+    }
+
+    #[test]
+    fn planner_uses_indexes_for_selective_queries() {
+        use crate::database::table::query::{QueryMatch, QueryPersonData};
+
+        let list =
+            |table: &PersonTable, query: &QueryPersonData, transaction_id: &TransactionId| {
+                match table.query_statement(Statement::List(Some(query.clone())), transaction_id) {
+                    Ok(StatementResult::List(people)) => people,
+                    _ => {
+                        assert!(false, "should be a list of people");
+                        vec![]
+                    }
+                }
+            };
+
+        let by_email = |email: &str| QueryPersonData {
+            full_name: QueryMatch::Any,
+            email: QueryMatch::Value(email.to_string()),
+            ..QueryPersonData::default()
+        };
+
+        // Given a table with a single person, reading the whole table is cheaper than the index
+        let mut table = PersonTable::new();
+        let (first, mut next_transaction_id) = add_test_person_to_empty_database(&mut table);
+        let first_email = first.email.clone().unwrap();
+
+        assert_eq!(table.plan(&Some(by_email(&first_email))).scan, Scan::Full);
+
+        let before_update = next_transaction_id.clone();
+
+        // Given a table with many people, each with a different email
+        for _ in 0..20 {
+            (_, next_transaction_id) = add_test_person(&mut table, next_transaction_id);
+        }
+
+        // Then a query on an email reads the email index
+        let plan = table.plan(&Some(by_email(&first_email)));
+
+        assert_eq!(
+            plan,
+            QueryPlan {
+                scan: Scan::Index {
+                    field: IndexedField::Email,
+                    value: first_email.clone()
+                },
+                estimated_rows: 1,
+                table_rows: 21,
+            }
+        );
+        assert_eq!(
+            list(&table, &by_email(&first_email), &next_transaction_id),
+            vec![first.clone()]
+        );
+
+        // And queries without a selective value read the whole table
+        let not_null = QueryPersonData {
+            full_name: QueryMatch::Any,
+            email: QueryMatch::NotNull,
+            ..QueryPersonData::default()
+        };
+
+        assert_eq!(table.plan(&Some(not_null.clone())).scan, Scan::Full);
+        assert_eq!(list(&table, &not_null, &next_transaction_id).len(), 21);
+
+        // When the email is updated, the index still serves snapshots from before the update
+        let (updated, next_transaction_id) =
+            update_test_person(&mut table, &first, next_transaction_id);
+
+        assert!(list(&table, &by_email(&first_email), &next_transaction_id).is_empty());
+        assert_eq!(
+            list(&table, &by_email(&first_email), &before_update),
+            vec![first]
+        );
+        assert_eq!(
+            list(&table, &by_email("email"), &next_transaction_id),
+            vec![updated]
+        );
+
+        // And the scans that were run are counted
+        assert!(table
+            .planner
+            .get_stats()
+            .contains(&("ListIndexScans".to_string(), "4".to_string())));
+    }
+
+    #[test]
+    fn rename_links_versions_and_rolls_back() {
+        // Given a table with two people
+        let mut table = PersonTable::new();
+
+        let (person, next_transaction_id) = add_test_person_to_empty_database(&mut table);
+        let (other, next_transaction_id) = add_test_person(&mut table, next_transaction_id);
+
+        let renamed_id = EntityId("renamed".to_string());
+
+        // When the first person is renamed
+        let renamed = table
+            .apply(
+                Statement::Rename(person.id.clone(), renamed_id.clone()),
+                next_transaction_id.clone(),
+            )
+            .unwrap()
+            .single();
+
+        // Then the person is moved and both versions point at each other
+        assert_eq!(renamed.id, renamed_id);
+        assert_eq!(renamed.full_name, person.full_name);
+
+        let from = table.get_version_row_test(&person.id);
+        assert_eq!(from.current_state(), None);
+        assert_eq!(
+            from.current_version().lineage,
+            Some(Lineage::RenamedTo(renamed_id.clone()))
+        );
+
+        let to = table.get_version_row_test(&renamed_id);
+        assert_eq!(to.current_state(), Some(renamed));
+        assert_eq!(
+            to.current_version().lineage,
+            Some(Lineage::RenamedFrom(person.id.clone()))
+        );
+
+        // A failed rename is not applied at all
+        let result = table.apply(
+            Statement::Rename(renamed_id.clone(), other.id.clone()),
+            next_transaction_id.increment(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ApplyErrors::CannotRenameWhenAlreadyExists(_))
+        ));
+        assert_eq!(table.get_version_row_test(&renamed_id).version_count(), 1);
+
+        // Rolling back the rename restores the original id
+        table.apply_rollback(
+            Statement::Rename(person.id.clone(), renamed_id.clone()),
+            &next_transaction_id,
+        );
+
+        assert!(table.person_rows.get(&renamed_id).is_none());
+        assert_eq!(
+            table.get_version_row_test(&person.id).current_state(),
+            Some(person)
+        );
+    }
+
+    #[test]
+    fn merge_and_split_record_lineage() {
+        // Given a table with two people
+        let mut table = PersonTable::new();
+
+        let (person, next_transaction_id) = add_test_person_to_empty_database(&mut table);
+        let (duplicate, next_transaction_id) = add_test_person(&mut table, next_transaction_id);
+
+        // When the duplicate is merged in, taking its email
+        let merged = table
+            .apply(
+                Statement::Merge(
+                    duplicate.id.clone(),
+                    person.id.clone(),
+                    vec![PersonField::Email],
+                ),
+                next_transaction_id.clone(),
+            )
+            .unwrap()
+            .single();
+
+        // Then only the chosen fields are taken from the duplicate
+        assert_eq!(merged.id, person.id);
+        assert_eq!(merged.full_name, person.full_name);
+        assert_eq!(merged.email, duplicate.email);
+
+        assert_eq!(
+            table
+                .get_version_row_test(&duplicate.id)
+                .current_version()
+                .lineage,
+            Some(Lineage::MergedInto(person.id.clone()))
+        );
+
+        // When the merged person is split back into two people
+        let next_transaction_id = next_transaction_id.increment();
+        let parts = vec![
+            Person::new("Part 1".to_string(), None),
+            Person::new("Part 2".to_string(), None),
+        ];
+
+        let split = table
+            .apply(
+                Statement::Split(person.id.clone(), parts.clone()),
+                next_transaction_id.clone(),
+            )
+            .unwrap()
+            .list();
+
+        // Then the person is deleted and each part records where it came from
+        assert_eq!(split, parts);
+
+        let from = table.get_version_row_test(&person.id);
+        assert_eq!(from.current_state(), None);
+        assert_eq!(
+            from.current_version().predecessor_id(),
+            None,
+            "the delete hands the person off"
+        );
+        assert_eq!(from.current_version().successor_ids().len(), 2);
+
+        for part in &parts {
+            assert_eq!(
+                table
+                    .get_version_row_test(&part.id)
+                    .current_version()
+                    .lineage,
+                Some(Lineage::SplitFrom(person.id.clone()))
+            );
+        }
+
+        // A split into an existing person is not applied at all
+        let next_transaction_id = next_transaction_id.increment();
+        let new_part = Person::new("Part 3".to_string(), None);
+
+        let result = table.apply(
+            Statement::Split(
+                parts[0].id.clone(),
+                vec![new_part.clone(), parts[1].clone()],
+            ),
+            next_transaction_id,
+        );
+
+        assert!(matches!(
+            result,
+            Err(ApplyErrors::CannotSplitWhenAlreadyExists(_))
+        ));
+        assert!(table.person_rows.get(&new_part.id).is_none());
+        assert_eq!(
+            table.get_version_row_test(&parts[0].id).current_state(),
+            Some(parts[0].clone())
+        );
+    }
+
+    #[test]
+    fn resolve_conflict_keeps_both_sides() {
+        use crate::database::table::conflict::{
+            Conflict, ConflictRecord, DivergentVersion, HybridTimestamp,
+        };
+
+        // Given a table with a person, resolving conflicts field by field
+        let mut table = PersonTable::new().set_conflict_resolution(ConflictResolution::FieldMerge);
+
+        let (person, next_transaction_id) = add_test_person_to_empty_database(&mut table);
+
+        let timestamp = |physical_ms| HybridTimestamp::new(physical_ms, 0, "replica".to_string());
+        let remote = DivergentVersion {
+            state: PersonVersionState::State(Person {
+                full_name: "Remote".to_string(),
+                email: None,
+                ..person.clone()
+            }),
+            timestamp: timestamp(2),
+        };
+
+        // When a later remote version without an email is resolved
+        let merged = table
+            .apply(
+                Statement::ResolveConflict(
+                    person.id.clone(),
+                    Conflict {
+                        local_timestamp: timestamp(1),
+                        remote: remote.clone(),
+                    },
+                ),
+                next_transaction_id.clone(),
+            )
+            .unwrap()
+            .get_single()
+            .unwrap();
+
+        // Then the remote name wins, the local email is kept and both sides are recorded in the lineage
+        assert_eq!(merged.full_name, "Remote");
+        assert_eq!(merged.email, person.email);
+
+        let row = table.get_version_row_test(&person.id);
+        assert_eq!(row.version_count(), 2);
+        assert_eq!(
+            row.current_version().lineage,
+            Some(Lineage::ConflictResolved(Box::new(ConflictRecord {
+                strategy: "FieldMerge".to_string(),
+                local: DivergentVersion {
+                    state: PersonVersionState::State(person.clone()),
+                    timestamp: timestamp(1),
+                },
+                remote: remote.clone(),
+            })))
+        );
+
+        // A later remote delete wins, and a conflict for a row that does not exist is rejected
+        let next_transaction_id = next_transaction_id.increment();
+        let deleted = DivergentVersion {
+            state: PersonVersionState::Delete,
+            timestamp: timestamp(3),
+        };
+        let conflict = Conflict {
+            local_timestamp: timestamp(2),
+            remote: deleted,
+        };
+
+        let resolved = table
+            .apply(
+                Statement::ResolveConflict(person.id.clone(), conflict.clone()),
+                next_transaction_id.clone(),
+            )
+            .unwrap()
+            .get_single();
+
+        assert_eq!(resolved, None);
+        assert_eq!(table.statistics.snapshot().deleted_rows, 1);
+
+        let result = table.apply(
+            Statement::ResolveConflict(
+                EntityId("other".to_string()),
+                Conflict {
+                    local_timestamp: timestamp(2),
+                    remote,
+                },
+            ),
+            next_transaction_id.increment(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ApplyErrors::CannotResolveDoesNotExist(_))
+        ));
+
+        // Rolling back the resolution restores the merged version
+        table.apply_rollback(
+            Statement::ResolveConflict(person.id.clone(), conflict),
+            &next_transaction_id,
+        );
+
+        assert_eq!(
+            table.get_version_row_test(&person.id).current_state(),
+            Some(merged)
+        );
+        assert_eq!(table.statistics.snapshot().live_rows, 1);
+    }
+
+    /// Rollbacks retire versions while readers hold the row, concurrent transactions stack their versions on the
+    /// same rows and roll back in any order
+    mod concurrent_rollback {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            thread,
+        };
+
+        use crate::database::table::statistics::TableStatisticsSnapshot;
+
+        use super::*;
+
+        const PEOPLE: usize = 8;
+        const WRITERS: usize = 4;
+        const READERS: usize = 4;
+        const ROLLBACKS: usize = 200;
+
+        #[test]
+        fn readers_at_old_transactions_see_committed_versions() {
+            let table = PersonTable::new();
+            let mut transaction_id = TransactionId::new_first_transaction();
+
+            let mut people = vec![];
+            let mut added_transaction_id = transaction_id.clone();
+            let mut committed_transaction_id = transaction_id.clone();
+
+            // Every person is added, then updated, by committed transactions
+            for index in 0..PEOPLE {
+                let person = Person::new(format!("Added {}", index), None);
+                table
+                    .apply(Statement::Add(person.clone()), transaction_id.clone())
+                    .unwrap();
+                people.push(person);
+                added_transaction_id = transaction_id.clone();
+                transaction_id = transaction_id.increment();
+            }
+
+            for (index, person) in people.iter_mut().enumerate() {
+                let update = UpdatePersonData {
+                    full_name: UpdateStatement::Set(format!("Updated {}", index)),
+                    ..UpdatePersonData::default()
+                };
+                table
+                    .apply(
+                        Statement::Update(person.id.clone(), update),
+                        transaction_id.clone(),
+                    )
+                    .unwrap();
+                person.full_name = format!("Updated {}", index);
+                committed_transaction_id = transaction_id.clone();
+                transaction_id = transaction_id.increment();
+            }
+
+            let next_transaction_id = AtomicUsize::new(transaction_id.to_number());
+
+            // Transactions are handed out and applied in order, so versions stack in transaction order
+            let apply_lock = Mutex::new(());
+            let rolled_back = AtomicUsize::new(0);
+
+            thread::scope(|scope| {
+                for writer in 0..WRITERS {
+                    let (table, people) = (&table, &people);
+                    let (apply_lock, next_transaction_id) = (&apply_lock, &next_transaction_id);
+                    let rolled_back = &rolled_back;
+
+                    scope.spawn(move || {
+                        for iteration in 0..ROLLBACKS {
+                            let person = &people[(writer + iteration) % PEOPLE];
+                            let statement = match iteration % 2 {
+                                0 => Statement::Remove(person.id.clone()),
+                                _ => Statement::Update(
+                                    person.id.clone(),
+                                    UpdatePersonData {
+                                        full_name: UpdateStatement::Set("Rolled back".to_string()),
+                                        ..UpdatePersonData::default()
+                                    },
+                                ),
+                            };
+
+                            let guard = apply_lock.lock().unwrap();
+                            let transaction_id =
+                                TransactionId(next_transaction_id.fetch_add(1, Ordering::SeqCst));
+
+                            // Another transaction may have stacked a delete on the row
+                            let applied = table.apply(statement.clone(), transaction_id.clone());
+                            drop(guard);
+
+                            if applied.is_ok() {
+                                thread::yield_now();
+                                table.apply_rollback(statement, &transaction_id);
+                                rolled_back.fetch_add(1, Ordering::SeqCst);
+                            }
+
+                            table.reclaim_retired_versions();
+                        }
+                    });
+                }
+
+                for _ in 0..READERS {
+                    let (table, people) = (&table, &people);
+                    let (added, committed) = (&added_transaction_id, &committed_transaction_id);
+
+                    scope.spawn(move || {
+                        for iteration in 0..ROLLBACKS * 4 {
+                            let (index, person) = (iteration % PEOPLE, &people[iteration % PEOPLE]);
+                            let row = table.person_rows.get(&person.id).unwrap();
+                            let row = row.value().read().unwrap();
+
+                            assert_eq!(row.at_transaction_id(committed), Some(person.clone()));
+                            assert_eq!(
+                                row.at_transaction_id(added).unwrap().full_name,
+                                format!("Added {}", index)
+                            );
+                            assert_eq!(
+                                row.person_at_version(VersionId(2), committed),
+                                Some(person.clone())
+                            );
+                            assert!(row.check_invariants(committed).is_ok());
+                        }
+                    });
+                }
+            });
+
+            assert!(rolled_back.load(Ordering::SeqCst) > 0);
+
+            table.reclaim_retired_versions();
+            assert_eq!(table.retired_row_count(), 0);
+
+            // Every rolled back version is gone, as if the transactions were never applied
+            let latest_transaction_id = TransactionId(next_transaction_id.load(Ordering::SeqCst));
+
+            for person in &people {
+                let row = table.get_version_row_test(&person.id);
+
+                assert_eq!(row.version_count(), 2);
+                assert_eq!(row.current_state(), Some(person.clone()));
+                assert!(row.check_invariants(&latest_transaction_id).is_ok());
+            }
+
+            assert_eq!(
+                table.statistics.snapshot(),
+                TableStatisticsSnapshot {
+                    live_rows: PEOPLE,
+                    deleted_rows: 0,
+                    total_versions: PEOPLE * 2,
+                }
+            );
+        }
+
+        #[test]
+        fn rolled_back_version_read_by_another_thread_is_reclaimed_later() {
+            let mut table = PersonTable::new();
+            let (person, transaction_id) = add_test_person_to_empty_database(&mut table);
+
+            let statement = Statement::Remove(person.id.clone());
+            table
+                .apply(statement.clone(), transaction_id.clone())
+                .unwrap();
+
+            let row = table.person_rows.get(&person.id).unwrap();
+            let reader = row.value().read().unwrap();
+
+            // The rollback does not wait on the reader, the version is retired until the reader is done
+            thread::scope(|scope| {
+                scope.spawn(|| table.apply_rollback(statement, &transaction_id));
+            });
+
+            assert_eq!(reader.current_state(), Some(person.clone()));
+            assert_eq!(reader.version_count(), 1);
+            assert_eq!(table.retired_row_count(), 1);
+            assert_eq!(table.reclaim_retired_versions(), 0);
+
+            drop(reader);
+
+            assert_eq!(table.reclaim_retired_versions(), 1);
+            assert_eq!(table.retired_row_count(), 0);
+            assert_eq!(table.statistics.snapshot().live_rows, 1);
+            assert!(table
+                .get_version_row_test(&person.id)
+                .check_rolled_back(&transaction_id)
+                .is_ok());
+        }
+    }
+
     #[allow(dead_code)]
     fn add_test_person_to_empty_database(table: &mut PersonTable) -> (Person, TransactionId) {
         let transaction_id = TransactionId::new_first_transaction();
@@ -936,6 +3038,7 @@ mod tests {
             UpdatePersonData {
                 full_name: UpdateStatement::NoChanges,
                 email: UpdateStatement::Set("email".to_string()),
+                ..UpdatePersonData::default()
             },
         );
 
@@ -0,0 +1,250 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    model::person::Person,
+};
+
+use super::query::{matches, QueryPersonData};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PersonField {
+    FullName,
+    Email,
+}
+
+/// A named, stored query that the database keeps up to date as transactions commit
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ViewDefinition {
+    pub name: String,
+    /// If none, every person is included in the view
+    pub query: Option<QueryPersonData>,
+    /// Fields to include in each row of the view, the id is always included
+    pub projection: Vec<PersonField>,
+}
+
+/// A projected person, fields that are not a part of the view's projection are none
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ViewRow {
+    pub id: EntityId,
+    pub full_name: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ViewResult {
+    pub rows: Vec<ViewRow>,
+    /// The view reflects every transaction up to and including this transaction id
+    pub freshness_transaction_id: TransactionId,
+}
+
+struct MaterializedView {
+    definition: ViewDefinition,
+    rows: BTreeMap<EntityId, ViewRow>,
+    freshness_transaction_id: TransactionId,
+}
+
+/// The person with only the fields of the projection, also used by prepared queries
+pub fn project(person: &Person, projection: &[PersonField]) -> ViewRow {
+    ViewRow {
+        id: person.id.clone(),
+        full_name: projection
+            .contains(&PersonField::FullName)
+            .then(|| person.full_name.clone()),
+        email: projection
+            .contains(&PersonField::Email)
+            .then(|| person.email.clone())
+            .flatten(),
+    }
+}
+
+impl MaterializedView {
+    /// Applies the latest state of a single row, `None` means the row has been removed
+    fn apply(&mut self, id: &EntityId, person: Option<&Person>) {
+        let person = person.filter(|p| match &self.definition.query {
+            Some(query) => matches(p, query),
+            None => true,
+        });
+
+        match person {
+            Some(person) => {
+                let row = project(person, &self.definition.projection);
+                self.rows.insert(id.clone(), row);
+            }
+            None => {
+                self.rows.remove(id);
+            }
+        }
+    }
+}
+
+/// Materialized views of the person table, views are maintained incrementally from the rows that each
+/// committed transaction changed rather than re-running the query
+#[derive(Default)]
+pub struct MaterializedViews {
+    views: RwLock<HashMap<String, MaterializedView>>,
+    /// Number of views, checked by every commit without taking the lock, see `is_empty`
+    view_count: AtomicUsize,
+}
+
+impl MaterializedViews {
+    /// Creates (or replaces) a view, the view is populated from `people` which should be the state of
+    /// the table at `transaction_id`
+    pub fn create(
+        &self,
+        definition: ViewDefinition,
+        people: Vec<Person>,
+        transaction_id: &TransactionId,
+    ) {
+        let mut view = MaterializedView {
+            definition,
+            rows: BTreeMap::new(),
+            freshness_transaction_id: transaction_id.clone(),
+        };
+
+        for person in people.iter() {
+            view.apply(&person.id, Some(person));
+        }
+
+        let mut views = self.views.write().unwrap();
+
+        views.insert(view.definition.name.clone(), view);
+        self.view_count.store(views.len(), Ordering::Release);
+    }
+
+    /// Returns whether a view was dropped
+    pub fn drop_view(&self, name: &str) -> bool {
+        let mut views = self.views.write().unwrap();
+
+        let dropped = views.remove(name).is_some();
+        self.view_count.store(views.len(), Ordering::Release);
+
+        dropped
+    }
+
+    pub fn definitions(&self) -> Vec<ViewDefinition> {
+        self.views
+            .read()
+            .unwrap()
+            .values()
+            .map(|view| view.definition.clone())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.view_count.load(Ordering::Acquire) == 0
+    }
+
+    pub fn reset(&self) {
+        let mut views = self.views.write().unwrap();
+
+        views.clear();
+        self.view_count.store(0, Ordering::Release);
+    }
+
+    /// Applies the changes of a committed transaction, `changes` is the state of every row
+    /// the transaction mutated at the transaction id
+    pub fn apply_transaction(
+        &self,
+        changes: &[(EntityId, Option<Person>)],
+        transaction_id: &TransactionId,
+    ) {
+        for view in self.views.write().unwrap().values_mut() {
+            for (id, person) in changes {
+                view.apply(id, person.as_ref());
+            }
+
+            if transaction_id > &view.freshness_transaction_id {
+                view.freshness_transaction_id = transaction_id.clone();
+            }
+        }
+    }
+
+    pub fn query(&self, name: &str) -> Option<ViewResult> {
+        self.views.read().unwrap().get(name).map(|view| ViewResult {
+            rows: view.rows.values().cloned().collect(),
+            freshness_transaction_id: view.freshness_transaction_id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::table::query::QueryMatch;
+
+    #[test]
+    fn maintained_incrementally() {
+        let views = MaterializedViews::default();
+        let first_transaction = TransactionId::new_first_transaction();
+
+        let with_email = Person::new("1".to_string(), Some("email".to_string()));
+        let without_email = Person::new("2".to_string(), None);
+
+        views.create(
+            ViewDefinition {
+                name: "with_email".to_string(),
+                query: Some(QueryPersonData {
+                    full_name: QueryMatch::Any,
+                    email: QueryMatch::NotNull,
+                    ..QueryPersonData::default()
+                }),
+                projection: vec![PersonField::Email],
+            },
+            vec![with_email.clone(), without_email.clone()],
+            &first_transaction,
+        );
+
+        let result = views.query("with_email").unwrap();
+
+        assert_eq!(
+            result.rows,
+            vec![ViewRow {
+                id: with_email.id.clone(),
+                full_name: None,
+                email: Some("email".to_string()),
+            }]
+        );
+        assert_eq!(result.freshness_transaction_id, first_transaction);
+
+        // When a person gains an email and the other is removed
+        let second_transaction = first_transaction.increment();
+
+        views.apply_transaction(
+            &[
+                (with_email.id.clone(), None),
+                (
+                    without_email.id.clone(),
+                    Some(Person {
+                        email: Some("new".to_string()),
+                        ..without_email.clone()
+                    }),
+                ),
+            ],
+            &second_transaction,
+        );
+
+        let result = views.query("with_email").unwrap();
+
+        assert_eq!(
+            result.rows,
+            vec![ViewRow {
+                id: without_email.id.clone(),
+                full_name: None,
+                email: Some("new".to_string()),
+            }]
+        );
+        assert_eq!(result.freshness_transaction_id, second_transaction);
+
+        assert!(views.drop_view("with_email"));
+        assert_eq!(views.query("with_email"), None);
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::consts::consts::TransactionId;
+
+use super::striped::StripedMax;
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Invalid table version, expected <epoch>-<transaction id>: {0}")]
+pub struct InvalidTableVersion(String);
+
+/// Identifies the contents of the table as seen by a read, used as an ETag by clients that poll. Transaction ids
+/// start over when the database is reset, the epoch tells the versions from before and after a reset apart
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TableVersion {
+    pub epoch: u64,
+    /// The last transaction that modified the table, as of the read
+    pub transaction_id: TransactionId,
+}
+
+impl fmt::Display for TableVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}-{}", self.epoch, self.transaction_id.0)
+    }
+}
+
+impl FromStr for TableVersion {
+    type Err = InvalidTableVersion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidTableVersion(s.to_string());
+
+        let (epoch, transaction_id) = s.split_once('-').ok_or_else(invalid)?;
+
+        Ok(TableVersion {
+            epoch: u64::from_str_radix(epoch, 16).map_err(|_| invalid())?,
+            transaction_id: TransactionId(transaction_id.parse().map_err(|_| invalid())?),
+        })
+    }
+}
+
+/// The last transaction that mutated the table, so a read can answer "not modified" without being run. Rolled back
+/// transactions still move the watermark, which only costs the next conditional read a re-run
+pub struct ModificationWatermark {
+    /// Random for each process, versions handed out before a restart (or a restore from a backup) never match
+    epoch: AtomicU64,
+    /// Moved by every mutation, so it is striped
+    last_modified: StripedMax,
+}
+
+impl Default for ModificationWatermark {
+    fn default() -> Self {
+        Self {
+            epoch: AtomicU64::new(rand::random()),
+            last_modified: StripedMax::default(),
+        }
+    }
+}
+
+impl ModificationWatermark {
+    /// Called before the mutation is applied, so a read that can see the mutation also sees the watermark
+    pub fn modified(&self, transaction_id: &TransactionId) {
+        self.last_modified.update(transaction_id.0);
+    }
+
+    /// Transaction ids start over after a reset, every version handed out before it stops matching
+    pub fn reset(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        self.last_modified.reset();
+    }
+
+    /// The version of the table as seen by a read at the transaction. A mutation that is applied after the read
+    /// started has a newer transaction id, capping at the read's transaction keeps it from being reported as seen
+    pub fn version(&self, transaction_id: &TransactionId) -> TableVersion {
+        TableVersion {
+            epoch: self.epoch.load(Ordering::SeqCst),
+            transaction_id: TransactionId(self.last_modified.get().min(transaction_id.0)),
+        }
+    }
+
+    /// Whether nothing has modified the table since the version was handed out
+    pub fn unchanged_since(&self, version: &TableVersion) -> bool {
+        self.epoch.load(Ordering::SeqCst) == version.epoch
+            && self.last_modified.get() <= version.transaction_id.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_modifications_and_resets() {
+        let watermark = ModificationWatermark::default();
+
+        watermark.modified(&TransactionId(5));
+
+        let version = watermark.version(&TransactionId(7));
+
+        assert_eq!(version.transaction_id, TransactionId(5));
+        assert_eq!(version.to_string().parse(), Ok(version.clone()));
+        assert!(watermark.unchanged_since(&version));
+
+        // A mutation newer than the read is not reported as seen by it
+        watermark.modified(&TransactionId(9));
+        assert_eq!(
+            watermark.version(&TransactionId(8)).transaction_id,
+            TransactionId(8)
+        );
+        assert!(!watermark.unchanged_since(&version));
+
+        let version = watermark.version(&TransactionId(10));
+
+        // Ids start over after a reset, the epoch keeps the old version from matching
+        watermark.reset();
+        watermark.modified(&TransactionId(2));
+        assert!(!watermark.unchanged_since(&version));
+
+        assert!("nope".parse::<TableVersion>().is_err());
+        assert!("zz-1".parse::<TableVersion>().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+/// How often a waiting background request checks whether a worker queue has emptied, queues do not notify
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// When the requests of a tag are queued on a worker, see `TagLimit`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TagPriority {
+    /// Queued right away
+    #[default]
+    Normal,
+    /// Queued once a worker of the request's pool has an empty queue, so the tag only uses capacity that other
+    /// requests leave idle and never queues ahead of them
+    Background,
+}
+
+/// Limits for the requests of a tag, a tag is set by the client (see `TransactionContext::set_tag`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagLimit {
+    pub max_concurrent: Option<usize>,
+    pub priority: TagPriority,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl TagLimit {
+    /// Requests of the tag that can be queued or running at once, further requests wait in the request manager
+    pub fn set_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent);
+        self
+    }
+
+    pub fn set_priority(mut self, priority: TagPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Request tagged {tag} timed out after waiting {waited_ms}ms for its tag limit")]
+pub struct TagThrottled {
+    pub tag: String,
+    pub waited_ms: u128,
+}
+
+#[derive(Default)]
+struct TagUsage {
+    in_flight: usize,
+    /// Requests that had to wait before they were queued
+    throttled: usize,
+    timed_out: usize,
+    total_wait: Duration,
+}
+
+struct TagThrottleInner {
+    limits: HashMap<String, TagLimit>,
+    usage: Mutex<HashMap<String, TagUsage>>,
+    released: Condvar,
+}
+
+/// Enforces the tag limits in the request manager, before requests are queued on a worker, so that background
+/// bulk jobs cannot crowd out interactive requests that share the request manager. Shared with the database,
+/// which reports the usage in its stats
+#[derive(Clone)]
+pub struct TagThrottle(Arc<TagThrottleInner>);
+
+/// A request of a limited tag that is queued or running, the slot is released when the request is dropped
+pub struct TagPermit {
+    throttle: TagThrottle,
+    tag: String,
+}
+
+impl Drop for TagPermit {
+    fn drop(&mut self) {
+        self.throttle.release(&self.tag);
+    }
+}
+
+impl TagThrottle {
+    pub fn new(limits: HashMap<String, TagLimit>) -> Self {
+        Self(Arc::new(TagThrottleInner {
+            limits,
+            usage: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }))
+    }
+
+    /// Blocks until the tag is below its concurrency limit and, for background tags, `is_idle` returns true.
+    /// Requests without a limited tag are not throttled
+    pub fn acquire(
+        &self,
+        tag: Option<&str>,
+        timeout: Duration,
+        is_idle: impl Fn() -> bool,
+    ) -> Result<Option<TagPermit>, TagThrottled> {
+        let Some((tag, limit)) = tag.and_then(|tag| self.0.limits.get_key_value(tag)) else {
+            return Ok(None);
+        };
+
+        let started = Instant::now();
+        let mut waited = false;
+        let mut usage = self.0.usage.lock().unwrap();
+
+        loop {
+            let tag_usage = usage.entry(tag.clone()).or_default();
+
+            let below_limit = limit
+                .max_concurrent
+                .map_or(true, |max_concurrent| tag_usage.in_flight < max_concurrent);
+
+            if below_limit && (limit.priority == TagPriority::Normal || is_idle()) {
+                if waited {
+                    tag_usage.throttled += 1;
+                    tag_usage.total_wait += started.elapsed();
+                }
+
+                tag_usage.in_flight += 1;
+
+                return Ok(Some(TagPermit {
+                    throttle: self.clone(),
+                    tag: tag.clone(),
+                }));
+            }
+
+            let remaining = timeout.saturating_sub(started.elapsed());
+
+            if remaining.is_zero() {
+                tag_usage.timed_out += 1;
+
+                return Err(TagThrottled {
+                    tag: tag.clone(),
+                    waited_ms: started.elapsed().as_millis(),
+                });
+            }
+
+            let wait = match (below_limit, limit.priority) {
+                (true, TagPriority::Background) => remaining.min(BACKGROUND_POLL_INTERVAL),
+                _ => remaining,
+            };
+
+            waited = true;
+            usage = self.0.released.wait_timeout(usage, wait).unwrap().0;
+        }
+    }
+
+    fn release(&self, tag: &str) {
+        if let Some(usage) = self.0.usage.lock().unwrap().get_mut(tag) {
+            usage.in_flight = usage.in_flight.saturating_sub(1);
+        }
+
+        self.0.released.notify_all();
+    }
+
+    /// Usage per limited tag, reported in the database stats
+    pub fn stats(&self) -> Vec<(String, String)> {
+        let usage = self.0.usage.lock().unwrap();
+
+        let mut tags: Vec<&String> = usage.keys().collect();
+        tags.sort();
+
+        tags.into_iter()
+            .flat_map(|tag| {
+                let usage = &usage[tag];
+
+                [
+                    (format!("TagInFlight[{}]", tag), usage.in_flight.to_string()),
+                    (
+                        format!("TagThrottled[{}]", tag),
+                        usage.throttled.to_string(),
+                    ),
+                    (format!("TagTimedOut[{}]", tag), usage.timed_out.to_string()),
+                    (
+                        format!("TagThrottleWaitMs[{}]", tag),
+                        usage.total_wait.as_millis().to_string(),
+                    ),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn limits_concurrent_requests_per_tag() {
+        let throttle = TagThrottle::new(HashMap::from([
+            (
+                "batch-import".to_string(),
+                TagLimit::default().set_max_concurrent(1),
+            ),
+            (
+                "background".to_string(),
+                TagLimit::default().set_priority(TagPriority::Background),
+            ),
+        ]));
+
+        let timeout = Duration::from_millis(50);
+
+        // Untagged requests and tags without a limit are never throttled
+        assert!(throttle.acquire(None, timeout, || false).unwrap().is_none());
+        assert!(throttle
+            .acquire(Some("interactive"), timeout, || false)
+            .unwrap()
+            .is_none());
+
+        let permit = throttle
+            .acquire(Some("batch-import"), timeout, || false)
+            .unwrap();
+        assert!(permit.is_some());
+
+        assert!(matches!(
+            throttle.acquire(Some("batch-import"), timeout, || false),
+            Err(TagThrottled { .. })
+        ));
+
+        // A waiting request is queued once the running one is done
+        let releasing = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            drop(permit);
+        });
+
+        assert!(throttle
+            .acquire(Some("batch-import"), Duration::from_secs(5), || false)
+            .unwrap()
+            .is_some());
+
+        releasing.join().unwrap();
+
+        // Background requests wait for an idle worker
+        let idle = Arc::new(AtomicBool::new(false));
+        let becoming_idle = {
+            let idle = idle.clone();
+
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                idle.store(true, Ordering::Relaxed);
+            })
+        };
+
+        assert!(throttle
+            .acquire(Some("background"), Duration::from_secs(5), || idle
+                .load(Ordering::Relaxed))
+            .unwrap()
+            .is_some());
+
+        becoming_idle.join().unwrap();
+
+        let stats = throttle.stats();
+        assert!(stats.contains(&("TagInFlight[batch-import]".to_string(), "0".to_string())));
+        assert!(stats.contains(&("TagThrottled[batch-import]".to_string(), "1".to_string())));
+        assert!(stats.contains(&("TagTimedOut[batch-import]".to_string(), "1".to_string())));
+        assert!(stats.contains(&("TagThrottled[background]".to_string(), "1".to_string())));
+    }
+}
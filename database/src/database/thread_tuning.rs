@@ -0,0 +1,179 @@
+use thiserror::Error;
+
+/// CPU affinity and priority of the worker threads and the WAL (Transaction Manager) thread. Latency sensitive
+/// deployments pin the WAL thread to its own CPU so fsyncs are not delayed by queries
+///
+/// Note: only available with the `thread-tuning` feature and only applied on Linux, on other platforms a warning
+/// is logged and the threads run untuned
+#[derive(Debug, Clone, Default)]
+pub struct ThreadTuningOptions {
+    pub workers: ThreadPlacement,
+    pub wal: ThreadPlacement,
+}
+
+impl ThreadTuningOptions {
+    pub fn set_workers(mut self, workers: ThreadPlacement) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    pub fn set_wal(mut self, wal: ThreadPlacement) -> Self {
+        self.wal = wal;
+        self
+    }
+}
+
+/// Where and at which priority a thread runs, unset values leave the thread as the OS started it
+#[derive(Debug, Clone, Default)]
+pub struct ThreadPlacement {
+    /// The CPUs the thread may run on, every worker may run on each of the CPUs
+    pub cpus: Vec<usize>,
+    /// The nice value of the thread, -20 (highest priority) to 19. Negative values require `CAP_SYS_NICE`
+    pub nice: Option<i32>,
+}
+
+impl ThreadPlacement {
+    pub fn set_cpus(mut self, cpus: Vec<usize>) -> Self {
+        self.cpus = cpus;
+        self
+    }
+
+    pub fn set_nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ThreadTuningError> {
+        if let Some(cpu) = self.cpus.iter().find(|cpu| **cpu >= MAX_CPUS) {
+            return Err(ThreadTuningError::InvalidCpu(*cpu));
+        }
+
+        match self.nice {
+            Some(nice) if !(-20..=19).contains(&nice) => Err(ThreadTuningError::InvalidNice(nice)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the placement to the calling thread, failures are logged and the thread keeps running untuned
+    pub fn apply_to_current_thread(&self) {
+        if let Err(e) = self.try_apply_to_current_thread() {
+            log::warn!(
+                "[{}] Could not apply the thread placement: {}",
+                std::thread::current().name().unwrap_or("unnamed"),
+                e
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn try_apply_to_current_thread(&self) -> Result<(), ThreadTuningError> {
+        if !self.cpus.is_empty() {
+            // Safety: the set is zeroed before use and only CPUs below `CPU_SETSIZE` are added (see `validate`)
+            let result = unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+
+                for cpu in &self.cpus {
+                    libc::CPU_SET(*cpu, &mut set);
+                }
+
+                // A pid of 0 is the calling thread
+                libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+            };
+
+            if result != 0 {
+                return Err(ThreadTuningError::Os(
+                    "sched_setaffinity",
+                    std::io::Error::last_os_error(),
+                ));
+            }
+        }
+
+        if let Some(nice) = self.nice {
+            // On Linux the nice value is per thread, the thread id selects the calling thread
+            let result = unsafe {
+                let thread_id = libc::syscall(libc::SYS_gettid) as libc::id_t;
+
+                libc::setpriority(libc::PRIO_PROCESS, thread_id, nice)
+            };
+
+            if result != 0 {
+                return Err(ThreadTuningError::Os(
+                    "setpriority",
+                    std::io::Error::last_os_error(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn try_apply_to_current_thread(&self) -> Result<(), ThreadTuningError> {
+        if self.cpus.is_empty() && self.nice.is_none() {
+            return Ok(());
+        }
+
+        Err(ThreadTuningError::Unsupported)
+    }
+}
+
+/// `CPU_SETSIZE` on Linux
+const MAX_CPUS: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum ThreadTuningError {
+    #[error("CPU {0} is out of range, CPUs must be below {MAX_CPUS}")]
+    InvalidCpu(usize),
+
+    #[error("The nice value must be between -20 and 19, got: {0}")]
+    InvalidNice(i32),
+
+    #[error("`{0}` failed: {1}")]
+    Os(&'static str, std::io::Error),
+
+    #[error("Thread tuning is only supported on Linux")]
+    Unsupported,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ThreadPlacement, ThreadTuningError};
+
+    #[test]
+    fn validates_cpus_and_nice() {
+        assert!(ThreadPlacement::default().validate().is_ok());
+        assert!(ThreadPlacement::default()
+            .set_cpus(vec![0, 1])
+            .set_nice(19)
+            .validate()
+            .is_ok());
+
+        assert!(matches!(
+            ThreadPlacement::default().set_cpus(vec![4096]).validate(),
+            Err(ThreadTuningError::InvalidCpu(4096))
+        ));
+        assert!(matches!(
+            ThreadPlacement::default().set_nice(-21).validate(),
+            Err(ThreadTuningError::InvalidNice(-21))
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn applies_the_placement_to_the_calling_thread() {
+        std::thread::spawn(|| {
+            // The CPU the thread runs on is always in its allowed set, raising the nice value needs no privileges
+            let cpu = unsafe { libc::sched_getcpu() };
+
+            ThreadPlacement::default()
+                .set_cpus(vec![cpu as usize])
+                .set_nice(5)
+                .try_apply_to_current_thread()
+                .expect("Should be able to pin the thread to its current CPU");
+
+            assert_eq!(unsafe { libc::sched_getcpu() }, cpu);
+        })
+        .join()
+        .unwrap();
+    }
+}
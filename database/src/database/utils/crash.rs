@@ -21,6 +21,16 @@ pub enum DatabaseCrash {
     #[error("Inconsistent storage from restarting database: {0}")]
     InconsistentStorageFromReset(StorageError),
 
+    /// Versions that have been spilled out of memory could not be read back, history can no longer
+    /// be served consistently
+    #[error("Unable to read versions spilled to storage: {0}")]
+    UnreadableColdVersions(StorageError),
+
+    /// The pre-commit hook could not make a batch durable elsewhere and the hook's failure policy is to crash,
+    /// see `PreCommitFailure::Crash`
+    #[error("Pre-commit hook failed: {0}")]
+    PreCommitHookFailed(String),
+
     #[error("Unhandled crash")]
     Unhandled,
 }
@@ -0,0 +1,152 @@
+use std::time::Instant;
+
+use flume::Sender;
+
+use crate::{consts::consts::EntityId, model::statement::Statement};
+
+use super::{
+    commands::{DatabaseCommandRequest, TransactionContext},
+    database::Database,
+    request_manager::RequestManager,
+};
+
+/// Work done once the database has restored and before it is handed to callers, so the first requests do not
+/// pay for cold caches and lazily initialized worker state, see `DatabaseOptions::set_warmup`
+#[derive(Clone, Debug, PartialEq)]
+pub struct WarmupOptions {
+    /// No-op read transactions sent to each worker thread
+    pub transactions_per_thread: usize,
+    /// Rows whose in-memory versions are read, in id order (the order scans read them in). Versions spilled
+    /// to storage are not loaded
+    pub preload_rows: usize,
+}
+
+impl Default for WarmupOptions {
+    fn default() -> Self {
+        Self {
+            transactions_per_thread: 8,
+            preload_rows: 0,
+        }
+    }
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl WarmupOptions {
+    pub fn set_transactions_per_thread(mut self, transactions_per_thread: usize) -> Self {
+        self.transactions_per_thread = transactions_per_thread;
+        self
+    }
+
+    /// Use `usize::MAX` to preload every row
+    pub fn set_preload_rows(mut self, preload_rows: usize) -> Self {
+        self.preload_rows = preload_rows;
+        self
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WarmupReport {
+    pub preloaded_rows: usize,
+    pub preloaded_versions: usize,
+    pub transactions: usize,
+}
+
+impl Database {
+    /// Reads the in-memory versions of the first `limit` rows
+    pub(super) fn preload_rows(&self, limit: usize, report: &mut WarmupReport) {
+        for entry in self.person_table.person_rows.iter().take(limit) {
+            report.preloaded_rows += 1;
+            report.preloaded_versions += entry.value().read().unwrap().touch();
+        }
+    }
+}
+
+/// Sends no-op reads to each worker thread directly, bypassing the routing of the request manager so every
+/// thread is warmed up
+pub(super) fn warm_up_threads(
+    worker_channels: &[Sender<DatabaseCommandRequest>],
+    transactions_per_thread: usize,
+    report: &mut WarmupReport,
+) {
+    for channel in worker_channels {
+        let thread_request_manager = RequestManager::new(vec![channel.clone()]);
+
+        for _ in 0..transactions_per_thread {
+            // A new id is never found, so the read does not return anything
+            let result = thread_request_manager.send_transaction(
+                vec![Statement::Get(EntityId::new())],
+                TransactionContext::default(),
+            );
+
+            match result {
+                Ok(_) => report.transactions += 1,
+                Err(e) => log::warn!("Warm-up transaction failed: {}", e),
+            }
+        }
+    }
+}
+
+pub(super) fn log_warmup(report: &WarmupReport, started_at: Instant) {
+    log::info!(
+        "🔥 Warm-up            [PreloadedRows: {}, PreloadedVersions: {}, Transactions: {}, Duration: {}ms]",
+        report.preloaded_rows,
+        report.preloaded_versions,
+        report.transactions,
+        started_at.elapsed().as_millis(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::{
+            database::test_utils::apply_transaction_at_next_timestamp, options::DatabaseOptions,
+        },
+        model::person::Person,
+    };
+
+    use super::*;
+
+    #[test]
+    fn preloads_the_first_rows() {
+        let database = Database::new(DatabaseOptions::new_test());
+
+        for i in 0..5 {
+            apply_transaction_at_next_timestamp(
+                &database,
+                vec![Statement::Add(Person::new(format!("Person {}", i), None))],
+            );
+        }
+
+        let mut report = WarmupReport::default();
+        database.preload_rows(3, &mut report);
+
+        assert_eq!(report.preloaded_rows, 3);
+        assert_eq!(report.preloaded_versions, 3);
+
+        let mut report = WarmupReport::default();
+        database.preload_rows(usize::MAX, &mut report);
+
+        assert_eq!(report.preloaded_rows, 5);
+    }
+
+    #[test]
+    fn every_thread_is_warmed_up() {
+        let options = DatabaseOptions::new_test()
+            .set_threads(2)
+            .set_warmup(WarmupOptions::default().set_preload_rows(usize::MAX));
+
+        let request_manager = Database::new(options).run();
+
+        let stats = request_manager
+            .send_info_request()
+            .expect("Should not timeout");
+
+        // Queue waits are only recorded by threads that have picked up a request
+        for thread_id in 0..2 {
+            let name = format!("QueueWaitP99Ms[{}]", thread_id);
+
+            assert!(stats.iter().any(|(stat, _)| *stat == name));
+        }
+    }
+}
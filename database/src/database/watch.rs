@@ -0,0 +1,149 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    consts::consts::EntityId,
+    database::table::{history::HistoryRequest, row::PersonVersion},
+    persistence::transaction::Transaction,
+};
+
+use super::{
+    commands::TransactionContext,
+    request_manager::{RequestManager, RequestManagerError},
+};
+
+/// Versions read per history request, a transaction rarely creates more than one version of a row
+const HISTORY_PAGE_SIZE: usize = 100;
+
+#[derive(Error, Debug)]
+pub enum EntityWatchError {
+    #[error("No version of the row was committed before the timeout")]
+    Timeout,
+
+    #[error("The database was closed")]
+    Closed,
+
+    #[error("Unable to read the committed versions: {0}")]
+    Request(#[from] RequestManagerError),
+}
+
+/// Every committed version change of a single row in commit order, see `RequestManager::watch_entity`
+///
+/// Built on the WAL's committed listener (the stream the publisher reads), so a version is only yielded once it
+/// is durable. The versions are read with the watch's transaction context, so the role's row policies apply. The
+/// row is watched by its id, renames and merges are not followed
+pub struct EntityWatch {
+    id: EntityId,
+    committed: flume::Receiver<Transaction>,
+    request_manager: RequestManager,
+    transaction_context: TransactionContext,
+    pending: VecDeque<PersonVersion>,
+}
+
+impl EntityWatch {
+    pub(crate) fn new(
+        id: EntityId,
+        committed: flume::Receiver<Transaction>,
+        request_manager: RequestManager,
+        transaction_context: TransactionContext,
+    ) -> Self {
+        Self {
+            id,
+            committed,
+            request_manager,
+            transaction_context,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn id(&self) -> &EntityId {
+        &self.id
+    }
+
+    /// Waits for at most the timeout for the next version, see `EntityWatchError::Timeout`
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<PersonVersion, EntityWatchError> {
+        self.next_before(Some(Instant::now() + timeout))
+    }
+
+    fn next_before(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<PersonVersion, EntityWatchError> {
+        loop {
+            if let Some(version) = self.pending.pop_front() {
+                return Ok(version);
+            }
+
+            let transaction = match deadline {
+                Some(deadline) => self
+                    .committed
+                    .recv_deadline(deadline)
+                    .map_err(|e| match e {
+                        flume::RecvTimeoutError::Timeout => EntityWatchError::Timeout,
+                        flume::RecvTimeoutError::Disconnected => EntityWatchError::Closed,
+                    })?,
+                None => self
+                    .committed
+                    .recv()
+                    .map_err(|_| EntityWatchError::Closed)?,
+            };
+
+            let mutated = transaction
+                .statements
+                .iter()
+                .any(|statement| statement.mutated_ids().contains(&&self.id));
+
+            if mutated {
+                self.pending = self.read_versions(&transaction)?;
+            }
+        }
+    }
+
+    /// The versions of the row the transaction created. None are returned if the row was purged since
+    fn read_versions(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<VecDeque<PersonVersion>, RequestManagerError> {
+        let mut versions = VecDeque::new();
+        let mut cursor = None;
+
+        loop {
+            let request = HistoryRequest::new(HISTORY_PAGE_SIZE)
+                .set_transaction_range(Some(transaction.id.clone()), Some(transaction.id.clone()))
+                .set_cursor(cursor);
+
+            let Some(page) = self.request_manager.send_history(
+                self.id.clone(),
+                request,
+                self.transaction_context.clone(),
+            )?
+            else {
+                return Ok(versions);
+            };
+
+            versions.extend(page.versions);
+
+            match page.next_cursor {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => return Ok(versions),
+            }
+        }
+    }
+}
+
+/// Blocks until the next version is committed, ends once the database is closed
+impl Iterator for EntityWatch {
+    type Item = Result<PersonVersion, RequestManagerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_before(None) {
+            Ok(version) => Some(Ok(version)),
+            Err(EntityWatchError::Request(e)) => Some(Err(e)),
+            Err(EntityWatchError::Timeout | EntityWatchError::Closed) => None,
+        }
+    }
+}
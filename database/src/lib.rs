@@ -2,3 +2,4 @@ pub mod consts;
 pub mod database;
 pub mod model;
 pub mod persistence;
+pub mod prelude;
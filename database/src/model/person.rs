@@ -2,11 +2,45 @@ use serde::{Deserialize, Serialize};
 
 use crate::consts::consts::EntityId;
 
+/// Structured postal address, every part is optional
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Address {
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+}
+
+impl Address {
+    pub fn is_empty(&self) -> bool {
+        self.street.is_none() && self.city.is_none() && self.country.is_none()
+    }
+}
+
+/// A binary payload (e.g. an avatar or a document) attached to a person. The bytes are stored as a blob keyed by
+/// their digest, versions only reference it, see `AttachmentStore`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Attachment {
+    /// Unique per person, attaching a payload under an existing name replaces it
+    pub name: String,
+    /// SHA-256 of the bytes, hex encoded
+    pub digest: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Person {
     pub id: EntityId,
     pub full_name: String,
     pub email: Option<String>,
+    /// People written before structured fields were introduced do not have an address
+    #[serde(default)]
+    pub address: Option<Address>,
+    #[serde(default)]
+    pub phone_numbers: Vec<String>,
+    /// Left out of the serialized person when empty, so people without attachments are written as before
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
 }
 
 impl Person {
@@ -15,14 +49,30 @@ impl Person {
             id: EntityId(uuid::Uuid::new_v4().to_string()),
             full_name,
             email,
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
         }
     }
 
+    pub fn set_address(mut self, address: Address) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn set_phone_numbers(mut self, phone_numbers: Vec<String>) -> Self {
+        self.phone_numbers = phone_numbers;
+        self
+    }
+
     pub fn new_test() -> Self {
         Person {
             id: EntityId("1".to_string()),
             full_name: "Full Name".to_string(),
             email: Some("Email".to_string()),
+            address: None,
+            phone_numbers: vec![],
+            attachments: vec![],
         }
     }
 }
@@ -1,26 +1,107 @@
+use std::{collections::BTreeMap, ops::Bound};
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    consts::consts::{EntityId, VersionId},
-    database::table::{
-        query::QueryPersonData,
-        row::{PersonVersion, UpdatePersonData},
+    consts::consts::{EntityId, TransactionId, VersionId},
+    database::{
+        system::SystemTable,
+        table::{
+            attachment::AttachmentContent,
+            conflict::Conflict,
+            history::{HistoryPage, HistoryRequest},
+            meta::EntityMeta,
+            outbox::{OutboxId, OutboxMessage},
+            pagination::{Page, PageRequest},
+            query::QueryPersonData,
+            row::{PersonVersion, UpdatePersonData},
+            view::{PersonField, ViewResult},
+            watermark::TableVersion,
+        },
     },
 };
 
 use super::person::Person;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(
+    Serialize, Deserialize, Clone, Debug, strum_macros::IntoStaticStr, strum_macros::VariantNames,
+)]
 pub enum Statement {
     Add(Person),
     Update(EntityId, UpdatePersonData),
     Remove(EntityId),
+    /// Moves a person to a new id (from, to). The old id is deleted and the person is added at the new id in
+    /// the same transaction, both versions record the other id so history can be followed across the move
+    Rename(EntityId, EntityId),
+    /// Merges a person into another person (from, into, fields). The person being merged is deleted and the person
+    /// it is merged into takes the listed fields from it, every other field survives. Both versions record the other id
+    Merge(EntityId, EntityId, Vec<PersonField>),
+    /// Splits a person into several new people. The person being split is deleted and each of the new people
+    /// records the id they were split from
+    Split(EntityId, Vec<Person>),
+    /// Resolves a divergent version of a person, found by replication or an import, against the current version
+    /// with the table's `ConflictResolution`. The resolution is added as a new version whose lineage records both
+    /// sides, even if the current version wins
+    ResolveConflict(EntityId, Conflict),
+    /// Irreversibly removes a person and every version of it, e.g. for a right to be forgotten request. Unlike
+    /// `Remove` no tombstone version is kept, so reads at earlier snapshots stop seeing the person too. The
+    /// statement stays in the WAL as the tombstone that erases the person again when the WAL is replayed
+    Purge(EntityId),
     Get(EntityId),
     GetVersion(EntityId, VersionId),
+    /// Whether the person exists (and is visible to the request), the person is not returned
+    Exists(EntityId),
+    /// Returns the number of people that match the query, the people are not returned
+    Count(Option<QueryPersonData>),
+    /// Returns the bytes of a person's attachment (id, attachment name) as of the snapshot
+    GetAttachment(EntityId, String),
+    /// Returns the people as of the transaction, so every row reflects the same commits even if they are read
+    /// from separate requests (e.g. by a sync agent). Ids that do not exist (or are deleted) at the transaction are
+    /// left out, the transaction cannot be newer than the snapshot the statement runs at
+    GetManyAtTransaction(Vec<EntityId>, TransactionId),
     /// Returns a list of Person
     List(Option<QueryPersonData>),
+    /// Returns a page of Person, ordered by id. Pages are read from the same snapshot
+    ListPage(Option<QueryPersonData>, PageRequest),
+    /// Returns the people with an id in the range, ordered by id and at most `limit` of them. e.g. rows between
+    /// two ids for a partitioned export, or the first N rows after a key for keyset pagination
+    Scan {
+        start: Bound<EntityId>,
+        end: Bound<EntityId>,
+        limit: Option<usize>,
+    },
     /// Returns list of PersonVersion (version id, worldstate, tx_id, etc)
     ListLatestVersions,
+    /// Returns every version of a person including the versions of the ids it was renamed or merged from (and
+    /// to), ordered by transaction id
+    Lineage(EntityId),
+    /// Returns a page of the versions of a person ordered by transaction id, filtered by a transaction range and
+    /// the fields the versions changed. Unlike `Lineage` renames and merges are not followed, so people with many
+    /// versions can be browsed a page at a time
+    History(EntityId, HistoryRequest),
+    /// Returns the rows of a materialized view and the transaction id the view is fresh as of
+    QueryView(String),
+    /// Runs a registered prepared query (name, parameter values), the rows are projected like the rows of a view
+    /// and are fresh as of the snapshot the statement runs at
+    ExecutePreparedQuery(String, BTreeMap<String, String>),
+    /// Returns the version of the table as seen by the transaction, see `TransactionContext::set_if_unchanged_since`
+    TableVersion,
+    /// Returns the rows of a read-only table of the database's internal state, e.g. `system.stats`, see
+    /// `SYSTEM_TABLES`
+    QuerySystemTable(String),
+    /// Increments a named sequence and returns its new value, the sequence is created on first use
+    NextVal(String),
+    /// Enqueues an opaque message (topic, payload) into the outbox, see `Outbox`. The message is only visible to
+    /// consumers once the transaction commits, so it is published if and only if the transaction's mutations are
+    Enqueue(String, String),
+    /// Returns at most `limit` pending outbox messages in enqueue order, optionally only those of a topic (topic,
+    /// limit). The outbox is not versioned, the latest committed messages are returned whatever the snapshot
+    ReadOutbox(Option<String>, usize),
+    /// Acknowledges outbox messages so that they are not returned again. A message can only be acknowledged once,
+    /// acknowledging a message that is not pending rolls back the transaction
+    AckOutbox(Vec<OutboxId>),
+    /// Version count and first / last modification of a row, without reading its history, see `EntityMeta`
+    Meta(EntityId),
 }
 
 impl Statement {
@@ -28,30 +109,152 @@ impl Statement {
         !self.is_mutation()
     }
 
+    /// The rows mutated by the statement, empty for reads, sequences and the outbox
+    pub fn mutated_ids(&self) -> Vec<&EntityId> {
+        match self {
+            Statement::Add(person) => vec![&person.id],
+            Statement::Update(id, _)
+            | Statement::Remove(id)
+            | Statement::ResolveConflict(id, _)
+            | Statement::Purge(id) => vec![id],
+            Statement::Rename(from, to) | Statement::Merge(from, to, _) => vec![from, to],
+            Statement::Split(from, people) => {
+                let mut ids = vec![from];
+                ids.extend(people.iter().map(|person| &person.id));
+                ids
+            }
+            Statement::Get(_)
+            | Statement::GetVersion(_, _)
+            | Statement::Exists(_)
+            | Statement::Count(_)
+            | Statement::GetAttachment(_, _)
+            | Statement::GetManyAtTransaction(_, _)
+            | Statement::List(_)
+            | Statement::ListPage(_, _)
+            | Statement::Scan { .. }
+            | Statement::ListLatestVersions
+            | Statement::Lineage(_)
+            | Statement::History(_, _)
+            | Statement::QueryView(_)
+            | Statement::ExecutePreparedQuery(_, _)
+            | Statement::TableVersion
+            | Statement::QuerySystemTable(_)
+            | Statement::NextVal(_)
+            | Statement::Enqueue(_, _)
+            | Statement::ReadOutbox(_, _)
+            | Statement::AckOutbox(_)
+            | Statement::Meta(_) => vec![],
+        }
+    }
+
+    /// Whether the statement filters people with a query that is not a prepared query, see
+    /// `DatabaseOptions::set_prepared_queries_only`
+    pub fn is_ad_hoc_query(&self) -> bool {
+        matches!(
+            self,
+            Statement::List(Some(_)) | Statement::ListPage(Some(_), _) | Statement::Count(Some(_))
+        )
+    }
+
     pub fn is_mutation(&self) -> bool {
         match self {
-            Statement::Add(_) | Statement::Remove(_) | Statement::Update(_, _) => true,
+            Statement::Add(_)
+            | Statement::Remove(_)
+            | Statement::Update(_, _)
+            | Statement::Rename(_, _)
+            | Statement::Merge(_, _, _)
+            | Statement::Split(_, _)
+            | Statement::ResolveConflict(_, _)
+            | Statement::Purge(_)
+            | Statement::NextVal(_)
+            | Statement::Enqueue(_, _)
+            | Statement::AckOutbox(_) => true,
             Statement::List(_)
+            | Statement::ListPage(_, _)
+            | Statement::Scan { .. }
             | Statement::ListLatestVersions
+            | Statement::Lineage(_)
+            | Statement::History(_, _)
+            | Statement::QueryView(_)
+            | Statement::ExecutePreparedQuery(_, _)
+            | Statement::TableVersion
+            | Statement::QuerySystemTable(_)
+            | Statement::ReadOutbox(_, _)
             | Statement::Get(_)
-            | Statement::GetVersion(_, _) => false,
+            | Statement::GetVersion(_, _)
+            | Statement::Exists(_)
+            | Statement::Count(_)
+            | Statement::GetAttachment(_, _)
+            | Statement::GetManyAtTransaction(_, _)
+            | Statement::Meta(_) => false,
         }
     }
 }
 
 // TODO: Is there a better way to type this? Like if we know we are going to get a SuccessStatus, we should be able to unwrap it
 //  Note: the solution could be similiar to how we make the send_request method accept specific statement types, and thus, return their corresponding response.
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, strum_macros::VariantNames)]
 pub enum StatementResult {
     /// Used for database status messages
     SuccessStatus(String),
     Single(Person),
     GetSingle(Option<Person>),
     List(Vec<Person>),
+    Page(Page),
     ListVersion(Vec<PersonVersion>),
+    HistoryPage(HistoryPage),
+    View(ViewResult),
+    SystemTable(SystemTable),
+    SequenceValue(u64),
+    TableVersion(TableVersion),
+    Exists(bool),
+    Count(usize),
+    /// None if the person has no attachment with the name
+    Attachment(Option<AttachmentContent>),
+    /// Number of versions removed by a purge, see `Statement::Purge`
+    Purged(usize),
+    /// Pending outbox messages, see `Statement::ReadOutbox`
+    Outbox(Vec<OutboxMessage>),
+    /// A read of a row that does not exist, e.g. the lineage of an unknown id. Reads do not roll back the
+    /// transaction when a row is missing, gets return `GetSingle(None)`
+    NotFound(EntityId),
+    /// None if the row did not exist at the transaction or is hidden by a row policy
+    Meta(Option<EntityMeta>),
 }
 
 impl StatementResult {
+    /// Number of people (or versions / view rows) in the result
+    pub fn row_count(&self) -> usize {
+        match self {
+            StatementResult::Single(_) => 1,
+            StatementResult::GetSingle(person) => person.iter().count(),
+            StatementResult::List(people) => people.len(),
+            StatementResult::Page(page) => page.people.len(),
+            StatementResult::ListVersion(versions) => versions.len(),
+            StatementResult::HistoryPage(page) => page.versions.len(),
+            StatementResult::View(view) => view.rows.len(),
+            StatementResult::SystemTable(table) => table.rows.len(),
+            StatementResult::Attachment(content) => content.iter().count(),
+            StatementResult::Outbox(messages) => messages.len(),
+            StatementResult::SuccessStatus(_)
+            | StatementResult::SequenceValue(_)
+            | StatementResult::TableVersion(_)
+            | StatementResult::Exists(_)
+            | StatementResult::Count(_)
+            | StatementResult::Purged(_)
+            | StatementResult::NotFound(_)
+            | StatementResult::Meta(_) => 0,
+        }
+    }
+
+    /// None if the statement read a row that does not exist, see `StatementResult::NotFound`
+    pub fn found(self) -> Option<StatementResult> {
+        match self {
+            StatementResult::NotFound(_) => None,
+            result => Some(result),
+        }
+    }
+
     // TODO: Consider removing these methods and localizing them in the request_manager
     pub fn single(self) -> Person {
         if let StatementResult::Single(p) = self {
@@ -77,6 +280,102 @@ impl StatementResult {
         }
     }
 
+    pub fn page(self) -> Page {
+        if let StatementResult::Page(p) = self {
+            p
+        } else {
+            panic!("Statement result is not of type Page")
+        }
+    }
+
+    pub fn history_page(self) -> HistoryPage {
+        if let StatementResult::HistoryPage(p) = self {
+            p
+        } else {
+            panic!("Statement result is not of type HistoryPage")
+        }
+    }
+
+    pub fn view(self) -> ViewResult {
+        if let StatementResult::View(v) = self {
+            v
+        } else {
+            panic!("Statement result is not of type View")
+        }
+    }
+
+    pub fn system_table(self) -> SystemTable {
+        if let StatementResult::SystemTable(t) = self {
+            t
+        } else {
+            panic!("Statement result is not of type SystemTable")
+        }
+    }
+
+    pub fn sequence_value(self) -> u64 {
+        if let StatementResult::SequenceValue(v) = self {
+            v
+        } else {
+            panic!("Statement result is not of type SequenceValue")
+        }
+    }
+
+    pub fn exists(self) -> bool {
+        if let StatementResult::Exists(e) = self {
+            e
+        } else {
+            panic!("Statement result is not of type Exists")
+        }
+    }
+
+    pub fn count(self) -> usize {
+        if let StatementResult::Count(c) = self {
+            c
+        } else {
+            panic!("Statement result is not of type Count")
+        }
+    }
+
+    pub fn attachment(self) -> Option<AttachmentContent> {
+        if let StatementResult::Attachment(content) = self {
+            content
+        } else {
+            panic!("Statement result is not of type Attachment")
+        }
+    }
+
+    pub fn purged(self) -> usize {
+        if let StatementResult::Purged(versions) = self {
+            versions
+        } else {
+            panic!("Statement result is not of type Purged")
+        }
+    }
+
+    pub fn outbox(self) -> Vec<OutboxMessage> {
+        if let StatementResult::Outbox(messages) = self {
+            messages
+        } else {
+            panic!("Statement result is not of type Outbox")
+        }
+    }
+
+    pub fn meta(self) -> Option<EntityMeta> {
+        if let StatementResult::Meta(meta) = self {
+            meta
+        } else {
+            panic!("Statement result is not of type Meta")
+        }
+    }
+
+    pub fn table_version(self) -> TableVersion {
+        if let StatementResult::TableVersion(v) = self {
+            v
+        } else {
+            panic!("Statement result is not of type TableVersion")
+        }
+    }
+
     #[allow(dead_code)]
     pub fn list_version(self) -> Vec<PersonVersion> {
         if let StatementResult::ListVersion(p) = self {
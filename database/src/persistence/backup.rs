@@ -0,0 +1,281 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    snapshot::Metadata,
+    storage::{ReadBlobState, Storage, StorageError},
+};
+
+#[derive(Error, Debug)]
+pub enum BackupRestoreError {
+    #[error("The backup does not contain a snapshot metadata blob")]
+    NoBackup,
+
+    #[error("The backup metadata is invalid: {0}")]
+    InvalidMetadata(serde_json::Error),
+
+    #[error("The storage engine already contains a database, clear it before restoring a backup into it")]
+    TargetNotEmpty,
+
+    #[error("Unable to read the backup: {0}")]
+    Read(StorageError),
+
+    #[error("Unable to write the backup to the storage engine: {0}")]
+    Write(StorageError),
+
+    #[error("Invalid snapshot archive on line {0}: {1}")]
+    InvalidArchive(usize, String),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BackupRestoreReport {
+    pub blobs: usize,
+    pub transactions: usize,
+    /// The storage engine already contained the backup, e.g. the database was restarted with the same options
+    pub already_restored: bool,
+}
+
+/// Copies the database stored in `backup` into `target` so that the next restore of `target` starts from it.
+/// The copy is done blob by blob, so the backup can be stored by any engine, e.g. a file backup restored into
+/// the S3 engine. Blobs are copied as is, field encrypted backups need the same field encryption keys.
+///
+/// The metadata is written last, a restore that fails midway leaves `target` without a database and can be retried
+pub fn restore_from_backup(
+    backup: &mut dyn Storage,
+    target: &mut dyn Storage,
+) -> Result<BackupRestoreReport, BackupRestoreError> {
+    let metadata_bytes = match backup.read_blob(Metadata::metadata_key()) {
+        Ok(ReadBlobState::Found(bytes)) => bytes,
+        Ok(ReadBlobState::NotFound) => return Err(BackupRestoreError::NoBackup),
+        Err(e) => return Err(BackupRestoreError::Read(e)),
+    };
+
+    let metadata: Metadata =
+        serde_json::from_slice(&metadata_bytes).map_err(BackupRestoreError::InvalidMetadata)?;
+
+    if let Some(report) = check_target(target, &metadata_bytes)? {
+        return Ok(report);
+    }
+
+    let mut report = BackupRestoreReport::default();
+
+    for key in metadata.blob_keys() {
+        match backup
+            .read_blob(key.clone())
+            .map_err(BackupRestoreError::Read)?
+        {
+            ReadBlobState::Found(bytes) => {
+                target
+                    .write_blob(key, bytes)
+                    .map_err(BackupRestoreError::Write)?;
+                report.blobs += 1;
+            }
+            ReadBlobState::NotFound => {}
+        }
+    }
+
+    let transactions: Vec<Vec<u8>> = backup
+        .transaction_load()
+        .map_err(BackupRestoreError::Read)?
+        .into_iter()
+        .map(String::into_bytes)
+        .collect();
+
+    if !transactions.is_empty() {
+        target
+            .transaction_write_batch(&transactions)
+            .and_then(|_| target.transaction_sync())
+            .map_err(BackupRestoreError::Write)?;
+        report.transactions = transactions.len();
+    }
+
+    target
+        .write_blob(Metadata::metadata_key(), metadata_bytes)
+        .map_err(BackupRestoreError::Write)?;
+    report.blobs += 1;
+
+    Ok(report)
+}
+
+/// Returns a report if `target` already contains the database described by `metadata_bytes`, and an error if it
+/// contains another database
+fn check_target(
+    target: &mut dyn Storage,
+    metadata_bytes: &[u8],
+) -> Result<Option<BackupRestoreReport>, BackupRestoreError> {
+    match target.read_blob(Metadata::metadata_key()) {
+        Ok(ReadBlobState::Found(bytes)) if bytes == metadata_bytes => {
+            return Ok(Some(BackupRestoreReport {
+                already_restored: true,
+                ..Default::default()
+            }))
+        }
+        Ok(ReadBlobState::Found(_)) => return Err(BackupRestoreError::TargetNotEmpty),
+        Ok(ReadBlobState::NotFound) => {}
+        Err(e) => return Err(BackupRestoreError::Write(e)),
+    }
+
+    // A target without metadata may still have a WAL, e.g. a database that never took a snapshot
+    let existing_transactions = target
+        .transaction_load()
+        .map_err(BackupRestoreError::Write)?;
+
+    if !existing_transactions.is_empty() {
+        return Err(BackupRestoreError::TargetNotEmpty);
+    }
+
+    Ok(None)
+}
+
+/// A line of an encoded `SnapshotArchive`, blob contents are base64 encoded
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ArchiveLine {
+    Blob { key: String, bytes: String },
+    Transaction { line: String },
+    Metadata { bytes: String },
+}
+
+/// A consistent copy of a database that can be moved without access to its storage engine: the latest snapshot
+/// (and the views, policies and prepared queries it restores with) plus the WAL tail written since. Older snapshots
+/// and WAL archives are left out, the copy cannot be restored to an earlier point in time
+///
+/// Encoded as newline delimited JSON, the metadata line comes last so that a truncated download is rejected
+#[derive(Debug, Default, PartialEq)]
+pub struct SnapshotArchive {
+    metadata: Vec<u8>,
+    blobs: Vec<(String, Vec<u8>)>,
+    transactions: Vec<String>,
+}
+
+impl SnapshotArchive {
+    /// Reads the archive from `storage`, the database must be paused (or stopped) so that the WAL tail matches the
+    /// snapshot
+    pub fn read(storage: &mut dyn Storage) -> Result<Self, BackupRestoreError> {
+        let metadata = match storage.read_blob(Metadata::metadata_key()) {
+            Ok(ReadBlobState::Found(bytes)) => bytes,
+            Ok(ReadBlobState::NotFound) => return Err(BackupRestoreError::NoBackup),
+            Err(e) => return Err(BackupRestoreError::Read(e)),
+        };
+
+        let parsed: Metadata =
+            serde_json::from_slice(&metadata).map_err(BackupRestoreError::InvalidMetadata)?;
+
+        let mut blobs = vec![];
+
+        for key in parsed.latest_blob_keys() {
+            match storage
+                .read_blob(key.clone())
+                .map_err(BackupRestoreError::Read)?
+            {
+                ReadBlobState::Found(bytes) => blobs.push((key, bytes)),
+                ReadBlobState::NotFound => {}
+            }
+        }
+
+        let transactions = storage
+            .transaction_load()
+            .map_err(BackupRestoreError::Read)?;
+
+        Ok(SnapshotArchive {
+            metadata,
+            blobs,
+            transactions,
+        })
+    }
+
+    pub fn to_ndjson(&self) -> String {
+        let blobs = self.blobs.iter().map(|(key, bytes)| ArchiveLine::Blob {
+            key: key.clone(),
+            bytes: STANDARD.encode(bytes),
+        });
+
+        let transactions = self
+            .transactions
+            .iter()
+            .map(|line| ArchiveLine::Transaction { line: line.clone() });
+
+        let metadata = ArchiveLine::Metadata {
+            bytes: STANDARD.encode(&self.metadata),
+        };
+
+        blobs
+            .chain(transactions)
+            .chain(std::iter::once(metadata))
+            .map(|line| {
+                serde_json::to_string(&line).expect("Archive lines always serialize") + "\n"
+            })
+            .collect()
+    }
+
+    pub fn from_ndjson(archive: &str) -> Result<Self, BackupRestoreError> {
+        let mut parsed = SnapshotArchive::default();
+        let mut has_metadata = false;
+
+        for (i, line) in archive.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+            let invalid = |e: String| BackupRestoreError::InvalidArchive(i + 1, e);
+
+            if has_metadata {
+                return Err(invalid("Lines after the metadata".to_string()));
+            }
+
+            let decode = |bytes: &str| STANDARD.decode(bytes).map_err(|e| invalid(e.to_string()));
+
+            match serde_json::from_str(line).map_err(|e| invalid(e.to_string()))? {
+                ArchiveLine::Blob { key, bytes } => parsed.blobs.push((key, decode(&bytes)?)),
+                ArchiveLine::Transaction { line } => parsed.transactions.push(line),
+                ArchiveLine::Metadata { bytes } => {
+                    parsed.metadata = decode(&bytes)?;
+                    has_metadata = true;
+                }
+            }
+        }
+
+        match has_metadata {
+            true => Ok(parsed),
+            false => Err(BackupRestoreError::NoBackup),
+        }
+    }
+
+    /// Writes the archive into `target` so that the next restore of `target` starts from it, see
+    /// `restore_from_backup`
+    pub fn restore_into(
+        self,
+        target: &mut dyn Storage,
+    ) -> Result<BackupRestoreReport, BackupRestoreError> {
+        if let Some(report) = check_target(target, &self.metadata)? {
+            return Ok(report);
+        }
+
+        let mut report = BackupRestoreReport::default();
+
+        for (key, bytes) in self.blobs {
+            target
+                .write_blob(key, bytes)
+                .map_err(BackupRestoreError::Write)?;
+            report.blobs += 1;
+        }
+
+        let transactions: Vec<Vec<u8>> = self
+            .transactions
+            .into_iter()
+            .map(String::into_bytes)
+            .collect();
+
+        if !transactions.is_empty() {
+            target
+                .transaction_write_batch(&transactions)
+                .and_then(|_| target.transaction_sync())
+                .map_err(BackupRestoreError::Write)?;
+            report.transactions = transactions.len();
+        }
+
+        target
+            .write_blob(Metadata::metadata_key(), self.metadata)
+            .map_err(BackupRestoreError::Write)?;
+        report.blobs += 1;
+
+        Ok(report)
+    }
+}
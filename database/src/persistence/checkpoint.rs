@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts::consts::TransactionId,
+    database::table::{outbox::OutboxMessage, row::PersonVersion, table::PersonTable},
+    model::statement::Statement,
+};
+
+use super::transaction::Transaction;
+
+/// Progress of a WAL replay, written every `DatabaseOptions::set_replay_checkpoint_interval` transactions so that
+/// a restore that is killed part way resumes from the checkpoint instead of the snapshot. Like a snapshot it only
+/// holds the latest version of each row. The checkpoint is cleared once the restore completes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplayCheckpoint {
+    /// The snapshot the replay started from, a checkpoint of another snapshot is ignored
+    pub snapshot_transaction_id: TransactionId,
+    /// The last replayed transaction, every WAL record up to and including it has been applied
+    pub transaction_id: TransactionId,
+    pub versions: Vec<PersonVersion>,
+    pub sequences: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub outbox: Vec<OutboxMessage>,
+    /// See `PersonTable::latest_purge`
+    pub latest_purge: Option<TransactionId>,
+}
+
+impl ReplayCheckpoint {
+    /// Copies the table once every transaction up to and including `transaction_id` has been replayed
+    pub fn new(
+        table: &PersonTable,
+        snapshot_transaction_id: TransactionId,
+        transaction_id: TransactionId,
+    ) -> Self {
+        let versions = table
+            .query_statement(
+                Statement::ListLatestVersions,
+                &TransactionId::new_highest_transaction(),
+            )
+            .expect("Should always be able to list latest versions")
+            .list_version();
+
+        Self {
+            snapshot_transaction_id,
+            transaction_id,
+            versions,
+            sequences: table.sequences.values(),
+            outbox: table.outbox.messages(),
+            latest_purge: table.latest_purge(),
+        }
+    }
+
+    /// Position of the checkpoint's transaction in the WAL records to replay, none if the checkpoint does not
+    /// continue the snapshot (e.g. a snapshot has been taken since) or its transaction is no longer in the WAL
+    pub fn resume_position(
+        &self,
+        snapshot_transaction_id: &TransactionId,
+        transactions: &[Transaction],
+    ) -> Option<usize> {
+        if &self.snapshot_transaction_id != snapshot_transaction_id {
+            return None;
+        }
+
+        transactions
+            .iter()
+            .position(|transaction| transaction.id == self.transaction_id)
+    }
+
+    /// Replaces the rows restored from the snapshot with the checkpoint's rows
+    pub fn restore_into(self, table: &PersonTable) {
+        table.restore_checkpoint(self.versions, self.latest_purge);
+        table.sequences.restore(self.sequences);
+        table.outbox.restore(self.outbox);
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    consts::consts::{EntityId, TransactionId},
+    database::table::{row::PersonVersion, table::PersonTable},
+    model::{person::Person, statement::Statement},
+};
+
+use super::field_encryption::FieldCipher;
+
+/// State of a row mutated by a transaction that could not be replayed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplayConflictRow {
+    pub id: EntityId,
+    /// None if the row does not exist or has been deleted
+    pub current_state: Option<Person>,
+    /// Every version of the row, earliest version first
+    pub versions: Vec<PersonVersion>,
+}
+
+/// Written to storage when a committed transaction rolls back while the WAL is being replayed, which means
+/// the restored state differs from the state the transaction was originally applied to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayConflictReport {
+    pub transaction_id: TransactionId,
+    pub message: String,
+    /// Position of the first statement of the transaction that could not be applied
+    pub statement_index: Option<usize>,
+    pub statement: Option<Statement>,
+    /// The rows mutated by the transaction, as they were before the transaction was replayed
+    pub rows: Vec<ReplayConflictRow>,
+}
+
+impl ReplayConflictReport {
+    /// Builds the report once the transaction has been rolled back. The statements are re-applied one at a
+    /// time to find the one that conflicts, then rolled back again so the table is left untouched.
+    ///
+    /// Note: this must only be called while nothing else is writing to the table, e.g. during a restore
+    pub fn new(
+        table: &PersonTable,
+        transaction_id: &TransactionId,
+        statements: &[Statement],
+        message: String,
+    ) -> Self {
+        let rows = statements
+            .iter()
+            .flat_map(Statement::mutated_ids)
+            .fold(Vec::<EntityId>::new(), |mut ids, id| {
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+                ids
+            })
+            .into_iter()
+            .map(|id| {
+                let (current_state, versions) = match table.person_rows.get(&id) {
+                    Some(row) => {
+                        let row = row.value().read().unwrap();
+                        (row.current_state(), row.history())
+                    }
+                    None => (None, vec![]),
+                };
+
+                ReplayConflictRow {
+                    id,
+                    current_state,
+                    versions,
+                }
+            })
+            .collect();
+
+        let mut applied = vec![];
+        let mut conflict = None;
+
+        for (index, statement) in statements.iter().enumerate() {
+            // Sequences cannot conflict and are not rolled back
+            if let Statement::NextVal(_) = statement {
+                continue;
+            }
+
+            match table.apply(statement.clone(), transaction_id.clone()) {
+                Ok(_) => applied.push(statement.clone()),
+                Err(_) => {
+                    conflict = Some((index, statement.clone()));
+                    break;
+                }
+            }
+        }
+
+        for statement in applied.into_iter().rev() {
+            table.apply_rollback(statement, transaction_id);
+        }
+
+        let (statement_index, statement) = conflict.unzip();
+
+        Self {
+            transaction_id: transaction_id.clone(),
+            message,
+            statement_index,
+            statement,
+            rows,
+        }
+    }
+
+    /// Sensitive fields are encrypted the same way they are in the WAL and snapshots
+    pub fn encrypt(self, cipher: &FieldCipher) -> Self {
+        Self {
+            statement: self
+                .statement
+                .map(|statement| cipher.encrypt_statement(statement)),
+            rows: self
+                .rows
+                .into_iter()
+                .map(|row| ReplayConflictRow {
+                    current_state: row
+                        .current_state
+                        .map(|person| cipher.encrypt_person(person)),
+                    versions: row
+                        .versions
+                        .into_iter()
+                        .map(|version| cipher.encrypt_version(version))
+                        .collect(),
+                    id: row.id,
+                })
+                .collect(),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for ReplayConflictReport {
+    // Row data is left out as it may contain sensitive fields
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[TX: {}] {}", self.transaction_id, self.message)?;
+
+        if let (Some(index), Some(statement)) = (self.statement_index, &self.statement) {
+            let kind: &'static str = statement.into();
+            write!(f, ", conflicting statement: {} ({})", index, kind)?;
+        }
+
+        for row in &self.rows {
+            write!(f, ", row {} has {} versions", row.id, row.versions.len())?;
+        }
+
+        Ok(())
+    }
+}
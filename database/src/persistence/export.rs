@@ -0,0 +1,116 @@
+use std::io::Write;
+
+use age::{
+    armor::{ArmoredWriter, Format},
+    x25519, Encryptor,
+};
+use thiserror::Error;
+
+use crate::database::table::row::PersonVersion;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("At least one recipient is required to encrypt an export")]
+    NoRecipients,
+
+    #[error("Invalid recipient public key: {0}")]
+    InvalidRecipient(String),
+
+    #[error("Failed to encrypt export: {0}")]
+    Encryption(String),
+}
+
+/// Serializes row versions and encrypts them so that only the holders of the recipients' x25519 (age) private
+/// keys can read them. The output is ASCII armored so that it can be shared as text
+pub fn encrypt_export(
+    versions: &[PersonVersion],
+    recipients: &[String],
+) -> Result<String, ExportError> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            recipient
+                .parse::<x25519::Recipient>()
+                .map(|r| Box::new(r) as Box<dyn age::Recipient + Send>)
+                .map_err(|_| ExportError::InvalidRecipient(recipient.clone()))
+        })
+        .collect::<Result<Vec<_>, ExportError>>()?;
+
+    let encryptor = Encryptor::with_recipients(recipients).ok_or(ExportError::NoRecipients)?;
+
+    let plaintext = serde_json::to_vec(versions).unwrap();
+
+    let encrypt = || -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let armored = ArmoredWriter::wrap_output(vec![], Format::AsciiArmor)?;
+        let mut writer = encryptor.wrap_output(armored)?;
+
+        writer.write_all(&plaintext)?;
+
+        Ok(writer.finish()?.finish()?)
+    };
+
+    let ciphertext = encrypt().map_err(|e| ExportError::Encryption(e.to_string()))?;
+
+    Ok(String::from_utf8(ciphertext).expect("Armored output should always be valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use age::{armor::ArmoredReader, Decryptor};
+
+    use crate::{
+        consts::consts::{TransactionId, VersionId},
+        database::table::row::PersonVersionState,
+        model::person::Person,
+    };
+
+    use super::*;
+
+    #[test]
+    fn recipient_can_decrypt_export() {
+        let identity = x25519::Identity::generate();
+        let person = Person::new("Tom".to_string(), None);
+
+        let versions = vec![PersonVersion {
+            id: person.id.clone(),
+            state: PersonVersionState::State(person),
+            version: VersionId(1),
+            transaction_id: TransactionId::new_first_transaction(),
+            lineage: None,
+        }];
+
+        let export =
+            encrypt_export(&versions, &[identity.to_public().to_string()]).expect("Should encrypt");
+
+        assert!(export.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        let Ok(Decryptor::Recipients(decryptor)) =
+            Decryptor::new(ArmoredReader::new(export.as_bytes()))
+        else {
+            panic!("Export should be encrypted to recipients");
+        };
+
+        let mut plaintext = vec![];
+
+        decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .expect("Recipient should be able to decrypt")
+            .read_to_end(&mut plaintext)
+            .unwrap();
+
+        let decrypted: Vec<PersonVersion> = serde_json::from_slice(&plaintext).unwrap();
+
+        assert_eq!(decrypted, versions);
+
+        assert!(matches!(
+            encrypt_export(&versions, &["not-a-key".to_string()]),
+            Err(ExportError::InvalidRecipient(_))
+        ));
+        assert!(matches!(
+            encrypt_export(&versions, &[]),
+            Err(ExportError::NoRecipients)
+        ));
+    }
+}
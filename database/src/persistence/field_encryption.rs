@@ -0,0 +1,357 @@
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use thiserror::Error;
+
+use crate::{
+    database::table::{
+        conflict::{Conflict, ConflictRecord, DivergentVersion},
+        row::{Lineage, PersonVersion, PersonVersionState, UpdatePersonData, UpdateStatement},
+        view::PersonField,
+    },
+    model::{person::Person, statement::Statement},
+};
+
+/// Prefix of an encrypted field value, values without the prefix were written before encryption was
+/// enabled and are read as is
+const ENCRYPTED_PREFIX: &str = "enc:";
+
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Error, Debug)]
+pub enum FieldEncryptionError {
+    #[error("Field encryption key must be 32 bytes encoded as base64")]
+    InvalidKey,
+
+    #[error("Encrypted field is malformed")]
+    Malformed,
+
+    #[error("Unable to decrypt field, the encryption key may have changed")]
+    Decryption,
+}
+
+/// Configures which fields are encrypted when written to the WAL, snapshots or cold version storage and
+/// which roles may read them, other roles receive masked values
+#[derive(Clone)]
+pub struct FieldEncryptionOptions {
+    key: [u8; 32],
+    pub sensitive_fields: Vec<PersonField>,
+    pub authorized_roles: Vec<String>,
+}
+
+impl FieldEncryptionOptions {
+    pub fn new(
+        base64_key: &str,
+        sensitive_fields: Vec<PersonField>,
+    ) -> Result<Self, FieldEncryptionError> {
+        let key = STANDARD
+            .decode(base64_key)
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or(FieldEncryptionError::InvalidKey)?;
+
+        Ok(Self {
+            key,
+            sensitive_fields,
+            authorized_roles: vec![],
+        })
+    }
+
+    /// Roles that can read the decrypted values of sensitive fields. Requests without a role are trusted
+    pub fn set_authorized_roles(mut self, authorized_roles: Vec<String>) -> Self {
+        self.authorized_roles = authorized_roles;
+        self
+    }
+
+    /// Whether a request running as `role` receives masked values for the sensitive fields
+    pub fn is_masked(&self, role: Option<&str>) -> bool {
+        match role {
+            Some(role) => !self.authorized_roles.iter().any(|r| r == role),
+            None => false,
+        }
+    }
+}
+
+// The key is never logged
+impl fmt::Debug for FieldEncryptionOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldEncryptionOptions")
+            .field("sensitive_fields", &self.sensitive_fields)
+            .field("authorized_roles", &self.authorized_roles)
+            .finish()
+    }
+}
+
+/// Encrypts and decrypts the sensitive fields of rows as they move between memory and storage
+pub struct FieldCipher {
+    cipher: ChaCha20Poly1305,
+    sensitive_fields: Vec<PersonField>,
+}
+
+impl FieldCipher {
+    pub fn new(options: &FieldEncryptionOptions) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&options.key)),
+            sensitive_fields: options.sensitive_fields.clone(),
+        }
+    }
+
+    pub fn encrypt_value(&self, value: &str) -> String {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, value.as_bytes())
+            .expect("Encrypting an in-memory buffer should not fail");
+
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+
+        format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload))
+    }
+
+    pub fn decrypt_value(&self, value: &str) -> Result<String, FieldEncryptionError> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+
+        let payload = STANDARD
+            .decode(encoded)
+            .map_err(|_| FieldEncryptionError::Malformed)?;
+
+        if payload.len() < NONCE_LENGTH {
+            return Err(FieldEncryptionError::Malformed);
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| FieldEncryptionError::Decryption)?;
+
+        String::from_utf8(plaintext).map_err(|_| FieldEncryptionError::Malformed)
+    }
+
+    fn map_person<E>(
+        &self,
+        mut person: Person,
+        f: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<Person, E> {
+        if self.sensitive_fields.contains(&PersonField::FullName) {
+            person.full_name = f(&person.full_name)?;
+        }
+
+        if self.sensitive_fields.contains(&PersonField::Email) {
+            person.email = person.email.as_deref().map(&f).transpose()?;
+        }
+
+        Ok(person)
+    }
+
+    fn map_state<E>(
+        &self,
+        state: PersonVersionState,
+        f: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<PersonVersionState, E> {
+        Ok(match state {
+            PersonVersionState::State(person) => {
+                PersonVersionState::State(self.map_person(person, f)?)
+            }
+            PersonVersionState::Delete => PersonVersionState::Delete,
+        })
+    }
+
+    fn map_divergent<E>(
+        &self,
+        version: DivergentVersion,
+        f: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<DivergentVersion, E> {
+        Ok(DivergentVersion {
+            state: self.map_state(version.state, f)?,
+            ..version
+        })
+    }
+
+    fn map_statement<E>(
+        &self,
+        statement: Statement,
+        f: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<Statement, E> {
+        let map_update = |field: PersonField, update: UpdateStatement| match update {
+            UpdateStatement::Set(value) if self.sensitive_fields.contains(&field) => {
+                f(&value).map(UpdateStatement::Set)
+            }
+            update => Ok(update),
+        };
+
+        let statement = match statement {
+            Statement::Add(person) => Statement::Add(self.map_person(person, &f)?),
+            Statement::Split(id, people) => Statement::Split(
+                id,
+                people
+                    .into_iter()
+                    .map(|person| self.map_person(person, &f))
+                    .collect::<Result<_, _>>()?,
+            ),
+            // Structured fields and attachments cannot be marked as sensitive, they are left as is
+            Statement::Update(
+                id,
+                UpdatePersonData {
+                    full_name,
+                    email,
+                    address,
+                    phone_numbers,
+                    attachments,
+                },
+            ) => Statement::Update(
+                id,
+                UpdatePersonData {
+                    full_name: map_update(PersonField::FullName, full_name)?,
+                    email: map_update(PersonField::Email, email)?,
+                    address,
+                    phone_numbers,
+                    attachments,
+                },
+            ),
+            Statement::ResolveConflict(id, conflict) => Statement::ResolveConflict(
+                id,
+                Conflict {
+                    remote: self.map_divergent(conflict.remote, &f)?,
+                    ..conflict
+                },
+            ),
+            statement => statement,
+        };
+
+        Ok(statement)
+    }
+
+    fn map_version<E>(
+        &self,
+        version: PersonVersion,
+        f: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<PersonVersion, E> {
+        let state = self.map_state(version.state, &f)?;
+
+        // Both sides of a resolved conflict are kept in the lineage
+        let lineage = match version.lineage {
+            Some(Lineage::ConflictResolved(record)) => {
+                Some(Lineage::ConflictResolved(Box::new(ConflictRecord {
+                    local: self.map_divergent(record.local, &f)?,
+                    remote: self.map_divergent(record.remote, &f)?,
+                    ..*record
+                })))
+            }
+            lineage => lineage,
+        };
+
+        Ok(PersonVersion {
+            state,
+            lineage,
+            ..version
+        })
+    }
+
+    pub fn encrypt_person(&self, person: Person) -> Person {
+        self.map_person(person, |v| {
+            Ok::<_, FieldEncryptionError>(self.encrypt_value(v))
+        })
+        .unwrap()
+    }
+
+    pub fn encrypt_statement(&self, statement: Statement) -> Statement {
+        self.map_statement(statement, |v| {
+            Ok::<_, FieldEncryptionError>(self.encrypt_value(v))
+        })
+        .unwrap()
+    }
+
+    pub fn decrypt_statement(
+        &self,
+        statement: Statement,
+    ) -> Result<Statement, FieldEncryptionError> {
+        self.map_statement(statement, |v| self.decrypt_value(v))
+    }
+
+    pub fn encrypt_version(&self, version: PersonVersion) -> PersonVersion {
+        self.map_version(version, |v| {
+            Ok::<_, FieldEncryptionError>(self.encrypt_value(v))
+        })
+        .unwrap()
+    }
+
+    pub fn decrypt_version(
+        &self,
+        version: PersonVersion,
+    ) -> Result<PersonVersion, FieldEncryptionError> {
+        self.map_version(version, |v| self.decrypt_value(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::consts::{EntityId, TransactionId, VersionId};
+
+    #[test]
+    fn sensitive_fields_round_trip() {
+        let options =
+            FieldEncryptionOptions::new(&STANDARD.encode([7u8; 32]), vec![PersonField::Email])
+                .expect("Key should be valid");
+        let cipher = FieldCipher::new(&options);
+
+        let person = Person::new("Name".to_string(), Some("someone@x.com".to_string()));
+
+        let encrypted = cipher.encrypt_version(PersonVersion {
+            id: person.id.clone(),
+            state: PersonVersionState::State(person.clone()),
+            version: VersionId(1),
+            transaction_id: TransactionId::new_first_transaction(),
+            lineage: None,
+        });
+
+        let encrypted_person = encrypted.get_person().unwrap();
+
+        // Only sensitive fields are encrypted
+        assert_eq!(encrypted_person.full_name, "Name");
+        assert!(encrypted_person
+            .email
+            .unwrap()
+            .starts_with(ENCRYPTED_PREFIX));
+
+        let decrypted = cipher.decrypt_version(encrypted).unwrap();
+        assert_eq!(decrypted.get_person(), Some(person));
+
+        // Values written before encryption was enabled are read as is
+        let update = Statement::Update(
+            EntityId("1".to_string()),
+            UpdatePersonData {
+                full_name: UpdateStatement::NoChanges,
+                email: UpdateStatement::Set("plain@x.com".to_string()),
+                ..UpdatePersonData::default()
+            },
+        );
+
+        let Statement::Update(_, data) = cipher.decrypt_statement(update).unwrap() else {
+            panic!("Should be an update");
+        };
+        assert!(matches!(data.email, UpdateStatement::Set(email) if email == "plain@x.com"));
+
+        // A different key cannot decrypt the value
+        let other = FieldCipher::new(
+            &FieldEncryptionOptions::new(&STANDARD.encode([8u8; 32]), vec![PersonField::Email])
+                .unwrap(),
+        );
+        assert!(matches!(
+            other.decrypt_value(&cipher.encrypt_value("secret")),
+            Err(FieldEncryptionError::Decryption)
+        ));
+
+        assert!(FieldEncryptionOptions::new("short", vec![]).is_err());
+    }
+}
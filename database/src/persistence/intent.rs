@@ -0,0 +1,65 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::consts::consts::TransactionId;
+
+/// Operations that are not atomic across storage engines, e.g. a file reset deletes several directories and
+/// a network reset issues a request per blob. A crash part way through leaves storage half-finished
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum IntentOperation {
+    Reset,
+    WalFlush,
+}
+
+impl fmt::Display for IntentOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntentOperation::Reset => write!(f, "Reset"),
+            IntentOperation::WalFlush => write!(f, "WAL flush"),
+        }
+    }
+}
+
+/// Written to storage before a destructive operation starts and rewritten as completed once it has finished.
+/// An intent that is not completed on startup means the operation was interrupted, see `Database::run`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IntentRecord {
+    pub operation: IntentOperation,
+    /// Milliseconds since the unix epoch
+    pub started_at: u128,
+    pub transaction_id: TransactionId,
+    pub completed: bool,
+}
+
+impl IntentRecord {
+    pub fn new(
+        operation: IntentOperation,
+        transaction_id: TransactionId,
+        started_at: u128,
+    ) -> Self {
+        Self {
+            operation,
+            started_at,
+            transaction_id,
+            completed: false,
+        }
+    }
+
+    pub fn complete(self) -> Self {
+        Self {
+            completed: true,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for IntentRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} started at transaction {} ({}ms since the epoch) did not finish",
+            self.operation, self.transaction_id, self.started_at
+        )
+    }
+}
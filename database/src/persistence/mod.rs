@@ -1,4 +1,15 @@
+pub mod backup;
+pub mod checkpoint;
+pub mod diagnostics;
+pub mod export;
+pub mod field_encryption;
+pub(crate) mod intent;
+pub mod parquet;
 pub mod persistence;
+pub mod pre_commit;
 pub mod snapshot;
+pub mod snapshot_diff;
+pub mod snapshot_shards;
 pub mod storage;
 pub mod transaction;
+pub mod wal_batching;
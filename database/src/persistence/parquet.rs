@@ -0,0 +1,359 @@
+use std::{collections::HashSet, fs, path::PathBuf, sync::Arc};
+
+use arrow_array::{
+    builder::{StringBuilder, UInt32Builder, UInt64Builder},
+    ArrayRef, RecordBatch, TimestampMillisecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::{arrow::ArrowWriter, errors::ParquetError};
+use thiserror::Error;
+
+use crate::{
+    consts::consts::EntityId,
+    database::table::row::{UpdateAddressStatement, UpdateListStatement, UpdateStatement},
+    model::statement::Statement,
+};
+
+use super::{
+    storage::{Storage, StorageError},
+    transaction::{Transaction, TransactionStatus},
+};
+
+#[derive(Error, Debug)]
+pub enum ParquetExportError {
+    #[error("Failed to write the Parquet file: {0}")]
+    Parquet(#[from] ParquetError),
+
+    #[error("Failed to build the record batch: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    #[error("Failed to write the Parquet file to the export directory: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// Where the WAL segments flushed by a snapshot are exported to, see `DatabaseOptions::set_parquet_export`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParquetExportTarget {
+    /// Written as a blob of the database's storage engine, next to the snapshot
+    Storage,
+    /// Written to a local directory, e.g. one that is synced to a data lake
+    Directory(PathBuf),
+}
+
+impl ParquetExportTarget {
+    /// Writes the Parquet file of a WAL segment, `key` is the key of the snapshot that flushed it
+    pub fn write(
+        &self,
+        storage: &mut dyn Storage,
+        key: &str,
+        bytes: Vec<u8>,
+    ) -> Result<(), ParquetExportError> {
+        let file_name = format!("wal-{}.parquet", key);
+
+        match self {
+            ParquetExportTarget::Storage => storage.write_blob(file_name, bytes)?,
+            ParquetExportTarget::Directory(directory) => {
+                fs::create_dir_all(directory)?;
+                fs::write(directory.join(file_name), bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Columns of the exported Parquet files, one row per statement
+pub fn wal_export_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("transaction_id", DataType::UInt64, false),
+        Field::new("status", DataType::Utf8, false),
+        // The global transaction id of a two-phase commit record
+        Field::new("global_transaction_id", DataType::Utf8, true),
+        Field::new("statement_index", DataType::UInt32, false),
+        // The WAL does not record when a transaction committed, this is when the segment was exported
+        Field::new(
+            "exported_at",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("statement", DataType::Utf8, false),
+        Field::new("entity_id", DataType::Utf8, true),
+        // The other row of a rename or merge
+        Field::new("target_id", DataType::Utf8, true),
+        Field::new("full_name", DataType::Utf8, true),
+        Field::new("email", DataType::Utf8, true),
+        // JSON, structured fields are exported as they are in the statement
+        Field::new("address", DataType::Utf8, true),
+        Field::new("phone_numbers", DataType::Utf8, true),
+        // JSON of the whole statement, for statements that do not fit the columns above (e.g. splits)
+        Field::new("statement_json", DataType::Utf8, false),
+    ])
+}
+
+/// Builds the columns of one row
+#[derive(Default)]
+struct StatementRow {
+    entity_id: Option<String>,
+    target_id: Option<String>,
+    full_name: Option<String>,
+    email: Option<String>,
+    address: Option<String>,
+    phone_numbers: Option<String>,
+}
+
+impl StatementRow {
+    fn from_statement(statement: &Statement) -> Self {
+        match statement {
+            Statement::Add(person) => StatementRow {
+                entity_id: Some(person.id.0.clone()),
+                full_name: Some(person.full_name.clone()),
+                email: person.email.clone(),
+                address: person
+                    .address
+                    .as_ref()
+                    .map(|address| serde_json::to_string(address).unwrap()),
+                phone_numbers: Some(serde_json::to_string(&person.phone_numbers).unwrap()),
+                ..Default::default()
+            },
+            // Only values that are set are exported, `statement_json` tells unset and unchanged fields apart
+            Statement::Update(id, update) => {
+                let set = |update: &UpdateStatement| match update {
+                    UpdateStatement::Set(value) => Some(value.clone()),
+                    UpdateStatement::Unset | UpdateStatement::NoChanges => None,
+                };
+
+                StatementRow {
+                    entity_id: Some(id.0.clone()),
+                    full_name: set(&update.full_name),
+                    email: set(&update.email),
+                    address: match &update.address {
+                        UpdateAddressStatement::Set(address) => {
+                            Some(serde_json::to_string(address).unwrap())
+                        }
+                        UpdateAddressStatement::Unset | UpdateAddressStatement::NoChanges => None,
+                    },
+                    phone_numbers: match &update.phone_numbers {
+                        UpdateListStatement::NoChanges => None,
+                        phone_numbers => Some(serde_json::to_string(phone_numbers).unwrap()),
+                    },
+                    ..Default::default()
+                }
+            }
+            Statement::Rename(from, to) | Statement::Merge(from, to, _) => StatementRow {
+                entity_id: Some(from.0.clone()),
+                target_id: Some(to.0.clone()),
+                ..Default::default()
+            },
+            Statement::Remove(id)
+            | Statement::Split(id, _)
+            | Statement::ResolveConflict(id, _)
+            | Statement::Purge(id) => StatementRow {
+                entity_id: Some(id.0.clone()),
+                ..Default::default()
+            },
+            // The WAL only holds mutations, sequences and the outbox are not a part of a row
+            _ => StatementRow::default(),
+        }
+    }
+}
+
+/// Drops the statements that mutated a person who is purged later in the transactions, only the purge itself
+/// (see `Statement::Purge`) is kept. Unlike a replay the export does not need the statements to be consistent
+pub fn without_purged(transactions: Vec<Transaction>) -> Vec<Transaction> {
+    let mut purged: HashSet<EntityId> = HashSet::new();
+
+    // Walks the transactions backwards so a statement is only dropped if the purge came after it
+    let mut transactions: Vec<Transaction> = transactions
+        .into_iter()
+        .rev()
+        .map(|mut transaction| {
+            let mut statements = vec![];
+
+            for statement in transaction.statements.into_iter().rev() {
+                if let Statement::Purge(id) = &statement {
+                    purged.insert(id.clone());
+                } else if statement
+                    .mutated_ids()
+                    .iter()
+                    .any(|id| purged.contains(*id))
+                {
+                    continue;
+                }
+
+                statements.push(statement);
+            }
+
+            statements.reverse();
+            transaction.statements = statements;
+
+            transaction
+        })
+        .collect();
+
+    transactions.reverse();
+
+    transactions
+}
+
+/// Converts WAL transactions into a Parquet file with a row per statement, so that the change history can be
+/// analyzed (e.g. with Spark or DuckDB) without reading the live database. Fields encrypted in the WAL (see
+/// `FieldCipher`) are exported encrypted
+pub fn transactions_to_parquet(
+    transactions: &[Transaction],
+    exported_at_ms: i64,
+) -> Result<Vec<u8>, ParquetExportError> {
+    let mut transaction_id = UInt64Builder::new();
+    let mut status = StringBuilder::new();
+    let mut global_transaction_id = StringBuilder::new();
+    let mut statement_index = UInt32Builder::new();
+    let mut statement_kind = StringBuilder::new();
+    let mut entity_id = StringBuilder::new();
+    let mut target_id = StringBuilder::new();
+    let mut full_name = StringBuilder::new();
+    let mut email = StringBuilder::new();
+    let mut address = StringBuilder::new();
+    let mut phone_numbers = StringBuilder::new();
+    let mut statement_json = StringBuilder::new();
+    let mut rows = 0;
+
+    for transaction in transactions {
+        let (status_name, global_id) = match &transaction.status {
+            TransactionStatus::Committed => ("Committed", None),
+            TransactionStatus::Prepared(id) => ("Prepared", Some(id)),
+            TransactionStatus::CommitPrepared(id) => ("CommitPrepared", Some(id)),
+            TransactionStatus::AbortPrepared(id) => ("AbortPrepared", Some(id)),
+        };
+
+        for (index, statement) in transaction.statements.iter().enumerate() {
+            let row = StatementRow::from_statement(statement);
+            let kind: &'static str = statement.into();
+
+            transaction_id.append_value(transaction.id.to_number() as u64);
+            status.append_value(status_name);
+            global_transaction_id.append_option(global_id);
+            statement_index.append_value(index as u32);
+            statement_kind.append_value(kind);
+            entity_id.append_option(row.entity_id);
+            target_id.append_option(row.target_id);
+            full_name.append_option(row.full_name);
+            email.append_option(row.email);
+            address.append_option(row.address);
+            phone_numbers.append_option(row.phone_numbers);
+            statement_json.append_value(serde_json::to_string(statement).unwrap());
+
+            rows += 1;
+        }
+    }
+
+    let schema = Arc::new(wal_export_schema());
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(transaction_id.finish()),
+        Arc::new(status.finish()),
+        Arc::new(global_transaction_id.finish()),
+        Arc::new(statement_index.finish()),
+        Arc::new(TimestampMillisecondArray::from(vec![exported_at_ms; rows]).with_timezone("UTC")),
+        Arc::new(statement_kind.finish()),
+        Arc::new(entity_id.finish()),
+        Arc::new(target_id.finish()),
+        Arc::new(full_name.finish()),
+        Arc::new(email.finish()),
+        Arc::new(address.finish()),
+        Arc::new(phone_numbers.finish()),
+        Arc::new(statement_json.finish()),
+    ];
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let mut writer = ArrowWriter::try_new(vec![], schema, None)?;
+    writer.write(&batch)?;
+
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use bytes::Bytes;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::{
+        consts::consts::{EntityId, TransactionId},
+        database::table::row::UpdatePersonData,
+        model::person::Person,
+    };
+
+    use super::*;
+
+    #[test]
+    fn exports_a_row_per_statement() {
+        let id = EntityId("a".to_string());
+
+        let transactions = vec![
+            Transaction {
+                id: TransactionId(1),
+                statements: vec![Statement::Add(Person {
+                    id: id.clone(),
+                    full_name: "Alice".to_string(),
+                    email: None,
+                    address: None,
+                    phone_numbers: vec![],
+                    attachments: vec![],
+                })],
+                status: TransactionStatus::Committed,
+            },
+            Transaction {
+                id: TransactionId(2),
+                statements: vec![
+                    Statement::Update(
+                        id.clone(),
+                        UpdatePersonData {
+                            email: UpdateStatement::Set("alice@example.com".to_string()),
+                            ..Default::default()
+                        },
+                    ),
+                    Statement::Rename(id.clone(), EntityId("b".to_string())),
+                ],
+                status: TransactionStatus::Committed,
+            },
+        ];
+
+        let bytes = transactions_to_parquet(&transactions, 1_700_000_000_000).unwrap();
+
+        let batches = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 3);
+
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+
+        let transaction_ids: Vec<u64> = column("transaction_id")
+            .as_primitive::<UInt64Type>()
+            .values()
+            .to_vec();
+        assert_eq!(transaction_ids, vec![1, 2, 2]);
+
+        let statements = column("statement");
+        let statements: Vec<Option<&str>> = statements.as_string::<i32>().iter().collect();
+        assert_eq!(
+            statements,
+            vec![Some("Add"), Some("Update"), Some("Rename")]
+        );
+
+        let email = column("email");
+        let email: Vec<Option<&str>> = email.as_string::<i32>().iter().collect();
+        assert_eq!(email, vec![None, Some("alice@example.com"), None]);
+
+        let target_id = column("target_id");
+        let target_id: Vec<Option<&str>> = target_id.as_string::<i32>().iter().collect();
+        assert_eq!(target_id, vec![None, None, Some("b")]);
+    }
+}
@@ -1,11 +1,24 @@
 use std::sync::{Arc, Mutex};
 
-use crate::database::options::DatabaseOptions;
+use crate::database::{
+    options::DatabaseOptions,
+    orchestrator::DatabasePauseEvent,
+    pause::{PauseOperation, PauseTracker},
+    table::row::PersonVersion,
+};
 
 use super::{
-    snapshot::SnapshotManager,
-    storage::{Storage, StorageEngine, StorageResult},
-    transaction::TransactionWAL,
+    field_encryption::FieldCipher,
+    snapshot::{
+        Metadata, OptionsFingerprint, SnapshotManager, SnapshotRecord, SnapshotVerification,
+    },
+    storage::{
+        migration::{MigrationPhase, StorageMigration},
+        network::StorageLatency,
+        replication::StorageReplication,
+        Storage, StorageEngine, StorageResult,
+    },
+    transaction::{Transaction, TransactionWAL, UnknownStatementPolicy},
 };
 
 // TODO: Do not expose the underlying WAL / Snapshot manager
@@ -13,21 +26,81 @@ pub struct Persistence {
     pub transaction_wal: TransactionWAL,
     pub snapshot_manager: SnapshotManager,
     storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    storage_latency: Arc<StorageLatency>,
+    storage_replication: Arc<StorageReplication>,
+    field_cipher: Option<Arc<FieldCipher>>,
+    migration: Option<Arc<StorageMigration>>,
 }
 
 impl Persistence {
     pub fn new(options: DatabaseOptions) -> Self {
-        let storage: Arc<Mutex<dyn Storage + Sync + Send>> =
-            StorageEngine::get_engine(options.clone());
+        let migration = options
+            .migrate_to
+            .clone()
+            .map(|target| Arc::new(StorageMigration::new(target)));
+
+        let storage_latency = Arc::new(StorageLatency::default());
+        let storage_replication = Arc::new(StorageReplication::default());
+
+        let storage: Arc<Mutex<dyn Storage + Sync + Send>> = StorageEngine::get_engine(
+            options.clone(),
+            migration.clone(),
+            storage_latency.clone(),
+            storage_replication.clone(),
+        );
+
+        let field_cipher = options
+            .field_encryption
+            .as_ref()
+            .map(|field_encryption| Arc::new(FieldCipher::new(field_encryption)));
 
-        let mut transaction_wal = TransactionWAL::new(options.clone(), storage.clone());
+        let mut transaction_wal =
+            TransactionWAL::new(options.clone(), storage.clone(), field_cipher.clone());
 
         transaction_wal.init();
 
         Self {
             transaction_wal: transaction_wal,
-            snapshot_manager: SnapshotManager::new(storage.clone()),
+            snapshot_manager: SnapshotManager::new(
+                storage.clone(),
+                OptionsFingerprint::from_options(&options),
+                field_cipher.clone(),
+                options.retained_snapshots,
+                options.archive_wal,
+                options.parquet_export.clone(),
+                options.snapshot_sharding.clone(),
+            ),
+            storage,
+            storage_latency,
+            storage_replication,
+            field_cipher,
+            migration,
+        }
+    }
+
+    /// Opens the storage to inspect its snapshots and WAL without restoring a database. Nothing is created or
+    /// written, so it is safe to open the storage of a running database
+    pub fn open_read_only(options: DatabaseOptions) -> ReadOnlyPersistence {
+        let storage = StorageEngine::get_read_only_engine(&options);
+
+        let field_cipher = options
+            .field_encryption
+            .as_ref()
+            .map(|field_encryption| Arc::new(FieldCipher::new(field_encryption)));
+
+        ReadOnlyPersistence {
+            snapshot_manager: SnapshotManager::new(
+                storage.clone(),
+                OptionsFingerprint::from_options(&options),
+                field_cipher.clone(),
+                options.retained_snapshots,
+                options.archive_wal,
+                options.parquet_export.clone(),
+                options.snapshot_sharding.clone(),
+            ),
             storage,
+            field_cipher,
+            unknown_statements: options.unknown_statements,
         }
     }
 
@@ -35,7 +108,184 @@ impl Persistence {
         return self.storage.lock().unwrap().init();
     }
 
+    pub fn get_storage(&self) -> Arc<Mutex<dyn Storage + Sync + Send>> {
+        self.storage.clone()
+    }
+
+    /// Latency of the operations of network storage engines, empty for the file engine
+    pub fn get_storage_latency(&self) -> Arc<StorageLatency> {
+        self.storage_latency.clone()
+    }
+
+    /// Progress of copying writes to the engine's replica, empty unless the engine has one
+    pub fn get_storage_replication(&self) -> Arc<StorageReplication> {
+        self.storage_replication.clone()
+    }
+
+    /// If set, sensitive fields are encrypted whenever rows are written to storage
+    pub fn get_field_cipher(&self) -> Option<Arc<FieldCipher>> {
+        self.field_cipher.clone()
+    }
+
+    /// The engine being migrated to and the phase of the migration, if the database is migrating
+    pub fn get_migration(&self) -> Option<(StorageEngine, MigrationPhase)> {
+        self.migration
+            .as_ref()
+            .map(|migration| (migration.target.clone(), migration.phase()))
+    }
+
+    /// Switches storage to the migration target, the storage lock is held so that no write is halfway through
+    /// being mirrored while the engines are switched
+    pub fn cutover_migration(&self) -> Result<StorageEngine, String> {
+        let migration = self
+            .migration
+            .as_ref()
+            .ok_or("The database is not migrating storage engines".to_string())?;
+
+        let _storage = self.storage.lock().unwrap();
+
+        migration.cutover()?;
+
+        Ok(migration.target.clone())
+    }
+
     pub fn reset(&self) -> StorageResult<()> {
         self.storage.lock().unwrap().reset_database()
     }
 }
+
+/// Read-only view of a database's storage, see `Persistence::open_read_only`. Every write is rejected by the
+/// storage engine
+pub struct ReadOnlyPersistence {
+    snapshot_manager: SnapshotManager,
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    field_cipher: Option<Arc<FieldCipher>>,
+    unknown_statements: UnknownStatementPolicy,
+}
+
+impl ReadOnlyPersistence {
+    /// The metadata of the latest snapshot, the default metadata if no snapshot has been written
+    pub fn metadata(&self) -> StorageResult<Metadata> {
+        self.snapshot_manager.read_metadata()
+    }
+
+    /// Promoted snapshots, newest first
+    pub fn list_snapshots(&self) -> StorageResult<Vec<SnapshotRecord>> {
+        self.snapshot_manager.list_snapshots()
+    }
+
+    /// Reads the versions of a promoted snapshot, none if there is no snapshot with the key
+    pub fn read_snapshot(&self, key: &str) -> StorageResult<Option<Vec<PersonVersion>>> {
+        self.snapshot_manager.read_snapshot(key)
+    }
+
+    /// Checks the latest snapshot against its checksum and record count
+    pub fn verify_snapshot(&self) -> StorageResult<SnapshotVerification> {
+        // There is no database, so there are no workers to pause
+        let tracker = PauseTracker::new(None);
+        let pause = DatabasePauseEvent::new(&vec![], &tracker, PauseOperation::VerifySnapshot);
+
+        self.snapshot_manager.verify_snapshot(&pause, None)
+    }
+
+    /// The transactions of the WAL in order with their line (starting at 1), sensitive fields are decrypted.
+    /// A transaction that cannot be decoded (e.g. a torn write) is an error for its line only. Transactions
+    /// archived with earlier snapshots are not included
+    pub fn transactions(
+        &self,
+    ) -> StorageResult<impl Iterator<Item = (usize, Result<Transaction, anyhow::Error>)>> {
+        let lines = self.storage.lock().unwrap().transaction_load()?;
+        let field_cipher = self.field_cipher.clone();
+        let unknown_statements = self.unknown_statements;
+
+        Ok(lines.into_iter().enumerate().map(move |(index, line)| {
+            let transaction = Transaction::from_wal(&line, unknown_statements)
+                .map_err(anyhow::Error::new)
+                .and_then(|mut transaction| {
+                    if let Some(cipher) = &field_cipher {
+                        transaction.statements = transaction
+                            .statements
+                            .into_iter()
+                            .map(|statement| cipher.decrypt_statement(statement))
+                            .collect::<Result<_, _>>()
+                            .map_err(anyhow::Error::new)?;
+                    }
+
+                    Ok(transaction)
+                });
+
+            (index + 1, transaction)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::{
+        database::{
+            commands::{ShutdownRequest, TransactionContext},
+            database::Database,
+        },
+        model::person::Person,
+        persistence::storage::file::FileOptions,
+    };
+
+    use super::*;
+
+    #[test]
+    fn read_only_persistence_does_not_change_storage() {
+        let dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+        let options = DatabaseOptions::default()
+            .set_storage_engine(StorageEngine::File(FileOptions::new(dir.clone())));
+
+        let missing = Persistence::open_read_only(options.clone());
+        assert_eq!(missing.transactions().unwrap().count(), 0);
+        assert!(missing.list_snapshots().unwrap().is_empty());
+        assert!(!dir.exists());
+
+        let request_manager = Database::new(options.clone()).run();
+        request_manager
+            .send_add(
+                Person::new("Snapshotted".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+        request_manager.send_snapshot_request().unwrap();
+        request_manager
+            .send_add(
+                Person::new("Logged".to_string(), None),
+                TransactionContext::default(),
+            )
+            .unwrap();
+        request_manager
+            .send_shutdown_request(ShutdownRequest::Coordinator)
+            .unwrap();
+
+        let persistence = Persistence::open_read_only(options);
+
+        let transactions: Vec<_> = persistence.transactions().unwrap().collect();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].0, 1);
+        assert_eq!(transactions[0].1.as_ref().unwrap().statements.len(), 1);
+
+        let metadata = persistence.metadata().unwrap();
+        let snapshot = persistence
+            .read_snapshot(&metadata.snapshots[0].key)
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.len(), 1);
+        assert!(persistence.verify_snapshot().unwrap().is_valid());
+
+        let storage = persistence.storage.lock().unwrap();
+        assert!(storage.write_blob("blob".to_string(), vec![1]).is_err());
+        assert!(storage
+            .delete_blob(metadata.snapshots[0].key.clone())
+            .is_err());
+    }
+}
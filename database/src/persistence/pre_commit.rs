@@ -0,0 +1,170 @@
+use std::{
+    fmt,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use super::transaction::Transaction;
+
+pub type PreCommitFn = Arc<dyn Fn(&[Transaction]) -> Result<(), String> + Send + Sync>;
+
+/// What happens to the callers of a batch the pre-commit hook failed (or timed out) on. The transactions have
+/// already been applied and written to the WAL either way
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PreCommitFailure {
+    /// The callers are told the transaction may not be durable, as when the WAL cannot be synced
+    #[default]
+    Reject,
+    /// The failure is logged and the callers are acknowledged, durability falls back to the WAL alone
+    Acknowledge,
+    /// The database crashes, the transactions are recovered from the WAL on restart
+    Crash,
+}
+
+/// Runs on the WAL thread once a batch is durable in the WAL and before the callers are acknowledged, e.g. to
+/// write the transactions to a second storage engine or a remote quorum. Set with
+/// `DatabaseOptions::set_pre_commit_hook`
+#[derive(Clone)]
+pub struct PreCommitHook {
+    hook: PreCommitFn,
+    pub timeout: Duration,
+    pub on_failure: PreCommitFailure,
+}
+
+impl PreCommitHook {
+    pub fn new(
+        hook: impl Fn(&[Transaction]) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            hook: Arc::new(hook),
+            timeout: Duration::from_secs(5),
+            on_failure: PreCommitFailure::default(),
+        }
+    }
+
+    /// How long a batch waits for the hook before it is treated as a failure
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn set_on_failure(mut self, on_failure: PreCommitFailure) -> Self {
+        self.on_failure = on_failure;
+        self
+    }
+}
+
+impl fmt::Debug for PreCommitHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreCommitHook")
+            .field("timeout", &self.timeout)
+            .field("on_failure", &self.on_failure)
+            .finish()
+    }
+}
+
+type PreCommitRequest = (Vec<Transaction>, flume::Sender<Result<(), String>>);
+
+/// Runs the hook on its own thread so a hook that hangs only fails its batch. A batch that arrives while the
+/// hook is still running a timed out batch waits behind it, any later batch fails straight away
+#[derive(Clone)]
+pub struct PreCommitRunner {
+    hook: PreCommitHook,
+    requests: flume::Sender<PreCommitRequest>,
+}
+
+impl PreCommitRunner {
+    pub fn new(hook: PreCommitHook) -> Self {
+        let (requests, receiver) = flume::bounded::<PreCommitRequest>(1);
+        let run = hook.hook.clone();
+
+        // The thread stops once every runner is dropped, i.e. when the WAL is closed
+        thread::Builder::new()
+            .name("Pre-commit Hook".to_string())
+            .spawn(move || {
+                for (transactions, done) in receiver.iter() {
+                    let result = catch_unwind(AssertUnwindSafe(|| run(&transactions)))
+                        .unwrap_or_else(|_| Err("the hook panicked".to_string()));
+
+                    let _ = done.send(result);
+                }
+            })
+            .expect("Should be able to spawn the pre-commit hook thread");
+
+        Self { hook, requests }
+    }
+
+    pub fn on_failure(&self) -> PreCommitFailure {
+        self.hook.on_failure
+    }
+
+    pub fn run(&self, transactions: Vec<Transaction>) -> Result<(), String> {
+        let (done, result) = flume::bounded(1);
+
+        self.requests
+            .try_send((transactions, done))
+            .map_err(|_| "the hook is still running an earlier batch".to_string())?;
+
+        match result.recv_timeout(self.hook.timeout) {
+            Ok(result) => result,
+            Err(flume::RecvTimeoutError::Timeout) => {
+                Err(format!("the hook timed out after {:?}", self.hook.timeout))
+            }
+            Err(flume::RecvTimeoutError::Disconnected) => {
+                Err("the hook thread has stopped".to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::{consts::consts::TransactionId, persistence::transaction::TransactionStatus};
+
+    use super::*;
+
+    fn transactions() -> Vec<Transaction> {
+        vec![Transaction {
+            id: TransactionId(1),
+            statements: vec![],
+            status: TransactionStatus::Committed,
+        }]
+    }
+
+    #[test]
+    fn slow_and_panicking_hooks_fail_the_batch() {
+        let hang = Arc::new(AtomicBool::new(false));
+        let hook_hang = hang.clone();
+
+        let runner = PreCommitRunner::new(
+            PreCommitHook::new(move |transactions| {
+                if hook_hang.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_secs(2));
+                }
+
+                match transactions.is_empty() {
+                    true => panic!("No transactions"),
+                    false => Ok(()),
+                }
+            })
+            .set_timeout(Duration::from_millis(500)),
+        );
+
+        assert_eq!(runner.run(transactions()), Ok(()));
+        assert_eq!(runner.run(vec![]), Err("the hook panicked".to_string()));
+
+        // The thread survives the panic
+        assert_eq!(runner.run(transactions()), Ok(()));
+
+        hang.store(true, Ordering::Relaxed);
+
+        assert!(runner
+            .run(transactions())
+            .unwrap_err()
+            .contains("timed out"));
+    }
+}
@@ -1,28 +1,242 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::{
     consts::consts::TransactionId,
     database::{
+        options::DatabaseOptions,
         orchestrator::DatabasePauseEvent,
-        table::{row::PersonVersion, table::PersonTable},
+        prepared::PreparedTransaction,
+        scheduler::JobDefinition,
+        table::{
+            index::IndexedField, outbox::OutboxMessage, policy::RowPolicy,
+            prepared_query::PreparedQueryDefinition, row::PersonVersion, table::PersonTable,
+            view::ViewDefinition,
+        },
     },
     model::statement::Statement,
 };
 
-use super::storage::{ReadBlobState, Storage, StorageResult};
+use super::{
+    checkpoint::ReplayCheckpoint,
+    diagnostics::ReplayConflictReport,
+    field_encryption::FieldCipher,
+    intent::{IntentOperation, IntentRecord},
+    parquet::{transactions_to_parquet, without_purged, ParquetExportTarget},
+    snapshot_shards::{SnapshotShard, SnapshotSharding},
+    storage::{ReadBlobState, Storage, StorageError, StorageResult},
+    transaction::{Transaction, UnknownStatementPolicy},
+};
 
 enum FileType {
     Metadata,
+    /// Snapshots written before snapshots were versioned, they were overwritten in place
     Snapshot,
+    /// A promoted snapshot, see `SnapshotRecord`
+    VersionedSnapshot(String),
+    /// The WAL that was flushed when a snapshot was promoted, see `SnapshotRecord::wal_archive`
+    WalArchive(String),
+    Views,
+    Policies,
+    PreparedQueries,
+    Indexes,
+    ReplayConflict,
+    Intent,
+    ReplayCheckpoint,
 }
 
 impl FileType {
-    fn as_str(&self) -> &'static str {
+    fn as_str(&self) -> &str {
         match self {
             FileType::Metadata => "metadata",
             FileType::Snapshot => "snapshot",
+            FileType::VersionedSnapshot(key) | FileType::WalArchive(key) => key,
+            FileType::Views => "views",
+            FileType::Policies => "policies",
+            FileType::PreparedQueries => "prepared_queries",
+            FileType::Indexes => "indexes",
+            FileType::ReplayConflict => "replay_conflict",
+            FileType::Intent => "intent",
+            FileType::ReplayCheckpoint => "replay_checkpoint",
+        }
+    }
+}
+
+/// Bumped whenever the way row versions are serialized into the snapshot changes
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+/// Bumped whenever the person table schema changes
+pub const TABLE_SCHEMA_VERSION: u32 = 2;
+/// Oldest table schema a snapshot can be restored from. Version 2 only added fields with defaults (the structured
+/// address and phone numbers), so version 1 snapshots are read as people without them
+pub const MIN_TABLE_SCHEMA_VERSION: u32 = 1;
+
+/// An on-disk feature that changes how the data directory has to be read. Features are recorded in the metadata
+/// once they have been enabled and are never removed, see `SnapshotManager::record_features`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StorageFeature {
+    /// Sensitive fields are encrypted in the WAL, snapshots and cold version storage
+    FieldEncryption,
+    /// Old versions of rows are spilled out of the snapshot into their own blobs
+    ColdVersions,
+}
+
+impl StorageFeature {
+    /// Every feature this build is able to read
+    pub const SUPPORTED: [StorageFeature; 2] = [
+        StorageFeature::FieldEncryption,
+        StorageFeature::ColdVersions,
+    ];
+
+    /// The name recorded in the metadata, names of unknown features are kept as is
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageFeature::FieldEncryption => "field-encryption",
+            StorageFeature::ColdVersions => "cold-versions",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StorageFeature> {
+        Self::SUPPORTED
+            .into_iter()
+            .find(|feature| feature.as_str() == name)
+    }
+
+    /// The features enabled by the options, by name
+    pub fn enabled(options: &DatabaseOptions) -> BTreeSet<String> {
+        let mut features = BTreeSet::new();
+
+        if options.field_encryption.is_some() {
+            features.insert(StorageFeature::FieldEncryption.as_str().to_string());
+        }
+
+        if options.hot_versions.is_some() {
+            features.insert(StorageFeature::ColdVersions.as_str().to_string());
+        }
+
+        features
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StorageFeatureError {
+    #[error("The data directory requires feature {0}, which this build does not support. Open it with a newer build")]
+    Unsupported(String),
+
+    #[error("The data directory requires feature {0}, which is not enabled")]
+    NotEnabled(String),
+}
+
+/// The parts of the database configuration that a snapshot depends on, stored in the metadata so that a
+/// database can tell whether it is able to restore a snapshot before reading it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OptionsFingerprint {
+    pub format_version: u32,
+    pub schema_version: u32,
+    pub threads: usize,
+    pub hot_versions: Option<usize>,
+    /// The storage features the database had enabled, see `StorageFeature`
+    #[serde(default)]
+    pub features: BTreeSet<String>,
+}
+
+impl OptionsFingerprint {
+    pub fn from_options(options: &DatabaseOptions) -> Self {
+        Self {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            schema_version: TABLE_SCHEMA_VERSION,
+            threads: options.worker_threads(),
+            hot_versions: options.hot_versions,
+            features: StorageFeature::enabled(options),
+        }
+    }
+
+    /// Checks whether a data directory that requires the `recorded` features can be opened by this database, a
+    /// feature has to be both supported by this build and enabled
+    pub fn check_features(&self, recorded: &BTreeSet<String>) -> Result<(), StorageFeatureError> {
+        for name in recorded {
+            if StorageFeature::from_name(name).is_none() {
+                return Err(StorageFeatureError::Unsupported(name.clone()));
+            }
+
+            if !self.features.contains(name) {
+                return Err(StorageFeatureError::NotEnabled(name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a snapshot written with the `snapshot` fingerprint can be restored by this database.
+    /// Threads and hot versions only affect the running database so they do not need to match
+    pub fn check_compatible(
+        &self,
+        snapshot: &OptionsFingerprint,
+    ) -> Result<(), SnapshotCompatibilityError> {
+        if snapshot.format_version != self.format_version {
+            return Err(SnapshotCompatibilityError::FormatVersion {
+                snapshot: snapshot.format_version,
+                database: self.format_version,
+            });
+        }
+
+        if snapshot.schema_version > self.schema_version
+            || snapshot.schema_version < MIN_TABLE_SCHEMA_VERSION
+        {
+            return Err(SnapshotCompatibilityError::SchemaVersion {
+                snapshot: snapshot.schema_version,
+                database: self.schema_version,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SnapshotCompatibilityError {
+    #[error("Snapshot was written with serialization format version {snapshot}, this database reads version {database}")]
+    FormatVersion { snapshot: u32, database: u32 },
+
+    #[error("Snapshot was written with table schema version {snapshot}, this database expects version {database}")]
+    SchemaVersion { snapshot: u32, database: u32 },
+}
+
+/// A snapshot blob that has been promoted. Snapshots are written to a new (timestamped) key and only promoted
+/// once the metadata pointing at them is written, so a crash mid-write leaves the previous snapshot in place
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotRecord {
+    /// Blob key of the snapshot, e.g. `snapshot-1718000000000-42`
+    pub key: String,
+    pub transaction_id: TransactionId,
+    /// CRC32 of the snapshot's blobs, the shards are checksummed in order
+    pub checksum: u32,
+    pub record_count: usize,
+    /// The shards the rows are split into, empty if the rows are stored in a single blob at `key`. The shards are
+    /// promoted along with the rest of the record, a snapshot is never restored from part of its shards
+    #[serde(default)]
+    pub shards: Vec<SnapshotShard>,
+    pub sequences: BTreeMap<String, u64>,
+    pub prepared: Vec<PreparedTransaction>,
+    #[serde(default)]
+    pub outbox: Vec<OutboxMessage>,
+    /// Blob key of the transactions between the previous snapshot and this one, only set if the WAL is archived.
+    /// A restore that rolls back past this snapshot replays them
+    #[serde(default)]
+    pub wal_archive: Option<String>,
+}
+
+impl SnapshotRecord {
+    /// Blob keys the rows of the snapshot are stored in, in order
+    pub fn snapshot_keys(&self) -> Vec<String> {
+        match self.shards.is_empty() {
+            true => vec![self.key.clone()],
+            false => self.shards.iter().map(|shard| shard.key.clone()).collect(),
         }
     }
 }
@@ -30,36 +244,401 @@ impl FileType {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Metadata {
     pub current_transaction_id: TransactionId,
+    /// CRC32 of the snapshot blob, snapshots written before checksums were introduced will not have one
+    #[serde(default)]
+    pub snapshot_checksum: Option<u32>,
+    /// Number of row versions stored in the snapshot blob
+    #[serde(default)]
+    pub snapshot_record_count: Option<usize>,
+    /// Scheduled jobs, stored in the metadata so that schedules survive restarts
+    #[serde(default)]
+    pub jobs: Vec<JobDefinition>,
+    /// Configuration of the database that wrote the snapshot, snapshots written before fingerprints were
+    /// introduced will not have one
+    #[serde(default)]
+    pub options: Option<OptionsFingerprint>,
+    /// Last value of each sequence as of the snapshot's transaction id
+    #[serde(default)]
+    pub sequences: BTreeMap<String, u64>,
+    /// Two-phase commit transactions that were in-doubt at the snapshot, their WAL records are flushed with
+    /// the snapshot
+    #[serde(default)]
+    pub prepared: Vec<PreparedTransaction>,
+    /// Outbox messages that had not been acknowledged as of the snapshot's transaction id
+    #[serde(default)]
+    pub outbox: Vec<OutboxMessage>,
+    /// Promoted snapshots, newest first. The newest is the snapshot described by the fields above, the older
+    /// ones are kept so that a restore can roll back to them if the newest is invalid
+    #[serde(default)]
+    pub snapshots: Vec<SnapshotRecord>,
+    /// Storage features that have ever been enabled for the data directory, by name. A database that does not
+    /// support or has not enabled one of them refuses to open the directory, see `StorageFeature`
+    #[serde(default)]
+    pub features: BTreeSet<String>,
+}
+
+impl Metadata {
+    /// The blobs the snapshot described by the metadata is stored in, more than one if it is sharded
+    fn snapshot_files(&self) -> Vec<FileType> {
+        match self.snapshots.first() {
+            Some(record) => record
+                .snapshot_keys()
+                .into_iter()
+                .map(FileType::VersionedSnapshot)
+                .collect(),
+            None => vec![FileType::Snapshot],
+        }
+    }
+
+    /// The shards of the snapshot described by the metadata, empty if it is not sharded
+    fn snapshot_shards(&self) -> &[SnapshotShard] {
+        self.snapshots
+            .first()
+            .map_or(&[], |record| record.shards.as_slice())
+    }
+
+    /// Every blob a restore from this metadata may read, apart from the metadata itself. Some of them may not
+    /// exist, e.g. a database without views has no views blob
+    pub fn blob_keys(&self) -> Vec<String> {
+        let mut files = self.latest_files();
+
+        for record in self.snapshots.iter().skip(1) {
+            files.extend(
+                record
+                    .snapshot_keys()
+                    .into_iter()
+                    .map(FileType::VersionedSnapshot),
+            );
+        }
+
+        for record in self.snapshots.iter() {
+            if let Some(wal_archive) = &record.wal_archive {
+                files.push(FileType::WalArchive(wal_archive.clone()));
+            }
+        }
+
+        files.iter().map(|file| file.as_str().to_string()).collect()
+    }
+
+    /// The blobs the latest snapshot is restored from, unlike `blob_keys` the older snapshots and WAL archives
+    /// (only needed by point in time restores) are left out
+    pub fn latest_blob_keys(&self) -> Vec<String> {
+        self.latest_files()
+            .iter()
+            .map(|file| file.as_str().to_string())
+            .collect()
+    }
+
+    fn latest_files(&self) -> Vec<FileType> {
+        let mut files = self.snapshot_files();
+
+        files.extend([
+            FileType::Views,
+            FileType::Policies,
+            FileType::PreparedQueries,
+            FileType::Indexes,
+        ]);
+
+        files
+    }
+
+    /// Blob key of the metadata, it is written last when a snapshot is promoted
+    pub fn metadata_key() -> String {
+        FileType::Metadata.as_str().to_string()
+    }
 }
 
 impl Default for Metadata {
     fn default() -> Self {
         Metadata {
             current_transaction_id: TransactionId::new_first_transaction(),
+            snapshot_checksum: None,
+            snapshot_record_count: None,
+            jobs: vec![],
+            options: None,
+            sequences: BTreeMap::new(),
+            prepared: vec![],
+            outbox: vec![],
+            snapshots: vec![],
+            features: BTreeSet::new(),
         }
     }
 }
 
+/// Result of re-reading the latest snapshot and comparing it against the stored metadata
+#[derive(Debug)]
+pub struct SnapshotVerification {
+    pub snapshot_transaction_id: TransactionId,
+    pub record_count: usize,
+    pub checksum: Option<u32>,
+    /// Row count of the shadow table rebuilt from the snapshot, only set if a shadow table was requested
+    pub shadow_row_count: Option<usize>,
+    /// Row count of the live table at the snapshot's transaction id, only set if a shadow table was requested
+    pub live_row_count: Option<usize>,
+    pub discrepancies: Vec<String>,
+}
+
+impl SnapshotVerification {
+    pub fn is_valid(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+
+    pub fn to_info(&self) -> Vec<(String, String)> {
+        let optional = |v: Option<String>| v.unwrap_or_else(|| "N/A".to_string());
+
+        let info = vec![
+            ("Valid".to_string(), self.is_valid().to_string()),
+            (
+                "SnapshotTransactionID".to_string(),
+                self.snapshot_transaction_id.to_string(),
+            ),
+            ("RecordCount".to_string(), self.record_count.to_string()),
+            (
+                "Checksum".to_string(),
+                optional(self.checksum.map(|c| format!("{:08x}", c))),
+            ),
+            (
+                "ShadowRowCount".to_string(),
+                optional(self.shadow_row_count.map(|c| c.to_string())),
+            ),
+            (
+                "LiveRowCount".to_string(),
+                optional(self.live_row_count.map(|c| c.to_string())),
+            ),
+        ];
+
+        info.into_iter()
+            .chain(
+                self.discrepancies
+                    .iter()
+                    .map(|d| ("Discrepancy".to_string(), d.clone())),
+            )
+            .collect()
+    }
+}
+
 pub struct SnapshotManager {
     storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    fingerprint: OptionsFingerprint,
+    /// If set, sensitive fields are encrypted in the snapshot
+    field_cipher: Option<Arc<FieldCipher>>,
+    /// Number of promoted snapshots that are kept, older ones are deleted
+    retained_snapshots: usize,
+    /// Whether the WAL is archived along with the snapshot before it is flushed
+    archive_wal: bool,
+    /// Where the WAL is exported to as Parquet before it is flushed
+    parquet_export: Option<ParquetExportTarget>,
+    /// If set, snapshots are split into shards that are uploaded concurrently
+    sharding: Option<SnapshotSharding>,
 }
 
 impl SnapshotManager {
-    pub fn new(storage: Arc<Mutex<dyn Storage + Sync + Send>>) -> Self {
-        Self { storage }
+    pub fn new(
+        storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+        fingerprint: OptionsFingerprint,
+        field_cipher: Option<Arc<FieldCipher>>,
+        retained_snapshots: usize,
+        archive_wal: bool,
+        parquet_export: Option<ParquetExportTarget>,
+        sharding: Option<SnapshotSharding>,
+    ) -> Self {
+        Self {
+            storage,
+            fingerprint,
+            field_cipher,
+            retained_snapshots: retained_snapshots.max(1),
+            archive_wal,
+            parquet_export,
+            sharding,
+        }
+    }
+
+    /// Checks the fingerprint stored with the latest snapshot against this database's configuration.
+    /// Returns the snapshot's fingerprint, none if there is no snapshot or it predates fingerprints
+    pub fn check_compatibility(
+        &self,
+    ) -> StorageResult<Result<Option<OptionsFingerprint>, SnapshotCompatibilityError>> {
+        let Metadata { options, .. } = self.read_file(FileType::Metadata)?;
+
+        let result = match options {
+            Some(snapshot) => self
+                .fingerprint
+                .check_compatible(&snapshot)
+                .map(|_| Some(snapshot)),
+            None => Ok(None),
+        };
+
+        Ok(result)
+    }
+
+    /// Checks the storage features recorded in the metadata against this database's configuration. Returns the
+    /// recorded features
+    pub fn check_features(&self) -> StorageResult<Result<BTreeSet<String>, StorageFeatureError>> {
+        let features = self.recorded_features()?;
+
+        Ok(self.fingerprint.check_features(&features).map(|_| features))
+    }
+
+    /// The storage features recorded for the data directory, by name
+    pub fn recorded_features(&self) -> StorageResult<BTreeSet<String>> {
+        let Metadata { features, .. } = self.read_file(FileType::Metadata)?;
+
+        Ok(features)
+    }
+
+    /// The storage features this database has enabled, by name
+    pub fn enabled_features(&self) -> &BTreeSet<String> {
+        &self.fingerprint.features
+    }
+
+    /// Adds the storage features this database has enabled to the metadata, before anything is written with them.
+    /// Recorded features are kept even once they are disabled, as data written with them may still be read
+    pub fn record_features(&self) -> StorageResult<()> {
+        let metadata: Metadata = self.read_file(FileType::Metadata)?;
+
+        if self.fingerprint.features.is_subset(&metadata.features) {
+            return Ok(());
+        }
+
+        let features = metadata
+            .features
+            .union(&self.fingerprint.features)
+            .cloned()
+            .collect();
+
+        self.write_file(
+            FileType::Metadata,
+            Metadata {
+                features,
+                ..metadata
+            },
+        )
+        .map(|_| ())
     }
 
-    pub fn restore_snapshot(&self, table: &PersonTable) -> StorageResult<(usize, Metadata)> {
+    /// Restores the newest valid snapshot into the table. Returns the number of restored versions, the metadata of
+    /// the snapshot and the archived transactions that have to be replayed before the WAL (if the restore rolled
+    /// back to an older snapshot)
+    pub fn restore_snapshot(
+        &self,
+        table: &PersonTable,
+    ) -> StorageResult<(usize, Metadata, Vec<String>)> {
+        let mut metadata_data: Metadata = self.read_file(FileType::Metadata)?;
+
         // -- Table
-        let version_snapshots: Vec<PersonVersion> = self.read_file(FileType::Snapshot)?;
+        let (mut version_snapshots, archived_transactions): (Vec<PersonVersion>, Vec<String>) =
+            match metadata_data.snapshots.is_empty() {
+                true => (self.read_file(FileType::Snapshot)?, vec![]),
+                false => self.read_newest_valid_snapshot(&mut metadata_data)?,
+            };
+
+        if let Some(cipher) = &self.field_cipher {
+            version_snapshots = version_snapshots
+                .into_iter()
+                .map(|version| cipher.decrypt_version(version))
+                .collect::<Result<_, _>>()
+                .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?;
+        }
 
         let snapshot_count = version_snapshots.len();
 
         table.restore_table(version_snapshots);
 
-        let metadata_data: Metadata = self.read_file(FileType::Metadata)?;
+        if let Some(cipher) = &self.field_cipher {
+            for transaction in metadata_data.prepared.iter_mut() {
+                transaction.statements = std::mem::take(&mut transaction.statements)
+                    .into_iter()
+                    .map(|statement| cipher.decrypt_statement(statement))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?;
+            }
+        }
+
+        table.sequences.restore(metadata_data.sequences.clone());
+        table.outbox.restore(metadata_data.outbox.clone());
 
-        return Ok((snapshot_count, metadata_data));
+        return Ok((snapshot_count, metadata_data, archived_transactions));
+    }
+
+    /// Reads the newest promoted snapshot that matches its checksum. If the newest is missing or corrupt the
+    /// restore rolls back to an older one, the metadata is updated to describe it. The transactions between the
+    /// two snapshots are returned from the WAL archives, they are lost if the WAL was not archived
+    fn read_newest_valid_snapshot(
+        &self,
+        metadata: &mut Metadata,
+    ) -> StorageResult<(Vec<PersonVersion>, Vec<String>)> {
+        for (index, record) in metadata.snapshots.clone().into_iter().enumerate() {
+            let files = record
+                .snapshot_keys()
+                .into_iter()
+                .map(FileType::VersionedSnapshot)
+                .collect();
+
+            let blobs = match self.read_snapshot_blobs(files)? {
+                Some(blobs) if snapshot_checksum(&blobs) == record.checksum => blobs,
+                Some(blobs) => {
+                    log::error!("Snapshot {} does not match its checksum", record.key);
+
+                    for shard in corrupt_shards(&record.shards, &blobs) {
+                        log::error!("Snapshot shard {} does not match its checksum", shard);
+                    }
+
+                    continue;
+                }
+                None => {
+                    log::error!("Snapshot {} is missing", record.key);
+                    continue;
+                }
+            };
+
+            let version_snapshots = match deserialize_snapshot(&blobs) {
+                Ok(version_snapshots) => version_snapshots,
+                Err(e) => {
+                    log::error!("Snapshot {} could not be deserialized: {}", record.key, e);
+                    continue;
+                }
+            };
+
+            if index == 0 {
+                return Ok((version_snapshots, vec![]));
+            }
+
+            let archived_transactions = match self.read_wal_archives(&metadata.snapshots[..index]) {
+                Some(archived_transactions) => {
+                    log::warn!(
+                        "Rolled back to snapshot {} at transaction {}, replaying {} archived transactions before the WAL",
+                        record.key,
+                        record.transaction_id,
+                        archived_transactions.len()
+                    );
+
+                    archived_transactions
+                }
+                None => {
+                    log::warn!(
+                        "Rolled back to snapshot {} at transaction {}, the WAL archives since are incomplete so transactions since are lost unless they are in the WAL",
+                        record.key,
+                        record.transaction_id
+                    );
+
+                    vec![]
+                }
+            };
+
+            metadata.current_transaction_id = record.transaction_id.clone();
+            metadata.snapshot_checksum = Some(record.checksum);
+            metadata.snapshot_record_count = Some(record.record_count);
+            metadata.sequences = record.sequences.clone();
+            metadata.prepared = record.prepared.clone();
+            metadata.outbox = record.outbox.clone();
+
+            return Ok((version_snapshots, archived_transactions));
+        }
+
+        Err(StorageError::UnableToReadBlob(anyhow::anyhow!(
+            "None of the {} retained snapshots are valid",
+            metadata.snapshots.len()
+        )))
     }
 
     pub fn create_snapshot(
@@ -67,6 +646,7 @@ impl SnapshotManager {
         _: &DatabasePauseEvent,
         table: &PersonTable,
         transaction_id: TransactionId,
+        prepared: Vec<PreparedTransaction>,
     ) -> StorageResult<()> {
         // -- Table
         let result = table
@@ -74,18 +654,593 @@ impl SnapshotManager {
             .expect("Should always be able to list latest versions")
             .list_version();
 
-        self.write_file(FileType::Snapshot, result)?;
+        let result = match &self.field_cipher {
+            Some(cipher) => result
+                .into_iter()
+                .map(|version| cipher.encrypt_version(version))
+                .collect(),
+            None => result,
+        };
+
+        let snapshot_record_count = result.len();
+
+        // Written to a new key, the previous snapshot stays valid until the metadata promotes this one
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let key = format!("snapshot-{}-{}", timestamp, transaction_id);
+
+        let (snapshot_blobs, shards) = match &self.sharding {
+            Some(sharding) => self.write_shards(sharding, &key, result)?,
+            None => (
+                vec![self.write_file(FileType::VersionedSnapshot(key.clone()), result)?],
+                vec![],
+            ),
+        };
+
+        // Jobs are not a part of the snapshot, carry them over from the previous metadata
+        let Metadata {
+            jobs,
+            mut snapshots,
+            mut features,
+            ..
+        } = self.read_file(FileType::Metadata)?;
+
+        // The older snapshots and the WAL flushed by this snapshot still hold the rows of a purge since the previous
+        //  snapshot. They cannot be rewritten without breaking the replay of the transactions after the purge, so
+        //  they are dropped, a point in time restore cannot roll back past a purge
+        let purged = match (table.latest_purge(), snapshots.first()) {
+            (Some(purge), Some(previous)) => purge >= previous.transaction_id,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        // The WAL holds the transactions since the previous snapshot, it is flushed once this snapshot is promoted
+        let transactions = match self.archive_wal || self.parquet_export.is_some() {
+            true => self.storage.lock().unwrap().transaction_load()?,
+            false => vec![],
+        };
+
+        if let Some(target) = &self.parquet_export {
+            self.export_parquet(target, &key, &transactions, &transaction_id, timestamp);
+        }
+
+        let wal_archive = match self.archive_wal && !purged {
+            true => {
+                let archive_key = format!("wal-{}", key);
+
+                self.write_file(FileType::WalArchive(archive_key.clone()), transactions)?;
+
+                Some(archive_key)
+            }
+            false => None,
+        };
+
+        let prepared = match &self.field_cipher {
+            Some(cipher) => prepared
+                .into_iter()
+                .map(|transaction| PreparedTransaction {
+                    statements: transaction
+                        .statements
+                        .into_iter()
+                        .map(|statement| cipher.encrypt_statement(statement))
+                        .collect(),
+                    ..transaction
+                })
+                .collect(),
+            None => prepared,
+        };
+
+        features.extend(self.fingerprint.features.iter().cloned());
+
+        let record = SnapshotRecord {
+            key,
+            transaction_id: transaction_id.clone(),
+            checksum: snapshot_checksum(&snapshot_blobs),
+            record_count: snapshot_record_count,
+            shards,
+            sequences: table.sequences.values(),
+            prepared: prepared.clone(),
+            outbox: table.outbox.messages(),
+            wal_archive,
+        };
 
+        snapshots.insert(0, record.clone());
+
+        let retained = match purged {
+            true => 1,
+            false => self.retained_snapshots.min(snapshots.len()),
+        };
+
+        let pruned = snapshots.split_off(retained);
+
+        // Promotes the snapshot, the metadata is a single blob so it is replaced atomically
         self.write_file(
             FileType::Metadata,
             &Metadata {
                 current_transaction_id: transaction_id,
+                snapshot_checksum: Some(record.checksum),
+                snapshot_record_count: Some(snapshot_record_count),
+                jobs,
+                options: Some(self.fingerprint.clone()),
+                sequences: record.sequences,
+                prepared,
+                outbox: record.outbox,
+                snapshots,
+                features,
             },
         )?;
 
+        // The snapshot is already promoted, a snapshot that could not be deleted is only wasted space
+        for record in pruned {
+            for key in record.snapshot_keys().into_iter().chain(record.wal_archive) {
+                if let Err(e) = self.storage.lock().unwrap().delete_blob(key.clone()) {
+                    log::warn!("Unable to delete {}: {}", key, e);
+                }
+            }
+        }
+
         Ok(())
     }
 
+    /// Writes the versions to a blob per shard, the shards are uploaded concurrently. Returns the bytes of each
+    /// shard and the shards' manifest
+    fn write_shards(
+        &self,
+        sharding: &SnapshotSharding,
+        key: &str,
+        versions: Vec<PersonVersion>,
+    ) -> StorageResult<(Vec<Vec<u8>>, Vec<SnapshotShard>)> {
+        let shards = sharding.split(versions);
+
+        let blobs: Vec<(String, Vec<u8>)> = shards
+            .iter()
+            .enumerate()
+            .map(|(index, versions)| {
+                (
+                    format!("{}-shard-{}", key, index),
+                    serde_json::to_vec(versions).unwrap(),
+                )
+            })
+            .collect();
+
+        let manifest = blobs
+            .iter()
+            .zip(&shards)
+            .map(|((key, bytes), versions)| SnapshotShard {
+                key: key.clone(),
+                checksum: crc32fast::hash(bytes),
+                record_count: versions.len(),
+            })
+            .collect();
+
+        sharding.upload(&*self.storage.lock().unwrap(), blobs.clone())?;
+
+        Ok((
+            blobs.into_iter().map(|(_, bytes)| bytes).collect(),
+            manifest,
+        ))
+    }
+
+    /// Exports the transactions the snapshot covers. The snapshot does not depend on the export, so a failed
+    /// export is logged instead of failing the snapshot
+    fn export_parquet(
+        &self,
+        target: &ParquetExportTarget,
+        key: &str,
+        transactions: &[String],
+        snapshot_transaction_id: &TransactionId,
+        timestamp: u128,
+    ) {
+        // A compacted WAL keeps the transactions after the snapshot, they are exported with the next snapshot
+        let transactions: Vec<Transaction> = transactions
+            .iter()
+            .filter_map(|transaction| {
+                match Transaction::from_wal(transaction, UnknownStatementPolicy::Fail) {
+                    Ok(transaction) => Some(transaction),
+                    Err(e) => {
+                        log::error!(
+                            "Skipping a WAL transaction that could not be decoded: {}",
+                            e
+                        );
+                        None
+                    }
+                }
+            })
+            .filter(|transaction| &transaction.id < snapshot_transaction_id)
+            .collect();
+
+        let transactions = without_purged(transactions);
+
+        let result = transactions_to_parquet(&transactions, timestamp as i64)
+            .and_then(|bytes| target.write(&mut *self.storage.lock().unwrap(), key, bytes));
+
+        match result {
+            Ok(()) => log::info!(
+                "Exported {} WAL transactions of snapshot {} to Parquet",
+                transactions.len(),
+                key
+            ),
+            Err(e) => log::error!(
+                "Unable to export the WAL of snapshot {} to Parquet: {}",
+                key,
+                e
+            ),
+        }
+    }
+
+    /// Re-reads the latest snapshot and checks it against the checksum and record count in the metadata.
+    ///
+    /// If a live table is provided, the snapshot is also restored into an in-memory shadow table and the
+    /// row counts are compared against the live table as it was at the snapshot's transaction id
+    pub fn verify_snapshot(
+        &self,
+        _: &DatabasePauseEvent,
+        live_table: Option<&PersonTable>,
+    ) -> StorageResult<SnapshotVerification> {
+        let metadata: Metadata = self.read_file(FileType::Metadata)?;
+
+        let mut verification = SnapshotVerification {
+            snapshot_transaction_id: metadata.current_transaction_id.clone(),
+            record_count: 0,
+            checksum: None,
+            shadow_row_count: None,
+            live_row_count: None,
+            discrepancies: vec![],
+        };
+
+        let snapshot_blobs = match self.read_snapshot_blobs(metadata.snapshot_files())? {
+            Some(blobs) => blobs,
+            None => {
+                verification
+                    .discrepancies
+                    .push("No snapshot has been written".to_string());
+
+                return Ok(verification);
+            }
+        };
+
+        let checksum = snapshot_checksum(&snapshot_blobs);
+
+        verification.checksum = Some(checksum);
+
+        match metadata.snapshot_checksum {
+            Some(expected) if expected != checksum => verification.discrepancies.push(format!(
+                "Checksum mismatch, expected: {:08x}, actual: {:08x}",
+                expected, checksum
+            )),
+            Some(_) => {}
+            None => verification
+                .discrepancies
+                .push("Metadata does not contain a snapshot checksum".to_string()),
+        }
+
+        for shard in corrupt_shards(metadata.snapshot_shards(), &snapshot_blobs) {
+            verification
+                .discrepancies
+                .push(format!("Shard {} does not match its checksum", shard));
+        }
+
+        let version_snapshots = match deserialize_snapshot(&snapshot_blobs) {
+            Ok(v) => v,
+            Err(e) => {
+                verification
+                    .discrepancies
+                    .push(format!("Snapshot could not be deserialized: {}", e));
+
+                return Ok(verification);
+            }
+        };
+
+        verification.record_count = version_snapshots.len();
+
+        if let Some(expected) = metadata.snapshot_record_count {
+            if expected != version_snapshots.len() {
+                verification.discrepancies.push(format!(
+                    "Record count mismatch, expected: {}, actual: {}",
+                    expected,
+                    version_snapshots.len()
+                ));
+            }
+        }
+
+        if let Some(live_table) = live_table {
+            let shadow_table = PersonTable::new();
+
+            shadow_table.restore_table(version_snapshots);
+
+            let shadow_row_count = shadow_table.person_rows.len();
+
+            // The live table has likely moved on since the snapshot, so we compare against the rows
+            //  that existed at the time the snapshot was taken
+            let live_row_count = live_table
+                .person_rows
+                .iter()
+                .filter(|row| {
+                    row.value()
+                        .read()
+                        .unwrap()
+                        .version_at_transaction_id(&metadata.current_transaction_id)
+                        .is_some()
+                })
+                .count();
+
+            if shadow_row_count != live_row_count {
+                verification.discrepancies.push(format!(
+                    "Row count mismatch, shadow table: {}, live table: {}",
+                    shadow_row_count, live_row_count
+                ));
+            }
+
+            verification.shadow_row_count = Some(shadow_row_count);
+            verification.live_row_count = Some(live_row_count);
+        }
+
+        Ok(verification)
+    }
+
+    /// The metadata of the latest snapshot, the default metadata if no snapshot has been written
+    pub fn read_metadata(&self) -> StorageResult<Metadata> {
+        self.read_file(FileType::Metadata)
+    }
+
+    /// Promoted snapshots, newest first
+    pub fn list_snapshots(&self) -> StorageResult<Vec<SnapshotRecord>> {
+        let Metadata { snapshots, .. } = self.read_file(FileType::Metadata)?;
+
+        Ok(snapshots)
+    }
+
+    /// Reads the versions of a promoted snapshot, see `list_snapshots`. None if there is no snapshot with the key
+    pub fn read_snapshot(&self, key: &str) -> StorageResult<Option<Vec<PersonVersion>>> {
+        let Some(record) = self
+            .list_snapshots()?
+            .into_iter()
+            .find(|record| record.key == key)
+        else {
+            return Ok(None);
+        };
+
+        let files = record
+            .snapshot_keys()
+            .into_iter()
+            .map(FileType::VersionedSnapshot)
+            .collect();
+
+        let blobs = match self.read_snapshot_blobs(files)? {
+            Some(blobs) if snapshot_checksum(&blobs) == record.checksum => blobs,
+            Some(_) => {
+                return Err(StorageError::UnableToReadBlob(anyhow::anyhow!(
+                    "Snapshot {} does not match its checksum",
+                    key
+                )))
+            }
+            None => {
+                return Err(StorageError::UnableToReadBlob(anyhow::anyhow!(
+                    "Snapshot {} is missing",
+                    key
+                )))
+            }
+        };
+
+        let versions = deserialize_snapshot(&blobs)
+            .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?;
+
+        match &self.field_cipher {
+            Some(cipher) => versions
+                .into_iter()
+                .map(|version| cipher.decrypt_version(version))
+                .collect::<Result<_, _>>()
+                .map(Some)
+                .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e))),
+            None => Ok(Some(versions)),
+        }
+    }
+
+    /// Reads the WAL archives of the (newest first) snapshots in order of the transactions, none if an archive is
+    /// missing. Replaying around a missing archive would apply transactions out of order
+    fn read_wal_archives(&self, records: &[SnapshotRecord]) -> Option<Vec<String>> {
+        let mut archived_transactions = vec![];
+
+        for record in records.iter().rev() {
+            let key = record.wal_archive.clone()?;
+
+            let bytes = match self.read_blob(FileType::WalArchive(key.clone())) {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => {
+                    log::error!("WAL archive {} is missing", key);
+                    return None;
+                }
+                Err(e) => {
+                    log::error!("WAL archive {} could not be read: {}", key, e);
+                    return None;
+                }
+            };
+
+            match serde_json::from_slice::<Vec<String>>(&bytes) {
+                Ok(transactions) => archived_transactions.extend(transactions),
+                Err(e) => {
+                    log::error!("WAL archive {} could not be deserialized: {}", key, e);
+                    return None;
+                }
+            }
+        }
+
+        Some(archived_transactions)
+    }
+
+    /// View definitions are persisted so that views can be rebuilt on startup, the rows of a view are not
+    pub fn save_view_definitions(&self, definitions: Vec<ViewDefinition>) -> StorageResult<()> {
+        self.write_file(FileType::Views, definitions).map(|_| ())
+    }
+
+    pub fn load_view_definitions(&self) -> StorageResult<Vec<ViewDefinition>> {
+        self.read_file(FileType::Views)
+    }
+
+    pub fn save_policies(&self, policies: Vec<RowPolicy>) -> StorageResult<()> {
+        self.write_file(FileType::Policies, policies).map(|_| ())
+    }
+
+    pub fn load_policies(&self) -> StorageResult<Vec<RowPolicy>> {
+        self.read_file(FileType::Policies)
+    }
+
+    pub fn save_prepared_queries(
+        &self,
+        definitions: Vec<PreparedQueryDefinition>,
+    ) -> StorageResult<()> {
+        self.write_file(FileType::PreparedQueries, definitions)
+            .map(|_| ())
+    }
+
+    pub fn load_prepared_queries(&self) -> StorageResult<Vec<PreparedQueryDefinition>> {
+        self.read_file(FileType::PreparedQueries)
+    }
+
+    /// Only the indexed fields are persisted, the indexes are rebuilt from the restored table on startup
+    pub fn save_indexes(&self, fields: Vec<IndexedField>) -> StorageResult<()> {
+        self.write_file(FileType::Indexes, fields).map(|_| ())
+    }
+
+    pub fn load_indexes(&self) -> StorageResult<Vec<IndexedField>> {
+        self.read_file(FileType::Indexes)
+    }
+
+    /// The transaction id the latest snapshot was taken at, none if there is no snapshot. Snapshots written
+    /// before record counts were introduced are treated as missing
+    pub fn snapshot_transaction_id(&self) -> StorageResult<Option<TransactionId>> {
+        let metadata: Metadata = self.read_file(FileType::Metadata)?;
+
+        Ok(metadata
+            .snapshot_record_count
+            .map(|_| metadata.current_transaction_id))
+    }
+
+    /// Saves the report of a transaction that could not be replayed, see `ReplayConflictReport`
+    pub fn save_replay_conflict(&self, report: ReplayConflictReport) -> StorageResult<()> {
+        let report = match &self.field_cipher {
+            Some(cipher) => report.encrypt(cipher),
+            None => report,
+        };
+
+        self.write_file(FileType::ReplayConflict, Some(report))
+            .map(|_| ())
+    }
+
+    /// The report of the last transaction that could not be replayed, if any
+    pub fn load_replay_conflict(&self) -> StorageResult<Option<ReplayConflictReport>> {
+        self.read_file(FileType::ReplayConflict)
+    }
+
+    /// Saves the progress of the WAL replay, replacing the previous checkpoint
+    pub fn save_replay_checkpoint(&self, checkpoint: ReplayCheckpoint) -> StorageResult<()> {
+        let checkpoint = match &self.field_cipher {
+            Some(cipher) => ReplayCheckpoint {
+                versions: checkpoint
+                    .versions
+                    .into_iter()
+                    .map(|version| cipher.encrypt_version(version))
+                    .collect(),
+                ..checkpoint
+            },
+            None => checkpoint,
+        };
+
+        self.write_file(FileType::ReplayCheckpoint, Some(checkpoint))
+            .map(|_| ())
+    }
+
+    /// The checkpoint of a replay that did not complete, if any
+    pub fn load_replay_checkpoint(&self) -> StorageResult<Option<ReplayCheckpoint>> {
+        let checkpoint: Option<ReplayCheckpoint> = self.read_file(FileType::ReplayCheckpoint)?;
+
+        match (checkpoint, &self.field_cipher) {
+            (Some(checkpoint), Some(cipher)) => Ok(Some(ReplayCheckpoint {
+                versions: checkpoint
+                    .versions
+                    .into_iter()
+                    .map(|version| cipher.decrypt_version(version))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e)))?,
+                ..checkpoint
+            })),
+            (checkpoint, _) => Ok(checkpoint),
+        }
+    }
+
+    /// Clears the checkpoint once the replay has completed. It is overwritten rather than deleted, as not every
+    /// engine can delete blobs and the checkpoint may hold rows that have since been purged
+    pub fn clear_replay_checkpoint(&self) -> StorageResult<()> {
+        self.write_file(FileType::ReplayCheckpoint, None::<ReplayCheckpoint>)
+            .map(|_| ())
+    }
+
+    /// Records that a destructive operation is about to start, it must be completed with `complete_intent`.
+    /// `started_at` is in milliseconds since the unix epoch, see `Clock::now_ms`
+    pub fn begin_intent(
+        &self,
+        operation: IntentOperation,
+        transaction_id: TransactionId,
+        started_at: u128,
+    ) -> StorageResult<IntentRecord> {
+        let intent = IntentRecord::new(operation, transaction_id, started_at);
+
+        self.write_file(FileType::Intent, Some(intent.clone()))?;
+
+        Ok(intent)
+    }
+
+    /// Marks the operation as finished. A reset deletes the intent along with the rest of storage, so the
+    /// completed intent is written again rather than deleted
+    pub fn complete_intent(&self, intent: IntentRecord) -> StorageResult<()> {
+        self.write_file(FileType::Intent, Some(intent.complete()))
+            .map(|_| ())
+    }
+
+    /// The last destructive operation if it was started but never completed
+    pub fn load_interrupted_intent(&self) -> StorageResult<Option<IntentRecord>> {
+        let intent: Option<IntentRecord> = self.read_file(FileType::Intent)?;
+
+        Ok(intent.filter(|intent| !intent.completed))
+    }
+
+    /// Updates the scheduled jobs stored in the metadata, the rest of the metadata is left untouched
+    pub fn save_jobs(&self, jobs: Vec<JobDefinition>) -> StorageResult<()> {
+        let metadata: Metadata = self.read_file(FileType::Metadata)?;
+
+        self.write_file(FileType::Metadata, Metadata { jobs, ..metadata })
+            .map(|_| ())
+    }
+
+    /// Reads the blobs of a snapshot in order, none if any of them is missing
+    fn read_snapshot_blobs(&self, files: Vec<FileType>) -> StorageResult<Option<Vec<Vec<u8>>>> {
+        let mut blobs = vec![];
+
+        for file in files {
+            match self.read_blob(file)? {
+                Some(bytes) => blobs.push(bytes),
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(blobs))
+    }
+
+    fn read_blob(&self, file_path: FileType) -> StorageResult<Option<Vec<u8>>> {
+        let result = self
+            .storage
+            .lock()
+            .unwrap()
+            .read_blob(file_path.as_str().to_string())?;
+
+        match result {
+            ReadBlobState::Found(file_contents) => Ok(Some(file_contents)),
+            ReadBlobState::NotFound => Ok(None),
+        }
+    }
+
     fn read_file<T: DeserializeOwned + Default>(&self, file_path: FileType) -> StorageResult<T> {
         let result = self
             .storage
@@ -94,27 +1249,171 @@ impl SnapshotManager {
             .read_blob(file_path.as_str().to_string());
 
         match result {
-            Ok(ReadBlobState::Found(file_contents)) => {
-                let data: T = serde_json::from_slice(&file_contents).unwrap();
-                return Ok(data);
-            }
-            Ok(ReadBlobState::NotFound) => {
-                return Ok(T::default());
-            }
-            Err(e) => {
-                return Err(e);
-            }
+            // Files are read back from storage, a corrupt file is an error rather than a panic
+            Ok(ReadBlobState::Found(file_contents)) => serde_json::from_slice(&file_contents)
+                .map_err(|e| StorageError::UnableToReadBlob(anyhow::Error::new(e))),
+            Ok(ReadBlobState::NotFound) => Ok(T::default()),
+            Err(e) => Err(e),
         }
     }
 
-    fn write_file<T: Serialize>(&self, file_path: FileType, data: T) -> StorageResult<()> {
-        let serialized_data = serde_json::to_string::<T>(&data).unwrap();
-
-        let serialized_bytes = serialized_data.as_str().as_bytes();
+    /// Writes the serialized data to storage, returns the bytes that were written
+    fn write_file<T: Serialize>(&self, file_path: FileType, data: T) -> StorageResult<Vec<u8>> {
+        let serialized_bytes = serde_json::to_vec::<T>(&data).unwrap();
 
         self.storage
             .lock()
             .unwrap()
-            .write_blob(file_path.as_str().to_string(), serialized_bytes.to_vec())
+            .write_blob(file_path.as_str().to_string(), serialized_bytes.clone())?;
+
+        Ok(serialized_bytes)
+    }
+}
+
+/// CRC32 of the blobs in order, for a snapshot that is not sharded this is the CRC32 of its blob
+fn snapshot_checksum(blobs: &[Vec<u8>]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+
+    for bytes in blobs {
+        hasher.update(bytes);
+    }
+
+    hasher.finalize()
+}
+
+/// Keys of the shards whose blob does not match its checksum
+fn corrupt_shards<'a>(shards: &'a [SnapshotShard], blobs: &[Vec<u8>]) -> Vec<&'a str> {
+    shards
+        .iter()
+        .zip(blobs)
+        .filter(|(shard, bytes)| crc32fast::hash(bytes) != shard.checksum)
+        .map(|(shard, _)| shard.key.as_str())
+        .collect()
+}
+
+fn deserialize_snapshot(blobs: &[Vec<u8>]) -> Result<Vec<PersonVersion>, serde_json::Error> {
+    let mut versions = vec![];
+
+    for bytes in blobs {
+        versions.extend(serde_json::from_slice::<Vec<PersonVersion>>(bytes)?);
+    }
+
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_compatibility() {
+        let database = OptionsFingerprint::from_options(&DatabaseOptions::default());
+
+        // Runtime options can differ between the snapshot and the database
+        let snapshot = OptionsFingerprint {
+            threads: database.threads + 1,
+            hot_versions: Some(10),
+            ..database.clone()
+        };
+
+        assert_eq!(database.check_compatible(&snapshot), Ok(()));
+
+        let snapshot = OptionsFingerprint {
+            schema_version: TABLE_SCHEMA_VERSION + 1,
+            ..database.clone()
+        };
+
+        assert_eq!(
+            database.check_compatible(&snapshot),
+            Err(SnapshotCompatibilityError::SchemaVersion {
+                snapshot: TABLE_SCHEMA_VERSION + 1,
+                database: TABLE_SCHEMA_VERSION
+            })
+        );
+
+        // Snapshots of older schemas that only lack fields with defaults are restored
+        let snapshot = OptionsFingerprint {
+            schema_version: MIN_TABLE_SCHEMA_VERSION,
+            ..database.clone()
+        };
+
+        assert_eq!(database.check_compatible(&snapshot), Ok(()));
+
+        let snapshot = OptionsFingerprint {
+            format_version: SNAPSHOT_FORMAT_VERSION + 1,
+            ..database.clone()
+        };
+
+        assert!(matches!(
+            database.check_compatible(&snapshot),
+            Err(SnapshotCompatibilityError::FormatVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn refuses_unknown_or_disabled_features() {
+        use std::path::PathBuf;
+
+        use uuid::Uuid;
+
+        use crate::persistence::{
+            storage::file::{FileOptions, FileStorage},
+            transaction::TransactionWriteMode,
+        };
+
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let mut storage =
+            FileStorage::new(FileOptions::new(database_dir), TransactionWriteMode::Off);
+        storage.init().unwrap();
+
+        let storage: Arc<Mutex<dyn Storage + Sync + Send>> = Arc::new(Mutex::new(storage));
+
+        let manager = |options: &DatabaseOptions| {
+            SnapshotManager::new(
+                storage.clone(),
+                OptionsFingerprint::from_options(options),
+                None,
+                1,
+                false,
+                None,
+                None,
+            )
+        };
+
+        // Given a directory written with cold versions
+        let cold = manager(&DatabaseOptions::default().set_hot_versions(2));
+        cold.record_features().unwrap();
+
+        let recorded = BTreeSet::from([StorageFeature::ColdVersions.as_str().to_string()]);
+        assert_eq!(cold.check_features().unwrap(), Ok(recorded.clone()));
+
+        // Then a database without cold versions refuses to open it, and does not drop the recorded feature
+        let default = manager(&DatabaseOptions::default());
+
+        assert_eq!(
+            default.check_features().unwrap(),
+            Err(StorageFeatureError::NotEnabled("cold-versions".to_string()))
+        );
+
+        default.record_features().unwrap();
+        assert_eq!(default.recorded_features().unwrap(), recorded);
+
+        // Then a feature recorded by a newer build is refused as unsupported
+        cold.write_file(
+            FileType::Metadata,
+            Metadata {
+                features: BTreeSet::from(["compression".to_string()]),
+                ..Metadata::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            cold.check_features().unwrap(),
+            Err(StorageFeatureError::Unsupported("compression".to_string()))
+        );
     }
 }
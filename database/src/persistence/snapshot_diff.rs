@@ -0,0 +1,270 @@
+use std::{cmp::Ordering, iter::Peekable, str::FromStr, vec};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{consts::consts::EntityId, database::table::row::PersonVersion, model::person::Person};
+
+/// A side of a snapshot diff, see `RequestManager::send_snapshot_diff_request`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffSource {
+    /// A promoted snapshot by its key, the keys are listed by the `system.snapshots` table
+    Snapshot(String),
+    /// The table as of the latest transaction
+    Live,
+}
+
+/// `live` is the live table, anything else is a snapshot key
+impl FromStr for DiffSource {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "live" => DiffSource::Live,
+            key => DiffSource::Snapshot(key.to_string()),
+        })
+    }
+}
+
+/// A field of a person that differs between the two sides, nested fields are named by their path (e.g.
+/// `address.city`). Missing values are null
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// A person that differs between the two sides of a diff
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum EntityDiff {
+    Added {
+        person: Person,
+    },
+    Removed {
+        person: Person,
+    },
+    Changed {
+        id: EntityId,
+        fields: Vec<FieldChange>,
+    },
+}
+
+impl EntityDiff {
+    pub fn id(&self) -> &EntityId {
+        match self {
+            EntityDiff::Added { person } | EntityDiff::Removed { person } => &person.id,
+            EntityDiff::Changed { id, .. } => id,
+        }
+    }
+
+    /// The diff as a line of newline delimited JSON, including the trailing newline
+    pub fn to_ndjson_line(&self) -> String {
+        format!(
+            "{}\n",
+            serde_json::to_string(self).expect("Diffs should always serialize")
+        )
+    }
+}
+
+/// The fields that differ between the two versions of a person, empty if they are the same
+pub fn field_changes(before: &Person, after: &Person) -> Vec<FieldChange> {
+    let address = |person: &Person| person.address.clone().unwrap_or_default();
+    let (before_address, after_address) = (address(before), address(after));
+
+    [
+        ("full_name", json!(before.full_name), json!(after.full_name)),
+        ("email", json!(before.email), json!(after.email)),
+        (
+            "address.street",
+            json!(before_address.street),
+            json!(after_address.street),
+        ),
+        (
+            "address.city",
+            json!(before_address.city),
+            json!(after_address.city),
+        ),
+        (
+            "address.country",
+            json!(before_address.country),
+            json!(after_address.country),
+        ),
+        (
+            "phone_numbers",
+            json!(before.phone_numbers),
+            json!(after.phone_numbers),
+        ),
+        (
+            "attachments",
+            json!(before.attachments),
+            json!(after.attachments),
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, before, after)| before != after)
+    .map(|(field, before, after)| FieldChange {
+        field: field.to_string(),
+        before,
+        after,
+    })
+    .collect()
+}
+
+/// The people that were added, removed or changed between two sets of versions, in id order. Deleted versions are
+/// treated as missing people
+///
+/// Both sides are held in memory (they are read from single blobs), the diffs are only computed as they are read
+/// so a large diff can be written out without collecting it
+pub struct SnapshotDiff {
+    before: Peekable<vec::IntoIter<Person>>,
+    after: Peekable<vec::IntoIter<Person>>,
+}
+
+impl SnapshotDiff {
+    pub fn new(before: Vec<PersonVersion>, after: Vec<PersonVersion>) -> Self {
+        Self {
+            before: people_by_id(before).into_iter().peekable(),
+            after: people_by_id(after).into_iter().peekable(),
+        }
+    }
+}
+
+fn people_by_id(versions: Vec<PersonVersion>) -> Vec<Person> {
+    let mut people: Vec<Person> = versions
+        .into_iter()
+        .filter_map(|version| version.get_person())
+        .collect();
+
+    people.sort_by(|a, b| a.id.cmp(&b.id));
+
+    people
+}
+
+impl Iterator for SnapshotDiff {
+    type Item = EntityDiff;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ordering = match (self.before.peek(), self.after.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(before), Some(after)) => before.id.cmp(&after.id),
+            };
+
+            match ordering {
+                Ordering::Less => {
+                    return self
+                        .before
+                        .next()
+                        .map(|person| EntityDiff::Removed { person })
+                }
+                Ordering::Greater => {
+                    return self.after.next().map(|person| EntityDiff::Added { person })
+                }
+                Ordering::Equal => {
+                    let (before, after) = (self.before.next()?, self.after.next()?);
+                    let fields = field_changes(&before, &after);
+
+                    if !fields.is_empty() {
+                        return Some(EntityDiff::Changed {
+                            id: after.id,
+                            fields,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        consts::consts::{TransactionId, VersionId},
+        database::table::row::PersonVersionState,
+        model::person::Address,
+    };
+
+    use super::*;
+
+    fn version(person: &Person) -> PersonVersion {
+        PersonVersion {
+            id: person.id.clone(),
+            state: PersonVersionState::State(person.clone()),
+            version: VersionId(1),
+            transaction_id: TransactionId(1),
+            lineage: None,
+        }
+    }
+
+    fn person(id: &str, full_name: &str) -> Person {
+        Person {
+            id: EntityId(id.to_string()),
+            ..Person::new(full_name.to_string(), None)
+        }
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_people_in_id_order() {
+        let unchanged = person("a", "Unchanged");
+        let removed = person("b", "Removed");
+        let changed = person("c", "Before");
+        let added = person("d", "Added");
+
+        let changed_after = Person {
+            email: Some("after@x.com".to_string()),
+            address: Some(Address {
+                street: None,
+                city: Some("Sydney".to_string()),
+                country: None,
+            }),
+            ..changed.clone()
+        };
+
+        let deleted = PersonVersion {
+            state: PersonVersionState::Delete,
+            ..version(&person("e", "Deleted"))
+        };
+
+        let diffs: Vec<EntityDiff> = SnapshotDiff::new(
+            vec![version(&changed), version(&unchanged), version(&removed)],
+            vec![
+                version(&added),
+                version(&changed_after),
+                version(&unchanged),
+                deleted,
+            ],
+        )
+        .collect();
+
+        assert_eq!(
+            diffs,
+            vec![
+                EntityDiff::Removed { person: removed },
+                EntityDiff::Changed {
+                    id: changed.id.clone(),
+                    fields: vec![
+                        FieldChange {
+                            field: "email".to_string(),
+                            before: Value::Null,
+                            after: json!("after@x.com"),
+                        },
+                        FieldChange {
+                            field: "address.city".to_string(),
+                            before: Value::Null,
+                            after: json!("Sydney"),
+                        },
+                    ],
+                },
+                EntityDiff::Added { person: added },
+            ]
+        );
+
+        assert!(diffs[0]
+            .to_ndjson_line()
+            .starts_with(r#"{"change":"removed","person":{"id":"b""#));
+    }
+}
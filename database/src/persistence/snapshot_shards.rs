@@ -0,0 +1,263 @@
+use std::{thread, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::{shard::ShardMap, table::row::PersonVersion};
+
+use super::storage::{Storage, StorageResult};
+
+/// Wait before the first retry of the failed uploads, doubled for every retry after
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Splits snapshots into shards that are uploaded concurrently, see `DatabaseOptions::set_snapshot_sharding`.
+/// Object stores (e.g. S3) limit the bandwidth of a single upload, so a snapshot written as several blobs at once
+/// is written far quicker than as one
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotSharding {
+    /// Rows are placed in a shard by consistent hashing of their id, so a row is kept in the same shard by every
+    /// snapshot and changing the number of shards only moves the rows of the shards that were added or removed
+    pub shards: usize,
+    /// Number of shards uploaded at once
+    pub parallelism: usize,
+    /// Number of times the uploads that failed are retried before the snapshot fails
+    pub retries: usize,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl SnapshotSharding {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards,
+            parallelism: 4,
+            retries: 3,
+        }
+    }
+
+    pub fn set_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    pub fn set_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Splits the versions into `shards` shards, a shard may be empty
+    pub fn split(&self, versions: Vec<PersonVersion>) -> Vec<Vec<PersonVersion>> {
+        let mut map = ShardMap::default();
+
+        for shard in 0..self.shards {
+            map.add_shard(&shard.to_string());
+        }
+
+        let mut shards = vec![vec![]; self.shards];
+
+        for version in versions {
+            let shard: usize = map
+                .owner(&version.id)
+                .expect("There should always be at least one shard")
+                .parse()
+                .expect("Shards should be named by their index");
+
+            shards[shard].push(version);
+        }
+
+        shards
+    }
+
+    /// Uploads the blobs, the uploads that failed are retried with a backoff. Returns the last error if an upload
+    /// still fails once the retries are exhausted
+    pub fn upload(
+        &self,
+        storage: &dyn Storage,
+        blobs: Vec<(String, Vec<u8>)>,
+    ) -> StorageResult<()> {
+        let mut pending = blobs;
+        let mut attempt = 0;
+
+        loop {
+            let results = storage.write_blobs(pending.clone(), self.parallelism);
+
+            let mut error = None;
+
+            pending = pending
+                .into_iter()
+                .zip(results)
+                .filter_map(|(blob, result)| match result {
+                    Ok(()) => None,
+                    Err(e) => {
+                        error = Some(e);
+                        Some(blob)
+                    }
+                })
+                .collect();
+
+            match error {
+                None => return Ok(()),
+                Some(e) if attempt >= self.retries => return Err(e),
+                Some(e) => {
+                    log::warn!(
+                        "Unable to upload {} snapshot shards, retrying: {}",
+                        pending.len(),
+                        e
+                    );
+
+                    thread::sleep(RETRY_BACKOFF * 2u32.pow(attempt as u32));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A shard of a promoted snapshot, see `SnapshotRecord::shards`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotShard {
+    pub key: String,
+    pub checksum: u32,
+    pub record_count: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use crate::{
+        consts::consts::{TransactionId, VersionId},
+        database::table::row::PersonVersionState,
+        model::person::Person,
+        persistence::storage::{ReadBlobState, StorageError},
+    };
+
+    use super::*;
+
+    /// Fails the first `failures` writes of every blob
+    struct FlakyStorage {
+        failures: usize,
+        attempts: Mutex<HashMap<String, usize>>,
+    }
+
+    impl Storage for FlakyStorage {
+        fn init(&mut self) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn reset_database(&mut self) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn write_blob(&self, path: String, _: Vec<u8>) -> StorageResult<()> {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(path).or_default();
+
+            *attempt += 1;
+
+            match *attempt > self.failures {
+                true => Ok(()),
+                false => Err(StorageError::UnableToWriteBlob(anyhow::anyhow!("flaky"))),
+            }
+        }
+
+        fn read_blob(&self, _: String) -> StorageResult<ReadBlobState> {
+            Ok(ReadBlobState::NotFound)
+        }
+
+        fn transaction_write(&mut self, _: &[u8]) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn transaction_sync(&self) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn transaction_flush(&mut self) -> StorageResult<()> {
+            Ok(())
+        }
+
+        fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+            Ok(vec![])
+        }
+    }
+
+    fn versions(count: usize) -> Vec<PersonVersion> {
+        (0..count)
+            .map(|index| {
+                let person = Person::new(index.to_string(), None);
+
+                PersonVersion {
+                    id: person.id.clone(),
+                    state: PersonVersionState::State(person),
+                    version: VersionId(1),
+                    transaction_id: TransactionId(index + 1),
+                    lineage: None,
+                }
+            })
+            .collect()
+    }
+
+    fn shard_of(shards: &[Vec<PersonVersion>]) -> HashMap<String, usize> {
+        shards
+            .iter()
+            .enumerate()
+            .flat_map(|(shard, versions)| {
+                versions
+                    .iter()
+                    .map(move |version| (version.id.0.clone(), shard))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn adding_a_shard_moves_few_rows() {
+        let versions = versions(1000);
+
+        let four = SnapshotSharding::new(4).split(versions.clone());
+        let five = SnapshotSharding::new(5).split(versions);
+
+        assert_eq!(four.len(), 4);
+        assert_eq!(four.iter().map(Vec::len).sum::<usize>(), 1000);
+        assert!(four.iter().all(|shard| !shard.is_empty()));
+
+        // Rows only move to the added shard, roughly a fifth of them
+        let (before, after) = (shard_of(&four), shard_of(&five));
+        let moved: Vec<&usize> = before
+            .iter()
+            .filter(|(id, shard)| after[*id] != **shard)
+            .map(|(id, _)| &after[id])
+            .collect();
+
+        assert!(moved.iter().all(|shard| **shard == 4));
+        assert!(moved.len() < 400);
+    }
+
+    #[test]
+    fn failed_uploads_are_retried() {
+        let blobs: Vec<(String, Vec<u8>)> = (0..3)
+            .map(|index| (format!("shard-{}", index), vec![]))
+            .collect();
+
+        let storage = FlakyStorage {
+            failures: 2,
+            attempts: Mutex::new(HashMap::new()),
+        };
+
+        assert!(SnapshotSharding::new(3)
+            .set_retries(1)
+            .upload(&storage, blobs.clone())
+            .is_err());
+
+        // Each blob has failed twice, the next attempts succeed
+        SnapshotSharding::new(3)
+            .set_retries(1)
+            .upload(&storage, blobs)
+            .unwrap();
+
+        assert!(storage
+            .attempts
+            .lock()
+            .unwrap()
+            .values()
+            .all(|attempts| *attempts == 3));
+    }
+}
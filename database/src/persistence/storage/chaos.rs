@@ -0,0 +1,128 @@
+use crate::database::chaos::roll;
+
+use super::{ReadBlobState, Storage, StorageError, StorageResult};
+
+/// Wraps a storage engine and fails calls at random before they reach it, see `ChaosOptions`
+///
+/// Control plane calls (init, reset) are never failed, the database cannot start without them
+pub struct ChaosStorage<S: Storage> {
+    storage: S,
+    error_probability: f64,
+}
+
+impl<S: Storage> ChaosStorage<S> {
+    pub fn new(storage: S, error_probability: f64) -> Self {
+        Self {
+            storage,
+            error_probability,
+        }
+    }
+
+    fn inject(&self, operation: &str) -> Result<(), anyhow::Error> {
+        match roll(self.error_probability) {
+            true => {
+                log::warn!("Chaos: failing storage call {}", operation);
+                Err(anyhow::anyhow!("Chaos: injected {} failure", operation))
+            }
+            false => Ok(()),
+        }
+    }
+}
+
+impl<S: Storage> Storage for ChaosStorage<S> {
+    fn init(&mut self) -> StorageResult<()> {
+        self.storage.init()
+    }
+
+    fn reset_database(&mut self) -> StorageResult<()> {
+        self.storage.reset_database()
+    }
+
+    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+        self.inject("write_blob")
+            .map_err(StorageError::UnableToWriteBlob)?;
+        self.storage.write_blob(path, bytes)
+    }
+
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        self.inject("read_blob")
+            .map_err(StorageError::UnableToReadBlob)?;
+        self.storage.read_blob(path)
+    }
+
+    fn delete_blob(&self, path: String) -> StorageResult<()> {
+        self.inject("delete_blob")
+            .map_err(StorageError::UnableToDeleteBlob)?;
+        self.storage.delete_blob(path)
+    }
+
+    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+        self.inject("transaction_write")
+            .map_err(StorageError::UnableToWriteTransaction)?;
+        self.storage.transaction_write(transaction)
+    }
+
+    fn transaction_write_batch(&mut self, transactions: &[Vec<u8>]) -> StorageResult<()> {
+        self.inject("transaction_write_batch")
+            .map_err(StorageError::UnableToWriteTransaction)?;
+        self.storage.transaction_write_batch(transactions)
+    }
+
+    fn transaction_sync(&self) -> StorageResult<()> {
+        self.inject("transaction_sync")
+            .map_err(StorageError::UnableToSyncTransactionBufferToPersistentStorage)?;
+        self.storage.transaction_sync()
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        self.inject("transaction_flush")
+            .map_err(StorageError::UnableToDeleteTransactionLog)?;
+        self.storage.transaction_flush()
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        self.inject("transaction_load")
+            .map_err(StorageError::UnableToLoadPreviousTransactions)?;
+        self.storage.transaction_load()
+    }
+
+    fn transaction_compact(&mut self, retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        self.inject("transaction_compact")
+            .map_err(StorageError::UnableToCompactTransactionLog)?;
+        self.storage.transaction_compact(retain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::persistence::{
+        storage::file::{FileOptions, FileStorage},
+        transaction::TransactionWriteMode,
+    };
+
+    use super::*;
+
+    #[test]
+    fn injects_storage_errors() {
+        let path: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let file_storage =
+            || FileStorage::new(FileOptions::new(path.clone()), TransactionWriteMode::Off);
+
+        let mut failing = ChaosStorage::new(file_storage(), 1.0);
+        failing.init().unwrap();
+        assert!(failing.write_blob("blob".to_string(), vec![1]).is_err());
+        assert!(failing.transaction_write(b"{}").is_err());
+
+        let mut passing = ChaosStorage::new(file_storage(), 0.0);
+        passing.init().unwrap();
+        passing.write_blob("blob".to_string(), vec![1]).unwrap();
+        passing.transaction_write(b"{}").unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use std::{fmt, sync::Arc};
+
+use super::Storage;
+
+type OpenStorageFn = Arc<dyn Fn(Option<&str>) -> Box<dyn Storage + Sync + Send> + Send + Sync>;
+
+/// A storage engine implemented outside of the crate, see `StorageEngine::Custom`. `open` is called with the
+/// engine's namespace every time the database opens its storage, e.g. on start, for a restore from backup or a
+/// storage migration
+#[derive(Clone)]
+pub struct CustomStorage {
+    /// Shown in the database's info, e.g. `Custom(in-memory)`
+    pub name: String,
+    open: OpenStorageFn,
+    namespace: Option<String>,
+}
+
+impl CustomStorage {
+    pub fn new(
+        name: String,
+        open: impl Fn(Option<&str>) -> Box<dyn Storage + Sync + Send> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            open: Arc::new(open),
+            namespace: None,
+        }
+    }
+
+    /// Passed to `open`, the engine is expected to scope its blobs and WAL to it
+    pub fn set_namespace(mut self, namespace: String) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub(crate) fn open(&self) -> Box<dyn Storage + Sync + Send> {
+        (self.open)(self.get_namespace())
+    }
+}
+
+impl fmt::Debug for CustomStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomStorage")
+            .field("name", &self.name)
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
@@ -12,7 +12,10 @@ use chrono::Utc;
 use tokio::sync::mpsc::{self};
 
 use super::{
-    network::{start_runtime, NetworkStorage, NetworkStorageAction},
+    network::{
+        start_runtime, NetworkStorage, NetworkStorageAction, StorageLatency, StorageTimeouts,
+    },
+    secret::load_aws_config,
     ReadBlobState, Storage, StorageError, StorageResult,
 };
 
@@ -31,15 +34,23 @@ pub struct DynamoDBStorage {
 }
 
 impl DynamoDBStorage {
-    pub fn new(options: DynamoOptions) -> Self {
+    pub fn new(
+        options: DynamoOptions,
+        timeouts: StorageTimeouts,
+        latency: Arc<StorageLatency>,
+    ) -> Self {
         let (action_sender, action_receiver) = mpsc::channel::<NetworkStorageAction>(16);
 
-        start_runtime(action_receiver, options, task_fn, client_fn);
+        let runtime = start_runtime(
+            action_receiver,
+            timeouts.clone(),
+            options,
+            task_fn,
+            client_fn,
+        );
 
         Self {
-            network_storage: NetworkStorage {
-                action_sender: action_sender,
-            },
+            network_storage: NetworkStorage::new(action_sender, runtime, timeouts, latency),
         }
     }
 }
@@ -47,28 +58,58 @@ impl DynamoDBStorage {
 #[derive(Clone, Debug)]
 pub struct DynamoOptions {
     pub table: String,
+    /// AWS profile used for credentials, see `load_aws_config`
+    pub profile: Option<String>,
     base_path: PathBuf,
+    namespace: Option<String>,
 }
 
 impl DynamoOptions {
     pub fn new(table: String) -> Self {
         Self {
             base_path: PathBuf::from("data"),
+            profile: None,
+            namespace: None,
             table,
         }
     }
 
+    pub fn set_profile(mut self, profile: String) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Stores the database in the `<namespace>#Blob` and `<namespace>#transaction_log` partitions, so databases
+    /// can share the table. A reset only deletes the namespace's partitions
+    pub fn set_namespace(mut self, namespace: String) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    fn get_partition(&self, partition: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}#{}", namespace, partition),
+            None => partition.to_string(),
+        }
+    }
+
     pub fn new_test() -> Self {
         Self {
             base_path: PathBuf::from("data"),
+            profile: None,
+            namespace: None,
             table: "lineagedb-ddb".to_string(),
         }
     }
 }
 
-fn client_fn(_options: DynamoOptions) -> Pin<Box<dyn Future<Output = Client> + Send + 'static>> {
-    Box::pin(async {
-        let sdk = aws_config::load_from_env().await;
+fn client_fn(options: DynamoOptions) -> Pin<Box<dyn Future<Output = Client> + Send + 'static>> {
+    Box::pin(async move {
+        let sdk = load_aws_config(options.profile).await;
 
         Client::new(&sdk)
     })
@@ -88,6 +129,14 @@ impl Storage for DynamoDBStorage {
         self.network_storage.write_blob(path, bytes)
     }
 
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        self.network_storage.write_blobs(blobs, parallelism)
+    }
+
     fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
         self.network_storage.read_blob(path)
     }
@@ -117,6 +166,8 @@ fn task_fn(
     Box::pin(async move {
         let table_str = &data.table;
         let base_path = &data.base_path;
+        let blob_partition = data.get_partition(BLOB_PARTITION);
+        let transaction_partition = data.get_partition(TRANSACTION_LOG_PATH);
 
         match action {
             NetworkStorageAction::Init(r) => {
@@ -171,7 +222,16 @@ fn task_fn(
                 let _ = r.send(response).unwrap();
             }
             NetworkStorageAction::Reset(r) => {
-                let result = reset_table(&client, table_str).await;
+                let mut result = Ok(());
+
+                // Only the database's own partitions are deleted, other namespaces may share the table
+                for partition in [&transaction_partition, &blob_partition] {
+                    result = delete_items_at_partition(&client, table_str, partition).await;
+
+                    if result.is_err() {
+                        break;
+                    }
+                }
 
                 let _ = r.sender.send(result).unwrap();
             }
@@ -181,7 +241,7 @@ fn task_fn(
                 let req = client
                     .put_item()
                     .table_name(table_str)
-                    .item(HASH_KEY, AttributeValue::S(BLOB_PARTITION.to_string()))
+                    .item(HASH_KEY, AttributeValue::S(blob_partition))
                     .item(
                         SORT_KEY,
                         AttributeValue::S(file_path.to_str().unwrap().to_string()),
@@ -205,7 +265,7 @@ fn task_fn(
                 let request = client
                     .get_item()
                     .table_name(table_str)
-                    .key(HASH_KEY, AttributeValue::S(BLOB_PARTITION.to_string()))
+                    .key(HASH_KEY, AttributeValue::S(blob_partition))
                     .key(
                         SORT_KEY,
                         AttributeValue::S(file_path.to_str().unwrap().to_string()),
@@ -234,10 +294,7 @@ fn task_fn(
                 let req = client
                     .put_item()
                     .table_name(table_str)
-                    .item(
-                        HASH_KEY,
-                        AttributeValue::S(TRANSACTION_LOG_PATH.to_string()),
-                    )
+                    .item(HASH_KEY, AttributeValue::S(transaction_partition))
                     .item(SORT_KEY, AttributeValue::S(Utc::now().to_rfc3339()))
                     .item(
                         DATA_KEY,
@@ -254,14 +311,13 @@ fn task_fn(
             }
             NetworkStorageAction::TransactionFlush(r) => {
                 let response =
-                    delete_transactions_at_partition(&client, table_str, TRANSACTION_LOG_PATH)
-                        .await;
+                    delete_items_at_partition(&client, table_str, &transaction_partition).await;
 
                 let _ = r.send(response).unwrap();
             }
             NetworkStorageAction::TransactionLoad(request) => {
                 let contents =
-                    get_transactions_at_partition(&client, table_str, TRANSACTION_LOG_PATH).await;
+                    get_transactions_at_partition(&client, table_str, &transaction_partition).await;
 
                 let _ = request.send(contents).unwrap();
             }
@@ -269,32 +325,6 @@ fn task_fn(
     })
 }
 
-async fn reset_table(client: &Client, table_name: &str) -> StorageResult<()> {
-    let mut response = client.scan().table_name(table_name).into_paginator().send();
-
-    while let Some(result) = response.next().await {
-        match result {
-            Ok(output) => {
-                for item in output.items() {
-                    client
-                        .delete_item()
-                        .table_name(table_name)
-                        .key(HASH_KEY.to_string(), item.get(HASH_KEY).unwrap().clone())
-                        .key(SORT_KEY.to_string(), item.get(SORT_KEY).unwrap().clone())
-                        .send()
-                        .await
-                        .map_err(|e| StorageError::UnableToDeleteTransactionLog(anyhow!(e)))?;
-                }
-            }
-            Err(err) => {
-                eprintln!("{err:?}")
-            }
-        }
-    }
-
-    Ok(())
-}
-
 async fn get_transactions_at_partition(
     client: &Client,
     table: &str,
@@ -330,7 +360,7 @@ async fn get_transactions_at_partition(
     Ok(contents)
 }
 
-async fn delete_transactions_at_partition(
+async fn delete_items_at_partition(
     client: &Client,
     table: &str,
     partition: &str,
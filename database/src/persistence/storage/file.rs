@@ -1,60 +1,365 @@
 use std::{
+    fmt,
     fs::{self, File, OpenOptions},
-    io::{Read, Write},
-    path::PathBuf,
+    io::{self, IoSlice, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
+use crate::persistence::transaction::{TransactionFileWriteMode, TransactionWriteMode};
+
 use super::{io_to_generic_error, ReadBlobState, Storage, StorageError, StorageResult};
 
 pub struct FileStorage {
-    base_path: PathBuf,
-    log_file: File,
-    transaction_file_path: PathBuf,
+    options: FileOptions,
+    write_mode: TransactionWriteMode,
+    /// One log file per WAL directory, the first is the primary and is used for restores
+    log_files: Vec<File>,
 }
 
 const JSON_DELIMITER: &str = "\n";
 
-impl FileStorage {
-    pub fn new(base_path: PathBuf) -> Self {
-        let transaction_file_path = base_path.join("transaction_log.json");
+const TRANSACTION_LOG_FILE: &str = "transaction_log.json";
+
+/// The compacted log is written here first, then renamed over the log so a crash never leaves a partial log
+const COMPACT_TRANSACTION_LOG_FILE: &str = "transaction_log.json.compact";
+
+/// Until multi-table support lands all table blobs belong to the person table
+const DEFAULT_TABLE: &str = "person";
+
+/// Blobs that describe the whole database rather than a single table, these always live at the
+/// root of the snapshot directory
+const DATABASE_BLOBS: [&str; 1] = ["metadata"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileLayout {
+    /// All blobs are written to the root of the snapshot directory
+    Flat,
+    /// Table blobs are written to a directory per table, e.g. `<snapshot_dir>/person/snapshot`
+    DirectoryPerTable,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    pub base_dir: PathBuf,
+    snapshot_dir: Option<PathBuf>,
+    wal_dirs: Vec<PathBuf>,
+    layout: FileLayout,
+    namespace: Option<String>,
+}
+
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl FileOptions {
+    pub fn new(base_dir: PathBuf) -> Self {
+        Self {
+            base_dir,
+            snapshot_dir: None,
+            wal_dirs: vec![],
+            layout: FileLayout::Flat,
+            namespace: None,
+        }
+    }
+
+    /// Where snapshot blobs (world state, metadata) are stored, defaults to the base directory
+    pub fn set_snapshot_dir(mut self, snapshot_dir: PathBuf) -> Self {
+        self.snapshot_dir = Some(snapshot_dir);
+        self
+    }
+
+    /// Where the WAL is stored, defaults to the base directory. When multiple directories are provided
+    /// the WAL is mirrored to each of them, the first directory is used when restoring
+    pub fn set_wal_dirs(mut self, wal_dirs: Vec<PathBuf>) -> Self {
+        self.wal_dirs = wal_dirs;
+        self
+    }
+
+    pub fn set_layout(mut self, layout: FileLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Stores the database in a `<namespace>` subdirectory of each directory (base, snapshot and WAL), so
+    /// databases can share the directories. A reset only deletes the subdirectories
+    pub fn set_namespace(mut self, namespace: String) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub fn get_snapshot_dir(&self) -> PathBuf {
+        self.namespaced(self.snapshot_dir.as_ref().unwrap_or(&self.base_dir))
+    }
+
+    pub fn get_wal_dirs(&self) -> Vec<PathBuf> {
+        match self.wal_dirs.is_empty() {
+            true => vec![self.namespaced(&self.base_dir)],
+            false => self
+                .wal_dirs
+                .iter()
+                .map(|dir| self.namespaced(dir))
+                .collect(),
+        }
+    }
+
+    fn namespaced(&self, dir: &Path) -> PathBuf {
+        match &self.namespace {
+            Some(namespace) => dir.join(namespace),
+            None => dir.to_path_buf(),
+        }
+    }
+
+    pub fn get_layout(&self) -> &FileLayout {
+        &self.layout
+    }
+
+    /// Every directory the storage engine reads / writes to
+    fn get_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.namespaced(&self.base_dir), self.get_snapshot_dir()];
+
+        dirs.extend(self.get_wal_dirs());
+        dirs.sort();
+        dirs.dedup();
+
+        dirs
+    }
+
+    fn get_transaction_file_paths(&self) -> Vec<PathBuf> {
+        self.get_wal_dirs()
+            .iter()
+            .map(|wal_dir| wal_dir.join(TRANSACTION_LOG_FILE))
+            .collect()
+    }
+}
+
+/// Opens a WAL file for appending, when using `DSync` the file is opened with O_DSYNC so that each
+/// write is durable once it returns
+fn open_log_file(
+    path: &Path,
+    create_new: bool,
+    write_mode: &TransactionWriteMode,
+) -> io::Result<File> {
+    let mut open_options = OpenOptions::new();
+
+    open_options
+        .create(!create_new)
+        .create_new(create_new)
+        .append(true);
+
+    #[cfg(unix)]
+    if let TransactionWriteMode::File(TransactionFileWriteMode::DSync) = write_mode {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        open_options.custom_flags(libc::O_DSYNC);
+    }
+
+    // O_DSYNC is not available, fall back to the OS buffered writes
+    #[cfg(not(unix))]
+    let _ = write_mode;
+
+    open_options.open(path)
+}
 
+impl FileStorage {
+    pub fn new(options: FileOptions, write_mode: TransactionWriteMode) -> Self {
         // TODO: This is duplicated from the init function
         //  should this be refactored into a common function?
-        std::fs::create_dir_all(&base_path).expect("Cannot create directory");
+        for dir in options.get_dirs() {
+            std::fs::create_dir_all(&dir).expect("Cannot create directory");
+        }
 
         // NOTE: Reset the log file goes away...
-        let log_file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(transaction_file_path.clone())
-            .expect("Cannot open file");
+        let log_files = options
+            .get_transaction_file_paths()
+            .iter()
+            .map(|transaction_file_path| {
+                open_log_file(transaction_file_path, false, &write_mode).expect("Cannot open file")
+            })
+            .collect();
 
         Self {
-            base_path,
-            log_file,
-            transaction_file_path,
+            options,
+            write_mode,
+            log_files,
+        }
+    }
+
+    /// Opens the storage without creating its directories or WAL files, nothing can be written to it. A WAL
+    /// that does not exist is loaded as empty, see `Persistence::open_read_only`
+    pub fn open_read_only(options: FileOptions, write_mode: TransactionWriteMode) -> Self {
+        Self {
+            options,
+            write_mode,
+            log_files: vec![],
         }
     }
 
     fn get_path(&self, path: &str) -> PathBuf {
-        self.base_path.join(path)
+        let snapshot_dir = self.options.get_snapshot_dir();
+
+        match self.options.get_layout() {
+            FileLayout::DirectoryPerTable if !DATABASE_BLOBS.contains(&path) => {
+                snapshot_dir.join(DEFAULT_TABLE).join(path)
+            }
+            FileLayout::Flat | FileLayout::DirectoryPerTable => snapshot_dir.join(path),
+        }
+    }
+
+    /// Buffered OS write, is not 'durable' without the fsync
+    fn write_log_files(&mut self, buffers: &[&[u8]]) -> StorageResult<()> {
+        for log_file in self.log_files.iter_mut() {
+            write_all_vectored(log_file, buffers)
+                .map_err(|e| StorageError::UnableToWriteTransaction(io_to_generic_error(e)))?;
+        }
+
+        Ok(())
+    }
+
+    fn open_log_files(&mut self, create_new: bool) -> StorageResult<()> {
+        let mut log_files = vec![];
+
+        for transaction_file_path in self.options.get_transaction_file_paths() {
+            let log_file = open_log_file(&transaction_file_path, create_new, &self.write_mode)
+                .map_err(|e| {
+                    StorageError::UnableToCreateNewTransactionLog(io_to_generic_error(e))
+                })?;
+
+            log_files.push(log_file);
+        }
+
+        self.log_files = log_files;
+
+        Ok(())
     }
 }
 
+/// Maximum number of buffers passed to a single `writev`, Linux rejects more than `IOV_MAX` (1024)
+const MAX_VECTORED_BUFFERS: usize = 1024;
+
+/// Writes every buffer in order with as few `writev` calls as possible, a partial write continues from the
+/// first byte that was not written
+fn write_all_vectored(file: &mut File, buffers: &[&[u8]]) -> io::Result<()> {
+    // First buffer that has not been fully written and how much of it has been written
+    let mut index = 0;
+    let mut offset = 0;
+
+    while index < buffers.len() {
+        let slices: Vec<IoSlice> = std::iter::once(IoSlice::new(&buffers[index][offset..]))
+            .chain(
+                buffers[index + 1..]
+                    .iter()
+                    .map(|buffer| IoSlice::new(buffer)),
+            )
+            .take(MAX_VECTORED_BUFFERS)
+            .collect();
+
+        let mut written = match file.write_vectored(&slices) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(written) => written,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        while index < buffers.len() && written >= buffers[index].len() - offset {
+            written -= buffers[index].len() - offset;
+            index += 1;
+            offset = 0;
+        }
+
+        offset += written;
+    }
+
+    Ok(())
+}
+
+/// Writes the retained transactions of a log to a new file, fsyncs it and swaps it in with a rename. Returns the
+/// number of transactions that were dropped
+fn compact_log_file(path: &Path, retain: &dyn Fn(&str) -> bool) -> io::Result<usize> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let compact_path = path.with_file_name(COMPACT_TRANSACTION_LOG_FILE);
+
+    let mut compact_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&compact_path)?;
+
+    let mut dropped = 0;
+
+    for transaction in contents.split(JSON_DELIMITER) {
+        if transaction.is_empty() {
+            continue;
+        }
+
+        if !retain(transaction) {
+            dropped += 1;
+            continue;
+        }
+
+        compact_file.write_all(transaction.as_bytes())?;
+        compact_file.write_all(JSON_DELIMITER.as_bytes())?;
+    }
+
+    compact_file.sync_all()?;
+
+    fs::rename(&compact_path, path)?;
+
+    // The rename is only durable once the directory entry has been synced
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(dropped)
+}
+
 impl Storage for FileStorage {
     fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
         log::debug!("write_blob");
 
+        let blob_path = self.get_path(&path);
+
+        if let Some(parent) = blob_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| StorageError::UnableToWriteBlob(io_to_generic_error(e)))?;
+        }
+
+        // Blobs are written to a temporary file and renamed over the previous blob, a crash mid-write leaves
+        //  the previous blob intact
+        let mut temporary_path = blob_path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
-            .open(self.get_path(&path))
+            .truncate(true)
+            .open(&temporary_path)
             .map_err(|e| StorageError::UnableToWriteBlob(io_to_generic_error(e)))?;
 
         file.write_all(&bytes)
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&temporary_path, &blob_path))
             .map_err(|e| StorageError::UnableToWriteBlob(io_to_generic_error(e)))
     }
 
+    fn delete_blob(&self, path: String) -> StorageResult<()> {
+        log::debug!("delete_blob");
+
+        match fs::remove_file(self.get_path(&path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(StorageError::UnableToDeleteBlob(io_to_generic_error(e)))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
         log::debug!("read_blob");
 
@@ -77,8 +382,10 @@ impl Storage for FileStorage {
     fn init(&mut self) -> StorageResult<()> {
         log::debug!("init");
 
-        std::fs::create_dir_all(&self.base_path)
-            .map_err(|e| StorageError::UnableToInitializePersistence(io_to_generic_error(e)))?;
+        for dir in self.options.get_dirs() {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| StorageError::UnableToInitializePersistence(io_to_generic_error(e)))?;
+        }
 
         Ok(())
     }
@@ -87,41 +394,58 @@ impl Storage for FileStorage {
     fn reset_database(&mut self) -> StorageResult<()> {
         log::debug!("reset_database");
 
-        fs::remove_dir_all(&self.base_path)
-            .map_err(|e| StorageError::UnableToInitializePersistence(io_to_generic_error(e)))?;
-
-        std::fs::create_dir_all(&self.base_path)
-            .map_err(|e| StorageError::UnableToInitializePersistence(io_to_generic_error(e)))?;
+        for dir in self.options.get_dirs() {
+            // Directories can be nested within each other (e.g. wal dir within the base dir)
+            if dir.exists() {
+                fs::remove_dir_all(&dir).map_err(|e| {
+                    StorageError::UnableToInitializePersistence(io_to_generic_error(e))
+                })?;
+            }
+        }
 
-        self.log_file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(self.transaction_file_path.clone())
-            .expect("Cannot open file");
+        for dir in self.options.get_dirs() {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| StorageError::UnableToInitializePersistence(io_to_generic_error(e)))?;
+        }
 
-        Ok(())
+        self.open_log_files(false)
     }
 
     fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
         log::debug!("transaction_write");
 
-        // Buffered OS write, is not 'durable' without the fsync
-        let _ = self
-            .log_file
-            .write(transaction)
-            .map_err(|e| StorageError::UnableToWriteTransaction(io_to_generic_error(e)));
+        self.write_log_files(&[transaction, JSON_DELIMITER.as_bytes()])
+    }
+
+    /// The batch is written with a single vectored write per log file (unless the OS writes it partially)
+    fn transaction_write_batch(&mut self, transactions: &[Vec<u8>]) -> StorageResult<()> {
+        log::debug!("transaction_write_batch");
 
-        self.log_file
-            .write_all(JSON_DELIMITER.as_bytes())
-            .map_err(|e| StorageError::UnableToWriteTransaction(io_to_generic_error(e)))
+        let buffers: Vec<&[u8]> = transactions
+            .iter()
+            .flat_map(|transaction| [transaction.as_slice(), JSON_DELIMITER.as_bytes()])
+            .collect();
+
+        self.write_log_files(&buffers)
     }
 
     fn transaction_sync(&self) -> StorageResult<()> {
         log::debug!("transaction_sync");
 
-        self.log_file.sync_all().map_err(|e| {
-            StorageError::UnableToSyncTransactionBufferToPersistentStorage(io_to_generic_error(e))
-        })?;
+        for log_file in self.log_files.iter() {
+            let sync_result = match &self.write_mode {
+                TransactionWriteMode::File(TransactionFileWriteMode::DataSync) => {
+                    log_file.sync_data()
+                }
+                _ => log_file.sync_all(),
+            };
+
+            sync_result.map_err(|e| {
+                StorageError::UnableToSyncTransactionBufferToPersistentStorage(io_to_generic_error(
+                    e,
+                ))
+            })?;
+        }
 
         Ok(())
     }
@@ -131,16 +455,12 @@ impl Storage for FileStorage {
 
         // TODO: When we are doing a dual reset, this could fail. Add
         //  the unwrap back and think this through
-        let _ = fs::remove_file(self.transaction_file_path.clone())
-            .map_err(|e| StorageError::UnableToDeleteTransactionLog(io_to_generic_error(e)));
-
-        self.log_file = OpenOptions::new()
-            .create_new(true)
-            .append(true)
-            .open(&self.transaction_file_path)
-            .map_err(|e| StorageError::UnableToCreateNewTransactionLog(io_to_generic_error(e)))?;
+        for transaction_file_path in self.options.get_transaction_file_paths() {
+            let _ = fs::remove_file(transaction_file_path)
+                .map_err(|e| StorageError::UnableToDeleteTransactionLog(io_to_generic_error(e)));
+        }
 
-        Ok(())
+        self.open_log_files(true)
     }
 
     // File may or may not exist
@@ -149,10 +469,29 @@ impl Storage for FileStorage {
 
         let mut contents = String::new();
 
-        let mut file = OpenOptions::new()
+        // The first WAL directory is the primary, the others are mirrors
+        let primary_transaction_file_path = self
+            .options
+            .get_transaction_file_paths()
+            .into_iter()
+            .next()
+            .expect("There is always at least one WAL directory");
+
+        let mut file = match OpenOptions::new()
             .read(true)
-            .open(&self.transaction_file_path)
-            .map_err(|e| StorageError::UnableToLoadPreviousTransactions(io_to_generic_error(e)))?;
+            .open(&primary_transaction_file_path)
+        {
+            Ok(file) => file,
+            // Only when opened read-only, otherwise the file is created when the storage is opened
+            Err(e) if e.kind() == io::ErrorKind::NotFound && self.log_files.is_empty() => {
+                return Ok(vec![])
+            }
+            Err(e) => {
+                return Err(StorageError::UnableToLoadPreviousTransactions(
+                    io_to_generic_error(e),
+                ))
+            }
+        };
 
         file.read_to_string(&mut contents)
             .map_err(|e| StorageError::UnableToLoadPreviousTransactions(io_to_generic_error(e)))?;
@@ -169,4 +508,201 @@ impl Storage for FileStorage {
 
         Ok(transactions)
     }
+
+    fn transaction_compact(&mut self, retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        log::debug!("transaction_compact");
+
+        let mut dropped = 0;
+
+        // Each mirror is compacted on its own, the primary is used for the count as it is the one that is restored
+        for (index, transaction_file_path) in
+            self.options.get_transaction_file_paths().iter().enumerate()
+        {
+            let mirror_dropped = compact_log_file(transaction_file_path, retain)
+                .map_err(|e| StorageError::UnableToCompactTransactionLog(io_to_generic_error(e)))?;
+
+            if index == 0 {
+                dropped = mirror_dropped;
+            }
+        }
+
+        // The open handles still point at the files that were replaced
+        self.open_log_files(false)?;
+
+        Ok(dropped)
+    }
+}
+
+/// Result of the durability self-test, describes the commit latency floor imposed by the storage
+#[derive(Debug)]
+pub struct DurabilityReport {
+    pub mode: TransactionFileWriteMode,
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for DurabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[Mode: {:?}, Samples: {}, Min: {}µs, Avg: {}µs, Max: {}µs]",
+            self.mode,
+            self.samples,
+            self.min.as_micros(),
+            self.avg.as_micros(),
+            self.max.as_micros()
+        )
+    }
+}
+
+const DURABILITY_SELF_TEST_FILE: &str = "durability_self_test.tmp";
+
+/// Measures how long a durable WAL write takes in the primary WAL directory. Each sample is a
+/// write followed by the sync that the mode requires, this mimics a single transaction commit.
+pub fn durability_self_test(
+    options: &FileOptions,
+    mode: &TransactionFileWriteMode,
+    samples: usize,
+) -> io::Result<DurabilityReport> {
+    let wal_dir = options
+        .get_wal_dirs()
+        .into_iter()
+        .next()
+        .expect("There is always at least one WAL directory");
+
+    fs::create_dir_all(&wal_dir)?;
+
+    let test_file_path = wal_dir.join(DURABILITY_SELF_TEST_FILE);
+
+    let _ = fs::remove_file(&test_file_path);
+
+    let write_mode = TransactionWriteMode::File(mode.clone());
+
+    let mut file = open_log_file(&test_file_path, true, &write_mode)?;
+
+    let mut durations: Vec<Duration> = Vec::with_capacity(samples);
+
+    for _ in 0..samples {
+        let now = Instant::now();
+
+        file.write_all(JSON_DELIMITER.as_bytes())?;
+
+        match mode {
+            TransactionFileWriteMode::Sync => file.sync_all()?,
+            TransactionFileWriteMode::DataSync => file.sync_data()?,
+            TransactionFileWriteMode::DSync | TransactionFileWriteMode::OSBuffered => {}
+        }
+
+        durations.push(now.elapsed());
+    }
+
+    fs::remove_file(&test_file_path)?;
+
+    let total: Duration = durations.iter().sum();
+
+    Ok(DurabilityReport {
+        mode: mode.clone(),
+        samples,
+        min: durations.iter().min().cloned().unwrap_or_default(),
+        avg: total / samples.max(1) as u32,
+        max: durations.iter().max().cloned().unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn durability_self_test_measures_every_mode() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let options = FileOptions::new(database_dir);
+
+        for mode in [
+            TransactionFileWriteMode::Sync,
+            TransactionFileWriteMode::DataSync,
+            TransactionFileWriteMode::DSync,
+        ] {
+            let report = durability_self_test(&options, &mode, 3).unwrap();
+
+            assert_eq!(report.samples, 3);
+            assert_eq!(report.mode, mode);
+            assert!(report.min <= report.avg && report.avg <= report.max);
+        }
+
+        // Self-test should clean up after itself
+        assert!(!options
+            .get_wal_dirs()
+            .first()
+            .unwrap()
+            .join(DURABILITY_SELF_TEST_FILE)
+            .exists());
+    }
+
+    #[test]
+    fn batched_transactions_load_in_order() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let mut storage = FileStorage::new(
+            FileOptions::new(database_dir),
+            TransactionWriteMode::File(TransactionFileWriteMode::OSBuffered),
+        );
+
+        storage.transaction_write(b"first").unwrap();
+        storage
+            .transaction_write_batch(&[b"second".to_vec(), vec![], b"third".to_vec()])
+            .unwrap();
+
+        assert_eq!(
+            storage.transaction_load().unwrap(),
+            vec!["first", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn reset_is_scoped_to_the_namespace() {
+        let database_dir: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        let namespace = |namespace: &str| {
+            FileStorage::new(
+                FileOptions::new(database_dir.clone()).set_namespace(namespace.to_string()),
+                TransactionWriteMode::File(TransactionFileWriteMode::OSBuffered),
+            )
+        };
+
+        let mut dev = namespace("dev");
+        let mut staging = namespace("staging");
+
+        for storage in [&mut dev, &mut staging] {
+            storage.transaction_write(b"transaction").unwrap();
+            storage
+                .write_blob("metadata".to_string(), b"{}".to_vec())
+                .unwrap();
+        }
+
+        dev.reset_database().unwrap();
+
+        assert!(dev.transaction_load().unwrap().is_empty());
+        assert!(matches!(
+            dev.read_blob("metadata".to_string()).unwrap(),
+            ReadBlobState::NotFound
+        ));
+
+        assert_eq!(staging.transaction_load().unwrap(), vec!["transaction"]);
+        assert!(matches!(
+            staging.read_blob("metadata".to_string()).unwrap(),
+            ReadBlobState::Found(_)
+        ));
+    }
 }
@@ -0,0 +1,263 @@
+use std::sync::{Arc, Mutex};
+
+use super::{ReadBlobState, Storage, StorageEngine, StorageError, StorageResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, strum_macros::Display)]
+pub enum MigrationPhase {
+    /// Writes go to both engines, the target is missing the state written before the migration started (or
+    /// before a failed target write)
+    DualWrite,
+    /// A snapshot was written to both engines and every write since has reached the target, the target holds
+    /// the same state as the current engine
+    Converged,
+    /// Reads and writes only use the target engine
+    CutOver,
+}
+
+struct MigrationState {
+    phase: MigrationPhase,
+    /// Whether a target write failed since the WAL was last flushed, the next snapshot cannot converge
+    target_failed: bool,
+}
+
+/// Moves the database to another storage engine without downtime. Reads use the current engine while every
+/// write is mirrored to the target, once a snapshot has been written to both engines they have converged and
+/// the database can be cut over to the target, see `Control::CutoverMigration`
+pub struct StorageMigration {
+    pub target: StorageEngine,
+    state: Mutex<MigrationState>,
+}
+
+impl StorageMigration {
+    pub fn new(target: StorageEngine) -> Self {
+        Self {
+            target,
+            state: Mutex::new(MigrationState {
+                phase: MigrationPhase::DualWrite,
+                target_failed: false,
+            }),
+        }
+    }
+
+    pub fn phase(&self) -> MigrationPhase {
+        self.state.lock().unwrap().phase
+    }
+
+    /// Switches reads and writes to the target. Only a converged migration can be cut over, the caller must hold
+    /// the storage lock so that no write is halfway through being mirrored
+    pub fn cutover(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.phase {
+            MigrationPhase::Converged => {
+                state.phase = MigrationPhase::CutOver;
+                Ok(())
+            }
+            MigrationPhase::DualWrite => Err(format!(
+                "The {} engine has not converged, snapshot the database first",
+                self.target
+            )),
+            MigrationPhase::CutOver => Err(format!(
+                "The database has already been cut over to the {} engine",
+                self.target
+            )),
+        }
+    }
+
+    fn target_failed(&self, error: StorageError) {
+        let mut state = self.state.lock().unwrap();
+
+        log::warn!(
+            "Migration: write to the {} engine failed, it has to converge again: {:?}",
+            self.target,
+            error
+        );
+
+        state.phase = MigrationPhase::DualWrite;
+        state.target_failed = true;
+    }
+
+    /// The WAL is flushed once a snapshot is written, if every target write since the previous flush succeeded
+    /// the target has the full state
+    fn flushed(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.phase == MigrationPhase::DualWrite && !state.target_failed {
+            log::info!(
+                "Migration: the {} engine has converged, the database can be cut over",
+                self.target
+            );
+
+            state.phase = MigrationPhase::Converged;
+        }
+
+        state.target_failed = false;
+    }
+
+    fn is_cut_over(&self) -> bool {
+        self.phase() == MigrationPhase::CutOver
+    }
+}
+
+pub struct MigrationStorage {
+    current: Box<dyn Storage + Sync + Send>,
+    target: Box<dyn Storage + Sync + Send>,
+    migration: Arc<StorageMigration>,
+}
+
+impl MigrationStorage {
+    pub fn new(
+        current: Box<dyn Storage + Sync + Send>,
+        target: Box<dyn Storage + Sync + Send>,
+        migration: Arc<StorageMigration>,
+    ) -> Self {
+        Self {
+            current,
+            target,
+            migration,
+        }
+    }
+
+    /// Target failures do not fail the write, they only stop the migration from converging
+    fn mirror(&self, result: StorageResult<()>) {
+        if let Err(e) = result {
+            self.migration.target_failed(e);
+        }
+    }
+}
+
+impl Storage for MigrationStorage {
+    fn init(&mut self) -> StorageResult<()> {
+        self.current.init()?;
+        self.target.init()
+    }
+
+    fn reset_database(&mut self) -> StorageResult<()> {
+        if !self.migration.is_cut_over() {
+            self.current.reset_database()?;
+        }
+
+        self.target.reset_database()
+    }
+
+    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+        if self.migration.is_cut_over() {
+            return self.target.write_blob(path, bytes);
+        }
+
+        self.current.write_blob(path.clone(), bytes.clone())?;
+        self.mirror(self.target.write_blob(path, bytes));
+
+        Ok(())
+    }
+
+    /// Blobs that are only read (e.g. cold versions spilled before the migration started) are not a part of the
+    /// snapshot, once cut over they are copied to the target the first time they are read
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        if !self.migration.is_cut_over() {
+            return self.current.read_blob(path);
+        }
+
+        match self.target.read_blob(path.clone())? {
+            ReadBlobState::Found(bytes) => Ok(ReadBlobState::Found(bytes)),
+            ReadBlobState::NotFound => match self.current.read_blob(path.clone())? {
+                ReadBlobState::Found(bytes) => {
+                    self.target.write_blob(path, bytes.clone())?;
+                    Ok(ReadBlobState::Found(bytes))
+                }
+                ReadBlobState::NotFound => Ok(ReadBlobState::NotFound),
+            },
+        }
+    }
+
+    fn delete_blob(&self, path: String) -> StorageResult<()> {
+        if self.migration.is_cut_over() {
+            return self.target.delete_blob(path);
+        }
+
+        self.current.delete_blob(path.clone())?;
+
+        // A blob left behind in the target is never read, it does not stop the migration from converging
+        if let Err(e) = self.target.delete_blob(path) {
+            log::warn!(
+                "Migration: unable to delete blob from the {} engine: {:?}",
+                self.migration.target,
+                e
+            );
+        }
+
+        Ok(())
+    }
+
+    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+        if self.migration.is_cut_over() {
+            return self.target.transaction_write(transaction);
+        }
+
+        self.current.transaction_write(transaction)?;
+        let result = self.target.transaction_write(transaction);
+        self.mirror(result);
+
+        Ok(())
+    }
+
+    fn transaction_write_batch(&mut self, transactions: &[Vec<u8>]) -> StorageResult<()> {
+        if self.migration.is_cut_over() {
+            return self.target.transaction_write_batch(transactions);
+        }
+
+        self.current.transaction_write_batch(transactions)?;
+        let result = self.target.transaction_write_batch(transactions);
+        self.mirror(result);
+
+        Ok(())
+    }
+
+    fn transaction_sync(&self) -> StorageResult<()> {
+        if self.migration.is_cut_over() {
+            return self.target.transaction_sync();
+        }
+
+        self.current.transaction_sync()?;
+        self.mirror(self.target.transaction_sync());
+
+        Ok(())
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        if self.migration.is_cut_over() {
+            return self.target.transaction_flush();
+        }
+
+        self.current.transaction_flush()?;
+
+        match self.target.transaction_flush() {
+            Ok(()) => self.migration.flushed(),
+            Err(e) => self.migration.target_failed(e),
+        }
+
+        Ok(())
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        match self.migration.is_cut_over() {
+            true => self.target.transaction_load(),
+            false => self.current.transaction_load(),
+        }
+    }
+
+    fn transaction_compact(&mut self, retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        if self.migration.is_cut_over() {
+            return self.target.transaction_compact(retain);
+        }
+
+        let dropped = self.current.transaction_compact(retain)?;
+
+        // Covered transactions left in the target WAL would be replayed on top of the snapshot
+        if let Err(e) = self.target.transaction_compact(retain) {
+            self.migration.target_failed(e);
+        }
+
+        Ok(dropped)
+    }
+}
@@ -1,22 +1,42 @@
 use std::{
     fs, io,
-    path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+use custom::CustomStorage;
+#[cfg(feature = "dynamodb")]
 use dynamodb::{DynamoDBStorage, DynamoOptions};
-use file::FileStorage;
+use file::{FileOptions, FileStorage};
+use migration::{MigrationStorage, StorageMigration};
+use network::{StorageLatency, StorageOperation};
+#[cfg(feature = "postgres")]
 use postgres::{PgStorage, PostgresOptions};
+use read_only::ReadOnlyStorage;
+#[cfg(feature = "s3")]
+use replication::ReplicatedStorage;
+use replication::StorageReplication;
+#[cfg(feature = "s3")]
 use s3::{S3Options, S3Storage};
 use thiserror::Error;
 
 use crate::database::options::DatabaseOptions;
 
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod custom;
+#[cfg(feature = "dynamodb")]
 pub mod dynamodb;
 pub mod file;
+pub mod migration;
 pub mod network;
+#[cfg(feature = "postgres")]
 pub mod postgres;
+pub mod read_only;
+pub mod replication;
+#[cfg(feature = "s3")]
 pub mod s3;
+pub mod secret;
 
 // Our use of anyhow is because each storage provider will return a different error type
 //  this means we cannot just standardize on something like say IO Error.
@@ -39,6 +59,9 @@ pub enum StorageError {
     #[error("No pervious save state found")]
     UnableToReadBlob(anyhow::Error),
 
+    #[error("Unable to delete blob from storage")]
+    UnableToDeleteBlob(anyhow::Error),
+
     // Transactions
     #[error("Unable to delete transaction log")]
     UnableToDeleteTransactionLog(anyhow::Error),
@@ -54,6 +77,17 @@ pub enum StorageError {
 
     #[error("Unable load previous transactions")]
     UnableToLoadPreviousTransactions(anyhow::Error),
+
+    #[error("Unable to compact transaction log")]
+    UnableToCompactTransactionLog(anyhow::Error),
+
+    /// The engine did not complete the operation in time, see `StorageTimeouts`. The operation may still have
+    /// been applied by the engine
+    #[error("Storage operation {operation} timed out after {timeout:?}")]
+    Timeout {
+        operation: StorageOperation,
+        timeout: Duration,
+    },
 }
 
 // Unable to easily convert io::Error to anyhow::Error
@@ -63,6 +97,23 @@ pub fn io_to_generic_error(error: io::Error) -> anyhow::Error {
 
 pub type StorageResult<T> = Result<T, StorageError>;
 
+/// Namespaces end up in file paths, object keys and Postgres schema names, so they are limited to ASCII letters,
+/// digits, `-` and `_`. See `StorageEngine::set_namespace`
+pub fn validate_namespace(namespace: &str) -> Result<(), String> {
+    let valid = !namespace.is_empty()
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    match valid {
+        true => Ok(()),
+        false => Err(format!(
+            "the namespace '{}' must only contain ASCII letters, digits, '-' and '_'",
+            namespace
+        )),
+    }
+}
+
 pub enum ReadBlobState {
     Found(Vec<u8>),
     /// If not found, this is an okay state, it may mean this is the first time the database has been initialized
@@ -90,36 +141,260 @@ pub trait Storage {
     // Snapshot (world state, meta data, etc.)
     fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()>;
     fn read_blob(&self, path: String) -> StorageResult<ReadBlobState>;
+    /// Writes the blobs with up to `parallelism` writes in flight, returns the result of each blob in order.
+    /// Engines that cannot write concurrently write them one at a time
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        _parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        blobs
+            .into_iter()
+            .map(|(path, bytes)| self.write_blob(path, bytes))
+            .collect()
+    }
+    /// Deletes a blob that is no longer needed (e.g. a pruned snapshot), engines that cannot delete blobs
+    /// leave them behind
+    fn delete_blob(&self, _path: String) -> StorageResult<()> {
+        Ok(())
+    }
 
     // Transactions
     fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()>;
+    /// Writes a batch of transactions in order, engines that can should coalesce them into as few writes as possible
+    fn transaction_write_batch(&mut self, transactions: &[Vec<u8>]) -> StorageResult<()> {
+        for transaction in transactions {
+            self.transaction_write(transaction)?;
+        }
+
+        Ok(())
+    }
     fn transaction_sync(&self) -> StorageResult<()>;
     fn transaction_flush(&mut self) -> StorageResult<()>;
     fn transaction_load(&mut self) -> StorageResult<Vec<String>>;
+
+    /// Rewrites the transaction log with only the transactions that are retained, returns the number of
+    /// transactions that were dropped. Writes are blocked while the log is rewritten
+    fn transaction_compact(&mut self, _retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        Err(StorageError::UnableToCompactTransactionLog(
+            anyhow::anyhow!("Compaction is not supported by this storage engine"),
+        ))
+    }
 }
 
+impl Storage for Box<dyn Storage + Sync + Send> {
+    fn init(&mut self) -> StorageResult<()> {
+        (**self).init()
+    }
+
+    fn reset_database(&mut self) -> StorageResult<()> {
+        (**self).reset_database()
+    }
+
+    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+        (**self).write_blob(path, bytes)
+    }
+
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        (**self).read_blob(path)
+    }
+
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        (**self).write_blobs(blobs, parallelism)
+    }
+
+    fn delete_blob(&self, path: String) -> StorageResult<()> {
+        (**self).delete_blob(path)
+    }
+
+    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+        (**self).transaction_write(transaction)
+    }
+
+    fn transaction_write_batch(&mut self, transactions: &[Vec<u8>]) -> StorageResult<()> {
+        (**self).transaction_write_batch(transactions)
+    }
+
+    fn transaction_sync(&self) -> StorageResult<()> {
+        (**self).transaction_sync()
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        (**self).transaction_flush()
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        (**self).transaction_load()
+    }
+
+    fn transaction_compact(&mut self, retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        (**self).transaction_compact(retain)
+    }
+}
+
+/// The network engines are only compiled with their cargo feature (`s3`, `dynamodb` or `postgres`)
 #[derive(Debug, Clone, strum_macros::Display)]
 pub enum StorageEngine {
-    File(PathBuf),
+    File(FileOptions),
+    #[cfg(feature = "s3")]
     S3(S3Options),
+    #[cfg(feature = "dynamodb")]
     DynamoDB(DynamoOptions),
+    #[cfg(feature = "postgres")]
     Postgres(PostgresOptions),
+    /// An engine implemented outside of the crate, e.g. by an embedder, see `CustomStorage`
+    Custom(CustomStorage),
 }
 
 impl StorageEngine {
-    pub fn get_engine(options: DatabaseOptions) -> Arc<Mutex<dyn Storage + Sync + Send>> {
-        match options.storage_engine {
-            StorageEngine::File(base_dir) => Arc::new(Mutex::new(FileStorage::new(base_dir))),
-            StorageEngine::S3(options) => Arc::new(Mutex::new(S3Storage::new(options.clone()))),
+    /// Scopes every key, blob and WAL entry of the engine to `namespace`, so multiple databases (e.g. dev and
+    /// staging) can share a directory, bucket, table or Postgres database. Resets only delete the namespace
+    pub fn set_namespace(self, namespace: String) -> Self {
+        match self {
+            StorageEngine::File(options) => StorageEngine::File(options.set_namespace(namespace)),
+            #[cfg(feature = "s3")]
+            StorageEngine::S3(options) => StorageEngine::S3(options.set_namespace(namespace)),
+            #[cfg(feature = "dynamodb")]
             StorageEngine::DynamoDB(options) => {
-                Arc::new(Mutex::new(DynamoDBStorage::new(options.clone())))
+                StorageEngine::DynamoDB(options.set_namespace(namespace))
             }
+            #[cfg(feature = "postgres")]
             StorageEngine::Postgres(options) => {
-                Arc::new(Mutex::new(PgStorage::new(options.clone())))
+                StorageEngine::Postgres(options.set_namespace(namespace))
+            }
+            StorageEngine::Custom(storage) => {
+                StorageEngine::Custom(storage.set_namespace(namespace))
+            }
+        }
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        match self {
+            StorageEngine::File(options) => options.get_namespace(),
+            #[cfg(feature = "s3")]
+            StorageEngine::S3(options) => options.get_namespace(),
+            #[cfg(feature = "dynamodb")]
+            StorageEngine::DynamoDB(options) => options.get_namespace(),
+            #[cfg(feature = "postgres")]
+            StorageEngine::Postgres(options) => options.get_namespace(),
+            StorageEngine::Custom(storage) => storage.get_namespace(),
+        }
+    }
+
+    /// When migrating, the engine mirrors writes to the migration target, see `StorageMigration`
+    /// Network engines record the latency of their operations to `latency`, engines with a replica their
+    /// progress copying to it to `replication`
+    pub fn get_engine(
+        options: DatabaseOptions,
+        migration: Option<Arc<StorageMigration>>,
+        latency: Arc<StorageLatency>,
+        replication: Arc<StorageReplication>,
+    ) -> Arc<Mutex<dyn Storage + Sync + Send>> {
+        let storage = options
+            .storage_engine
+            .build(&options, latency.clone(), replication);
+
+        match migration {
+            Some(migration) => {
+                let target = migration.target.build(
+                    &options,
+                    latency,
+                    Arc::new(StorageReplication::default()),
+                );
+
+                Self::wrap_engine(&options, MigrationStorage::new(storage, target, migration))
             }
+            None => Self::wrap_engine(&options, storage),
         }
     }
 
+    /// An engine that rejects every write, the file engine does not create its directories or WAL files.
+    /// Chaos and migrations are not applied, see `Persistence::open_read_only`
+    pub fn get_read_only_engine(
+        options: &DatabaseOptions,
+    ) -> Arc<Mutex<dyn Storage + Sync + Send>> {
+        let storage: Box<dyn Storage + Sync + Send> = match &options.storage_engine {
+            StorageEngine::File(file_options) => Box::new(FileStorage::open_read_only(
+                file_options.clone(),
+                options.write_mode.clone(),
+            )),
+            engine => engine.build(
+                options,
+                Arc::new(StorageLatency::default()),
+                Arc::new(StorageReplication::default()),
+            ),
+        };
+
+        Arc::new(Mutex::new(ReadOnlyStorage::new(storage)))
+    }
+
+    #[cfg_attr(not(feature = "s3"), allow(unused_variables))]
+    pub(crate) fn build(
+        &self,
+        options: &DatabaseOptions,
+        latency: Arc<StorageLatency>,
+        replication: Arc<StorageReplication>,
+    ) -> Box<dyn Storage + Sync + Send> {
+        let timeouts = options.storage_timeouts.clone();
+
+        match self {
+            StorageEngine::File(file_options) => Box::new(FileStorage::new(
+                file_options.clone(),
+                options.write_mode.clone(),
+            )),
+            #[cfg(feature = "s3")]
+            StorageEngine::S3(s3_options) => {
+                let storage = S3Storage::new(s3_options.clone(), timeouts.clone(), latency);
+
+                match s3_options.get_replica_options() {
+                    // The replica's latency is not mixed into the primary's
+                    Some(replica_options) => Box::new(ReplicatedStorage::new(
+                        storage,
+                        Box::new(S3Storage::new(
+                            replica_options,
+                            timeouts,
+                            Arc::new(StorageLatency::default()),
+                        )),
+                        replication,
+                    )),
+                    None => Box::new(storage),
+                }
+            }
+            #[cfg(feature = "dynamodb")]
+            StorageEngine::DynamoDB(dynamo_options) => Box::new(DynamoDBStorage::new(
+                dynamo_options.clone(),
+                timeouts,
+                latency,
+            )),
+            #[cfg(feature = "postgres")]
+            StorageEngine::Postgres(postgres_options) => {
+                Box::new(PgStorage::new(postgres_options.clone(), timeouts, latency))
+            }
+            StorageEngine::Custom(storage) => storage.open(),
+        }
+    }
+
+    /// Storage errors are injected by wrapping the engine, see `ChaosOptions`
+    #[cfg_attr(not(feature = "chaos"), allow(unused_variables))]
+    fn wrap_engine<S: Storage + Sync + Send + 'static>(
+        options: &DatabaseOptions,
+        storage: S,
+    ) -> Arc<Mutex<dyn Storage + Sync + Send>> {
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &options.chaos {
+            return Arc::new(Mutex::new(chaos::ChaosStorage::new(
+                storage,
+                chaos.storage_error_probability,
+            )));
+        }
+
+        Arc::new(Mutex::new(storage))
+    }
+
     pub fn get_engine_info_stats(&self) -> Vec<(String, String)> {
         let storage_engine = ("StorageEngine".to_string(), format!("{}", self));
 
@@ -127,18 +402,84 @@ impl StorageEngine {
             format!("- {}", info_type)
         }
 
-        let storage_engine_config_info: (String, String) = match self {
-            StorageEngine::File(base_dir) => (
-                prefix("BaseDir"),
-                format!("{}", fs::canonicalize(base_dir).unwrap().display()),
-            ),
-            StorageEngine::S3(options) => (prefix("S3 Bucket"), format!("{}", options.bucket)),
-            StorageEngine::DynamoDB(options) => (prefix("DDB Table"), format!("{}", options.table)),
-            StorageEngine::Postgres(options) => {
-                (prefix("SQL Database"), format!("{}", options.database))
-            }
+        #[cfg(any(feature = "s3", feature = "dynamodb"))]
+        fn aws_profile(profile: &Option<String>) -> String {
+            profile
+                .clone()
+                .unwrap_or("<default credential chain>".to_string())
+        }
+
+        let storage_engine_config_info: Vec<(String, String)> = match self {
+            StorageEngine::File(options) => vec![
+                (
+                    prefix("BaseDir"),
+                    format!("{}", fs::canonicalize(&options.base_dir).unwrap().display()),
+                ),
+                (
+                    prefix("SnapshotDir"),
+                    format!(
+                        "{}",
+                        fs::canonicalize(options.get_snapshot_dir())
+                            .unwrap()
+                            .display()
+                    ),
+                ),
+                (
+                    prefix("WALDirs"),
+                    options
+                        .get_wal_dirs()
+                        .iter()
+                        .map(|dir| format!("{}", fs::canonicalize(dir).unwrap().display()))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                ),
+                (prefix("Layout"), format!("{:?}", options.get_layout())),
+            ],
+            #[cfg(feature = "s3")]
+            StorageEngine::S3(options) => vec![
+                (prefix("S3 Bucket"), options.bucket.to_string()),
+                (prefix("AWS Profile"), aws_profile(&options.profile)),
+            ]
+            .into_iter()
+            .chain(
+                options
+                    .region
+                    .clone()
+                    .map(|region| (prefix("AWS Region"), region)),
+            )
+            .chain(options.replica.iter().flat_map(|replica| {
+                vec![
+                    (prefix("Replica S3 Bucket"), replica.bucket.to_string()),
+                    (
+                        prefix("Replica AWS Region"),
+                        replica.region.clone().unwrap_or("<default>".to_string()),
+                    ),
+                ]
+            }))
+            .collect(),
+            #[cfg(feature = "dynamodb")]
+            StorageEngine::DynamoDB(options) => vec![
+                (prefix("DDB Table"), options.table.to_string()),
+                (prefix("AWS Profile"), aws_profile(&options.profile)),
+            ],
+            #[cfg(feature = "postgres")]
+            StorageEngine::Postgres(options) => vec![
+                (prefix("SQL Database"), options.database.to_string()),
+                (prefix("SQL Host"), options.host.to_string()),
+                (prefix("SQL User"), options.user.to_string()),
+                (prefix("SQL Password"), options.password.to_string()),
+            ],
+            StorageEngine::Custom(storage) => vec![(prefix("Name"), storage.name.clone())],
         };
 
-        return vec![storage_engine, storage_engine_config_info];
+        let namespace = self
+            .get_namespace()
+            .map(|namespace| (prefix("Namespace"), namespace.to_string()));
+
+        return vec![storage_engine]
+            .into_iter()
+            .chain(storage_engine_config_info)
+            .chain(namespace)
+            .collect();
     }
 }
@@ -1,32 +1,52 @@
-use std::{future::Future, pin::Pin, sync::Arc, thread};
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+#[cfg(feature = "network")]
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use strum::IntoEnumIterator;
 
+#[cfg(feature = "network")]
 use tokio::{
     runtime::Builder,
-    sync::mpsc::{Receiver, Sender},
+    sync::mpsc::{self, Receiver, Sender},
 };
 
-use super::{ReadBlobState, Storage, StorageResult};
+#[cfg(feature = "network")]
+use super::{ReadBlobState, Storage, StorageError, StorageResult};
+
+// The requests and runtime of the network engines are only compiled with one of the engines (the `s3`, `dynamodb`
+//  or `postgres` features). The timeouts and latencies are a part of the options and stats either way
 
+#[cfg(feature = "network")]
 pub struct WriteFileRequest {
     pub bytes: Vec<u8>,
     pub file_path: String,
     pub sender: oneshot::Sender<StorageResult<()>>,
 }
 
+#[cfg(feature = "network")]
 pub struct ResetFileRequest {
     pub sender: oneshot::Sender<StorageResult<()>>,
 }
 
+#[cfg(feature = "network")]
 pub struct ReadFileRequest {
     pub file_path: String,
     pub sender: oneshot::Sender<StorageResult<ReadBlobState>>,
 }
 
+#[cfg(feature = "network")]
 pub struct TransactionWriteRequest {
     pub bytes: Vec<u8>,
     pub sender: oneshot::Sender<StorageResult<()>>,
 }
 
+#[cfg(feature = "network")]
 pub enum NetworkStorageAction {
     Init(oneshot::Sender<StorageResult<()>>),
     WriteBlob(WriteFileRequest),
@@ -37,98 +57,314 @@ pub enum NetworkStorageAction {
     TransactionLoad(oneshot::Sender<StorageResult<Vec<String>>>),
 }
 
-pub struct NetworkStorage {
-    pub action_sender: Sender<NetworkStorageAction>,
+/// Kind of request sent to a network storage engine, see `StorageTimeouts`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display, strum_macros::EnumIter)]
+pub enum StorageOperation {
+    Init,
+    WriteBlob,
+    ReadBlob,
+    Reset,
+    TransactionWrite,
+    TransactionFlush,
+    TransactionLoad,
 }
 
-const RECEIVER_EXPECTED_TO_WORK: &str = "should not have issues with the receiver";
+#[cfg(feature = "network")]
+impl NetworkStorageAction {
+    pub fn operation(&self) -> StorageOperation {
+        match self {
+            NetworkStorageAction::Init(_) => StorageOperation::Init,
+            NetworkStorageAction::WriteBlob(_) => StorageOperation::WriteBlob,
+            NetworkStorageAction::ReadBlob(_) => StorageOperation::ReadBlob,
+            NetworkStorageAction::Reset(_) => StorageOperation::Reset,
+            NetworkStorageAction::TransactionWrite(_) => StorageOperation::TransactionWrite,
+            NetworkStorageAction::TransactionFlush(_) => StorageOperation::TransactionFlush,
+            NetworkStorageAction::TransactionLoad(_) => StorageOperation::TransactionLoad,
+        }
+    }
+}
 
-impl Storage for NetworkStorage {
-    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<()>>();
+/// How long a network storage engine has to complete an operation before it fails with `StorageError::Timeout`.
+/// A hung call would otherwise block the WAL thread, and with it every commit, indefinitely
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageTimeouts {
+    /// WAL writes, these are on the commit path so they should be kept short
+    pub transaction_write: Duration,
+    /// Reading and writing snapshots and other blobs
+    pub blob: Duration,
+    /// Initializing and resetting the engine, loading and flushing the WAL
+    pub control: Duration,
+}
 
-        let write_file_request = NetworkStorageAction::WriteBlob(WriteFileRequest {
-            file_path: path,
-            bytes: bytes,
-            sender: sender,
-        });
+impl Default for StorageTimeouts {
+    fn default() -> Self {
+        Self {
+            transaction_write: Duration::from_secs(10),
+            blob: Duration::from_secs(60),
+            control: Duration::from_secs(120),
+        }
+    }
+}
 
-        self.action_sender
-            .blocking_send(write_file_request)
-            .unwrap();
+// Implements: https://rust-unofficial.github.io/patterns/patterns/creational/builder.html
+impl StorageTimeouts {
+    pub fn set_transaction_write(mut self, timeout: Duration) -> Self {
+        self.transaction_write = timeout;
+        self
+    }
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+    pub fn set_blob(mut self, timeout: Duration) -> Self {
+        self.blob = timeout;
+        self
     }
 
-    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<ReadBlobState>>();
+    pub fn set_control(mut self, timeout: Duration) -> Self {
+        self.control = timeout;
+        self
+    }
 
-        // Is the problem that this is happening within the main thread?
-        self.action_sender
-            .blocking_send(NetworkStorageAction::ReadBlob(ReadFileRequest {
-                file_path: path,
-                sender: sender,
-            }))
-            .unwrap();
+    pub fn get(&self, operation: StorageOperation) -> Duration {
+        match operation {
+            StorageOperation::TransactionWrite => self.transaction_write,
+            StorageOperation::WriteBlob | StorageOperation::ReadBlob => self.blob,
+            StorageOperation::Init
+            | StorageOperation::Reset
+            | StorageOperation::TransactionFlush
+            | StorageOperation::TransactionLoad => self.control,
+        }
+    }
+}
+
+/// Number of recent operations per kind of operation used to calculate the latency percentiles
+const LATENCY_WINDOW_SIZE: usize = 1024;
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+/// Rolling latency of the operations sent to the storage engine, reported by the database stats. Shared with
+/// the stats rather than read through the storage lock, which is held for as long as an operation hangs
+pub struct StorageLatency {
+    /// Indexed by `StorageOperation`
+    windows: Vec<Mutex<VecDeque<Duration>>>,
+}
+
+impl Default for StorageLatency {
+    fn default() -> Self {
+        Self {
+            windows: StorageOperation::iter()
+                .map(|_| Mutex::new(VecDeque::with_capacity(LATENCY_WINDOW_SIZE)))
+                .collect(),
+        }
     }
+}
 
-    fn init(&mut self) -> StorageResult<()> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<()>>();
+impl StorageLatency {
+    /// Operations that timed out are recorded at their timeout
+    pub fn record(&self, operation: StorageOperation, latency: Duration) {
+        let mut window = self.windows[operation as usize].lock().unwrap();
 
-        self.action_sender
-            .blocking_send(NetworkStorageAction::Init(sender))
-            .unwrap();
+        if window.len() == LATENCY_WINDOW_SIZE {
+            window.pop_front();
+        }
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+        window.push_back(latency);
     }
 
-    fn reset_database(&mut self) -> StorageResult<()> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<()>>();
+    /// Percentile (0 to 1) of the recent latencies of an operation, none if the operation has not run
+    pub fn percentile(&self, operation: StorageOperation, percentile: f64) -> Option<Duration> {
+        let window = self.windows[operation as usize].lock().unwrap();
 
-        self.action_sender
-            .blocking_send(NetworkStorageAction::Reset(ResetFileRequest {
-                sender: sender,
-            }))
-            .unwrap();
+        if window.is_empty() {
+            return None;
+        }
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+        let mut latencies = window.iter().copied().collect::<Vec<Duration>>();
+        latencies.sort();
+
+        let index = ((latencies.len() as f64 * percentile).ceil() as usize).saturating_sub(1);
+
+        Some(latencies[index])
     }
 
-    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<()>>();
+    /// p50 and p99 of each operation that has run
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        StorageOperation::iter()
+            .flat_map(|operation| {
+                [("P50", 0.5), ("P99", 0.99)]
+                    .into_iter()
+                    .filter_map(move |(name, percentile)| {
+                        self.percentile(operation, percentile).map(|latency| {
+                            (
+                                format!("StorageLatency{}Ms[{}]", name, operation),
+                                format!("{:.3}", latency.as_secs_f64() * 1000.0),
+                            )
+                        })
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "network")]
+const RECEIVER_EXPECTED_TO_WORK: &str = "should not have issues with the receiver";
 
-        self.action_sender
-            .blocking_send(NetworkStorageAction::TransactionWrite(
-                TransactionWriteRequest {
-                    bytes: transaction.to_vec(),
-                    sender: sender,
-                },
-            ))
-            .unwrap();
+/// An action sent to the runtime whose result has not been received yet
+#[cfg(feature = "network")]
+struct PendingRequest<T> {
+    receiver: oneshot::Receiver<StorageResult<T>>,
+    operation: StorageOperation,
+    started_at: Instant,
+}
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+#[cfg(feature = "network")]
+pub struct NetworkStorage {
+    action_sender: Sender<NetworkStorageAction>,
+    /// The runtime thread started by `start_runtime`, joined once the storage is dropped
+    runtime: Option<JoinHandle<()>>,
+    timeouts: StorageTimeouts,
+    latency: Arc<StorageLatency>,
+}
+
+#[cfg(feature = "network")]
+impl NetworkStorage {
+    pub fn new(
+        action_sender: Sender<NetworkStorageAction>,
+        runtime: JoinHandle<()>,
+        timeouts: StorageTimeouts,
+        latency: Arc<StorageLatency>,
+    ) -> Self {
+        Self {
+            action_sender,
+            runtime: Some(runtime),
+            timeouts,
+            latency,
+        }
     }
 
-    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<Vec<String>>>();
+    /// Sends the action to the runtime and waits at most the operation's timeout for the result
+    fn request<T>(
+        &self,
+        action: impl FnOnce(oneshot::Sender<StorageResult<T>>) -> NetworkStorageAction,
+    ) -> StorageResult<T> {
+        self.wait(self.send(action))
+    }
 
-        self.action_sender
-            .blocking_send(NetworkStorageAction::TransactionLoad(sender))
-            .unwrap();
+    /// Sends the action to the runtime without waiting for the result, the runtime runs every action it receives
+    /// concurrently
+    fn send<T>(
+        &self,
+        action: impl FnOnce(oneshot::Sender<StorageResult<T>>) -> NetworkStorageAction,
+    ) -> PendingRequest<T> {
+        let (sender, receiver) = oneshot::channel::<StorageResult<T>>();
+
+        let action = action(sender);
+        let operation = action.operation();
+        let started_at = Instant::now();
+
+        self.action_sender.blocking_send(action).unwrap();
+
+        PendingRequest {
+            receiver,
+            operation,
+            started_at,
+        }
+    }
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+    /// Waits for the result of a sent action until the operation's timeout, counted from when it was sent
+    fn wait<T>(&self, pending: PendingRequest<T>) -> StorageResult<T> {
+        let PendingRequest {
+            receiver,
+            operation,
+            started_at,
+        } = pending;
+
+        let timeout = self.timeouts.get(operation);
+
+        let result = match receiver.recv_timeout(timeout.saturating_sub(started_at.elapsed())) {
+            Ok(result) => result,
+            // The runtime also drops operations that exceed their timeout, which disconnects the receiver
+            Err(_) if started_at.elapsed() >= timeout => {
+                Err(StorageError::Timeout { operation, timeout })
+            }
+            Err(_) => panic!("{}", RECEIVER_EXPECTED_TO_WORK),
+        };
+
+        self.latency
+            .record(operation, started_at.elapsed().min(timeout));
+
+        result
     }
+}
 
-    fn transaction_flush(&mut self) -> StorageResult<()> {
-        let (sender, receiver) = oneshot::channel::<StorageResult<()>>();
+#[cfg(feature = "network")]
+impl Storage for NetworkStorage {
+    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+        self.request(|sender| {
+            NetworkStorageAction::WriteBlob(WriteFileRequest {
+                file_path: path,
+                bytes: bytes,
+                sender: sender,
+            })
+        })
+    }
+
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        let mut results = Vec::with_capacity(blobs.len());
+        let mut in_flight = VecDeque::new();
+
+        for (path, bytes) in blobs {
+            // Writes complete in any order, waiting on the oldest write keeps the results in order
+            if in_flight.len() >= parallelism.max(1) {
+                results.push(self.wait(in_flight.pop_front().unwrap()));
+            }
+
+            in_flight.push_back(self.send(|sender| {
+                NetworkStorageAction::WriteBlob(WriteFileRequest {
+                    file_path: path,
+                    bytes,
+                    sender,
+                })
+            }));
+        }
+
+        results.extend(in_flight.into_iter().map(|pending| self.wait(pending)));
+
+        results
+    }
+
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        self.request(|sender| {
+            NetworkStorageAction::ReadBlob(ReadFileRequest {
+                file_path: path,
+                sender: sender,
+            })
+        })
+    }
 
-        self.action_sender
-            .blocking_send(NetworkStorageAction::TransactionFlush(sender))
-            .unwrap();
+    fn init(&mut self) -> StorageResult<()> {
+        self.request(NetworkStorageAction::Init)
+    }
 
-        receiver.recv().expect(RECEIVER_EXPECTED_TO_WORK)
+    fn reset_database(&mut self) -> StorageResult<()> {
+        self.request(|sender| NetworkStorageAction::Reset(ResetFileRequest { sender: sender }))
+    }
+
+    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+        self.request(|sender| {
+            NetworkStorageAction::TransactionWrite(TransactionWriteRequest {
+                bytes: transaction.to_vec(),
+                sender: sender,
+            })
+        })
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        self.request(NetworkStorageAction::TransactionLoad)
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        self.request(NetworkStorageAction::TransactionFlush)
     }
 
     fn transaction_sync(&self) -> StorageResult<()> {
@@ -140,13 +376,16 @@ impl Storage for NetworkStorage {
 /// Context, provided during initial set-up and is passed to both the client and task functions
 /// Client function, run once and is used to pass the client to the task function
 /// Task function, called for each incoming action
+/// Operations that exceed their timeout are dropped, see `StorageTimeouts`
+#[cfg(feature = "network")]
 pub fn start_runtime<T: Clone + Send + 'static, C: Clone + Send + 'static>(
     mut action_receiver: Receiver<NetworkStorageAction>,
+    timeouts: StorageTimeouts,
     context: T,
     task: fn(T, Arc<C>, NetworkStorageAction) -> Pin<Box<dyn Future<Output = ()> + Send>>,
     client: fn(T) -> Pin<Box<dyn Future<Output = C> + Send>>,
-) {
-    let _ = thread::Builder::new()
+) -> JoinHandle<()> {
+    thread::Builder::new()
         .name("AWS SDK Tokio".to_string())
         .spawn(move || {
             let rt = Builder::new_current_thread().enable_all().build().unwrap();
@@ -155,8 +394,147 @@ pub fn start_runtime<T: Clone + Send + 'static, C: Clone + Send + 'static>(
                 let client = Arc::new(client(context.clone()).await);
 
                 while let Some(request) = action_receiver.recv().await {
-                    tokio::spawn(task(context.clone(), client.clone(), request));
+                    let operation = request.operation();
+                    let timeout = timeouts.get(operation);
+                    let task = task(context.clone(), client.clone(), request);
+
+                    tokio::spawn(async move {
+                        if tokio::time::timeout(timeout, task).await.is_err() {
+                            log::error!(
+                                "Storage operation {} timed out after {:?}",
+                                operation,
+                                timeout
+                            );
+                        }
+                    });
                 }
             });
-        });
+        })
+        .expect("Should be able to spawn the storage runtime thread")
+}
+
+#[cfg(feature = "network")]
+impl Drop for NetworkStorage {
+    fn drop(&mut self) {
+        // The runtime exits once its action channel is closed, operations that are still running are cancelled
+        let (closed_sender, _) = mpsc::channel(1);
+        drop(std::mem::replace(&mut self.action_sender, closed_sender));
+
+        if let Some(runtime) = self.runtime.take() {
+            if runtime.join().is_err() {
+                log::error!("The storage runtime thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// Answers every operation except WAL writes, which hang
+    fn hanging_task(
+        _: (),
+        _: Arc<()>,
+        action: NetworkStorageAction,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            match action {
+                NetworkStorageAction::TransactionWrite(request) => {
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                    let _ = request.sender.send(Ok(()));
+                }
+                NetworkStorageAction::TransactionLoad(sender) => {
+                    let _ = sender.send(Ok(vec![]));
+                }
+                _ => unimplemented!(),
+            }
+        })
+    }
+
+    /// Answers blob writes after a delay
+    fn slow_write_task(
+        _: (),
+        _: Arc<()>,
+        action: NetworkStorageAction,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            match action {
+                NetworkStorageAction::WriteBlob(request) => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    let _ = request.sender.send(Ok(()));
+                }
+                _ => unimplemented!(),
+            }
+        })
+    }
+
+    fn client(_: ()) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+
+    #[test]
+    fn hung_operations_time_out() {
+        let timeouts = StorageTimeouts::default().set_transaction_write(Duration::from_millis(50));
+        let latency = Arc::new(StorageLatency::default());
+
+        let (action_sender, action_receiver) = mpsc::channel::<NetworkStorageAction>(16);
+        let runtime = start_runtime(action_receiver, timeouts.clone(), (), hanging_task, client);
+
+        let mut storage = NetworkStorage::new(action_sender, runtime, timeouts, latency.clone());
+
+        assert!(matches!(
+            storage.transaction_write(b"{}"),
+            Err(StorageError::Timeout {
+                operation: StorageOperation::TransactionWrite,
+                ..
+            })
+        ));
+
+        // Other operations are not affected by the hung write
+        assert!(storage.transaction_load().unwrap().is_empty());
+
+        assert_eq!(
+            latency.percentile(StorageOperation::TransactionWrite, 0.99),
+            Some(Duration::from_millis(50))
+        );
+
+        let stats = latency.get_stats();
+
+        assert!(stats
+            .iter()
+            .any(|(name, _)| name == "StorageLatencyP99Ms[TransactionLoad]"));
+        assert!(!stats.iter().any(|(name, _)| name.ends_with("[ReadBlob]")));
+    }
+
+    #[test]
+    fn blob_writes_run_concurrently() {
+        let timeouts = StorageTimeouts::default();
+        let latency = Arc::new(StorageLatency::default());
+
+        let (action_sender, action_receiver) = mpsc::channel::<NetworkStorageAction>(16);
+        let runtime = start_runtime(
+            action_receiver,
+            timeouts.clone(),
+            (),
+            slow_write_task,
+            client,
+        );
+
+        let storage = NetworkStorage::new(action_sender, runtime, timeouts, latency);
+
+        let blobs: Vec<(String, Vec<u8>)> = (0..8)
+            .map(|index| (format!("shard-{}", index), vec![]))
+            .collect();
+
+        let started_at = Instant::now();
+        let results = storage.write_blobs(blobs, 4);
+
+        // Two rounds of four writes rather than eight writes one after another
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert!(started_at.elapsed() < Duration::from_millis(600));
+    }
 }
@@ -5,7 +5,10 @@ use tokio::sync::mpsc::{self};
 use tokio_postgres::{Client, NoTls};
 
 use super::{
-    network::{start_runtime, NetworkStorage, NetworkStorageAction},
+    network::{
+        start_runtime, NetworkStorage, NetworkStorageAction, StorageLatency, StorageTimeouts,
+    },
+    secret::Secret,
     ReadBlobState, Storage, StorageError, StorageResult,
 };
 
@@ -14,15 +17,23 @@ pub struct PgStorage {
 }
 
 impl PgStorage {
-    pub fn new(options: PostgresOptions) -> Self {
+    pub fn new(
+        options: PostgresOptions,
+        timeouts: StorageTimeouts,
+        latency: Arc<StorageLatency>,
+    ) -> Self {
         let (action_sender, action_receiver) = mpsc::channel::<NetworkStorageAction>(16);
 
-        start_runtime(action_receiver, options, task_fn, client_fn);
+        let runtime = start_runtime(
+            action_receiver,
+            timeouts.clone(),
+            options,
+            task_fn,
+            client_fn,
+        );
 
         Self {
-            network_storage: NetworkStorage {
-                action_sender: action_sender,
-            },
+            network_storage: NetworkStorage::new(action_sender, runtime, timeouts, latency),
         }
     }
 }
@@ -32,16 +43,19 @@ pub struct PostgresOptions {
     pub database: String,
     pub host: String,
     pub user: String,
-    pub password: String,
+    /// Redacted when the options are printed, see `Secret`
+    pub password: Secret,
+    namespace: Option<String>,
 }
 
 impl PostgresOptions {
-    pub fn new(user: String, database: String, host: String, password: String) -> Self {
+    pub fn new(user: String, database: String, host: String, password: Secret) -> Self {
         Self {
             user,
             database,
             host,
             password,
+            namespace: None,
         }
     }
 
@@ -50,9 +64,27 @@ impl PostgresOptions {
             user: "dalesalter".to_string(),
             database: "dalesalter1".to_string(),
             host: "localhost".to_string(),
-            password: "mysecretpassword".to_string(),
+            password: Secret::new("mysecretpassword".to_string()),
+            namespace: None,
         }
     }
+
+    /// Creates the tables in the `<namespace>` schema rather than `public`, so databases can share the Postgres
+    /// database. A reset only deletes the schema's rows
+    pub fn set_namespace(mut self, namespace: String) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// The schema of the tables, quoted for use in statements. Namespaces are validated when the database options
+    /// are built, see `validate_namespace`
+    fn schema(&self) -> String {
+        format!(r#""{}""#, self.namespace.as_deref().unwrap_or("public"))
+    }
 }
 
 pub fn format_connection_string(options: &PostgresOptions, database_name: &str) -> String {
@@ -65,7 +97,7 @@ pub fn format_connection_string(options: &PostgresOptions, database_name: &str)
         "#,
         dbname = database_name,
         host = options.host,
-        password = options.password,
+        password = options.password.expose(),
         user = options.user,
     )
 }
@@ -106,32 +138,44 @@ fn client_fn(
             }
         });
 
+        let schema = options.schema();
+
         // DO baseline creates
-        let data_table = r#"
-            CREATE TABLE IF NOT EXISTS "public"."data" (
+        let create_schema = format!(r#"CREATE SCHEMA IF NOT EXISTS {schema};"#);
+
+        client.execute(&create_schema, &[]).await.unwrap();
+
+        let data_table = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {schema}."data" (
                 "id" text NOT NULL,
                 "data" jsonb,
                 PRIMARY KEY ("id")
             );
-        "#;
+        "#
+        );
 
-        client.execute(data_table, &[]).await.unwrap();
+        client.execute(&data_table, &[]).await.unwrap();
 
-        let tx_sequence = r#"
-            CREATE SEQUENCE IF NOT EXISTS transaction_id_seq;
-        "#;
+        let tx_sequence = format!(
+            r#"
+            CREATE SEQUENCE IF NOT EXISTS {schema}.transaction_id_seq;
+        "#
+        );
 
-        client.execute(tx_sequence, &[]).await.unwrap();
+        client.execute(&tx_sequence, &[]).await.unwrap();
 
-        let transaction_table = r#"
-            CREATE TABLE IF NOT EXISTS "public"."transaction" (
-                "id" int4 NOT NULL DEFAULT nextval('transaction_id_seq'::regclass),
+        let transaction_table = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {schema}."transaction" (
+                "id" int4 NOT NULL DEFAULT nextval('{schema}.transaction_id_seq'::regclass),
                 "data" jsonb,
                 PRIMARY KEY ("id")
             );
-        "#;
+        "#
+        );
 
-        client.execute(transaction_table, &[]).await.unwrap();
+        client.execute(&transaction_table, &[]).await.unwrap();
 
         Arc::new(client)
     })
@@ -151,6 +195,14 @@ impl Storage for PgStorage {
         self.network_storage.write_blob(path, bytes)
     }
 
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        self.network_storage.write_blobs(blobs, parallelism)
+    }
+
     fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
         self.network_storage.read_blob(path)
     }
@@ -182,21 +234,25 @@ impl Storage for PgStorage {
 //  a connection pool. Though again, this is a little odd because we mutex the persistence client (meaning)
 //  there already is exclusive access. Wild.
 fn task_fn(
-    _data: PostgresOptions,
+    data: PostgresOptions,
     client: Arc<Arc<Client>>,
     action: NetworkStorageAction,
 ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
     Box::pin(async move {
+        let schema = data.schema();
+
         match action {
             NetworkStorageAction::Init(r) => {
                 let _ = r.send(Ok(())).unwrap();
             }
             NetworkStorageAction::Reset(r) => {
-                let delete_transactions = r#"
-                    DELETE FROM "public"."transaction";
-                "#;
+                let delete_transactions = format!(
+                    r#"
+                    DELETE FROM {schema}."transaction";
+                "#
+                );
 
-                if let Err(e) = client.execute(delete_transactions, &[]).await {
+                if let Err(e) = client.execute(&delete_transactions, &[]).await {
                     r.sender
                         .send(Err(StorageError::UnableToResetPersistence(anyhow!(e))))
                         .unwrap();
@@ -204,11 +260,13 @@ fn task_fn(
                     return;
                 }
 
-                let delete_data = r#"
-                    DELETE FROM "public"."data";
-                "#;
+                let delete_data = format!(
+                    r#"
+                    DELETE FROM {schema}."data";
+                "#
+                );
 
-                if let Err(e) = client.execute(delete_data, &[]).await {
+                if let Err(e) = client.execute(&delete_data, &[]).await {
                     r.sender
                         .send(Err(StorageError::UnableToResetPersistence(anyhow!(e))))
                         .unwrap();
@@ -219,14 +277,16 @@ fn task_fn(
                 r.sender.send(Ok(())).unwrap();
             }
             NetworkStorageAction::WriteBlob(file_request) => {
-                let write_blob = r#"
-                    INSERT INTO "public"."data" ("id", "data") VALUES ($1, $2);
-                "#;
+                let write_blob = format!(
+                    r#"
+                    INSERT INTO {schema}."data" ("id", "data") VALUES ($1, $2);
+                "#
+                );
 
                 let json: Value = byte_array_to_value(&file_request.bytes);
 
                 let result = client
-                    .execute(write_blob, &[&file_request.file_path, &json])
+                    .execute(&write_blob, &[&file_request.file_path, &json])
                     .await;
 
                 let response = match result {
@@ -241,11 +301,13 @@ fn task_fn(
                 let _ = file_request.sender.send(response).unwrap();
             }
             NetworkStorageAction::ReadBlob(file_request) => {
-                let read_blob = r#"
-                    SELECT * FROM "public"."data" WHERE id = $1;
-                "#;
+                let read_blob = format!(
+                    r#"
+                    SELECT * FROM {schema}."data" WHERE id = $1;
+                "#
+                );
 
-                let result = client.query(read_blob, &[&file_request.file_path]).await;
+                let result = client.query(&read_blob, &[&file_request.file_path]).await;
 
                 let response = match result {
                     Ok(rows) => match rows.first() {
@@ -265,13 +327,15 @@ fn task_fn(
                 let _ = file_request.sender.send(response).unwrap();
             }
             NetworkStorageAction::TransactionWrite(request) => {
-                let transaction_insert = r#"
-                    INSERT INTO "public"."transaction" ("data") VALUES ($1);
-                "#;
+                let transaction_insert = format!(
+                    r#"
+                    INSERT INTO {schema}."transaction" ("data") VALUES ($1);
+                "#
+                );
 
                 let json: Value = byte_array_to_value(&request.bytes);
 
-                let response = match client.execute(transaction_insert, &[&json]).await {
+                let response = match client.execute(&transaction_insert, &[&json]).await {
                     Ok(1) => Ok(()),
                     Ok(insert_count) => Err(StorageError::UnableToWriteTransaction(anyhow!(
                         "Expected 1 row to be inserted, got {}",
@@ -283,11 +347,13 @@ fn task_fn(
                 request.sender.send(response).unwrap();
             }
             NetworkStorageAction::TransactionFlush(request) => {
-                let reset_sql = r#"
-                    DELETE FROM "public"."transaction";
-                "#;
+                let reset_sql = format!(
+                    r#"
+                    DELETE FROM {schema}."transaction";
+                "#
+                );
 
-                let delete_transaction_response = match client.execute(reset_sql, &[]).await {
+                let delete_transaction_response = match client.execute(&reset_sql, &[]).await {
                     Ok(_) => Ok(()),
                     Err(e) => Err(StorageError::UnableToDeleteTransactionLog(anyhow!(e))),
                 };
@@ -295,11 +361,13 @@ fn task_fn(
                 request.send(delete_transaction_response).unwrap();
             }
             NetworkStorageAction::TransactionLoad(request) => {
-                let transaction_select = r#"
-                    SELECT * FROM "public"."transaction";
-                "#;
+                let transaction_select = format!(
+                    r#"
+                    SELECT * FROM {schema}."transaction";
+                "#
+                );
 
-                let result = client.query(transaction_select, &[]).await.unwrap();
+                let result = client.query(&transaction_select, &[]).await.unwrap();
 
                 let mut contents: Vec<String> = vec![];
 
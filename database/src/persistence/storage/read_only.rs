@@ -0,0 +1,86 @@
+use super::{ReadBlobState, Storage, StorageError, StorageResult};
+
+/// Wraps a storage engine and rejects every call that would change it, see `Persistence::open_read_only`
+pub struct ReadOnlyStorage<S: Storage> {
+    storage: S,
+}
+
+impl<S: Storage> ReadOnlyStorage<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+}
+
+fn read_only(operation: &str) -> anyhow::Error {
+    anyhow::anyhow!("Storage is opened read-only, {} is not allowed", operation)
+}
+
+impl<S: Storage> Storage for ReadOnlyStorage<S> {
+    fn init(&mut self) -> StorageResult<()> {
+        Err(StorageError::UnableToInitializePersistence(read_only(
+            "init",
+        )))
+    }
+
+    fn reset_database(&mut self) -> StorageResult<()> {
+        Err(StorageError::UnableToResetPersistence(read_only(
+            "reset_database",
+        )))
+    }
+
+    fn write_blob(&self, _path: String, _bytes: Vec<u8>) -> StorageResult<()> {
+        Err(StorageError::UnableToWriteBlob(read_only("write_blob")))
+    }
+
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        self.storage.read_blob(path)
+    }
+
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        _parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        blobs
+            .iter()
+            .map(|_| Err(StorageError::UnableToWriteBlob(read_only("write_blobs"))))
+            .collect()
+    }
+
+    fn delete_blob(&self, _path: String) -> StorageResult<()> {
+        Err(StorageError::UnableToDeleteBlob(read_only("delete_blob")))
+    }
+
+    fn transaction_write(&mut self, _transaction: &[u8]) -> StorageResult<()> {
+        Err(StorageError::UnableToWriteTransaction(read_only(
+            "transaction_write",
+        )))
+    }
+
+    fn transaction_write_batch(&mut self, _transactions: &[Vec<u8>]) -> StorageResult<()> {
+        Err(StorageError::UnableToWriteTransaction(read_only(
+            "transaction_write_batch",
+        )))
+    }
+
+    // Nothing is ever written, so there is nothing to sync
+    fn transaction_sync(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        Err(StorageError::UnableToDeleteTransactionLog(read_only(
+            "transaction_flush",
+        )))
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        self.storage.transaction_load()
+    }
+
+    fn transaction_compact(&mut self, _retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        Err(StorageError::UnableToCompactTransactionLog(read_only(
+            "transaction_compact",
+        )))
+    }
+}
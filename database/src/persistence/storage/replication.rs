@@ -0,0 +1,456 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{ReadBlobState, Storage, StorageError, StorageResult};
+
+/// How long a failed copy waits before it is retried, doubled after every failure
+const INITIAL_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A write of the primary that is copied to the replica
+enum ReplicationOp {
+    WriteBlob(String, Vec<u8>),
+    DeleteBlob(String),
+    TransactionWrite(Vec<u8>),
+    TransactionFlush,
+    Reset,
+}
+
+impl ReplicationOp {
+    fn apply(&self, replica: &mut dyn Storage) -> StorageResult<()> {
+        match self {
+            ReplicationOp::WriteBlob(path, bytes) => {
+                replica.write_blob(path.clone(), bytes.clone())
+            }
+            ReplicationOp::DeleteBlob(path) => replica.delete_blob(path.clone()),
+            ReplicationOp::TransactionWrite(bytes) => {
+                replica.transaction_write(bytes)?;
+                replica.transaction_sync()
+            }
+            ReplicationOp::TransactionFlush => replica.transaction_flush(),
+            ReplicationOp::Reset => replica.reset_database(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ReplicationState {
+    enabled: bool,
+    /// When each write that has not been copied yet was made, oldest first
+    pending: VecDeque<Instant>,
+    replicated: usize,
+    failures: usize,
+    last_error: Option<String>,
+}
+
+/// Progress of the copies to an engine's replica, see `ReplicatedStorage`. Shared with the database so the lag
+/// can be reported without taking the storage lock
+#[derive(Default)]
+pub struct StorageReplication {
+    state: Mutex<ReplicationState>,
+}
+
+impl StorageReplication {
+    fn enable(&self) {
+        self.state.lock().unwrap().enabled = true;
+    }
+
+    fn enqueued(&self) {
+        self.state.lock().unwrap().pending.push_back(Instant::now());
+    }
+
+    fn replicated(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        state.pending.pop_front();
+        state.replicated += 1;
+    }
+
+    fn failed(&self, error: &StorageError) {
+        let mut state = self.state.lock().unwrap();
+
+        state.failures += 1;
+        state.last_error = Some(format!("{:?}", error));
+    }
+
+    /// Writes that have not been copied to the replica yet
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// How long ago the oldest write that has not been copied was made, zero once the replica has caught up
+    pub fn lag(&self) -> Duration {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .front()
+            .map_or(Duration::ZERO, Instant::elapsed)
+    }
+
+    /// Empty unless the engine has a replica
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+
+        if !state.enabled {
+            return vec![];
+        }
+
+        let lag = state
+            .pending
+            .front()
+            .map_or(Duration::ZERO, Instant::elapsed);
+
+        vec![
+            (
+                "ReplicationPending".to_string(),
+                state.pending.len().to_string(),
+            ),
+            (
+                "ReplicationLagMs".to_string(),
+                format!("{:.3}", lag.as_secs_f64() * 1000.0),
+            ),
+            (
+                "ReplicationReplicated".to_string(),
+                state.replicated.to_string(),
+            ),
+            (
+                "ReplicationFailures".to_string(),
+                state.failures.to_string(),
+            ),
+        ]
+        .into_iter()
+        .chain(
+            state
+                .last_error
+                .clone()
+                .map(|error| ("ReplicationLastError".to_string(), error)),
+        )
+        .collect()
+    }
+}
+
+/// Writes to the primary engine and copies every write to a replica (e.g. a bucket in another region) on a
+/// background thread, so a write only waits for the primary. Copies are made in the order of the writes and are
+/// retried until they succeed, how far behind the replica is is reported by `StorageReplication`
+///
+/// If the primary cannot be read, e.g. during a regional outage, blobs and the WAL are read from the replica
+/// instead. Only a replica that has caught up is read from, otherwise it may be missing writes the primary
+/// acknowledged. Writes that have not been copied when the process exits are lost from the replica until the
+/// next snapshot rewrites them
+pub struct ReplicatedStorage<S: Storage> {
+    primary: S,
+    replica: Arc<Mutex<Box<dyn Storage + Sync + Send>>>,
+    sender: flume::Sender<ReplicationOp>,
+    replication: Arc<StorageReplication>,
+}
+
+impl<S: Storage> ReplicatedStorage<S> {
+    pub fn new(
+        primary: S,
+        replica: Box<dyn Storage + Sync + Send>,
+        replication: Arc<StorageReplication>,
+    ) -> Self {
+        let replica = Arc::new(Mutex::new(replica));
+        let (sender, receiver) = flume::unbounded::<ReplicationOp>();
+
+        replication.enable();
+
+        {
+            let replica = replica.clone();
+            let replication = replication.clone();
+
+            // Exits once the storage is dropped and the remaining copies have been made
+            thread::Builder::new()
+                .name("Replication".to_string())
+                .spawn(move || {
+                    for op in receiver.iter() {
+                        copy(&op, &replica, &replication);
+                    }
+                })
+                .expect("Should be able to spawn the replication thread");
+        }
+
+        Self {
+            primary,
+            replica,
+            sender,
+            replication,
+        }
+    }
+
+    fn replicate(&self, op: ReplicationOp) {
+        self.replication.enqueued();
+
+        // The thread only exits once the sender is dropped
+        let _ = self.sender.send(op);
+    }
+
+    /// The replica is only read from once it has every write the primary acknowledged
+    fn failover<T>(
+        &self,
+        error: StorageError,
+        read: impl FnOnce(&mut dyn Storage) -> StorageResult<T>,
+    ) -> StorageResult<T> {
+        if self.replication.pending() > 0 {
+            return Err(error);
+        }
+
+        log::warn!(
+            "Replication: reading from the replica, the primary failed: {:?}",
+            error
+        );
+
+        read(self.replica.lock().unwrap().as_mut())
+    }
+}
+
+fn copy(
+    op: &ReplicationOp,
+    replica: &Mutex<Box<dyn Storage + Sync + Send>>,
+    replication: &StorageReplication,
+) {
+    let mut retry_interval = INITIAL_RETRY_INTERVAL;
+
+    loop {
+        let result = op.apply(replica.lock().unwrap().as_mut());
+
+        match result {
+            Ok(()) => {
+                replication.replicated();
+                return;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Replication: copy failed, retrying in {:?}: {:?}",
+                    retry_interval,
+                    e
+                );
+
+                replication.failed(&e);
+                thread::sleep(retry_interval);
+                retry_interval = (retry_interval * 2).min(MAX_RETRY_INTERVAL);
+            }
+        }
+    }
+}
+
+impl<S: Storage> Storage for ReplicatedStorage<S> {
+    /// The replica is initialized as well, the database still starts if only one of them could be initialized so
+    /// that it can be restored from the other
+    fn init(&mut self) -> StorageResult<()> {
+        let primary = self.primary.init();
+        let replica = self.replica.lock().unwrap().init();
+
+        match (primary, replica) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(e), Ok(())) => {
+                log::error!("Replication: unable to initialize the primary: {:?}", e);
+                Ok(())
+            }
+            (Ok(()), Err(e)) => {
+                log::error!("Replication: unable to initialize the replica: {:?}", e);
+                self.replication.failed(&e);
+                Ok(())
+            }
+            (Err(e), Err(_)) => Err(e),
+        }
+    }
+
+    fn reset_database(&mut self) -> StorageResult<()> {
+        self.primary.reset_database()?;
+        self.replicate(ReplicationOp::Reset);
+
+        Ok(())
+    }
+
+    fn write_blob(&self, path: String, bytes: Vec<u8>) -> StorageResult<()> {
+        self.primary.write_blob(path.clone(), bytes.clone())?;
+        self.replicate(ReplicationOp::WriteBlob(path, bytes));
+
+        Ok(())
+    }
+
+    fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
+        match self.primary.read_blob(path.clone()) {
+            Ok(state) => Ok(state),
+            Err(e) => self.failover(e, |replica| replica.read_blob(path)),
+        }
+    }
+
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        let results = self.primary.write_blobs(blobs.clone(), parallelism);
+
+        for ((path, bytes), result) in blobs.into_iter().zip(&results) {
+            if result.is_ok() {
+                self.replicate(ReplicationOp::WriteBlob(path, bytes));
+            }
+        }
+
+        results
+    }
+
+    fn delete_blob(&self, path: String) -> StorageResult<()> {
+        self.primary.delete_blob(path.clone())?;
+        self.replicate(ReplicationOp::DeleteBlob(path));
+
+        Ok(())
+    }
+
+    fn transaction_write(&mut self, transaction: &[u8]) -> StorageResult<()> {
+        self.primary.transaction_write(transaction)?;
+        self.replicate(ReplicationOp::TransactionWrite(transaction.to_vec()));
+
+        Ok(())
+    }
+
+    fn transaction_write_batch(&mut self, transactions: &[Vec<u8>]) -> StorageResult<()> {
+        self.primary.transaction_write_batch(transactions)?;
+
+        for transaction in transactions {
+            self.replicate(ReplicationOp::TransactionWrite(transaction.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn transaction_sync(&self) -> StorageResult<()> {
+        self.primary.transaction_sync()
+    }
+
+    fn transaction_flush(&mut self) -> StorageResult<()> {
+        self.primary.transaction_flush()?;
+        self.replicate(ReplicationOp::TransactionFlush);
+
+        Ok(())
+    }
+
+    fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+        match self.primary.transaction_load() {
+            Ok(transactions) => Ok(transactions),
+            Err(e) => self.failover(e, |replica| replica.transaction_load()),
+        }
+    }
+
+    // The retained transactions cannot be sent to the replication thread, the replica's WAL is only rewritten
+    // by the next flush
+    fn transaction_compact(&mut self, retain: &dyn Fn(&str) -> bool) -> StorageResult<usize> {
+        self.primary.transaction_compact(retain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::persistence::{
+        storage::file::{FileOptions, FileStorage},
+        transaction::TransactionWriteMode,
+    };
+
+    use super::*;
+
+    fn file_storage() -> FileStorage {
+        let path: PathBuf = ["/", "tmp", "lineagedb", &Uuid::new_v4().to_string()]
+            .iter()
+            .collect();
+
+        FileStorage::new(FileOptions::new(path), TransactionWriteMode::Off)
+    }
+
+    fn wait_for_replica(replication: &StorageReplication) {
+        let started = Instant::now();
+
+        while replication.pending() > 0 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Fails every call, a primary in a region that is down
+    struct Unavailable;
+
+    impl Storage for Unavailable {
+        fn init(&mut self) -> StorageResult<()> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn reset_database(&mut self) -> StorageResult<()> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn write_blob(&self, _: String, _: Vec<u8>) -> StorageResult<()> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn read_blob(&self, _: String) -> StorageResult<ReadBlobState> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn transaction_write(&mut self, _: &[u8]) -> StorageResult<()> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn transaction_sync(&self) -> StorageResult<()> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn transaction_flush(&mut self) -> StorageResult<()> {
+            Err(StorageError::Unhandled)
+        }
+
+        fn transaction_load(&mut self) -> StorageResult<Vec<String>> {
+            Err(StorageError::Unhandled)
+        }
+    }
+
+    #[test]
+    fn copies_writes_and_fails_over_to_the_replica() {
+        let replica = file_storage();
+        let replication = Arc::new(StorageReplication::default());
+
+        let mut storage =
+            ReplicatedStorage::new(file_storage(), Box::new(replica), replication.clone());
+        storage.init().unwrap();
+
+        storage.write_blob("metadata".to_string(), vec![1]).unwrap();
+        storage.transaction_write(b"{\"id\":1}").unwrap();
+        storage.transaction_write(b"{\"id\":2}").unwrap();
+
+        wait_for_replica(&replication);
+
+        assert!(replication
+            .get_stats()
+            .contains(&("ReplicationReplicated".to_string(), "3".to_string())));
+        assert_eq!(replication.lag(), Duration::ZERO);
+
+        // The primary's region goes down, the replica is read instead
+        let mut failed_over = ReplicatedStorage {
+            primary: Unavailable,
+            replica: storage.replica.clone(),
+            sender: storage.sender.clone(),
+            replication: replication.clone(),
+        };
+
+        assert!(matches!(
+            failed_over.read_blob("metadata".to_string()).unwrap(),
+            ReadBlobState::Found(bytes) if bytes == vec![1]
+        ));
+        assert_eq!(failed_over.transaction_load().unwrap().len(), 2);
+
+        // Writes are not acknowledged without the primary
+        assert!(failed_over
+            .write_blob("metadata".to_string(), vec![2])
+            .is_err());
+    }
+}
@@ -1,12 +1,24 @@
-use std::{future::Future, path::PathBuf, pin::Pin, sync::Arc};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
 
 use anyhow::anyhow;
-use aws_sdk_s3::{primitives::ByteStream, Client, Error as S3Error};
+use aws_sdk_s3::{
+    config::{Builder, Region},
+    primitives::ByteStream,
+    Client, Error as S3Error,
+};
 use chrono::Utc;
 use tokio::sync::mpsc::{self};
 
 use super::{
-    network::{start_runtime, NetworkStorage, NetworkStorageAction},
+    network::{
+        start_runtime, NetworkStorage, NetworkStorageAction, StorageLatency, StorageTimeouts,
+    },
+    secret::load_aws_config,
     ReadBlobState, Storage, StorageError, StorageResult,
 };
 
@@ -17,15 +29,23 @@ pub struct S3Storage {
 }
 
 impl S3Storage {
-    pub fn new(options: S3Options) -> Self {
+    pub fn new(
+        options: S3Options,
+        timeouts: StorageTimeouts,
+        latency: Arc<StorageLatency>,
+    ) -> Self {
         let (action_sender, action_receiver) = mpsc::channel::<NetworkStorageAction>(16);
 
-        start_runtime(action_receiver, options, task_fn, client_fn);
+        let runtime = start_runtime(
+            action_receiver,
+            timeouts.clone(),
+            options,
+            task_fn,
+            client_fn,
+        );
 
         Self {
-            network_storage: NetworkStorage {
-                action_sender: action_sender,
-            },
+            network_storage: NetworkStorage::new(action_sender, runtime, timeouts, latency),
         }
     }
 }
@@ -33,30 +53,133 @@ impl S3Storage {
 #[derive(Clone, Debug)]
 pub struct S3Options {
     pub bucket: String,
+    /// AWS profile used for credentials, see `load_aws_config`
+    pub profile: Option<String>,
+    /// Region of the bucket, defaults to the region of the profile
+    pub region: Option<String>,
+    /// Bucket every write is copied to, see `set_replica`
+    pub replica: Option<S3Replica>,
     base_path: PathBuf,
+    namespace: Option<String>,
+}
+
+/// A bucket, usually in another region, that the snapshots and WAL are copied to for disaster recovery
+#[derive(Clone, Debug)]
+pub struct S3Replica {
+    pub bucket: String,
+    pub region: Option<String>,
+    /// Defaults to the primary bucket's profile
+    pub profile: Option<String>,
+}
+
+impl S3Replica {
+    pub fn new(bucket: String) -> Self {
+        Self {
+            bucket,
+            region: None,
+            profile: None,
+        }
+    }
+
+    pub fn set_region(mut self, region: String) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn set_profile(mut self, profile: String) -> Self {
+        self.profile = Some(profile);
+        self
+    }
 }
 
 impl S3Options {
     pub fn new(bucket: String) -> Self {
         Self {
             base_path: PathBuf::from("data"),
+            profile: None,
+            region: None,
+            replica: None,
+            namespace: None,
             bucket,
         }
     }
 
+    pub fn set_profile(mut self, profile: String) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn set_region(mut self, region: String) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Copies every write to the replica in the background, writes only wait for the primary bucket. When the
+    /// primary bucket cannot be read, the database is restored from the replica, see `ReplicatedStorage`
+    pub fn set_replica(mut self, replica: S3Replica) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Prefix of every object key in the bucket [default: data]
+    pub fn set_base_path(mut self, base_path: PathBuf) -> Self {
+        self.base_path = base_path;
+        self
+    }
+
+    /// Stores the database under `<base path>/<namespace>/`, so databases can share the bucket. A reset only
+    /// deletes the namespace's objects
+    pub fn set_namespace(mut self, namespace: String) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// The replica is stored under the same keys as the primary bucket
+    pub(crate) fn get_replica_options(&self) -> Option<S3Options> {
+        self.replica.as_ref().map(|replica| Self {
+            bucket: replica.bucket.clone(),
+            profile: replica.profile.clone().or(self.profile.clone()),
+            region: replica.region.clone(),
+            replica: None,
+            base_path: self.base_path.clone(),
+            namespace: self.namespace.clone(),
+        })
+    }
+
+    /// Prefix of the database's object keys
+    fn get_key_prefix(&self) -> PathBuf {
+        match &self.namespace {
+            Some(namespace) => self.base_path.join(namespace),
+            None => self.base_path.clone(),
+        }
+    }
+
     pub fn new_test() -> Self {
         Self {
             base_path: PathBuf::from("data"),
+            profile: None,
+            region: None,
+            replica: None,
+            namespace: None,
             bucket: "dalesalter-test-bucket".to_string(),
         }
     }
 }
 
-fn client_fn(_: S3Options) -> Pin<Box<dyn Future<Output = Client> + Send + 'static>> {
-    Box::pin(async {
-        let sdk = aws_config::load_from_env().await;
+fn client_fn(options: S3Options) -> Pin<Box<dyn Future<Output = Client> + Send + 'static>> {
+    Box::pin(async move {
+        let sdk = load_aws_config(options.profile).await;
+
+        let config = match options.region {
+            Some(region) => Builder::from(&sdk).region(Region::new(region)),
+            None => Builder::from(&sdk),
+        };
 
-        Client::new(&sdk)
+        Client::from_conf(config.build())
     })
 }
 
@@ -74,6 +197,14 @@ impl Storage for S3Storage {
         self.network_storage.write_blob(path, bytes)
     }
 
+    fn write_blobs(
+        &self,
+        blobs: Vec<(String, Vec<u8>)>,
+        parallelism: usize,
+    ) -> Vec<StorageResult<()>> {
+        self.network_storage.write_blobs(blobs, parallelism)
+    }
+
     fn read_blob(&self, path: String) -> StorageResult<ReadBlobState> {
         self.network_storage.read_blob(path)
     }
@@ -106,7 +237,7 @@ fn task_fn(
 ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
     Box::pin(async move {
         let bucket = &data.bucket;
-        let base_path = data.base_path;
+        let base_path = data.get_key_prefix();
 
         match action {
             NetworkStorageAction::Init(r) => {
@@ -208,10 +339,15 @@ fn task_fn(
     })
 }
 
+/// Objects under the directory `path`, the trailing `/` keeps e.g. `data/dev` from listing `data/dev2`
+fn directory_prefix(path: &Path) -> String {
+    format!("{}/", path.to_str().unwrap().trim_end_matches('/'))
+}
+
 async fn delete_files_at_path(client: &Client, bucket: &str, path: PathBuf) -> StorageResult<()> {
     let mut response = client
         .list_objects_v2()
-        .prefix(path.to_str().unwrap())
+        .prefix(directory_prefix(&path))
         .bucket(bucket)
         .max_keys(10)
         .into_paginator()
@@ -246,7 +382,7 @@ async fn get_file_contents_at_path(
 ) -> StorageResult<Vec<String>> {
     let mut response = client
         .list_objects_v2()
-        .prefix(path.to_str().unwrap())
+        .prefix(directory_prefix(&path))
         .bucket(bucket)
         .max_keys(10)
         .into_paginator()
@@ -266,9 +402,17 @@ async fn get_file_contents_at_path(
                         .await
                         .map_err(|e| StorageError::UnableToLoadPreviousTransactions(anyhow!(e)))?;
 
-                    let result_bytes = result.body.collect().await.unwrap().into_bytes();
+                    let result_bytes = result
+                        .body
+                        .collect()
+                        .await
+                        .map_err(|e| StorageError::UnableToLoadPreviousTransactions(anyhow!(e)))?
+                        .into_bytes();
+
+                    let transaction = std::str::from_utf8(&result_bytes)
+                        .map_err(|e| StorageError::UnableToLoadPreviousTransactions(anyhow!(e)))?;
 
-                    contents.push(std::str::from_utf8(&result_bytes).unwrap().to_string());
+                    contents.push(transaction.to_string());
                 }
             }
             Err(err) => {
@@ -0,0 +1,70 @@
+use std::{fmt, fs, io, path::Path};
+
+/// A storage credential, `Debug` and `Display` never print the value so options can be logged safely
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    /// Reads the secret from a file, e.g. a mounted Docker / Kubernetes secret. Trailing newlines are ignored
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+
+        Ok(Self(contents.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", self)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+/// AWS profile used by an engine, falls back to the default credential chain (`AWS_PROFILE`, environment
+/// variables, instance role) when no profile is set
+#[cfg(any(feature = "s3", feature = "dynamodb"))]
+pub async fn load_aws_config(profile: Option<String>) -> aws_config::SdkConfig {
+    let loader = aws_config::from_env();
+
+    match profile {
+        Some(profile) => loader.profile_name(profile).load().await,
+        None => loader.load().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let path: PathBuf = ["/", "tmp", &format!("lineagedb-secret-{}", Uuid::new_v4())]
+            .iter()
+            .collect();
+        fs::write(&path, "hunter2\n").unwrap();
+
+        let secret = Secret::from_file(&path).unwrap();
+
+        assert_eq!(secret.expose(), "hunter2");
+        assert!(!format!("{:?} {}", secret, secret).contains("hunter2"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
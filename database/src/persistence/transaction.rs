@@ -1,8 +1,10 @@
 use oneshot::Sender;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Serialize};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use strum::VariantNames;
 
 use crate::consts::consts::TransactionId;
 use crate::database::commands::DatabaseCommandResponse;
@@ -12,24 +14,59 @@ use crate::database::orchestrator::DatabasePauseEvent;
 use crate::database::utils::crash::{crash_database, DatabaseCrash};
 use crate::model::statement::Statement;
 
-use super::storage::{Storage, StorageResult};
+use super::field_encryption::FieldCipher;
+use super::pre_commit::{PreCommitFailure, PreCommitRunner};
+use super::storage::{Storage, StorageError, StorageResult};
+use super::wal_batching::WalBatching;
 
 // Todo: use this status to denote if we have done an fsync on the transaction log
 //  once fsync is done, THEN we can consider the transaction committed / durable
 //  then we can send the message to the caller that we have committed the transaction
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TransactionStatus {
     Committed,
+    /// Phase one of a two-phase commit (global transaction id), the statements are not applied until the
+    /// transaction commits, see `PreparedTransactions`
+    Prepared(String),
+    /// The prepared transaction committed, the statements are applied
+    CommitPrepared(String),
+    /// The prepared transaction was rolled back, there are no statements
+    AbortPrepared(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TransactionFileWriteMode {
     /// Writes the file to disk and performs a batched fsync
     Sync,
+    /// Writes the file to disk and performs a batched fdatasync, this skips flushing file metadata
+    /// (e.g. modified time) which can be faster than an fsync
+    DataSync,
+    /// Opens the WAL with O_DSYNC, every write is durable once it returns so there is no batched sync
+    DSync,
     /// Writes the file to disk, lets the OS buffer the writes
     OSBuffered,
 }
 
+impl TransactionFileWriteMode {
+    /// Whether the WAL thread needs to perform a batched sync once it has written the transactions
+    pub fn requires_batch_sync(&self) -> bool {
+        match self {
+            TransactionFileWriteMode::Sync | TransactionFileWriteMode::DataSync => true,
+            TransactionFileWriteMode::DSync | TransactionFileWriteMode::OSBuffered => false,
+        }
+    }
+
+    /// Whether the mode guarantees a transaction is durable before the caller is told it has committed
+    pub fn is_durable(&self) -> bool {
+        match self {
+            TransactionFileWriteMode::Sync
+            | TransactionFileWriteMode::DataSync
+            | TransactionFileWriteMode::DSync => true,
+            TransactionFileWriteMode::OSBuffered => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TransactionWriteMode {
     /// Writes the WAL to disk
@@ -38,18 +75,177 @@ pub enum TransactionWriteMode {
     Off,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
     pub id: TransactionId,
     pub statements: Vec<Statement>,
     pub status: TransactionStatus,
 }
 
+/// Version of the statement encoding, written with every WAL transaction. Adding a `Statement` variant does not
+/// change it (statements are tagged with their variant name), changing how an existing statement is encoded does
+pub const STATEMENT_FORMAT_VERSION: u32 = 1;
+
+/// What decoding the WAL does with a statement this build does not know, i.e. one written by a newer build during a
+/// rolling upgrade that is then replayed by an older build. A statement is unknown if its variant does not exist
+/// in this build, or if it cannot be decoded and was written with a newer `STATEMENT_FORMAT_VERSION`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UnknownStatementPolicy {
+    /// Decoding the transaction fails, so does the restore. The WAL is left as it is for a newer build to replay
+    #[default]
+    Fail,
+    /// The statement is logged and left out of its transaction, the other statements are replayed. A snapshot
+    /// taken afterwards does not include the statement's changes
+    Skip,
+}
+
+/// A transaction as it is written to the WAL
+#[derive(Serialize)]
+struct WalTransaction<'a> {
+    #[serde(flatten)]
+    transaction: &'a Transaction,
+    format: u32,
+}
+
+/// A WAL transaction whose statements have not been decoded yet, see `Transaction::from_wal`
+#[derive(Deserialize)]
+struct EncodedTransaction {
+    id: TransactionId,
+    statements: Vec<serde_json::Value>,
+    status: TransactionStatus,
+    /// Missing for transactions written before the format was versioned, which were encoded as version 1
+    #[serde(default)]
+    format: u32,
+}
+
+impl Transaction {
+    /// Encodes the transaction as a line of the WAL
+    pub fn to_wal(&self) -> Vec<u8> {
+        serde_json::to_vec(&WalTransaction {
+            transaction: self,
+            format: STATEMENT_FORMAT_VERSION,
+        })
+        .unwrap()
+    }
+
+    /// Decodes a transaction of the WAL. The WAL is read back from storage, so a malformed transaction (e.g. a
+    /// torn write or a corrupt file) is an error rather than a panic. Statements written by a newer build are
+    /// handled according to `unknown_statements`
+    pub fn from_wal(
+        transaction: &str,
+        unknown_statements: UnknownStatementPolicy,
+    ) -> Result<Self, serde_json::Error> {
+        // Every statement is known unless the WAL was written by a newer build
+        if let Ok(transaction) = serde_json::from_str(transaction) {
+            return Ok(transaction);
+        }
+
+        let encoded: EncodedTransaction = serde_json::from_str(transaction)?;
+        let mut statements = Vec::with_capacity(encoded.statements.len());
+
+        for (index, statement) in encoded.statements.into_iter().enumerate() {
+            let variant = match &statement {
+                serde_json::Value::String(variant) => Some(variant.clone()),
+                serde_json::Value::Object(tagged) if tagged.len() == 1 => {
+                    tagged.keys().next().cloned()
+                }
+                _ => None,
+            };
+
+            let e = match serde_json::from_value(statement) {
+                Ok(statement) => {
+                    statements.push(statement);
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            let unknown = match &variant {
+                Some(variant) => {
+                    !Statement::VARIANTS.contains(&variant.as_str())
+                        || encoded.format > STATEMENT_FORMAT_VERSION
+                }
+                None => false,
+            };
+
+            match (unknown, unknown_statements) {
+                (true, UnknownStatementPolicy::Skip) => {
+                    log::warn!(
+                        "Skipping statement {} ({}) of transaction {}, written with statement format {}: {}",
+                        index,
+                        variant.unwrap_or_default(),
+                        encoded.id,
+                        encoded.format,
+                        e
+                    );
+                }
+                (true, UnknownStatementPolicy::Fail) => {
+                    return Err(serde_json::Error::custom(format!(
+                        "statement {} ({}) is not known to this build (statement format {}, written with {}): {}",
+                        index,
+                        variant.unwrap_or_default(),
+                        STATEMENT_FORMAT_VERSION,
+                        encoded.format,
+                        e
+                    )));
+                }
+                (false, _) => return Err(e),
+            }
+        }
+
+        Ok(Transaction {
+            id: encoded.id,
+            statements,
+            status: encoded.status,
+        })
+    }
+}
+
+/// Only the id of a WAL transaction, used to decide if a transaction is kept without decoding its statements
+#[derive(Deserialize)]
+struct TransactionHeader {
+    id: TransactionId,
+}
+
 pub struct TransactionCommitData {
     applied_transaction_id: TransactionId,
     statements: Vec<Statement>,
+    status: TransactionStatus,
     response: DatabaseCommandResponse,
     resolver: oneshot::Sender<DatabaseCommandResponse>,
+    queued_at: Instant,
+}
+
+/// Commits that were sent to the Transaction Manager thread and have not been written and responded to yet, see
+/// `TransactionWAL::wait_for_commits`
+#[derive(Default)]
+struct InFlightCommits {
+    count: Mutex<usize>,
+    written: Condvar,
+}
+
+impl InFlightCommits {
+    fn sent(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    fn written(&self, commits: usize) {
+        let mut count = self.count.lock().unwrap();
+
+        *count = count.saturating_sub(commits);
+
+        if *count == 0 {
+            self.written.notify_all();
+        }
+    }
+
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+
+        while *count > 0 {
+            count = self.written.wait(count).unwrap();
+        }
+    }
 }
 
 pub enum TransactionWalStatus {
@@ -65,134 +261,163 @@ pub struct TransactionWAL {
     size: AtomicUsize,
     commit_sender: TransactionWalStatus,
     storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    /// If set, sensitive fields are encrypted in the WAL
+    field_cipher: Option<Arc<FieldCipher>>,
+    /// See `add_committed_listener`
+    committed_listeners: Arc<Mutex<Vec<flume::Sender<Transaction>>>>,
+    /// See `wait_for_commits`
+    in_flight: Arc<InFlightCommits>,
+    batching: Arc<WalBatching>,
+    /// The Transaction Manager thread, joined by `close`
+    thread: Option<JoinHandle<()>>,
 }
 
 impl TransactionWAL {
     pub fn new(
         database_options: DatabaseOptions,
         storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+        field_cipher: Option<Arc<FieldCipher>>,
     ) -> Self {
+        let batching = Arc::new(WalBatching::new(
+            database_options.adaptive_wal_batching.clone(),
+        ));
+
         Self {
             current_transaction_id: LocalClock::new(),
             size: AtomicUsize::new(0),
             database_options,
             commit_sender: TransactionWalStatus::Uninitialized,
             storage,
+            field_cipher,
+            committed_listeners: Arc::new(Mutex::new(vec![])),
+            in_flight: Arc::new(InFlightCommits::default()),
+            batching,
+            thread: None,
         }
     }
 
+    /// Committed transactions (including prepared transactions that committed) are sent to every listener once
+    /// they are durable, in commit order. Sensitive fields are encrypted as they are in the WAL. A listener is
+    /// removed once its receiver is dropped
+    pub fn add_committed_listener(&self, listener: flume::Sender<Transaction>) {
+        self.committed_listeners.lock().unwrap().push(listener);
+    }
+
     pub fn init(&mut self) {
+        // A re-initialized WAL starts a new Transaction Manager thread
+        self.join();
+
         let sync_file_write = self.database_options.write_mode.clone();
         let storage_thread = self.storage.clone();
+        let field_cipher = self.field_cipher.clone();
+        let committed_listeners = self.committed_listeners.clone();
+        let in_flight = self.in_flight.clone();
+        let batching = self.batching.clone();
+        let pre_commit = self
+            .database_options
+            .pre_commit_hook
+            .clone()
+            .map(PreCommitRunner::new);
+        #[cfg(feature = "thread-tuning")]
+        let placement = self
+            .database_options
+            .thread_tuning
+            .as_ref()
+            .map(|thread_tuning| thread_tuning.wal.clone());
 
         let (sender, receiver) = flume::unbounded::<TransactionCommitData>();
 
         // Mark the WAL as ready to accept transactions
         self.commit_sender = TransactionWalStatus::Ready(sender);
 
-        let _ = thread::Builder::new()
+        let pipeline = self.database_options.pipeline_wal;
+
+        let thread = thread::Builder::new()
             .name("Transaction Manager".to_string())
             .spawn(move || {
-                let worker_storage = storage_thread;
-
-                loop {
-                    let mut batch: Vec<(Sender<DatabaseCommandResponse>, DatabaseCommandResponse)> =
-                        vec![];
-
-                    log::debug!("Start");
-
-                    // Receiver.recv() gives us a nice blocking call
-                    let Ok(blocking_data) = receiver.recv() else {
-                        // Error will be because the sender has been dropped, we can safely exit the thread
-                        return
-                    };                    
-
-                    // once the thread is token up we use `try_iter` to attempt to take a decent batch
-                    let batched_data = vec![blocking_data].into_iter()
-                        .chain(receiver.try_iter().take(50).collect::<Vec<TransactionCommitData>>())
-                        .collect::<Vec<TransactionCommitData>>();
-
-                    // Then we can persist the transactions to disk
-                    for transaction_data in batched_data.into_iter() {
-                        log::debug!("Processing Data");
-
-                        let TransactionCommitData {
-                            applied_transaction_id,
-                            statements,
-                            response,
-                            resolver,
-                        } = transaction_data;
-
-                        if matches!(sync_file_write, TransactionWriteMode::File(_)) {
-                            let transaction_json_line = format!(
-                                "{}",
-                                serde_json::to_string(&Transaction {
-                                    id: applied_transaction_id,
-                                    statements: statements,
-                                    status: TransactionStatus::Committed,
-                                })
-                                .unwrap()
-                            );
-
-                            // - NOTE: For disk, this is fast (because it is technically async, the OS will buffer the writes)
-                            //  though for S3 it is very slow, is there any way we can buffer this?
-                            let result = worker_storage
-                                .lock()
-                                .unwrap()
-                                .transaction_write(transaction_json_line.as_bytes());
-
-                            // There are a few problems here:
-                            // 1. We are 'committing' to world state, and other writes can read that commit BEFORE it is durable to disk.
-                            //      the only benefit to this approach is that at least we are not responding committed to the CLIENT until it is durable.
-                            // 2. The above is not great -- though this type of error is especially bad, this is because once we get to this point
-                            //      of not being able to commit the transaction to disk, the world state is now invalid and non-recoverable w/o
-                            //      restoring from the existing WAL / snapshot. Crash, and let the caller restart the DB process.
-                            if let Err(e) = result {
-                                let _ =
-                                    resolver.send(DatabaseCommandResponse::transaction_rollback(
-                                        "Transaction aborted. Critical error writing to WAL, world state is invalid. Database crash",
-                                    ));
-
-                                crash_database(DatabaseCrash::InconsistentUncommittedInMemoryWorldStateFromWALWrite(e));
-                            }
-                        }
+                #[cfg(feature = "thread-tuning")]
+                if let Some(placement) = &placement {
+                    placement.apply_to_current_thread();
+                }
 
-                        batch.push((resolver, response));
+                let stage = WalStage {
+                    write_mode: sync_file_write,
+                    storage: storage_thread,
+                    field_cipher,
+                    committed_listeners,
+                    in_flight,
+                    batching,
+                    pre_commit,
+                };
+
+                if !pipeline {
+                    while let Some(batch) = stage.receive_batch(&receiver) {
+                        stage.flush(batch);
                     }
 
-                    // Performs an fsync on the transaction log, ensuring that the transaction is durable
-                    // https://www.postgresql.org/docs/current/wal-reliability.html
-                    //
-                    // Note: This is a slow operation and if possible we should allow multiple transactions to be committed at once
-                    //   e.g. every 5ms, we flush the log and send back to the caller we have committed.
-                    //
-                    // Note: The observed speed of fsync is ~3ms on my machine. This is a _very_ slow operation.
-                    if batch.len() > 0 {
-                        if let TransactionWriteMode::File(m) = &sync_file_write {
-                            if m == &TransactionFileWriteMode::Sync {
-                                let transaction_sync_error_result = worker_storage.lock().unwrap().transaction_sync();
-    
-                                if let Err(e) = transaction_sync_error_result {
-                                    log::error!("Unable to fsync transaction to disk: {}", e);
-    
-                                    for (resolver, _) in batch {
-                                        let _ = resolver.send(DatabaseCommandResponse::transaction_status(
-                                            "Unable to flush transaction to disk, unsure if transaction is durable",
-                                        ));
-                                    }
-    
-                                    continue;
-                                }
-    
-                            }
+                    return;
+                }
+
+                // The next batch is serialized while the previous batch is being written, holding at most one
+                //  batch back means commits keep batching up in the channel while the storage is busy
+                let (batch_sender, batch_receiver) = flume::bounded::<SerializedBatch>(1);
+                let flush_stage = stage.clone();
+
+                let writer = thread::Builder::new()
+                    .name("WAL Writer".to_string())
+                    .spawn(move || {
+                        #[cfg(feature = "thread-tuning")]
+                        if let Some(placement) = &placement {
+                            placement.apply_to_current_thread();
                         }
-                    }
 
-                    for (resolver, response) in batch {
-                        let _ = resolver.send(response);
+                        // Batches are flushed in the order they were received, so responses keep commit order
+                        for batch in batch_receiver.iter() {
+                            flush_stage.flush(batch);
+                        }
+                    })
+                    .expect("Should be able to spawn the WAL Writer thread");
+
+                while let Some(batch) = stage.receive_batch(&receiver) {
+                    if batch_sender.send(batch).is_err() {
+                        break;
                     }
                 }
+
+                // Every batch that has been received is written before the WAL is closed
+                drop(batch_sender);
+
+                if writer.join().is_err() {
+                    log::error!("The WAL Writer thread panicked");
+                }
             });
+
+        self.thread = Some(thread.expect("Should be able to spawn the Transaction Manager thread"));
+    }
+
+    /// Stops accepting commits and waits for the Transaction Manager thread to write the commits it has already
+    /// received, then drops the committed listeners (see `add_committed_listener`) so the listeners can exit too
+    pub fn close(&mut self) {
+        self.join();
+
+        self.committed_listeners.lock().unwrap().clear();
+    }
+
+    fn join(&mut self) {
+        self.commit_sender = TransactionWalStatus::Uninitialized;
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("The Transaction Manager thread panicked");
+            }
+        }
+    }
+
+    /// Waits until every commit sent to the Transaction Manager thread has been written to the WAL and responded
+    /// to. With the database paused no new commits are applied, so afterwards the WAL holds every transaction
+    /// that was applied in memory and none of them can be written after the WAL is flushed or reset
+    pub fn wait_for_commits(&self, _: &DatabasePauseEvent) {
+        self.in_flight.wait();
     }
 
     // We have persisted the current state, we can delete the transaction log
@@ -206,10 +431,39 @@ impl TransactionWAL {
         Ok(flushed_size)
     }
 
+    /// Drops the transactions covered by the snapshot taken at `snapshot_transaction_id` from the log. Unlike
+    /// `flush_transactions` the database does not need to be paused, commits wait on the storage lock while the
+    /// log is rewritten
+    pub fn compact(&self, snapshot_transaction_id: &TransactionId) -> StorageResult<usize> {
+        // The snapshot's transaction id belongs to the snapshot request itself, after a restore the clock resumes
+        //  from it so a transaction with the same id is not a part of the snapshot
+        let retain =
+            |transaction: &str| match serde_json::from_str::<TransactionHeader>(transaction) {
+                Ok(header) => &header.id >= snapshot_transaction_id,
+                // Unreadable transactions are kept so that the restore reports them
+                Err(_) => true,
+            };
+
+        let dropped = self.storage.lock().unwrap().transaction_compact(&retain)?;
+
+        let _ = self
+            .size
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |size| {
+                Some(size.saturating_sub(dropped))
+            });
+
+        Ok(dropped)
+    }
+
     pub fn get_wal_size(&self) -> usize {
         self.size.load(Ordering::SeqCst)
     }
 
+    /// The batch sizes the WAL chooses and the latencies it chooses them from, see `WalBatching`
+    pub fn get_batching_stats(&self) -> Vec<(String, String)> {
+        self.batching.get_stats()
+    }
+
     pub fn get_increment_current_transaction_id(&self) -> TransactionId {
         self.current_transaction_id.get_timestamp()
     }
@@ -220,17 +474,40 @@ impl TransactionWAL {
         statements: Vec<Statement>,
         response: DatabaseCommandResponse,
         mode: ApplyMode,
+    ) {
+        self.write(
+            applied_transaction_id,
+            statements,
+            TransactionStatus::Committed,
+            response,
+            mode,
+        )
+    }
+
+    /// Same as `commit` for any record status, e.g. the prepare / commit records of a two-phase commit. The
+    /// response is sent once the record is durable
+    pub fn write(
+        &self,
+        applied_transaction_id: TransactionId,
+        statements: Vec<Statement>,
+        status: TransactionStatus,
+        response: DatabaseCommandResponse,
+        mode: ApplyMode,
     ) {
         if let ApplyMode::Request(resolver) = mode {
             let commit_data = TransactionCommitData {
                 applied_transaction_id: applied_transaction_id.clone(),
                 statements,
+                status,
                 response,
                 resolver,
+                queued_at: Instant::now(),
             };
 
             match self.commit_sender {
                 TransactionWalStatus::Ready(ref sender) => {
+                    self.in_flight.sent();
+
                     sender.send(commit_data).unwrap();
                 }
                 TransactionWalStatus::Uninitialized => {
@@ -246,13 +523,42 @@ impl TransactionWAL {
         self.size.fetch_add(1, Ordering::SeqCst);
     }
 
-    pub fn restore(&self) -> StorageResult<Vec<Transaction>> {
+    /// Loads the transactions to replay, `archived_transactions` (see `SnapshotManager::restore_snapshot`) are
+    /// replayed before the WAL
+    pub fn restore(&self, archived_transactions: Vec<String>) -> StorageResult<Vec<Transaction>> {
         let mut transactions: Vec<Transaction> = vec![];
 
         let transactions_data = self.storage.lock().unwrap().transaction_load()?;
 
-        for transaction_string in transactions_data {
-            transactions.push(serde_json::from_str(&transaction_string).unwrap());
+        for (index, transaction_string) in archived_transactions
+            .into_iter()
+            .chain(transactions_data)
+            .enumerate()
+        {
+            let mut transaction = Transaction::from_wal(
+                &transaction_string,
+                self.database_options.unknown_statements,
+            )
+            .map_err(|e| {
+                StorageError::UnableToLoadPreviousTransactions(anyhow::anyhow!(
+                    "Transaction {} could not be decoded: {}",
+                    index,
+                    e
+                ))
+            })?;
+
+            if let Some(cipher) = &self.field_cipher {
+                transaction.statements = transaction
+                    .statements
+                    .into_iter()
+                    .map(|statement| cipher.decrypt_statement(statement))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| {
+                        StorageError::UnableToLoadPreviousTransactions(anyhow::Error::new(e))
+                    })?;
+            }
+
+            transactions.push(transaction);
         }
 
         Ok(transactions)
@@ -263,6 +569,249 @@ impl TransactionWAL {
     }
 }
 
+/// A batch of commits that has been serialized and is waiting to be written, see `WalStage`
+struct SerializedBatch {
+    /// Empty if the WAL is not written to storage
+    transaction_json_lines: Vec<Vec<u8>>,
+    /// Sent to the committed listeners once the batch is durable
+    committed: Vec<Transaction>,
+    /// Every transaction of the batch, empty without a pre-commit hook
+    pre_commit: Vec<Transaction>,
+    responses: Vec<(Sender<DatabaseCommandResponse>, DatabaseCommandResponse)>,
+    /// When the batch's oldest commit was sent to the WAL
+    queued_at: Instant,
+}
+
+/// The two stages of the Transaction Manager, a batch is serialized and then flushed (written, synced and
+/// responded to). With `DatabaseOptions::set_pipeline_wal` the stages run on separate threads, so serializing a
+/// batch overlaps with the network write of the previous batch
+#[derive(Clone)]
+struct WalStage {
+    write_mode: TransactionWriteMode,
+    storage: Arc<Mutex<dyn Storage + Sync + Send>>,
+    field_cipher: Option<Arc<FieldCipher>>,
+    committed_listeners: Arc<Mutex<Vec<flume::Sender<Transaction>>>>,
+    in_flight: Arc<InFlightCommits>,
+    batching: Arc<WalBatching>,
+    pre_commit: Option<PreCommitRunner>,
+}
+
+impl WalStage {
+    /// Waits for the next commit and serializes it along with the commits that are already waiting, none once
+    /// the WAL is closed
+    fn receive_batch(
+        &self,
+        receiver: &flume::Receiver<TransactionCommitData>,
+    ) -> Option<SerializedBatch> {
+        log::debug!("Start");
+
+        // Receiver.recv() gives us a nice blocking call. Error will be because the sender has been dropped, we can
+        //  safely exit the thread
+        let blocking_data = receiver.recv().ok()?;
+
+        let queued_at = blocking_data.queued_at;
+
+        // once the thread is token up we use `try_iter` to attempt to take a batch of the size `WalBatching` chose
+        let batched_data = vec![blocking_data]
+            .into_iter()
+            .chain(
+                receiver
+                    .try_iter()
+                    .take(self.batching.limit().saturating_sub(1))
+                    .collect::<Vec<TransactionCommitData>>(),
+            )
+            .collect::<Vec<TransactionCommitData>>();
+
+        // Serialize the whole batch, so it can be written to the WAL in a single (vectored) write
+        let mut batch = SerializedBatch {
+            transaction_json_lines: vec![],
+            committed: vec![],
+            pre_commit: vec![],
+            responses: vec![],
+            queued_at,
+        };
+
+        let write_to_file = matches!(self.write_mode, TransactionWriteMode::File(_));
+        let has_listeners = !self.committed_listeners.lock().unwrap().is_empty();
+
+        for transaction_data in batched_data.into_iter() {
+            log::debug!("Processing Data");
+
+            let TransactionCommitData {
+                applied_transaction_id,
+                statements,
+                status,
+                response,
+                resolver,
+                ..
+            } = transaction_data;
+
+            let notify = has_listeners
+                && matches!(
+                    status,
+                    TransactionStatus::Committed | TransactionStatus::CommitPrepared(_)
+                );
+
+            if write_to_file || notify || self.pre_commit.is_some() {
+                let statements = match &self.field_cipher {
+                    Some(cipher) => statements
+                        .into_iter()
+                        .map(|statement| cipher.encrypt_statement(statement))
+                        .collect(),
+                    None => statements,
+                };
+
+                let transaction = Transaction {
+                    id: applied_transaction_id,
+                    statements,
+                    status,
+                };
+
+                if write_to_file {
+                    batch.transaction_json_lines.push(transaction.to_wal());
+                }
+
+                if self.pre_commit.is_some() {
+                    batch.pre_commit.push(transaction.clone());
+                }
+
+                if notify {
+                    batch.committed.push(transaction);
+                }
+            }
+
+            batch.responses.push((resolver, response));
+        }
+
+        Some(batch)
+    }
+
+    /// Writes the batch to the WAL and syncs it, the callers are only responded to once the batch is durable
+    fn flush(&self, batch: SerializedBatch) {
+        let commits = batch.responses.len();
+        let started_at = Instant::now();
+        let queue_wait = started_at.duration_since(batch.queued_at);
+
+        self.write_batch(batch);
+
+        self.batching
+            .record(commits, queue_wait, started_at.elapsed());
+
+        // Also once a write failed, its commits have been responded to
+        self.in_flight.written(commits);
+    }
+
+    fn write_batch(&self, batch: SerializedBatch) {
+        let SerializedBatch {
+            transaction_json_lines,
+            committed,
+            pre_commit,
+            responses,
+            ..
+        } = batch;
+
+        if !transaction_json_lines.is_empty() {
+            // - NOTE: For disk, this is fast (because it is technically async, the OS will buffer the writes)
+            //  though for S3 it is very slow, the batch at least lets the engine coalesce the writes
+            let result = self
+                .storage
+                .lock()
+                .unwrap()
+                .transaction_write_batch(&transaction_json_lines);
+
+            // There are a few problems here:
+            // 1. We are 'committing' to world state, and other writes can read that commit BEFORE it is durable to disk.
+            //      the only benefit to this approach is that at least we are not responding committed to the CLIENT until it is durable.
+            // 2. The above is not great -- though this type of error is especially bad, this is because once we get to this point
+            //      of not being able to commit the transaction to disk, the world state is now invalid and non-recoverable w/o
+            //      restoring from the existing WAL / snapshot. Crash, and let the caller restart the DB process.
+            if let Err(e) = result {
+                for (resolver, _) in responses {
+                    let _ = resolver.send(DatabaseCommandResponse::transaction_rollback(
+                        "Transaction aborted. Critical error writing to WAL, world state is invalid. Database crash",
+                    ));
+                }
+
+                crash_database(DatabaseCrash::InconsistentUncommittedInMemoryWorldStateFromWALWrite(e));
+            }
+        }
+
+        // Performs an fsync on the transaction log, ensuring that the transaction is durable
+        // https://www.postgresql.org/docs/current/wal-reliability.html
+        //
+        // Note: This is a slow operation and if possible we should allow multiple transactions to be committed at once
+        //   e.g. every 5ms, we flush the log and send back to the caller we have committed.
+        //
+        // Note: The observed speed of fsync is ~3ms on my machine. This is a _very_ slow operation.
+        if let TransactionWriteMode::File(m) = &self.write_mode {
+            if !responses.is_empty() && m.requires_batch_sync() {
+                let transaction_sync_error_result = self.storage.lock().unwrap().transaction_sync();
+
+                if let Err(e) = transaction_sync_error_result {
+                    log::error!("Unable to fsync transaction to disk: {}", e);
+
+                    for (resolver, _) in responses {
+                        let _ = resolver.send(DatabaseCommandResponse::transaction_status(
+                            "Unable to flush transaction to disk, unsure if transaction is durable",
+                        ));
+                    }
+
+                    return;
+                }
+            }
+        }
+
+        if let (Some(runner), false) = (&self.pre_commit, pre_commit.is_empty()) {
+            if let Err(e) = runner.run(pre_commit) {
+                match runner.on_failure() {
+                    PreCommitFailure::Reject => {
+                        log::error!("Pre-commit hook failed: {}", e);
+
+                        for (resolver, _) in responses {
+                            let _ = resolver.send(DatabaseCommandResponse::transaction_status(&format!(
+                                "Pre-commit hook failed, unsure if transaction is durable: {}",
+                                e
+                            )));
+                        }
+
+                        return;
+                    }
+                    PreCommitFailure::Acknowledge => {
+                        log::warn!("Pre-commit hook failed, acknowledging the batch: {}", e)
+                    }
+                    PreCommitFailure::Crash => {
+                        for (resolver, _) in responses {
+                            let _ = resolver.send(DatabaseCommandResponse::transaction_status(
+                                "Pre-commit hook failed, database crash",
+                            ));
+                        }
+
+                        crash_database(DatabaseCrash::PreCommitHookFailed(e));
+                    }
+                }
+            }
+        }
+
+        for (resolver, response) in responses {
+            let _ = resolver.send(response);
+        }
+
+        if !committed.is_empty() {
+            self.committed_listeners.lock().unwrap().retain(|listener| {
+                committed
+                    .iter()
+                    .all(|transaction| listener.send(transaction.clone()).is_ok())
+            });
+        }
+    }
+}
+
+impl Drop for TransactionWAL {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
 // TODO: Usize seems odd, but that's what transaction id uses. Should change to u64
 #[derive(Debug, Default)]
 pub struct LocalClock {
@@ -293,3 +842,82 @@ impl LocalClock {
         self.ts_sequence.store(value, Ordering::SeqCst);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_wal_transactions_are_errors() {
+        let transaction = Transaction {
+            id: TransactionId(7),
+            statements: vec![Statement::List(None)],
+            status: TransactionStatus::Committed,
+        };
+
+        let encoded = String::from_utf8(transaction.to_wal()).unwrap();
+
+        assert_eq!(
+            Transaction::from_wal(&encoded, UnknownStatementPolicy::Fail)
+                .unwrap()
+                .id,
+            TransactionId(7)
+        );
+
+        // Torn write, garbage and a transaction with an unknown status
+        for malformed in [
+            &encoded[..encoded.len() / 2],
+            "\u{0}\u{ff}not json",
+            r#"{"id":1,"statements":[],"status":"Unknown"}"#,
+        ] {
+            for unknown_statements in [UnknownStatementPolicy::Fail, UnknownStatementPolicy::Skip] {
+                assert!(Transaction::from_wal(malformed, unknown_statements).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn statements_of_newer_builds_follow_the_unknown_statement_policy() {
+        // Written before the format was versioned
+        let unversioned = r#"{"id":3,"statements":[{"List":null}],"status":"Committed"}"#;
+
+        assert_eq!(
+            Transaction::from_wal(unversioned, UnknownStatementPolicy::Fail)
+                .unwrap()
+                .statements
+                .len(),
+            1
+        );
+
+        // A variant this build does not have, and a known variant whose encoding changed in a newer format
+        for (format, unknown) in [
+            (STATEMENT_FORMAT_VERSION, r#"{"Archive":{"id":"a"}}"#),
+            (STATEMENT_FORMAT_VERSION, r#""Vacuum""#),
+            (STATEMENT_FORMAT_VERSION + 1, r#"{"List":7}"#),
+        ] {
+            let encoded = format!(
+                r#"{{"id":4,"statements":[{{"List":null}},{}],"status":"Committed","format":{}}}"#,
+                unknown, format
+            );
+
+            assert!(Transaction::from_wal(&encoded, UnknownStatementPolicy::Fail).is_err());
+
+            let transaction =
+                Transaction::from_wal(&encoded, UnknownStatementPolicy::Skip).unwrap();
+
+            assert_eq!(transaction.id, TransactionId(4));
+            assert!(matches!(
+                transaction.statements.as_slice(),
+                [Statement::List(None)]
+            ));
+        }
+
+        // A known variant that cannot be decoded in the current format is corrupt rather than unknown
+        let corrupt = format!(
+            r#"{{"id":5,"statements":[{{"List":7}}],"status":"Committed","format":{}}}"#,
+            STATEMENT_FORMAT_VERSION
+        );
+
+        assert!(Transaction::from_wal(&corrupt, UnknownStatementPolicy::Skip).is_err());
+    }
+}
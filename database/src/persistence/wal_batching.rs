@@ -0,0 +1,243 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Commits written to the WAL at once without adaptive batching
+const FIXED_BATCH_LIMIT: usize = 51;
+
+/// Weight of the latest batch in the averages
+const SMOOTHING: f64 = 0.2;
+
+/// Defines the commit latency the WAL sizes its batches for, see `WalBatching`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveBatchingOptions {
+    /// Target for the time from a commit being sent to the WAL until it is durable
+    pub commit_latency_slo: Duration,
+    pub min_batch: usize,
+    pub max_batch: usize,
+}
+
+impl AdaptiveBatchingOptions {
+    pub fn new(commit_latency_slo: Duration) -> Self {
+        Self {
+            commit_latency_slo,
+            min_batch: 1,
+            max_batch: 1000,
+        }
+    }
+
+    pub fn set_min_batch(mut self, min_batch: usize) -> Self {
+        self.min_batch = min_batch;
+        self
+    }
+
+    pub fn set_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch;
+        self
+    }
+
+    fn clamp(&self, limit: usize) -> usize {
+        let min_batch = self.min_batch.max(1);
+
+        limit.clamp(min_batch, self.max_batch.max(min_batch))
+    }
+}
+
+struct BatchingState {
+    limit: usize,
+    /// Averages over the recent batches, none before the first batch
+    write_latency: Option<f64>,
+    batch_size: Option<f64>,
+    /// Commits per second
+    arrival_rate: Option<f64>,
+    last_batch_at: Option<Instant>,
+}
+
+/// How many commits the WAL writes (and syncs) at once. Without `AdaptiveBatchingOptions` the limit is fixed.
+/// Otherwise a batch whose oldest commit took longer than the SLO to become durable doubles the limit, so a slow
+/// device spreads its syncs over more commits. A batch well within the SLO shrinks the limit, but not below the
+/// commits that arrive during one write, which the next batch has to take to keep up
+pub struct WalBatching {
+    adaptive: Option<AdaptiveBatchingOptions>,
+    state: Mutex<BatchingState>,
+}
+
+impl WalBatching {
+    pub fn new(adaptive: Option<AdaptiveBatchingOptions>) -> Self {
+        let limit = match &adaptive {
+            Some(adaptive) => adaptive.clamp(FIXED_BATCH_LIMIT),
+            None => FIXED_BATCH_LIMIT,
+        };
+
+        Self {
+            adaptive,
+            state: Mutex::new(BatchingState {
+                limit,
+                write_latency: None,
+                batch_size: None,
+                arrival_rate: None,
+                last_batch_at: None,
+            }),
+        }
+    }
+
+    /// Most commits the next batch takes
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Records a batch once it is durable, `queue_wait` is how long its oldest commit waited for the batch to be
+    /// written and `write` how long the write and sync took
+    pub fn record(&self, commits: usize, queue_wait: Duration, write: Duration) {
+        self.record_at(Instant::now(), commits, queue_wait, write)
+    }
+
+    fn record_at(&self, now: Instant, commits: usize, queue_wait: Duration, write: Duration) {
+        let mut state = self.state.lock().unwrap();
+
+        let average = |average: Option<f64>, value: f64| match average {
+            Some(average) => average + SMOOTHING * (value - average),
+            None => value,
+        };
+
+        state.write_latency = Some(average(state.write_latency, write.as_secs_f64()));
+        state.batch_size = Some(average(state.batch_size, commits as f64));
+
+        if let Some(last_batch_at) = state.last_batch_at {
+            let interval = now.duration_since(last_batch_at).as_secs_f64();
+
+            if interval > 0.0 {
+                state.arrival_rate = Some(average(state.arrival_rate, commits as f64 / interval));
+            }
+        }
+
+        state.last_batch_at = Some(now);
+
+        let Some(adaptive) = &self.adaptive else {
+            return;
+        };
+
+        let latency = queue_wait + write;
+
+        let limit = if latency > adaptive.commit_latency_slo {
+            state.limit.saturating_mul(2)
+        } else if latency < adaptive.commit_latency_slo / 2 {
+            let keep_up = (state.arrival_rate.unwrap_or(0.0) * state.write_latency.unwrap_or(0.0))
+                .round() as usize;
+
+            (state.limit - state.limit / 4).max(keep_up)
+        } else {
+            state.limit
+        };
+
+        state.limit = adaptive.clamp(limit);
+    }
+
+    pub fn get_stats(&self) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+
+        let mut stats = vec![("WALBatchLimit".to_string(), state.limit.to_string())];
+
+        if let Some(batch_size) = state.batch_size {
+            stats.push((
+                "WALAverageBatchSize".to_string(),
+                format!("{:.2}", batch_size),
+            ));
+        }
+
+        if let Some(write_latency) = state.write_latency {
+            stats.push((
+                "WALWriteLatencyMs".to_string(),
+                format!("{:.3}", write_latency * 1000.0),
+            ));
+        }
+
+        if let Some(arrival_rate) = state.arrival_rate {
+            stats.push((
+                "WALCommitsPerSecond".to_string(),
+                format!("{:.1}", arrival_rate),
+            ));
+        }
+
+        if let Some(adaptive) = &self.adaptive {
+            stats.push((
+                "WALCommitLatencySLOMs".to_string(),
+                format!("{:.3}", adaptive.commit_latency_slo.as_secs_f64() * 1000.0),
+            ));
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(batching: &WalBatching, name: &str) -> Option<String> {
+        batching
+            .get_stats()
+            .into_iter()
+            .find(|(stat, _)| stat == name)
+            .map(|(_, value)| value)
+    }
+
+    #[test]
+    fn limit_follows_the_commit_latency_slo() {
+        let batching = WalBatching::new(Some(
+            AdaptiveBatchingOptions::new(Duration::from_millis(10))
+                .set_min_batch(4)
+                .set_max_batch(200),
+        ));
+
+        assert_eq!(batching.limit(), 51);
+
+        let mut now = Instant::now();
+
+        // A slow device misses the SLO, the limit grows up to the maximum
+        for _ in 0..4 {
+            now += Duration::from_millis(14);
+            batching.record_at(now, 50, Duration::from_millis(8), Duration::from_millis(6));
+        }
+
+        assert_eq!(batching.limit(), 200);
+
+        // A fast device leaves budget, the limit shrinks to the commits that arrive during a write
+        for _ in 0..32 {
+            now += Duration::from_millis(1);
+            batching.record_at(now, 20, Duration::ZERO, Duration::from_millis(1));
+        }
+
+        assert_eq!(batching.limit(), 20);
+
+        // Fewer commits arrive, the limit shrinks down to the minimum
+        for _ in 0..32 {
+            now += Duration::from_millis(10);
+            batching.record_at(now, 1, Duration::ZERO, Duration::from_micros(100));
+        }
+
+        assert_eq!(batching.limit(), 4);
+        assert_eq!(stat(&batching, "WALBatchLimit").as_deref(), Some("4"));
+        assert_eq!(
+            stat(&batching, "WALCommitLatencySLOMs").as_deref(),
+            Some("10.000")
+        );
+    }
+
+    #[test]
+    fn limit_is_fixed_without_adaptive_batching() {
+        let batching = WalBatching::new(None);
+
+        assert_eq!(stat(&batching, "WALAverageBatchSize"), None);
+
+        batching.record(10, Duration::from_secs(1), Duration::from_secs(1));
+
+        assert_eq!(batching.limit(), FIXED_BATCH_LIMIT);
+        assert_eq!(
+            stat(&batching, "WALAverageBatchSize").as_deref(),
+            Some("10.00")
+        );
+        assert_eq!(stat(&batching, "WALCommitLatencySLOMs"), None);
+    }
+}
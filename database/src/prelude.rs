@@ -0,0 +1,89 @@
+//! The types needed to embed the database, `use database::prelude::*;`
+//!
+//! The prelude is the stable API of the crate, its items keep their names and meaning across internal refactors.
+//! The module paths behind it (e.g. `database::database::table::row`) are an implementation detail and may move.
+//! Removing or renaming an item of the prelude is a breaking change, see `tests/public_api.rs`
+
+// Running a database
+pub use crate::database::{
+    admin::{self, AdminError, WalInspection},
+    commands::{ShutdownRequest, SnapshotTimestamp, TransactionContext},
+    config::{read_config_file, ConfigError, DatabaseConfig, StorageEngineFlag},
+    database::Database,
+    logging::{LogFormat, LoggingConfig},
+    options::{DatabaseOptions, DatabaseOptionsBuilder, OptionsError},
+    protocol::{
+        decode_statements, encode_results, statement_proto, Capabilities, ClientHello, Negotiated,
+        StatementFrameError,
+    },
+    request_manager::{ConditionalRead, RequestManager, RequestManagerError},
+    watch::{EntityWatch, EntityWatchError},
+};
+pub use crate::persistence::backup::{BackupRestoreError, BackupRestoreReport, SnapshotArchive};
+pub use crate::persistence::snapshot_diff::{DiffSource, EntityDiff, FieldChange, SnapshotDiff};
+
+// Options
+pub use crate::database::{
+    audit::RollbackAuditOptions,
+    backfill::IndexBackfillOptions,
+    capture::{CaptureError, CaptureReplay, ReplayPacing, RequestCaptureOptions},
+    clock::{Clock, MockClock, SystemClock},
+    context_policy::{ContextField, ContextPolicy},
+    hooks::LifecycleEvent,
+    ids::IdGeneration,
+    limits::TransactionLimits,
+    quota::Quota,
+    request_log::RequestLogSampling,
+    restore_verification::RestoreVerificationOptions,
+    seed::{Seed, SeedError, SeedMutation},
+    shadow::ShadowReadOptions,
+    table::{conflict::ConflictResolution, planner::ReadPath, policy::RowPolicy},
+    warmup::WarmupOptions,
+};
+pub use crate::persistence::{
+    field_encryption::FieldEncryptionOptions,
+    parquet::ParquetExportTarget,
+    persistence::{Persistence, ReadOnlyPersistence},
+    pre_commit::{PreCommitFailure, PreCommitHook},
+    snapshot::{Metadata, SnapshotRecord, SnapshotVerification},
+    snapshot_shards::SnapshotSharding,
+    storage::{
+        custom::CustomStorage, file::FileOptions, network::StorageTimeouts, ReadBlobState, Storage,
+        StorageEngine, StorageError, StorageResult,
+    },
+    transaction::{
+        Transaction, TransactionFileWriteMode, TransactionStatus, TransactionWriteMode,
+        UnknownStatementPolicy,
+    },
+    wal_batching::AdaptiveBatchingOptions,
+};
+#[cfg(feature = "dynamodb")]
+pub use crate::persistence::storage::dynamodb::DynamoOptions;
+#[cfg(feature = "postgres")]
+pub use crate::persistence::storage::postgres::PostgresOptions;
+#[cfg(feature = "s3")]
+pub use crate::persistence::storage::s3::{S3Options, S3Replica};
+
+// Statements and their results
+pub use crate::{
+    consts::consts::{EntityId, TransactionId, VersionId},
+    database::table::{
+        attachment::AttachmentContent,
+        history::{HistoryCursor, HistoryPage, HistoryRequest},
+        meta::EntityMeta,
+        outbox::{OutboxId, OutboxMessage},
+        pagination::{Cursor, Page, PageRequest},
+        query::{QueryAddressData, QueryMatch, QueryPersonData},
+        query_builder::{FieldQuery, PersonQuery, Query},
+        row::{
+            Lineage, PersonVersion, PersonVersionState, UpdateAddressData, UpdateAddressStatement,
+            UpdateAttachmentStatement, UpdateListStatement, UpdatePersonData, UpdateStatement,
+        },
+        view::{PersonField, ViewDefinition, ViewResult},
+        watermark::TableVersion,
+    },
+    model::{
+        person::{Address, Attachment, Person},
+        statement::{Statement, StatementResult},
+    },
+};
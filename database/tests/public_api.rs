@@ -0,0 +1,118 @@
+//! Guards the stable API of the crate (see `database::prelude`). The test is compiled as a separate crate, so it
+//! only sees what client code sees
+//!
+//! If the prelude changes on purpose, update the snapshot with `UPDATE_PUBLIC_API=1 cargo test --test public_api`.
+//! Removing or renaming an item is a breaking change
+
+use std::{fs, path::PathBuf};
+
+use database::prelude::*;
+
+const SNAPSHOT: &str = "tests/public_api.txt";
+
+/// Names re-exported by the prelude, the last segment of each `pub use` path
+fn prelude_items() -> Vec<String> {
+    let source =
+        fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/prelude.rs"))
+            .unwrap();
+
+    let mut items = vec![];
+
+    for statement in source.split("pub use").skip(1) {
+        let statement = statement.split(';').next().unwrap();
+
+        // A segment followed by `::` is a module of the path, the others are the re-exported items
+        let tokens: Vec<&str> = statement
+            .split(|c: char| c == '{' || c == '}' || c == ',' || c.is_whitespace())
+            .flat_map(|token| token.split_inclusive("::"))
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        items.extend(
+            tokens
+                .iter()
+                .filter(|token| !token.ends_with("::"))
+                .map(|token| token.to_string()),
+        );
+    }
+
+    items.sort();
+    items
+}
+
+#[test]
+fn prelude_matches_the_public_api_snapshot() {
+    let snapshot_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+    let items = prelude_items().join("\n") + "\n";
+
+    if std::env::var("UPDATE_PUBLIC_API").is_ok() {
+        fs::write(&snapshot_path, &items).unwrap();
+        return;
+    }
+
+    let snapshot = fs::read_to_string(&snapshot_path).unwrap();
+
+    let removed: Vec<&str> = snapshot
+        .lines()
+        .filter(|item| !items.lines().any(|line| line == *item))
+        .collect();
+
+    assert!(
+        removed.is_empty(),
+        "Items were removed from the prelude, this breaks client code: {:?}",
+        removed
+    );
+
+    assert_eq!(
+        snapshot, items,
+        "Items were added to the prelude, update the snapshot with UPDATE_PUBLIC_API=1"
+    );
+}
+
+#[test]
+fn prelude_embeds_a_database() {
+    let data = std::env::temp_dir().join(format!("lineagedb-public-api-{}", std::process::id()));
+
+    let options = DatabaseOptionsBuilder::new()
+        .set_storage_engine(StorageEngine::File(FileOptions::new(data.clone())))
+        .set_restore(false)
+        .set_sync_file_write(TransactionWriteMode::Off)
+        .build()
+        .unwrap();
+
+    let request_manager: RequestManager = Database::new(options).run();
+
+    let person = Person {
+        id: EntityId("luke".to_string()),
+        full_name: "Luke Skywalker".to_string(),
+        email: None,
+        address: None,
+        phone_numbers: vec![],
+        attachments: vec![],
+    };
+
+    request_manager
+        .send_transaction(
+            vec![Statement::Add(person.clone())],
+            TransactionContext::default(),
+        )
+        .unwrap();
+
+    let people = request_manager
+        .send_list(
+            Some(QueryPersonData {
+                full_name: QueryMatch::Value("Luke Skywalker".to_string()),
+                ..Default::default()
+            }),
+            TransactionContext::default(),
+        )
+        .unwrap();
+
+    assert_eq!(people, vec![person]);
+
+    request_manager
+        .send_shutdown_request(ShutdownRequest::Coordinator)
+        .unwrap();
+
+    let _ = fs::remove_dir_all(data);
+}
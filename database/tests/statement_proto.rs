@@ -0,0 +1,57 @@
+//! Guards the wire format of the generated statement proto (see `statement_proto`). Fields are numbered by the
+//! declaration order of `Statement` and `StatementResult`, reordering or removing a variant renumbers the fields
+//! and breaks clients generated from an older proto
+//!
+//! If a variant is added on purpose, update the snapshot with `UPDATE_STATEMENT_PROTO=1 cargo test --test statement_proto`
+
+use std::{fs, path::PathBuf};
+
+use database::prelude::*;
+
+const SNAPSHOT: &str = "tests/statement.proto";
+
+/// The fields of the proto qualified by their message, e.g. `Statement string add = 1;`
+fn fields(proto: &str) -> Vec<String> {
+    let mut message = "";
+    let mut fields = vec![];
+
+    for line in proto.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("message ") {
+            message = name.trim_end_matches(" {");
+        } else if line.starts_with("string ") {
+            fields.push(format!("{} {}", message, line));
+        }
+    }
+
+    fields
+}
+
+#[test]
+fn statement_proto_matches_the_snapshot() {
+    let snapshot_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+    let proto = statement_proto();
+
+    if std::env::var("UPDATE_STATEMENT_PROTO").is_ok() {
+        fs::write(&snapshot_path, &proto).unwrap();
+        return;
+    }
+
+    let snapshot = fs::read_to_string(&snapshot_path).unwrap();
+
+    let current = fields(&proto);
+    let renumbered: Vec<String> = fields(&snapshot)
+        .into_iter()
+        .filter(|field| !current.contains(field))
+        .collect();
+
+    assert!(
+        renumbered.is_empty(),
+        "Fields were removed or renumbered, add new variants at the end of the enums: {:?}",
+        renumbered
+    );
+
+    assert_eq!(
+        snapshot, proto,
+        "Statements were added, update the snapshot with UPDATE_STATEMENT_PROTO=1"
+    );
+}